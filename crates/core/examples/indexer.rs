@@ -54,6 +54,7 @@ fn main() -> anyhow::Result<()> {
         batch_size: 512,
         autocommit_after_num_inserts:
             stract::config::defaults::Indexing::autocommit_after_num_inserts(),
+        main_content_extraction: stract::config::defaults::Indexing::main_content_extraction(),
         dual_encoder: args
             .dual_encoder_path
             .map(|p| stract::config::IndexerDualEncoderConfig {
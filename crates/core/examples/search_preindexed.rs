@@ -34,6 +34,7 @@ pub async fn main() {
         gossip_seed_nodes: None,
         gossip_addr: "0.0.0.0:8002".parse().unwrap(),
         collector: collector_conf.clone(),
+        diversity: Default::default(),
         thresholds: ApiThresholds::default(),
         widgets: WidgetsConfig {
             thesaurus_paths: vec!["data/english-wordnet-2022-subset.ttl".to_string()],
@@ -17,13 +17,22 @@
 use rayon::prelude::*;
 use std::{
     collections::{BTreeMap, VecDeque},
-    time::Duration,
+    path::Path,
+    time::{Duration, Instant},
 };
 
-use super::{DhtConn, Finisher, Job, JobScheduled, RemoteWorker, Setup, Worker, WorkerRef};
+use super::{
+    progress::{Progress, StallDetector, StatusFile},
+    DhtConn, Finisher, Job, JobScheduled, RemoteWorker, Setup, Worker, WorkerRef,
+};
 use crate::{distributed::retry_strategy::ExponentialBackoff, Result};
 use anyhow::anyhow;
 
+struct ScheduledJob<J> {
+    job: J,
+    last_heartbeat: Instant,
+}
+
 pub struct Coordinator<J>
 where
     J: Job,
@@ -31,6 +40,8 @@ where
     workers: BTreeMap<WorkerRef, <<J as Job>::Worker as Worker>::Remote>,
     setup: Box<dyn Setup<DhtTables = J::DhtTables>>,
     mappers: Vec<J::Mapper>,
+    stall_detector: StallDetector,
+    status_file: Option<StatusFile>,
 }
 
 impl<J> Coordinator<J>
@@ -49,6 +60,8 @@ where
                 .enumerate()
                 .map(|(i, w)| (WorkerRef(i), w))
                 .collect(),
+            stall_detector: StallDetector::default(),
+            status_file: None,
         }
     }
 
@@ -57,6 +70,28 @@ where
         self
     }
 
+    /// Reschedule the job of a worker on another worker if the first worker hasn't
+    /// responded for longer than `timeout`.
+    pub fn with_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_detector = StallDetector::new(timeout);
+        self
+    }
+
+    /// Write the progress of the computation to `path`, which can be read with
+    /// `stract ampc status`.
+    pub fn with_status_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.status_file = Some(StatusFile::new(path));
+        self
+    }
+
+    fn report(&self, progress: &Progress) {
+        if let Some(status_file) = &self.status_file {
+            if let Err(err) = status_file.write(progress) {
+                tracing::warn!("failed to write status file: {}", err);
+            }
+        }
+    }
+
     fn send_dht_to_workers(&self, dht: &DhtConn<J::DhtTables>) -> Result<()> {
         self.workers
             .par_iter()
@@ -66,6 +101,13 @@ where
         Ok(())
     }
 
+    fn worker_jobs(&self) -> BTreeMap<WorkerRef, Result<Option<J>>> {
+        self.workers
+            .iter()
+            .map(|(r, w)| (*r, w.current_job()))
+            .collect()
+    }
+
     fn schedule_job(
         &self,
         job: J,
@@ -99,48 +141,84 @@ where
         }
     }
 
-    fn await_scheduled_jobs(
+    /// Update the scheduled jobs with the latest responses from the workers. Jobs that have
+    /// completed are removed, and so are the stalled jobs which are returned to be rescheduled.
+    fn handle_heartbeats(
         &self,
-        mut scheduled_jobs: BTreeMap<WorkerRef, J>,
-        mapper: J::Mapper,
-    ) -> Result<()> {
-        tracing::debug!("Awaiting scheduled jobs");
+        scheduled_jobs: &mut BTreeMap<WorkerRef, ScheduledJob<J>>,
+        worker_jobs: &BTreeMap<WorkerRef, Result<Option<J>>>,
+        progress: &mut Progress,
+    ) -> Vec<J> {
+        let now = Instant::now();
+        let mut stalled = Vec::new();
+
+        scheduled_jobs.retain(|r, scheduled| match &worker_jobs[r] {
+            Ok(Some(_)) => {
+                scheduled.last_heartbeat = now;
+                true
+            }
+            Ok(None) => {
+                progress.current().jobs_completed += 1;
+                false
+            }
+            Err(_) => {
+                if self
+                    .stall_detector
+                    .is_stalled(scheduled.last_heartbeat, now)
+                {
+                    tracing::warn!("job {:?} stalled on worker {:?}", scheduled.job, r);
+                    progress.current().jobs_failed += 1;
+                    stalled.push(scheduled.job.clone());
+                    false
+                } else {
+                    true
+                }
+            }
+        });
+
+        stalled
+    }
+
+    fn run_mapper(&self, jobs: &[J], mapper: &J::Mapper, progress: &mut Progress) -> Result<()> {
+        let mut remaining_jobs: VecDeque<_> = jobs.iter().cloned().collect();
+        let mut scheduled_jobs: BTreeMap<WorkerRef, ScheduledJob<J>> = BTreeMap::new();
         let mut sleeper = ExponentialBackoff::from_millis(100).with_limit(Duration::from_secs(10));
 
-        loop {
-            let worker_jobs = self
-                .workers
-                .iter()
-                .map(|(r, w)| (*r, w.current_job()))
-                .collect::<BTreeMap<_, _>>();
-
-            let mut finished_workers = 0;
-            for (r, j) in worker_jobs.iter() {
-                match j {
-                    Ok(Some(_)) => break,
-                    Ok(None) => finished_workers += 1,
-                    Err(_) => {
-                        if let Some(job) = &scheduled_jobs.remove(r) {
-                            match self.schedule_job(job.clone(), mapper.clone(), &worker_jobs)? {
-                                JobScheduled::Success(r) => {
-                                    scheduled_jobs.insert(r, job.clone());
-                                    break; // need to break to avoid double scheduling to same worker
-                                }
-                                JobScheduled::NoAvailableWorkers => {
-                                    // retry scheduling.
-                                    break;
-                                }
-                            }
-                        }
+        while !remaining_jobs.is_empty() || !scheduled_jobs.is_empty() {
+            // get current status from workers
+            let worker_jobs = self.worker_jobs();
+
+            for job in self.handle_heartbeats(&mut scheduled_jobs, &worker_jobs, progress) {
+                remaining_jobs.push_front(job);
+            }
+
+            let mut scheduled = false;
+
+            if let Some(job) = remaining_jobs.pop_front() {
+                match self.schedule_job(job.clone(), mapper.clone(), &worker_jobs)? {
+                    JobScheduled::Success(worker) => {
+                        scheduled_jobs.insert(
+                            worker,
+                            ScheduledJob {
+                                job,
+                                last_heartbeat: Instant::now(),
+                            },
+                        );
+                        progress.current().jobs_scheduled += 1;
+                        sleeper.success();
+                        scheduled = true;
+                    }
+                    JobScheduled::NoAvailableWorkers => {
+                        remaining_jobs.push_front(job);
                     }
                 }
             }
 
-            if finished_workers == self.workers.len() {
-                break;
-            }
+            self.report(progress);
 
-            std::thread::sleep(sleeper.next().expect("sleeper should not be exhausted"));
+            if !scheduled {
+                std::thread::sleep(sleeper.next().expect("sleeper should not be exhausted"));
+            }
         }
 
         Ok(())
@@ -156,57 +234,291 @@ where
         self.setup.setup_first_round(dht.prev());
         self.setup.setup_first_round(dht.next());
 
+        let mut progress = Progress::new(self.workers.len(), self.mappers.len(), jobs.len());
+        let mut round = 0;
+
         while !finisher.is_finished(dht.prev()) {
-            tracing::debug!("Starting new round");
+            round += 1;
+            tracing::debug!("Starting round {}", round);
+            progress.start_round(round);
+            self.report(&progress);
+
             self.setup.setup_round(dht.next());
             self.send_dht_to_workers(&dht)?;
 
             for mapper in &self.mappers {
-                // run round
-                let mut remaining_jobs: VecDeque<_> = jobs.clone().into_iter().collect();
-                let mut sleeper =
-                    ExponentialBackoff::from_millis(100).with_limit(Duration::from_secs(10));
-
-                let mut scheduled_jobs: BTreeMap<WorkerRef, J> = BTreeMap::new();
-
-                while let Some(job) = remaining_jobs.pop_front() {
-                    // get current status from workers
-                    let worker_jobs = self
-                        .workers
-                        .iter()
-                        .map(|(r, w)| (*r, w.current_job()))
-                        .collect::<BTreeMap<_, _>>();
-
-                    // handle failed workers
-                    for r in worker_jobs
-                        .iter()
-                        .filter_map(|(r, j)| j.as_ref().err().map(|_| r))
-                    {
-                        if let Some(job) = scheduled_jobs.remove(r) {
-                            remaining_jobs.push_front(job);
-                        }
-                    }
+                self.run_mapper(&jobs, mapper, &mut progress)?;
+                progress.current().mappers_completed += 1;
+            }
 
-                    match self.schedule_job(job.clone(), mapper.clone(), &worker_jobs)? {
-                        JobScheduled::Success(worker) => {
-                            scheduled_jobs.insert(worker, job);
-                            sleeper.success();
-                        }
-                        JobScheduled::NoAvailableWorkers => {
-                            remaining_jobs.push_front(job);
-                            let sleep_duration =
-                                sleeper.next().expect("sleeper should not be exhausted");
-                            std::thread::sleep(sleep_duration);
+            progress.current().dht_keys_written = Some(dht.next().num_keys());
+
+            let converged = finisher.is_converged(round, dht.prev(), dht.next());
+            dht.next_round();
+
+            progress.estimated_rounds_remaining =
+                finisher.estimated_rounds_remaining(round, dht.prev());
+            self.report(&progress);
+
+            if converged {
+                break;
+            }
+        }
+
+        progress.finished = true;
+        progress.estimated_rounds_remaining = Some(0);
+        self.report(&progress);
+
+        Ok(dht.take_prev())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use crate::{
+        ampc::{prelude::*, CoordReq, CoordResp, DefaultDhtTable, JobConn, JobReq, Resp},
+        distributed::sonic,
+        free_socket_addr,
+    };
+
+    use super::*;
+
+    #[derive(bincode::Encode, bincode::Decode, Debug, Clone)]
+    pub struct CountTables {
+        counts: DefaultDhtTable<u64, u64>,
+    }
+
+    impl_dht_tables!(CountTables, [counts]);
+
+    #[derive(bincode::Encode, bincode::Decode, Debug, Clone)]
+    pub struct CountJob {
+        id: u64,
+    }
+
+    impl Job for CountJob {
+        type DhtTables = CountTables;
+        type Worker = CountWorker;
+        type Mapper = CountMapper;
+    }
+
+    #[derive(
+        serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Debug, Clone,
+    )]
+    pub enum CountMapper {
+        Count,
+    }
+
+    impl Mapper for CountMapper {
+        type Job = CountJob;
+
+        fn map(&self, job: CountJob, _: &CountWorker, dht: &DhtConn<CountTables>) {
+            dht.next().counts.set(job.id, 1);
+        }
+    }
+
+    pub struct CountWorker;
+
+    #[derive(
+        serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Debug, Clone,
+    )]
+    pub struct Ping;
+
+    impl Message<CountWorker> for Ping {
+        type Response = ();
+
+        fn handle(self, _: &CountWorker) -> Self::Response {}
+    }
+
+    impl_worker!(CountJob, RemoteCountWorker => CountWorker, [Ping]);
+
+    /// A worker that can be told to stop responding once it has been assigned a job,
+    /// while the job itself keeps running.
+    #[derive(Clone)]
+    pub struct RemoteCountWorker {
+        pool: Arc<sonic::ConnectionPool<JobConn<CountJob>>>,
+        stall_on_schedule: bool,
+        stalled: Arc<AtomicBool>,
+    }
+
+    impl RemoteCountWorker {
+        fn start(stall_on_schedule: bool) -> Self {
+            let addr = free_socket_addr();
+            let (tx, rx) = crossbeam_channel::bounded(1);
+
+            std::thread::spawn(move || {
+                let mut server = CountWorker.bind(addr).unwrap();
+                tx.send(()).unwrap();
+                server.run().unwrap();
+            });
+
+            rx.recv().unwrap();
+
+            Self {
+                pool: Arc::new(sonic::ConnectionPool::new(addr).unwrap()),
+                stall_on_schedule,
+                stalled: Arc::new(AtomicBool::new(false)),
+            }
+        }
+    }
+
+    impl RemoteWorker for RemoteCountWorker {
+        type Job = CountJob;
+
+        fn pool(&self) -> &sonic::ConnectionPool<JobConn<CountJob>> {
+            &self.pool
+        }
+
+        fn schedule_job(&self, job: &CountJob, mapper: CountMapper) -> Result<()> {
+            self.send_raw(&JobReq::Coordinator(CoordReq::ScheduleJob {
+                job: job.clone(),
+                mapper,
+            }))?;
+
+            if self.stall_on_schedule {
+                self.stalled.store(true, Ordering::Relaxed);
+            }
+
+            Ok(())
+        }
+
+        fn current_job(&self) -> Result<Option<CountJob>> {
+            if self.stalled.load(Ordering::Relaxed) {
+                return Err(anyhow!("no heartbeat"));
+            }
+
+            match self.send_raw(&JobReq::Coordinator(CoordReq::CurrentJob))? {
+                Resp::Coordinator(CoordResp::CurrentJob(job)) => Ok(job),
+                _ => Err(anyhow!("unexpected response")),
+            }
+        }
+    }
+
+    struct CountSetup {
+        dht: DhtConn<CountTables>,
+    }
+
+    impl CountSetup {
+        fn new(dht: (crate::ampc::dht::ShardId, SocketAddr)) -> Self {
+            Self {
+                dht: DhtConn::new(CountTables {
+                    counts: DefaultDhtTable::new(&[dht], "counts"),
+                }),
+            }
+        }
+    }
+
+    impl Setup for CountSetup {
+        type DhtTables = CountTables;
+
+        fn init_dht(&self) -> DhtConn<CountTables> {
+            self.dht.clone()
+        }
+    }
+
+    fn finisher(rounds: usize) -> impl Finisher<Job = CountJob> {
+        ConvergenceFinisher::new(|_: &CountTables, _: &CountTables| 1.0, 0.5)
+            .with_max_rounds(rounds)
+    }
+
+    fn jobs(n: u64) -> Vec<CountJob> {
+        (0..n).map(|id| CountJob { id }).collect()
+    }
+
+    #[test]
+    fn progress_advances_by_round() {
+        let dht = crate::entrypoint::ampc::dht::tests::setup();
+        let workers = vec![
+            RemoteCountWorker::start(false),
+            RemoteCountWorker::start(false),
+        ];
+
+        let path = crate::gen_temp_path().join("status.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        let observer = {
+            let file = StatusFile::new(&path);
+            std::thread::spawn(move || {
+                let mut observed = Vec::new();
+
+                loop {
+                    if let Ok(progress) = file.read() {
+                        observed.push(progress.rounds.len());
+
+                        if progress.finished {
+                            break observed;
                         }
                     }
+
+                    std::thread::sleep(Duration::from_millis(10));
                 }
+            })
+        };
 
-                self.await_scheduled_jobs(scheduled_jobs, mapper.clone())?;
-            }
+        let res = Coordinator::new(CountSetup::new(dht), workers)
+            .with_mapper(CountMapper::Count)
+            .with_status_file(&path)
+            .run(jobs(4), finisher(3))
+            .unwrap();
 
-            dht.next_round();
+        assert_eq!(res.counts.num_keys(), 4);
+
+        let observed = observer.join().unwrap();
+        assert!(observed.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(observed.last(), Some(&3));
+
+        let progress = StatusFile::new(&path).read().unwrap();
+        assert!(progress.finished);
+        assert_eq!(
+            progress.rounds.iter().map(|r| r.round).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        for round in &progress.rounds {
+            assert_eq!(round.mappers_completed, 1);
+            assert_eq!(round.jobs_scheduled, 4);
+            assert_eq!(round.jobs_completed, 4);
+            assert_eq!(round.jobs_failed, 0);
+            assert_eq!(round.dht_keys_written, Some(4));
         }
 
-        Ok(dht.take_prev())
+        let rendered = progress.render();
+        assert!(rendered.contains("status: finished"));
+        assert_eq!(rendered.lines().filter(|l| l.contains("4/4")).count(), 3);
+    }
+
+    #[test]
+    fn stalled_job_is_rescheduled() {
+        let dht = crate::entrypoint::ampc::dht::tests::setup();
+        let workers = vec![
+            RemoteCountWorker::start(true),
+            RemoteCountWorker::start(false),
+        ];
+
+        let path = crate::gen_temp_path().join("status.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        let res = Coordinator::new(CountSetup::new(dht), workers)
+            .with_mapper(CountMapper::Count)
+            .with_stall_timeout(Duration::from_secs(1))
+            .with_status_file(&path)
+            .run(jobs(2), finisher(1))
+            .unwrap();
+
+        assert_eq!(res.counts.num_keys(), 2);
+
+        let progress = StatusFile::new(&path).read().unwrap();
+        let round = &progress.rounds[0];
+
+        assert_eq!(round.jobs_failed, 1);
+        assert_eq!(round.jobs_scheduled, 3);
+        assert_eq!(round.jobs_completed, 2);
     }
 }
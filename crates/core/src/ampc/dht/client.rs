@@ -15,7 +15,6 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use async_stream::stream;
-use bloom::fast_stable_hash_64;
 use futures::Stream;
 use rand::seq::SliceRandom;
 use std::{
@@ -30,7 +29,7 @@ use super::{
     key::{Key, KeyTrait},
     network::api,
     store::Table,
-    upsert::UpsertEnum,
+    upsert::{UpsertEnum, WriteId},
     value::Value,
     UpsertAction,
 };
@@ -127,19 +126,24 @@ impl Node {
         &self,
         table: Table,
         upsert: F,
+        write_id: Option<WriteId>,
         key: Key,
         value: Value,
     ) -> Result<UpsertAction> {
-        self.api.upsert(table, upsert, key, value).await
+        self.api.upsert(table, upsert, write_id, key, value).await
     }
 
     pub async fn batch_upsert<F: Into<UpsertEnum>>(
         &self,
         table: Table,
         upsert: F,
+        write_id: Option<WriteId>,
         values: Vec<(Key, Value)>,
     ) -> Result<Vec<(Key, UpsertAction)>> {
-        let res = self.api.batch_upsert(table, upsert, values.clone()).await?;
+        let res = self
+            .api
+            .batch_upsert(table, upsert, write_id, values.clone())
+            .await?;
 
         debug_assert_eq!(res.len(), values.len());
         debug_assert!(res
@@ -235,19 +239,25 @@ impl Shard {
         &self,
         table: Table,
         upsert: F,
+        write_id: Option<WriteId>,
         key: Key,
         value: Value,
     ) -> Result<UpsertAction> {
-        self.node().upsert(table, upsert, key, value).await
+        self.node()
+            .upsert(table, upsert, write_id, key, value)
+            .await
     }
 
     pub async fn batch_upsert<F: Into<UpsertEnum>>(
         &self,
         table: Table,
         upsert: F,
+        write_id: Option<WriteId>,
         values: Vec<(Key, Value)>,
     ) -> Result<Vec<(Key, UpsertAction)>> {
-        self.node().batch_upsert(table, upsert, values).await
+        self.node()
+            .batch_upsert(table, upsert, write_id, values)
+            .await
     }
 
     pub fn stream(&self, table: Table) -> impl Stream<Item = Result<(Key, Value)>> + '_ {
@@ -287,19 +297,18 @@ impl Client {
         self.ids = self.shards.keys().cloned().collect();
     }
 
-    fn shard_id_for_key(&self, key: &[u8]) -> Result<&ShardId> {
-        if self.ids.is_empty() {
-            return Err(anyhow::anyhow!("No shards"));
-        }
-
-        let hash = fast_stable_hash_64(key);
+    /// The sorted ids of the shards the keys are spread over.
+    pub fn shard_ids(&self) -> &[ShardId] {
+        &self.ids
+    }
 
-        Ok(&self.ids[hash as usize % self.ids.len()])
+    fn shard_id_for_key(&self, key: &[u8]) -> Result<ShardId> {
+        ShardId::for_key(key, &self.ids).ok_or_else(|| anyhow::anyhow!("No shards"))
     }
 
     fn shard_for_key(&self, key: &[u8]) -> Result<&Shard> {
         let shard_id = self.shard_id_for_key(key)?;
-        Ok(self.shards.get(shard_id).unwrap())
+        Ok(self.shards.get(&shard_id).unwrap())
     }
 
     pub async fn get(&self, table: Table, key: Key) -> Result<Option<Value>> {
@@ -311,7 +320,7 @@ impl Client {
 
         for key in keys {
             let shard = self.shard_id_for_key(&key.as_bytes())?;
-            shard_keys.entry(*shard).or_default().push(key);
+            shard_keys.entry(shard).or_default().push(key);
         }
 
         let mut futures = Vec::with_capacity(shard_keys.len());
@@ -338,7 +347,7 @@ impl Client {
 
         for (key, value) in values {
             let shard = self.shard_id_for_key(&key.as_bytes())?;
-            shard_values.entry(*shard).or_default().push((key, value));
+            shard_values.entry(shard).or_default().push((key, value));
         }
 
         let mut futures = Vec::with_capacity(shard_values.len());
@@ -366,11 +375,12 @@ impl Client {
         &self,
         table: Table,
         upsert: F,
+        write_id: Option<WriteId>,
         key: Key,
         value: Value,
     ) -> Result<UpsertAction> {
         self.shard_for_key(&key.as_bytes())?
-            .upsert(table, upsert, key, value)
+            .upsert(table, upsert, write_id, key, value)
             .await
     }
 
@@ -378,13 +388,14 @@ impl Client {
         &self,
         table: Table,
         upsert: F,
+        write_id: Option<WriteId>,
         values: Vec<(Key, Value)>,
     ) -> Result<Vec<(Key, UpsertAction)>> {
         let mut shard_values: BTreeMap<ShardId, Vec<(Key, Value)>> = BTreeMap::new();
 
         for (key, value) in values {
             let shard = self.shard_id_for_key(&key.as_bytes())?;
-            shard_values.entry(*shard).or_default().push((key, value));
+            shard_values.entry(shard).or_default().push((key, value));
         }
 
         let mut futures = Vec::with_capacity(shard_values.len());
@@ -393,6 +404,7 @@ impl Client {
             futures.push(self.shards[&shard_id].batch_upsert(
                 table.clone(),
                 upsert.clone(),
+                write_id,
                 values,
             ));
         }
@@ -458,3 +470,31 @@ impl Client {
         futures::stream::select_all(streams)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::webgraph::NodeID;
+
+    use super::*;
+
+    #[test]
+    fn node_keys_are_routed_to_their_shard() {
+        // the order the members are discovered in must not matter
+        // and a shard may have left the cluster.
+        let mut members: Vec<_> = [0, 1, 2, 4, 5, 9, 10, 12]
+            .into_iter()
+            .map(|shard| (ShardId::new(shard), crate::free_socket_addr()))
+            .collect();
+        members.reverse();
+
+        let client = Client::new(&members);
+
+        for id in 0..10_000u64 {
+            let node = NodeID::from(id.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let shard = client.shard_id_for_key(&node.as_bytes()).unwrap();
+
+            assert!(client.shards().contains_key(&shard));
+            assert_eq!(node.shard(client.shard_ids()), Some(shard));
+        }
+    }
+}
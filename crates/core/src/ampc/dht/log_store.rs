@@ -60,6 +60,17 @@ impl<C: RaftTypeConfig> Default for LogStoreInner<C> {
     }
 }
 
+impl<C: RaftTypeConfig> LogStore<C> {
+    /// Call `f` with every entry that has not been purged from the log.
+    pub async fn for_each_entry(&self, mut f: impl FnMut(&C::Entry)) {
+        let inner = self.inner.lock().await;
+
+        for entry in inner.log.values() {
+            f(entry);
+        }
+    }
+}
+
 impl<C: RaftTypeConfig> LogStoreInner<C> {
     async fn try_get_log_entries<RB: RangeBounds<u64> + Clone + Debug>(
         &mut self,
@@ -0,0 +1,288 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Inspection and compaction of the tables of a DHT node.
+//!
+//! Every write stays in the raft log of the node until the log is purged, so a key that is
+//! overwritten takes up space for each of its writes and not only for its current value.
+//! The same goes for the writes to a table that has been dropped. Openraft only purges the
+//! log after it has built a snapshot of the state machine, which by default happens every
+//! few thousand writes. A compaction builds the snapshot right away and purges the log up
+//! to it, so only the current values of the keys are left.
+//!
+//! The size of a table is the encoded size of its current keys and values plus the size
+//! of the writes to it that are still in the log.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use openraft::{EntryPayload, Raft};
+
+use super::{
+    log_store::LogStore, network::api, store::StateMachineStore, NodeId, Request, Table, TypeConfig,
+};
+use crate::Result;
+
+/// How long a compaction waits for the snapshot to be built and the log to be purged.
+pub const COMPACTION_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(
+    Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+)]
+pub struct TableStats {
+    pub table: Table,
+    pub num_keys: u64,
+    /// Encoded size of the current keys and values of the table.
+    pub data_bytes: u64,
+    /// Number of writes to the table that are still in the log.
+    pub log_entries: u64,
+    /// Encoded size of the keys and values of those writes.
+    pub log_bytes: u64,
+}
+
+impl TableStats {
+    fn new(table: Table) -> Self {
+        Self {
+            table,
+            num_keys: 0,
+            data_bytes: 0,
+            log_entries: 0,
+            log_bytes: 0,
+        }
+    }
+
+    pub fn size_bytes(&self) -> u64 {
+        self.data_bytes + self.log_bytes
+    }
+}
+
+#[derive(
+    Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+)]
+pub struct NodeStats {
+    pub id: NodeId,
+    pub leader: Option<NodeId>,
+    pub last_applied_index: Option<u64>,
+    pub snapshot_index: Option<u64>,
+    pub purged_index: Option<u64>,
+    /// Number of entries in the log, including the ones that don't write to a table.
+    pub log_entries: u64,
+    /// The tables of the node, including the dropped tables that still have writes in the log.
+    pub tables: Vec<TableStats>,
+}
+
+impl NodeStats {
+    pub fn is_leader(&self) -> bool {
+        self.leader == Some(self.id)
+    }
+
+    pub fn table(&self, table: &Table) -> Option<&TableStats> {
+        self.tables.iter().find(|stats| &stats.table == table)
+    }
+
+    pub fn size_bytes(&self) -> u64 {
+        self.tables.iter().map(TableStats::size_bytes).sum()
+    }
+}
+
+pub(super) fn encoded_len<T: bincode::Encode>(value: &T) -> u64 {
+    bincode::encode_to_vec(value, bincode::config::standard())
+        .map(|bytes| bytes.len() as u64)
+        .unwrap_or_default()
+}
+
+/// The table a request writes to and the encoded size of the keys and values it writes.
+fn written_table(request: &Request) -> Option<(&Table, u64)> {
+    match request {
+        Request::Set(api::Set { table, key, value })
+        | Request::Upsert(api::Upsert {
+            table, key, value, ..
+        }) => Some((table, encoded_len(key) + encoded_len(value))),
+        Request::BatchSet(api::BatchSet { table, values })
+        | Request::BatchUpsert(api::BatchUpsert { table, values, .. }) => Some((
+            table,
+            values
+                .iter()
+                .map(|(key, value)| encoded_len(key) + encoded_len(value))
+                .sum(),
+        )),
+        Request::CreateTable(api::CreateTable { table })
+        | Request::DropTable(api::DropTable { table })
+        | Request::CloneTable(api::CloneTable { to: table, .. }) => Some((table, 0)),
+        Request::AllTables(api::AllTables) => None,
+    }
+}
+
+pub async fn stats(
+    raft: &Raft<TypeConfig>,
+    log_store: &LogStore<TypeConfig>,
+    state_machine_store: &StateMachineStore,
+) -> NodeStats {
+    let metrics = raft.metrics().borrow().clone();
+    let mut tables: BTreeMap<Table, TableStats> = BTreeMap::new();
+
+    {
+        let state_machine = state_machine_store.state_machine.read().await;
+        let db = &state_machine.db;
+
+        for table in db.tables() {
+            let mut stats = TableStats::new(table.clone());
+            stats.num_keys = db.num_keys(&table) as u64;
+            stats.data_bytes = db.data_bytes(&table);
+
+            tables.insert(table, stats);
+        }
+    }
+
+    let mut log_entries = 0;
+    log_store
+        .for_each_entry(|entry| {
+            log_entries += 1;
+
+            if let EntryPayload::Normal(request) = &entry.payload {
+                if let Some((table, bytes)) = written_table(request) {
+                    let stats = tables
+                        .entry(table.clone())
+                        .or_insert_with(|| TableStats::new(table.clone()));
+
+                    stats.log_entries += 1;
+                    stats.log_bytes += bytes;
+                }
+            }
+        })
+        .await;
+
+    NodeStats {
+        id: metrics.id,
+        leader: metrics.current_leader,
+        last_applied_index: metrics.last_applied.map(|log_id| log_id.index),
+        snapshot_index: metrics.snapshot.map(|log_id| log_id.index),
+        purged_index: metrics.purged.map(|log_id| log_id.index),
+        log_entries,
+        tables: tables.into_values().collect(),
+    }
+}
+
+/// Snapshot the state machine of the node and purge its log up to the snapshot.
+/// The log is not purged while it is still being replicated to a follower, in which case
+/// the compaction fails after [`COMPACTION_TIMEOUT`].
+pub async fn compact(
+    raft: &Raft<TypeConfig>,
+    log_store: &LogStore<TypeConfig>,
+    state_machine_store: &StateMachineStore,
+) -> Result<NodeStats> {
+    let last_applied = raft.metrics().borrow().last_applied;
+
+    if let Some(last_applied) = last_applied {
+        raft.trigger().snapshot().await?;
+        raft.wait(Some(COMPACTION_TIMEOUT))
+            .metrics(
+                |metrics| metrics.snapshot >= Some(last_applied),
+                "snapshot of the applied log",
+            )
+            .await?;
+
+        raft.trigger().purge_log(last_applied.index).await?;
+        raft.wait(Some(COMPACTION_TIMEOUT))
+            .metrics(
+                |metrics| metrics.purged >= Some(last_applied),
+                "purge of the snapshotted log",
+            )
+            .await?;
+    }
+
+    Ok(stats(raft, log_store, state_machine_store).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use openraft::error::InitializeError;
+
+    use crate::ampc::dht::{tests::server, BasicNode, RemoteClient, Value};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn compaction_reclaims_overwritten_keys() -> anyhow::Result<()> {
+        let (raft, server, addr) = server(1).await?;
+
+        tokio::spawn(async move {
+            loop {
+                server.accept().await.unwrap();
+            }
+        });
+
+        let members: BTreeMap<u64, _> = BTreeMap::from([(1, BasicNode::new(addr))]);
+        if let Err(e) = raft.initialize(members).await {
+            match e {
+                openraft::error::RaftError::APIError(InitializeError::NotAllowed(_)) => {}
+                e => panic!("{:?}", e),
+            }
+        }
+
+        let client = RemoteClient::new(addr);
+        let table = Table::from("test");
+        let dropped = Table::from("dropped");
+
+        for round in 0..10 {
+            for key in 0..10 {
+                client
+                    .set(
+                        table.clone(),
+                        format!("key-{key}").into(),
+                        format!("value-{key}-{round}").into(),
+                    )
+                    .await?;
+            }
+        }
+
+        client
+            .batch_set(
+                dropped.clone(),
+                vec![("a".to_string().into(), "b".to_string().into())],
+            )
+            .await?;
+        client.drop_table(dropped.clone()).await?;
+
+        let before = client.inspect().await?;
+        let before_table = before.table(&table).unwrap().clone();
+
+        assert!(before.is_leader());
+        assert_eq!(before_table.num_keys, 10);
+        assert_eq!(before_table.log_entries, 100);
+        assert!(before_table.log_bytes > before_table.data_bytes);
+        assert!(before.table(&dropped).unwrap().log_bytes > 0);
+
+        let after = client.compact().await?;
+        let after_table = after.table(&table).unwrap();
+
+        assert!(after_table.size_bytes() < before_table.size_bytes());
+        assert_eq!(after_table.num_keys, 10);
+        assert_eq!(after_table.data_bytes, before_table.data_bytes);
+        assert_eq!(after_table.log_entries, 0);
+        assert!(after.table(&dropped).is_none());
+        assert!(after.snapshot_index >= before.last_applied_index);
+        assert!(after.log_entries < before.log_entries);
+
+        for key in 0..10 {
+            let value = client
+                .get(table.clone(), format!("key-{key}").into())
+                .await?;
+            assert_eq!(value, Some(Value::from(format!("value-{key}-9"))));
+        }
+
+        Ok(())
+    }
+}
@@ -29,6 +29,7 @@
 mod client;
 pub mod key;
 pub mod log_store;
+pub mod maintenance;
 pub mod network;
 pub mod store;
 pub mod upsert;
@@ -44,7 +45,7 @@ use std::io::Cursor;
 use openraft::TokioRuntime;
 
 pub use key::{Key, KeyTrait};
-pub use value::{Value, ValueTrait};
+pub use value::{Codec, Value, ValueCompression, ValueTrait};
 
 pub use self::network::Server;
 
@@ -174,12 +175,18 @@ pub mod tests {
 
         let network = network::Network;
 
-        let raft = openraft::Raft::new(id, config, network, log_store, state_machine_store.clone())
-            .await?;
+        let raft = openraft::Raft::new(
+            id,
+            config,
+            network,
+            log_store.clone(),
+            state_machine_store.clone(),
+        )
+        .await?;
 
         let addr = free_socket_addr();
 
-        let server = Server::new(raft.clone(), state_machine_store)
+        let server = Server::new(raft.clone(), log_store, state_machine_store)
             .bind(addr)
             .await?;
 
@@ -23,7 +23,12 @@ use std::{
 
 use crate::{
     ampc::dht::{
-        key::Key, store::Table, upsert::UpsertEnum, value::Value, BasicNode, UpsertAction,
+        key::Key,
+        maintenance::{self, NodeStats},
+        store::Table,
+        upsert::{UpsertEnum, WriteId},
+        value::Value,
+        BasicNode, UpsertAction,
     },
     distributed::retry_strategy::RandomBackoff,
     Result,
@@ -55,6 +60,7 @@ pub struct Upsert {
     pub key: Key,
     pub value: Value,
     pub upsert_fn: UpsertEnum,
+    pub write_id: Option<WriteId>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
@@ -62,6 +68,7 @@ pub struct BatchUpsert {
     pub table: Table,
     pub values: Arc<Vec<(Key, Value)>>,
     pub upsert_fn: UpsertEnum,
+    pub write_id: Option<WriteId>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
@@ -107,6 +114,12 @@ pub struct RangeGet {
     pub limit: Option<usize>,
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct Inspect;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct Compact;
+
 impl sonic::service::Message<Server> for Set {
     type Response = Result<
         (),
@@ -292,6 +305,32 @@ impl sonic::service::Message<Server> for RangeGet {
     }
 }
 
+impl sonic::service::Message<Server> for Inspect {
+    type Response = NodeStats;
+
+    async fn handle(self, server: &Server) -> Self::Response {
+        maintenance::stats(&server.raft, &server.log_store, &server.state_machine_store).await
+    }
+}
+
+impl sonic::service::Message<Server> for Compact {
+    type Response = Option<NodeStats>;
+
+    async fn handle(self, server: &Server) -> Self::Response {
+        tracing::info!("compacting the log");
+
+        match maintenance::compact(&server.raft, &server.log_store, &server.state_machine_store)
+            .await
+        {
+            Ok(stats) => Some(stats),
+            Err(e) => {
+                tracing::error!("failed to compact the log: {:?}", e);
+                None
+            }
+        }
+    }
+}
+
 impl std::fmt::Debug for RemoteClient {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RemoteClient")
@@ -459,6 +498,45 @@ impl RemoteClient {
         Err(anyhow!("failed to batch set values"))
     }
 
+    pub async fn inspect(&self) -> Result<NodeStats> {
+        for backoff in Self::retry_strat() {
+            match self
+                .self_remote
+                .send_with_timeout(Inspect, Duration::from_secs(60))
+                .await
+            {
+                Ok(res) => return Ok(res),
+                Err(e) => match e {
+                    sonic::Error::IO(_)
+                    | sonic::Error::ConnectionTimeout
+                    | sonic::Error::RequestTimeout
+                    | sonic::Error::PoolGet => {
+                        tokio::time::sleep(backoff).await;
+                    }
+                    sonic::Error::BadRequest
+                    | sonic::Error::BodyTooLarge {
+                        body_size: _,
+                        max_size: _,
+                    }
+                    | sonic::Error::Application(_) => return Err(e.into()),
+                },
+            }
+        }
+
+        Err(anyhow!("failed to inspect node"))
+    }
+
+    /// Compaction is not retried, as it can take long for a node with a large state.
+    pub async fn compact(&self) -> Result<NodeStats> {
+        self.self_remote
+            .send_with_timeout(
+                Compact,
+                maintenance::COMPACTION_TIMEOUT * 2 + Duration::from_secs(60),
+            )
+            .await?
+            .ok_or_else(|| anyhow!("failed to compact node {}", self.addr()))
+    }
+
     pub async fn num_keys(&self, table: Table) -> Result<u64> {
         for backoff in Self::retry_strat() {
             match self
@@ -822,6 +900,7 @@ impl RemoteClient {
         &self,
         table: Table,
         upsert: F,
+        write_id: Option<WriteId>,
         key: Key,
         value: Value,
     ) -> Result<UpsertAction> {
@@ -840,6 +919,7 @@ impl RemoteClient {
                         key: key.clone(),
                         value: value.clone(),
                         upsert_fn: upsert.clone(),
+                        write_id,
                     },
                     Duration::from_secs(60),
                 )
@@ -898,6 +978,7 @@ impl RemoteClient {
         &self,
         table: Table,
         upsert: F,
+        write_id: Option<WriteId>,
         values: Vec<(Key, Value)>,
     ) -> Result<Vec<(Key, UpsertAction)>> {
         let upsert = upsert.into();
@@ -915,6 +996,7 @@ impl RemoteClient {
                         table: table.clone(),
                         upsert_fn: upsert.clone(),
                         values: values.clone(),
+                        write_id,
                     },
                     Duration::from_secs(60),
                 )
@@ -18,8 +18,8 @@ pub mod api;
 pub mod raft;
 
 use api::{
-    AllTables, BatchGet, BatchSet, BatchUpsert, CloneTable, CreateTable, DropTable, Get, NumKeys,
-    RangeGet, Set, Upsert,
+    AllTables, BatchGet, BatchSet, BatchUpsert, CloneTable, Compact, CreateTable, DropTable, Get,
+    Inspect, NumKeys, RangeGet, Set, Upsert,
 };
 use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
 
@@ -28,7 +28,7 @@ use openraft::{Raft, RaftNetworkFactory};
 use self::raft::RemoteClient;
 use crate::distributed::sonic::service::sonic_service;
 
-use super::{store::StateMachineStore, BasicNode, NodeId, TypeConfig};
+use super::{log_store::LogStore, store::StateMachineStore, BasicNode, NodeId, TypeConfig};
 
 #[derive(Clone)]
 pub struct Network;
@@ -97,18 +97,26 @@ sonic_service!(
         AllTables,
         CloneTable,
         RangeGet,
+        Inspect,
+        Compact,
     ]
 );
 
 pub struct Server {
     raft: Raft<TypeConfig>,
+    log_store: LogStore<TypeConfig>,
     state_machine_store: Arc<StateMachineStore>,
 }
 
 impl Server {
-    pub fn new(raft: Raft<TypeConfig>, state_machine_store: Arc<StateMachineStore>) -> Self {
+    pub fn new(
+        raft: Raft<TypeConfig>,
+        log_store: LogStore<TypeConfig>,
+        state_machine_store: Arc<StateMachineStore>,
+    ) -> Self {
         Self {
             raft,
+            log_store,
             state_machine_store,
         }
     }
@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::fmt::Debug;
 use std::io::Cursor;
 use std::ops::Bound;
@@ -37,8 +38,10 @@ use tokio::sync::RwLock;
 use crate::ampc::dht::network::api;
 
 use super::key::Key;
+use super::maintenance::encoded_len;
 use super::upsert::UpsertEnum;
 use super::upsert::UpsertFn;
+use super::upsert::WriteId;
 use super::value::Value;
 use super::BasicNode;
 use super::NodeId;
@@ -79,15 +82,30 @@ impl From<&str> for Table {
     }
 }
 
+/// Upsert functions only know how to merge plain values, so compressed values
+/// are decompressed before merging. The merged value is compressed again
+/// if the stored value was compressed. Fails if either value is corrupt.
+fn merge_values(upsert_fn: &UpsertEnum, old: &Value, new: Value) -> crate::Result<Value> {
+    let codec = old.codec();
+    let merged = upsert_fn.upsert(old.clone().decompressed()?, new.decompressed()?);
+
+    Ok(match codec {
+        Some(codec) => merged.compressed(codec),
+        None => merged,
+    })
+}
+
 #[derive(
     serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Debug, Default, Clone,
 )]
 pub struct Db {
     data: BTreeMap<Table, BTreeMap<Key, Value>>,
+    applied_writes: BTreeMap<Table, BTreeSet<WriteId>>,
 }
 
 impl Db {
     pub fn drop_table(&mut self, table: &Table) {
+        self.applied_writes.remove(table);
         let table = self.data.remove(table);
         if let Some(table) = table {
             // drop in background as some tables can be large
@@ -125,6 +143,18 @@ impl Db {
         self.data.get(table).map(|m| m.len()).unwrap_or(0)
     }
 
+    /// Encoded size of the keys and values in the table.
+    pub fn data_bytes(&self, table: &Table) -> u64 {
+        self.data
+            .get(table)
+            .map(|m| {
+                m.iter()
+                    .map(|(key, value)| encoded_len(key) + encoded_len(value))
+                    .sum()
+            })
+            .unwrap_or(0)
+    }
+
     pub fn upsert(
         &mut self,
         table: Table,
@@ -136,7 +166,13 @@ impl Db {
 
         match table.get_mut(&key) {
             Some(old) => {
-                let merged = upsert_fn.upsert(old.clone(), value);
+                let merged = match merge_values(upsert_fn, old, value) {
+                    Ok(merged) => merged,
+                    Err(err) => {
+                        tracing::error!("failed to merge values: {}", err);
+                        return UpsertAction::NoChange;
+                    }
+                };
 
                 let has_changed = merged != *old;
 
@@ -155,6 +191,47 @@ impl Db {
         }
     }
 
+    /// Record that `write_id` has been applied to `table`.
+    /// Returns `false` if the write has already been applied.
+    fn mark_applied(&mut self, table: &Table, write_id: WriteId) -> bool {
+        self.applied_writes
+            .entry(table.clone())
+            .or_default()
+            .insert(write_id)
+    }
+
+    pub fn upsert_once(
+        &mut self,
+        table: Table,
+        upsert_fn: &UpsertEnum,
+        write_id: WriteId,
+        key: Key,
+        value: Value,
+    ) -> UpsertAction {
+        if !self.mark_applied(&table, write_id) {
+            return UpsertAction::NoChange;
+        }
+
+        self.upsert(table, upsert_fn, key, value)
+    }
+
+    pub fn batch_upsert_once(
+        &mut self,
+        table: Table,
+        upsert_fn: &UpsertEnum,
+        write_id: WriteId,
+        values: Vec<(Key, Value)>,
+    ) -> Vec<(Key, UpsertAction)> {
+        if !self.mark_applied(&table, write_id) {
+            return values
+                .into_iter()
+                .map(|(key, _)| (key, UpsertAction::NoChange))
+                .collect();
+        }
+
+        self.batch_upsert(table, upsert_fn, values)
+    }
+
     pub fn batch_upsert(
         &mut self,
         table: Table,
@@ -167,7 +244,14 @@ impl Db {
         for (key, value) in values {
             match table.get_mut(&key) {
                 Some(old) => {
-                    let merged = upsert_fn.upsert(old.clone(), value);
+                    let merged = match merge_values(upsert_fn, old, value) {
+                        Ok(merged) => merged,
+                        Err(err) => {
+                            tracing::error!("failed to merge values: {}", err);
+                            res.push((key, UpsertAction::NoChange));
+                            continue;
+                        }
+                    };
                     let has_changed = merged != *old;
 
                     *old = merged;
@@ -188,12 +272,20 @@ impl Db {
         res
     }
 
+    /// Note that the applied write ids are not cloned. The new table
+    /// is typically used for the next round, where the same jobs
+    /// must be able to write to it again. The round that wrote to `from`
+    /// is over, so its write ids are dropped as well to keep them from
+    /// growing over the rounds.
     pub fn clone_table(&mut self, from: &Table, to: Table) {
         let data = self.data.get(from).cloned().unwrap_or_default();
+        self.applied_writes.remove(from);
+        self.applied_writes.remove(&to);
         self.data.insert(to, data);
     }
 
     pub fn new_table(&mut self, table: Table) {
+        self.applied_writes.remove(&table);
         self.data.insert(table, BTreeMap::new());
     }
 
@@ -367,21 +459,46 @@ impl RaftStateMachine<TypeConfig> for Arc<StateMachineStore> {
                         key,
                         value,
                         upsert_fn,
-                    }) => res.push(Response::Upsert(Ok(sm.db.upsert(
-                        table.clone(),
-                        upsert_fn,
-                        key.clone(),
-                        value.clone(),
-                    )))),
+                        write_id,
+                    }) => {
+                        let action = match write_id {
+                            Some(write_id) => sm.db.upsert_once(
+                                table.clone(),
+                                upsert_fn,
+                                *write_id,
+                                key.clone(),
+                                value.clone(),
+                            ),
+                            None => {
+                                sm.db
+                                    .upsert(table.clone(), upsert_fn, key.clone(), value.clone())
+                            }
+                        };
+
+                        res.push(Response::Upsert(Ok(action)))
+                    }
                     Request::BatchUpsert(api::BatchUpsert {
                         table,
                         upsert_fn,
                         values,
-                    }) => res.push(Response::BatchUpsert(Ok(sm.db.batch_upsert(
-                        table.clone(),
-                        upsert_fn,
-                        values.as_ref().clone(),
-                    )))),
+                        write_id,
+                    }) => {
+                        let actions = match write_id {
+                            Some(write_id) => sm.db.batch_upsert_once(
+                                table.clone(),
+                                upsert_fn,
+                                *write_id,
+                                values.as_ref().clone(),
+                            ),
+                            None => sm.db.batch_upsert(
+                                table.clone(),
+                                upsert_fn,
+                                values.as_ref().clone(),
+                            ),
+                        };
+
+                        res.push(Response::BatchUpsert(Ok(actions)))
+                    }
                     Request::CreateTable(api::CreateTable { table }) => {
                         sm.db.new_table(table.clone());
                         res.push(Response::CreateTable(Ok(())))
@@ -27,9 +27,49 @@ pub enum UpsertAction {
     Inserted,
 }
 
+/// Identifies a single write performed by a mapper.
+///
+/// The DHT remembers which write ids it has applied to a table and silently
+/// skips an upsert whose id it has already seen. A retried mapper that derives
+/// the same ids for the same writes (e.g. `job_id` from the job and `mapper_key`
+/// from the index of the batch being written) will therefore never apply a write twice.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct WriteId {
+    pub job_id: u64,
+    pub mapper_key: u64,
+}
+
+impl WriteId {
+    pub fn new(job_id: u64, mapper_key: u64) -> Self {
+        Self { job_id, mapper_key }
+    }
+}
+
 #[enum_dispatch]
 pub trait UpsertFn {
     fn upsert(&self, old: Value, new: Value) -> Value;
+
+    /// Whether applying the same value twice yields the same result as applying it once.
+    ///
+    /// Idempotent upserts (like hyperloglog merges) are always safe to retry.
+    /// Non-idempotent upserts (like sums) must be given a [`WriteId`] if the
+    /// mapper performing them can be rescheduled.
+    fn is_idempotent(&self) -> bool {
+        false
+    }
 }
 
 #[enum_dispatch(UpsertFn)]
@@ -72,6 +112,10 @@ macro_rules! hyperloglog_upsert {
 
                 Value::$variant(old)
             }
+
+            fn is_idempotent(&self) -> bool {
+                true
+            }
         }
     };
 }
@@ -16,7 +16,7 @@
 
 use bloom::U64BloomFilter;
 
-use crate::kahan_sum::KahanSum;
+use crate::{kahan_sum::KahanSum, Result};
 
 pub trait ValueTrait: TryFrom<Value> + Into<Value> {}
 
@@ -73,6 +73,160 @@ pub enum Value {
     HarmonicMeta(HarmonicMeta),
     U64BloomFilter(U64BloomFilter),
     Unit(Unit),
+    Compressed(CompressedValue),
+}
+
+impl Value {
+    pub fn codec(&self) -> Option<Codec> {
+        match self {
+            Value::Compressed(compressed) => Some(compressed.codec),
+            _ => None,
+        }
+    }
+
+    /// Fails if the value is compressed and its data is corrupt.
+    pub fn decompressed(self) -> Result<Value> {
+        match self {
+            Value::Compressed(compressed) => compressed.decompress(),
+            value => Ok(value),
+        }
+    }
+
+    pub fn compressed(self, codec: Codec) -> Value {
+        match self {
+            Value::Compressed(compressed) => Value::Compressed(compressed),
+            value => match CompressedValue::new(codec, &value) {
+                Some(compressed) => Value::Compressed(compressed),
+                None => value,
+            },
+        }
+    }
+}
+
+/// Zstd is only built with the `dht-zstd` feature. Values are stored uncompressed
+/// if the codec of their table is not built.
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+pub enum Codec {
+    Lz4,
+    Zstd,
+}
+
+impl Codec {
+    /// `None` if the binary was built without the codec.
+    fn compress(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Codec::Lz4 => Some(lz4_flex::compress_prepend_size(bytes)),
+            #[cfg(feature = "dht-zstd")]
+            Codec::Zstd => Some(zstd::encode_all(bytes, zstd::DEFAULT_COMPRESSION_LEVEL).unwrap()),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            Codec::Lz4 => lz4_flex::decompress_size_prepended(bytes)?,
+            #[cfg(feature = "dht-zstd")]
+            Codec::Zstd => zstd::decode_all(bytes)?,
+            #[allow(unreachable_patterns)]
+            codec => anyhow::bail!("built without the {:?} codec", codec),
+        })
+    }
+}
+
+/// Per-table configuration of how values are compressed before they are sent to the DHT.
+#[derive(
+    serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Debug, Clone, Copy,
+)]
+pub struct ValueCompression {
+    codec: Codec,
+    threshold: usize,
+}
+
+impl ValueCompression {
+    /// Values smaller than this (in bytes) are not worth the overhead of compressing.
+    pub const DEFAULT_THRESHOLD: usize = 1024;
+
+    pub fn new(codec: Codec) -> Self {
+        Self {
+            codec,
+            threshold: Self::DEFAULT_THRESHOLD,
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    pub fn compress(&self, value: Value) -> Value {
+        if matches!(value, Value::Compressed(_)) {
+            return value;
+        }
+
+        let bytes = bincode::encode_to_vec(&value, bincode::config::standard()).unwrap();
+
+        if bytes.len() < self.threshold {
+            return value;
+        }
+
+        match CompressedValue::from_encoded(self.codec, &bytes) {
+            Some(compressed) => Value::Compressed(compressed),
+            None => value,
+        }
+    }
+}
+
+#[derive(
+    serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Debug, Clone, PartialEq,
+)]
+pub struct CompressedValue {
+    codec: Codec,
+    data: Vec<u8>,
+}
+
+impl CompressedValue {
+    /// `None` if the binary was built without the codec.
+    pub fn new(codec: Codec, value: &Value) -> Option<Self> {
+        let bytes = bincode::encode_to_vec(value, bincode::config::standard()).unwrap();
+        Self::from_encoded(codec, &bytes)
+    }
+
+    fn from_encoded(codec: Codec, bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            codec,
+            data: codec.compress(bytes)?,
+        })
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    pub fn num_bytes(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn decompress(&self) -> Result<Value> {
+        let bytes = self.codec.decompress(&self.data)?;
+        let (value, _) = bincode::decode_from_slice(&bytes, bincode::config::standard())?;
+
+        Ok(value)
+    }
 }
 
 macro_rules! impl_from_to_value {
@@ -110,3 +264,29 @@ impl_from_to_value!(HyperLogLog128, HyperLogLog128);
 impl_from_to_value!(HarmonicMeta, HarmonicMeta);
 impl_from_to_value!(U64BloomFilter, U64BloomFilter);
 impl_from_to_value!(Unit, Unit);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrupt_values_fail_to_decompress() {
+        let value = Value::String("a".repeat(4096));
+
+        for codec in [
+            Codec::Lz4,
+            #[cfg(feature = "dht-zstd")]
+            Codec::Zstd,
+        ] {
+            let compressed = CompressedValue::new(codec, &value).unwrap();
+            assert_eq!(compressed.decompress().unwrap(), value);
+
+            let truncated = CompressedValue {
+                codec,
+                data: compressed.data[..compressed.data.len() / 2].to_vec(),
+            };
+            assert!(truncated.decompress().is_err());
+            assert!(Value::Compressed(truncated).decompressed().is_err());
+        }
+    }
+}
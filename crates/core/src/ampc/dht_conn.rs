@@ -17,7 +17,11 @@
 use crate::block_on;
 use std::{collections::BTreeMap, net::SocketAddr, pin::Pin};
 
-use super::dht::{self, upsert::UpsertEnum, KeyTrait, UpsertAction, ValueTrait};
+use super::dht::{
+    self,
+    upsert::{UpsertEnum, WriteId},
+    KeyTrait, UpsertAction, ValueCompression, ValueTrait,
+};
 
 use crate::Result;
 
@@ -28,6 +32,9 @@ where
     fn drop_tables(&self);
     fn next(&self) -> Self;
     fn cleanup_prev_tables(&self);
+
+    /// The total number of keys in all the tables.
+    fn num_keys(&self) -> u64;
 }
 
 // TODO: this could be a derive proc macro instead
@@ -58,6 +65,10 @@ macro_rules! impl_dht_tables {
                     }
                 )*
             }
+
+            fn num_keys(&self) -> u64 {
+                0 $(+ self.$field.num_keys())*
+            }
         }
     };
 }
@@ -100,6 +111,7 @@ impl Table {
 pub struct DefaultDhtTable<K, V> {
     table: Table,
     client: dht::Client,
+    compression: Option<ValueCompression>,
     _maker: std::marker::PhantomData<(K, V)>,
 }
 
@@ -109,7 +121,8 @@ impl<K, V> bincode::Encode for DefaultDhtTable<K, V> {
         encoder: &mut E,
     ) -> std::prelude::v1::Result<(), bincode::error::EncodeError> {
         self.table.encode(encoder)?;
-        self.client.encode(encoder)
+        self.client.encode(encoder)?;
+        self.compression.encode(encoder)
     }
 }
 
@@ -119,10 +132,12 @@ impl<K, V> bincode::Decode for DefaultDhtTable<K, V> {
     ) -> std::prelude::v1::Result<Self, bincode::error::DecodeError> {
         let table = Table::decode(decoder)?;
         let client = dht::Client::decode(decoder)?;
+        let compression = Option::<ValueCompression>::decode(decoder)?;
 
         Ok(Self {
             table,
             client,
+            compression,
             _maker: std::marker::PhantomData,
         })
     }
@@ -134,10 +149,12 @@ impl<'de, K, V> bincode::BorrowDecode<'de> for DefaultDhtTable<K, V> {
     ) -> std::prelude::v1::Result<Self, bincode::error::DecodeError> {
         let table = Table::borrow_decode(decoder)?;
         let client = dht::Client::borrow_decode(decoder)?;
+        let compression = Option::<ValueCompression>::borrow_decode(decoder)?;
 
         Ok(Self {
             table,
             client,
+            compression,
             _maker: std::marker::PhantomData,
         })
     }
@@ -148,6 +165,7 @@ impl<K, V> Clone for DefaultDhtTable<K, V> {
         Self {
             table: self.table.clone(),
             client: self.client.clone(),
+            compression: self.compression,
             _maker: std::marker::PhantomData,
         }
     }
@@ -161,15 +179,36 @@ where
         Self {
             table: Table::new(prefix),
             client: dht::Client::new(members),
+            compression: None,
             _maker: std::marker::PhantomData,
         }
     }
 
+    /// Compress values larger than the compression threshold before they are sent to the DHT.
+    /// Values are transparently decompressed when read.
+    pub fn with_compression(mut self, compression: ValueCompression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
     pub fn shards(&self) -> &BTreeMap<dht::ShardId, dht::Shard> {
         self.client.shards()
     }
 }
 
+/// A typed view of a table in the DHT.
+///
+/// Mappers can be rescheduled by the coordinator if their worker fails, in which case
+/// every write the mapper made before failing will be made again. The following
+/// operations are safe to retry:
+///  * `set` and `batch_set`, as long as the retried mapper writes the same values.
+///  * `upsert` and `batch_upsert` with an idempotent upsert function
+///    (see [`dht::UpsertFn::is_idempotent`]), e.g. hyperloglog merges.
+///  * `upsert_once` and `batch_upsert_once` for any upsert function, as long as the retried
+///    mapper uses the same [`WriteId`] for the same write.
+///
+/// Using `upsert` or `batch_upsert` with a non-idempotent upsert function (e.g. sums)
+/// will double-apply the writes that happened before the failure.
 pub trait DhtTable: Clone + bincode::Encode + bincode::Decode {
     type Key: KeyTrait;
     type Value: ValueTrait;
@@ -178,12 +217,28 @@ pub trait DhtTable: Clone + bincode::Encode + bincode::Decode {
     fn table(&self) -> &Table;
     fn next(&self) -> Self;
 
+    fn compression(&self) -> Option<ValueCompression> {
+        None
+    }
+
+    fn encode_value(&self, value: Self::Value) -> dht::Value {
+        let value: dht::Value = value.into();
+
+        match self.compression() {
+            Some(compression) => compression.compress(value),
+            None => value,
+        }
+    }
+
     fn get(&self, key: Self::Key) -> Option<Self::Value> {
         block_on(self.client().get(self.table().dht(), key.into()))
             .unwrap()
             .map(|v| {
-                Self::Value::try_from(v)
-                    .map_err(|_| anyhow!("unexpected value type in DHT table get"))
+                v.decompressed()
+                    .and_then(|v| {
+                        Self::Value::try_from(v)
+                            .map_err(|_| anyhow!("unexpected value type in DHT table get"))
+                    })
                     .unwrap()
             })
     }
@@ -199,8 +254,12 @@ pub trait DhtTable: Clone + bincode::Encode + bincode::Decode {
                     .map_err(|_| anyhow!("unexpected key type in DHT table batch-get"))
                     .unwrap();
 
-                let v = Self::Value::try_from(v)
-                    .map_err(|_| anyhow!("unexpected value type in DHT table batch-get"))
+                let v = v
+                    .decompressed()
+                    .and_then(|v| {
+                        Self::Value::try_from(v)
+                            .map_err(|_| anyhow!("unexpected value type in DHT table batch-get"))
+                    })
                     .unwrap();
 
                 (k, v)
@@ -211,7 +270,7 @@ pub trait DhtTable: Clone + bincode::Encode + bincode::Decode {
     fn set(&self, key: Self::Key, value: Self::Value) {
         block_on(
             self.client()
-                .set(self.table().dht(), key.into(), value.into()),
+                .set(self.table().dht(), key.into(), self.encode_value(value)),
         )
         .unwrap();
     }
@@ -219,7 +278,7 @@ pub trait DhtTable: Clone + bincode::Encode + bincode::Decode {
     fn batch_set(&self, pairs: Vec<(Self::Key, Self::Value)>) {
         let pairs: Vec<(dht::Key, dht::Value)> = pairs
             .into_iter()
-            .map(|(k, v)| (k.into(), v.into()))
+            .map(|(k, v)| (k.into(), self.encode_value(v)))
             .collect();
 
         block_on(self.client().batch_set(self.table().dht(), pairs)).unwrap();
@@ -235,10 +294,32 @@ pub trait DhtTable: Clone + bincode::Encode + bincode::Decode {
         key: Self::Key,
         value: Self::Value,
     ) -> UpsertAction {
-        block_on(
-            self.client()
-                .upsert(self.table().dht(), upsert, key.into(), value.into()),
-        )
+        block_on(self.client().upsert(
+            self.table().dht(),
+            upsert,
+            None,
+            key.into(),
+            self.encode_value(value),
+        ))
+        .unwrap()
+    }
+
+    /// Upsert the value unless a write with the same `write_id` has
+    /// already been applied to the table.
+    fn upsert_once<F: Into<UpsertEnum>>(
+        &self,
+        upsert: F,
+        write_id: WriteId,
+        key: Self::Key,
+        value: Self::Value,
+    ) -> UpsertAction {
+        block_on(self.client().upsert(
+            self.table().dht(),
+            upsert,
+            Some(write_id),
+            key.into(),
+            self.encode_value(value),
+        ))
         .unwrap()
     }
 
@@ -246,15 +327,37 @@ pub trait DhtTable: Clone + bincode::Encode + bincode::Decode {
         &self,
         upsert: F,
         pairs: Vec<(Self::Key, Self::Value)>,
+    ) -> Vec<(Self::Key, UpsertAction)> {
+        self.raw_batch_upsert(upsert, None, pairs)
+    }
+
+    /// Upsert the batch unless a write with the same `write_id` has
+    /// already been applied to the table. The batch is split between the shards
+    /// and each shard keeps track of its part of the write, so a retry will
+    /// only apply the parts that didn't make it the first time.
+    fn batch_upsert_once<F: Into<UpsertEnum> + Clone>(
+        &self,
+        upsert: F,
+        write_id: WriteId,
+        pairs: Vec<(Self::Key, Self::Value)>,
+    ) -> Vec<(Self::Key, UpsertAction)> {
+        self.raw_batch_upsert(upsert, Some(write_id), pairs)
+    }
+
+    fn raw_batch_upsert<F: Into<UpsertEnum> + Clone>(
+        &self,
+        upsert: F,
+        write_id: Option<WriteId>,
+        pairs: Vec<(Self::Key, Self::Value)>,
     ) -> Vec<(Self::Key, UpsertAction)> {
         let pairs: Vec<(dht::Key, dht::Value)> = pairs
             .into_iter()
-            .map(|(k, v)| (k.into(), v.into()))
+            .map(|(k, v)| (k.into(), self.encode_value(v)))
             .collect();
 
         block_on(
             self.client()
-                .batch_upsert(self.table().dht(), upsert, pairs),
+                .batch_upsert(self.table().dht(), upsert, write_id, pairs),
         )
         .unwrap()
         .into_iter()
@@ -291,8 +394,12 @@ pub trait DhtTable: Clone + bincode::Encode + bincode::Decode {
                 .map_err(|_| anyhow!("unexpected key type in DHT table iter"))
                 .unwrap();
 
-            let value = Self::Value::try_from(value)
-                .map_err(|_| anyhow!("unexpected value type in DHT table iter"))
+            let value = value
+                .decompressed()
+                .and_then(|value| {
+                    Self::Value::try_from(value)
+                        .map_err(|_| anyhow!("unexpected value type in DHT table iter"))
+                })
                 .unwrap();
 
             (key, value)
@@ -370,10 +477,15 @@ where
         &self.table
     }
 
+    fn compression(&self) -> Option<ValueCompression> {
+        self.compression
+    }
+
     fn next(&self) -> DefaultDhtTable<Self::Key, Self::Value> {
         let new = Self {
             table: self.table().next(),
             client: self.client().clone(),
+            compression: self.compression,
             _maker: std::marker::PhantomData,
         };
 
@@ -510,4 +622,116 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    #[traced_test]
+    fn test_retried_mapper_is_idempotent() -> anyhow::Result<()> {
+        let addr = start_dht_background();
+        const JOB_ID: u64 = 0;
+
+        let clean = Tables {
+            id: DefaultDhtTable::new(&[(1.into(), addr)], "clean"),
+        };
+
+        let retried = Tables {
+            id: DefaultDhtTable::new(&[(1.into(), addr)], "retried"),
+        };
+
+        let batches: Vec<Vec<(Id, Counter)>> = (0..4)
+            .map(|batch| (0..8).map(|key| (key, batch + 1)).collect())
+            .collect();
+
+        // writes all batches unless it fails before writing batch `fail_at`
+        let map = |tables: &Tables, fail_at: Option<usize>| {
+            for (i, batch) in batches.iter().enumerate() {
+                if fail_at == Some(i) {
+                    return;
+                }
+
+                tables.id.batch_upsert_once(
+                    upsert::U64Add,
+                    WriteId::new(JOB_ID, i as u64),
+                    batch.clone(),
+                );
+            }
+        };
+
+        map(&clean, None);
+
+        map(&retried, Some(2));
+        map(&retried, None);
+
+        let mut expected: Vec<_> = clean.id.iter().collect();
+        expected.sort();
+
+        let mut res: Vec<_> = retried.id.iter().collect();
+        res.sort();
+
+        assert_eq!(res, expected);
+        assert_eq!(res.len(), 8);
+        assert!(res.iter().all(|(_, counter)| *counter == 1 + 2 + 3 + 4));
+
+        // the same job in the next round should be able to write again
+        let next = retried.next();
+        map(&next, None);
+
+        assert!(next
+            .id
+            .iter()
+            .all(|(_, counter)| counter == 2 * (1 + 2 + 3 + 4)));
+
+        Ok(())
+    }
+
+    #[test]
+    #[traced_test]
+    fn test_compressed_values() -> anyhow::Result<()> {
+        let addr = start_dht_background();
+
+        for codec in [
+            dht::Codec::Lz4,
+            #[cfg(feature = "dht-zstd")]
+            dht::Codec::Zstd,
+        ] {
+            let table: DefaultDhtTable<Id, String> =
+                DefaultDhtTable::new(&[(1.into(), addr)], format!("compressed-{:?}", codec))
+                    .with_compression(ValueCompression::new(codec));
+
+            let large = "stract ".repeat(10_000);
+            let small = "small".to_string();
+
+            table.set(0, large.clone());
+            table.batch_set(vec![(1, small.clone()), (2, large.clone())]);
+
+            assert_eq!(table.get(0), Some(large.clone()));
+
+            let mut res = table.batch_get(vec![0, 1, 2]);
+            res.sort_by(|(a, _), (b, _)| a.cmp(b));
+            assert_eq!(
+                res,
+                vec![(0, large.clone()), (1, small.clone()), (2, large.clone())]
+            );
+
+            let raw = |key: Id| {
+                block_on(table.client().get(table.table().dht(), key.into()))
+                    .unwrap()
+                    .unwrap()
+            };
+
+            match raw(0) {
+                dht::Value::Compressed(compressed) => {
+                    assert_eq!(compressed.codec(), codec);
+                    assert!(compressed.num_bytes() < large.len() / 10);
+                }
+                value => panic!("expected large value to be compressed, got {:?}", value),
+            }
+
+            assert_eq!(raw(1), dht::Value::String(small.clone()));
+
+            let next = table.next();
+            assert_eq!(next.get(2), Some(large.clone()));
+        }
+
+        Ok(())
+    }
 }
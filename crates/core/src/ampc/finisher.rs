@@ -14,10 +14,160 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licensepub trait Finisher {
 
+use std::marker::PhantomData;
+
 use super::prelude::Job;
 
 pub trait Finisher {
     type Job: Job;
 
     fn is_finished(&self, dht: &<<Self as Finisher>::Job as Job>::DhtTables) -> bool;
+
+    /// Called by the coordinator after each round with the tables the round read from (`prev`)
+    /// and the tables it wrote to (`next`). Rounds are counted from 1.
+    /// Returning `true` stops the computation after the round.
+    #[allow(unused_variables)] // reason = "tables might be used by implementors"
+    fn is_converged(
+        &self,
+        round: usize,
+        prev: &<<Self as Finisher>::Job as Job>::DhtTables,
+        next: &<<Self as Finisher>::Job as Job>::DhtTables,
+    ) -> bool {
+        false
+    }
+
+    /// The number of rounds that are expected to run after `round`, if the finisher can
+    /// tell. `dht` holds the tables written by `round`. Only used to report progress.
+    #[allow(unused_variables)] // reason = "arguments might be used by implementors"
+    fn estimated_rounds_remaining(
+        &self,
+        round: usize,
+        dht: &<<Self as Finisher>::Job as Job>::DhtTables,
+    ) -> Option<usize> {
+        None
+    }
+}
+
+/// Finishes an iterative computation once the change between two consecutive rounds
+/// falls below `epsilon`, or when `max_rounds` rounds have been run.
+///
+/// The change is computed by the caller supplied `delta` function from the
+/// previous and current tables.
+pub struct ConvergenceFinisher<J, F> {
+    delta: F,
+    epsilon: f64,
+    max_rounds: Option<usize>,
+    _job: PhantomData<J>,
+}
+
+impl<J, F> ConvergenceFinisher<J, F>
+where
+    J: Job,
+    F: Fn(&J::DhtTables, &J::DhtTables) -> f64,
+{
+    pub fn new(delta: F, epsilon: f64) -> Self {
+        Self {
+            delta,
+            epsilon,
+            max_rounds: None,
+            _job: PhantomData,
+        }
+    }
+
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = Some(max_rounds);
+        self
+    }
+
+    fn should_stop(&self, round: usize, delta: f64) -> bool {
+        if delta < self.epsilon {
+            return true;
+        }
+
+        match self.max_rounds {
+            Some(max_rounds) => round >= max_rounds,
+            None => false,
+        }
+    }
+}
+
+impl<J, F> Finisher for ConvergenceFinisher<J, F>
+where
+    J: Job,
+    F: Fn(&J::DhtTables, &J::DhtTables) -> f64,
+{
+    type Job = J;
+
+    fn is_finished(&self, _: &J::DhtTables) -> bool {
+        // the first round always needs to run before there is anything to compare
+        false
+    }
+
+    fn is_converged(&self, round: usize, prev: &J::DhtTables, next: &J::DhtTables) -> bool {
+        let delta = (self.delta)(prev, next);
+        tracing::info!("round {} changed by {}", round, delta);
+
+        self.should_stop(round, delta)
+    }
+
+    fn estimated_rounds_remaining(&self, round: usize, _: &J::DhtTables) -> Option<usize> {
+        self.max_rounds
+            .map(|max_rounds| max_rounds.saturating_sub(round))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::ampc::prelude::Setup;
+    use crate::entrypoint::ampc::harmonic_centrality::{
+        CentralityJob, CentralitySetup, CentralityTables,
+    };
+
+    use super::*;
+
+    /// Runs the finisher the way the coordinator does, where the values
+    /// in the tables after round `r` are `1/2^r`.
+    fn rounds_until_finished<F>(finisher: &F) -> usize
+    where
+        F: Finisher<Job = CentralityJob>,
+    {
+        let dht = CentralitySetup::new_for_dht_members(&[], vec![]).init_dht();
+        let mut round = 0;
+
+        while !finisher.is_finished(dht.prev()) {
+            round += 1;
+
+            if finisher.is_converged(round, dht.prev(), dht.next()) {
+                break;
+            }
+        }
+
+        round
+    }
+
+    #[test]
+    fn test_convergence_finisher() {
+        let round = Cell::new(0);
+        let delta = |_: &CentralityTables, _: &CentralityTables| {
+            round.set(round.get() + 1);
+            let prev = 0.5f64.powi(round.get() - 1);
+            let next = 0.5f64.powi(round.get());
+
+            (prev - next).abs()
+        };
+
+        // deltas are 0.5, 0.25, 0.125, 0.0625, 0.03125, 0.015625, 0.0078125, ...
+        let finisher = ConvergenceFinisher::<CentralityJob, _>::new(delta, 0.01);
+        assert_eq!(rounds_until_finished(&finisher), 7);
+
+        round.set(0);
+        let finisher = ConvergenceFinisher::<CentralityJob, _>::new(delta, 0.01).with_max_rounds(3);
+        assert_eq!(rounds_until_finished(&finisher), 3);
+
+        round.set(0);
+        let finisher = ConvergenceFinisher::<CentralityJob, _>::new(delta, 1.0);
+        assert_eq!(rounds_until_finished(&finisher), 1);
+    }
 }
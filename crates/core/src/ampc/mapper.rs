@@ -16,6 +16,11 @@
 
 use super::{prelude::Job, DhtConn};
 
+/// A mapper is run by a worker for each job in a round.
+///
+/// If the worker fails, the coordinator reschedules the job on another worker
+/// and the mapper is run again from the start. Writes to the DHT must therefore be
+/// safe to retry. See [`super::DhtTable`] for which operations are.
 pub trait Mapper: bincode::Encode + bincode::Decode + Send + Sync + Clone {
     type Job: Job<Mapper = Self>;
 
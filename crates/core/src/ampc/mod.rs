@@ -24,6 +24,7 @@ mod finisher;
 mod job;
 mod mapper;
 pub mod prelude;
+pub mod progress;
 mod server;
 mod setup;
 mod worker;
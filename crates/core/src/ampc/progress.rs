@@ -0,0 +1,225 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Progress of an AMPC computation as tracked by the [`super::Coordinator`].
+//!
+//! The coordinator can write its progress to a status file after every change. The file is
+//! replaced atomically, so `stract ampc status <file>` can read it at any time while the
+//! computation runs.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::Result;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RoundProgress {
+    pub round: usize,
+    pub mappers_completed: usize,
+    pub jobs_scheduled: usize,
+    pub jobs_completed: usize,
+    /// Jobs whose worker stopped responding. They are rescheduled on another worker.
+    pub jobs_failed: usize,
+    /// The number of keys in the tables written by the round once it has completed.
+    pub dht_keys_written: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Progress {
+    pub num_workers: usize,
+    pub num_mappers: usize,
+    pub jobs_per_mapper: usize,
+    pub rounds: Vec<RoundProgress>,
+    /// Estimated by the [`super::Finisher`] of the computation if it knows.
+    pub estimated_rounds_remaining: Option<usize>,
+    pub finished: bool,
+    /// Seconds since the unix epoch of the last update.
+    pub updated_at: u64,
+}
+
+impl Progress {
+    pub fn new(num_workers: usize, num_mappers: usize, jobs_per_mapper: usize) -> Self {
+        Self {
+            num_workers,
+            num_mappers,
+            jobs_per_mapper,
+            ..Default::default()
+        }
+    }
+
+    pub fn start_round(&mut self, round: usize) {
+        self.rounds.push(RoundProgress {
+            round,
+            ..Default::default()
+        });
+    }
+
+    /// The progress of the round currently running. Counters are only updated during a round.
+    pub fn current(&mut self) -> &mut RoundProgress {
+        self.rounds
+            .last_mut()
+            .expect("a round must be started before it is updated")
+    }
+
+    pub fn render(&self) -> String {
+        let mut res = format!(
+            "{:>6} {:>8} {:>10} {:>10} {:>7} {:>12}\n",
+            "round", "mappers", "scheduled", "completed", "failed", "keys written"
+        );
+
+        for round in &self.rounds {
+            res.push_str(&format!(
+                "{:>6} {:>8} {:>10} {:>10} {:>7} {:>12}\n",
+                round.round,
+                format!("{}/{}", round.mappers_completed, self.num_mappers),
+                round.jobs_scheduled,
+                format!(
+                    "{}/{}",
+                    round.jobs_completed,
+                    self.jobs_per_mapper * self.num_mappers
+                ),
+                round.jobs_failed,
+                round
+                    .dht_keys_written
+                    .map(|keys| keys.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            ));
+        }
+
+        res.push('\n');
+        res.push_str(&format!("workers: {}\n", self.num_workers));
+
+        if self.finished {
+            res.push_str("status: finished\n");
+        } else {
+            res.push_str("status: running\n");
+
+            if let Some(remaining) = self.estimated_rounds_remaining {
+                res.push_str(&format!("estimated rounds remaining: {remaining}\n"));
+            }
+        }
+
+        res
+    }
+}
+
+/// A status file that is written by the coordinator and read by the status command.
+pub struct StatusFile {
+    path: PathBuf,
+}
+
+impl StatusFile {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Replaces the content of the file. The progress is written to a temporary file next to
+    /// the status file which is then renamed, so readers never see a partially written file.
+    pub fn write(&self, progress: &Progress) -> Result<()> {
+        let mut progress = progress.clone();
+        progress.updated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+
+        std::fs::write(&tmp, serde_json::to_vec(&progress)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+
+        Ok(())
+    }
+
+    pub fn read(&self) -> Result<Progress> {
+        Ok(serde_json::from_slice(&std::fs::read(&self.path)?)?)
+    }
+}
+
+/// Detects jobs that are stuck on a worker that stopped responding.
+///
+/// Every successful response to a status request from a worker is a heartbeat, whether the
+/// worker is still running the job or not. A job is stalled when its worker has not sent a
+/// heartbeat for longer than the timeout. Transient errors from a worker therefore don't
+/// cause its job to be run twice, as long as the worker recovers within the timeout.
+#[derive(Debug, Clone, Copy)]
+pub struct StallDetector {
+    timeout: Duration,
+}
+
+impl StallDetector {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+
+    pub fn is_stalled(&self, last_heartbeat: Instant, now: Instant) -> bool {
+        now.saturating_duration_since(last_heartbeat) > self.timeout
+    }
+}
+
+impl Default for StallDetector {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_file_roundtrip() {
+        let path = crate::gen_temp_path().join("status.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = StatusFile::new(&path);
+
+        let mut progress = Progress::new(2, 3, 4);
+        progress.start_round(1);
+        progress.current().jobs_scheduled = 5;
+        progress.current().jobs_completed = 4;
+        progress.current().jobs_failed = 1;
+        progress.estimated_rounds_remaining = Some(2);
+
+        file.write(&progress).unwrap();
+
+        let read = file.read().unwrap();
+        assert!(read.updated_at > 0);
+        assert_eq!(read.rounds, progress.rounds);
+
+        let rendered = read.render();
+        assert!(rendered.contains("estimated rounds remaining: 2"));
+        assert!(rendered.contains("4/12"));
+        assert!(rendered.contains("status: running"));
+
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        assert!(!Path::new(&tmp).exists());
+    }
+
+    #[test]
+    fn stall_detector() {
+        let detector = StallDetector::new(Duration::from_secs(10));
+        let now = Instant::now();
+
+        assert!(!detector.is_stalled(now, now));
+        assert!(!detector.is_stalled(now, now + Duration::from_secs(10)));
+        assert!(detector.is_stalled(now, now + Duration::from_secs(11)));
+    }
+}
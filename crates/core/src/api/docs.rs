@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use super::{autosuggest, explore, hosts, search, webgraph};
+use super::{autosuggest, explore, hosts, index, search, webgraph};
 use axum::Router;
 use utoipa::{Modify, OpenApi};
 use utoipa_swagger_ui::SwaggerUi;
@@ -30,25 +30,40 @@ use utoipa_swagger_ui::SwaggerUi;
             webgraph::host::knows,
             webgraph::host::ingoing_hosts,
             webgraph::host::outgoing_hosts,
+            webgraph::host::link_report,
+            webgraph::host::neighborhood,
             webgraph::page::ingoing_pages,
             webgraph::page::outgoing_pages,
             autosuggest::route,
             hosts::hosts_export_optic,
+            hosts::site_info,
             explore::explore_export_optic,
+            index::freshness,
         ),
         components(
             schemas(
                 crate::webpage::region::Region,
                 optics::HostRankings,
+                optics::Features,
                 search::ApiSearchQuery,
                 search::ApiSearchResult,
                 search::WidgetQuery,
                 search::SidebarQuery,
                 search::SpellcheckQuery,
                 search::ReturnBody,
+                search::SafeSearch,
+                search::ApiSafeSearch,
+                search::PageSort,
+                search::SortField,
+                search::SortOrder,
+                crate::webpage::content_labels::ContentLabel,
                 crate::searcher::WebsitesResult,
+                crate::searcher::timings::Timings,
+                crate::ranking::pipeline::DegradedStage,
+                crate::ranking::pipeline::PipelineStage,
                 crate::search_prettifier::HighlightedSpellCorrection,
                 crate::search_prettifier::DisplayedWebpage,
+                crate::search_prettifier::Breadcrumb,
                 crate::search_prettifier::DisplayedEntity,
                 crate::search_prettifier::DisplayedAnswer,
                 crate::search_prettifier::DisplayedSidebar,
@@ -62,6 +77,7 @@ use utoipa_swagger_ui::SwaggerUi;
                 crate::highlighted::HighlightedFragment,
                 crate::highlighted::HighlightedKind,
 
+                crate::entity_index::EntityCandidate,
                 crate::entity_index::entity::EntitySnippet,
                 crate::entity_index::entity::EntitySnippetFragment,
 
@@ -90,10 +106,23 @@ use utoipa_swagger_ui::SwaggerUi;
                 autosuggest::Suggestion,
 
                 hosts::HostsExportOpticParams,
+                hosts::SiteInfoQuery,
+                crate::searcher::api::SiteInfo,
                 explore::ExploreExportOpticParams,
 
+                crate::freshness::FreshnessHistogram,
+                crate::freshness::FreshnessBucket,
+
                 crate::webgraph::Node,
                 crate::webgraph::FullEdge,
+                crate::webgraph::LinkReport,
+                crate::webgraph::LinkingHostGroup,
+                crate::webgraph::AnchorCount,
+                crate::webgraph::RelHistogram,
+                crate::webgraph::CoLinkedHost,
+                crate::webgraph::Neighborhood,
+                crate::webgraph::NeighborhoodNode,
+                crate::webgraph::NeighborhoodEdge,
 
                 crate::search_prettifier::StructuredData,
                 crate::search_prettifier::OneOrManyString,
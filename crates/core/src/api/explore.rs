@@ -66,6 +66,8 @@ pub async fn explore_export_optic(
         },
         rules: vec![rule],
         discard_non_matching: true,
+        verbatim: false,
+        ..Default::default()
     };
 
     Ok(optic.to_string())
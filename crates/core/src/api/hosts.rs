@@ -14,15 +14,22 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::sync::Arc;
+
 use axum::{
     body::Body,
     extract,
     response::{IntoResponse, Response},
+    Json,
 };
 use http::StatusCode;
 use optics::{HostRankings, Optic};
 use utoipa::ToSchema;
 
+use crate::searcher::api::SiteInfo;
+
+use super::State;
+
 #[derive(serde::Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct HostsExportOpticParams {
@@ -46,3 +53,23 @@ pub async fn hosts_export_optic(
 
     Ok(optic.to_string().into_response())
 }
+
+#[derive(serde::Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SiteInfoQuery {
+    host: String,
+}
+
+#[utoipa::path(post,
+    path = "/beta/api/hosts/site_info",
+    request_body(content = SiteInfoQuery),
+    responses(
+        (status = 200, description = "Information about the site. Fields from sources that did not answer in time are left out", body = SiteInfo),
+    )
+)]
+pub async fn site_info(
+    extract::State(state): extract::State<Arc<State>>,
+    extract::Json(SiteInfoQuery { host }): extract::Json<SiteInfoQuery>,
+) -> Json<SiteInfo> {
+    Json(state.site_info.site_info(&host).await)
+}
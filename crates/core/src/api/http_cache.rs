@@ -0,0 +1,306 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! HTTP caching of the api responses.
+//!
+//! Successful responses of the cached routes get an `ETag` that is a hash of their body
+//! and, for the routes that depend on the index, of the generation of the index. A request
+//! whose `If-None-Match` header has the tag is answered with `304 Not Modified` and no body,
+//! so clients that send the same request again don't have to download an identical payload.
+//! The generation is refreshed from the search servers in the background, which changes the
+//! tags of the index dependent routes as soon as a new index is served.
+//!
+//! The `Cache-Control` header lets clients keep the responses for the max age of the route
+//! in [`HttpCacheConfig`]. Search results are not stored by clients unless a max age is
+//! configured for them, and then only the results of queries without an optic or host
+//! rankings, which the search handler marks as [`Cacheable`].
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+};
+
+use crate::{config::HttpCacheConfig, searcher::DistributedSearcher};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheRoute {
+    Static,
+    Autosuggest,
+    SiteInfo,
+    /// Routes like the freshness of the index whose responses can change with a new index.
+    Index,
+    Search,
+}
+
+impl CacheRoute {
+    fn depends_on_index(&self) -> bool {
+        matches!(self, Self::SiteInfo | Self::Index | Self::Search)
+    }
+
+    fn max_age_secs(&self, config: &HttpCacheConfig) -> Option<u64> {
+        match self {
+            Self::Static => config.static_max_age_secs,
+            Self::Autosuggest => config.autosuggest_max_age_secs,
+            Self::SiteInfo => config.site_info_max_age_secs,
+            Self::Index => config.index_max_age_secs,
+            Self::Search => config.search_max_age_secs,
+        }
+    }
+}
+
+/// Added to the extensions of a search response that is the same for every client.
+#[derive(Debug, Clone, Copy)]
+pub struct Cacheable;
+
+pub struct HttpCache {
+    config: HttpCacheConfig,
+    generation: AtomicU64,
+}
+
+impl HttpCache {
+    pub fn new(config: HttpCacheConfig) -> Self {
+        Self {
+            config,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    pub fn set_generation(&self, generation: u64) {
+        self.generation.store(generation, Ordering::Relaxed);
+    }
+
+    fn etag(&self, route: CacheRoute, body: &[u8]) -> String {
+        let mut context = md5::Context::new();
+        context.consume(body);
+
+        if route.depends_on_index() {
+            context.consume(self.generation().to_le_bytes());
+        }
+
+        format!("\"{:x}\"", context.compute())
+    }
+}
+
+/// Fetch the generation of the index every `generation_refresh_secs`. The previous
+/// generation is kept if the search servers can't be reached.
+pub async fn refresh_generation_loop(cache: Arc<HttpCache>, searcher: Arc<DistributedSearcher>) {
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(cache.config.generation_refresh_secs));
+
+    loop {
+        interval.tick().await;
+
+        match searcher.index_generation().await {
+            Ok(generation) => cache.set_generation(generation),
+            Err(err) => tracing::warn!("failed to get the generation of the index: {:?}", err),
+        }
+    }
+}
+
+/// Whether the `If-None-Match` headers of the request have the tag. Weak tags match
+/// as well, since the body of the responses is compared and not their encoding.
+fn has_etag(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|val| val.to_str().ok())
+        .flat_map(|val| val.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+pub async fn http_cache(
+    extract::State((cache, route)): extract::State<(Arc<HttpCache>, CacheRoute)>,
+    request: axum::extract::Request,
+    next: middleware::Next,
+) -> Response {
+    let request_headers = request.headers().clone();
+    let mut response = next.run(request).await;
+
+    if !response.status().is_success() {
+        return response;
+    }
+
+    let max_age_secs = route.max_age_secs(&cache.config);
+
+    if route == CacheRoute::Search
+        && (max_age_secs.is_none() || response.extensions().get::<Cacheable>().is_none())
+    {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => {
+            tracing::error!("failed to read the response body: {:?}", err);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let etag = cache.etag(route, &body);
+    let cache_control = match max_age_secs {
+        Some(secs) => format!("public, max-age={secs}"),
+        None => "no-cache".to_string(),
+    };
+
+    parts
+        .headers
+        .insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+    parts.headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(&cache_control).unwrap(),
+    );
+
+    if has_etag(&request_headers, &etag) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_LENGTH);
+
+        return Response::from_parts(parts, Body::empty());
+    }
+
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{routing::get, Router};
+
+    use super::*;
+
+    /// Serve a route behind the cache that always responds with the same payload.
+    async fn serve(cache: Arc<HttpCache>, route: CacheRoute, cacheable: bool) -> SocketAddr {
+        let app = Router::new()
+            .route(
+                "/",
+                get(move || async move {
+                    let mut response = "the same payload".into_response();
+                    if cacheable {
+                        response.extensions_mut().insert(Cacheable);
+                    }
+                    response
+                }),
+            )
+            .route_layer(middleware::from_fn_with_state((cache, route), http_cache));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        addr
+    }
+
+    async fn get_with_etag(addr: SocketAddr, etag: Option<&str>) -> reqwest::Response {
+        let mut request = reqwest::Client::new().get(format!("http://{addr}/"));
+
+        if let Some(etag) = etag {
+            request = request.header("if-none-match", etag);
+        }
+
+        request.send().await.unwrap()
+    }
+
+    fn etag(response: &reqwest::Response) -> String {
+        response.headers()["etag"].to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn second_request_is_not_modified() {
+        let cache = Arc::new(HttpCache::new(HttpCacheConfig::default()));
+        let addr = serve(cache, CacheRoute::Autosuggest, false).await;
+
+        let first = get_with_etag(addr, None).await;
+        assert_eq!(first.status(), reqwest::StatusCode::OK);
+        assert_eq!(first.headers()["cache-control"], "public, max-age=3600");
+        let tag = etag(&first);
+        assert_eq!(first.text().await.unwrap(), "the same payload");
+
+        let second = get_with_etag(addr, Some(&tag)).await;
+        assert_eq!(second.status(), reqwest::StatusCode::NOT_MODIFIED);
+        assert_eq!(etag(&second), tag);
+        assert!(second.bytes().await.unwrap().is_empty());
+
+        let weak = get_with_etag(addr, Some(&format!("\"other\", W/{tag}"))).await;
+        assert_eq!(weak.status(), reqwest::StatusCode::NOT_MODIFIED);
+
+        let other = get_with_etag(addr, Some("\"other\"")).await;
+        assert_eq!(other.status(), reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn new_generation_changes_etag() {
+        let cache = Arc::new(HttpCache::new(HttpCacheConfig::default()));
+        let index = serve(cache.clone(), CacheRoute::Index, false).await;
+        let autosuggest = serve(cache.clone(), CacheRoute::Autosuggest, false).await;
+
+        let index_tag = etag(&get_with_etag(index, None).await);
+        let autosuggest_tag = etag(&get_with_etag(autosuggest, None).await);
+
+        cache.set_generation(cache.generation() + 1);
+
+        let res = get_with_etag(index, Some(&index_tag)).await;
+        assert_eq!(res.status(), reqwest::StatusCode::OK);
+        assert_ne!(etag(&res), index_tag);
+
+        let res = get_with_etag(autosuggest, Some(&autosuggest_tag)).await;
+        assert_eq!(res.status(), reqwest::StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn search_is_only_cached_when_enabled() {
+        let disabled = Arc::new(HttpCache::new(HttpCacheConfig::default()));
+        let enabled = Arc::new(HttpCache::new(HttpCacheConfig {
+            search_max_age_secs: Some(60),
+            ..Default::default()
+        }));
+
+        for (cache, cacheable) in [(disabled.clone(), true), (enabled.clone(), false)] {
+            let addr = serve(cache, CacheRoute::Search, cacheable).await;
+            let res = get_with_etag(addr, None).await;
+
+            assert_eq!(res.headers()["cache-control"], "no-store");
+            assert!(res.headers().get("etag").is_none());
+        }
+
+        let addr = serve(enabled, CacheRoute::Search, true).await;
+        let res = get_with_etag(addr, None).await;
+        assert_eq!(res.headers()["cache-control"], "public, max-age=60");
+
+        let res = get_with_etag(addr, Some(&etag(&res))).await;
+        assert_eq!(res.status(), reqwest::StatusCode::NOT_MODIFIED);
+    }
+}
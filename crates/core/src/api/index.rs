@@ -0,0 +1,42 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2023 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+
+use axum::{extract, Json};
+use http::StatusCode;
+
+use crate::freshness::FreshnessHistogram;
+
+use super::State;
+
+#[utoipa::path(get,
+    path = "/beta/api/index/freshness",
+    responses(
+        (status = 200, description = "The number of pages in the index that were crawled within the maximum age of each bucket, summed over all shards", body = FreshnessHistogram),
+    )
+)]
+pub async fn freshness(
+    extract::State(state): extract::State<Arc<State>>,
+) -> Result<Json<FreshnessHistogram>, StatusCode> {
+    match state.distributed_searcher.freshness().await {
+        Ok(freshness) => Ok(Json(freshness)),
+        Err(err) => {
+            tracing::error!("failed to get the freshness of the index: {:?}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
@@ -23,20 +23,29 @@ use tower::limit::ConcurrencyLimitLayer;
 use tower_http::compression::CompressionLayer;
 
 use crate::{
+    api::{
+        http_cache::{CacheRoute, HttpCache},
+        rate_limit::{RateLimitCounters, RateLimiter, Route},
+    },
     autosuggest::Autosuggest,
     bangs::Bangs,
     config::ApiConfig,
     distributed::{
         cluster::Cluster,
         member::{Member, Service},
+        sonic::CircuitBreakers,
     },
     improvement::{store_improvements_loop, ImprovementEvent},
     leaky_queue::LeakyQueue,
     models::dual_encoder::DualEncoder,
     ranking::models::lambdamart::LambdaMART,
-    searcher::{api::ApiSearcher, live::LiveSearcher, DistributedSearcher},
+    searcher::{
+        api::{ApiSearcher, RemoteSiteInfoSources, SiteInfoManager},
+        live::LiveSearcher,
+        DistributedSearcher,
+    },
     similar_hosts::SimilarHostsFinder,
-    webgraph::remote::RemoteWebgraph,
+    webgraph::{remote::RemoteWebgraph, QueryPriority},
 };
 
 use crate::ranking::models::cross_encoder::CrossEncoderModel;
@@ -58,8 +67,11 @@ mod autosuggest;
 mod docs;
 mod explore;
 mod hosts;
+pub mod http_cache;
 pub mod improvement;
+mod index;
 mod metrics;
+pub mod rate_limit;
 pub mod search;
 pub mod user_count;
 mod webgraph;
@@ -69,6 +81,7 @@ pub struct Counters {
     pub search_counter_fail: crate::metrics::Counter,
     pub explore_counter: crate::metrics::Counter,
     pub daily_active_users: user_count::UserCount<user_count::Daily>,
+    pub rate_limited: RateLimitCounters,
 }
 
 pub struct State {
@@ -81,6 +94,10 @@ pub struct State {
     pub improvement_queue: Option<Arc<Mutex<LeakyQueue<ImprovementEvent>>>>,
     pub _cluster: Arc<Cluster>,
     pub similar_hosts: SimilarHostsFinder,
+    pub site_info: SiteInfoManager<RemoteSiteInfoSources>,
+    pub distributed_searcher: Arc<DistributedSearcher>,
+    pub rate_limiter: RateLimiter,
+    pub http_cache: Arc<HttpCache>,
 }
 
 pub async fn favicon() -> impl IntoResponse {
@@ -95,7 +112,15 @@ pub async fn favicon() -> impl IntoResponse {
 fn build_router(state: Arc<State>) -> Router {
     let mut search = Router::new()
         .route("/beta/api/search", post(search::search))
+        .route_layer(middleware::from_fn_with_state(
+            (state.http_cache.clone(), CacheRoute::Search),
+            http_cache::http_cache,
+        ))
         .route_layer(middleware::from_fn_with_state(state.clone(), search_metric))
+        .route_layer(middleware::from_fn_with_state(
+            (state.clone(), Route::Search),
+            rate_limit::rate_limit,
+        ))
         .layer(cors_layer());
 
     if let Some(limit) = state.config.max_concurrent_searches {
@@ -104,7 +129,14 @@ fn build_router(state: Arc<State>) -> Router {
 
     Router::new()
         .merge(search)
-        .route("/favicon.ico", get(favicon))
+        .merge(
+            Router::new()
+                .route("/favicon.ico", get(favicon))
+                .route_layer(middleware::from_fn_with_state(
+                    (state.http_cache.clone(), CacheRoute::Static),
+                    http_cache::http_cache,
+                )),
+        )
         .merge(
             Router::new()
                 .route("/improvement/click", post(improvement::click))
@@ -119,8 +151,6 @@ fn build_router(state: Arc<State>) -> Router {
                 .route("/api/search/widget", post(search::widget))
                 .route("/api/search/sidebar", post(search::sidebar))
                 .route("/api/search/spellcheck", post(search::spellcheck))
-                .route("/api/autosuggest", post(autosuggest::route))
-                .route("/api/autosuggest/browser", get(autosuggest::browser))
                 .route("/api/webgraph/host/similar", post(webgraph::host::similar))
                 .route("/api/webgraph/host/knows", post(webgraph::host::knows))
                 .route(
@@ -131,6 +161,14 @@ fn build_router(state: Arc<State>) -> Router {
                     "/api/webgraph/host/outgoing",
                     post(webgraph::host::outgoing_hosts),
                 )
+                .route(
+                    "/api/webgraph/host/link_report",
+                    post(webgraph::host::link_report),
+                )
+                .route(
+                    "/api/webgraph/host/neighborhood",
+                    post(webgraph::host::neighborhood),
+                )
                 .route(
                     "/api/webgraph/page/ingoing",
                     post(webgraph::page::ingoing_pages),
@@ -141,7 +179,40 @@ fn build_router(state: Arc<State>) -> Router {
                 )
                 .route("/api/hosts/export", post(hosts::hosts_export_optic))
                 .route("/api/explore/export", post(explore::explore_export_optic))
-                .route("/api/entity_image", get(search::entity_image))
+                .merge(
+                    Router::new()
+                        .route("/api/hosts/site_info", post(hosts::site_info))
+                        .route_layer(middleware::from_fn_with_state(
+                            (state.http_cache.clone(), CacheRoute::SiteInfo),
+                            http_cache::http_cache,
+                        )),
+                )
+                .merge(
+                    Router::new()
+                        .route("/api/index/freshness", get(index::freshness))
+                        .route("/api/entity_image", get(search::entity_image))
+                        .route_layer(middleware::from_fn_with_state(
+                            (state.http_cache.clone(), CacheRoute::Index),
+                            http_cache::http_cache,
+                        )),
+                )
+                .route_layer(middleware::from_fn_with_state(
+                    (state.clone(), Route::Api),
+                    rate_limit::rate_limit,
+                ))
+                .merge(
+                    Router::new()
+                        .route("/api/autosuggest", post(autosuggest::route))
+                        .route("/api/autosuggest/browser", get(autosuggest::browser))
+                        .route_layer(middleware::from_fn_with_state(
+                            (state.http_cache.clone(), CacheRoute::Autosuggest),
+                            http_cache::http_cache,
+                        ))
+                        .route_layer(middleware::from_fn_with_state(
+                            (state.clone(), Route::Autosuggest),
+                            rate_limit::rate_limit,
+                        )),
+                )
                 .layer(cors_layer()),
         )
         .with_state(state)
@@ -187,11 +258,18 @@ pub async fn router(config: &ApiConfig, counters: Counters) -> Result<Router> {
     );
 
     let host_webgraph =
-        RemoteWebgraph::new(cluster.clone(), crate::config::WebgraphGranularity::Host).await;
+        RemoteWebgraph::new(cluster.clone(), crate::config::WebgraphGranularity::Host)
+            .await
+            .with_priority(QueryPriority::Frontend);
     let page_webgraph =
-        RemoteWebgraph::new(cluster.clone(), crate::config::WebgraphGranularity::Page).await;
-
-    let dist_searcher = DistributedSearcher::new(Arc::clone(&cluster)).await;
+        RemoteWebgraph::new(cluster.clone(), crate::config::WebgraphGranularity::Page)
+            .await
+            .with_priority(QueryPriority::Frontend);
+
+    let circuit_breakers = CircuitBreakers::new(config.circuit_breaker.clone());
+    let dist_searcher =
+        DistributedSearcher::with_circuit_breakers(Arc::clone(&cluster), circuit_breakers.clone())
+            .await;
     let live_searcher = LiveSearcher::new(Arc::clone(&cluster));
 
     let state = {
@@ -222,6 +300,28 @@ pub async fn router(config: &ApiConfig, counters: Counters) -> Result<Router> {
         let similar_hosts =
             SimilarHostsFinder::new(Arc::clone(&host_webgraph), config.max_similar_hosts);
 
+        let distributed_searcher = Arc::new(
+            DistributedSearcher::with_circuit_breakers(Arc::clone(&cluster), circuit_breakers)
+                .await,
+        );
+
+        let site_info = SiteInfoManager::new(
+            RemoteSiteInfoSources::new(
+                Arc::clone(&distributed_searcher),
+                config.thresholds.entity_sidebar,
+            )
+            .with_host_webgraph(Arc::clone(&host_webgraph)),
+            &config.site_info,
+        );
+
+        let rate_limiter = RateLimiter::new(&config.rate_limit, &counters.rate_limited)?;
+
+        let http_cache = Arc::new(HttpCache::new(config.http_cache.clone()));
+        tokio::spawn(http_cache::refresh_generation_loop(
+            Arc::clone(&http_cache),
+            Arc::clone(&distributed_searcher),
+        ));
+
         Arc::new(State {
             config: config.clone(),
             searcher: Arc::new(searcher),
@@ -232,6 +332,10 @@ pub async fn router(config: &ApiConfig, counters: Counters) -> Result<Router> {
             improvement_queue: query_store_queue,
             _cluster: cluster,
             similar_hosts,
+            site_info,
+            distributed_searcher,
+            rate_limiter,
+            http_cache,
         })
     };
 
@@ -0,0 +1,473 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Token bucket rate limiting of the api.
+//!
+//! Every client has a bucket per route group that holds up to `burst` tokens and is refilled
+//! with `requests_per_sec` tokens per second. A request takes a token, and requests to an
+//! empty bucket are answered with `429 Too Many Requests` and a `Retry-After` header.
+//! Clients are identified by their api key if they present one of the configured keys, and
+//! by their ip otherwise. The buckets are kept in an LRU cache so the memory used by the
+//! limiter is bounded no matter how many clients there are.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract,
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+};
+use lru::LruCache;
+
+use crate::{
+    config::{RateLimit, RateLimitConfig},
+    metrics::Counter,
+};
+
+use super::State;
+
+const API_KEY_HEADER: &str = "x-api-key";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Search,
+    Autosuggest,
+    Api,
+}
+
+/// The number of throttled requests per route.
+#[derive(Default, Clone)]
+pub struct RateLimitCounters {
+    pub search: Counter,
+    pub autosuggest: Counter,
+    pub api: Counter,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    ApiKey(String),
+    Ip(IpAddr),
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+struct Limiter {
+    limit: RateLimit,
+    buckets: Mutex<LruCache<Client, Bucket>>,
+    throttled: Counter,
+}
+
+impl Limiter {
+    fn new(limit: RateLimit, max_clients: NonZeroUsize, throttled: Counter) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::new(LruCache::new(max_clients)),
+            throttled,
+        }
+    }
+
+    /// Take a token from the bucket of the client, or return how long the client
+    /// has to wait before the bucket has a token again.
+    fn take(&self, client: Client, now: Instant) -> Result<(), Duration> {
+        let burst = self.limit.burst as f64;
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        let bucket = buckets.get_or_insert_mut(client, || Bucket {
+            tokens: burst,
+            last_refill: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * self.limit.requests_per_sec).min(burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            self.throttled.inc();
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.limit.requests_per_sec,
+            ))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Network {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl Network {
+    fn parse(network: &str) -> Result<Self> {
+        let (addr, prefix) = match network.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>()?, Some(prefix.parse::<u32>()?)),
+            None => (network.parse::<IpAddr>()?, None),
+        };
+
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max_prefix);
+
+        if prefix > max_prefix {
+            return Err(anyhow!("invalid network prefix in '{network}'"));
+        }
+
+        Ok(Self { addr, prefix })
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        let mask = |bits: u32, prefix: u32| {
+            if prefix == 0 {
+                0
+            } else {
+                u128::MAX << (bits - prefix)
+            }
+        };
+
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = mask(32, self.prefix) as u32;
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = mask(128, self.prefix);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct RateLimiter {
+    search: Option<Limiter>,
+    autosuggest: Option<Limiter>,
+    api: Option<Limiter>,
+    allowlist: Vec<Network>,
+    api_keys: Vec<String>,
+    trusted_proxies: usize,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig, counters: &RateLimitCounters) -> Result<Self> {
+        let max_clients = NonZeroUsize::new(config.max_clients)
+            .ok_or_else(|| anyhow!("rate limit max_clients must be positive"))?;
+
+        let limiter = |limit: Option<RateLimit>, throttled: &Counter| -> Result<_> {
+            match limit {
+                Some(limit) if limit.requests_per_sec <= 0.0 || limit.burst == 0 => {
+                    Err(anyhow!("rate limits must have a positive rate and burst"))
+                }
+                Some(limit) => Ok(Some(Limiter::new(limit, max_clients, throttled.clone()))),
+                None => Ok(None),
+            }
+        };
+
+        Ok(Self {
+            search: limiter(config.search, &counters.search)?,
+            autosuggest: limiter(config.autosuggest, &counters.autosuggest)?,
+            api: limiter(config.api, &counters.api)?,
+            allowlist: config
+                .allowlist
+                .iter()
+                .map(|network| Network::parse(network))
+                .collect::<Result<_>>()?,
+            api_keys: config.api_keys.clone(),
+            trusted_proxies: config.trusted_proxies,
+        })
+    }
+
+    fn limiter(&self, route: Route) -> Option<&Limiter> {
+        match route {
+            Route::Search => self.search.as_ref(),
+            Route::Autosuggest => self.autosuggest.as_ref(),
+            Route::Api => self.api.as_ref(),
+        }
+    }
+
+    /// The ip of the client. Every trusted proxy appends the address it received the request
+    /// from to `x-forwarded-for`, so the entry added by the outermost proxy is the client.
+    /// A header with fewer entries than there are trusted proxies can't be told apart from
+    /// one the client wrote, so the address the request came from is used instead.
+    fn client_ip(&self, headers: &HeaderMap, addr: SocketAddr) -> IpAddr {
+        if self.trusted_proxies == 0 {
+            return addr.ip();
+        }
+
+        let forwarded: Vec<_> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|val| val.to_str().ok())
+            .flat_map(|val| val.split(','))
+            .map(|ip| ip.trim())
+            .collect();
+
+        forwarded
+            .len()
+            .checked_sub(self.trusted_proxies)
+            .and_then(|idx| forwarded.get(idx))
+            .and_then(|ip| ip.parse().ok())
+            .unwrap_or_else(|| addr.ip())
+    }
+
+    /// Take a token for the request, or return how long the client has to wait before
+    /// it can make a request to the route again.
+    fn check(
+        &self,
+        route: Route,
+        headers: &HeaderMap,
+        addr: SocketAddr,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let limiter = match self.limiter(route) {
+            Some(limiter) => limiter,
+            None => return Ok(()),
+        };
+
+        let ip = self.client_ip(headers, addr);
+
+        if self.allowlist.iter().any(|network| network.contains(&ip)) {
+            return Ok(());
+        }
+
+        let client = headers
+            .get(API_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+            .filter(|key| self.api_keys.iter().any(|known| known == key))
+            .map(|key| Client::ApiKey(key.to_string()))
+            .unwrap_or(Client::Ip(ip));
+
+        limiter.take(client, now)
+    }
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+    // round up so the client does not retry before the bucket has a token
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, secs.max(1).to_string())],
+    )
+        .into_response()
+}
+
+pub async fn rate_limit(
+    extract::State((state, route)): extract::State<(Arc<State>, Route)>,
+    extract::ConnectInfo(addr): extract::ConnectInfo<SocketAddr>,
+    request: axum::extract::Request,
+    next: middleware::Next,
+) -> Response {
+    match state
+        .rate_limiter
+        .check(route, request.headers(), addr, Instant::now())
+    {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => too_many_requests(retry_after),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            search: Some(RateLimit {
+                requests_per_sec: 2.0,
+                burst: 3,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn addr(ip: &str) -> SocketAddr {
+        SocketAddr::new(ip.parse().unwrap(), 1234)
+    }
+
+    #[test]
+    fn throttles_past_burst() {
+        let counters = RateLimitCounters::default();
+        let limiter = RateLimiter::new(&config(), &counters).unwrap();
+        let headers = HeaderMap::new();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter
+                .check(Route::Search, &headers, addr("1.2.3.4"), now)
+                .is_ok());
+        }
+
+        let retry_after = limiter
+            .check(Route::Search, &headers, addr("1.2.3.4"), now)
+            .unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(500));
+
+        let res = too_many_requests(retry_after);
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "1");
+
+        // other clients and routes have their own buckets
+        assert!(limiter
+            .check(Route::Search, &headers, addr("1.2.3.5"), now)
+            .is_ok());
+        for _ in 0..10 {
+            assert!(limiter
+                .check(Route::Api, &headers, addr("1.2.3.4"), now)
+                .is_ok());
+        }
+
+        assert_eq!(counters.search.get(), 1);
+    }
+
+    #[test]
+    fn buckets_refill() {
+        let limiter = RateLimiter::new(&config(), &RateLimitCounters::default()).unwrap();
+        let headers = HeaderMap::new();
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter
+                .check(Route::Search, &headers, addr("1.2.3.4"), start)
+                .is_ok());
+        }
+
+        let later = start + Duration::from_millis(500);
+        assert!(limiter
+            .check(Route::Search, &headers, addr("1.2.3.4"), later)
+            .is_ok());
+        assert!(limiter
+            .check(Route::Search, &headers, addr("1.2.3.4"), later)
+            .is_err());
+
+        // the bucket never holds more than the burst
+        let much_later = start + Duration::from_secs(3600);
+        for _ in 0..3 {
+            assert!(limiter
+                .check(Route::Search, &headers, addr("1.2.3.4"), much_later)
+                .is_ok());
+        }
+        assert!(limiter
+            .check(Route::Search, &headers, addr("1.2.3.4"), much_later)
+            .is_err());
+    }
+
+    #[test]
+    fn allowlist_bypasses_limits() {
+        let config = RateLimitConfig {
+            allowlist: vec!["10.0.0.0/8".to_string(), "::1".to_string()],
+            ..config()
+        };
+        let limiter = RateLimiter::new(&config, &RateLimitCounters::default()).unwrap();
+        let headers = HeaderMap::new();
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert!(limiter
+                .check(Route::Search, &headers, addr("10.1.2.3"), now)
+                .is_ok());
+            assert!(limiter
+                .check(Route::Search, &headers, addr("::1"), now)
+                .is_ok());
+        }
+
+        for _ in 0..3 {
+            assert!(limiter
+                .check(Route::Search, &headers, addr("11.1.2.3"), now)
+                .is_ok());
+        }
+        assert!(limiter
+            .check(Route::Search, &headers, addr("11.1.2.3"), now)
+            .is_err());
+
+        assert!(RateLimiter::new(
+            &RateLimitConfig {
+                allowlist: vec!["10.0.0.0/33".to_string()],
+                ..Default::default()
+            },
+            &RateLimitCounters::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn clients_behind_proxies() {
+        let config = RateLimitConfig {
+            trusted_proxies: 1,
+            api_keys: vec!["secret".to_string()],
+            ..config()
+        };
+        let limiter = RateLimiter::new(&config, &RateLimitCounters::default()).unwrap();
+        let now = Instant::now();
+        let proxy = addr("10.0.0.1");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("6.6.6.6, 1.2.3.4"),
+        );
+        assert_eq!(
+            limiter.client_ip(&headers, proxy),
+            "1.2.3.4".parse::<IpAddr>().unwrap()
+        );
+
+        for _ in 0..3 {
+            assert!(limiter.check(Route::Search, &headers, proxy, now).is_ok());
+        }
+        assert!(limiter.check(Route::Search, &headers, proxy, now).is_err());
+
+        // a spoofed entry does not give the client a new bucket
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("7.7.7.7, 1.2.3.4"),
+        );
+        assert!(limiter.check(Route::Search, &headers, proxy, now).is_err());
+
+        // known api keys have their own bucket, unknown keys are ignored
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("unknown"));
+        assert!(limiter.check(Route::Search, &headers, proxy, now).is_err());
+
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("secret"));
+        assert!(limiter.check(Route::Search, &headers, proxy, now).is_ok());
+
+        // too few entries for the trusted proxies
+        let limiter = RateLimiter::new(
+            &RateLimitConfig {
+                trusted_proxies: 3,
+                ..config
+            },
+            &RateLimitCounters::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            limiter.client_ip(&headers, proxy),
+            "10.0.0.1".parse::<IpAddr>().unwrap()
+        );
+    }
+}
@@ -15,13 +15,15 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
+    collation::Collator,
     config::defaults,
     enum_map::EnumMap,
     ranking::{SignalCoefficient, SignalEnum, SignalEnumDiscriminants},
+    search_prettifier::DisplayedWebpage,
 };
 use http::StatusCode;
 use optics::{HostRankings, Optic};
-use std::{collections::HashMap, sync::Arc};
+use std::{cmp::Reverse, collections::HashMap, sync::Arc};
 use utoipa::ToSchema;
 
 use axum::Json;
@@ -33,7 +35,7 @@ use crate::{
     webpage::region::Region,
 };
 
-use super::State;
+use super::{http_cache, State};
 
 use axum::{extract, response::IntoResponse};
 
@@ -53,6 +55,171 @@ pub enum ReturnBody {
     Truncated(usize),
 }
 
+/// How the results with content labels are treated,
+/// see [`crate::webpage::content_labels`].
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum SafeSearch {
+    /// Return the labelled results like any other result.
+    #[default]
+    Off,
+    /// Return the labelled results, but with a content warning so their
+    /// thumbnails can be blurred behind a click-through.
+    Warn,
+    /// Leave out the labelled results.
+    Strict,
+}
+
+impl From<bool> for SafeSearch {
+    fn from(enabled: bool) -> Self {
+        if enabled {
+            SafeSearch::Strict
+        } else {
+            SafeSearch::Off
+        }
+    }
+}
+
+/// A [`SafeSearch`] mode, where `true` and `false` are
+/// accepted for the strict mode and no safe search.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(untagged)]
+pub enum ApiSafeSearch {
+    Enabled(bool),
+    Mode(SafeSearch),
+}
+
+impl From<ApiSafeSearch> for SafeSearch {
+    fn from(safe_search: ApiSafeSearch) -> Self {
+        match safe_search {
+            ApiSafeSearch::Enabled(enabled) => enabled.into(),
+            ApiSafeSearch::Mode(mode) => mode,
+        }
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum SortField {
+    Title,
+    Url,
+    Domain,
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// Reorder the results of a single page by a string field instead of their score.
+///
+/// The results are still ranked and paginated by their score, and only the results of the
+/// requested page are reordered. Each page is in sorted order, but the pages are not sorted
+/// relative to each other.
+#[derive(
+    Clone,
+    Debug,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PageSort {
+    pub field: SortField,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Compare the strings by the collation of this locale, e.g. `fr` or `sv-SE`,
+    /// instead of their byte order.
+    ///
+    /// Letters are compared by their base letter, then their accents and then their case in
+    /// every locale. Only Spanish (`es`), Swedish and Finnish (`sv`, `fi`) and Danish and
+    /// Norwegian (`da`, `nb`, `nn`, `no`) order some letters of their own, and only `fr-CA`
+    /// compares the accents from the end. Other locales, including scripts other than Latin,
+    /// use this default order.
+    pub locale: Option<String>,
+}
+
+impl PageSort {
+    fn value<'a>(&self, webpage: &'a DisplayedWebpage) -> &'a str {
+        match self.field {
+            SortField::Title => &webpage.title,
+            SortField::Url => &webpage.url,
+            SortField::Domain => &webpage.domain,
+        }
+    }
+
+    /// The sort is stable, so results with the same value keep the order of their score.
+    pub fn sort(&self, webpages: &mut [DisplayedWebpage]) {
+        match &self.locale {
+            Some(locale) => {
+                let collator = Collator::new(locale);
+                let key = |webpage: &DisplayedWebpage| collator.sort_key(self.value(webpage));
+
+                match self.order {
+                    SortOrder::Ascending => webpages.sort_by_cached_key(key),
+                    SortOrder::Descending => {
+                        webpages.sort_by_cached_key(|webpage| Reverse(key(webpage)))
+                    }
+                }
+            }
+            None => match self.order {
+                SortOrder::Ascending => webpages.sort_by(|a, b| self.value(a).cmp(self.value(b))),
+                SortOrder::Descending => webpages.sort_by(|a, b| self.value(b).cmp(self.value(a))),
+            },
+        }
+    }
+}
+
 #[derive(
     Debug, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, ToSchema,
 )]
@@ -65,10 +232,13 @@ pub struct ApiSearchQuery {
     pub selected_region: Option<Region>,
     pub optic: Option<String>,
     pub host_rankings: Option<HostRankings>,
-    pub safe_search: Option<bool>,
+    pub safe_search: Option<ApiSafeSearch>,
 
     pub signal_coefficients: Option<HashMap<SignalEnumDiscriminants, f64>>,
 
+    /// Boost results where the terms of the query are close together by this weight.
+    pub proximity_weight: Option<f64>,
+
     #[serde(default = "defaults::SearchQuery::return_ranking_signals")]
     pub return_ranking_signals: bool,
 
@@ -81,6 +251,46 @@ pub struct ApiSearchQuery {
     #[serde(default = "defaults::SearchQuery::return_structured_data")]
     pub return_structured_data: bool,
 
+    /// Record which optic rules matched each result. Only meant for optic development.
+    #[serde(default = "defaults::SearchQuery::optic_debug")]
+    pub optic_debug: bool,
+
+    /// Include a breakdown of where the time of the search was spent. Only meant for debugging.
+    #[serde(default = "defaults::SearchQuery::debug_timings")]
+    pub debug_timings: bool,
+
+    /// Tradeoff between relevance (`1.0`) and topical diversity (`0.0`) of the top results.
+    pub diversity_lambda: Option<f64>,
+
+    /// Only return results from these hosts.
+    #[serde(default)]
+    pub restrict_hosts: Vec<String>,
+
+    /// Only return one result for urls that point to the same page,
+    /// e.g. `http://example.com/a` and `https://example.com/a/`.
+    #[serde(default = "defaults::SearchQuery::dedup_urls")]
+    pub dedup_urls: bool,
+
+    /// Latency budget of the ranking stages in milliseconds, instead of the configured one.
+    pub latency_budget_ms: Option<u64>,
+
+    /// The hosts the user visits the most, kept by the client. Results from these hosts
+    /// are boosted slightly. Ignored if there are more than 32 hosts.
+    #[serde(default)]
+    pub boosted_hosts: Vec<String>,
+
+    /// Only match the terms of the query in these fields, e.g. `["title"]`.
+    /// Terms for a specific field like `intitle:` are not restricted.
+    pub search_fields: Option<Vec<String>>,
+
+    /// Reorder the results of the page by a string field instead of their score.
+    /// The pages themselves are still chosen by score, see [`PageSort`].
+    pub sort_page: Option<PageSort>,
+
+    /// Search for the exact words of the query without stemming, synonyms or spelling corrections.
+    #[serde(default = "defaults::SearchQuery::verbatim")]
+    pub verbatim: bool,
+
     #[cfg(feature = "return_body")]
     pub return_body: Option<ReturnBody>,
 }
@@ -114,14 +324,30 @@ impl TryFrom<ApiSearchQuery> for SearchQuery {
             optic,
             host_rankings: api.host_rankings,
             return_ranking_signals: api.return_ranking_signals,
-            safe_search: api.safe_search.unwrap_or(default.safe_search),
+            safe_search: api
+                .safe_search
+                .map(SafeSearch::from)
+                .unwrap_or(default.safe_search),
             count_results_exact: api.count_results_exact,
             signal_coefficients: signal_coefficients.unwrap_or(default.signal_coefficients),
+            proximity_weight: api.proximity_weight,
             #[cfg(feature = "return_body")]
             return_body: api.return_body,
             #[cfg(not(feature = "return_body"))]
             return_body: None,
             return_structured_data: api.return_structured_data,
+            optic_debug: api.optic_debug,
+            diversity_lambda: api.diversity_lambda,
+            restrict_hosts: api.restrict_hosts,
+            dedup_urls: api.dedup_urls,
+            latency_budget_ms: api.latency_budget_ms,
+            boosted_hosts: api.boosted_hosts,
+            debug_timings: api.debug_timings,
+            search_after: None,
+            pinned_entity: None,
+            search_fields: api.search_fields,
+            sort_page: api.sort_page,
+            verbatim: api.verbatim,
         })
     }
 }
@@ -151,6 +377,7 @@ impl From<SearchResult> for ApiSearchResult {
     request_body(content = ApiSearchQuery),
     responses(
         (status = 200, description = "Search results", body = ApiSearchResult),
+        (status = 400, description = "The query is empty or too long", body = String),
     )
 )]
 pub async fn search(
@@ -159,6 +386,7 @@ pub async fn search(
 ) -> Result<impl IntoResponse, StatusCode> {
     tracing::debug!(?query);
     let flatten_result = query.flatten_response;
+    let cacheable = query.optic.is_none() && query.host_rankings.is_none();
     let query = SearchQuery::try_from(query);
 
     if let Err(err) = query {
@@ -169,21 +397,32 @@ pub async fn search(
 
     query.num_results = query.num_results.min(100);
 
+    if query.optic_debug {
+        // debugging requires evaluating every optic rule against the results,
+        // so keep these requests small.
+        query.num_results = query.num_results.min(searcher::NUM_RESULTS_PER_PAGE);
+    }
+
     match state.searcher.search(&query).await {
         Ok(result) => {
-            if flatten_result {
-                Ok(Json(ApiSearchResult::from(result)).into_response())
+            let mut response = if flatten_result {
+                Json(ApiSearchResult::from(result)).into_response()
             } else {
-                Ok(Json(result).into_response())
+                Json(result).into_response()
+            };
+
+            if cacheable {
+                response.extensions_mut().insert(http_cache::Cacheable);
             }
+
+            Ok(response)
         }
 
         Err(err) => match err.downcast_ref() {
-            Some(searcher::distributed::Error::EmptyQuery) => {
-                Ok(searcher::distributed::Error::EmptyQuery
-                    .to_string()
-                    .into_response())
-            }
+            Some(
+                err @ (searcher::distributed::Error::EmptyQuery
+                | searcher::distributed::Error::QueryTooLong { .. }),
+            ) => Ok((StatusCode::BAD_REQUEST, err.to_string()).into_response()),
             _ => {
                 tracing::error!("{:?}", err);
                 Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -197,6 +436,15 @@ pub async fn search(
 )]
 pub struct WidgetQuery {
     pub query: String,
+    /// Instant answers are skipped if the optic turns them off.
+    #[serde(default)]
+    pub optic: Option<String>,
+}
+
+/// Parse the optic of a widget or sidebar request. Invalid optics are ignored
+/// since the search request with the same optic reports the error.
+fn parse_features_optic(optic: Option<&str>) -> Option<Optic> {
+    optic.and_then(|optic| Optic::parse(optic).ok())
 }
 
 #[debug_handler]
@@ -212,14 +460,27 @@ pub async fn widget(
     extract::State(state): extract::State<Arc<State>>,
     extract::Json(req): extract::Json<WidgetQuery>,
 ) -> impl IntoResponse {
-    Json(state.searcher.widget(&req.query).await)
+    let query = SearchQuery {
+        query: req.query,
+        optic: parse_features_optic(req.optic.as_deref()),
+        ..Default::default()
+    };
+
+    Json(state.searcher.widget(&query).await)
 }
 
 #[derive(
     Debug, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, ToSchema,
 )]
+#[serde(rename_all = "camelCase")]
 pub struct SidebarQuery {
     pub query: String,
+    /// The id of one of the alternatives of the entity from an earlier sidebar.
+    #[serde(default)]
+    pub pinned_entity: Option<String>,
+    /// The sidebar is skipped if the optic turns it off.
+    #[serde(default)]
+    pub optic: Option<String>,
 }
 
 #[debug_handler]
@@ -235,7 +496,14 @@ pub async fn sidebar(
     extract::State(state): extract::State<Arc<State>>,
     extract::Json(req): extract::Json<SidebarQuery>,
 ) -> impl IntoResponse {
-    Json(state.searcher.sidebar(&req.query).await)
+    let query = SearchQuery {
+        query: req.query,
+        pinned_entity: req.pinned_entity,
+        optic: parse_features_optic(req.optic.as_deref()),
+        ..Default::default()
+    };
+
+    Json(state.searcher.sidebar(&query).await)
 }
 
 #[derive(
@@ -243,6 +511,9 @@ pub async fn sidebar(
 )]
 pub struct SpellcheckQuery {
     pub query: String,
+    /// Verbatim searches are never corrected.
+    #[serde(default = "defaults::SearchQuery::verbatim")]
+    pub verbatim: bool,
 }
 
 #[debug_handler]
@@ -258,6 +529,10 @@ pub async fn spellcheck(
     extract::State(state): extract::State<Arc<State>>,
     extract::Json(req): extract::Json<SpellcheckQuery>,
 ) -> impl IntoResponse {
+    if req.verbatim {
+        return Json(None);
+    }
+
     Json(state.searcher.spell_check(&req.query))
 }
 
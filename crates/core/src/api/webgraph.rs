@@ -21,8 +21,8 @@ use http::StatusCode;
 use utoipa::{IntoParams, ToSchema};
 
 use crate::{
-    config::WebgraphGranularity,
-    webgraph::{EdgeLimit, FullEdge, Node},
+    config::{defaults, WebgraphGranularity},
+    webgraph::{EdgeLimit, FullEdge, LinkReport, Neighborhood, Node},
 };
 
 use super::State;
@@ -53,6 +53,18 @@ pub mod host {
         pub host: String,
     }
 
+    #[derive(serde::Deserialize, IntoParams)]
+    #[serde(rename_all = "camelCase")]
+    pub struct NeighborhoodParams {
+        pub host: String,
+        /// Number of backlinks and of forwardlinks. At most 50 of each are returned.
+        #[serde(default = "defaults::Neighborhood::per_direction_limit")]
+        pub per_direction_limit: usize,
+        /// Also return the edges among the neighbors.
+        #[serde(default)]
+        pub include_interconnections: bool,
+    }
+
     #[utoipa::path(post,
         path = "/beta/api/webgraph/host/similar",
         request_body(content = SimilarHostsParams),
@@ -153,6 +165,58 @@ pub mod host {
 
         Ok(Json(links))
     }
+
+    #[utoipa::path(post,
+        path = "/beta/api/webgraph/host/link_report",
+        params(HostLinksParams),
+        responses(
+            (status = 200, description = "Incoming links to the pages of a host grouped by the linking host, with their most common anchors and rel flags", body = LinkReport),
+        )
+    )]
+    pub async fn link_report(
+        extract::State(state): extract::State<Arc<State>>,
+        extract::Query(params): extract::Query<HostLinksParams>,
+    ) -> std::result::Result<impl IntoResponse, StatusCode> {
+        let url = Url::parse(&("http://".to_string() + params.host.as_str()))
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+        let node = Node::from(url).into_host();
+        let report = state.page_webgraph.link_report(node).await.map_err(|_| {
+            tracing::error!("Failed to send request to webgraph");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(Json(report))
+    }
+
+    #[utoipa::path(post,
+        path = "/beta/api/webgraph/host/neighborhood",
+        params(NeighborhoodParams),
+        responses(
+            (status = 200, description = "The most central backlinks and forwardlinks of a host as the nodes and edges of a graph", body = Neighborhood),
+        )
+    )]
+    pub async fn neighborhood(
+        extract::State(state): extract::State<Arc<State>>,
+        extract::Query(params): extract::Query<NeighborhoodParams>,
+    ) -> std::result::Result<impl IntoResponse, StatusCode> {
+        let url = Url::parse(&("http://".to_string() + params.host.as_str()))
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+        let node = Node::from(url).into_host();
+        let neighborhood = state
+            .host_webgraph
+            .neighborhood(
+                node,
+                params.per_direction_limit,
+                params.include_interconnections,
+            )
+            .await
+            .map_err(|_| {
+                tracing::error!("Failed to send request to webgraph");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        Ok(Json(neighborhood))
+    }
 }
 
 pub mod page {
@@ -0,0 +1,272 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Locale aware comparison of strings.
+//!
+//! Strings are compared in three levels like the unicode collation algorithm: first by
+//! their base letters, then by their accents and finally by their case, so `été` sorts
+//! between `etage` and `zèbre` instead of after `z` like it does in byte order. The
+//! locales that treat some accented letters as letters of their own, like `å` that sorts
+//! after `z` in Swedish, are tailored, and the accents are compared from the end of the
+//! string in Canadian French. Other locales use the default order.
+
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+// the primary weights of the letters are spaced out so the tailored letters
+// can be placed in between them.
+const PRIMARY_SPACING: u64 = 0x100;
+
+const LEVEL_SEPARATOR: u64 = 0;
+
+// the accents of a letter are packed into its secondary weight with the first accent in
+// the highest bits, so letters are ordered by their first accent, then their second and
+// so on. A code point fits in 21 bits, so at most 3 accents of a letter are compared.
+const ACCENT_BITS: u32 = 21;
+const MAX_ACCENTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tailoring {
+    Root,
+    /// `ñ` is a letter between `n` and `o`.
+    Spanish,
+    /// `å`, `ä` and `ö` are letters after `z`.
+    Swedish,
+    /// `æ`, `ø` and `å` are letters after `z`.
+    Danish,
+}
+
+#[derive(Debug, Clone)]
+pub struct Collator {
+    tailoring: Tailoring,
+    backwards_accents: bool,
+}
+
+impl Collator {
+    /// The collator of a locale like `fr`, `fr-CA` or `sv_SE`.
+    /// Unknown locales use the default order.
+    pub fn new(locale: &str) -> Self {
+        let mut parts = locale.split(['-', '_']);
+        let language = parts.next().unwrap_or_default().to_lowercase();
+        let region = parts.next().unwrap_or_default().to_uppercase();
+
+        let tailoring = match language.as_str() {
+            "es" => Tailoring::Spanish,
+            "sv" | "fi" => Tailoring::Swedish,
+            "da" | "nb" | "nn" | "no" => Tailoring::Danish,
+            _ => Tailoring::Root,
+        };
+
+        Self {
+            tailoring,
+            backwards_accents: language == "fr" && region == "CA",
+        }
+    }
+
+    fn tailored(&self, c: char) -> Option<u64> {
+        let after = |letter: char, offset: u64| primary(letter) + offset;
+
+        match (self.tailoring, c) {
+            (Tailoring::Spanish, 'ñ') => Some(after('n', 1)),
+            (Tailoring::Swedish, 'å') => Some(after('z', 1)),
+            (Tailoring::Swedish, 'ä' | 'æ') => Some(after('z', 2)),
+            (Tailoring::Swedish, 'ö' | 'ø') => Some(after('z', 3)),
+            (Tailoring::Danish, 'æ' | 'ä') => Some(after('z', 1)),
+            (Tailoring::Danish, 'ø' | 'ö') => Some(after('z', 2)),
+            (Tailoring::Danish, 'å') => Some(after('z', 3)),
+            _ => None,
+        }
+    }
+
+    /// A key where the order of the keys of two strings is their collation order,
+    /// so the key of every string only has to be computed once when sorting.
+    pub fn sort_key(&self, s: &str) -> Vec<u64> {
+        let mut primaries = Vec::new();
+        let mut secondaries = Vec::new();
+        let mut tertiaries = Vec::new();
+
+        for c in s.chars() {
+            let tertiary = u64::from(c.is_uppercase());
+            let lower = c.to_lowercase().next().unwrap_or(c);
+
+            if let Some(weight) = self.tailored(lower) {
+                primaries.push(weight);
+                secondaries.push(0);
+                tertiaries.push(tertiary);
+                continue;
+            }
+
+            let mut decomposed = Vec::new();
+            decompose_canonical(lower, |d| decomposed.push(d));
+
+            let mut accents = Vec::new();
+            let mut num_letters = 0;
+
+            for d in decomposed {
+                if is_combining_mark(d) {
+                    accents.push(d);
+                    continue;
+                }
+
+                for letter in expand(d) {
+                    primaries.push(primary(letter));
+                    num_letters += 1;
+                }
+            }
+
+            // a combining mark that is not part of a precomposed
+            // character belongs to the letter before it
+            if num_letters == 0 {
+                if let Some(last) = secondaries.last_mut() {
+                    *last = accents.into_iter().fold(*last, with_accent);
+                }
+                continue;
+            }
+
+            let accents = accents.into_iter().fold(0, with_accent);
+
+            // the accents belong to the last letter of the character
+            for i in 0..num_letters {
+                secondaries.push(if i + 1 == num_letters { accents } else { 0 });
+                tertiaries.push(tertiary);
+            }
+        }
+
+        if self.backwards_accents {
+            secondaries.reverse();
+        }
+
+        let mut key = primaries;
+        key.push(LEVEL_SEPARATOR);
+        key.extend(secondaries);
+        key.push(LEVEL_SEPARATOR);
+        key.extend(tertiaries);
+
+        key
+    }
+}
+
+/// Add the accent to the secondary weight of a letter in the first free position.
+fn with_accent(weight: u64, mark: char) -> u64 {
+    let digit_mask = (1 << ACCENT_BITS) - 1;
+
+    (0..MAX_ACCENTS)
+        .map(|i| ACCENT_BITS * (MAX_ACCENTS - 1 - i))
+        .find(|shift| (weight >> shift) & digit_mask == 0)
+        .map_or(weight, |shift| weight | ((u64::from(mark) + 1) << shift))
+}
+
+fn primary(c: char) -> u64 {
+    (u64::from(c) + 1) * PRIMARY_SPACING
+}
+
+/// The letters that sort like the ligatures and letters without a decomposition.
+fn expand(c: char) -> Vec<char> {
+    match c {
+        'ß' => vec!['s', 's'],
+        'æ' => vec!['a', 'e'],
+        'œ' => vec!['o', 'e'],
+        'ø' => vec!['o'],
+        'ł' => vec!['l'],
+        'đ' => vec!['d'],
+        'þ' => vec!['t', 'h'],
+        _ => vec![c],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(locale: &str, words: &[&str]) -> Vec<String> {
+        let collator = Collator::new(locale);
+        let mut words: Vec<_> = words.iter().map(|word| word.to_string()).collect();
+        words.sort_by_cached_key(|word| collator.sort_key(word));
+        words
+    }
+
+    #[test]
+    fn accents_sort_with_their_letter() {
+        let words = ["zèbre", "Été", "eau", "élève", "etage", "ete"];
+
+        assert_eq!(
+            sorted("fr", &words),
+            vec!["eau", "élève", "etage", "ete", "Été", "zèbre"]
+        );
+
+        let mut bytes = words.to_vec();
+        bytes.sort();
+        assert_eq!(bytes, vec!["eau", "etage", "ete", "zèbre", "Été", "élève"]);
+    }
+
+    #[test]
+    fn accents_are_compared_backwards_in_canadian_french() {
+        let words = ["côté", "coté", "côte", "cote"];
+
+        assert_eq!(sorted("fr", &words), vec!["cote", "coté", "côte", "côté"]);
+        assert_eq!(
+            sorted("fr-CA", &words),
+            vec!["cote", "côte", "coté", "côté"]
+        );
+    }
+
+    #[test]
+    fn tailored_letters() {
+        let words = ["öl", "zon", "ål", "apa", "äpple"];
+
+        assert_eq!(
+            sorted("sv", &words),
+            vec!["apa", "zon", "ål", "äpple", "öl"]
+        );
+        assert_eq!(
+            sorted("de", &words),
+            vec!["ål", "apa", "äpple", "öl", "zon"]
+        );
+
+        assert_eq!(
+            sorted("es", &["nube", "ñu", "oso"]),
+            vec!["nube", "ñu", "oso"]
+        );
+        assert_eq!(
+            sorted("en", &["nube", "ñu", "oso"]),
+            vec!["ñu", "nube", "oso"]
+        );
+
+        assert_eq!(
+            sorted("en", &["strassf", "Straße", "strasse"]),
+            vec!["strasse", "Straße", "strassf"]
+        );
+    }
+
+    #[test]
+    fn letters_are_ordered_by_each_of_their_accents() {
+        assert_eq!(
+            sorted("vi", &["ệ", "ẹ", "ê", "e"]),
+            vec!["e", "ê", "ẹ", "ệ"]
+        );
+
+        // marks outside of the combining diacritical marks block
+        assert_eq!(
+            sorted("en", &["ec", "e\u{20d7}b", "eb", "ea"]),
+            vec!["ea", "eb", "e\u{20d7}b", "ec"]
+        );
+
+        let stacked = format!("e{}", "\u{301}".repeat(10));
+        assert_eq!(
+            sorted("en", &[&stacked, "é", "f"]),
+            vec!["é".to_string(), stacked, "f".to_string()]
+        );
+    }
+}
@@ -0,0 +1,88 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The memory budget of the collectors of a single search request.
+//!
+//! Every segment collector keeps the documents it considers in memory until the segment
+//! has been searched, and the top documents of all segments are then merged. The budget
+//! bounds how many documents each segment collector may consider, so a search over a huge
+//! index considers fewer documents instead of allocating without bound. If the budget
+//! cannot even fit the requested results, the search fails with [`BudgetExceeded`].
+
+use super::top_docs::SegmentDoc;
+
+/// The estimated number of bytes a collector uses for each document it holds.
+const DOC_BYTES: usize = std::mem::size_of::<SegmentDoc>() + std::mem::size_of::<f64>();
+
+#[derive(Debug, thiserror::Error)]
+#[error("Memory budget exceeded ({required} bytes needed, the budget is {budget} bytes)")]
+pub struct BudgetExceeded {
+    pub required: usize,
+    pub budget: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudget {
+    bytes: usize,
+}
+
+impl MemoryBudget {
+    pub fn new(bytes: usize) -> Self {
+        Self { bytes }
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// The number of documents each of the `segments` collectors may consider when `top_n`
+    /// documents are requested. Every segment collector must have room for the `top_n`
+    /// documents, and so must the merge of their results.
+    pub fn docs_per_segment(&self, top_n: usize, segments: usize) -> Result<usize, BudgetExceeded> {
+        let segments = segments.max(1);
+        let merged = top_n * segments;
+        let required = (merged + top_n * segments) * DOC_BYTES;
+
+        if required > self.bytes {
+            return Err(BudgetExceeded {
+                required,
+                budget: self.bytes,
+            });
+        }
+
+        Ok((self.bytes / DOC_BYTES - merged) / segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn docs_per_segment() {
+        let budget = MemoryBudget::new(1_000 * DOC_BYTES);
+
+        assert_eq!(budget.docs_per_segment(10, 1).unwrap(), 990);
+        assert_eq!(budget.docs_per_segment(10, 4).unwrap(), 240);
+        assert_eq!(budget.docs_per_segment(10, 0).unwrap(), 990);
+
+        assert!(budget.docs_per_segment(10, 50).unwrap() >= 10);
+
+        let err = budget.docs_per_segment(10, 51).unwrap_err();
+        assert_eq!(err.required, 1_020 * DOC_BYTES);
+        assert_eq!(err.budget, 1_000 * DOC_BYTES);
+    }
+}
@@ -0,0 +1,296 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+
+//! Field collapsing of the top documents. Only the highest scoring document is kept for each
+//! value of the collapse field, so e.g. a cluster of near identical pages is represented by a
+//! single result. Documents without a value are never collapsed.
+
+use std::collections::HashMap;
+
+use tantivy::{
+    collector::{Collector, ScoreSegmentTweaker, ScoreTweaker, SegmentCollector},
+    DocId, SegmentReader,
+};
+
+use crate::{
+    fastfield_reader::{self, Value},
+    inverted_index::WebpagePointer,
+    ranking::initial::Score,
+    schema::FastFieldEnum,
+};
+
+use super::{
+    top_docs::{SegmentDoc, TopTweakedScoreSegmentCollector, TweakedScoreTopCollector},
+    Doc, TopDocs,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CollapseKey {
+    U64(u64),
+    Bytes(Vec<u8>),
+}
+
+impl CollapseKey {
+    /// The u64 fast fields store `u64::MAX` when the webpage has no value,
+    /// and the bytes fast fields return nothing.
+    fn get(
+        reader: &fastfield_reader::SegmentReader,
+        field: FastFieldEnum,
+        doc: DocId,
+    ) -> Option<Self> {
+        match reader.get_field_reader(doc).get(field)? {
+            Value::U64(u64::MAX) => None,
+            Value::U64(val) => Some(Self::U64(val)),
+            Value::Bytes(val) => Some(Self::Bytes(val)),
+        }
+    }
+}
+
+fn is_better(doc: &SegmentDoc, other: &SegmentDoc) -> bool {
+    doc.score()
+        .total_cmp(&other.score())
+        .then_with(|| other.hashes().url.0.cmp(&doc.hashes().url.0))
+        .is_gt()
+}
+
+/// Keep the best document of each key in `best`, or in `unique` if it has no key.
+fn insert(
+    best: &mut HashMap<CollapseKey, SegmentDoc>,
+    unique: &mut Vec<SegmentDoc>,
+    key: Option<CollapseKey>,
+    doc: SegmentDoc,
+) {
+    match key {
+        Some(key) => match best.get_mut(&key) {
+            Some(current) => {
+                if is_better(&doc, current) {
+                    *current = doc;
+                }
+            }
+            None => {
+                best.insert(key, doc);
+            }
+        },
+        None => unique.push(doc),
+    }
+}
+
+pub struct CollapsingTopDocsCollector<TScoreTweaker> {
+    field: FastFieldEnum,
+    collector: TweakedScoreTopCollector<TScoreTweaker>,
+}
+
+impl<TScoreTweaker> CollapsingTopDocsCollector<TScoreTweaker> {
+    pub fn new(field: FastFieldEnum, collector: TweakedScoreTopCollector<TScoreTweaker>) -> Self {
+        Self { field, collector }
+    }
+
+    pub fn top_docs(&self) -> &TopDocs {
+        self.collector.top_docs()
+    }
+}
+
+impl<TScoreTweaker> Collector for CollapsingTopDocsCollector<TScoreTweaker>
+where
+    TScoreTweaker: ScoreTweaker<Score> + Send + Sync,
+{
+    type Fruit = Vec<WebpagePointer>;
+
+    type Child = CollapsingSegmentCollector<TScoreTweaker::Child>;
+
+    fn for_segment(
+        &self,
+        segment_local_id: u32,
+        segment_reader: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        Ok(CollapsingSegmentCollector {
+            field: self.field,
+            collector: self
+                .collector
+                .for_segment(segment_local_id, segment_reader)?,
+            best: HashMap::new(),
+            unique: Vec::new(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.collector.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+    ) -> tantivy::Result<Self::Fruit> {
+        let mut best = HashMap::new();
+        let mut unique = Vec::new();
+
+        for (key, doc) in segment_fruits.into_iter().flatten() {
+            insert(&mut best, &mut unique, key, doc);
+        }
+
+        Ok(self.top_docs().merge(best.into_values().chain(unique)))
+    }
+}
+
+pub struct CollapsingSegmentCollector<TSegmentScoreTweaker>
+where
+    TSegmentScoreTweaker: ScoreSegmentTweaker<Score>,
+{
+    field: FastFieldEnum,
+    collector: TopTweakedScoreSegmentCollector<TSegmentScoreTweaker>,
+    best: HashMap<CollapseKey, SegmentDoc>,
+    unique: Vec<SegmentDoc>,
+}
+
+impl<TSegmentScoreTweaker> SegmentCollector for CollapsingSegmentCollector<TSegmentScoreTweaker>
+where
+    TSegmentScoreTweaker: 'static + ScoreSegmentTweaker<Score>,
+{
+    type Fruit = Vec<(Option<CollapseKey>, SegmentDoc)>;
+
+    fn collect(&mut self, doc: DocId, score: tantivy::Score) {
+        if self.collector.is_done() {
+            return;
+        }
+
+        if let Some(segment_doc) = self.collector.segment_doc(doc, score) {
+            let key = CollapseKey::get(self.collector.fastfield_segment_reader(), self.field, doc);
+            insert(&mut self.best, &mut self.unique, key, segment_doc);
+        }
+    }
+
+    fn harvest(mut self) -> Self::Fruit {
+        let mut keys = HashMap::with_capacity(self.best.len());
+
+        for (key, doc) in self.best {
+            keys.insert(doc.id, key);
+            self.collector.insert(doc);
+        }
+
+        for doc in self.unique {
+            self.collector.insert(doc);
+        }
+
+        self.collector
+            .harvest()
+            .into_iter()
+            .map(|doc| (keys.remove(&doc.id), doc))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        index::Index,
+        query::Query,
+        ranking::{Ranker, SignalComputer},
+        schema::fast_field,
+        searcher::SearchQuery,
+        webgraph::NodeID,
+        webpage::{Html, Webpage},
+    };
+
+    #[test]
+    fn one_result_per_value() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        // (cluster, host centrality)
+        let pages = [
+            (Some(1), 1.0),
+            (Some(1), 5.0),
+            (Some(1), 3.0),
+            (Some(2), 2.0),
+            (Some(2), 4.0),
+            (None, 0.5),
+            (None, 0.25),
+        ];
+
+        for (i, (cluster, host_centrality)) in pages.into_iter().enumerate() {
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        r#"
+            <html>
+                <head>
+                    <title>Example website</title>
+                </head>
+                <body>
+                    test
+                </body>
+            </html>
+            "#,
+                        &format!("https://www.{i}.com"),
+                    )
+                    .unwrap(),
+                    host_centrality,
+                    fetch_time_ms: 500,
+                    node_id: cluster.map(|cluster: u64| NodeID::from(cluster)),
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let ctx = index.inverted_index.local_search_ctx();
+        let query = Query::parse(
+            &ctx,
+            &SearchQuery {
+                query: "test".to_string(),
+                ..Default::default()
+            },
+            &index.inverted_index,
+        )
+        .unwrap();
+
+        let mut ranker = Ranker::new(
+            SignalComputer::new(Some(&query)),
+            index.inverted_index.fastfield_reader(),
+            Default::default(),
+        );
+        ranker.de_rank_similar(false);
+
+        let res = index
+            .inverted_index
+            .search_initial(
+                &query,
+                &ctx,
+                ranker
+                    .collector(ctx.clone())
+                    .collapse_by(fast_field::HostNodeID.into()),
+            )
+            .unwrap();
+
+        let urls: Vec<_> = index
+            .inverted_index
+            .retrieve_websites(&res.top_websites, &query)
+            .unwrap()
+            .into_iter()
+            .map(|page| page.url)
+            .collect();
+
+        assert_eq!(
+            urls,
+            vec![
+                "https://www.1.com/",
+                "https://www.4.com/",
+                "https://www.5.com/",
+                "https://www.6.com/",
+            ]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Search-after cursors for deep pagination of the initial search.
+//!
+//! With a cursor the documents are collected in a stable order: by their initial score,
+//! with ties broken by the hash of their url and then by their address. A page is the
+//! top documents in that order that come after the cursor of the previous page, so the
+//! collector never has to keep the documents of the previous pages around like it does
+//! with an offset. The pages neither overlap nor leave gaps as long as the index is not
+//! changed between the requests.
+//!
+//! Similar documents are not de-ranked across pages, as that would depend on the documents
+//! of the previous pages. Results are still reordered within their page by the ranking
+//! pipeline.
+
+use std::cmp::Ordering;
+
+use crate::inverted_index::{DocAddress, WebpagePointer};
+
+/// The position of a document in the stable order of the cursors.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct Position {
+    pub score: f64,
+    pub url: u128,
+    pub address: DocAddress,
+}
+
+impl Position {
+    /// [`Ordering::Less`] if the document ranks higher than the other one.
+    pub fn cmp_rank(&self, other: &Self) -> Ordering {
+        other
+            .score
+            .total_cmp(&self.score)
+            .then_with(|| self.url.cmp(&other.url))
+            .then_with(|| {
+                (self.address.segment, self.address.doc_id)
+                    .cmp(&(other.address.segment, other.address.doc_id))
+            })
+    }
+}
+
+impl From<&WebpagePointer> for Position {
+    fn from(pointer: &WebpagePointer) -> Self {
+        Self {
+            score: pointer.score.total,
+            url: pointer.hashes.url.0,
+            address: pointer.address,
+        }
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub enum SearchCursor {
+    /// Before the first document, i.e. the first page of results.
+    Start,
+    /// After the last document of a page.
+    After(Position),
+}
+
+impl SearchCursor {
+    pub fn after(pointer: &WebpagePointer) -> Self {
+        Self::After(pointer.into())
+    }
+
+    /// Whether the cursor comes before the document, so the document belongs on a later page.
+    pub fn comes_before(&self, position: &Position) -> bool {
+        match self {
+            Self::Start => true,
+            Self::After(cursor) => cursor.cmp_rank(position) == Ordering::Less,
+        }
+    }
+}
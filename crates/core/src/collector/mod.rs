@@ -14,14 +14,42 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::{prehashed::Prehashed, ranking::initial::InitialScoreTweaker, simhash};
+use crate::{
+    inverted_index::WebpagePointer, prehashed::Prehashed, ranking::initial::InitialScoreTweaker,
+    simhash,
+};
 
 pub mod approx_count;
+mod budget;
+mod collapse;
+mod cursor;
+mod timed;
 mod top_docs;
 
+pub use budget::{BudgetExceeded, MemoryBudget};
+pub use collapse::CollapsingTopDocsCollector;
+pub use cursor::{Position, SearchCursor};
+pub use timed::{SegmentTiming, TimedCollector};
 pub use top_docs::{BucketCollector, TopDocs};
 pub type MainCollector = top_docs::TweakedScoreTopCollector<InitialScoreTweaker>;
 
+/// The collectors of the initial search that return the top documents.
+pub trait TopDocsCollector: tantivy::collector::Collector<Fruit = Vec<WebpagePointer>> {
+    fn top_docs(&self) -> &TopDocs;
+}
+
+impl TopDocsCollector for MainCollector {
+    fn top_docs(&self) -> &TopDocs {
+        self.top_docs()
+    }
+}
+
+impl TopDocsCollector for CollapsingTopDocsCollector<InitialScoreTweaker> {
+    fn top_docs(&self) -> &TopDocs {
+        self.top_docs()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct MaxDocsConsidered {
     pub total_docs: usize,
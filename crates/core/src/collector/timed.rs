@@ -0,0 +1,122 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::time::{Duration, Instant};
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    query::Weight,
+    DocId, Score, SegmentOrdinal, SegmentReader,
+};
+
+/// The time it took to collect the documents of a segment.
+#[derive(Debug, Clone, Copy)]
+pub struct SegmentTiming {
+    pub segment: SegmentOrdinal,
+    pub duration: Duration,
+}
+
+/// Wraps a collector to time the collection of each segment.
+pub struct TimedCollector<'a, C> {
+    inner: &'a C,
+}
+
+impl<'a, C: Collector> TimedCollector<'a, C> {
+    pub fn new(inner: &'a C) -> Self {
+        Self { inner }
+    }
+}
+
+impl<C: Collector> Collector for TimedCollector<'_, C> {
+    type Fruit = (C::Fruit, Vec<SegmentTiming>);
+    type Child = TimedSegmentCollector<C::Child>;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        let start = Instant::now();
+
+        Ok(TimedSegmentCollector {
+            inner: self.inner.for_segment(segment_local_id, segment)?,
+            segment: segment_local_id,
+            start,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.inner.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+    ) -> tantivy::Result<Self::Fruit> {
+        let (fruits, mut timings): (Vec<_>, Vec<_>) = segment_fruits.into_iter().unzip();
+        timings.sort_by_key(|timing: &SegmentTiming| timing.segment);
+
+        Ok((self.inner.merge_fruits(fruits)?, timings))
+    }
+
+    // the inner collector might have its own way of collecting a segment,
+    // so it collects the segment while it is timed.
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> tantivy::Result<<Self::Child as SegmentCollector>::Fruit> {
+        let start = Instant::now();
+        let fruit = self.inner.collect_segment(weight, segment_ord, reader)?;
+
+        Ok((
+            fruit,
+            SegmentTiming {
+                segment: segment_ord,
+                duration: start.elapsed(),
+            },
+        ))
+    }
+}
+
+pub struct TimedSegmentCollector<S> {
+    inner: S,
+    segment: SegmentOrdinal,
+    start: Instant,
+}
+
+impl<S: SegmentCollector> SegmentCollector for TimedSegmentCollector<S> {
+    type Fruit = (S::Fruit, SegmentTiming);
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        self.inner.collect(doc, score);
+    }
+
+    fn collect_block(&mut self, docs: &[DocId]) {
+        self.inner.collect_block(docs);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        (
+            self.inner.harvest(),
+            SegmentTiming {
+                segment: self.segment,
+                duration: self.start.elapsed(),
+            },
+        )
+    }
+}
@@ -14,7 +14,10 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use bloom::combine_u64s;
 use min_max_heap::MinMaxHeap;
@@ -34,7 +37,10 @@ use crate::{
     simhash,
 };
 
-use super::{Doc, Hashes, MainCollector, MaxDocsConsidered};
+use super::{
+    cursor::{Position, SearchCursor},
+    CollapsingTopDocsCollector, Doc, Hashes, MainCollector, MaxDocsConsidered,
+};
 
 pub struct TopDocs {
     top_n: usize,
@@ -43,6 +49,8 @@ pub struct TopDocs {
     fastfield_reader: fastfield_reader::FastFieldReader,
     de_rank_similar: bool,
     collector_config: CollectorConfig,
+    host_filter: Option<Arc<HashSet<Prehashed>>>,
+    search_after: Option<SearchCursor>,
 }
 
 impl TopDocs {
@@ -54,6 +62,8 @@ impl TopDocs {
             de_rank_similar: false,
             fastfield_reader,
             collector_config: CollectorConfig::default(),
+            host_filter: None,
+            search_after: None,
         }
     }
 
@@ -76,6 +86,18 @@ impl TopDocs {
         self
     }
 
+    /// Skip documents whose site hash is not in the filter.
+    pub fn and_host_filter(mut self, host_filter: Arc<HashSet<Prehashed>>) -> Self {
+        self.host_filter = Some(host_filter);
+        self
+    }
+
+    /// Only collect the documents after the cursor, in the stable order of the cursors.
+    pub fn and_search_after(mut self, cursor: SearchCursor) -> Self {
+        self.search_after = Some(cursor);
+        self
+    }
+
     pub fn and_collector_config(mut self, collector_config: CollectorConfig) -> Self {
         self.collector_config = collector_config;
         self
@@ -84,6 +106,47 @@ impl TopDocs {
     pub fn main_collector(self, score_tweaker: InitialScoreTweaker) -> MainCollector {
         MainCollector::new(score_tweaker, self)
     }
+
+    /// The top documents across all segments.
+    pub(super) fn merge(&self, docs: impl IntoIterator<Item = SegmentDoc>) -> Vec<WebpagePointer> {
+        if self.search_after.is_some() {
+            return sort_by_position(docs.into_iter().collect())
+                .into_iter()
+                .skip(self.offset)
+                .take(self.top_n)
+                .map(SegmentDoc::into_pointer)
+                .collect();
+        }
+
+        let capacity = self
+            .max_docs
+            .as_ref()
+            .map_or(self.collector_config.max_docs_considered, |max_docs| {
+                max_docs.total_docs
+            });
+
+        let mut collector = BucketCollector::with_capacity(
+            self.top_n + self.offset,
+            capacity.min(self.collector_config.max_docs_considered) + 1,
+            self.collector_config.clone(),
+        );
+
+        for doc in docs {
+            collector.insert(doc);
+        }
+
+        collector
+            .into_sorted_vec(self.de_rank_similar)
+            .into_iter()
+            .skip(self.offset)
+            .map(SegmentDoc::into_pointer)
+            .collect()
+    }
+}
+
+fn sort_by_position(mut docs: Vec<SegmentDoc>) -> Vec<SegmentDoc> {
+    docs.sort_by(|a, b| a.position().cmp_rank(&b.position()));
+    docs
 }
 
 impl TopDocs {
@@ -101,9 +164,16 @@ impl TopDocs {
             fastfield_segment_reader: self.fastfield_reader.get_segment(&segment.segment_id()),
             max_docs,
             num_docs_taken: 0,
+            host_filter: self.host_filter.clone(),
+            search_after: self.search_after,
+            top_n: self.top_n + self.offset,
             segment_ord: segment_local_id,
-            bucket_collector: BucketCollector::new(
+            bucket_collector: BucketCollector::with_capacity(
                 self.top_n + self.offset,
+                max_docs
+                    .unwrap_or(self.collector_config.max_docs_considered)
+                    .min(self.collector_config.max_docs_considered)
+                    + 1,
                 self.collector_config.clone(),
             ),
         })
@@ -114,11 +184,18 @@ pub struct TopSegmentCollector {
     fastfield_segment_reader: Arc<fastfield_reader::SegmentReader>,
     max_docs: Option<usize>,
     num_docs_taken: usize,
+    host_filter: Option<Arc<HashSet<Prehashed>>>,
+    search_after: Option<SearchCursor>,
+    top_n: usize,
     segment_ord: SegmentOrdinal,
     bucket_collector: BucketCollector<SegmentDoc>,
 }
 
 impl TopSegmentCollector {
+    pub(super) fn fastfield_segment_reader(&self) -> &fastfield_reader::SegmentReader {
+        &self.fastfield_segment_reader
+    }
+
     fn get_hash(&self, doc: DocId, field1: FastFieldEnum, field2: FastFieldEnum) -> Prehashed {
         let field_reader = self.fastfield_segment_reader.get_field_reader(doc);
 
@@ -140,8 +217,27 @@ impl TopSegmentCollector {
     }
 
     fn collect(&mut self, doc: DocId, score: Score) {
+        if let Some(doc) = self.segment_doc(doc, score) {
+            self.insert(doc);
+        }
+    }
+
+    /// The document as it would be collected, or `None` if it should be skipped.
+    fn segment_doc(&mut self, doc: DocId, score: Score) -> Option<SegmentDoc> {
         if self.is_done() {
-            return;
+            return None;
+        }
+
+        let site = self.get_hash(
+            doc,
+            fast_field::SiteHash1.into(),
+            fast_field::SiteHash2.into(),
+        );
+
+        if let Some(host_filter) = &self.host_filter {
+            if !host_filter.contains(&site) {
+                return None;
+            }
         }
 
         self.num_docs_taken += 1;
@@ -153,13 +249,9 @@ impl TopSegmentCollector {
             .unwrap()
             .into();
 
-        self.bucket_collector.insert(SegmentDoc {
+        let doc = SegmentDoc {
             hashes: Hashes {
-                site: self.get_hash(
-                    doc,
-                    fast_field::SiteHash1.into(),
-                    fast_field::SiteHash2.into(),
-                ),
+                site,
                 title: self.get_hash(
                     doc,
                     fast_field::TitleHash1.into(),
@@ -180,10 +272,26 @@ impl TopSegmentCollector {
             id: doc,
             segment: self.segment_ord,
             score,
-        });
+        };
+
+        match &self.search_after {
+            Some(cursor) if !cursor.comes_before(&doc.position()) => None,
+            _ => Some(doc),
+        }
+    }
+
+    fn insert(&mut self, doc: SegmentDoc) {
+        self.bucket_collector.insert(doc);
     }
 
     fn harvest(self) -> Vec<SegmentDoc> {
+        if self.search_after.is_some() {
+            let mut docs = sort_by_position(self.bucket_collector.into_unsorted_vec());
+            docs.truncate(self.top_n);
+
+            return docs;
+        }
+
         self.bucket_collector.into_sorted_vec(true)
     }
 }
@@ -201,13 +309,17 @@ impl<T: Doc> PartialOrd for ScoredDoc<T> {
 
 impl<T: Doc> PartialEq for ScoredDoc<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.adjusted_score == other.adjusted_score
+        self.cmp(other) == std::cmp::Ordering::Equal
     }
 }
 
 impl<T: Doc> Ord for ScoredDoc<T> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.adjusted_score.total_cmp(&other.adjusted_score)
+        // ties are broken by the url hash so the order of equally scored
+        // documents does not depend on the order they were collected in.
+        self.adjusted_score
+            .total_cmp(&other.adjusted_score)
+            .then_with(|| other.doc.hashes().url.0.cmp(&self.doc.hashes().url.0))
     }
 }
 
@@ -271,11 +383,16 @@ pub struct BucketCollector<T: Doc> {
 
 impl<T: Doc> BucketCollector<T> {
     pub fn new(top_n: usize, config: CollectorConfig) -> Self {
+        Self::with_capacity(top_n, config.max_docs_considered + 1, config)
+    }
+
+    /// A collector with room for `capacity` documents before it has to grow.
+    pub fn with_capacity(top_n: usize, capacity: usize, config: CollectorConfig) -> Self {
         assert!(top_n > 0);
 
         Self {
             top_n,
-            documents: MinMaxHeap::with_capacity(config.max_docs_considered + 1),
+            documents: MinMaxHeap::with_capacity(capacity),
             count: BucketCount::new(config),
         }
     }
@@ -301,6 +418,15 @@ impl<T: Doc> BucketCollector<T> {
         }
     }
 
+    /// The documents in no particular order.
+    pub fn into_unsorted_vec(self) -> Vec<T> {
+        self.documents
+            .into_vec()
+            .into_iter()
+            .map(|doc| doc.doc)
+            .collect()
+    }
+
     pub fn into_sorted_vec(mut self, de_rank_similar: bool) -> Vec<T> {
         let mut res = Vec::new();
         let mut simhash_dups = Vec::new();
@@ -338,11 +464,36 @@ impl<T: Doc> BucketCollector<T> {
 #[derive(Debug, Clone)]
 pub struct SegmentDoc {
     hashes: Hashes,
-    id: DocId,
+    pub(super) id: DocId,
     segment: SegmentOrdinal,
     score: Score,
 }
 
+impl SegmentDoc {
+    fn address(&self) -> DocAddress {
+        DocAddress {
+            segment: self.segment,
+            doc_id: self.id,
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            score: self.score.total,
+            url: self.hashes.url.0,
+            address: self.address(),
+        }
+    }
+
+    fn into_pointer(self) -> WebpagePointer {
+        WebpagePointer {
+            address: self.address(),
+            score: self.score,
+            hashes: self.hashes,
+        }
+    }
+}
+
 impl Doc for SegmentDoc {
     fn score(&self) -> f64 {
         self.score.total
@@ -372,6 +523,11 @@ impl<TScoreTweaker> TweakedScoreTopCollector<TScoreTweaker> {
     pub fn top_docs(&self) -> &TopDocs {
         &self.top_docs
     }
+
+    /// Only keep the highest scoring document for each value of `field`.
+    pub fn collapse_by(self, field: FastFieldEnum) -> CollapsingTopDocsCollector<TScoreTweaker> {
+        CollapsingTopDocsCollector::new(field, self)
+    }
 }
 
 impl<TScoreTweaker> Collector for TweakedScoreTopCollector<TScoreTweaker>
@@ -407,30 +563,7 @@ where
         &self,
         segment_fruits: Vec<<Self::Child as tantivy::collector::SegmentCollector>::Fruit>,
     ) -> tantivy::Result<Self::Fruit> {
-        let mut collector = BucketCollector::new(
-            self.top_docs.top_n + self.top_docs.offset,
-            self.top_docs.collector_config.clone(),
-        );
-
-        for docs in segment_fruits {
-            for doc in docs {
-                collector.insert(doc);
-            }
-        }
-
-        Ok(collector
-            .into_sorted_vec(self.top_docs.de_rank_similar)
-            .into_iter()
-            .skip(self.top_docs.offset)
-            .map(|doc| WebpagePointer {
-                score: doc.score,
-                hashes: doc.hashes,
-                address: DocAddress {
-                    segment: doc.segment,
-                    doc_id: doc.id,
-                },
-            })
-            .collect())
+        Ok(self.top_docs.merge(segment_fruits.into_iter().flatten()))
     }
 }
 
@@ -442,6 +575,29 @@ where
     segment_scorer: TSegmentScoreTweaker,
 }
 
+impl<TSegmentScoreTweaker> TopTweakedScoreSegmentCollector<TSegmentScoreTweaker>
+where
+    TSegmentScoreTweaker: ScoreSegmentTweaker<Score>,
+{
+    pub(super) fn fastfield_segment_reader(&self) -> &fastfield_reader::SegmentReader {
+        self.segment_collector.fastfield_segment_reader()
+    }
+
+    pub(super) fn is_done(&self) -> bool {
+        self.segment_collector.is_done()
+    }
+
+    /// Score the document without collecting it.
+    pub(super) fn segment_doc(&mut self, doc: DocId, score: tantivy::Score) -> Option<SegmentDoc> {
+        let score = self.segment_scorer.score(doc, score);
+        self.segment_collector.segment_doc(doc, score)
+    }
+
+    pub(super) fn insert(&mut self, doc: SegmentDoc) {
+        self.segment_collector.insert(doc);
+    }
+}
+
 impl<TSegmentScoreTweaker> SegmentCollector
     for TopTweakedScoreSegmentCollector<TSegmentScoreTweaker>
 where
@@ -38,6 +38,66 @@ impl Collector {
     }
 }
 
+pub struct Diversity;
+
+impl Diversity {
+    pub fn lambda() -> f64 {
+        1.0
+    }
+
+    pub fn top_n() -> usize {
+        30
+    }
+
+    pub fn max_displacement() -> usize {
+        5
+    }
+
+    pub fn similarity_threshold() -> f64 {
+        0.5
+    }
+}
+
+pub struct RelFlagWeights;
+
+impl RelFlagWeights {
+    pub fn nofollow() -> f64 {
+        0.5
+    }
+
+    pub fn sponsored() -> f64 {
+        0.25
+    }
+
+    pub fn is_in_footer() -> f64 {
+        1.0
+    }
+
+    pub fn is_in_navigation() -> f64 {
+        1.0
+    }
+}
+
+pub struct HostRankingWeights;
+
+impl HostRankingWeights {
+    pub fn optic() -> f64 {
+        1.0
+    }
+}
+
+pub struct LatencyBudget;
+
+impl LatencyBudget {
+    pub fn stage_ms() -> u64 {
+        20
+    }
+
+    pub fn min_top_k() -> usize {
+        10
+    }
+}
+
 pub struct Api;
 
 impl Api {
@@ -58,6 +118,106 @@ impl Api {
     }
 }
 
+pub struct SiteInfo;
+
+impl SiteInfo {
+    pub fn budget_ms() -> u64 {
+        300
+    }
+
+    pub fn cache_ttl_secs() -> u64 {
+        60 * 60
+    }
+
+    pub fn cache_size() -> usize {
+        10_000
+    }
+
+    pub fn co_links_max_linkers() -> usize {
+        32
+    }
+
+    pub fn co_links_max_destinations() -> usize {
+        64
+    }
+
+    pub fn num_co_linked_hosts() -> usize {
+        8
+    }
+}
+
+pub struct RateLimit;
+
+impl RateLimit {
+    pub fn max_clients() -> usize {
+        100_000
+    }
+}
+
+pub struct HttpCache;
+
+impl HttpCache {
+    pub fn static_max_age_secs() -> Option<u64> {
+        Some(24 * 60 * 60)
+    }
+
+    pub fn autosuggest_max_age_secs() -> Option<u64> {
+        Some(60 * 60)
+    }
+
+    pub fn site_info_max_age_secs() -> Option<u64> {
+        Some(60 * 60)
+    }
+
+    pub fn index_max_age_secs() -> Option<u64> {
+        Some(5 * 60)
+    }
+
+    pub fn generation_refresh_secs() -> u64 {
+        60
+    }
+}
+
+pub struct S3;
+
+impl S3 {
+    pub fn path_style() -> bool {
+        true
+    }
+}
+
+pub struct Retrieval;
+
+impl Retrieval {
+    pub fn threads() -> usize {
+        8
+    }
+
+    pub fn timeout_ms() -> u64 {
+        500
+    }
+
+    pub fn max_queued_reads() -> usize {
+        1024
+    }
+}
+
+pub struct CircuitBreaker;
+
+impl CircuitBreaker {
+    pub fn failure_threshold() -> usize {
+        5
+    }
+
+    pub fn cool_down_ms() -> u64 {
+        10_000
+    }
+
+    pub fn request_timeout_ms() -> u64 {
+        60_000
+    }
+}
+
 pub struct Snippet;
 
 impl Snippet {
@@ -126,6 +286,14 @@ impl Crawler {
     }
 }
 
+pub struct Neighborhood;
+
+impl Neighborhood {
+    pub fn per_direction_limit() -> usize {
+        20
+    }
+}
+
 pub struct SearchQuery;
 
 impl SearchQuery {
@@ -137,8 +305,8 @@ impl SearchQuery {
         false
     }
 
-    pub fn safe_search() -> bool {
-        false
+    pub fn safe_search() -> crate::api::search::SafeSearch {
+        crate::api::search::SafeSearch::Off
     }
 
     pub fn count_results_exact() -> bool {
@@ -148,6 +316,22 @@ impl SearchQuery {
     pub fn return_structured_data() -> bool {
         false
     }
+
+    pub fn optic_debug() -> bool {
+        false
+    }
+
+    pub fn debug_timings() -> bool {
+        false
+    }
+
+    pub fn dedup_urls() -> bool {
+        false
+    }
+
+    pub fn verbatim() -> bool {
+        false
+    }
 }
 
 pub struct Correction;
@@ -184,6 +368,38 @@ impl Indexing {
     pub fn autocommit_after_num_inserts() -> usize {
         25_000
     }
+
+    pub fn main_content_extraction() -> bool {
+        true
+    }
+
+    pub fn freshness_buckets_days() -> Vec<u64> {
+        vec![30, 90, 365]
+    }
+}
+
+pub struct TermPruning;
+
+impl TermPruning {
+    pub fn sketch_width() -> usize {
+        1 << 20
+    }
+}
+
+pub struct Tokenization;
+
+impl Tokenization {
+    pub fn languages() -> std::collections::HashMap<String, crate::tokenizer::TokenizerKind> {
+        crate::tokenizer::TokenizerKind::CJK_LANGS
+            .iter()
+            .map(|lang| {
+                (
+                    lang.code().to_string(),
+                    crate::tokenizer::TokenizerKind::Cjk,
+                )
+            })
+            .collect()
+    }
 }
 
 pub struct ApproxHarmonic;
@@ -206,4 +422,124 @@ impl Webgraph {
     pub fn merge_all_segments() -> bool {
         true
     }
+
+    pub fn max_label_length() -> usize {
+        crate::webgraph::MAX_LABEL_LENGTH
+    }
+}
+
+pub struct EdgeBufferPool;
+impl EdgeBufferPool {
+    pub fn max_buffers() -> usize {
+        16
+    }
+
+    pub fn max_buffer_len() -> usize {
+        65_536
+    }
+}
+
+pub struct CrawlRetry;
+impl CrawlRetry {
+    pub fn max_retries() -> u32 {
+        3
+    }
+
+    pub fn backoff_ms() -> u64 {
+        60_000
+    }
+
+    pub fn max_backoff_ms() -> u64 {
+        60 * 60 * 1000
+    }
+}
+
+pub struct CrawlSpace;
+impl CrawlSpace {
+    pub fn url_threshold() -> u64 {
+        1_000
+    }
+
+    pub fn min_reported_pages() -> u64 {
+        50
+    }
+
+    pub fn max_distinct_fraction() -> f64 {
+        0.1
+    }
+
+    pub fn trickle_every() -> u64 {
+        100
+    }
+
+    pub fn max_signatures_per_host() -> usize {
+        10_000
+    }
+}
+
+pub struct EdgeQueryCaps;
+impl EdgeQueryCaps {
+    pub fn max_edges() -> usize {
+        100_000
+    }
+
+    pub fn max_edges_internal() -> usize {
+        1_000_000
+    }
+
+    pub fn timeout_ms() -> Option<u64> {
+        Some(10_000)
+    }
+}
+
+pub struct Synonyms;
+
+impl Synonyms {
+    pub fn boost() -> f64 {
+        0.5
+    }
+}
+
+pub struct Segmentation;
+
+impl Segmentation {
+    pub fn min_doc_freq() -> u64 {
+        5
+    }
+
+    pub fn boost() -> f64 {
+        0.5
+    }
+}
+
+pub struct Replica;
+
+impl Replica {
+    pub fn poll_interval_sec() -> u64 {
+        60
+    }
+
+    pub fn chunk_size_bytes() -> u64 {
+        16 * 1024 * 1024
+    }
+}
+
+pub struct Warmup;
+
+impl Warmup {
+    pub fn priority_segments() -> usize {
+        4
+    }
+
+    pub fn background_delay_ms() -> u64 {
+        0
+    }
+}
+
+pub struct ArtifactManifest;
+
+impl ArtifactManifest {
+    pub fn expiry_secs() -> u32 {
+        24 * 60 * 60
+    }
 }
@@ -20,6 +20,9 @@ use super::Result;
 use crate::ampc::dht;
 use crate::distributed::member::ShardId;
 use crate::feed::scheduler::SplitId;
+use crate::ranking::SignalBound;
+use crate::tokenizer::TokenizerKind;
+use crate::webpage::html::links::RelFlags;
 
 use std::fs::File;
 use std::io::{self, BufRead};
@@ -45,7 +48,96 @@ pub struct IndexerConfig {
     #[serde(default = "defaults::Indexing::autocommit_after_num_inserts")]
     pub autocommit_after_num_inserts: usize,
 
+    /// Index the main content of pages in the body and the rest in the boilerplate field.
+    /// Disable to index all the clean text of pages in the body.
+    #[serde(default = "defaults::Indexing::main_content_extraction")]
+    pub main_content_extraction: bool,
+
+    /// The maximum ages in days of the buckets of the freshness histogram of the index.
+    #[serde(default = "defaults::Indexing::freshness_buckets_days")]
+    pub freshness_buckets_days: Vec<u64>,
+
+    #[serde(default)]
+    pub tokenization: TokenizationConfig,
+
     pub dual_encoder: Option<IndexerDualEncoderConfig>,
+
+    /// Leave the rare terms of the configured fields out of the index. The pages are then
+    /// processed twice, once to count the terms and once to index them.
+    #[serde(default)]
+    pub term_pruning: Option<TermPruningConfig>,
+}
+
+/// See [`crate::inverted_index::TermPruning`] for how pruning affects the search results.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct TermPruningConfig {
+    /// Terms that occur fewer times than this in the whole corpus are not indexed.
+    pub min_term_freq: u64,
+
+    /// Names of the text fields to prune, e.g. `clean_body_bigrams`.
+    pub fields: Vec<String>,
+
+    /// Number of counters in each row of the sketch the terms of a field are counted in.
+    /// Each indexing thread keeps 16 bytes per counter for every pruned field. A narrower
+    /// sketch uses less memory, but more of the rare terms share a counter with a frequent
+    /// term and are kept.
+    #[serde(default = "defaults::TermPruning::sketch_width")]
+    pub sketch_width: usize,
+}
+
+impl TermPruningConfig {
+    pub fn text_fields(&self) -> Result<Vec<crate::schema::TextFieldEnum>> {
+        self.fields
+            .iter()
+            .map(|name| {
+                crate::schema::Field::from_name(name)
+                    .and_then(|field| field.as_text())
+                    .ok_or_else(|| anyhow::anyhow!("unknown text field: {name}"))
+            })
+            .collect()
+    }
+}
+
+/// The tokenizer that the text of a page is split into words with, chosen by the detected
+/// language of the page. The runs of Chinese and Japanese characters in queries are always
+/// split like by [`TokenizerKind::Cjk`], so that text of the pages that are tokenized by
+/// [`TokenizerKind::Standard`] is not matched by the queries. The mapping should only be
+/// changed for new indexes.
+#[derive(
+    Debug, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Clone, PartialEq,
+)]
+pub struct TokenizationConfig {
+    /// The tokenizer of the pages in each language, keyed by ISO 639-3 code (e.g. `jpn`).
+    /// Pages in the other languages use the standard tokenizer.
+    #[serde(default = "defaults::Tokenization::languages")]
+    pub languages: std::collections::HashMap<String, TokenizerKind>,
+
+    /// The tokenizer of the pages whose language could not be detected reliably,
+    /// e.g. because they mix several languages.
+    #[serde(default)]
+    pub default: TokenizerKind,
+}
+
+impl Default for TokenizationConfig {
+    fn default() -> Self {
+        Self {
+            languages: defaults::Tokenization::languages(),
+            default: TokenizerKind::default(),
+        }
+    }
+}
+
+impl TokenizationConfig {
+    pub fn tokenizer(&self, lang: Option<&whatlang::Lang>) -> TokenizerKind {
+        match lang {
+            Some(lang) => self
+                .languages
+                .get(lang.code())
+                .copied()
+                .unwrap_or(TokenizerKind::Standard),
+            None => self.default,
+        }
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
@@ -77,6 +169,10 @@ pub struct WebgraphConstructConfig {
 
     #[serde(default = "defaults::Webgraph::merge_all_segments")]
     pub merge_all_segments: bool,
+
+    /// The maximum number of characters stored for the anchor text of a link.
+    #[serde(default = "defaults::Webgraph::max_label_length")]
+    pub max_label_length: usize,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Clone)]
@@ -101,21 +197,7 @@ impl WarcSource {
                 warc_paths.clone_from(&config.names);
             }
             WarcSource::S3(config) => {
-                let bucket = s3::Bucket::new(
-                    &config.bucket,
-                    s3::Region::Custom {
-                        region: "".to_string(),
-                        endpoint: config.endpoint.clone(),
-                    },
-                    s3::creds::Credentials {
-                        access_key: Some(config.access_key.clone()),
-                        secret_key: Some(config.secret_key.clone()),
-                        security_token: None,
-                        session_token: None,
-                        expiration: None,
-                    },
-                )?
-                .with_path_style();
+                let bucket = config.bucket()?;
 
                 let mut folder = config.folder.clone();
 
@@ -162,9 +244,78 @@ pub struct HttpConfig {
 pub struct S3Config {
     pub bucket: String,
     pub folder: String,
-    pub access_key: String,
-    pub secret_key: String,
-    pub endpoint: String,
+
+    /// Without the keys, the credentials are looked up in the environment, the AWS profile
+    /// and the instance metadata.
+    #[serde(default)]
+    pub access_key: Option<String>,
+    #[serde(default)]
+    pub secret_key: Option<String>,
+
+    /// Send unsigned requests, for public buckets.
+    #[serde(default)]
+    pub anonymous: bool,
+
+    /// Url of an S3 compatible store, e.g. `http://localhost:9000` for MinIO.
+    /// Without it, the AWS endpoint of the region is used.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+
+    /// The region the requests are signed for.
+    #[serde(default)]
+    pub region: Option<String>,
+
+    /// Address the bucket in the path of the urls (`endpoint/bucket/key`) instead of the
+    /// host (`bucket.endpoint/key`). Most stores other than AWS only support the path style.
+    #[serde(default = "defaults::S3::path_style")]
+    pub path_style: bool,
+}
+
+impl S3Config {
+    fn region(&self) -> Result<s3::Region> {
+        match (&self.endpoint, &self.region) {
+            (Some(endpoint), region) => Ok(s3::Region::Custom {
+                region: region.clone().unwrap_or_default(),
+                endpoint: endpoint.clone(),
+            }),
+            (None, Some(region)) => Ok(region.parse()?),
+            (None, None) => Err(anyhow::anyhow!(
+                "the s3 config of bucket '{}' needs an endpoint or a region",
+                self.bucket
+            )),
+        }
+    }
+
+    fn credentials(&self) -> Result<s3::creds::Credentials> {
+        if self.anonymous {
+            return Ok(s3::creds::Credentials::anonymous()?);
+        }
+
+        match (&self.access_key, &self.secret_key) {
+            (Some(access_key), Some(secret_key)) => Ok(s3::creds::Credentials {
+                access_key: Some(access_key.clone()),
+                secret_key: Some(secret_key.clone()),
+                security_token: None,
+                session_token: None,
+                expiration: None,
+            }),
+            (None, None) => Ok(s3::creds::Credentials::default()?),
+            _ => Err(anyhow::anyhow!(
+                "the s3 config of bucket '{}' must have both keys or neither",
+                self.bucket
+            )),
+        }
+    }
+
+    pub fn bucket(&self) -> Result<Box<s3::Bucket>> {
+        let bucket = s3::Bucket::new(&self.bucket, self.region()?, self.credentials()?)?;
+
+        if self.path_style {
+            Ok(bucket.with_path_style())
+        } else {
+            Ok(bucket)
+        }
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
@@ -183,6 +334,11 @@ pub struct CollectorConfig {
 
     #[serde(default = "defaults::Collector::max_docs_considered")]
     pub max_docs_considered: usize,
+
+    /// The number of bytes the collectors of a search request may use. Searches consider
+    /// fewer documents to stay within the budget, and fail if it cannot fit their results.
+    #[serde(default)]
+    pub memory_budget: Option<usize>,
 }
 
 impl Default for CollectorConfig {
@@ -193,6 +349,138 @@ impl Default for CollectorConfig {
             url_penalty: defaults::Collector::url_penalty(),
             url_without_tld_penalty: defaults::Collector::url_without_tld_penalty(),
             max_docs_considered: defaults::Collector::max_docs_considered(),
+            memory_budget: None,
+        }
+    }
+}
+
+/// How much the links with each of the rel flags count towards the link based ranking
+/// signals, relative to a plain link. A link with several of the flags is weighted
+/// by the lowest of their weights.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct RelFlagWeights {
+    #[serde(default = "defaults::RelFlagWeights::nofollow")]
+    pub nofollow: f64,
+
+    #[serde(default = "defaults::RelFlagWeights::sponsored")]
+    pub sponsored: f64,
+
+    #[serde(default = "defaults::RelFlagWeights::is_in_footer")]
+    pub is_in_footer: f64,
+
+    #[serde(default = "defaults::RelFlagWeights::is_in_navigation")]
+    pub is_in_navigation: f64,
+}
+
+impl Default for RelFlagWeights {
+    fn default() -> Self {
+        Self {
+            nofollow: defaults::RelFlagWeights::nofollow(),
+            sponsored: defaults::RelFlagWeights::sponsored(),
+            is_in_footer: defaults::RelFlagWeights::is_in_footer(),
+            is_in_navigation: defaults::RelFlagWeights::is_in_navigation(),
+        }
+    }
+}
+
+impl RelFlagWeights {
+    /// The weight of a link with the rel flags.
+    pub fn weight(&self, rel: RelFlags) -> f64 {
+        [
+            (RelFlags::NOFOLLOW, self.nofollow),
+            (RelFlags::SPONSORED, self.sponsored),
+            (RelFlags::IS_IN_FOOTER, self.is_in_footer),
+            (RelFlags::IS_IN_NAVIGATION, self.is_in_navigation),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| rel.contains(*flag))
+        .map(|(_, weight)| weight.clamp(0.0, 1.0))
+        .fold(1.0, f64::min)
+    }
+}
+
+/// How much the liked and disliked hosts of different sources count towards the inbound
+/// similarity of the results. The hosts ranked by the user have a weight of 1.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct HostRankingWeights {
+    /// Weight of the hosts that are liked or disliked by the optic of the search.
+    #[serde(default = "defaults::HostRankingWeights::optic")]
+    pub optic: f64,
+}
+
+impl Default for HostRankingWeights {
+    fn default() -> Self {
+        Self {
+            optic: defaults::HostRankingWeights::optic(),
+        }
+    }
+}
+
+/// Re-ranks the top results to trade relevance for topical diversity (maximal marginal relevance).
+/// The topics are compared by the title or keyword embeddings of the results, so the results
+/// from indexes built without an embedding model are not diversified. The search servers warn
+/// about such indexes when they start.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct DiversityConfig {
+    /// Weight of relevance against dissimilarity to the results already selected.
+    /// `1.0` only considers relevance and disables diversification.
+    #[serde(default = "defaults::Diversity::lambda")]
+    pub lambda: f64,
+
+    /// Number of top results considered for diversification.
+    #[serde(default = "defaults::Diversity::top_n")]
+    pub top_n: usize,
+
+    /// No result is moved more than this many positions from its rank by relevance.
+    #[serde(default = "defaults::Diversity::max_displacement")]
+    pub max_displacement: usize,
+
+    /// Results less similar than this are considered to be about different topics.
+    #[serde(default = "defaults::Diversity::similarity_threshold")]
+    pub similarity_threshold: f64,
+}
+
+impl Default for DiversityConfig {
+    fn default() -> Self {
+        Self {
+            lambda: defaults::Diversity::lambda(),
+            top_n: defaults::Diversity::top_n(),
+            max_displacement: defaults::Diversity::max_displacement(),
+            similarity_threshold: defaults::Diversity::similarity_threshold(),
+        }
+    }
+}
+
+impl DiversityConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.lambda < 1.0
+    }
+}
+
+/// Latency budget of the ranking stages after recall. Every stage gets a slice of what
+/// remains of the budget, and stages whose slice is too small score fewer candidates or,
+/// if they are optional, are skipped.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct LatencyBudgetConfig {
+    /// Budget of a search in milliseconds. No budget if not set.
+    #[serde(default)]
+    pub budget_ms: Option<u64>,
+
+    /// Time a stage is expected to need for scoring all its candidates.
+    #[serde(default = "defaults::LatencyBudget::stage_ms")]
+    pub stage_ms: u64,
+
+    /// Stages that are not skipped always score at least this many candidates.
+    #[serde(default = "defaults::LatencyBudget::min_top_k")]
+    pub min_top_k: usize,
+}
+
+impl Default for LatencyBudgetConfig {
+    fn default() -> Self {
+        Self {
+            budget_ms: None,
+            stage_ms: defaults::LatencyBudget::stage_ms(),
+            min_top_k: defaults::LatencyBudget::min_top_k(),
         }
     }
 }
@@ -251,8 +539,205 @@ pub struct ApiConfig {
     #[serde(default)]
     pub collector: CollectorConfig,
 
+    #[serde(default)]
+    pub diversity: DiversityConfig,
+
+    #[serde(default)]
+    pub latency_budget: LatencyBudgetConfig,
+
     #[serde(default = "defaults::Api::max_concurrent_searches")]
     pub max_concurrent_searches: Option<usize>,
+
+    /// Fail the search if a shard cannot be reached instead of
+    /// returning the partial results from the remaining shards.
+    #[serde(default)]
+    pub fail_on_missing_shards: bool,
+
+    #[serde(default)]
+    pub site_info: SiteInfoConfig,
+
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+
+    #[serde(default)]
+    pub http_cache: HttpCacheConfig,
+
+    #[serde(default)]
+    pub rel_flag_weights: RelFlagWeights,
+
+    #[serde(default)]
+    pub host_ranking_weights: HostRankingWeights,
+
+    /// The store of host centralities from the centrality job. If set, the linking hosts
+    /// of the inbound similarity are weighted by their centrality, so sharing a link from a
+    /// central hub counts more than sharing one from an obscure host. Linking hosts that are
+    /// not in the store have no weight.
+    #[serde(default)]
+    pub host_centrality_store_path: Option<String>,
+
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+}
+
+/// The circuit breaker of each search and entity search server the api sends requests to.
+/// See [`crate::distributed::sonic::circuit_breaker`].
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    /// Number of requests in a row that must fail for the breaker to open.
+    #[serde(default = "defaults::CircuitBreaker::failure_threshold")]
+    pub failure_threshold: usize,
+
+    /// Time the breaker stays open before a request is let through to probe the server.
+    #[serde(default = "defaults::CircuitBreaker::cool_down_ms")]
+    pub cool_down_ms: u64,
+
+    /// Timeout of a single request to a server. Requests that time out count as failed.
+    #[serde(default = "defaults::CircuitBreaker::request_timeout_ms")]
+    pub request_timeout_ms: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: defaults::CircuitBreaker::failure_threshold(),
+            cool_down_ms: defaults::CircuitBreaker::cool_down_ms(),
+            request_timeout_ms: defaults::CircuitBreaker::request_timeout_ms(),
+        }
+    }
+}
+
+/// Token bucket rate limits of the api, per api key if the request has one of the known
+/// keys and per client ip otherwise. Routes without a limit are not rate limited.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub search: Option<RateLimit>,
+
+    #[serde(default)]
+    pub autosuggest: Option<RateLimit>,
+
+    /// The rest of the json api, e.g. the webgraph and explore routes.
+    #[serde(default)]
+    pub api: Option<RateLimit>,
+
+    /// The number of clients that have a bucket per route. The least recently seen clients
+    /// are forgotten first, which gives them a full bucket when they come back.
+    #[serde(default = "defaults::RateLimit::max_clients")]
+    pub max_clients: usize,
+
+    /// Number of reverse proxies in front of the api. The client ip is taken from the
+    /// `x-forwarded-for` entry added by the outermost of them, as the entries before it
+    /// can be set by the client.
+    #[serde(default)]
+    pub trusted_proxies: usize,
+
+    /// Networks like `10.0.0.0/8` that are never rate limited.
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            search: None,
+            autosuggest: None,
+            api: None,
+            max_clients: defaults::RateLimit::max_clients(),
+            trusted_proxies: 0,
+            allowlist: Vec::new(),
+            api_keys: Vec::new(),
+        }
+    }
+}
+
+/// How long clients may cache the responses of the api, see [`crate::api::http_cache`].
+/// Responses of routes without a max age are revalidated on every request.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq)]
+pub struct HttpCacheConfig {
+    /// Static files like the favicon.
+    #[serde(default = "defaults::HttpCache::static_max_age_secs")]
+    pub static_max_age_secs: Option<u64>,
+
+    #[serde(default = "defaults::HttpCache::autosuggest_max_age_secs")]
+    pub autosuggest_max_age_secs: Option<u64>,
+
+    #[serde(default = "defaults::HttpCache::site_info_max_age_secs")]
+    pub site_info_max_age_secs: Option<u64>,
+
+    /// Other responses that depend on the index, like its freshness and entity images.
+    #[serde(default = "defaults::HttpCache::index_max_age_secs")]
+    pub index_max_age_secs: Option<u64>,
+
+    /// Search results are never stored by clients unless this is set, and then only
+    /// the results of queries without an optic or host rankings.
+    #[serde(default)]
+    pub search_max_age_secs: Option<u64>,
+
+    /// How often the generation of the index is fetched from the search servers.
+    #[serde(default = "defaults::HttpCache::generation_refresh_secs")]
+    pub generation_refresh_secs: u64,
+}
+
+impl Default for HttpCacheConfig {
+    fn default() -> Self {
+        Self {
+            static_max_age_secs: defaults::HttpCache::static_max_age_secs(),
+            autosuggest_max_age_secs: defaults::HttpCache::autosuggest_max_age_secs(),
+            site_info_max_age_secs: defaults::HttpCache::site_info_max_age_secs(),
+            index_max_age_secs: defaults::HttpCache::index_max_age_secs(),
+            search_max_age_secs: None,
+            generation_refresh_secs: defaults::HttpCache::generation_refresh_secs(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy)]
+pub struct RateLimit {
+    pub requests_per_sec: f64,
+    /// The number of requests a client can make at once after being idle.
+    pub burst: u32,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct SiteInfoConfig {
+    /// Time budget for gathering the information about a site.
+    /// Sources that have not answered within the budget are left out.
+    #[serde(default = "defaults::SiteInfo::budget_ms")]
+    pub budget_ms: u64,
+
+    #[serde(default = "defaults::SiteInfo::cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+
+    #[serde(default = "defaults::SiteInfo::cache_size")]
+    pub cache_size: usize,
+
+    /// Number of the most central linking hosts whose other links are counted
+    /// for the co-linked hosts of the site.
+    #[serde(default = "defaults::SiteInfo::co_links_max_linkers")]
+    pub co_links_max_linkers: usize,
+
+    /// Number of the links of each linking host that are counted for the co-linked hosts.
+    #[serde(default = "defaults::SiteInfo::co_links_max_destinations")]
+    pub co_links_max_destinations: usize,
+
+    #[serde(default = "defaults::SiteInfo::num_co_linked_hosts")]
+    pub num_co_linked_hosts: usize,
+}
+
+impl Default for SiteInfoConfig {
+    fn default() -> Self {
+        Self {
+            budget_ms: defaults::SiteInfo::budget_ms(),
+            cache_ttl_secs: defaults::SiteInfo::cache_ttl_secs(),
+            cache_size: defaults::SiteInfo::cache_size(),
+            co_links_max_linkers: defaults::SiteInfo::co_links_max_linkers(),
+            co_links_max_destinations: defaults::SiteInfo::co_links_max_destinations(),
+            num_co_linked_hosts: defaults::SiteInfo::num_co_linked_hosts(),
+        }
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
@@ -295,6 +780,35 @@ impl Default for SnippetConfig {
     }
 }
 
+/// How the documents of the results are read from the doc store.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq)]
+pub struct RetrievalConfig {
+    /// Number of threads that read the documents of a page of results in parallel.
+    /// With 0 threads the documents are read one after the other without a timeout.
+    #[serde(default = "defaults::Retrieval::threads")]
+    pub threads: usize,
+
+    /// Time a document has to be read before it is returned with only its url and title.
+    #[serde(default = "defaults::Retrieval::timeout_ms")]
+    pub timeout_ms: u64,
+
+    /// Maximum number of reads that are queued or running in the pool, including the slow
+    /// reads that are left to finish after their timeout. The documents of a page that would
+    /// exceed it are returned with only their url and title right away.
+    #[serde(default = "defaults::Retrieval::max_queued_reads")]
+    pub max_queued_reads: usize,
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> Self {
+        Self {
+            threads: defaults::Retrieval::threads(),
+            timeout_ms: defaults::Retrieval::timeout_ms(),
+            max_queued_reads: defaults::Retrieval::max_queued_reads(),
+        }
+    }
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
 pub struct SearchServerConfig {
     pub cluster_id: String,
@@ -312,6 +826,152 @@ pub struct SearchServerConfig {
 
     #[serde(default)]
     pub snippet: SnippetConfig,
+
+    /// Number of retrieved webpages to keep in memory so popular results
+    /// are not read from the doc store on every request. 0 disables the cache.
+    #[serde(default)]
+    pub webpage_cache_capacity: usize,
+
+    /// Number of decompressed doc store blocks to keep in a cache shared by all segments
+    /// and requests. Without it, every segment caches its own most recent blocks.
+    #[serde(default)]
+    pub store_block_cache_capacity: Option<usize>,
+
+    /// The reads of the documents go through the block cache, so fewer of them time out
+    /// the larger the cache is.
+    #[serde(default)]
+    pub retrieval: RetrievalConfig,
+
+    /// Floors and ceilings for the ranking signals by signal name (e.g. `host_centrality`).
+    /// The signals are clamped before they are weighted by their coefficients.
+    #[serde(default)]
+    pub signal_bounds: std::collections::HashMap<String, SignalBound>,
+
+    /// Warm only the priority segments before the server joins the cluster and the rest in
+    /// the background. Without it, all segments are warmed before the server joins.
+    #[serde(default)]
+    pub warmup: Option<WarmupConfig>,
+
+    /// Expand the terms of the queries with their synonyms to find more results.
+    #[serde(default)]
+    pub synonyms: Option<SynonymsConfig>,
+
+    /// Split the terms of the queries that are written without spaces, and match the
+    /// terms that look like a domain in the pages of the host.
+    #[serde(default)]
+    pub segmentation: Option<SegmentationConfig>,
+
+    /// Serve the index as a warm standby of another search server. The generations
+    /// replicated from the primary are stored in `index_path`.
+    #[serde(default)]
+    pub replica_of: Option<ReplicaConfig>,
+
+    /// Serve the rest of the index if some of its segments are corrupt, instead of
+    /// failing to start. Not used by replicas, as they verify the files they download.
+    #[serde(default)]
+    pub skip_corrupt_segments: bool,
+
+    /// Log an event for each query the server answers, for analytics.
+    /// See [`crate::query_log`] for the redaction of the queries.
+    #[serde(default)]
+    pub query_log: Option<QueryLogConfig>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct QueryLogConfig {
+    #[serde(default)]
+    pub redaction: QueryRedaction,
+
+    /// Key of the HMAC of the queries in the hashed mode, so the hashes of common queries
+    /// cannot be looked up in a table of precomputed hashes. Required in the hashed mode.
+    #[serde(default, alias = "salt")]
+    pub secret: String,
+
+    pub sink: QueryLogSink,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryRedaction {
+    /// Log the query as it was searched for.
+    Raw,
+    /// Log a keyed hash of the query, so the same queries can be counted
+    /// without storing what was searched for.
+    #[default]
+    Hashed,
+    /// Only log the number of terms of the query.
+    TokenCount,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueryLogSink {
+    Stdout,
+    /// Append the events to the file as json lines.
+    File {
+        path: String,
+    },
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct ReplicaConfig {
+    /// Address of the search server to replicate the index from.
+    pub primary: SocketAddr,
+
+    #[serde(default = "defaults::Replica::poll_interval_sec")]
+    pub poll_interval_sec: u64,
+
+    /// Size of the ranges the segment files are downloaded in.
+    /// The primary reads at most 16MiB for a range.
+    #[serde(default = "defaults::Replica::chunk_size_bytes")]
+    pub chunk_size_bytes: u64,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct SynonymsConfig {
+    /// File with a group of synonyms on each line, separated by commas.
+    pub path: String,
+
+    /// Weight of a synonym in the ranking relative to the term it expands.
+    #[serde(default = "defaults::Synonyms::boost")]
+    pub boost: f64,
+}
+
+/// Splitting of query terms written without spaces. The dictionary of words is built from the
+/// index when the search server starts, and is not rebuilt when a replica swaps in a new
+/// generation of the index. The words of new pages are only in the dictionary once the
+/// server is restarted.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct SegmentationConfig {
+    /// Words of the body that are in fewer documents are left out of the dictionary
+    /// the terms are split with.
+    #[serde(default = "defaults::Segmentation::min_doc_freq")]
+    pub min_doc_freq: u64,
+
+    /// Weight of the words of a split term in the ranking relative to the term itself.
+    #[serde(default = "defaults::Segmentation::boost")]
+    pub boost: f64,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct WarmupConfig {
+    /// Number of segments, largest first, to warm before the index is ready.
+    #[serde(default = "defaults::Warmup::priority_segments")]
+    pub priority_segments: usize,
+
+    /// Pause between the segments warmed in the background, so the warmup
+    /// does not compete with the queries for disk and cpu.
+    #[serde(default = "defaults::Warmup::background_delay_ms")]
+    pub background_delay_ms: u64,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self {
+            priority_segments: defaults::Warmup::priority_segments(),
+            background_delay_ms: defaults::Warmup::background_delay_ms(),
+        }
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
@@ -327,12 +987,193 @@ pub struct EntitySearchServerConfig {
 pub struct CrawlCoordinatorConfig {
     pub job_queue: String,
     pub host: SocketAddr,
+
+    /// Domains that should never be crawled. See [`crate::crawler::domain_filter::DomainPattern`]
+    /// for the supported patterns. Updates made while the coordinator is running
+    /// are persisted next to the job queue and take precedence over this list and the allowlist.
+    #[serde(default)]
+    pub blocklist: Vec<String>,
+
+    /// If set, only the domains matching the allowlist are crawled.
+    #[serde(default)]
+    pub allowlist: Option<Vec<String>>,
+
+    /// Store created by `stract indexer host-languages` used to look up the
+    /// dominant language of the hosts.
+    #[serde(default)]
+    pub host_languages: Option<String>,
+
+    /// Multiplier for the wander budget of hosts with the given dominant language,
+    /// keyed by ISO 639-3 code (e.g. `dan`). Hosts with an unknown language are not affected.
+    #[serde(default)]
+    pub language_budget_multipliers: std::collections::HashMap<String, f64>,
+
+    /// Rules that exclude urls as they are discovered. Updates made while the coordinator
+    /// is running are persisted next to the job queue and take precedence over these rules.
+    #[serde(default)]
+    pub intake_rules: CrawlIntakeRulesConfig,
+
+    #[serde(default)]
+    pub retry: CrawlRetryConfig,
+
+    #[serde(default)]
+    pub crawl_space: CrawlSpaceConfig,
+
+    /// Maximum number of links followed from the seed urls. Discovered urls that are
+    /// deeper are not enqueued, and the workers don't wander deeper.
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+
+    /// Maximum number of urls of a host that are enqueued while the coordinator runs.
+    #[serde(default)]
+    pub max_pages_per_host: Option<u64>,
+
+    /// Crawl rates negotiated with the operators of hosts, keyed by host (e.g. `docs.example.com`).
+    /// The rates are sent to the workers with the jobs of the hosts. Updates made while the
+    /// coordinator is running are persisted next to the job queue and take precedence over these.
+    #[serde(default)]
+    pub rate_overrides: std::collections::HashMap<String, CrawlRateOverride>,
+}
+
+/// A crawl rate that has been negotiated with the operator of a host. The workers use the
+/// delay of the override instead of their default politeness for the host, but never wait
+/// less than the crawl-delay of the robots.txt of the host.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct CrawlRateOverride {
+    pub crawl_delay_ms: u64,
+}
+
+/// Throttling of url signatures that generate many urls without new content,
+/// like calendars and faceted navigation. See [`crate::crawler::crawl_space`].
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+pub struct CrawlSpaceConfig {
+    /// Number of urls a signature can have on a host before it can be throttled.
+    #[serde(default = "defaults::CrawlSpace::url_threshold")]
+    pub url_threshold: u64,
+
+    /// Number of fetched pages of a signature the workers must have reported
+    /// before the diversity of its content is known.
+    #[serde(default = "defaults::CrawlSpace::min_reported_pages")]
+    pub min_reported_pages: u64,
+
+    /// Signatures where at most this fraction of the reported pages are
+    /// distinct from each other are throttled.
+    #[serde(default = "defaults::CrawlSpace::max_distinct_fraction")]
+    pub max_distinct_fraction: f64,
+
+    /// A throttled signature only adds one of every `trickle_every` urls to the frontier.
+    #[serde(default = "defaults::CrawlSpace::trickle_every")]
+    pub trickle_every: u64,
+
+    /// Signatures beyond this number on a host are not tracked.
+    #[serde(default = "defaults::CrawlSpace::max_signatures_per_host")]
+    pub max_signatures_per_host: usize,
+}
+
+impl Default for CrawlSpaceConfig {
+    fn default() -> Self {
+        Self {
+            url_threshold: defaults::CrawlSpace::url_threshold(),
+            min_reported_pages: defaults::CrawlSpace::min_reported_pages(),
+            max_distinct_fraction: defaults::CrawlSpace::max_distinct_fraction(),
+            trickle_every: defaults::CrawlSpace::trickle_every(),
+            max_signatures_per_host: defaults::CrawlSpace::max_signatures_per_host(),
+        }
+    }
+}
+
+/// Retries of urls that failed with a transient error like a timeout or a DNS failure.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct CrawlRetryConfig {
+    /// Urls that have failed more often than this are written to the dead-letter log
+    /// next to the job queue instead of being retried.
+    #[serde(default = "defaults::CrawlRetry::max_retries")]
+    pub max_retries: u32,
+
+    /// Delay before the first retry. The delay doubles with every failed attempt.
+    #[serde(default = "defaults::CrawlRetry::backoff_ms")]
+    pub backoff_ms: u64,
+
+    #[serde(default = "defaults::CrawlRetry::max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+impl Default for CrawlRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: defaults::CrawlRetry::max_retries(),
+            backoff_ms: defaults::CrawlRetry::backoff_ms(),
+            max_backoff_ms: defaults::CrawlRetry::max_backoff_ms(),
+        }
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct CrawlIntakeRulesConfig {
+    /// Top level domains to exclude, e.g. `ru` or `co.uk`.
+    #[serde(default)]
+    pub excluded_tlds: Vec<String>,
+
+    /// `example.com` excludes the domain and all its subdomains
+    /// while `*.example.com` only excludes the subdomains.
+    #[serde(default)]
+    pub excluded_domains: Vec<String>,
+
+    /// Substrings of the urls to exclude, where `*` matches any sequence of characters.
+    #[serde(default)]
+    pub excluded_url_patterns: Vec<String>,
+
+    /// Fraction of the excluded urls to log.
+    #[serde(default)]
+    pub log_sample_rate: f64,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
 pub struct UserAgent {
+    /// The user agent of every request. `{version}` is replaced by the version of stract
+    /// and `{contact_url}` by the contact url, e.g. `StractBot/{version} (+{contact_url})`.
     pub full: String,
+
+    /// The token matched against the user-agent lines of robots.txt, e.g. `StractBot`.
     pub token: String,
+
+    /// Where the operators of the crawled sites can find out about the crawler.
+    #[serde(default)]
+    pub contact_url: Option<String>,
+
+    /// Sent in the `From` header of every request.
+    #[serde(default)]
+    pub from_email: Option<String>,
+}
+
+impl UserAgent {
+    pub fn header(&self) -> String {
+        self.full
+            .replace("{version}", env!("CARGO_PKG_VERSION"))
+            .replace(
+                "{contact_url}",
+                self.contact_url.as_deref().unwrap_or_default(),
+            )
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
@@ -364,6 +1205,26 @@ pub struct CrawlerConfig {
     pub timeout_seconds: u64,
     pub s3: S3Config,
     pub router_hosts: Vec<String>,
+
+    /// Record a presigned url for each uploaded WARC file in a manifest next to the files,
+    /// so downstream consumers can fetch them without credentials for the bucket.
+    #[serde(default)]
+    pub artifact_manifest: Option<ArtifactManifestConfig>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct ArtifactManifestConfig {
+    /// How long the presigned urls are valid. S3 allows at most 7 days.
+    #[serde(default = "defaults::ArtifactManifest::expiry_secs")]
+    pub expiry_secs: u32,
+}
+
+impl Default for ArtifactManifestConfig {
+    fn default() -> Self {
+        Self {
+            expiry_secs: defaults::ArtifactManifest::expiry_secs(),
+        }
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
@@ -408,6 +1269,76 @@ pub struct WebgraphServerConfig {
     pub cluster_id: String,
     pub gossip_seed_nodes: Option<Vec<SocketAddr>>,
     pub gossip_addr: SocketAddr,
+
+    /// Store created by `stract indexer host-languages`.
+    #[serde(default)]
+    pub host_languages: Option<String>,
+
+    /// The `harmonic` store in the output of `stract centrality host`. The hosts of the
+    /// link neighborhoods are ranked and sized by their centrality when it is set.
+    #[serde(default)]
+    pub host_centrality: Option<String>,
+
+    #[serde(default)]
+    pub edge_buffer_pool: EdgeBufferPoolConfig,
+
+    #[serde(default)]
+    pub edge_query_caps: EdgeQueryCapsConfig,
+
+    /// Fail the lookups of nodes whose id has several names stored in the graph,
+    /// instead of returning the newest name. Each lookup searches every segment.
+    #[serde(default)]
+    pub strict_id2node: bool,
+}
+
+/// Bounds on the work the webgraph server does for a single edge query.
+/// Queries that hit a bound return the edges collected so far and are marked as truncated.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeQueryCapsConfig {
+    /// Maximum number of edges returned for a query from the frontend.
+    #[serde(default = "defaults::EdgeQueryCaps::max_edges")]
+    pub max_edges: usize,
+
+    /// Maximum number of edges returned for an internal query, e.g. from the ranking pipeline.
+    #[serde(default = "defaults::EdgeQueryCaps::max_edges_internal")]
+    pub max_edges_internal: usize,
+
+    /// Segments that have not been read when the timeout is reached are skipped.
+    #[serde(default = "defaults::EdgeQueryCaps::timeout_ms")]
+    pub timeout_ms: Option<u64>,
+}
+
+impl Default for EdgeQueryCapsConfig {
+    fn default() -> Self {
+        Self {
+            max_edges: defaults::EdgeQueryCaps::max_edges(),
+            max_edges_internal: defaults::EdgeQueryCaps::max_edges_internal(),
+            timeout_ms: defaults::EdgeQueryCaps::timeout_ms(),
+        }
+    }
+}
+
+/// Per thread pool of the buffers used to collect the edges of a webgraph query.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeBufferPoolConfig {
+    /// Maximum number of buffers kept by each thread. `0` disables the pool.
+    /// A query uses a buffer for each segment of the graph and one for the result,
+    /// so with fewer buffers than that some of them are allocated for every query.
+    #[serde(default = "defaults::EdgeBufferPool::max_buffers")]
+    pub max_buffers: usize,
+
+    /// Buffers that grew beyond this many edges are freed instead of being returned to the pool.
+    #[serde(default = "defaults::EdgeBufferPool::max_buffer_len")]
+    pub max_buffer_len: usize,
+}
+
+impl Default for EdgeBufferPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_buffers: defaults::EdgeBufferPool::max_buffers(),
+            max_buffer_len: defaults::EdgeBufferPool::max_buffer_len(),
+        }
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
@@ -571,6 +1502,10 @@ pub struct HarmonicCoordinatorConfig {
     pub gossip: GossipConfig,
     pub host: SocketAddr,
     pub output_path: String,
+
+    /// Write the progress of the computation to this file. See `stract ampc status`.
+    #[serde(default)]
+    pub status_path: Option<String>,
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -587,6 +1522,10 @@ pub struct ApproxHarmonicCoordinatorConfig {
     pub host: SocketAddr,
     pub output_path: String,
 
+    /// Write the progress of the computation to this file. See `stract ampc status`.
+    #[serde(default)]
+    pub status_path: Option<String>,
+
     #[serde(default = "defaults::ApproxHarmonic::sample_rate")]
     pub sample_rate: f64,
 
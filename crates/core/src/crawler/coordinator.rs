@@ -1,5 +1,5 @@
 // Stract is an open source web search engine.
-// Copyright (C) 2023 Stract ApS
+// Copyright (C) 2024 Stract ApS
 //
 // This program is free software: you can redistribute it and/or modify
 // it under the terms of the GNU Affero General Public License as
@@ -14,25 +14,1222 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use super::{file_queue::FileQueue, Job, Result};
-use std::{path::Path, sync::Mutex};
+use super::{
+    crawl_space::{CrawlSpace, CrawlSpaceDetector, CrawlSpaceOverride},
+    domain_filter::DomainFilter,
+    file_queue::FileQueue,
+    intake_rules::IntakeFilter,
+    retry::{self, DeadLetter, RetryQueue},
+    ContentFingerprint, Domain, FailedUrl, Job, Result,
+};
+use crate::{
+    config::{CrawlIntakeRulesConfig, CrawlRateOverride, CrawlRetryConfig, CrawlSpaceConfig},
+    host_languages::HostLanguageStore,
+    webgraph::Node,
+};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+    time::Instant,
+};
+use url::Url;
+use whatlang::Lang;
+
+const DOMAIN_FILTER_KEY: &str = "domain_filter.json";
+const INTAKE_RULES_KEY: &str = "intake_rules.json";
+const DEAD_LETTER_KEY: &str = "dead_letter.jsonl";
+const CRAWL_SPACE_OVERRIDES_KEY: &str = "crawl_space_overrides.json";
+const RATE_OVERRIDES_KEY: &str = "rate_overrides.json";
+
+/// Scales the wander budget of the jobs by the dominant language of their host.
+pub struct LanguageBudget {
+    store: HostLanguageStore,
+    multipliers: HashMap<Lang, f64>,
+}
+
+impl LanguageBudget {
+    /// `multipliers` are keyed by ISO 639-3 language codes.
+    pub fn new(store: HostLanguageStore, multipliers: HashMap<String, f64>) -> Result<Self> {
+        let multipliers = multipliers
+            .into_iter()
+            .map(|(code, multiplier)| {
+                Lang::from_code(&code)
+                    .map(|lang| (lang, multiplier))
+                    .ok_or_else(|| anyhow::anyhow!("unknown language code: {code}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { store, multipliers })
+    }
+
+    /// The host of the first url in the job decides the language of the job.
+    fn apply(&self, job: &mut Job) {
+        let host = match job.urls.front() {
+            Some(url) => Node::from(&url.url).into_host().id(),
+            None => return,
+        };
+
+        let multiplier = self
+            .store
+            .dominant_language(&host)
+            .and_then(|lang| self.multipliers.get(&lang));
+
+        if let Some(multiplier) = multiplier {
+            job.wandering_urls = (job.wandering_urls as f64 * multiplier).round() as u64;
+        }
+    }
+}
 
 pub struct CrawlCoordinator {
     jobs: Mutex<FileQueue<Job>>,
+    jobs_path: PathBuf,
+    filter: RwLock<DomainFilter>,
+    /// Serializes the updates of the filter, so the persisted filter is the latest one.
+    filter_update: Mutex<()>,
+    filter_path: PathBuf,
+    intake: RwLock<IntakeFilter>,
+    intake_path: PathBuf,
+    intake_exclusions: Mutex<BTreeMap<String, u64>>,
+    retries: Mutex<RetryQueue>,
+    dead_letter_path: PathBuf,
+    language_budget: Option<LanguageBudget>,
+    crawl_spaces: Mutex<CrawlSpaceDetector>,
+    crawl_space_overrides_path: PathBuf,
+    rate_overrides: RwLock<HashMap<String, CrawlRateOverride>>,
+    rate_overrides_path: PathBuf,
+    max_depth: Option<u32>,
+    max_pages_per_host: Option<u64>,
+    /// Number of urls of each host that have been enqueued since the coordinator started.
+    host_pages: Mutex<HashMap<String, u64>>,
 }
 
 impl CrawlCoordinator {
-    pub fn new<P: AsRef<Path>>(jobs_queue: P) -> Result<Self> {
+    /// Open the job queue. If the domain filter has been updated while the coordinator was running,
+    /// the persisted filter is used instead of `filter`.
+    pub fn new<P: AsRef<Path>>(jobs_queue: P, filter: DomainFilter) -> Result<Self> {
+        let filter_path = jobs_queue.as_ref().join(DOMAIN_FILTER_KEY);
+        let intake_path = jobs_queue.as_ref().join(INTAKE_RULES_KEY);
+        let dead_letter_path = jobs_queue.as_ref().join(DEAD_LETTER_KEY);
+        let crawl_space_overrides_path = jobs_queue.as_ref().join(CRAWL_SPACE_OVERRIDES_KEY);
+        let rate_overrides_path = jobs_queue.as_ref().join(RATE_OVERRIDES_KEY);
+
+        let filter = if filter_path.exists() {
+            let file = std::fs::File::open(&filter_path).map_err(anyhow::Error::from)?;
+            serde_json::from_reader(file).map_err(anyhow::Error::from)?
+        } else {
+            filter
+        };
+
+        let mut crawl_spaces = CrawlSpaceDetector::new(CrawlSpaceConfig::default());
+        if crawl_space_overrides_path.exists() {
+            let file =
+                std::fs::File::open(&crawl_space_overrides_path).map_err(anyhow::Error::from)?;
+            crawl_spaces.set_overrides(serde_json::from_reader(file).map_err(anyhow::Error::from)?);
+        }
+
         Ok(Self {
-            jobs: Mutex::new(FileQueue::open(jobs_queue)?),
+            jobs: Mutex::new(FileQueue::open(&jobs_queue)?),
+            jobs_path: jobs_queue.as_ref().to_path_buf(),
+            filter: RwLock::new(filter),
+            filter_update: Mutex::new(()),
+            filter_path,
+            intake: RwLock::new(IntakeFilter::default()),
+            intake_path,
+            intake_exclusions: Mutex::new(BTreeMap::new()),
+            retries: Mutex::new(RetryQueue::new(
+                CrawlRetryConfig::default(),
+                dead_letter_path.clone(),
+            )),
+            dead_letter_path,
+            language_budget: None,
+            crawl_spaces: Mutex::new(crawl_spaces),
+            crawl_space_overrides_path,
+            rate_overrides: RwLock::new(HashMap::new()),
+            rate_overrides_path,
+            max_depth: None,
+            max_pages_per_host: None,
+            host_pages: Mutex::new(HashMap::new()),
         })
     }
 
+    pub fn with_retry(self, config: CrawlRetryConfig) -> Self {
+        self.retries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .set_config(config);
+        self
+    }
+
+    /// Exclude urls matching the rules when they are discovered by the workers or handed
+    /// out from the queue. If the rules have been replaced
+    /// while the coordinator was running, the persisted rules are used instead of `rules`.
+    pub fn with_intake_rules(self, rules: CrawlIntakeRulesConfig) -> Result<Self> {
+        let rules: CrawlIntakeRulesConfig = if self.intake_path.exists() {
+            let file = std::fs::File::open(&self.intake_path).map_err(anyhow::Error::from)?;
+            serde_json::from_reader(file).map_err(anyhow::Error::from)?
+        } else {
+            rules
+        };
+
+        *self.intake.write().unwrap_or_else(|e| e.into_inner()) = IntakeFilter::new(&rules);
+
+        Ok(self)
+    }
+
+    pub fn with_language_budget(mut self, budget: LanguageBudget) -> Self {
+        self.language_budget = Some(budget);
+        self
+    }
+
+    pub fn with_crawl_space(self, config: CrawlSpaceConfig) -> Self {
+        self.crawl_spaces
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .set_config(config);
+        self
+    }
+
+    /// Attach the negotiated crawl rates to the jobs of the hosts. If the overrides have been
+    /// updated while the coordinator was running, the persisted overrides are used instead.
+    pub fn with_rate_overrides(
+        self,
+        overrides: HashMap<String, CrawlRateOverride>,
+    ) -> Result<Self> {
+        let overrides = if self.rate_overrides_path.exists() {
+            let file =
+                std::fs::File::open(&self.rate_overrides_path).map_err(anyhow::Error::from)?;
+            serde_json::from_reader(file).map_err(anyhow::Error::from)?
+        } else {
+            overrides
+        };
+
+        *self
+            .rate_overrides
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = overrides;
+
+        Ok(self)
+    }
+
+    /// Don't enqueue urls that are more than `max_depth` links away from the seeds.
+    /// The depth is also sent to the workers with the jobs, so they don't wander deeper.
+    pub fn with_max_depth(mut self, max_depth: Option<u32>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// The depth limit that is handed out with the jobs, so the workers don't follow
+    /// links beyond it.
+    pub fn max_depth(&self) -> Option<u32> {
+        self.max_depth
+    }
+
+    /// Enqueue at most `max_pages_per_host` urls of each host. The urls of the jobs in the
+    /// queue count when the jobs are handed out, and the urls the workers wander to when
+    /// they are admitted.
+    pub fn with_max_pages_per_host(mut self, max_pages_per_host: Option<u64>) -> Self {
+        self.max_pages_per_host = max_pages_per_host;
+        self
+    }
+
     pub fn sample_job(&self) -> Result<Option<Job>> {
-        self.jobs
+        loop {
+            // the retried urls were counted towards the page caps and crawl spaces when they
+            // were first handed out, so only the jobs of the queue are counted here.
+            let mut queued = false;
+
+            let job = match self.due_retry() {
+                Some(job) => Some(job),
+                None => {
+                    let job = self.jobs.lock().unwrap_or_else(|e| e.into_inner()).pop()?;
+                    queued = job.is_some();
+                    job
+                }
+            };
+
+            match job {
+                Some(mut job) => {
+                    self.exclude_by_intake(&mut job);
+                    self.filter
+                        .read()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .retain(&mut job);
+
+                    if queued {
+                        self.throttle_crawl_spaces(&mut job);
+                        self.cap_pages(
+                            &mut self.host_pages.lock().unwrap_or_else(|e| e.into_inner()),
+                            &mut job,
+                        );
+                    }
+
+                    if !job.urls.is_empty() {
+                        if let Some(budget) = &self.language_budget {
+                            budget.apply(&mut job);
+                        }
+
+                        return Ok(Some(job));
+                    }
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// The rate overrides of the hosts of the domain, which are handed out with its jobs.
+    pub fn rate_overrides_for(&self, domain: &Domain) -> super::HashMap<String, CrawlRateOverride> {
+        self.rate_overrides
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|(host, _)| {
+                Url::parse(&format!("http://{host}"))
+                    .map(|url| Domain::from(&url) == *domain)
+                    .unwrap_or(false)
+            })
+            .map(|(host, rate_override)| (host.clone(), *rate_override))
+            .collect()
+    }
+
+    /// Drop the urls of hosts that have reached the page cap and count the rest.
+    /// Returns the number of dropped urls.
+    fn cap_pages(&self, host_pages: &mut HashMap<String, u64>, job: &mut Job) -> usize {
+        let Some(max_pages) = self.max_pages_per_host else {
+            return 0;
+        };
+
+        let before = job.urls.len();
+
+        job.urls.retain(|url| {
+            let pages = host_pages
+                .entry(url.url.host_str().unwrap_or_default().to_string())
+                .or_default();
+
+            if *pages < max_pages {
+                *pages += 1;
+                true
+            } else {
+                false
+            }
+        });
+
+        before - job.urls.len()
+    }
+
+    /// Failed urls are retried one at a time, before any other job.
+    fn due_retry(&self) -> Option<Job> {
+        let url = self
+            .retries
             .lock()
             .unwrap_or_else(|e| e.into_inner())
-            .pop()
-            .map_err(Into::into)
+            .pop_due(Instant::now())?;
+
+        Some(Job {
+            domain: Domain::from(&url.url),
+            urls: VecDeque::from([url]),
+            wandering_urls: 0,
+        })
+    }
+
+    /// Schedule retries of urls that failed with a transient error. Urls that have exhausted
+    /// their retries are written to the dead-letter log. Returns the number of such urls.
+    pub fn report_failed(&self, failed: Vec<FailedUrl>) -> Result<usize> {
+        let mut retries = self.retries.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let mut dead = 0;
+
+        for url in failed {
+            if retries.failed(url, now)? {
+                dead += 1;
+            }
+        }
+
+        Ok(dead)
+    }
+
+    pub fn dead_letters(&self) -> Result<Vec<DeadLetter>> {
+        retry::read_dead_letters(&self.dead_letter_path)
+    }
+
+    /// Drop the urls a worker has discovered while crawling the job and wants to wander to,
+    /// but which are rejected by the intake rules, the domain filter, the throttled crawl spaces,
+    /// the depth limit or the page cap of their hosts. Returns the number of dropped urls.
+    pub fn admit(&self, job: &mut Job) -> usize {
+        let mut rejected = 0;
+
+        if let Some(max_depth) = self.max_depth {
+            let before = job.urls.len();
+            job.urls.retain(|url| url.depth <= max_depth);
+            rejected += before - job.urls.len();
+        }
+
+        rejected += self.exclude_by_intake(job);
+
+        rejected += self
+            .filter
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(job);
+
+        rejected += self.throttle_crawl_spaces(job);
+
+        rejected += self.cap_pages(
+            &mut self.host_pages.lock().unwrap_or_else(|e| e.into_inner()),
+            job,
+        );
+
+        rejected
+    }
+
+    /// Drop the urls of the throttled crawl spaces, except for the trickle that is still let
+    /// through, and count the rest towards their crawl space. Returns the number of dropped urls.
+    fn throttle_crawl_spaces(&self, job: &mut Job) -> usize {
+        let before = job.urls.len();
+
+        let mut crawl_spaces = self.crawl_spaces.lock().unwrap_or_else(|e| e.into_inner());
+        job.urls.retain(|url| crawl_spaces.admit(&url.url));
+
+        before - job.urls.len()
+    }
+
+    /// Drop the urls excluded by the intake rules and count them per rule.
+    /// Returns the number of dropped urls.
+    fn exclude_by_intake(&self, job: &mut Job) -> usize {
+        let intake = self.intake.read().unwrap_or_else(|e| e.into_inner());
+
+        if intake.is_empty() {
+            return 0;
+        }
+
+        let before = job.urls.len();
+        let mut exclusions: HashMap<usize, u64> = HashMap::new();
+
+        job.urls.retain(|url| match intake.excluded_by(&url.url) {
+            Some(rule) => {
+                *exclusions.entry(rule).or_default() += 1;
+
+                if intake.should_log() {
+                    tracing::info!(
+                        "excluded {} by intake rule {}",
+                        url.url,
+                        intake.rule_name(rule)
+                    );
+                }
+
+                false
+            }
+            None => true,
+        });
+
+        if !exclusions.is_empty() {
+            let mut counts = self
+                .intake_exclusions
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+
+            for (rule, count) in exclusions {
+                *counts
+                    .entry(intake.rule_name(rule).to_string())
+                    .or_default() += count;
+            }
+        }
+
+        before - job.urls.len()
+    }
+
+    /// Replace the intake rules. The new rules apply to the urls admitted from now on
+    /// and to the queued urls when their job is handed out.
+    pub fn set_intake_rules(&self, rules: CrawlIntakeRulesConfig) -> Result<()> {
+        let file = std::fs::File::create(&self.intake_path).map_err(anyhow::Error::from)?;
+        serde_json::to_writer(file, &rules).map_err(anyhow::Error::from)?;
+
+        *self.intake.write().unwrap_or_else(|e| e.into_inner()) = IntakeFilter::new(&rules);
+
+        Ok(())
+    }
+
+    /// Number of urls excluded by each intake rule since the coordinator started,
+    /// keyed by rule name. Rules that have been removed keep their counts.
+    pub fn intake_exclusions(&self) -> BTreeMap<String, u64> {
+        self.intake_exclusions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Record the fingerprints of fetched pages, which tell how many distinct
+    /// pages the urls of a crawl space lead to.
+    pub fn report_content(&self, fingerprints: Vec<ContentFingerprint>) {
+        let mut crawl_spaces = self.crawl_spaces.lock().unwrap_or_else(|e| e.into_inner());
+
+        for fingerprint in fingerprints {
+            if let Ok(url) = Url::try_from(&fingerprint.url) {
+                crawl_spaces.report_content(&url, fingerprint.simhash);
+            }
+        }
+    }
+
+    /// The crawl spaces that are currently throttled.
+    pub fn crawl_spaces(&self) -> Vec<CrawlSpace> {
+        self.crawl_spaces
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .throttled()
+    }
+
+    /// Exempt the host from the throttling of crawl spaces or throttle all its large
+    /// signatures. `None` removes the override, so the host is detected as usual.
+    pub fn set_crawl_space_override(
+        &self,
+        host: String,
+        crawl_space_override: Option<CrawlSpaceOverride>,
+    ) -> Result<()> {
+        let mut crawl_spaces = self.crawl_spaces.lock().unwrap_or_else(|e| e.into_inner());
+        let mut overrides = crawl_spaces.overrides().clone();
+
+        match crawl_space_override {
+            Some(crawl_space_override) => {
+                overrides.insert(host, crawl_space_override);
+            }
+            None => {
+                overrides.remove(&host);
+            }
+        }
+
+        let file =
+            std::fs::File::create(&self.crawl_space_overrides_path).map_err(anyhow::Error::from)?;
+        serde_json::to_writer(file, &overrides).map_err(anyhow::Error::from)?;
+
+        crawl_spaces.set_overrides(overrides);
+
+        Ok(())
+    }
+
+    pub fn rate_overrides(&self) -> HashMap<String, CrawlRateOverride> {
+        self.rate_overrides
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Set the negotiated crawl rate of the host, or remove it with `None`. The rate
+    /// applies to the jobs handed out from now on.
+    pub fn set_rate_override(
+        &self,
+        host: String,
+        rate_override: Option<CrawlRateOverride>,
+    ) -> Result<()> {
+        let mut overrides = self
+            .rate_overrides
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+
+        match rate_override {
+            Some(rate_override) => {
+                overrides.insert(host, rate_override);
+            }
+            None => {
+                overrides.remove(&host);
+            }
+        }
+
+        let file = std::fs::File::create(&self.rate_overrides_path).map_err(anyhow::Error::from)?;
+        serde_json::to_writer(file, &*overrides).map_err(anyhow::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Block the domains matching the patterns. Returns the number of pending urls
+    /// that will be dropped from the queue.
+    pub fn add_blocked(&self, patterns: Vec<String>) -> Result<usize> {
+        self.update_filter(|filter| filter.block(patterns))
+    }
+
+    pub fn remove_blocked(&self, patterns: Vec<String>) -> Result<()> {
+        self.update_filter(|filter| filter.unblock(patterns))?;
+        Ok(())
+    }
+
+    /// Only schedule the domains matching the allowlist, or every domain if it is `None`.
+    /// Returns the number of pending urls that will be dropped from the queue.
+    pub fn set_allowlist(&self, patterns: Option<Vec<String>>) -> Result<usize> {
+        self.update_filter(|filter| filter.set_allowlist(patterns))
+    }
+
+    fn update_filter<F>(&self, update: F) -> Result<usize>
+    where
+        F: FnOnce(&mut DomainFilter),
+    {
+        let _update = self.filter_update.lock().unwrap_or_else(|e| e.into_inner());
+
+        // the filter is updated on a copy, so it is only locked while it is replaced
+        // and not while it is persisted or the queue is scanned.
+        let mut filter = self
+            .filter
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        update(&mut filter);
+
+        let file = std::fs::File::create(&self.filter_path).map_err(anyhow::Error::from)?;
+        serde_json::to_writer(file, &filter).map_err(anyhow::Error::from)?;
+
+        *self.filter.write().unwrap_or_else(|e| e.into_inner()) = filter.clone();
+
+        let mut dropped = 0;
+
+        // the jobs in the queue are immutable, so they are filtered when popped.
+        // the queue is scanned through its own handle to not hold up the sampling of jobs.
+        for job in FileQueue::<Job>::open(&self.jobs_path)?.peek_remaining() {
+            dropped += filter.num_rejected(&job?);
+        }
+
+        if dropped > 0 {
+            tracing::info!("{} pending urls will be dropped from the queue", dropped);
+        }
+
+        Ok(dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        crawler::{file_queue::FileQueueWriter, Domain, WeightedUrl},
+        host_languages,
+    };
+
+    use super::*;
+
+    fn job(domain: &str, urls: &[&str]) -> Job {
+        Job {
+            domain: Domain::from(domain.to_string()),
+            urls: urls
+                .iter()
+                .map(|url| WeightedUrl {
+                    url: Url::parse(url).unwrap(),
+                    weight: 1.0,
+                    depth: 0,
+                })
+                .collect(),
+            wandering_urls: 0,
+        }
+    }
+
+    /// The urls a worker found while crawling a job of the domain.
+    fn wandered(domain: &str, urls: &[&str]) -> Job {
+        wandered_at_depth(domain, urls, 1)
+    }
+
+    fn wandered_at_depth(domain: &str, urls: &[&str], depth: u32) -> Job {
+        let mut res = job(domain, urls);
+
+        for url in &mut res.urls {
+            url.depth = depth;
+        }
+
+        res
+    }
+
+    fn admitted(coordinator: &CrawlCoordinator, mut job: Job) -> Vec<String> {
+        coordinator.admit(&mut job);
+        job.urls
+            .into_iter()
+            .map(|url| url.url.to_string())
+            .collect()
+    }
+
+    fn wandering_job(domain: &str, url: &str, wandering_urls: u64) -> Job {
+        Job {
+            wandering_urls,
+            ..job(domain, &[url])
+        }
+    }
+
+    fn coordinator(jobs: Vec<Job>) -> (PathBuf, CrawlCoordinator) {
+        let path = crate::gen_temp_path();
+        let mut writer = FileQueueWriter::new(&path).unwrap();
+
+        for job in jobs {
+            writer.push(job).unwrap();
+        }
+
+        writer.finalize().unwrap();
+
+        let coordinator = CrawlCoordinator::new(&path, DomainFilter::default()).unwrap();
+
+        (path, coordinator)
+    }
+
+    fn domains(coordinator: &CrawlCoordinator) -> Vec<String> {
+        let mut res = Vec::new();
+
+        while let Some(job) = coordinator.sample_job().unwrap() {
+            res.push(job.domain.as_str().to_string());
+        }
+
+        res
+    }
+
+    #[test]
+    fn block_mid_run() {
+        let (_, coordinator) = coordinator(vec![
+            job("a.com", &["https://a.com/1", "https://a.com/2"]),
+            job("b.com", &["https://b.com/1", "https://www.b.com/2"]),
+            job("c.com", &["https://c.com/1"]),
+            job("b.com", &["https://b.com/3"]),
+        ]);
+
+        let first = coordinator.sample_job().unwrap().unwrap();
+        assert_eq!(first.domain.as_str(), "a.com");
+
+        assert_eq!(
+            coordinator.add_blocked(vec!["b.com".to_string()]).unwrap(),
+            3
+        );
+
+        assert_eq!(
+            coordinator.admit(&mut wandered("b.com", &["https://b.com/4"])),
+            1
+        );
+        assert_eq!(
+            coordinator.admit(&mut wandered("d.com", &["https://d.com/1"])),
+            0
+        );
+
+        assert_eq!(domains(&coordinator), vec!["c.com"]);
+    }
+
+    #[test]
+    fn allowlist() {
+        let (_, coordinator) = coordinator(vec![
+            job("a.com", &["https://a.com/1"]),
+            job("b.com", &["https://b.com/1", "https://blog.b.com/1"]),
+            job("c.com", &["https://c.com/1"]),
+        ]);
+
+        assert_eq!(
+            coordinator
+                .set_allowlist(Some(vec!["*.b.com".to_string()]))
+                .unwrap(),
+            3
+        );
+
+        let job = coordinator.sample_job().unwrap().unwrap();
+        assert_eq!(job.domain.as_str(), "b.com");
+        assert_eq!(job.urls.len(), 1);
+        assert_eq!(job.urls[0].url.as_str(), "https://blog.b.com/1");
+
+        assert!(coordinator.sample_job().unwrap().is_none());
+    }
+
+    #[test]
+    fn persisted_filter() {
+        let (path, coordinator) = coordinator(vec![
+            job("a.com", &["https://a.com/1"]),
+            job("b.com", &["https://b.com/1"]),
+        ]);
+
+        coordinator.add_blocked(vec!["a.com".to_string()]).unwrap();
+        drop(coordinator);
+
+        let coordinator = CrawlCoordinator::new(&path, DomainFilter::default()).unwrap();
+        assert_eq!(domains(&coordinator), vec!["b.com"]);
+
+        coordinator
+            .remove_blocked(vec!["a.com".to_string()])
+            .unwrap();
+        assert_eq!(
+            coordinator.admit(&mut wandered("a.com", &["https://a.com/2"])),
+            0
+        );
+    }
+
+    #[test]
+    fn intake_rules() {
+        let (path, coordinator) = coordinator(vec![]);
+        let coordinator = coordinator
+            .with_intake_rules(CrawlIntakeRulesConfig {
+                excluded_tlds: vec!["ru".to_string()],
+                excluded_domains: vec!["*.spam.com".to_string()],
+                excluded_url_patterns: vec!["/tag/*/feed".to_string()],
+                log_sample_rate: 0.0,
+            })
+            .unwrap();
+
+        let stream = [
+            ("a.ru", "https://a.ru/1"),
+            ("a.ru", "https://www.a.ru/2"),
+            ("spam.com", "https://spam.com/1"),
+            ("spam.com", "https://www.spam.com/1"),
+            ("b.com", "https://b.com/tag/rust/feed"),
+            ("b.com", "https://b.com/tag/rust"),
+            ("c.org", "https://c.org/1"),
+        ];
+
+        let mut rejected = 0;
+        for (domain, url) in stream {
+            rejected += coordinator.admit(&mut wandered(domain, &[url]));
+        }
+        assert_eq!(rejected, 4);
+
+        assert_eq!(
+            coordinator.intake_exclusions(),
+            [
+                ("domain:*.spam.com".to_string(), 1),
+                ("tld:ru".to_string(), 2),
+                ("url:/tag/*/feed".to_string(), 1),
+            ]
+            .into_iter()
+            .collect()
+        );
+
+        coordinator
+            .set_intake_rules(CrawlIntakeRulesConfig {
+                excluded_domains: vec!["c.org".to_string()],
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(
+            coordinator.admit(&mut wandered("a.ru", &["https://a.ru/3"])),
+            0
+        );
+        assert_eq!(
+            coordinator.admit(&mut wandered("c.org", &["https://c.org/2"])),
+            1
+        );
+        assert_eq!(coordinator.intake_exclusions()["tld:ru"], 2);
+        assert_eq!(coordinator.intake_exclusions()["domain:c.org"], 1);
+
+        assert_eq!(
+            admitted(
+                &coordinator,
+                wandered(
+                    "b.com",
+                    &["https://b.com/tag/rust/feed", "https://b.com/tag/go"]
+                )
+            ),
+            vec!["https://b.com/tag/rust/feed", "https://b.com/tag/go"]
+        );
+
+        // the replaced rules survive a restart
+        drop(coordinator);
+        let coordinator = CrawlCoordinator::new(&path, DomainFilter::default())
+            .unwrap()
+            .with_intake_rules(CrawlIntakeRulesConfig::default())
+            .unwrap();
+        assert_eq!(
+            coordinator.admit(&mut wandered("c.org", &["https://c.org/3"])),
+            1
+        );
+    }
+
+    #[test]
+    fn intake_rules_apply_to_queued_urls() {
+        let (_, coordinator) = coordinator(vec![
+            job("a.ru", &["https://a.ru/1"]),
+            job("b.com", &["https://b.com/1", "https://b.com/tag/rust/feed"]),
+        ]);
+
+        coordinator
+            .set_intake_rules(CrawlIntakeRulesConfig {
+                excluded_tlds: vec!["ru".to_string()],
+                excluded_url_patterns: vec!["/tag/*/feed".to_string()],
+                ..Default::default()
+            })
+            .unwrap();
+
+        let job = coordinator.sample_job().unwrap().unwrap();
+        assert_eq!(job.domain.as_str(), "b.com");
+        assert_eq!(job.urls.len(), 1);
+        assert_eq!(job.urls[0].url.as_str(), "https://b.com/1");
+
+        assert!(coordinator.sample_job().unwrap().is_none());
+        assert_eq!(coordinator.intake_exclusions()["tld:ru"], 1);
+        assert_eq!(coordinator.intake_exclusions()["url:/tag/*/feed"], 1);
+    }
+
+    #[test]
+    fn rate_overrides_are_attached_to_their_domain() {
+        let (path, coordinator) = coordinator(vec![
+            job("a.com", &["https://a.com/1"]),
+            job("b.com", &["https://b.com/1"]),
+        ]);
+        let coordinator = coordinator
+            .with_rate_overrides(
+                [(
+                    "docs.a.com".to_string(),
+                    CrawlRateOverride {
+                        crawl_delay_ms: 500,
+                    },
+                )]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap();
+
+        let job = coordinator.sample_job().unwrap().unwrap();
+        assert_eq!(job.domain.as_str(), "a.com");
+        assert_eq!(
+            coordinator
+                .rate_overrides_for(&job.domain)
+                .get("docs.a.com"),
+            Some(&CrawlRateOverride {
+                crawl_delay_ms: 500
+            })
+        );
+
+        let job = coordinator.sample_job().unwrap().unwrap();
+        assert_eq!(job.domain.as_str(), "b.com");
+        assert!(coordinator.rate_overrides_for(&job.domain).is_empty());
+
+        coordinator
+            .set_rate_override("docs.a.com".to_string(), None)
+            .unwrap();
+        coordinator
+            .set_rate_override(
+                "b.com".to_string(),
+                Some(CrawlRateOverride {
+                    crawl_delay_ms: 1_000,
+                }),
+            )
+            .unwrap();
+
+        // the updated overrides survive a restart
+        drop(coordinator);
+        let coordinator = CrawlCoordinator::new(&path, DomainFilter::default())
+            .unwrap()
+            .with_retry(no_backoff(1))
+            .with_rate_overrides(HashMap::new())
+            .unwrap();
+
+        assert_eq!(
+            coordinator.rate_overrides(),
+            [(
+                "b.com".to_string(),
+                CrawlRateOverride {
+                    crawl_delay_ms: 1_000
+                }
+            )]
+            .into_iter()
+            .collect()
+        );
+
+        coordinator
+            .report_failed(vec![failed("https://b.com/2")])
+            .unwrap();
+        let job = coordinator.sample_job().unwrap().unwrap();
+        assert_eq!(coordinator.rate_overrides_for(&job.domain).len(), 1);
+    }
+
+    #[test]
+    fn crawl_space_is_throttled() {
+        let (_, coordinator) = coordinator(vec![]);
+        let coordinator = coordinator.with_crawl_space(CrawlSpaceConfig {
+            url_threshold: 100,
+            min_reported_pages: 20,
+            max_distinct_fraction: 0.1,
+            trickle_every: 10,
+            max_signatures_per_host: 1_000,
+        });
+
+        let add = |urls: Vec<String>| {
+            let urls: Vec<_> = urls.iter().map(|url| url.as_str()).collect();
+            coordinator.admit(&mut wandered("a.com", &urls))
+        };
+        let calendar = |range: std::ops::Range<usize>| {
+            range
+                .map(|day| format!("https://a.com/calendar?date={day}&view=day"))
+                .collect::<Vec<_>>()
+        };
+        let products = |range: std::ops::Range<usize>| {
+            range
+                .map(|id| format!("https://a.com/products?id={id}"))
+                .collect::<Vec<_>>()
+        };
+        let report = |urls: Vec<String>, simhash: &dyn Fn(u64) -> u64| {
+            coordinator.report_content(
+                urls.into_iter()
+                    .enumerate()
+                    .map(|(i, url)| ContentFingerprint {
+                        url: Url::parse(&url).unwrap().into(),
+                        simhash: simhash(i as u64),
+                    })
+                    .collect(),
+            )
+        };
+
+        assert_eq!(add(calendar(0..200)), 0);
+        assert_eq!(add(products(0..200)), 0);
+
+        // every day of the calendar is the same page while the products differ
+        report(calendar(0..50), &|_| 42);
+        report(products(0..50), &|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+
+        assert_eq!(add(calendar(200..2_000)), 1_620);
+        assert_eq!(add(products(200..1_000)), 0);
+        assert_eq!(
+            add((0..100)
+                .map(|i| format!("https://a.com/blog/post-{i}"))
+                .collect()),
+            0
+        );
+
+        assert_eq!(
+            coordinator.crawl_spaces(),
+            vec![CrawlSpace {
+                host: "a.com".to_string(),
+                signature: "/calendar?date&view".to_string(),
+                urls: 2_000,
+                reported_pages: 50,
+                distinct_pages: 1,
+                throttled_urls: 1_620,
+            }]
+        );
+
+        coordinator
+            .set_crawl_space_override("a.com".to_string(), Some(CrawlSpaceOverride::Exempt))
+            .unwrap();
+        assert_eq!(add(calendar(2_000..2_100)), 0);
+        assert!(coordinator.crawl_spaces().is_empty());
+
+        coordinator
+            .set_crawl_space_override("a.com".to_string(), Some(CrawlSpaceOverride::Throttle))
+            .unwrap();
+        assert_eq!(add(products(1_000..1_010)), 9);
+    }
+
+    #[test]
+    fn crawl_space_throttles_queued_urls() {
+        let calendar = |range: std::ops::Range<usize>| {
+            range
+                .map(|day| format!("https://a.com/calendar?date={day}"))
+                .collect::<Vec<_>>()
+        };
+        let queued = calendar(200..400);
+        let queued: Vec<_> = queued.iter().map(|url| url.as_str()).collect();
+
+        let (_, coordinator) = coordinator(vec![job("a.com", &queued)]);
+        let coordinator = coordinator.with_crawl_space(CrawlSpaceConfig {
+            url_threshold: 100,
+            min_reported_pages: 20,
+            max_distinct_fraction: 0.1,
+            trickle_every: 10,
+            max_signatures_per_host: 1_000,
+        });
+
+        let wandered = calendar(0..200);
+        let wandered: Vec<_> = wandered.iter().map(|url| url.as_str()).collect();
+        assert_eq!(coordinator.admit(&mut job("a.com", &wandered)), 0);
+
+        coordinator.report_content(
+            calendar(0..50)
+                .into_iter()
+                .map(|url| ContentFingerprint {
+                    url: Url::parse(&url).unwrap().into(),
+                    simhash: 42,
+                })
+                .collect(),
+        );
+
+        let job = coordinator.sample_job().unwrap().unwrap();
+        assert_eq!(job.urls.len(), 20);
+        assert_eq!(coordinator.crawl_spaces()[0].throttled_urls, 180);
+    }
+
+    fn failed(url: &str) -> FailedUrl {
+        FailedUrl {
+            url: Url::parse(url).unwrap().into(),
+            weight: 1.0,
+            depth: 0,
+            reason: "dns error".to_string(),
+        }
+    }
+
+    fn no_backoff(max_retries: u32) -> CrawlRetryConfig {
+        CrawlRetryConfig {
+            max_retries,
+            backoff_ms: 0,
+            max_backoff_ms: 0,
+        }
+    }
+
+    #[test]
+    fn transient_failure_is_retried() {
+        let (_, coordinator) = coordinator(vec![job("a.com", &["https://a.com/1"])]);
+        let coordinator = coordinator.with_retry(no_backoff(3));
+
+        let job = coordinator.sample_job().unwrap().unwrap();
+        assert_eq!(job.urls[0].url.as_str(), "https://a.com/1");
+
+        // the first two attempts fail and the third succeeds
+        for _ in 0..2 {
+            assert_eq!(
+                coordinator
+                    .report_failed(vec![failed("https://a.com/1")])
+                    .unwrap(),
+                0
+            );
+
+            let job = coordinator.sample_job().unwrap().unwrap();
+            assert_eq!(job.domain.as_str(), "a.com");
+            assert_eq!(job.urls.len(), 1);
+            assert_eq!(job.urls[0].url.as_str(), "https://a.com/1");
+        }
+
+        assert!(coordinator.sample_job().unwrap().is_none());
+        assert!(coordinator.dead_letters().unwrap().is_empty());
+    }
+
+    #[test]
+    fn permanent_failure_is_dead_lettered() {
+        let (_, coordinator) = coordinator(vec![
+            job("a.com", &["https://a.com/1"]),
+            job("b.com", &["https://b.com/1"]),
+        ]);
+        let coordinator = coordinator.with_retry(no_backoff(2));
+
+        let mut sampled = Vec::new();
+
+        while let Some(job) = coordinator.sample_job().unwrap() {
+            sampled.push(job.domain.as_str().to_string());
+
+            if job.domain.as_str() == "a.com" {
+                coordinator
+                    .report_failed(vec![failed("https://a.com/1")])
+                    .unwrap();
+            }
+        }
+
+        // one attempt from the job queue and two retries
+        assert_eq!(sampled, vec!["a.com", "a.com", "a.com", "b.com"]);
+
+        assert_eq!(
+            coordinator.dead_letters().unwrap(),
+            vec![DeadLetter {
+                url: "https://a.com/1".to_string(),
+                domain: "a.com".to_string(),
+                reason: "dns error".to_string(),
+                attempts: 3,
+            }]
+        );
+    }
+
+    #[test]
+    fn retry_waits_for_backoff() {
+        let (_, coordinator) = coordinator(vec![]);
+        let coordinator = coordinator.with_retry(CrawlRetryConfig {
+            max_retries: 3,
+            backoff_ms: 60_000,
+            max_backoff_ms: 60_000,
+        });
+
+        coordinator
+            .report_failed(vec![failed("https://a.com/1")])
+            .unwrap();
+
+        assert!(coordinator.sample_job().unwrap().is_none());
+    }
+
+    #[test]
+    fn language_budget() {
+        let (_, coordinator) = coordinator(vec![
+            wandering_job("a.com", "https://www.a.com/1", 10),
+            wandering_job("b.com", "https://www.b.com/1", 10),
+            wandering_job("c.com", "https://www.c.com/1", 10),
+        ]);
+
+        // a.com is mostly english and b.com has too few pages to have a known language.
+        let store = HostLanguageStore::build(
+            crate::gen_temp_path(),
+            host_languages::compute(&host_languages::tests::index(), 3).unwrap(),
+        )
+        .unwrap();
+
+        let budget = LanguageBudget::new(
+            store,
+            [("eng".to_string(), 0.5), ("fra".to_string(), 2.0)]
+                .into_iter()
+                .collect(),
+        )
+        .unwrap();
+
+        let coordinator = coordinator.with_language_budget(budget);
+
+        let mut wandering = Vec::new();
+        while let Some(job) = coordinator.sample_job().unwrap() {
+            wandering.push((job.domain.as_str().to_string(), job.wandering_urls));
+        }
+
+        assert_eq!(
+            wandering,
+            vec![
+                ("a.com".to_string(), 5),
+                ("b.com".to_string(), 10),
+                ("c.com".to_string(), 10)
+            ]
+        );
+
+        assert!(LanguageBudget::new(
+            HostLanguageStore::open(crate::gen_temp_path()).unwrap(),
+            [("not-a-language".to_string(), 1.0)].into_iter().collect(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn urls_beyond_max_depth_are_not_enqueued() {
+        let (_, coordinator) = coordinator(vec![job("a.com", &["https://a.com/seed"])]);
+        let coordinator = coordinator.with_max_depth(Some(2));
+
+        assert!(coordinator.sample_job().unwrap().is_some());
+        assert_eq!(coordinator.max_depth(), Some(2));
+
+        let mut rejected = 0;
+        let mut depths = Vec::new();
+        for depth in 0..5 {
+            let mut job = wandered_at_depth("a.com", &[&format!("https://a.com/{depth}")], depth);
+            rejected += coordinator.admit(&mut job);
+            depths.extend(job.urls.iter().map(|url| url.depth));
+        }
+        assert_eq!(rejected, 2);
+        assert_eq!(depths, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn hosts_are_capped_at_max_pages() {
+        let (_, coordinator) = coordinator(vec![job(
+            "a.com",
+            &["https://a.com/seed-1", "https://a.com/seed-2"],
+        )]);
+        let coordinator = coordinator.with_max_pages_per_host(Some(3));
+
+        let job = coordinator.sample_job().unwrap().unwrap();
+        assert_eq!(job.urls.len(), 2);
+
+        assert_eq!(
+            admitted(
+                &coordinator,
+                wandered(
+                    "a.com",
+                    &[
+                        "https://a.com/1",
+                        "https://a.com/2",
+                        "https://blog.a.com/1",
+                        "https://b.com/1",
+                    ],
+                )
+            ),
+            vec!["https://a.com/1", "https://blog.a.com/1", "https://b.com/1"]
+        );
+        assert_eq!(
+            admitted(
+                &coordinator,
+                wandered("a.com", &["https://a.com/3", "https://blog.a.com/2"])
+            ),
+            vec!["https://blog.a.com/2"]
+        );
     }
 }
@@ -0,0 +1,319 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Detection of infinite crawl spaces like calendars and faceted navigation.
+//!
+//! The urls discovered on a host are grouped by their signature: the path with the
+//! segments that look like values (numbers, dates and ids) replaced by placeholders,
+//! followed by the sorted names of the query parameters. The workers report a
+//! near-duplicate fingerprint of the pages they fetch, so the coordinator knows how many
+//! distinct pages the urls of a signature lead to. A signature with many urls that
+//! mostly lead to the same pages is throttled to a trickle of its urls.
+
+use std::collections::{BTreeMap, HashMap};
+
+use url::Url;
+
+use crate::{config::CrawlSpaceConfig, simhash};
+
+// the fingerprints are only needed to tell whether there are few distinct pages,
+// so a signature stops remembering new fingerprints after this many.
+const MAX_FINGERPRINTS: u64 = 1_000;
+
+/// The signature of the urls that only differ in the values of their path
+/// segments and query parameters.
+pub fn signature(url: &Url) -> String {
+    let mut signature: String = url
+        .path()
+        .split('/')
+        .map(segment_pattern)
+        .collect::<Vec<_>>()
+        .join("/");
+
+    let mut params: Vec<_> = url.query_pairs().map(|(name, _)| name).collect();
+    params.sort();
+    params.dedup();
+
+    if !params.is_empty() {
+        signature.push('?');
+        signature.push_str(&params.join("&"));
+    }
+
+    signature
+}
+
+fn segment_pattern(segment: &str) -> &str {
+    if segment.is_empty() {
+        return segment;
+    }
+
+    if segment.chars().all(|c| c.is_ascii_digit()) {
+        return "{n}";
+    }
+
+    let is_date = segment.chars().any(|c| c.is_ascii_digit())
+        && segment.chars().any(|c| matches!(c, '-' | '_' | '.'))
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '-' | '_' | '.'));
+
+    if is_date {
+        return "{date}";
+    }
+
+    let is_id = segment.len() >= 16
+        && segment.chars().any(|c| c.is_ascii_digit())
+        && segment.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+
+    if is_id {
+        return "{id}";
+    }
+
+    segment
+}
+
+/// Manual decision for the signatures of a host.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub enum CrawlSpaceOverride {
+    /// Never throttle the signatures of the host.
+    Exempt,
+    /// Throttle every signature of the host with more urls than the threshold,
+    /// even if its pages are distinct.
+    Throttle,
+}
+
+/// A signature that is throttled.
+#[derive(
+    Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+)]
+pub struct CrawlSpace {
+    pub host: String,
+    pub signature: String,
+    /// Urls discovered with the signature.
+    pub urls: u64,
+    pub reported_pages: u64,
+    /// Reported pages that were not near-duplicates of an earlier page.
+    pub distinct_pages: u64,
+    /// Urls that were not added to the frontier because of the throttling.
+    pub throttled_urls: u64,
+}
+
+#[derive(Default)]
+struct SignatureStats {
+    urls: u64,
+    reported_pages: u64,
+    distinct_pages: u64,
+    fingerprints: simhash::Table,
+    throttled: bool,
+    urls_since_throttled: u64,
+    throttled_urls: u64,
+}
+
+impl SignatureStats {
+    fn is_repetitive(&self, config: &CrawlSpaceConfig) -> bool {
+        self.reported_pages >= config.min_reported_pages
+            && (self.distinct_pages as f64)
+                <= (self.reported_pages as f64) * config.max_distinct_fraction
+    }
+
+    fn report(&mut self, fingerprint: simhash::HashType) {
+        self.reported_pages += 1;
+
+        if !self.fingerprints.contains(&fingerprint) {
+            self.distinct_pages += 1;
+
+            if self.distinct_pages <= MAX_FINGERPRINTS {
+                self.fingerprints.insert(fingerprint);
+            }
+        }
+    }
+}
+
+pub struct CrawlSpaceDetector {
+    config: CrawlSpaceConfig,
+    hosts: HashMap<String, HashMap<String, SignatureStats>>,
+    overrides: BTreeMap<String, CrawlSpaceOverride>,
+}
+
+impl CrawlSpaceDetector {
+    pub fn new(config: CrawlSpaceConfig) -> Self {
+        Self {
+            config,
+            hosts: HashMap::new(),
+            overrides: BTreeMap::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: CrawlSpaceConfig) {
+        self.config = config;
+    }
+
+    pub fn overrides(&self) -> &BTreeMap<String, CrawlSpaceOverride> {
+        &self.overrides
+    }
+
+    pub fn set_overrides(&mut self, overrides: BTreeMap<String, CrawlSpaceOverride>) {
+        self.overrides = overrides;
+
+        for (host, signatures) in &mut self.hosts {
+            for stats in signatures.values_mut() {
+                if self.overrides.get(host) == Some(&CrawlSpaceOverride::Exempt) {
+                    stats.throttled = false;
+                }
+            }
+        }
+    }
+
+    /// Whether the discovered url should be added to the frontier.
+    pub fn admit(&mut self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return true;
+        };
+
+        let host_override = self.overrides.get(host).copied();
+
+        if host_override == Some(CrawlSpaceOverride::Exempt) {
+            return true;
+        }
+
+        let signatures = self.hosts.entry(host.to_string()).or_default();
+        let signature = signature(url);
+
+        if !signatures.contains_key(&signature)
+            && signatures.len() >= self.config.max_signatures_per_host
+        {
+            return true;
+        }
+
+        let stats = signatures.entry(signature.clone()).or_default();
+        stats.urls += 1;
+
+        let throttled = stats.urls > self.config.url_threshold
+            && (host_override == Some(CrawlSpaceOverride::Throttle)
+                || stats.is_repetitive(&self.config));
+
+        if !throttled {
+            stats.throttled = false;
+            return true;
+        }
+
+        if !stats.throttled {
+            tracing::warn!(
+                "throttling crawl space {}{} after {} urls with {} distinct of {} reported pages",
+                host,
+                signature,
+                stats.urls,
+                stats.distinct_pages,
+                stats.reported_pages
+            );
+
+            stats.throttled = true;
+            stats.urls_since_throttled = 0;
+        }
+
+        let admit = stats.urls_since_throttled % self.config.trickle_every.max(1) == 0;
+        stats.urls_since_throttled += 1;
+
+        if !admit {
+            stats.throttled_urls += 1;
+        }
+
+        admit
+    }
+
+    /// Record the fingerprint of a fetched page. Pages of signatures that haven't been
+    /// discovered by this coordinator are ignored.
+    pub fn report_content(&mut self, url: &Url, fingerprint: simhash::HashType) {
+        let Some(stats) = url
+            .host_str()
+            .and_then(|host| self.hosts.get_mut(host))
+            .and_then(|signatures| signatures.get_mut(&signature(url)))
+        else {
+            return;
+        };
+
+        stats.report(fingerprint);
+    }
+
+    /// The throttled signatures, ordered by host and signature.
+    pub fn throttled(&self) -> Vec<CrawlSpace> {
+        let mut res: Vec<_> =
+            self.hosts
+                .iter()
+                .flat_map(|(host, signatures)| {
+                    signatures.iter().filter(|(_, stats)| stats.throttled).map(
+                        |(signature, stats)| CrawlSpace {
+                            host: host.clone(),
+                            signature: signature.clone(),
+                            urls: stats.urls,
+                            reported_pages: stats.reported_pages,
+                            distinct_pages: stats.distinct_pages,
+                            throttled_urls: stats.throttled_urls,
+                        },
+                    )
+                })
+                .collect();
+
+        res.sort_by(|a, b| (&a.host, &a.signature).cmp(&(&b.host, &b.signature)));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sig(url: &str) -> String {
+        signature(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn signatures() {
+        assert_eq!(
+            sig("https://a.com/calendar?view=month&date=2024-01-01"),
+            "/calendar?date&view"
+        );
+        assert_eq!(
+            sig("https://a.com/calendar?date=3000-12-31&view=day"),
+            "/calendar?date&view"
+        );
+        assert_eq!(
+            sig("https://a.com/events/2024/05/01"),
+            "/events/{n}/{n}/{n}"
+        );
+        assert_eq!(sig("https://a.com/archive/2024-05-01/"), "/archive/{date}/");
+        assert_eq!(sig("https://a.com/item/3f2b9c1d4e5f6a7b8c9d"), "/item/{id}");
+        assert_eq!(
+            sig("https://a.com/shoes?size=9&color=red&color=blue"),
+            "/shoes?color&size"
+        );
+        assert_eq!(sig("https://a.com/about"), "/about");
+        assert_ne!(
+            sig("https://a.com/shoes?size=9"),
+            sig("https://a.com/shoes")
+        );
+    }
+}
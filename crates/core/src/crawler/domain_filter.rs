@@ -0,0 +1,199 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::BTreeSet;
+
+use url::Url;
+
+use crate::webpage::url_ext::UrlExt;
+
+use super::Job;
+
+/// A pattern for the urls of a domain.
+///
+/// `example.com` matches every url on the registrable domain `example.com` including
+/// its subdomains, `blog.example.com` only matches the host `blog.example.com`
+/// and `*.example.com` matches the subdomains of `example.com` but not `example.com` itself.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct DomainPattern(String);
+
+impl DomainPattern {
+    pub fn new(pattern: &str) -> Self {
+        Self(pattern.trim().trim_end_matches('.').to_lowercase())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.trim_end_matches('.').to_lowercase(),
+            None => return false,
+        };
+
+        match self.0.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|subdomain| subdomain.ends_with('.')),
+            None => host == self.0 || url.icann_domain() == Some(self.0.as_str()),
+        }
+    }
+}
+
+impl From<&str> for DomainPattern {
+    fn from(pattern: &str) -> Self {
+        Self::new(pattern)
+    }
+}
+
+impl From<String> for DomainPattern {
+    fn from(pattern: String) -> Self {
+        Self::new(&pattern)
+    }
+}
+
+/// Decides which urls the crawler is allowed to schedule. A url is allowed if it
+/// does not match the blocklist and, when an allowlist is set, matches the allowlist.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct DomainFilter {
+    blocked: BTreeSet<DomainPattern>,
+    allowed: Option<BTreeSet<DomainPattern>>,
+}
+
+impl DomainFilter {
+    pub fn new(blocked: Vec<String>, allowed: Option<Vec<String>>) -> Self {
+        Self {
+            blocked: blocked.into_iter().map(DomainPattern::from).collect(),
+            allowed: allowed.map(|allowed| allowed.into_iter().map(DomainPattern::from).collect()),
+        }
+    }
+
+    pub fn block<I, P>(&mut self, patterns: I)
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<DomainPattern>,
+    {
+        self.blocked.extend(patterns.into_iter().map(Into::into));
+    }
+
+    pub fn unblock<I, P>(&mut self, patterns: I)
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<DomainPattern>,
+    {
+        for pattern in patterns {
+            self.blocked.remove(&pattern.into());
+        }
+    }
+
+    pub fn set_allowlist<P: Into<DomainPattern>>(&mut self, allowed: Option<Vec<P>>) {
+        self.allowed = allowed.map(|allowed| allowed.into_iter().map(Into::into).collect());
+    }
+
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        if self.blocked.iter().any(|pattern| pattern.matches(url)) {
+            return false;
+        }
+
+        match &self.allowed {
+            Some(allowed) => allowed.iter().any(|pattern| pattern.matches(url)),
+            None => true,
+        }
+    }
+
+    /// Remove the urls from the job that are not allowed and return how many were removed.
+    pub fn retain(&self, job: &mut Job) -> usize {
+        let before = job.urls.len();
+        job.urls.retain(|url| self.is_allowed(&url.url));
+
+        before - job.urls.len()
+    }
+
+    /// The number of urls in the job that are not allowed.
+    pub fn num_rejected(&self, job: &Job) -> usize {
+        job.urls
+            .iter()
+            .filter(|url| !self.is_allowed(&url.url))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn registrable_domain() {
+        let pattern = DomainPattern::new("Example.com");
+
+        assert!(pattern.matches(&url("https://example.com/")));
+        assert!(pattern.matches(&url("https://www.example.com/page")));
+        assert!(pattern.matches(&url("https://a.b.example.com/")));
+        assert!(!pattern.matches(&url("https://example.org/")));
+        assert!(!pattern.matches(&url("https://notexample.com/")));
+    }
+
+    #[test]
+    fn exact_host() {
+        let pattern = DomainPattern::new("blog.example.com");
+
+        assert!(pattern.matches(&url("https://blog.example.com/")));
+        assert!(!pattern.matches(&url("https://example.com/")));
+        assert!(!pattern.matches(&url("https://www.example.com/")));
+    }
+
+    #[test]
+    fn subdomain_wildcard() {
+        let pattern = DomainPattern::new("*.example.com");
+
+        assert!(pattern.matches(&url("https://www.example.com/")));
+        assert!(pattern.matches(&url("https://a.b.example.com/")));
+        assert!(!pattern.matches(&url("https://example.com/")));
+        assert!(!pattern.matches(&url("https://wwwexample.com/")));
+    }
+
+    #[test]
+    fn blocklist_and_allowlist() {
+        let mut filter = DomainFilter::new(vec!["spam.com".to_string()], None);
+
+        assert!(!filter.is_allowed(&url("https://www.spam.com/")));
+        assert!(filter.is_allowed(&url("https://example.com/")));
+
+        filter.set_allowlist(Some(vec!["example.com", "*.test.org"]));
+
+        assert!(filter.is_allowed(&url("https://example.com/")));
+        assert!(filter.is_allowed(&url("https://www.test.org/")));
+        assert!(!filter.is_allowed(&url("https://test.org/")));
+        assert!(!filter.is_allowed(&url("https://other.com/")));
+
+        filter.block(["example.com"]);
+        assert!(!filter.is_allowed(&url("https://example.com/")));
+
+        filter.unblock(["example.com"]);
+        assert!(filter.is_allowed(&url("https://example.com/")));
+
+        filter.set_allowlist::<String>(None);
+        assert!(filter.is_allowed(&url("https://other.com/")));
+        assert!(!filter.is_allowed(&url("https://spam.com/")));
+    }
+}
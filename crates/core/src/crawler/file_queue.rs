@@ -162,27 +162,51 @@ where
         })
     }
 
-    pub fn pop(&mut self) -> Result<Option<T>> {
-        let cur_pointer = self.pointer.get();
-
-        if cur_pointer >= self.file.len() {
+    /// Decode the item at `pointer` and return it together with the pointer to the next item.
+    fn read(&self, pointer: usize) -> Result<Option<(T, usize)>> {
+        if pointer >= self.file.len() {
             return Ok(None);
         }
 
         let header_size = Header::POSTCARD_MAX_SIZE;
 
-        let header_bytes = &self.file[cur_pointer..cur_pointer + header_size];
+        let header_bytes = &self.file[pointer..pointer + header_size];
 
         let header: Header = postcard::from_bytes(header_bytes).unwrap();
 
-        let body =
-            &self.file[cur_pointer + header_size..cur_pointer + header_size + header.body_size];
+        let body = &self.file[pointer + header_size..pointer + header_size + header.body_size];
         let (item, _) = bincode::decode_from_slice(body, bincode::config::standard())?;
 
-        self.pointer
-            .set(cur_pointer + header_size + header.body_size)?;
+        Ok(Some((item, pointer + header_size + header.body_size)))
+    }
+
+    pub fn pop(&mut self) -> Result<Option<T>> {
+        let cur_pointer = self.pointer.get();
 
-        Ok(Some(item))
+        match self.read(cur_pointer)? {
+            Some((item, next_pointer)) => {
+                self.pointer.set(next_pointer)?;
+                Ok(Some(item))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Iterate the items that have not been popped yet without removing them from the queue.
+    pub fn peek_remaining(&mut self) -> impl Iterator<Item = Result<T>> + '_ {
+        let mut pointer = self.pointer.get();
+
+        std::iter::from_fn(move || match self.read(pointer) {
+            Ok(Some((item, next_pointer))) => {
+                pointer = next_pointer;
+                Some(Ok(item))
+            }
+            Ok(None) => None,
+            Err(err) => {
+                pointer = self.file.len();
+                Some(Err(err))
+            }
+        })
     }
 }
 
@@ -205,6 +229,24 @@ mod tests {
         assert_eq!(queue.pop().unwrap(), None);
     }
 
+    #[test]
+    fn peek_remaining() {
+        let mut writer = FileQueueWriter::new(crate::gen_temp_path()).unwrap();
+
+        writer.push("Hello".to_string()).unwrap();
+        writer.push("World".to_string()).unwrap();
+
+        let mut queue = writer.finalize().unwrap();
+
+        assert_eq!(queue.pop().unwrap().unwrap(), "Hello");
+
+        let remaining: Vec<String> = queue.peek_remaining().map(|s| s.unwrap()).collect();
+        assert_eq!(remaining, vec!["World".to_string()]);
+
+        assert_eq!(queue.pop().unwrap().unwrap(), "World");
+        assert_eq!(queue.peek_remaining().count(), 0);
+    }
+
     proptest! {
         #[test]
         fn prop(data: Vec<String>) {
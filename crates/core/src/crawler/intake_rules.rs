@@ -0,0 +1,259 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Rules that exclude urls as they are discovered, before they reach the frontier.
+//!
+//! The TLD and domain rules are compiled into a single set of host suffixes, so checking
+//! a url costs one lookup per label of its host regardless of the number of rules.
+//! Url patterns are plain substrings where `*` matches any sequence of characters.
+
+use std::collections::HashMap;
+
+use url::Url;
+
+use crate::config::CrawlIntakeRulesConfig;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SuffixRule {
+    /// Matches the suffix itself and all its subdomains.
+    Domain(usize),
+    /// Only matches the subdomains of the suffix.
+    Subdomains(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UrlPattern {
+    parts: Vec<String>,
+}
+
+impl UrlPattern {
+    fn new(pattern: &str) -> Self {
+        Self {
+            parts: pattern
+                .split('*')
+                .filter(|part| !part.is_empty())
+                .map(|part| part.to_string())
+                .collect(),
+        }
+    }
+
+    /// The pattern is not anchored, so finding the parts one after
+    /// another from the left is enough to decide if it matches.
+    fn matches(&self, url: &str) -> bool {
+        let mut rest = url;
+
+        for part in &self.parts {
+            match rest.find(part.as_str()) {
+                Some(idx) => rest = &rest[idx + part.len()..],
+                None => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// The compiled form of [`CrawlIntakeRulesConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct IntakeFilter {
+    rules: Vec<String>,
+    suffixes: HashMap<String, SuffixRule>,
+    url_patterns: Vec<(usize, UrlPattern)>,
+    log_sample_rate: f64,
+}
+
+fn normalize_suffix(s: &str) -> String {
+    s.trim().trim_matches('.').to_lowercase()
+}
+
+impl IntakeFilter {
+    pub fn new(config: &CrawlIntakeRulesConfig) -> Self {
+        let mut filter = Self {
+            log_sample_rate: config.log_sample_rate.clamp(0.0, 1.0),
+            ..Default::default()
+        };
+
+        for tld in &config.excluded_tlds {
+            let suffix = normalize_suffix(tld);
+
+            if !suffix.is_empty() {
+                let rule = filter.add_rule(format!("tld:{suffix}"));
+                filter
+                    .suffixes
+                    .entry(suffix)
+                    .or_insert(SuffixRule::Domain(rule));
+            }
+        }
+
+        for domain in &config.excluded_domains {
+            let domain = normalize_suffix(domain);
+
+            let (suffix, rule) = match domain.strip_prefix("*.") {
+                Some(suffix) => (
+                    suffix.to_string(),
+                    SuffixRule::Subdomains(filter.add_rule(format!("domain:{domain}"))),
+                ),
+                None => (
+                    domain.clone(),
+                    SuffixRule::Domain(filter.add_rule(format!("domain:{domain}"))),
+                ),
+            };
+
+            if !suffix.is_empty() {
+                filter.suffixes.entry(suffix).or_insert(rule);
+            }
+        }
+
+        for pattern in &config.excluded_url_patterns {
+            let compiled = UrlPattern::new(pattern);
+
+            if !compiled.parts.is_empty() {
+                let rule = filter.add_rule(format!("url:{pattern}"));
+                filter.url_patterns.push((rule, compiled));
+            }
+        }
+
+        filter
+    }
+
+    fn add_rule(&mut self, name: String) -> usize {
+        self.rules.push(name);
+        self.rules.len() - 1
+    }
+
+    /// Name of the rule, e.g. `tld:de`, `domain:*.example.com` or `url:/feed/`.
+    pub fn rule_name(&self, rule: usize) -> &str {
+        &self.rules[rule]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns the first rule that excludes the url, if any.
+    /// The most specific host suffix is checked first, then the url patterns in order.
+    pub fn excluded_by(&self, url: &Url) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+
+        if let Some(host) = url.host_str() {
+            let host = host.trim_end_matches('.').to_lowercase();
+            let mut suffix = host.as_str();
+
+            loop {
+                match self.suffixes.get(suffix) {
+                    Some(SuffixRule::Domain(rule)) => return Some(*rule),
+                    Some(SuffixRule::Subdomains(rule)) if suffix.len() < host.len() => {
+                        return Some(*rule)
+                    }
+                    _ => {}
+                }
+
+                match suffix.split_once('.') {
+                    Some((_, rest)) => suffix = rest,
+                    None => break,
+                }
+            }
+        }
+
+        self.url_patterns
+            .iter()
+            .find(|(_, pattern)| pattern.matches(url.as_str()))
+            .map(|(rule, _)| *rule)
+    }
+
+    /// Whether an excluded url should be logged.
+    pub fn should_log(&self) -> bool {
+        self.log_sample_rate > 0.0 && rand::random::<f64>() < self.log_sample_rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    fn rule(filter: &IntakeFilter, s: &str) -> Option<String> {
+        filter
+            .excluded_by(&url(s))
+            .map(|rule| filter.rule_name(rule).to_string())
+    }
+
+    #[test]
+    fn suffixes() {
+        let filter = IntakeFilter::new(&CrawlIntakeRulesConfig {
+            excluded_tlds: vec![".ru".to_string(), "co.uk".to_string()],
+            excluded_domains: vec!["spam.com".to_string(), "*.blogspot.com".to_string()],
+            ..Default::default()
+        });
+
+        assert_eq!(rule(&filter, "https://a.ru/"), Some("tld:ru".to_string()));
+        assert_eq!(
+            rule(&filter, "https://www.x.co.uk/"),
+            Some("tld:co.uk".to_string())
+        );
+        assert_eq!(rule(&filter, "https://uk/"), None);
+        assert_eq!(rule(&filter, "https://ru.com/"), None);
+
+        assert_eq!(
+            rule(&filter, "https://spam.com/"),
+            Some("domain:spam.com".to_string())
+        );
+        assert_eq!(
+            rule(&filter, "https://WWW.Spam.com./a"),
+            Some("domain:spam.com".to_string())
+        );
+        assert_eq!(rule(&filter, "https://notspam.com/"), None);
+
+        assert_eq!(
+            rule(&filter, "https://a.blogspot.com/"),
+            Some("domain:*.blogspot.com".to_string())
+        );
+        assert_eq!(rule(&filter, "https://blogspot.com/"), None);
+    }
+
+    #[test]
+    fn url_patterns() {
+        let filter = IntakeFilter::new(&CrawlIntakeRulesConfig {
+            excluded_url_patterns: vec![
+                "/wp-login.php".to_string(),
+                "?replytocom=*".to_string(),
+                "/tag/*/feed".to_string(),
+                "*".to_string(),
+            ],
+            ..Default::default()
+        });
+
+        assert_eq!(
+            rule(&filter, "https://a.com/wp-login.php?x=1"),
+            Some("url:/wp-login.php".to_string())
+        );
+        assert_eq!(
+            rule(&filter, "https://a.com/post?replytocom=12"),
+            Some("url:?replytocom=*".to_string())
+        );
+        assert_eq!(
+            rule(&filter, "https://a.com/tag/rust/feed/"),
+            Some("url:/tag/*/feed".to_string())
+        );
+        assert_eq!(rule(&filter, "https://a.com/feed/tag/rust"), None);
+        assert_eq!(rule(&filter, "https://a.com/"), None);
+    }
+}
@@ -22,17 +22,27 @@ use anyhow::anyhow;
 use futures::StreamExt;
 use url::Url;
 
-use crate::{config::CrawlerConfig, warc, webpage::url_ext::UrlExt};
+use crate::{
+    config::{CrawlRateOverride, CrawlerConfig},
+    warc,
+    webpage::url_ext::UrlExt,
+};
 
 use self::{warc_writer::WarcWriter, worker::WorkerThread};
-pub use worker::JobExecutor;
+pub use worker::{JobExecutor, JobReport};
 
 pub mod coordinator;
+pub mod crawl_space;
+pub mod domain_filter;
+pub mod intake_rules;
+pub mod retry;
+pub mod robots_audit;
 mod robots_txt;
 pub mod router;
 pub use router::Router;
 mod file_queue;
 pub mod planner;
+mod politeness;
 mod wander_prirotiser;
 mod warc_writer;
 mod worker;
@@ -132,6 +142,9 @@ pub struct WeightedUrl {
     #[bincode(with_serde)]
     pub url: Url,
     pub weight: f64,
+    /// Number of links followed from a seed url to reach the url. Seeds have depth 0.
+    #[serde(default)]
+    pub depth: u32,
 }
 
 impl PartialEq for WeightedUrl {
@@ -157,6 +170,28 @@ pub struct Job {
     pub wandering_urls: u64,
 }
 
+/// A job as a coordinator hands it out, with the settings of the coordinator for the job.
+/// The settings are not part of the [`Job`], as the jobs are persisted in the queues of
+/// the coordinators and the settings may change while the jobs are queued.
+#[derive(serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Debug, Clone)]
+pub struct SampledJob {
+    pub job: Job,
+    /// The negotiated crawl rates of the hosts of the domain, keyed by host.
+    pub rate_overrides: HashMap<String, CrawlRateOverride>,
+    /// Links are not followed to urls deeper than this.
+    pub max_depth: Option<u32>,
+}
+
+/// A job as the router hands it out to the workers.
+#[derive(serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Debug, Clone)]
+pub struct ScheduledJob {
+    pub job: Job,
+    pub rate_overrides: HashMap<String, CrawlRateOverride>,
+    pub max_depth: Option<u32>,
+    /// The coordinator of the job, which admits the urls the job wanders to.
+    pub coordinator: SocketAddr,
+}
+
 #[derive(
     Debug,
     Clone,
@@ -195,6 +230,9 @@ impl TryFrom<&UrlString> for Url {
 pub struct UrlToInsert {
     pub url: UrlString,
     pub weight: f64,
+    /// The depth of the url, one more than the page it was found on.
+    #[serde(default)]
+    pub depth: u32,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
@@ -202,12 +240,31 @@ pub struct DiscoveredUrls {
     pub urls: HashMap<Domain, Vec<UrlToInsert>>,
 }
 
+/// The near-duplicate fingerprint of a fetched page, used by the coordinators
+/// to detect crawl spaces that keep generating urls without new content.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct ContentFingerprint {
+    pub url: UrlString,
+    pub simhash: u64,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
 pub struct DomainCrawled {
     pub domain: Domain,
     pub budget_used: f64,
 }
 
+/// A url that could not be fetched because of an error that may go away
+/// on a later attempt, e.g. a timeout, a DNS failure or a server error.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct FailedUrl {
+    pub url: UrlString,
+    pub weight: f64,
+    #[serde(default)]
+    pub depth: u32,
+    pub reason: String,
+}
+
 pub struct RetrieableUrl {
     weighted_url: WeightedUrl,
     retries: u8,
@@ -232,6 +289,11 @@ pub struct WorkerJob {
     pub domain: Domain,
     pub urls: VecDeque<RetrieableUrl>,
     pub wandering_urls: u64,
+    pub rate_overrides: HashMap<String, CrawlRateOverride>,
+    pub max_depth: Option<u32>,
+    /// The coordinator that admits the urls the job wanders to.
+    /// Jobs without a coordinator wander to any url of their domain.
+    pub coordinator: Option<SocketAddr>,
 }
 
 impl From<Job> for WorkerJob {
@@ -240,6 +302,20 @@ impl From<Job> for WorkerJob {
             domain: value.domain,
             urls: value.urls.into_iter().map(RetrieableUrl::from).collect(),
             wandering_urls: value.wandering_urls,
+            rate_overrides: HashMap::default(),
+            max_depth: None,
+            coordinator: None,
+        }
+    }
+}
+
+impl From<ScheduledJob> for WorkerJob {
+    fn from(value: ScheduledJob) -> Self {
+        Self {
+            rate_overrides: value.rate_overrides,
+            max_depth: value.max_depth,
+            coordinator: Some(value.coordinator),
+            ..Self::from(value.job)
         }
     }
 }
@@ -250,6 +326,16 @@ pub struct CrawlDatum {
     pub payload_type: warc::PayloadType,
     pub body: String,
     pub fetch_time_ms: u64,
+    /// The requested url if the server redirected to `url`.
+    pub redirected_from: Option<Url>,
+    /// Seconds since the unix epoch of the `Last-Modified` header of the response.
+    pub last_modified: Option<u64>,
+    /// Seconds since the unix epoch of the `<lastmod>` of the url in the sitemap of the site.
+    pub sitemap_lastmod: Option<u64>,
+    /// The robots.txt that allowed the requested url to be fetched.
+    pub robots: Option<robots_audit::RobotsEvidence>,
+    /// Seconds since the unix epoch of when the page was fetched.
+    pub fetched_at: u64,
 }
 
 pub struct Crawler {
@@ -259,7 +345,10 @@ pub struct Crawler {
 
 impl Crawler {
     pub async fn new(config: CrawlerConfig) -> Result<Self> {
-        let writer = Arc::new(WarcWriter::new(config.s3.clone()));
+        let writer = Arc::new(WarcWriter::new(
+            config.s3.clone(),
+            config.artifact_manifest.clone(),
+        ));
         let mut handles = Vec::new();
         let mut router_hosts = Vec::new();
 
@@ -309,13 +398,20 @@ pub fn reqwest_client(config: &CrawlerConfig) -> Result<reqwest::Client> {
         reqwest::header::HeaderValue::from_static("en-US,en;q=0.9,*;q=0.8"),
     );
 
+    if let Some(from_email) = &config.user_agent.from_email {
+        headers.insert(
+            reqwest::header::FROM,
+            reqwest::header::HeaderValue::from_str(from_email).map_err(|e| anyhow!(e))?,
+        );
+    }
+
     reqwest::Client::builder()
         .timeout(timeout)
         .connect_timeout(timeout)
         .http2_keep_alive_interval(None)
         .default_headers(headers)
         .redirect(reqwest::redirect::Policy::limited(config.max_redirects))
-        .user_agent(&config.user_agent.full)
+        .user_agent(config.user_agent.header())
         .build()
         .map_err(|e| Error::from(anyhow!(e)))
 }
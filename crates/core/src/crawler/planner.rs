@@ -224,6 +224,7 @@ impl CrawlPlanner {
                     .get(&Node::from(url).id())
                     .unwrap()
                     .unwrap_or_default(),
+                depth: 0,
             })
             .collect();
 
@@ -263,7 +264,11 @@ impl CrawlPlanner {
                 let mut new_scheduled = 0;
                 for node in nodes.into_iter().flatten() {
                     if let Ok(url) = Url::parse(&format!("https://{}", node.as_str())) {
-                        urls.push(WeightedUrl { url, weight: 0.0 });
+                        urls.push(WeightedUrl {
+                            url,
+                            weight: 0.0,
+                            depth: 0,
+                        });
                         new_scheduled += 1;
                     }
                 }
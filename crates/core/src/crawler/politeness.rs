@@ -0,0 +1,251 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! How long a worker waits after each request of a job.
+//!
+//! By default the worker waits for as long as the request took, but at least the minimum
+//! crawl delay or the crawl-delay of the robots.txt, multiplied by the politeness factor.
+//! A host with a negotiated [`CrawlRateOverride`] is instead crawled with the delay of the
+//! override, unless the robots.txt of the host asks for a longer delay. Both delays double
+//! every time the server responds with 429 and never exceed the maximum crawl delay.
+
+use std::time::Duration;
+
+use url::Url;
+
+use super::HashMap;
+use crate::config::{CrawlRateOverride, CrawlerConfig};
+
+pub struct Politeness {
+    factor: f32,
+    max_factor: f32,
+    /// Multiplies the delays of the overrides.
+    backoff: f32,
+    min_crawl_delay: Duration,
+    max_crawl_delay: Duration,
+    robots_crawl_delays: HashMap<String, Duration>,
+    rate_overrides: HashMap<String, CrawlRateOverride>,
+}
+
+impl Politeness {
+    pub fn new(config: &CrawlerConfig, rate_overrides: HashMap<String, CrawlRateOverride>) -> Self {
+        Self {
+            factor: config.politeness_factor,
+            max_factor: config.max_politeness_factor,
+            backoff: 1.0,
+            min_crawl_delay: Duration::from_millis(config.min_crawl_delay_ms),
+            max_crawl_delay: Duration::from_millis(config.max_crawl_delay_ms),
+            robots_crawl_delays: HashMap::default(),
+            rate_overrides,
+        }
+    }
+
+    pub fn max_crawl_delay(&self) -> Duration {
+        self.max_crawl_delay
+    }
+
+    /// Record the crawl-delay of the robots.txt of the host. The default delay of the job
+    /// is raised to the longest crawl-delay of its hosts.
+    pub fn set_robots_crawl_delay(&mut self, host: &str, delay: Duration) {
+        let delay = delay.min(self.max_crawl_delay);
+
+        if delay > self.min_crawl_delay {
+            self.min_crawl_delay = delay;
+        }
+
+        self.robots_crawl_delays.insert(host.to_string(), delay);
+    }
+
+    /// Called when a server responds with 429.
+    pub fn increase(&mut self) {
+        self.factor = (self.factor * 2.0).min(self.max_factor);
+        self.backoff = (self.backoff * 2.0).min(self.max_factor);
+
+        tracing::warn!("politeness factor increased to {}", self.factor);
+    }
+
+    /// The delay after a request to the url that took `fetch_time`.
+    pub fn delay(&self, url: &Url, fetch_time: Duration) -> Duration {
+        let host = url.host_str().unwrap_or_default();
+
+        let delay = match self.rate_overrides.get(host) {
+            Some(rate_override) => {
+                let robots = self
+                    .robots_crawl_delays
+                    .get(host)
+                    .copied()
+                    .unwrap_or_default();
+
+                Duration::from_millis(rate_override.crawl_delay_ms)
+                    .max(robots)
+                    .mul_f32(self.backoff)
+            }
+            None => fetch_time.max(self.min_crawl_delay).mul_f32(self.factor),
+        };
+
+        delay.min(self.max_crawl_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use axum::{extract::State, http::HeaderMap, routing::get};
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    fn politeness(rate_overrides: &[(&str, u64)]) -> Politeness {
+        Politeness {
+            factor: 1.0,
+            max_factor: 2048.0,
+            backoff: 1.0,
+            min_crawl_delay: Duration::from_secs(5),
+            max_crawl_delay: Duration::from_secs(60),
+            robots_crawl_delays: HashMap::default(),
+            rate_overrides: rate_overrides
+                .iter()
+                .map(|(host, crawl_delay_ms)| {
+                    (
+                        host.to_string(),
+                        CrawlRateOverride {
+                            crawl_delay_ms: *crawl_delay_ms,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Requests per minute to the url when each request takes `fetch_time`.
+    fn request_rate(politeness: &Politeness, url: &str, fetch_time: Duration) -> f64 {
+        let url = Url::parse(url).unwrap();
+        let mut elapsed = Duration::ZERO;
+        let mut requests = 0;
+
+        while elapsed < Duration::from_secs(60) {
+            elapsed += fetch_time + politeness.delay(&url, fetch_time);
+            requests += 1;
+        }
+
+        requests as f64 * 60.0 / elapsed.as_secs_f64()
+    }
+
+    #[test]
+    fn override_raises_rate_of_allowed_host_only() {
+        let fetch_time = Duration::from_millis(100);
+        let default = politeness(&[]);
+        let negotiated = politeness(&[("docs.a.com", 1_000)]);
+
+        let baseline = request_rate(&default, "https://docs.a.com/1", fetch_time);
+        assert!(request_rate(&negotiated, "https://docs.a.com/1", fetch_time) > 4.0 * baseline);
+
+        assert_eq!(
+            request_rate(&negotiated, "https://www.a.com/1", fetch_time),
+            baseline
+        );
+        assert_eq!(
+            request_rate(&negotiated, "https://b.com/1", fetch_time),
+            request_rate(&default, "https://b.com/1", fetch_time)
+        );
+    }
+
+    #[test]
+    fn stricter_robots_crawl_delay_wins() {
+        let url = Url::parse("https://docs.a.com/1").unwrap();
+        let mut politeness = politeness(&[("docs.a.com", 1_000)]);
+
+        politeness.set_robots_crawl_delay("docs.a.com", Duration::from_millis(500));
+        assert_eq!(
+            politeness.delay(&url, Duration::ZERO),
+            Duration::from_secs(1)
+        );
+
+        politeness.set_robots_crawl_delay("docs.a.com", Duration::from_secs(3));
+        assert_eq!(
+            politeness.delay(&url, Duration::ZERO),
+            Duration::from_secs(3)
+        );
+
+        politeness.increase();
+        assert_eq!(
+            politeness.delay(&url, Duration::ZERO),
+            Duration::from_secs(6)
+        );
+
+        politeness.set_robots_crawl_delay("docs.a.com", Duration::from_secs(600));
+        assert_eq!(
+            politeness.delay(&url, Duration::ZERO),
+            politeness.max_crawl_delay()
+        );
+    }
+
+    #[tokio::test]
+    async fn identity_headers() {
+        type Requests = Arc<Mutex<Vec<HeaderMap>>>;
+        let requests: Requests = Arc::default();
+
+        let app = axum::Router::new()
+            .route(
+                "/",
+                get(
+                    |State(requests): State<Requests>, headers: HeaderMap| async move {
+                        requests.lock().await.push(headers);
+                    },
+                ),
+            )
+            .with_state(requests.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config: CrawlerConfig = toml::from_str(
+            r#"
+            num_worker_threads = 1
+            timeout_seconds = 30
+            router_hosts = []
+
+            [user_agent]
+            full = "StractBot/{version} (+{contact_url})"
+            token = "StractBot"
+            contact_url = "https://stract.com/webmasters"
+            from_email = "crawler@stract.com"
+
+            [s3]
+            bucket = "crawl"
+            folder = "test"
+            "#,
+        )
+        .unwrap();
+
+        let client = crate::crawler::reqwest_client(&config).unwrap();
+        client.get(format!("http://{addr}/")).send().await.unwrap();
+
+        let requests = requests.lock().await;
+        let header = |name: &str| requests[0].get(name).unwrap().to_str().unwrap();
+
+        assert_eq!(
+            header("user-agent"),
+            format!(
+                "StractBot/{} (+https://stract.com/webmasters)",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        assert_eq!(header("from"), "crawler@stract.com");
+    }
+}
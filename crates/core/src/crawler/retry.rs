@@ -0,0 +1,223 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use url::Url;
+
+use crate::config::CrawlRetryConfig;
+
+use super::{Domain, FailedUrl, Result, UrlString, WeightedUrl};
+
+/// An entry in the dead-letter log. The log has one json object per line.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DeadLetter {
+    pub url: String,
+    pub domain: String,
+    pub reason: String,
+    pub attempts: u32,
+}
+
+/// Failed urls waiting for their next attempt.
+///
+/// The number of attempts of a url is kept until the url either fails permanently or the
+/// coordinator is restarted, since the workers only report the urls that failed.
+pub struct RetryQueue {
+    config: CrawlRetryConfig,
+    attempts: HashMap<UrlString, u32>,
+    pending: BTreeMap<Instant, Vec<WeightedUrl>>,
+    dead_letter_path: PathBuf,
+}
+
+impl RetryQueue {
+    pub fn new(config: CrawlRetryConfig, dead_letter_path: PathBuf) -> Self {
+        Self {
+            config,
+            attempts: HashMap::new(),
+            pending: BTreeMap::new(),
+            dead_letter_path,
+        }
+    }
+
+    pub fn set_config(&mut self, config: CrawlRetryConfig) {
+        self.config = config;
+    }
+
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempts.saturating_sub(1));
+
+        Duration::from_millis(
+            self.config
+                .backoff_ms
+                .saturating_mul(factor)
+                .min(self.config.max_backoff_ms),
+        )
+    }
+
+    /// Schedule a retry of the url, or write it to the dead-letter log if it
+    /// has exhausted its retries. Returns whether the url was dead-lettered.
+    pub fn failed(&mut self, failed: FailedUrl, now: Instant) -> Result<bool> {
+        let url = match Url::try_from(&failed.url) {
+            Ok(url) => url,
+            Err(_) => return Ok(false),
+        };
+
+        let attempts = self.attempts.entry(failed.url.clone()).or_default();
+        *attempts += 1;
+        let attempts = *attempts;
+
+        if attempts > self.config.max_retries {
+            self.attempts.remove(&failed.url);
+            self.dead_letter(DeadLetter {
+                domain: Domain::from(&url).as_str().to_string(),
+                url: url.to_string(),
+                reason: failed.reason,
+                attempts,
+            })?;
+
+            return Ok(true);
+        }
+
+        self.pending
+            .entry(now + self.backoff(attempts))
+            .or_default()
+            .push(WeightedUrl {
+                url,
+                weight: failed.weight,
+                depth: failed.depth,
+            });
+
+        Ok(false)
+    }
+
+    fn dead_letter(&self, entry: DeadLetter) -> Result<()> {
+        tracing::warn!(
+            "giving up on {} after {} attempts: {}",
+            entry.url,
+            entry.attempts,
+            entry.reason
+        );
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.dead_letter_path)
+            .map_err(anyhow::Error::from)?;
+
+        let mut line = serde_json::to_string(&entry).map_err(anyhow::Error::from)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())
+            .map_err(anyhow::Error::from)?;
+
+        Ok(())
+    }
+
+    /// The url whose backoff expired first, if any has expired.
+    pub fn pop_due(&mut self, now: Instant) -> Option<WeightedUrl> {
+        let mut entry = self.pending.first_entry()?;
+
+        if *entry.key() > now {
+            return None;
+        }
+
+        let url = entry.get_mut().pop();
+
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+
+        url
+    }
+
+    pub fn num_pending(&self) -> usize {
+        self.pending.values().map(|urls| urls.len()).sum()
+    }
+}
+
+/// Read the dead-letter log.
+pub fn read_dead_letters(path: &std::path::Path) -> Result<Vec<DeadLetter>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = std::fs::read_to_string(path).map_err(anyhow::Error::from)?;
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| anyhow::Error::from(e).into()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed(url: &str) -> FailedUrl {
+        FailedUrl {
+            url: Url::parse(url).unwrap().into(),
+            weight: 1.0,
+            depth: 0,
+            reason: "timeout".to_string(),
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let queue = RetryQueue::new(
+            CrawlRetryConfig {
+                max_retries: 10,
+                backoff_ms: 1_000,
+                max_backoff_ms: 5_000,
+            },
+            crate::gen_temp_path(),
+        );
+
+        assert_eq!(queue.backoff(1), Duration::from_secs(1));
+        assert_eq!(queue.backoff(2), Duration::from_secs(2));
+        assert_eq!(queue.backoff(3), Duration::from_secs(4));
+        assert_eq!(queue.backoff(4), Duration::from_secs(5));
+        assert_eq!(queue.backoff(100), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn waits_for_backoff() {
+        let mut queue = RetryQueue::new(
+            CrawlRetryConfig {
+                max_retries: 3,
+                backoff_ms: 1_000,
+                max_backoff_ms: 60_000,
+            },
+            crate::gen_temp_path(),
+        );
+
+        let now = Instant::now();
+        assert!(!queue.failed(failed("https://a.com/"), now).unwrap());
+        assert_eq!(queue.num_pending(), 1);
+
+        assert!(queue.pop_due(now).is_none());
+        assert!(queue.pop_due(now + Duration::from_millis(999)).is_none());
+
+        let url = queue.pop_due(now + Duration::from_secs(1)).unwrap();
+        assert_eq!(url.url.as_str(), "https://a.com/");
+        assert_eq!(queue.num_pending(), 0);
+    }
+}
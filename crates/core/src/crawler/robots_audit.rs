@@ -0,0 +1,457 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The robots.txt evidence that the crawler was allowed to fetch a url.
+//!
+//! Every robots.txt the crawler fetches for a host is a [`RobotsVersion`], identified by
+//! the hash of its content and the time it was fetched. The metadata of each record in
+//! the WARC files only holds the id of the version that allowed the fetch and the index
+//! of the rule that matched the url. The versions themselves are interned, so the content
+//! of a robots.txt is stored once per WARC file in the `.robots.jsonl` file uploaded next
+//! to it, no matter how many urls of the host the file contains. The ids are derived from
+//! the host, hash and fetch time, so the workers never have to agree on them.
+//!
+//! [`audit`] reads the crawl output back and checks every fetch of a host against the
+//! robots.txt that was recorded for it, to answer questions like "were you allowed to
+//! crawl this page when you did?".
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use url::Url;
+
+use crate::{warc::WarcFile, Result};
+
+pub const VERSIONS_EXTENSION: &str = ".robots.jsonl";
+
+/// A robots.txt of a host as it was when the crawler fetched it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RobotsVersion {
+    pub id: u32,
+    pub host: String,
+    /// Seconds since the unix epoch.
+    pub fetched_at: u64,
+    /// The md5 hash of the robots.txt, or `None` if the host had no robots.txt
+    /// and everything was allowed.
+    pub content_hash: Option<String>,
+    pub body: Option<String>,
+}
+
+impl RobotsVersion {
+    pub fn new(host: String, body: Option<String>, fetched_at: u64) -> Self {
+        let content_hash = body
+            .as_ref()
+            .map(|body| format!("{:x}", md5::compute(body)));
+
+        let digest = md5::compute(format!(
+            "{host}\n{}\n{fetched_at}",
+            content_hash.as_deref().unwrap_or_default()
+        ));
+        let id = u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]]);
+
+        Self {
+            id,
+            host,
+            fetched_at,
+            content_hash,
+            body,
+        }
+    }
+
+    pub fn fetched_now(host: String, body: Option<String>) -> Self {
+        let fetched_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        Self::new(host, body, fetched_at)
+    }
+}
+
+/// The robots.txt that allowed a fetch.
+#[derive(Debug, Clone)]
+pub struct RobotsEvidence {
+    pub version: Arc<RobotsVersion>,
+    /// The index of the rule that matched the url, or `None` if no rule matched.
+    pub rule: Option<u32>,
+}
+
+/// The robots.txt versions used by the records of a WARC file.
+#[derive(Debug, Default)]
+pub struct RobotsVersions {
+    versions: BTreeMap<(String, u32), Arc<RobotsVersion>>,
+}
+
+impl RobotsVersions {
+    pub fn insert(&mut self, version: &Arc<RobotsVersion>) {
+        self.versions
+            .entry((version.host.clone(), version.id))
+            .or_insert_with(|| Arc::clone(version));
+    }
+
+    pub fn get(&self, host: &str, id: u32) -> Option<&Arc<RobotsVersion>> {
+        self.versions.get(&(host.to_string(), id))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.versions.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.versions.len()
+    }
+
+    pub fn to_jsonl(&self) -> Result<String> {
+        let mut data = String::new();
+
+        for version in self.versions.values() {
+            data.push_str(&serde_json::to_string(version.as_ref())?);
+            data.push('\n');
+        }
+
+        Ok(data)
+    }
+
+    pub fn extend_from_jsonl(&mut self, data: &str) -> Result<()> {
+        for line in data.lines().filter(|line| !line.trim().is_empty()) {
+            let version: RobotsVersion = serde_json::from_str(line)?;
+            self.insert(&Arc::new(version));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allowed,
+    /// The recorded robots.txt disallows the url.
+    Disallowed,
+    /// The record has no robots.txt evidence, or its version is
+    /// not in any of the `.robots.jsonl` files.
+    MissingEvidence,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditedFetch {
+    /// The requested url. This is the url whose robots.txt was checked,
+    /// even if the server redirected to another url.
+    pub url: String,
+    pub warc_file: String,
+    pub version: Option<Arc<RobotsVersion>>,
+    /// The recorded rule as it is written in the robots.txt.
+    pub rule: Option<String>,
+    pub verdict: Verdict,
+}
+
+#[derive(Debug, Default)]
+pub struct AuditReport {
+    pub fetches: Vec<AuditedFetch>,
+}
+
+impl AuditReport {
+    pub fn disallowed(&self) -> impl Iterator<Item = &AuditedFetch> {
+        self.fetches
+            .iter()
+            .filter(|fetch| fetch.verdict == Verdict::Disallowed)
+    }
+
+    pub fn missing_evidence(&self) -> impl Iterator<Item = &AuditedFetch> {
+        self.fetches
+            .iter()
+            .filter(|fetch| fetch.verdict == Verdict::MissingEvidence)
+    }
+}
+
+fn files_with_extension(dir: &Path, extension: &str) -> Result<Vec<std::path::PathBuf>> {
+    let mut files: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(extension))
+        })
+        .collect();
+    files.sort();
+
+    Ok(files)
+}
+
+/// Check every fetch of the host in the WARC files of the crawl directory against
+/// the robots.txt that was recorded for it. The robots.txt is parsed for the user agent
+/// the crawler identified as.
+pub fn audit<P: AsRef<Path>>(crawl_dir: P, host: &str, user_agent: &str) -> Result<AuditReport> {
+    let crawl_dir = crawl_dir.as_ref();
+    let host = host.to_lowercase();
+
+    let mut versions = RobotsVersions::default();
+    for path in files_with_extension(crawl_dir, VERSIONS_EXTENSION)? {
+        versions.extend_from_jsonl(&std::fs::read_to_string(path)?)?;
+    }
+
+    let mut parsed: HashMap<u32, Option<robotstxt::Robots>> = HashMap::new();
+    let mut report = AuditReport::default();
+
+    for path in files_with_extension(crawl_dir, ".warc.gz")? {
+        let warc_file = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+            .to_string();
+
+        for record in WarcFile::open(&path)?.records() {
+            let record = record?;
+            let url = record
+                .metadata
+                .redirected_from
+                .unwrap_or(record.request.url);
+
+            let Ok(parsed_url) = Url::parse(&url) else {
+                continue;
+            };
+
+            if parsed_url.host_str() != Some(host.as_str()) {
+                continue;
+            }
+
+            let version = record
+                .metadata
+                .robots_version
+                .and_then(|id| versions.get(&host, id))
+                .cloned();
+
+            let Some(version) = version else {
+                report.fetches.push(AuditedFetch {
+                    url,
+                    warc_file: warc_file.clone(),
+                    version: None,
+                    rule: None,
+                    verdict: Verdict::MissingEvidence,
+                });
+                continue;
+            };
+
+            if !parsed.contains_key(&version.id) {
+                let robots = match &version.body {
+                    Some(body) => Some(robotstxt::Robots::parse(user_agent, body)?),
+                    None => None,
+                };
+
+                parsed.insert(version.id, robots);
+            }
+
+            let (rule, verdict) = match &parsed[&version.id] {
+                Some(robots) => (
+                    record
+                        .metadata
+                        .robots_rule
+                        .and_then(|idx| robots.rule(idx as usize)),
+                    if robots.is_allowed(&parsed_url) {
+                        Verdict::Allowed
+                    } else {
+                        Verdict::Disallowed
+                    },
+                ),
+                None => (None, Verdict::Allowed),
+            };
+
+            report.fetches.push(AuditedFetch {
+                url,
+                warc_file: warc_file.clone(),
+                version: Some(version),
+                rule,
+                verdict,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        gen_temp_path,
+        warc::{Metadata, Request, Response, WarcRecord, WarcWriter},
+    };
+
+    use super::*;
+
+    const USER_AGENT: &str = "StractBot";
+
+    fn record(url: &str, version: Option<&Arc<RobotsVersion>>, rule: Option<u32>) -> WarcRecord {
+        WarcRecord {
+            request: Request {
+                url: url.to_string(),
+            },
+            response: Response {
+                body: "<html><body>page</body></html>".to_string(),
+                payload_type: None,
+            },
+            metadata: Metadata {
+                fetch_time_ms: 1,
+                redirected_from: None,
+                last_modified: None,
+                sitemap_lastmod: None,
+                robots_version: version.map(|version| version.id),
+                robots_rule: rule,
+                fetched_at: None,
+            },
+        }
+    }
+
+    #[test]
+    fn version_ids() {
+        let a = RobotsVersion::new("a.com".to_string(), Some("Disallow: /".to_string()), 10);
+
+        assert_eq!(
+            a,
+            RobotsVersion::new("a.com".to_string(), Some("Disallow: /".to_string()), 10)
+        );
+        assert_ne!(
+            a.id,
+            RobotsVersion::new("a.com".to_string(), Some("Disallow: /".to_string()), 11).id
+        );
+        assert_ne!(
+            a.id,
+            RobotsVersion::new("b.com".to_string(), Some("Disallow: /".to_string()), 10).id
+        );
+        assert_eq!(
+            RobotsVersion::new("a.com".to_string(), None, 10).content_hash,
+            None
+        );
+    }
+
+    #[test]
+    fn robots_change_mid_crawl() {
+        let dir = gen_temp_path();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let before = Arc::new(RobotsVersion::new(
+            "example.com".to_string(),
+            Some("User-agent: *\nDisallow: /private".to_string()),
+            1_700_000_000,
+        ));
+        // the site started to disallow its blog during the crawl
+        let after = Arc::new(RobotsVersion::new(
+            "example.com".to_string(),
+            Some("User-agent: *\nDisallow: /private\nDisallow: /blog".to_string()),
+            1_700_086_400,
+        ));
+
+        let mut writer = WarcWriter::new();
+        for record in [
+            record("https://example.com/blog/first", Some(&before), None),
+            record("https://example.com/about", Some(&after), None),
+            // a fetch that the recorded robots.txt did not allow
+            record("https://example.com/blog/second", Some(&after), None),
+            record("https://example.com/legacy", None, None),
+            record("https://other.com/blog/first", Some(&after), None),
+        ] {
+            writer.write(&record).unwrap();
+        }
+        std::fs::write(dir.join("0.warc.gz"), writer.finish().unwrap()).unwrap();
+
+        let mut versions = RobotsVersions::default();
+        versions.insert(&before);
+        versions.insert(&after);
+        versions.insert(&after);
+        assert_eq!(versions.len(), 2);
+        std::fs::write(
+            dir.join(format!("0{VERSIONS_EXTENSION}")),
+            versions.to_jsonl().unwrap(),
+        )
+        .unwrap();
+
+        let report = audit(&dir, "example.com", USER_AGENT).unwrap();
+        assert_eq!(report.fetches.len(), 4);
+
+        let verdicts: Vec<_> = report
+            .fetches
+            .iter()
+            .map(|fetch| (fetch.url.as_str(), fetch.verdict))
+            .collect();
+        assert_eq!(
+            verdicts,
+            vec![
+                ("https://example.com/blog/first", Verdict::Allowed),
+                ("https://example.com/about", Verdict::Allowed),
+                ("https://example.com/blog/second", Verdict::Disallowed),
+                ("https://example.com/legacy", Verdict::MissingEvidence),
+            ]
+        );
+
+        assert_eq!(
+            report.fetches[0].version.as_deref().map(|v| v.fetched_at),
+            Some(before.fetched_at)
+        );
+        assert_eq!(
+            report.fetches[1].version.as_deref().map(|v| v.fetched_at),
+            Some(after.fetched_at)
+        );
+
+        let disallowed: Vec<_> = report.disallowed().collect();
+        assert_eq!(disallowed.len(), 1);
+        assert_eq!(
+            disallowed[0].version.as_ref().unwrap().content_hash,
+            after.content_hash
+        );
+        assert_eq!(report.missing_evidence().count(), 1);
+    }
+
+    #[test]
+    fn recorded_rule() {
+        let dir = gen_temp_path();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let body = "User-agent: *\nDisallow: /private\nAllow: /private/public";
+        let version = Arc::new(RobotsVersion::new(
+            "example.com".to_string(),
+            Some(body.to_string()),
+            1_700_000_000,
+        ));
+        let robots = robotstxt::Robots::parse(USER_AGENT, body).unwrap();
+        let url = Url::parse("https://example.com/private/public/page").unwrap();
+        let rule = robots.matched_rule(&url).map(|idx| idx as u32);
+
+        let mut writer = WarcWriter::new();
+        writer
+            .write(&record(url.as_str(), Some(&version), rule))
+            .unwrap();
+        std::fs::write(dir.join("0.warc.gz"), writer.finish().unwrap()).unwrap();
+
+        let mut versions = RobotsVersions::default();
+        versions.insert(&version);
+        std::fs::write(
+            dir.join(format!("0{VERSIONS_EXTENSION}")),
+            versions.to_jsonl().unwrap(),
+        )
+        .unwrap();
+
+        let report = audit(&dir, "example.com", USER_AGENT).unwrap();
+        assert_eq!(report.fetches.len(), 1);
+        assert_eq!(report.fetches[0].verdict, Verdict::Allowed);
+        assert_eq!(
+            report.fetches[0].rule.as_deref(),
+            Some("Allow: /private/public")
+        );
+    }
+}
@@ -14,18 +14,22 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{collections::BTreeMap, panic, time::Duration};
+use std::{collections::BTreeMap, panic, sync::Arc, time::Duration};
 
 use url::Url;
 
 use crate::{config::CrawlerConfig, crawler};
 
-use super::{encoded_body, Result, Site};
+use super::{
+    encoded_body,
+    robots_audit::{RobotsEvidence, RobotsVersion},
+    Result, Site,
+};
 
 enum Lookup<T> {
     Found(T),
-    /// 404
-    Unavailable,
+    /// 404. The version records when the robots.txt was found to be missing.
+    Unavailable(Arc<RobotsVersion>),
     /// 5xx
     Unreachable,
 }
@@ -59,22 +63,27 @@ impl RobotsTxtManager {
         }
     }
 
-    pub async fn is_allowed(&mut self, url: &Url) -> bool {
+    /// The evidence that the robots.txt of the site allows the url to be crawled,
+    /// or `None` if the url is disallowed or the robots.txt could not be fetched.
+    pub async fn authorize(&mut self, url: &Url) -> Option<RobotsEvidence> {
         match self.get_mut(url).await {
-            Lookup::Found(robots_txt) => robots_txt.is_allowed(url),
-            Lookup::Unavailable => true,
-            Lookup::Unreachable => false,
+            Lookup::Found(robots_txt) => robots_txt.authorize(url),
+            Lookup::Unavailable(version) => Some(RobotsEvidence {
+                version: Arc::clone(version),
+                rule: None,
+            }),
+            Lookup::Unreachable => None,
         }
     }
 
     pub async fn crawl_delay(&mut self, url: &Url) -> Option<Duration> {
         match self.get_mut(url).await {
             Lookup::Found(robots_txt) => robots_txt.robots.crawl_delay(),
-            Lookup::Unavailable | Lookup::Unreachable => None,
+            Lookup::Unavailable(_) | Lookup::Unreachable => None,
         }
     }
 
-    async fn fetch_robots_txt_from_url(&self, url: &str) -> Lookup<RobotsTxt> {
+    async fn fetch_robots_txt_from_url(&self, site: &Site, url: &str) -> Lookup<RobotsTxt> {
         let res = match self
             .client
             .get(url)
@@ -85,7 +94,12 @@ impl RobotsTxtManager {
             Ok(res) => {
                 if res.status() != reqwest::StatusCode::OK {
                     match res.status() {
-                        reqwest::StatusCode::NOT_FOUND => return Lookup::Unavailable,
+                        reqwest::StatusCode::NOT_FOUND => {
+                            return Lookup::Unavailable(Arc::new(RobotsVersion::fetched_now(
+                                site.0.clone(),
+                                None,
+                            )))
+                        }
                         _ => return Lookup::Unreachable,
                     }
                 }
@@ -96,7 +110,7 @@ impl RobotsTxtManager {
                 };
 
                 let self_user_agent = self.user_agent.clone();
-                match panic::catch_unwind(|| RobotsTxt::new(&self_user_agent, body)) {
+                match panic::catch_unwind(|| RobotsTxt::new(&self_user_agent, site, body)) {
                     Ok(Ok(r)) => Lookup::Found(r),
                     _ => Lookup::Unreachable,
                 }
@@ -111,27 +125,27 @@ impl RobotsTxtManager {
 
     async fn fetch_robots_txt_without_retry(&self, site: &Site) -> Lookup<RobotsTxt> {
         match self
-            .fetch_robots_txt_from_url(&format!("http://{}/robots.txt", site.0))
+            .fetch_robots_txt_from_url(site, &format!("http://{}/robots.txt", site.0))
             .await
         {
-            Lookup::Unavailable => {
+            Lookup::Unavailable(_) => {
                 match self
-                    .fetch_robots_txt_from_url(&format!("https://{}/robots.txt", site.0))
+                    .fetch_robots_txt_from_url(site, &format!("https://{}/robots.txt", site.0))
                     .await
                 {
                     Lookup::Found(robots_txt) => Lookup::Found(robots_txt),
                     Lookup::Unreachable => Lookup::Unreachable,
-                    Lookup::Unavailable
+                    Lookup::Unavailable(_)
                         if !site.0.starts_with("www.")
                             && site.0.chars().filter(|&c| c == '.').count() == 1 =>
                     {
-                        self.fetch_robots_txt_from_url(&format!(
-                            "https://www.{}/robots.txt",
-                            &site.0
-                        ))
+                        self.fetch_robots_txt_from_url(
+                            site,
+                            &format!("https://www.{}/robots.txt", &site.0),
+                        )
                         .await
                     }
-                    Lookup::Unavailable => Lookup::Unavailable,
+                    Lookup::Unavailable(version) => Lookup::Unavailable(version),
                 }
             }
             res => res,
@@ -142,7 +156,7 @@ impl RobotsTxtManager {
         for _ in 0..3 {
             match self.fetch_robots_txt_without_retry(site).await {
                 Lookup::Found(robots_txt) => return Lookup::Found(robots_txt),
-                Lookup::Unavailable => return Lookup::Unavailable,
+                Lookup::Unavailable(version) => return Lookup::Unavailable(version),
                 Lookup::Unreachable => {}
             }
 
@@ -171,7 +185,7 @@ impl RobotsTxtManager {
 
         let cache_should_update = match self.cache.get_mut(&site) {
             Some(Lookup::Found(robots_txt)) => robots_txt.is_expired(&self.cache_expiration),
-            Some(Lookup::Unavailable) => false,
+            Some(Lookup::Unavailable(_)) => false,
             _ => true,
         };
 
@@ -190,7 +204,7 @@ impl RobotsTxtManager {
                 .iter()
                 .filter_map(|s| Url::parse(s).ok())
                 .collect(),
-            Lookup::Unavailable => vec![],
+            Lookup::Unavailable(_) => vec![],
             Lookup::Unreachable => vec![],
         }
     }
@@ -199,13 +213,15 @@ impl RobotsTxtManager {
 struct RobotsTxt {
     download_time: std::time::Instant,
     robots: robotstxt::Robots,
+    version: Arc<RobotsVersion>,
 }
 
 impl RobotsTxt {
-    fn new(user_agent: &str, body: String) -> Result<Self> {
+    fn new(user_agent: &str, site: &Site, body: String) -> Result<Self> {
         Ok(Self {
             robots: robotstxt::Robots::parse(user_agent, &body)?,
             download_time: std::time::Instant::now(),
+            version: Arc::new(RobotsVersion::fetched_now(site.0.clone(), Some(body))),
         })
     }
 
@@ -217,6 +233,17 @@ impl RobotsTxt {
         self.robots.is_allowed(url)
     }
 
+    fn authorize(&self, url: &Url) -> Option<RobotsEvidence> {
+        if !self.is_allowed(url) {
+            return None;
+        }
+
+        Some(RobotsEvidence {
+            version: Arc::clone(&self.version),
+            rule: self.robots.matched_rule(url).map(|idx| idx as u32),
+        })
+    }
+
     fn sitemaps(&self) -> &[String] {
         self.robots.sitemaps()
     }
@@ -226,11 +253,16 @@ impl RobotsTxt {
 mod tests {
     use super::*;
 
+    fn site() -> Site {
+        Site("example.com".to_string())
+    }
+
     #[test]
     fn simple() {
         let ua_token = "StractBot";
         let robots_txt = RobotsTxt::new(
             ua_token,
+            &site(),
             r#"User-agent: StractBot
             Disallow: /test"#
                 .to_string(),
@@ -246,6 +278,7 @@ mod tests {
         let ua_token = "StractBot";
         let robots_txt = RobotsTxt::new(
             ua_token,
+            &site(),
             r#"User-agent: stractbot
             Disallow: /test"#
                 .to_string(),
@@ -261,6 +294,7 @@ mod tests {
         let ua_token = "StractBot";
         let robots_txt = RobotsTxt::new(
             ua_token,
+            &site(),
             r#"User-agent: StractBot
 
 
@@ -279,6 +313,7 @@ mod tests {
 
         let robots_txt = RobotsTxt::new(
             ua_token,
+            &site(),
             r#"User-Agent: GoogleBot
 User-Agent: StractBot
 Disallow: /
@@ -295,6 +330,7 @@ Allow: /"#
 
         let robots_txt = RobotsTxt::new(
             ua_token,
+            &site(),
             r#"User-Agent: GoogleBot, StractBot
 Disallow: /
 
@@ -312,6 +348,7 @@ Allow: /"#
         let ua_token = "StractBot";
         let robots_txt = RobotsTxt::new(
             ua_token,
+            &site(),
             r#"User-agent: *
 Disallow: /test
 
@@ -324,6 +361,7 @@ Sitemap: http://example.com/sitemap.xml"#
 
         let robots_txt = RobotsTxt::new(
             ua_token,
+            &site(),
             r#"User-agent: *
 Disallow: /test
 
@@ -341,6 +379,7 @@ SiTeMaP: http://example.com/sitemap.xml"#
 
         let robots_txt = RobotsTxt::new(
             ua_token,
+            &site(),
             r#"User-agent: StractBot
 Disallow: /test/*
 "#
@@ -356,6 +395,7 @@ Disallow: /test/*
 
         let robots_txt = RobotsTxt::new(
             ua_token,
+            &site(),
             r#"User-agent: StractBot
     Disallow: /test/*/bar
     "#
@@ -370,4 +410,38 @@ Disallow: /test/*
         assert!(robots_txt.is_allowed(&Url::parse("http://example.com/test").unwrap()));
         assert!(robots_txt.is_allowed(&Url::parse("http://example.com/testfoo").unwrap()));
     }
+
+    #[test]
+    fn evidence() {
+        let robots_txt = RobotsTxt::new(
+            "StractBot",
+            &site(),
+            r#"User-agent: StractBot
+Disallow: /private
+Allow: /private/public"#
+                .to_string(),
+        )
+        .unwrap();
+
+        assert!(robots_txt
+            .authorize(&Url::parse("http://example.com/private/secret").unwrap())
+            .is_none());
+
+        let evidence = robots_txt
+            .authorize(&Url::parse("http://example.com/private/public/page").unwrap())
+            .unwrap();
+        assert_eq!(evidence.version.host, "example.com");
+        assert_eq!(evidence.version, robots_txt.version);
+        assert_eq!(
+            evidence
+                .rule
+                .and_then(|idx| robots_txt.robots.rule(idx as usize)),
+            Some("Allow: /private/public".to_string())
+        );
+
+        let evidence = robots_txt
+            .authorize(&Url::parse("http://example.com/other").unwrap())
+            .unwrap();
+        assert_eq!(evidence.rule, None);
+    }
 }
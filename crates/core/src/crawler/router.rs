@@ -5,10 +5,12 @@ use tokio::sync::Mutex;
 
 use crate::{
     distributed::{retry_strategy::ExponentialBackoff, sonic},
-    entrypoint::crawler::coordinator::{CoordinatorService, GetJob},
+    entrypoint::crawler::coordinator::{
+        AdmitUrls, CoordinatorService, GetJob, ReportContent, ReportFailed,
+    },
 };
 
-use super::Job;
+use super::{ContentFingerprint, FailedUrl, Job, SampledJob, ScheduledJob};
 
 struct RemoteCoordinator {
     addr: SocketAddr,
@@ -26,7 +28,7 @@ impl RemoteCoordinator {
         .await?)
     }
 
-    async fn sample_job(&self) -> Result<Option<Job>> {
+    async fn sample_job(&self) -> Result<Option<SampledJob>> {
         let mut conn = self.conn().await?;
 
         let response = conn
@@ -35,6 +37,34 @@ impl RemoteCoordinator {
 
         Ok(response)
     }
+
+    async fn admit(&self, job: Job) -> Result<Job> {
+        let mut conn = self.conn().await?;
+
+        let response = conn
+            .send_with_timeout(AdmitUrls(job), Duration::from_secs(90))
+            .await?;
+
+        Ok(response)
+    }
+
+    async fn report_failed(&self, failed: Vec<FailedUrl>) -> Result<()> {
+        let mut conn = self.conn().await?;
+
+        conn.send_with_timeout(ReportFailed(failed), Duration::from_secs(90))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn report_content(&self, fingerprints: Vec<ContentFingerprint>) -> Result<()> {
+        let mut conn = self.conn().await?;
+
+        conn.send_with_timeout(ReportContent(fingerprints), Duration::from_secs(90))
+            .await?;
+
+        Ok(())
+    }
 }
 
 struct InnerRouter {
@@ -51,13 +81,23 @@ impl InnerRouter {
         })
     }
 
-    async fn sample_job(&mut self) -> Result<Option<Job>> {
+    async fn sample_job(&mut self) -> Result<Option<ScheduledJob>> {
         while !self.coordinators.is_empty() {
             let idx = rand::thread_rng().gen_range(0..self.coordinators.len());
             let res = self.coordinators[idx].sample_job().await?;
 
-            if res.is_some() {
-                return Ok(res);
+            if let Some(SampledJob {
+                job,
+                rate_overrides,
+                max_depth,
+            }) = res
+            {
+                return Ok(Some(ScheduledJob {
+                    job,
+                    rate_overrides,
+                    max_depth,
+                    coordinator: self.coordinators[idx].addr,
+                }));
             }
 
             self.coordinators.remove(idx);
@@ -65,20 +105,62 @@ impl InnerRouter {
 
         Ok(None)
     }
+
+    /// The jobs don't record which coordinator they came from, so the failed urls are sent to
+    /// any coordinator that still has jobs. The urls are dropped if all coordinators are done.
+    async fn report_failed(&self, failed: Vec<FailedUrl>) -> Result<()> {
+        if self.coordinators.is_empty() {
+            return Ok(());
+        }
+
+        let idx = rand::thread_rng().gen_range(0..self.coordinators.len());
+        self.coordinators[idx].report_failed(failed).await
+    }
+
+    /// The fingerprints are only useful to the coordinator that discovered the urls,
+    /// which isn't known, so they are sent to all coordinators that still have jobs.
+    async fn report_content(&self, fingerprints: Vec<ContentFingerprint>) -> Result<()> {
+        for coordinator in &self.coordinators {
+            coordinator.report_content(fingerprints.clone()).await?;
+        }
+
+        Ok(())
+    }
 }
 
 pub struct Router {
     inner: Mutex<InnerRouter>,
+    coordinator_addrs: Vec<SocketAddr>,
 }
 
 impl Router {
     pub async fn new(coordinator_addrs: Vec<SocketAddr>) -> Result<Self> {
         Ok(Self {
-            inner: Mutex::new(InnerRouter::new(coordinator_addrs).await?),
+            inner: Mutex::new(InnerRouter::new(coordinator_addrs.clone()).await?),
+            coordinator_addrs,
         })
     }
 
-    pub async fn sample_job(&self) -> Result<Option<Job>> {
+    pub async fn sample_job(&self) -> Result<Option<ScheduledJob>> {
         self.inner.lock().await.sample_job().await
     }
+
+    /// Ask the coordinator of a job which of the urls the job wants to wander to may be
+    /// crawled. The coordinator may have run out of jobs, so it is asked even if it is
+    /// no longer sampled from.
+    pub async fn admit(&self, coordinator: SocketAddr, job: Job) -> Result<Job> {
+        if !self.coordinator_addrs.contains(&coordinator) {
+            return Err(anyhow::anyhow!("unknown coordinator: {coordinator}"));
+        }
+
+        RemoteCoordinator { addr: coordinator }.admit(job).await
+    }
+
+    pub async fn report_failed(&self, failed: Vec<FailedUrl>) -> Result<()> {
+        self.inner.lock().await.report_failed(failed).await
+    }
+
+    pub async fn report_content(&self, fingerprints: Vec<ContentFingerprint>) -> Result<()> {
+        self.inner.lock().await.report_content(fingerprints).await
+    }
 }
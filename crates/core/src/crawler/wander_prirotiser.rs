@@ -20,9 +20,15 @@ use std::collections::BTreeMap;
 
 use url::Url;
 
+struct Priority {
+    weight: f64,
+    /// The lowest depth the url has been found at.
+    depth: u32,
+}
+
 #[derive(Default)]
 pub struct WanderPrioritiser {
-    url_weights: BTreeMap<Url, f64>,
+    url_weights: BTreeMap<Url, Priority>,
 }
 
 impl WanderPrioritiser {
@@ -30,26 +36,26 @@ impl WanderPrioritiser {
         Self::default()
     }
 
-    pub fn inc(&mut self, url: Url, weight: f64) {
+    pub fn inc(&mut self, url: Url, weight: f64, depth: u32) {
         self.url_weights
             .entry(url)
-            .and_modify(|w| *w += weight)
-            .or_insert(weight);
+            .and_modify(|p| {
+                p.weight += weight;
+                p.depth = p.depth.min(depth);
+            })
+            .or_insert(Priority { weight, depth });
     }
 
-    pub fn top_and_clear(&mut self, top_n: usize) -> Vec<(Url, f64)> {
-        let mut urls: Vec<_> = self
-            .url_weights
-            .iter()
-            .map(|(url, weight)| (weight, url))
-            .collect();
+    /// The `top_n` urls with the highest weights with their weights and depths.
+    pub fn top_and_clear(&mut self, top_n: usize) -> Vec<(Url, f64, u32)> {
+        let mut urls: Vec<_> = self.url_weights.iter().collect();
 
-        urls.sort_by(|(w1, _), (w2, _)| w2.total_cmp(w1));
+        urls.sort_by(|(_, p1), (_, p2)| p2.weight.total_cmp(&p1.weight));
 
         let res = urls
             .into_iter()
             .take(top_n)
-            .map(|(w, url)| (url.clone(), *w))
+            .map(|(url, p)| (url.clone(), p.weight, p.depth))
             .collect();
 
         self.url_weights = BTreeMap::new();
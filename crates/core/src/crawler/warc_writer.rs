@@ -17,15 +17,22 @@
 use std::time::Duration;
 
 use crate::{
-    config::{self, S3Config},
+    config::{self, ArtifactManifestConfig, S3Config},
     warc,
 };
 
-use super::{CrawlDatum, DatumStream, Error, Result};
+use super::{
+    robots_audit::{RobotsVersions, VERSIONS_EXTENSION},
+    CrawlDatum, DatumStream, Error, Result,
+};
 use anyhow::anyhow;
 
+/// S3 does not accept presigned urls that are valid for more than 7 days.
+const MAX_PRESIGN_EXPIRY_SECS: u32 = 7 * 24 * 60 * 60;
+
 /// The WarcWriter is responsible for storing the crawl datums
-/// as WARC files on S3.
+/// as WARC files on S3. The robots.txt versions that allowed the urls
+/// of a file are uploaded next to it in a `.robots.jsonl` file.
 pub struct WarcWriter {
     tx: tokio::sync::mpsc::Sender<WarcWriterMessage>,
 }
@@ -57,55 +64,136 @@ pub enum WarcWriterMessage {
     Finish,
 }
 
-async fn commit(writer: warc::DeduplicatedWarcWriter, s3: config::S3Config) {
-    let filename = format!(
-        "{}_{}.warc.gz",
+/// An uploaded WARC file in the artifact manifest. The manifest has one json object per line.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ManifestEntry {
+    pub key: String,
+    pub url: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The presigned urls of the files uploaded by a single writer. The manifest is
+/// re-uploaded after each file, so it always lists every file uploaded so far.
+struct Manifest {
+    config: ArtifactManifestConfig,
+    key: String,
+    entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    fn new(config: ArtifactManifestConfig, s3: &S3Config) -> Self {
+        Self {
+            config,
+            key: format!("{}/manifests/{}.jsonl", &s3.folder, uuid::Uuid::new_v4()),
+            entries: Vec::new(),
+        }
+    }
+
+    async fn add(&mut self, bucket: &s3::Bucket, key: String) -> Result<()> {
+        let expiry_secs = self.config.expiry_secs.min(MAX_PRESIGN_EXPIRY_SECS);
+        let url = bucket
+            .presign_get(&key, expiry_secs, None)
+            .map_err(|e| Error::from(anyhow!(e)))?;
+
+        self.entries.push(ManifestEntry {
+            key,
+            url,
+            expires_at: chrono::Utc::now() + chrono::Duration::seconds(expiry_secs as i64),
+        });
+
+        let mut data = String::new();
+        for entry in &self.entries {
+            data.push_str(&serde_json::to_string(entry).map_err(|e| Error::from(anyhow!(e)))?);
+            data.push('\n');
+        }
+
+        bucket
+            .put_object_with_content_type(&self.key, data.as_bytes(), "application/x-ndjson")
+            .await
+            .map_err(|e| Error::from(anyhow!(e)))?;
+
+        Ok(())
+    }
+}
+
+fn bucket(s3: &config::S3Config) -> crate::Result<Box<s3::Bucket>> {
+    Ok(s3
+        .bucket()?
+        .with_request_timeout(Duration::from_secs(30 * 60))?)
+}
+
+async fn commit(
+    writer: warc::DeduplicatedWarcWriter,
+    versions: RobotsVersions,
+    s3: config::S3Config,
+    manifest: Option<&mut Manifest>,
+) {
+    let name = format!(
+        "{}_{}",
         chrono::Utc::now().to_rfc3339(),
         uuid::Uuid::new_v4()
     );
     let data = writer.finish().unwrap();
 
-    match s3::Bucket::new(
-        &s3.bucket,
-        s3::Region::Custom {
-            region: "".to_string(),
-            endpoint: s3.endpoint.clone(),
-        },
-        s3::creds::Credentials {
-            access_key: Some(s3.access_key.clone()),
-            secret_key: Some(s3.secret_key.clone()),
-            security_token: None,
-            session_token: None,
-            expiration: None,
-        },
-    ) {
+    match bucket(&s3) {
         Ok(bucket) => {
-            let bucket = bucket
-                .with_path_style()
-                .with_request_timeout(Duration::from_secs(30 * 60))
-                .unwrap();
+            let key = format!("{}/{}.warc.gz", &s3.folder, name);
 
             if let Err(err) = bucket
-                .put_object_with_content_type(
-                    &format!("{}/{}", &s3.folder, filename),
-                    &data,
-                    "application/warc",
-                )
+                .put_object_with_content_type(&key, &data, "application/warc")
                 .await
             {
                 tracing::error!("failed to upload to bucket: {:?}", err);
+                return;
+            }
+
+            if !versions.is_empty() {
+                let versions_key = format!("{}/{}{}", &s3.folder, name, VERSIONS_EXTENSION);
+
+                let res = match versions.to_jsonl() {
+                    Ok(data) => bucket
+                        .put_object_with_content_type(
+                            &versions_key,
+                            data.as_bytes(),
+                            "application/x-ndjson",
+                        )
+                        .await
+                        .map(|_| ())
+                        .map_err(|e| anyhow!(e)),
+                    Err(err) => Err(err),
+                };
+
+                if let Err(err) = res {
+                    tracing::error!("failed to upload robots.txt versions: {:?}", err);
+                }
+            }
+
+            if let Some(manifest) = manifest {
+                if let Err(err) = manifest.add(&bucket, key).await {
+                    tracing::error!("failed to update artifact manifest: {:?}", err);
+                }
             }
         }
         Err(err) => tracing::error!("failed to connect to bucket: {:?}", err),
     }
 }
 
-async fn writer_task(mut rx: tokio::sync::mpsc::Receiver<WarcWriterMessage>, s3: S3Config) {
+async fn writer_task(
+    mut rx: tokio::sync::mpsc::Receiver<WarcWriterMessage>,
+    s3: S3Config,
+    manifest: Option<ArtifactManifestConfig>,
+) {
     let mut writer = warc::DeduplicatedWarcWriter::new();
+    let mut versions = RobotsVersions::default();
+    let mut manifest = manifest.map(|config| Manifest::new(config, &s3));
 
     while let Some(message) = rx.recv().await {
         match message {
             WarcWriterMessage::Crawl(datum) => {
+                if let Some(robots) = &datum.robots {
+                    versions.insert(&robots.version);
+                }
+
                 let w = &mut writer;
                 let (send, recv) = tokio::sync::oneshot::channel();
 
@@ -121,6 +209,15 @@ async fn writer_task(mut rx: tokio::sync::mpsc::Receiver<WarcWriterMessage>, s3:
                             },
                             metadata: warc::Metadata {
                                 fetch_time_ms: datum.fetch_time_ms,
+                                redirected_from: datum.redirected_from.map(|url| url.to_string()),
+                                last_modified: datum.last_modified,
+                                sitemap_lastmod: datum.sitemap_lastmod,
+                                robots_version: datum
+                                    .robots
+                                    .as_ref()
+                                    .map(|robots| robots.version.id),
+                                robots_rule: datum.robots.and_then(|robots| robots.rule),
+                                fetched_at: Some(datum.fetched_at),
                             },
                         };
 
@@ -132,13 +229,19 @@ async fn writer_task(mut rx: tokio::sync::mpsc::Receiver<WarcWriterMessage>, s3:
                 recv.await.unwrap();
 
                 if writer.num_bytes() > 1_000_000_000 {
-                    commit(writer, s3.clone()).await;
+                    commit(
+                        writer,
+                        std::mem::take(&mut versions),
+                        s3.clone(),
+                        manifest.as_mut(),
+                    )
+                    .await;
                     writer = warc::DeduplicatedWarcWriter::new();
                 }
             }
             WarcWriterMessage::Finish => {
                 if writer.num_writes() > 0 {
-                    commit(writer, s3.clone()).await;
+                    commit(writer, versions, s3.clone(), manifest.as_mut()).await;
                 }
                 break;
             }
@@ -147,11 +250,306 @@ async fn writer_task(mut rx: tokio::sync::mpsc::Receiver<WarcWriterMessage>, s3:
 }
 
 impl WarcWriter {
-    pub fn new(s3: S3Config) -> Self {
+    pub fn new(s3: S3Config, manifest: Option<ArtifactManifestConfig>) -> Self {
         let (tx, rx) = tokio::sync::mpsc::channel(1);
 
-        tokio::spawn(writer_task(rx, s3));
+        tokio::spawn(writer_task(rx, s3, manifest));
 
         Self { tx }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        body::Bytes,
+        extract::{Path, Query, State},
+        http::StatusCode,
+        routing::put,
+    };
+    use chrono::TimeZone;
+    use url::Url;
+
+    use crate::crawler::robots_audit::{RobotsEvidence, RobotsVersion};
+
+    use super::*;
+
+    type Objects = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+    async fn put_object(
+        State(objects): State<Objects>,
+        Path(key): Path<String>,
+        body: Bytes,
+    ) -> StatusCode {
+        objects.lock().unwrap().insert(key, body.to_vec());
+        StatusCode::OK
+    }
+
+    /// The mock only serves objects through presigned urls that have not expired.
+    async fn get_object(
+        State(objects): State<Objects>,
+        Path(key): Path<String>,
+        Query(params): Query<HashMap<String, String>>,
+    ) -> std::result::Result<Vec<u8>, StatusCode> {
+        let signed = params.get("X-Amz-Algorithm").map(String::as_str) == Some("AWS4-HMAC-SHA256")
+            && params
+                .get("X-Amz-Credential")
+                .is_some_and(|credential| credential.starts_with("access/"))
+            && params.contains_key("X-Amz-Signature");
+
+        if !signed {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let date = params
+            .get("X-Amz-Date")
+            .and_then(|date| chrono::NaiveDateTime::parse_from_str(date, "%Y%m%dT%H%M%SZ").ok())
+            .ok_or(StatusCode::FORBIDDEN)?;
+        let expires: i64 = params
+            .get("X-Amz-Expires")
+            .and_then(|expires| expires.parse().ok())
+            .ok_or(StatusCode::FORBIDDEN)?;
+
+        if chrono::Utc.from_utc_datetime(&date) + chrono::Duration::seconds(expires)
+            < chrono::Utc::now()
+        {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        objects
+            .lock()
+            .unwrap()
+            .get(&key)
+            .cloned()
+            .ok_or(StatusCode::NOT_FOUND)
+    }
+
+    async fn mock_s3() -> (Objects, S3Config) {
+        let objects = Objects::default();
+        let app = axum::Router::new()
+            .route("/*key", put(put_object).get(get_object))
+            .with_state(objects.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let s3 = S3Config {
+            bucket: "crawl".to_string(),
+            folder: "warc".to_string(),
+            access_key: Some("access".to_string()),
+            secret_key: Some("secret".to_string()),
+            anonymous: false,
+            endpoint: Some(format!("http://{addr}")),
+            region: None,
+            path_style: true,
+        };
+
+        (objects, s3)
+    }
+
+    #[tokio::test]
+    async fn presigned_manifest() {
+        let (objects, s3) = mock_s3().await;
+
+        let writer = WarcWriter::new(s3, Some(ArtifactManifestConfig { expiry_secs: 3600 }));
+        writer
+            .write(CrawlDatum {
+                url: Url::parse("https://www.example.com/").unwrap(),
+                payload_type: warc::PayloadType::Html,
+                body: "<html><body>test</body></html>".to_string(),
+                fetch_time_ms: 100,
+                redirected_from: None,
+                last_modified: None,
+                sitemap_lastmod: None,
+                robots: None,
+                fetched_at: 0,
+            })
+            .await
+            .unwrap();
+        writer.finish().await.unwrap();
+
+        let manifest = objects
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(key, _)| key.starts_with("crawl/warc/manifests/"))
+            .map(|(_, data)| String::from_utf8(data.clone()).unwrap())
+            .unwrap();
+
+        let entries: Vec<ManifestEntry> = manifest
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 1);
+
+        let entry = &entries[0];
+        assert!(entry.key.starts_with("warc/"));
+        assert!(entry.key.ends_with(".warc.gz"));
+
+        let remaining = entry.expires_at - chrono::Utc::now();
+        assert!(remaining <= chrono::Duration::seconds(3600));
+        assert!(remaining > chrono::Duration::seconds(3500));
+
+        let url = Url::parse(&entry.url).unwrap();
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["X-Amz-Expires"], "3600");
+
+        let res = reqwest::get(url.clone()).await.unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::OK);
+        let uploaded = objects.lock().unwrap()[&format!("crawl/{}", entry.key)].clone();
+        assert_eq!(res.bytes().await.unwrap().to_vec(), uploaded);
+
+        let mut unsigned = url.clone();
+        unsigned.set_query(None);
+        assert_eq!(
+            reqwest::get(unsigned).await.unwrap().status(),
+            reqwest::StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn robots_versions_next_to_warc() {
+        let (objects, s3) = mock_s3().await;
+
+        let version = Arc::new(RobotsVersion::new(
+            "www.example.com".to_string(),
+            Some("User-agent: *\nDisallow: /private".to_string()),
+            1_700_000_000,
+        ));
+
+        let writer = WarcWriter::new(s3, None);
+        for path in ["a", "b"] {
+            writer
+                .write(CrawlDatum {
+                    url: Url::parse(&format!("https://www.example.com/{path}")).unwrap(),
+                    payload_type: warc::PayloadType::Html,
+                    body: format!("<html><body>{path}</body></html>"),
+                    fetch_time_ms: 100,
+                    redirected_from: None,
+                    last_modified: None,
+                    sitemap_lastmod: None,
+                    robots: Some(RobotsEvidence {
+                        version: Arc::clone(&version),
+                        rule: None,
+                    }),
+                    fetched_at: 0,
+                })
+                .await
+                .unwrap();
+        }
+        writer.finish().await.unwrap();
+
+        let objects = objects.lock().unwrap();
+        let (warc_key, warc) = objects
+            .iter()
+            .find(|(key, _)| key.ends_with(".warc.gz"))
+            .unwrap();
+        let versions_key = warc_key.replace(".warc.gz", VERSIONS_EXTENSION);
+
+        let mut versions = RobotsVersions::default();
+        versions
+            .extend_from_jsonl(std::str::from_utf8(&objects[&versions_key]).unwrap())
+            .unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(
+            versions.get("www.example.com", version.id).map(Arc::as_ref),
+            Some(version.as_ref())
+        );
+
+        for record in warc::WarcFile::new(warc.clone()).records() {
+            assert_eq!(record.unwrap().metadata.robots_version, Some(version.id));
+        }
+    }
+
+    /// The host, path and authorization header of each request.
+    type Requests = Arc<Mutex<Vec<(String, String, Option<String>)>>>;
+
+    async fn record_request(
+        State(requests): State<Requests>,
+        headers: axum::http::HeaderMap,
+        uri: axum::http::Uri,
+        _body: Bytes,
+    ) -> StatusCode {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &axum::http::HeaderValue| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        requests.lock().unwrap().push((
+            header(axum::http::header::HOST).unwrap_or_default(),
+            uri.path().to_string(),
+            header(axum::http::header::AUTHORIZATION),
+        ));
+
+        StatusCode::OK
+    }
+
+    #[tokio::test]
+    async fn custom_endpoint_path_style() {
+        let requests = Requests::default();
+        let app = axum::Router::new()
+            .fallback(record_request)
+            .with_state(requests.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        for anonymous in [false, true] {
+            requests.lock().unwrap().clear();
+
+            let s3 = S3Config {
+                bucket: "crawl".to_string(),
+                folder: "warc".to_string(),
+                access_key: Some("access".to_string()),
+                secret_key: Some("secret".to_string()),
+                anonymous,
+                endpoint: Some(format!("http://{addr}")),
+                region: Some("eu-central-1".to_string()),
+                path_style: true,
+            };
+
+            let writer = WarcWriter::new(s3, None);
+            writer
+                .write(CrawlDatum {
+                    url: Url::parse("https://www.example.com/").unwrap(),
+                    payload_type: warc::PayloadType::Html,
+                    body: "<html><body>test</body></html>".to_string(),
+                    fetch_time_ms: 100,
+                    redirected_from: None,
+                    last_modified: None,
+                    sitemap_lastmod: None,
+                    robots: None,
+                    fetched_at: 0,
+                })
+                .await
+                .unwrap();
+            writer.finish().await.unwrap();
+
+            let requests = requests.lock().unwrap();
+            assert!(!requests.is_empty());
+
+            for (host, path, authorization) in requests.iter() {
+                assert_eq!(host, &addr.to_string());
+                assert!(path.starts_with("/crawl/warc/"), "{path}");
+
+                if anonymous {
+                    assert!(authorization.is_none());
+                } else {
+                    assert!(authorization
+                        .as_deref()
+                        .unwrap()
+                        .contains("/eu-central-1/s3/aws4_request"));
+                }
+            }
+        }
+    }
+}
@@ -14,7 +14,8 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use anyhow::anyhow;
-use hashbrown::HashSet;
+use chrono::{DateTime, NaiveDate, Utc};
+use hashbrown::{HashMap, HashSet};
 use quick_xml::events::Event;
 use rand::seq::SliceRandom;
 
@@ -31,16 +32,17 @@ use crate::{
     config::CrawlerConfig,
     crawler::MAX_URL_LEN_BYTES,
     distributed::{retry_strategy::ExponentialBackoff, sonic},
-    entrypoint::crawler::router::{NewJob, RouterService},
-    warc,
+    entrypoint::crawler::router::{AdmitUrls, NewJob, ReportContent, ReportFailed, RouterService},
+    simhash, warc,
+    webgraph::Node,
     webpage::{url_ext::UrlExt, Html},
 };
 
 use super::{
-    encoded_body, reqwest_client, robots_txt::RobotsTxtManager,
-    wander_prirotiser::WanderPrioritiser, CrawlDatum, DatumStream, Domain, Error, Result,
-    RetrieableUrl, Site, WarcWriter, WeightedUrl, WorkerJob, MAX_CONTENT_LENGTH,
-    MAX_OUTGOING_URLS_PER_PAGE,
+    encoded_body, politeness::Politeness, reqwest_client, robots_audit::RobotsEvidence,
+    robots_txt::RobotsTxtManager, wander_prirotiser::WanderPrioritiser, ContentFingerprint,
+    CrawlDatum, DatumStream, Domain, Error, FailedUrl, Job, Result, RetrieableUrl, Site,
+    WarcWriter, WeightedUrl, WorkerJob, MAX_CONTENT_LENGTH, MAX_OUTGOING_URLS_PER_PAGE,
 };
 
 const IGNORED_EXTENSIONS: [&str; 27] = [
@@ -51,7 +53,7 @@ const IGNORED_EXTENSIONS: [&str; 27] = [
 
 enum UrlVisit {
     Skip,
-    CanCrawl,
+    CanCrawl(RobotsEvidence),
 }
 
 struct ProcessedUrl {
@@ -82,17 +84,29 @@ impl WorkerThread {
     }
 
     async fn router_conn(&self) -> Result<sonic::service::Connection<RouterService>> {
-        let retry = ExponentialBackoff::from_millis(1_000).with_limit(Duration::from_secs(10));
+        router_conn(&self.router_hosts).await
+    }
 
-        let router = *self.router_hosts.choose(&mut rand::thread_rng()).unwrap();
+    /// The connection used to get the job may have timed out while the job was running,
+    /// so the failed urls are sent on a new connection.
+    async fn report_failed(&self, failed: Vec<FailedUrl>) -> Result<()> {
+        let mut conn = self.router_conn().await?;
 
-        sonic::service::Connection::create_with_timeout_retry(
-            router,
-            Duration::from_secs(90),
-            retry,
-        )
-        .await
-        .map_err(|e| Error::from(anyhow!(e)))
+        conn.send_with_timeout(ReportFailed(failed), Duration::from_secs(90))
+            .await
+            .map_err(|e| Error::from(anyhow!(e)))?;
+
+        Ok(())
+    }
+
+    async fn report_content(&self, fingerprints: Vec<ContentFingerprint>) -> Result<()> {
+        let mut conn = self.router_conn().await?;
+
+        conn.send_with_timeout(ReportContent(fingerprints), Duration::from_secs(90))
+            .await
+            .map_err(|e| Error::from(anyhow!(e)))?;
+
+        Ok(())
     }
 
     pub async fn run(self) {
@@ -109,8 +123,21 @@ impl WorkerThread {
                         self.client.clone(),
                         self.config.clone(),
                         self.writer.clone(),
-                    );
-                    executor.run().await;
+                    )
+                    .with_router_hosts(self.router_hosts.clone());
+                    let report = executor.run().await;
+
+                    if !report.failed.is_empty() {
+                        if let Err(err) = self.report_failed(report.failed).await {
+                            tracing::error!("failed to report failed urls: {}", err);
+                        }
+                    }
+
+                    if !report.content.is_empty() {
+                        if let Err(err) = self.report_content(report.content).await {
+                            tracing::error!("failed to report content: {}", err);
+                        }
+                    }
                 }
                 Ok(None) => {
                     return;
@@ -124,23 +151,53 @@ impl WorkerThread {
     }
 }
 
+async fn router_conn(
+    router_hosts: &[SocketAddr],
+) -> Result<sonic::service::Connection<RouterService>> {
+    let retry = ExponentialBackoff::from_millis(1_000).with_limit(Duration::from_secs(10));
+
+    let router = *router_hosts
+        .choose(&mut rand::thread_rng())
+        .ok_or_else(|| anyhow!("no router hosts"))?;
+
+    sonic::service::Connection::create_with_timeout_retry(router, Duration::from_secs(90), retry)
+        .await
+        .map_err(|e| Error::from(anyhow!(e)))
+}
+
+pub struct JobReport {
+    /// The scheduled urls that failed with a transient error.
+    pub failed: Vec<FailedUrl>,
+    pub content: Vec<ContentFingerprint>,
+}
+
 pub struct JobExecutor<S: DatumStream> {
     writer: Arc<S>,
     client: reqwest::Client,
-    politeness_factor: f32,
+    politeness: Politeness,
     robotstxt: RobotsTxtManager,
     crawled_urls: HashSet<Url>,
     crawled_sitemaps: HashSet<Site>,
     sitemap_urls: HashSet<Url>,
-    min_crawl_delay: Duration,
-    max_crawl_delay: Duration,
+    sitemap_lastmod: HashMap<Url, u64>,
     max_url_slowdown_retry: u8,
-    max_politeness_factor: f32,
     wander_prioritiser: WanderPrioritiser,
     wandered_urls: u64,
+    failed: Vec<FailedUrl>,
+    content: Vec<ContentFingerprint>,
+    router_hosts: Vec<SocketAddr>,
     job: WorkerJob,
 }
 
+/// Errors that may go away if the url is fetched again later.
+fn is_transient(err: &Error) -> bool {
+    match err {
+        Error::FetchFailed { status_code, .. } => status_code.is_server_error(),
+        Error::Anyhow(err) => err.downcast_ref::<reqwest::Error>().is_some(),
+        _ => false,
+    }
+}
+
 impl<S: DatumStream> JobExecutor<S> {
     pub fn new(
         job: WorkerJob,
@@ -150,23 +207,31 @@ impl<S: DatumStream> JobExecutor<S> {
     ) -> Self {
         Self {
             writer,
-            politeness_factor: config.politeness_factor,
+            politeness: Politeness::new(&config, job.rate_overrides.clone()),
             robotstxt: RobotsTxtManager::new(&config),
             client,
             crawled_urls: HashSet::new(),
             crawled_sitemaps: HashSet::new(),
             wandered_urls: 0,
             sitemap_urls: HashSet::new(),
-            min_crawl_delay: Duration::from_millis(config.min_crawl_delay_ms),
-            max_crawl_delay: Duration::from_millis(config.max_crawl_delay_ms),
+            sitemap_lastmod: HashMap::new(),
             max_url_slowdown_retry: config.max_url_slowdown_retry,
-            max_politeness_factor: config.max_politeness_factor,
             wander_prioritiser: WanderPrioritiser::new(),
+            failed: Vec::new(),
+            content: Vec::new(),
+            router_hosts: Vec::new(),
             job,
         }
     }
 
-    pub async fn run(mut self) {
+    /// The routers through which the coordinator of the job is asked to admit the urls
+    /// the job wanders to.
+    pub fn with_router_hosts(mut self, router_hosts: Vec<SocketAddr>) -> Self {
+        self.router_hosts = router_hosts;
+        self
+    }
+
+    pub async fn run(mut self) -> JobReport {
         tracing::info!("Processing job: {:?}", self.job.domain);
         self.scheduled_urls().await;
 
@@ -175,6 +240,11 @@ impl<S: DatumStream> JobExecutor<S> {
         {
             self.wander().await;
         }
+
+        JobReport {
+            failed: self.failed,
+            content: self.content,
+        }
     }
 
     async fn scheduled_urls(&mut self) {
@@ -183,35 +253,46 @@ impl<S: DatumStream> JobExecutor<S> {
     }
 
     async fn wander(&mut self) {
-        let mut urls: Vec<(Url, f64)> = self
+        let mut urls: Vec<(Url, f64, u32)> = self
             .wander_prioritiser
             .top_and_clear(self.job.wandering_urls.saturating_sub(self.wandered_urls) as usize)
             .into_iter()
-            .chain(self.sitemap_urls.drain().map(|url| (url.clone(), 0.0)))
-            .map(|(mut url, score)| {
+            // the sitemaps are linked from the robots.txt of the seeds
+            .chain(self.sitemap_urls.drain().map(|url| (url.clone(), 0.0, 1)))
+            .map(|(mut url, score, depth)| {
                 url.normalize();
-                (url, score)
+                (url, score, depth)
             })
-            .filter(|(url, _)| !self.crawled_urls.contains(url))
-            .filter(|(url, _)| self.job.domain == Domain::from(url))
-            .filter(|(_, score)| score.is_finite())
+            .filter(|(url, _, _)| !self.crawled_urls.contains(url))
+            .filter(|(url, _, _)| self.job.domain == Domain::from(url))
+            .filter(|(_, score, _)| score.is_finite())
+            .filter(|(_, _, depth)| self.job.max_depth.map_or(true, |max| *depth <= max))
             .collect();
 
-        urls.sort_by(|(a, _), (b, _)| a.cmp(b));
-        urls.dedup_by(|(a, _), (b, _)| a == b);
+        urls.sort_by(|(a, _, a_depth), (b, _, b_depth)| a.cmp(b).then(a_depth.cmp(b_depth)));
+        urls.dedup_by(|(a, _, _), (b, _, _)| a == b);
 
-        urls.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        urls.sort_by(|(_, a, _), (_, b, _)| b.total_cmp(a));
 
         let urls: VecDeque<_> = urls
             .into_iter()
-            .map(|(url, _)| url)
-            .map(|mut url| {
+            .map(|(mut url, _, depth)| {
                 url.normalize();
-                url
+                (url, depth)
             })
-            .filter(|url| !self.crawled_urls.contains(url))
+            .filter(|(url, _)| !self.crawled_urls.contains(url))
             .take(self.job.wandering_urls.saturating_sub(self.wandered_urls) as usize)
-            .map(|url| WeightedUrl { url, weight: 0.0 })
+            .map(|(url, depth)| WeightedUrl {
+                url,
+                weight: 0.0,
+                depth,
+            })
+            .collect();
+
+        let urls: VecDeque<_> = self
+            .admit(urls)
+            .await
+            .into_iter()
             .map(RetrieableUrl::from)
             .collect();
 
@@ -220,6 +301,41 @@ impl<S: DatumStream> JobExecutor<S> {
         self.process_urls(urls, false).await;
     }
 
+    /// Ask the coordinator of the job which of the urls may be crawled. No urls are
+    /// admitted if the coordinator can't be asked, as they may have been blocked.
+    async fn admit(&self, urls: VecDeque<WeightedUrl>) -> VecDeque<WeightedUrl> {
+        let Some(coordinator) = self.job.coordinator else {
+            return urls;
+        };
+
+        if urls.is_empty() {
+            return urls;
+        }
+
+        let job = Job {
+            domain: self.job.domain.clone(),
+            urls,
+            wandering_urls: 0,
+        };
+
+        let res = match router_conn(&self.router_hosts).await {
+            Ok(mut conn) => conn
+                .send_with_timeout(AdmitUrls { coordinator, job }, Duration::from_secs(90))
+                .await
+                .map_err(|e| Error::from(anyhow!(e))),
+            Err(err) => Err(err),
+        };
+
+        match res {
+            Ok(Some(job)) => job.urls,
+            Ok(None) => VecDeque::new(),
+            Err(err) => {
+                tracing::error!("failed to admit wandered urls: {}", err);
+                VecDeque::new()
+            }
+        }
+    }
+
     async fn verify_url(&mut self, retryable_url: &RetrieableUrl) -> UrlVisit {
         if Domain::from(retryable_url.url()) != self.job.domain {
             return UrlVisit::Skip;
@@ -239,28 +355,28 @@ impl<S: DatumStream> JobExecutor<S> {
             return UrlVisit::Skip;
         }
 
-        if !self.robotstxt.is_allowed(retryable_url.url()).await {
-            return UrlVisit::Skip;
-        }
-
         if let Some(port) = retryable_url.url().port() {
             if port != 80 && port != 443 {
                 return UrlVisit::Skip;
             }
         }
 
-        UrlVisit::CanCrawl
+        match self.robotstxt.authorize(retryable_url.url()).await {
+            Some(evidence) => UrlVisit::CanCrawl(evidence),
+            None => UrlVisit::Skip,
+        }
     }
 
     async fn process_urls(&mut self, mut urls: VecDeque<RetrieableUrl>, fetch_sitemap: bool) {
         while let Some(retryable_url) = urls.pop_front() {
-            if let UrlVisit::Skip = self.verify_url(&retryable_url).await {
-                continue;
-            }
+            let robots = match self.verify_url(&retryable_url).await {
+                UrlVisit::CanCrawl(robots) => robots,
+                UrlVisit::Skip => continue,
+            };
 
             if let Some(delay) = self.robotstxt.crawl_delay(retryable_url.url()).await {
-                if delay > self.min_crawl_delay {
-                    self.min_crawl_delay = delay.min(self.max_crawl_delay);
+                if let Some(host) = retryable_url.url().host_str() {
+                    self.politeness.set_robots_crawl_delay(host, delay);
                 }
             }
 
@@ -277,16 +393,28 @@ impl<S: DatumStream> JobExecutor<S> {
                 let sitemaps = self.robotstxt.sitemaps(retryable_url.url()).await;
 
                 for sitemap in sitemaps {
-                    self.sitemap_urls
-                        .extend(self.urls_from_sitemap(sitemap, 5).await);
+                    for (mut url, lastmod) in self.urls_from_sitemap(sitemap, 5).await {
+                        url.normalize();
+
+                        if let Some(lastmod) = lastmod {
+                            self.sitemap_lastmod.insert(url.clone(), lastmod);
+                        }
+
+                        self.sitemap_urls.insert(url);
+                    }
                 }
             }
 
-            let res = self.process_url(retryable_url.url().clone()).await;
+            let res = self.process_url(retryable_url.url().clone(), robots).await;
 
             match res {
                 Ok(res) => {
                     let weight = retryable_url.weighted_url.weight;
+                    let depth = retryable_url.weighted_url.depth + 1;
+
+                    if self.job.max_depth.is_some_and(|max| depth > max) {
+                        continue;
+                    }
 
                     for new_url in res.new_urls {
                         if new_url.host_str().is_none() {
@@ -297,7 +425,7 @@ impl<S: DatumStream> JobExecutor<S> {
                             continue;
                         }
 
-                        self.wander_prioritiser.inc(new_url, weight);
+                        self.wander_prioritiser.inc(new_url, weight, depth);
                     }
                 }
                 Err(Error::FetchFailed {
@@ -312,7 +440,7 @@ impl<S: DatumStream> JobExecutor<S> {
                             .map(Duration::from_secs);
 
                         if let Some(retry_after) = retry_after {
-                            if retry_after > self.max_crawl_delay {
+                            if retry_after > self.politeness.max_crawl_delay() {
                                 return; // don't crawl anymore from this site
                             }
 
@@ -320,26 +448,27 @@ impl<S: DatumStream> JobExecutor<S> {
                         }
                     }
 
-                    self.increase_politeness();
+                    self.politeness.increase();
                     let mut retryable_url = retryable_url;
                     retryable_url.retries += 1;
                     urls.push_back(retryable_url);
                 }
-                Err(_) => {}
+                Err(err) => {
+                    // wandered urls are not known to the coordinator, so only scheduled
+                    // urls are retried.
+                    if fetch_sitemap && is_transient(&err) {
+                        self.failed.push(FailedUrl {
+                            url: retryable_url.url().into(),
+                            weight: retryable_url.weighted_url.weight,
+                            depth: retryable_url.weighted_url.depth,
+                            reason: err.to_string(),
+                        });
+                    }
+                }
             }
         }
     }
 
-    fn increase_politeness(&mut self) {
-        self.politeness_factor *= 2.0;
-
-        if self.politeness_factor > self.max_politeness_factor {
-            self.politeness_factor = self.max_politeness_factor;
-        }
-
-        tracing::warn!("politeness factor increased to {}", self.politeness_factor);
-    }
-
     fn new_urls(&self, html: &Html) -> Vec<Url> {
         html.anchor_links()
             .into_iter()
@@ -358,12 +487,22 @@ impl<S: DatumStream> JobExecutor<S> {
             .collect()
     }
 
-    async fn process_url(&mut self, url: Url) -> Result<ProcessedUrl> {
-        let datum = self.crawl_url(url.clone()).await?;
+    async fn process_url(&mut self, url: Url, robots: RobotsEvidence) -> Result<ProcessedUrl> {
+        let mut datum = self.crawl_url(url.clone()).await?;
+        datum.robots = Some(robots);
+
         self.save_datum(datum.clone()).await;
 
         match Html::parse(&datum.body, datum.url.as_str()) {
             Ok(html) => {
+                self.content.push(ContentFingerprint {
+                    url: (&datum.url).into(),
+                    simhash: html
+                        .clean_text()
+                        .map(|text| simhash::hash(text.as_str()))
+                        .unwrap_or_default(),
+                });
+
                 let root_domain = datum.url.root_domain();
                 let new_urls = self
                     .new_urls(&html)
@@ -399,7 +538,7 @@ impl<S: DatumStream> JobExecutor<S> {
             match self.fetch(https).await {
                 Ok(res) => Ok(res),
                 Err(_) => {
-                    tokio::time::sleep(self.delay_duration()).await;
+                    tokio::time::sleep(self.politeness.delay(&url, Duration::ZERO)).await;
                     self.fetch(url.clone()).await
                 }
             }
@@ -408,33 +547,6 @@ impl<S: DatumStream> JobExecutor<S> {
         }
     }
 
-    fn delay_duration(&self) -> Duration {
-        let mut delay = self.min_crawl_delay;
-        delay = delay.mul_f32(self.politeness_factor);
-
-        if delay > self.max_crawl_delay {
-            delay = self.max_crawl_delay;
-        }
-
-        delay
-    }
-
-    async fn politeness_delay(&self, fetch_time: Duration) {
-        let mut delay = fetch_time;
-
-        if delay < self.min_crawl_delay {
-            delay = self.min_crawl_delay;
-        }
-
-        delay = delay.mul_f32(self.politeness_factor);
-
-        if delay > self.max_crawl_delay {
-            delay = self.max_crawl_delay;
-        }
-
-        tokio::time::sleep(delay).await;
-    }
-
     fn check_headers(&self, res: &reqwest::Response) -> Result<warc::PayloadType> {
         // check if content length is too large
         if let Some(content_length) = res
@@ -478,15 +590,20 @@ impl<S: DatumStream> JobExecutor<S> {
 
             let location = location.to_str().map_err(|_| Error::InvalidRedirect)?;
 
-            let url = Url::parse(location)
+            let target = Url::parse(location)
                 .or_else(|_| url.join(location))
                 .map_err(|_| Error::InvalidRedirect)?;
 
             Ok(Some(CrawlDatum {
-                url,
+                redirected_from: Some(url.clone()),
+                url: target,
                 payload_type,
                 body: String::new(),
                 fetch_time_ms: fetch_time.as_millis() as u64,
+                last_modified: None,
+                sitemap_lastmod: None,
+                robots: None,
+                fetched_at: Utc::now().timestamp().max(0) as u64,
             }))
         } else {
             Ok(None)
@@ -504,7 +621,7 @@ impl<S: DatumStream> JobExecutor<S> {
         let start = Instant::now();
         let res = self.fetch_with_https_priority(url.clone()).await;
         let fetch_time = start.elapsed();
-        self.politeness_delay(fetch_time).await;
+        tokio::time::sleep(self.politeness.delay(&url, fetch_time)).await;
 
         // we want to delay before returning the error
         let res = res?;
@@ -530,6 +647,23 @@ impl<S: DatumStream> JobExecutor<S> {
 
         self.crawled_urls.insert(res_url.clone());
 
+        let redirected_from = if Node::from(&res_url).id() != Node::from(&url).id() {
+            Some(url)
+        } else {
+            None
+        };
+
+        let sitemap_lastmod = self
+            .sitemap_lastmod
+            .get(&res_url)
+            .or_else(|| {
+                redirected_from
+                    .as_ref()
+                    .and_then(|url| self.sitemap_lastmod.get(url))
+            })
+            .copied();
+
+        let last_modified = last_modified(res.headers(), Utc::now());
         let body = encoded_body(res).await?;
 
         Ok(CrawlDatum {
@@ -537,10 +671,15 @@ impl<S: DatumStream> JobExecutor<S> {
             body,
             payload_type,
             fetch_time_ms: fetch_time.as_millis() as u64,
+            redirected_from,
+            last_modified,
+            sitemap_lastmod,
+            robots: None,
+            fetched_at: Utc::now().timestamp().max(0) as u64,
         })
     }
 
-    async fn urls_from_sitemap(&self, sitemap: Url, max_depth: usize) -> Vec<Url> {
+    async fn urls_from_sitemap(&self, sitemap: Url, max_depth: usize) -> Vec<(Url, Option<u64>)> {
         let mut stack = vec![(sitemap, 0)];
         let mut urls = vec![];
 
@@ -574,8 +713,8 @@ impl<S: DatumStream> JobExecutor<S> {
 
             for entry in entries {
                 match entry {
-                    SitemapEntry::Url(url) => {
-                        urls.push(url);
+                    SitemapEntry::Url { url, lastmod } => {
+                        urls.push((url, lastmod));
                     }
                     SitemapEntry::Sitemap(url) => {
                         stack.push((url, depth + 1));
@@ -590,10 +729,55 @@ impl<S: DatumStream> JobExecutor<S> {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum SitemapEntry {
-    Url(Url),
+    Url { url: Url, lastmod: Option<u64> },
     Sitemap(Url),
 }
 
+/// The `Last-Modified` header of a response in seconds since the unix epoch.
+///
+/// A server should never send a `Last-Modified` later than the `Date` of the response,
+/// so it is capped by the `Date` header, or the current time if the response has no date.
+fn last_modified(headers: &reqwest::header::HeaderMap, now: DateTime<Utc>) -> Option<u64> {
+    let http_date = |name: reqwest::header::HeaderName| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value.trim()).ok())
+            .map(|date| date.with_timezone(&Utc))
+    };
+
+    let last_modified = http_date(reqwest::header::LAST_MODIFIED)?;
+    let date = http_date(reqwest::header::DATE).unwrap_or(now).min(now);
+
+    let timestamp = last_modified.min(date).timestamp();
+
+    if timestamp > 0 {
+        Some(timestamp as u64)
+    } else {
+        None
+    }
+}
+
+/// Parse the `<lastmod>` of a sitemap entry. The sitemap protocol uses the W3C datetime
+/// format, which is either a full timestamp or just a date.
+fn parse_lastmod(s: &str) -> Option<u64> {
+    let s = s.trim();
+
+    let timestamp = DateTime::parse_from_rfc3339(s)
+        .map(|date| date.timestamp())
+        .or_else(|_| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp())
+        })
+        .ok()?;
+
+    if timestamp > 0 {
+        Some(timestamp as u64)
+    } else {
+        None
+    }
+}
+
 fn parse_sitemap(s: &str) -> Vec<SitemapEntry> {
     let mut reader = quick_xml::Reader::from_str(s);
 
@@ -602,6 +786,10 @@ fn parse_sitemap(s: &str) -> Vec<SitemapEntry> {
     let mut in_sitemap = false;
     let mut in_url = false;
     let mut in_loc = false;
+    let mut in_lastmod = false;
+
+    let mut url_loc = None;
+    let mut url_lastmod = None;
 
     loop {
         match reader.read_event() {
@@ -610,8 +798,12 @@ fn parse_sitemap(s: &str) -> Vec<SitemapEntry> {
                     in_sitemap = true;
                 } else if e.name().as_ref() == b"url" {
                     in_url = true;
+                    url_loc = None;
+                    url_lastmod = None;
                 } else if e.name().as_ref() == b"loc" {
                     in_loc = true;
+                } else if e.name().as_ref() == b"lastmod" {
+                    in_lastmod = true;
                 }
             }
             Ok(Event::End(ref e)) => {
@@ -619,8 +811,17 @@ fn parse_sitemap(s: &str) -> Vec<SitemapEntry> {
                     in_sitemap = false;
                 } else if e.name().as_ref() == b"url" {
                     in_url = false;
+
+                    if let Some(url) = url_loc.take() {
+                        res.push(SitemapEntry::Url {
+                            url,
+                            lastmod: url_lastmod.take(),
+                        });
+                    }
                 } else if e.name().as_ref() == b"loc" {
                     in_loc = false;
+                } else if e.name().as_ref() == b"lastmod" {
+                    in_lastmod = false;
                 }
             }
             Ok(Event::Text(e)) => {
@@ -629,9 +830,11 @@ fn parse_sitemap(s: &str) -> Vec<SitemapEntry> {
                         res.push(SitemapEntry::Sitemap(url));
                     }
                 } else if in_url && in_loc {
-                    if let Ok(url) = Url::parse(&e.unescape().unwrap()) {
-                        res.push(SitemapEntry::Url(url));
+                    if let Ok(url) = Url::parse(e.unescape().unwrap().trim()) {
+                        url_loc = Some(url);
                     }
+                } else if in_url && in_lastmod {
+                    url_lastmod = parse_lastmod(&e.unescape().unwrap());
                 }
             }
             Ok(Event::Eof) => break,
@@ -648,6 +851,21 @@ fn parse_sitemap(s: &str) -> Vec<SitemapEntry> {
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn transient_errors() {
+        let failed = |status_code: u16| super::Error::FetchFailed {
+            status_code: reqwest::StatusCode::from_u16(status_code).unwrap(),
+            headers: reqwest::header::HeaderMap::new(),
+        };
+
+        assert!(super::is_transient(&failed(503)));
+        assert!(!super::is_transient(&failed(404)));
+        assert!(!super::is_transient(&super::Error::InvalidHtml));
+        assert!(!super::is_transient(&super::Error::from(anyhow::anyhow!(
+            "url already crawled"
+        ))));
+    }
+
     #[test]
     fn parse_sitemap() {
         let dr = r#"<sitemapindex>
@@ -712,30 +930,68 @@ mod tests {
         </urlset>"#;
 
         let entries = super::parse_sitemap(dr);
+        let url = |url: &str| super::SitemapEntry::Url {
+            url: url.parse().unwrap(),
+            lastmod: Some(1_697_607_604),
+        };
         assert_eq!(
             entries,
             vec![
-                super::SitemapEntry::Url(
-                    "https://www.dr.dk/drtv/serie/sleepover_6382"
-                        .parse()
-                        .unwrap()
-                ),
-                super::SitemapEntry::Url(
-                    "https://www.dr.dk/drtv/saeson/sleepover_9673"
-                        .parse()
-                        .unwrap()
-                ),
-                super::SitemapEntry::Url(
-                    "https://www.dr.dk/drtv/episode/sleepover_-zoologisk-museum_52239"
-                        .parse()
-                        .unwrap()
-                ),
-                super::SitemapEntry::Url(
-                    "https://www.dr.dk/drtv/episode/sleepover_-koebenhavns-raadhus_52252"
-                        .parse()
-                        .unwrap()
-                ),
+                url("https://www.dr.dk/drtv/serie/sleepover_6382"),
+                url("https://www.dr.dk/drtv/saeson/sleepover_9673"),
+                url("https://www.dr.dk/drtv/episode/sleepover_-zoologisk-museum_52239"),
+                url("https://www.dr.dk/drtv/episode/sleepover_-koebenhavns-raadhus_52252"),
+            ]
+        );
+
+        let entries = super::parse_sitemap(
+            r#"<urlset>
+            <url><loc>https://example.com/a</loc><lastmod>2024-01-02</lastmod></url>
+            <url><loc>https://example.com/b</loc></url>
+            </urlset>"#,
+        );
+        assert_eq!(
+            entries,
+            vec![
+                super::SitemapEntry::Url {
+                    url: "https://example.com/a".parse().unwrap(),
+                    lastmod: Some(1_704_153_600),
+                },
+                super::SitemapEntry::Url {
+                    url: "https://example.com/b".parse().unwrap(),
+                    lastmod: None,
+                },
             ]
         );
     }
+
+    #[test]
+    fn last_modified_header() {
+        use chrono::{DateTime, Utc};
+        use reqwest::header::{HeaderMap, HeaderValue, DATE, LAST_MODIFIED};
+
+        let now: DateTime<Utc> = "2024-01-01T00:00:00Z".parse().unwrap();
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(super::last_modified(&headers, now), None);
+
+        headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(super::last_modified(&headers, now), Some(1_445_412_480));
+
+        // a last modified after the date of the response is capped
+        headers.insert(
+            DATE,
+            HeaderValue::from_static("Wed, 21 Oct 2015 00:00:00 GMT"),
+        );
+        assert_eq!(
+            super::last_modified(&headers, now),
+            Some(1_445_412_480 - 7 * 3600 - 28 * 60)
+        );
+
+        headers.insert(LAST_MODIFIED, HeaderValue::from_static("not a date"));
+        assert_eq!(super::last_modified(&headers, now), None);
+    }
 }
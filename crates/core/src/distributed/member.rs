@@ -42,6 +42,22 @@ impl ShardId {
     pub fn as_u64(&self) -> u64 {
         self.0
     }
+
+    /// The shard among `shards` that a key is assigned to, or `None` if there are no shards.
+    ///
+    /// The key is hashed with [`bloom::fast_stable_hash_64`], which is xxh3 with a fixed secret,
+    /// so the assignment does not change between runs or machines. The hash picks a position
+    /// in the list and not a shard id, so the ids need not be contiguous, but `shards` must
+    /// be sorted and without duplicates for every client to agree on the assignment.
+    pub fn for_key(key: &[u8], shards: &[ShardId]) -> Option<Self> {
+        if shards.is_empty() {
+            return None;
+        }
+
+        let idx = bloom::fast_stable_hash_64(key) % shards.len() as u64;
+
+        Some(shards[idx as usize])
+    }
 }
 
 impl From<u64> for ShardId {
@@ -0,0 +1,362 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Circuit breakers that stop sending requests to a backend that keeps failing.
+//!
+//! A breaker is closed while the backend answers. After `failure_threshold` requests in a
+//! row have failed, e.g. because they timed out, the breaker opens and requests to the
+//! backend fail right away without waiting for it, so the replica selectors pick the
+//! other replicas instead. Once the cool-down has passed, the breaker is half-open and lets
+//! a single request through as a probe. The breaker closes if the probe succeeds and opens
+//! again for another cool-down if it fails.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::config::CircuitBreakerConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Inner {
+    consecutive_failures: usize,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: usize,
+    cool_down: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &CircuitBreakerConfig) -> Self {
+        Self {
+            failure_threshold: config.failure_threshold.max(1),
+            cool_down: Duration::from_millis(config.cool_down_ms),
+            inner: Mutex::new(Inner {
+                consecutive_failures: 0,
+                opened_at: None,
+                probe_in_flight: false,
+            }),
+        }
+    }
+
+    fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn state_of(&self, inner: &Inner) -> State {
+        match inner.opened_at {
+            None => State::Closed,
+            Some(opened_at) if opened_at.elapsed() >= self.cool_down => State::HalfOpen,
+            Some(_) => State::Open,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state_of(&self.inner())
+    }
+
+    /// Whether a request would currently be let through.
+    pub fn is_available(&self) -> bool {
+        let inner = self.inner();
+
+        match self.state_of(&inner) {
+            State::Closed => true,
+            State::Open => false,
+            State::HalfOpen => !inner.probe_in_flight,
+        }
+    }
+
+    /// Ask to send a request. If the request is let through, its outcome is reported
+    /// with [`Permit::record`]. A permit that is dropped without an outcome, e.g. because
+    /// the request was cancelled by a timeout, lets the next request probe the backend.
+    pub fn try_acquire(&self) -> Option<Permit<'_>> {
+        let mut inner = self.inner();
+
+        let probe = match self.state_of(&inner) {
+            State::Closed => false,
+            State::Open => return None,
+            State::HalfOpen => {
+                if inner.probe_in_flight {
+                    return None;
+                }
+
+                inner.probe_in_flight = true;
+                true
+            }
+        };
+
+        Some(Permit {
+            breaker: self,
+            probe,
+            recorded: false,
+        })
+    }
+
+    fn record(&self, success: bool) {
+        let mut inner = self.inner();
+        inner.probe_in_flight = false;
+
+        if success {
+            inner.consecutive_failures = 0;
+            inner.opened_at = None;
+            return;
+        }
+
+        inner.consecutive_failures += 1;
+
+        let reopen = inner.opened_at.is_some();
+        if reopen || inner.consecutive_failures >= self.failure_threshold {
+            if !reopen {
+                tracing::warn!(
+                    "opening circuit breaker after {} failed requests",
+                    inner.consecutive_failures
+                );
+            }
+
+            inner.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+/// A request that was let through by a [`CircuitBreaker`].
+#[must_use]
+pub struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    recorded: bool,
+}
+
+impl Permit<'_> {
+    pub fn record(mut self, success: bool) {
+        self.recorded = true;
+        self.breaker.record(success);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.recorded {
+            self.breaker.inner().probe_in_flight = false;
+        }
+    }
+}
+
+/// The breakers of the backends by their address. The clients of the backends are
+/// recreated when the members of the cluster are refreshed, so the breakers are kept
+/// here to survive the refreshes.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakers {
+    config: CircuitBreakerConfig,
+    breakers: Arc<Mutex<HashMap<SocketAddr, Arc<CircuitBreaker>>>>,
+}
+
+impl Default for CircuitBreakers {
+    fn default() -> Self {
+        Self::new(CircuitBreakerConfig::default())
+    }
+}
+
+impl CircuitBreakers {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            breakers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The timeout of a single request to a backend.
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.request_timeout_ms)
+    }
+
+    pub fn get(&self, addr: SocketAddr) -> Arc<CircuitBreaker> {
+        let mut breakers = self.breakers.lock().unwrap_or_else(|e| e.into_inner());
+
+        Arc::clone(
+            breakers
+                .entry(addr)
+                .or_insert_with(|| Arc::new(CircuitBreaker::new(&self.config))),
+        )
+    }
+
+    /// The client of the backend with its breaker and the request timeout.
+    pub fn client<S>(&self, addr: SocketAddr) -> super::replication::RemoteClient<S>
+    where
+        S: super::service::Service,
+    {
+        super::replication::RemoteClient::new(addr)
+            .with_timeout(self.request_timeout())
+            .with_circuit_breaker(self.get(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::distributed::sonic::{
+        self,
+        replication::{RandomReplicaSelector, RemoteClient, ReplicaSelector},
+        service::{sonic_service, Message},
+    };
+
+    fn config(cool_down: Duration) -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            failure_threshold: 3,
+            cool_down_ms: cool_down.as_millis() as u64,
+            request_timeout_ms: 1_000,
+        }
+    }
+
+    #[test]
+    fn opens_and_recovers() {
+        let breaker = CircuitBreaker::new(&config(Duration::from_millis(50)));
+
+        for _ in 0..2 {
+            breaker.try_acquire().unwrap().record(false);
+        }
+        assert_eq!(breaker.state(), State::Closed);
+
+        breaker.try_acquire().unwrap().record(false);
+        assert_eq!(breaker.state(), State::Open);
+        assert!(breaker.try_acquire().is_none());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.state(), State::HalfOpen);
+
+        // a single probe is let through, and a failed probe opens the breaker again
+        let probe = breaker.try_acquire().unwrap();
+        assert!(breaker.try_acquire().is_none());
+        probe.record(false);
+        assert_eq!(breaker.state(), State::Open);
+
+        std::thread::sleep(Duration::from_millis(60));
+        breaker.try_acquire().unwrap().record(true);
+        assert_eq!(breaker.state(), State::Closed);
+
+        // the failures are counted from the last success
+        breaker.try_acquire().unwrap().record(false);
+        assert_eq!(breaker.state(), State::Closed);
+    }
+
+    #[test]
+    fn cancelled_probe_is_released() {
+        let breaker = CircuitBreaker::new(&config(Duration::from_millis(50)));
+
+        for _ in 0..3 {
+            breaker.try_acquire().unwrap().record(false);
+        }
+        std::thread::sleep(Duration::from_millis(60));
+
+        let probe = breaker.try_acquire().unwrap();
+        assert!(!breaker.is_available());
+        drop(probe);
+
+        // the cancelled probe is neither a success nor a failure
+        assert_eq!(breaker.state(), State::HalfOpen);
+        assert!(breaker.is_available());
+        breaker.try_acquire().unwrap().record(true);
+        assert_eq!(breaker.state(), State::Closed);
+    }
+
+    pub struct EchoService;
+
+    sonic_service!(EchoService, [Echo]);
+
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct Echo(u64);
+
+    impl Message<EchoService> for Echo {
+        type Response = u64;
+
+        async fn handle(self, _: &EchoService) -> Self::Response {
+            self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_backend() {
+        let cool_down = Duration::from_millis(200);
+        let breakers = CircuitBreakers::new(config(cool_down));
+
+        // nothing listens on the address until the backend comes back below
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let client: RemoteClient<EchoService> = breakers.client(addr);
+
+        for _ in 0..3 {
+            assert!(client
+                .send_with_timeout(Echo(1), Duration::from_millis(100))
+                .await
+                .is_err());
+        }
+
+        assert_eq!(breakers.get(addr).state(), State::Open);
+        assert!(matches!(
+            client
+                .send_with_timeout(Echo(1), Duration::from_millis(100))
+                .await,
+            Err(sonic::Error::CircuitOpen)
+        ));
+
+        // the replicas of the open breaker are skipped while the others are available
+        let replicas = vec![client.clone(), RemoteClient::new(addr)];
+        for _ in 0..10 {
+            let selected = RandomReplicaSelector.select(&replicas);
+            assert_eq!(selected.len(), 1);
+            assert!(selected[0].is_available());
+        }
+
+        let server = EchoService.bind(addr).await.unwrap();
+        let server = tokio::spawn(async move {
+            loop {
+                server.accept().await.unwrap();
+            }
+        });
+
+        tokio::time::sleep(cool_down).await;
+        assert_eq!(breakers.get(addr).state(), State::HalfOpen);
+
+        assert_eq!(
+            client
+                .send_with_timeout(Echo(7), Duration::from_secs(1))
+                .await
+                .unwrap(),
+            7
+        );
+        assert_eq!(breakers.get(addr).state(), State::Closed);
+
+        server.abort();
+    }
+}
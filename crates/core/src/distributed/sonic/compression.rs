@@ -0,0 +1,373 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Optional compression of sonic frames.
+//!
+//! A client that wants compression starts the connection with a handshake frame
+//! listing the codecs it supports, and the server answers with the codec it picked.
+//! After that, each side compresses the frames it sends that are larger than its
+//! threshold and tags them with the codec in the frame header.
+//!
+//! Servers without compression support drop the connection when they receive the
+//! handshake, as it looks like an oversized frame to them. The client then reconnects
+//! and talks to them uncompressed. Clients without compression support never send
+//! the handshake, so the server never compresses its responses to them.
+//!
+//! The connections of a pool share the outcome of the first handshake, so later
+//! connections send the handshake for the negotiated codec along with their first
+//! request instead of waiting for it, and never send it to servers without support.
+//!
+//! The codecs are only built with the `sonic-lz4` and `sonic-zstd` features. Frames that
+//! would decompress to more than the maximum frame size are rejected.
+
+use once_cell::sync::Lazy;
+
+use crate::metrics::{Counter, PrometheusRegistry};
+
+use super::{Error, Header, Result, MAX_BODY_SIZE_BYTES};
+
+/// The codec of a frame is stored in the top byte of the body size in the header.
+/// Body sizes are bounded by `MAX_BODY_SIZE_BYTES` so this byte is otherwise unused.
+const TAG_SHIFT: u32 = usize::BITS - 8;
+const SIZE_MASK: usize = (1 << TAG_SHIFT) - 1;
+
+const UNCOMPRESSED_TAG: u8 = 0;
+const HANDSHAKE_TAG: u8 = 0xff;
+
+/// Lz4 can't compress better than this, so frames that claim a larger
+/// uncompressed size are corrupt.
+#[cfg(feature = "sonic-lz4")]
+const LZ4_MAX_RATIO: usize = 255;
+
+pub static METRICS: Lazy<CompressionMetrics> = Lazy::new(CompressionMetrics::default);
+
+/// Byte counts of the frames that were sent compressed.
+#[derive(Default)]
+pub struct CompressionMetrics {
+    pub uncompressed_bytes: Counter,
+    pub compressed_bytes: Counter,
+}
+
+impl CompressionMetrics {
+    pub fn register(&self, registry: &mut PrometheusRegistry) {
+        registry
+            .new_group(
+                "stract_sonic_uncompressed_bytes".to_string(),
+                Some("Bytes of the sonic frames sent compressed, before compression.".to_string()),
+            )
+            .unwrap()
+            .register(self.uncompressed_bytes.clone(), vec![]);
+
+        registry
+            .new_group(
+                "stract_sonic_compressed_bytes".to_string(),
+                Some("Bytes of the sonic frames sent compressed, after compression.".to_string()),
+            )
+            .unwrap()
+            .register(self.compressed_bytes.clone(), vec![]);
+    }
+}
+
+#[derive(bincode::Encode, bincode::Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Lz4,
+    Zstd,
+}
+
+impl Codec {
+    /// The codecs this binary was built with, in order of preference.
+    pub fn supported() -> Vec<Codec> {
+        #[allow(unused_mut)]
+        let mut codecs = Vec::new();
+
+        #[cfg(feature = "sonic-zstd")]
+        codecs.push(Codec::Zstd);
+
+        #[cfg(feature = "sonic-lz4")]
+        codecs.push(Codec::Lz4);
+
+        codecs
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            Codec::Lz4 => 1,
+            Codec::Zstd => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(Codec::Lz4),
+            2 => Some(Codec::Zstd),
+            _ => None,
+        }
+    }
+
+    /// `None` if the binary was built without the codec.
+    fn compress(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            #[cfg(feature = "sonic-lz4")]
+            Codec::Lz4 => Some(lz4_flex::compress_prepend_size(bytes)),
+            #[cfg(feature = "sonic-zstd")]
+            Codec::Zstd => {
+                // the bulk api stores the uncompressed size in the frame header
+                Some(zstd::bulk::compress(bytes, zstd::DEFAULT_COMPRESSION_LEVEL).unwrap())
+            }
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    /// Frames that would decompress to more than `max_size` bytes are rejected
+    /// before anything is allocated for them.
+    fn decompress(&self, bytes: &[u8], max_size: usize) -> Result<Vec<u8>> {
+        let check_size = |body_size: usize| {
+            if body_size > max_size {
+                Err(Error::BodyTooLarge {
+                    body_size,
+                    max_size,
+                })
+            } else {
+                Ok(body_size)
+            }
+        };
+
+        match self {
+            #[cfg(feature = "sonic-lz4")]
+            Codec::Lz4 => {
+                if bytes.len() < 4 {
+                    return Err(Error::Decompression);
+                }
+
+                let (size, compressed) = bytes.split_at(4);
+                let size = check_size(u32::from_le_bytes(size.try_into().unwrap()) as usize)?;
+
+                if size > compressed.len().saturating_mul(LZ4_MAX_RATIO) + 16 {
+                    return Err(Error::Decompression);
+                }
+
+                lz4_flex::decompress(compressed, size).map_err(|_| Error::Decompression)
+            }
+            #[cfg(feature = "sonic-zstd")]
+            Codec::Zstd => {
+                let size =
+                    zstd::bulk::Decompressor::upper_bound(bytes).ok_or(Error::Decompression)?;
+                let size = check_size(size)?;
+
+                zstd::bulk::decompress(bytes, size).map_err(|_| Error::Decompression)
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                let _ = check_size;
+                Err(Error::Decompression)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    /// Codecs to offer (as a client) or accept (as a server), in order of preference.
+    pub codecs: Vec<Codec>,
+    /// Frames smaller than this (in bytes) are sent uncompressed.
+    pub threshold_bytes: usize,
+}
+
+impl CompressionConfig {
+    pub const DEFAULT_THRESHOLD_BYTES: usize = 64 * 1024;
+
+    pub fn disabled() -> Self {
+        Self {
+            codecs: Vec::new(),
+            threshold_bytes: Self::DEFAULT_THRESHOLD_BYTES,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.codecs.is_empty()
+    }
+
+    /// The first of the client's codecs that is also accepted here
+    /// and that the binary was built with.
+    pub(super) fn pick(&self, offered: &[Codec]) -> Option<Codec> {
+        let supported = Codec::supported();
+
+        offered
+            .iter()
+            .find(|codec| self.codecs.contains(codec) && supported.contains(codec))
+            .copied()
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            codecs: Codec::supported(),
+            threshold_bytes: Self::DEFAULT_THRESHOLD_BYTES,
+        }
+    }
+}
+
+#[derive(bincode::Encode, bincode::Decode, Debug, Clone, PartialEq, Eq)]
+pub(super) struct Handshake {
+    pub codecs: Vec<Codec>,
+}
+
+#[derive(bincode::Encode, bincode::Decode, Debug, Clone, PartialEq, Eq)]
+pub(super) struct HandshakeResponse {
+    pub codec: Option<Codec>,
+}
+
+impl Header {
+    fn new(tag: u8, body_size: usize) -> Self {
+        Self {
+            body_size: ((tag as usize) << TAG_SHIFT) | body_size,
+        }
+    }
+
+    pub(super) fn handshake(body_size: usize) -> Self {
+        Self::new(HANDSHAKE_TAG, body_size)
+    }
+
+    /// The size of the body as sent on the wire.
+    pub(super) fn len(&self) -> usize {
+        self.body_size & SIZE_MASK
+    }
+
+    fn tag(&self) -> u8 {
+        (self.body_size >> TAG_SHIFT) as u8
+    }
+
+    pub(super) fn is_handshake(&self) -> bool {
+        self.tag() == HANDSHAKE_TAG
+    }
+}
+
+/// Prepare a frame for sending. The body is compressed with `codec` if it is
+/// at least `threshold_bytes` large and compression makes it smaller.
+pub(super) fn encode(
+    bytes: Vec<u8>,
+    codec: Option<Codec>,
+    threshold_bytes: usize,
+) -> (Header, Vec<u8>) {
+    if let Some(codec) = codec {
+        if bytes.len() >= threshold_bytes {
+            if let Some(compressed) = codec
+                .compress(&bytes)
+                .filter(|compressed| compressed.len() < bytes.len())
+            {
+                METRICS.uncompressed_bytes.add(bytes.len() as u64);
+                METRICS.compressed_bytes.add(compressed.len() as u64);
+
+                return (Header::new(codec.tag(), compressed.len()), compressed);
+            }
+        }
+    }
+
+    (Header::new(UNCOMPRESSED_TAG, bytes.len()), bytes)
+}
+
+/// The uncompressed body of a received frame.
+pub(super) fn decode(header: &Header, body: Vec<u8>) -> Result<Vec<u8>> {
+    match header.tag() {
+        UNCOMPRESSED_TAG => Ok(body),
+        tag => match Codec::from_tag(tag) {
+            Some(codec) => codec.decompress(&body, MAX_BODY_SIZE_BYTES),
+            None => Err(Error::BadRequest),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_frames_are_not_compressed() {
+        let bytes = vec![0; 128];
+
+        let (header, body) = encode(bytes.clone(), Some(Codec::Lz4), 1024);
+        assert_eq!(header.tag(), UNCOMPRESSED_TAG);
+        assert_eq!(header.len(), bytes.len());
+        assert_eq!(body, bytes);
+
+        let (header, body) = encode(bytes.clone(), None, 0);
+        assert_eq!(header.tag(), UNCOMPRESSED_TAG);
+        assert_eq!(body, bytes);
+    }
+
+    #[test]
+    fn large_frames_are_compressed() {
+        let bytes = "a fairly repetitive body ".repeat(1_000).into_bytes();
+
+        for codec in Codec::supported() {
+            let (header, body) = encode(bytes.clone(), Some(codec), 1024);
+
+            assert_eq!(header.tag(), codec.tag());
+            assert_eq!(header.len(), body.len());
+            assert!(body.len() < bytes.len());
+            assert_eq!(decode(&header, body).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        let bytes = vec![0; 4096];
+
+        for codec in Codec::supported() {
+            let compressed = codec.compress(&bytes).unwrap();
+
+            assert_eq!(codec.decompress(&compressed, 4096).unwrap(), bytes);
+            assert!(matches!(
+                codec.decompress(&compressed, 4095),
+                Err(Error::BodyTooLarge {
+                    body_size: 4096,
+                    max_size: 4095
+                })
+            ));
+        }
+    }
+
+    #[cfg(feature = "sonic-lz4")]
+    #[test]
+    fn lz4_size_must_match_the_body() {
+        let mut compressed = Codec::Lz4.compress(&[0; 4096]).unwrap();
+        compressed[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(matches!(
+            Codec::Lz4.decompress(&compressed, MAX_BODY_SIZE_BYTES),
+            Err(Error::Decompression)
+        ));
+        assert!(matches!(
+            Codec::Lz4.decompress(&[1, 0], MAX_BODY_SIZE_BYTES),
+            Err(Error::Decompression)
+        ));
+    }
+
+    #[cfg(feature = "sonic-lz4")]
+    #[test]
+    fn pick_codec() {
+        let config = CompressionConfig {
+            codecs: vec![Codec::Lz4],
+            threshold_bytes: 0,
+        };
+
+        assert_eq!(config.pick(&[Codec::Zstd, Codec::Lz4]), Some(Codec::Lz4));
+        assert_eq!(config.pick(&[Codec::Zstd]), None);
+        assert_eq!(CompressionConfig::disabled().pick(&[Codec::Lz4]), None);
+    }
+}
@@ -14,12 +14,12 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Duration};
 
 use crate::Result;
 use deadpool::managed;
 
-use super::service::Service;
+use super::{service::Service, CompressionConfig, Negotiation};
 
 pub trait Connection {
     type Manager: managed::Manager;
@@ -69,8 +69,11 @@ where
     }
 }
 
+/// The connections of a manager share the outcome of the compression handshake,
+/// so it is only waited for when the first connection is created.
 pub struct Manager<Req, Res> {
     addr: SocketAddr,
+    negotiation: Negotiation,
     _marker: std::marker::PhantomData<(Req, Res)>,
 }
 
@@ -78,6 +81,7 @@ impl<Req, Res> Manager<Req, Res> {
     pub fn new(addr: SocketAddr) -> Self {
         Self {
             addr,
+            negotiation: Negotiation::default(),
             _marker: std::marker::PhantomData,
         }
     }
@@ -104,7 +108,13 @@ where
     type Error = anyhow::Error;
 
     async fn create(&self) -> Result<Self::Type, Self::Error> {
-        Ok(super::Connection::connect(self.addr).await?)
+        Ok(super::Connection::create_negotiated(
+            self.addr,
+            Duration::from_secs(30),
+            CompressionConfig::default(),
+            &self.negotiation,
+        )
+        .await?)
     }
 
     async fn recycle(
@@ -128,6 +138,7 @@ where
 
 pub struct ServiceManager<S> {
     addr: SocketAddr,
+    negotiation: Negotiation,
     _marker: std::marker::PhantomData<S>,
 }
 
@@ -135,6 +146,7 @@ impl<S> ServiceManager<S> {
     pub fn new(addr: SocketAddr) -> Self {
         Self {
             addr,
+            negotiation: Negotiation::default(),
             _marker: std::marker::PhantomData,
         }
     }
@@ -159,7 +171,7 @@ where
     type Error = anyhow::Error;
 
     async fn create(&self) -> Result<Self::Type, Self::Error> {
-        Ok(super::service::Connection::create(self.addr).await?)
+        Ok(super::service::Connection::create_negotiated(self.addr, &self.negotiation).await?)
     }
 
     async fn recycle(
@@ -14,13 +14,21 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+pub mod circuit_breaker;
+pub mod compression;
 pub mod connection_pool;
 pub mod replication;
 pub mod service;
 
+pub use circuit_breaker::{CircuitBreaker, CircuitBreakers};
+pub use compression::{Codec, CompressionConfig};
 pub use connection_pool::ConnectionPool;
 
-use std::{marker::PhantomData, time::Duration};
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
@@ -46,12 +54,18 @@ pub enum Error {
     #[error("Could not get connection from pool")]
     PoolGet,
 
+    #[error("The circuit breaker of the peer is open")]
+    CircuitOpen,
+
     #[error("The request could not be processed")]
     BadRequest,
 
     #[error("The body size ({body_size}) is larger than the maximum allowed ({max_size})")]
     BodyTooLarge { body_size: usize, max_size: usize },
 
+    #[error("Failed to decompress frame")]
+    Decompression,
+
     #[error("An application error occurred: {0}")]
     Application(#[from] anyhow::Error),
 }
@@ -61,6 +75,96 @@ pub struct Connection<Req, Res> {
     created: std::time::Instant,
     marker: PhantomData<(Req, Res)>,
     awaiting_res: bool,
+    codec: Option<Codec>,
+    threshold_bytes: usize,
+    /// Set while the response to a handshake sent without waiting for it is unread.
+    pending_handshake: Option<Negotiation>,
+}
+
+/// The codec negotiated with a server, shared by the connections of a pool so the
+/// handshake is only waited for by the first of them. `None` until it has been negotiated.
+#[derive(Debug, Clone, Default)]
+pub struct Negotiation(Arc<Mutex<Option<Option<Codec>>>>);
+
+impl Negotiation {
+    fn get(&self) -> Option<Option<Codec>> {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set(&self, codec: Option<Codec>) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Some(codec);
+    }
+
+    /// Negotiate again on the next connection, e.g. because the server was replaced.
+    fn reset(&self) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+async fn read_frame(stream: &mut TcpStream) -> Result<(Header, Vec<u8>)> {
+    let mut header_buf = vec![0; std::mem::size_of::<Header>()];
+    stream.read_exact(&mut header_buf).await?;
+    let header: Header = *bytemuck::from_bytes(&header_buf);
+
+    if header.len() > MAX_BODY_SIZE_BYTES {
+        return Err(Error::BodyTooLarge {
+            body_size: header.len(),
+            max_size: MAX_BODY_SIZE_BYTES,
+        });
+    }
+
+    let mut buf = vec![0; header.len()];
+    stream.read_exact(&mut buf).await?;
+
+    Ok((header, buf))
+}
+
+async fn write_frame(stream: &mut TcpStream, header: Header, body: &[u8]) -> Result<()> {
+    stream.write_all(bytemuck::bytes_of(&header)).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+
+    Ok(())
+}
+
+/// Offer the codecs to the server and return the one it picked.
+async fn handshake(stream: &mut TcpStream, codecs: &[Codec]) -> Result<Option<Codec>> {
+    send_handshake(stream, codecs).await?;
+    read_handshake_response(stream).await
+}
+
+async fn send_handshake(stream: &mut TcpStream, codecs: &[Codec]) -> Result<()> {
+    let bytes = bincode::encode_to_vec(
+        compression::Handshake {
+            codecs: codecs.to_vec(),
+        },
+        bincode::config::standard(),
+    )
+    .unwrap();
+
+    write_frame(stream, Header::handshake(bytes.len()), &bytes).await
+}
+
+async fn read_handshake_response(stream: &mut TcpStream) -> Result<Option<Codec>> {
+    let (header, body) = read_frame(stream).await?;
+    let body = compression::decode(&header, body)?;
+    let (res, _): (compression::HandshakeResponse, _) =
+        bincode::decode_from_slice(&body, bincode::config::standard())
+            .map_err(|_| Error::BadRequest)?;
+
+    Ok(res.codec)
+}
+
+async fn connect_with_timeout(server: impl ToSocketAddrs, timeout: Duration) -> Result<TcpStream> {
+    match tokio::time::timeout(timeout, TcpStream::connect(server)).await {
+        Ok(stream) => {
+            let stream = stream?;
+            stream.set_nodelay(true)?;
+
+            Ok(stream)
+        }
+        Err(_) => Err(Error::ConnectionTimeout),
+    }
 }
 
 impl<Req, Res> Connection<Req, Res>
@@ -80,20 +184,69 @@ where
         server: impl ToSocketAddrs,
         timeout: Duration,
     ) -> Result<Self> {
-        match tokio::time::timeout(timeout, TcpStream::connect(server)).await {
-            Ok(stream) => {
-                let stream = stream?;
-                stream.set_nodelay(true)?;
-
-                Ok(Connection {
-                    stream,
-                    awaiting_res: false,
-                    created: std::time::Instant::now(),
-                    marker: PhantomData,
-                })
+        Self::create_with_compression(server, timeout, CompressionConfig::default()).await
+    }
+
+    /// Connect to the server and negotiate compression if it is enabled in the config.
+    /// Servers that do not support compression are talked to uncompressed.
+    pub async fn create_with_compression(
+        server: impl ToSocketAddrs,
+        timeout: Duration,
+        compression: CompressionConfig,
+    ) -> Result<Self> {
+        Self::create_negotiated(server, timeout, compression, &Negotiation::default()).await
+    }
+
+    /// Like [`Connection::create_with_compression`], but only waits for the handshake if
+    /// the codec has not already been negotiated by another connection to the server.
+    /// Otherwise the handshake for the negotiated codec is sent along with the first
+    /// request, so the server compresses its responses, and no handshake is sent to
+    /// servers that do not support compression.
+    pub async fn create_negotiated(
+        server: impl ToSocketAddrs,
+        timeout: Duration,
+        compression: CompressionConfig,
+        negotiation: &Negotiation,
+    ) -> Result<Self> {
+        let mut stream = connect_with_timeout(server, timeout).await?;
+        let mut codec = None;
+        let mut pending_handshake = None;
+
+        if compression.is_enabled() {
+            match negotiation.get() {
+                Some(None) => {}
+                Some(Some(negotiated)) => {
+                    send_handshake(&mut stream, &[negotiated]).await?;
+                    codec = Some(negotiated);
+                    pending_handshake = Some(negotiation.clone());
+                }
+                None => {
+                    let addr = stream.peer_addr()?;
+
+                    match tokio::time::timeout(timeout, handshake(&mut stream, &compression.codecs))
+                        .await
+                    {
+                        Ok(Ok(picked)) => codec = picked,
+                        _ => {
+                            tracing::debug!(?addr, "peer does not support compression");
+                            stream = connect_with_timeout(addr, timeout).await?;
+                        }
+                    }
+
+                    negotiation.set(codec);
+                }
             }
-            Err(_) => Err(Error::ConnectionTimeout),
         }
+
+        Ok(Connection {
+            stream,
+            awaiting_res: false,
+            created: std::time::Instant::now(),
+            marker: PhantomData,
+            codec,
+            threshold_bytes: compression.threshold_bytes,
+            pending_handshake,
+        })
     }
 
     pub async fn create_with_timeout_retry(
@@ -123,28 +276,30 @@ where
         self.awaiting_res = true;
         let bytes = bincode::encode_to_vec(request, bincode::config::standard()).unwrap();
 
-        let header = Header {
-            body_size: bytes.len(),
+        // the request is sent uncompressed until the server has confirmed the codec
+        let codec = if self.pending_handshake.is_some() {
+            None
+        } else {
+            self.codec
         };
-
-        self.stream.write_all(bytemuck::bytes_of(&header)).await?;
-        self.stream.write_all(&bytes).await?;
-        self.stream.flush().await?;
-
-        let mut header_buf = vec![0; std::mem::size_of::<Header>()];
-        self.stream.read_exact(&mut header_buf).await?;
-        let header: Header = *bytemuck::from_bytes(&header_buf);
-
-        if header.body_size > MAX_BODY_SIZE_BYTES {
-            return Err(Error::BodyTooLarge {
-                body_size: header.body_size,
-                max_size: MAX_BODY_SIZE_BYTES,
-            });
+        let (header, bytes) = compression::encode(bytes, codec, self.threshold_bytes);
+        write_frame(&mut self.stream, header, &bytes).await?;
+
+        if let Some(negotiation) = self.pending_handshake.take() {
+            match read_handshake_response(&mut self.stream).await {
+                Ok(codec) => {
+                    self.codec = codec;
+                    negotiation.set(codec);
+                }
+                Err(err) => {
+                    negotiation.reset();
+                    return Err(err);
+                }
+            }
         }
 
-        let mut buf = vec![0; header.body_size];
-        self.stream.read_exact(&mut buf).await?;
-        self.stream.flush().await?;
+        let (header, buf) = read_frame(&mut self.stream).await?;
+        let buf = compression::decode(&header, buf)?;
 
         tracing::debug!("deserializing {:?}", std::any::type_name::<(Req, Res)>());
         let (res, _) = bincode::decode_from_slice(&buf, bincode::config::standard()).unwrap();
@@ -173,6 +328,11 @@ where
         self.awaiting_res
     }
 
+    /// The codec negotiated with the server, if any.
+    pub fn codec(&self) -> Option<Codec> {
+        self.codec
+    }
+
     pub async fn is_closed(&mut self) -> bool {
         if self.created.elapsed() > MAX_CONNECTION_TTL {
             self.stream.shutdown().await.ok();
@@ -194,6 +354,7 @@ struct Header {
 
 pub struct Server<Req, Res> {
     listener: TcpListener,
+    compression: CompressionConfig,
     marker: PhantomData<(Req, Res)>,
 }
 
@@ -202,9 +363,18 @@ where
     Req: bincode::Decode,
 {
     pub async fn bind(addr: impl ToSocketAddrs) -> Result<Self> {
+        Self::bind_with_compression(addr, CompressionConfig::default()).await
+    }
+
+    /// Bind a server that accepts the codecs of the config when clients ask for compression.
+    pub async fn bind_with_compression(
+        addr: impl ToSocketAddrs,
+        compression: CompressionConfig,
+    ) -> Result<Self> {
         let listener = TcpListener::bind(addr).await?;
         Ok(Server {
             listener,
+            compression,
             marker: PhantomData,
         })
     }
@@ -213,12 +383,14 @@ where
         let (stream, client) = self.listener.accept().await?;
         tracing::debug!(?client, "accepted connection");
 
-        Ok(ServerConnection::new(stream))
+        Ok(ServerConnection::new(stream, self.compression.clone()))
     }
 }
 
 pub struct ServerConnection<Req, Res> {
     stream: TcpStream,
+    compression: CompressionConfig,
+    codec: Option<Codec>,
     marker: PhantomData<(Req, Res)>,
 }
 
@@ -226,29 +398,41 @@ impl<Req, Res> ServerConnection<Req, Res>
 where
     Req: bincode::Decode,
 {
-    fn new(stream: TcpStream) -> Self {
+    fn new(stream: TcpStream, compression: CompressionConfig) -> Self {
         ServerConnection {
             stream,
+            compression,
+            codec: None,
             marker: PhantomData,
         }
     }
 
-    pub async fn request(&mut self) -> Result<Request<'_, Req, Res>> {
-        let mut header_buf = vec![0; std::mem::size_of::<Header>()];
-        self.stream.read_exact(&mut header_buf).await?;
-        let header: Header = *bytemuck::from_bytes(&header_buf);
-
-        if header.body_size > MAX_BODY_SIZE_BYTES {
-            return Err(Error::BodyTooLarge {
-                body_size: header.body_size,
-                max_size: MAX_BODY_SIZE_BYTES,
-            });
-        }
+    async fn handshake(&mut self, body: &[u8]) -> Result<()> {
+        let (handshake, _): (compression::Handshake, _) =
+            bincode::decode_from_slice(body, bincode::config::standard())
+                .map_err(|_| Error::BadRequest)?;
 
-        let mut buf = vec![0; header.body_size];
+        self.codec = self.compression.pick(&handshake.codecs);
 
-        self.stream.read_exact(&mut buf).await?;
+        let bytes = bincode::encode_to_vec(
+            compression::HandshakeResponse { codec: self.codec },
+            bincode::config::standard(),
+        )
+        .unwrap();
+        let (header, bytes) = compression::encode(bytes, None, 0);
+
+        write_frame(&mut self.stream, header, &bytes).await
+    }
+
+    pub async fn request(&mut self) -> Result<Request<'_, Req, Res>> {
+        let (mut header, mut buf) = read_frame(&mut self.stream).await?;
 
+        while header.is_handshake() {
+            self.handshake(&buf).await?;
+            (header, buf) = read_frame(&mut self.stream).await?;
+        }
+
+        let buf = compression::decode(&header, buf)?;
         let (body, _) = bincode::decode_from_slice(&buf, bincode::config::standard()).unwrap();
 
         Ok(Request {
@@ -269,18 +453,13 @@ where
 {
     async fn respond_without_timeout(self, response: Res) -> Result<()> {
         let bytes = bincode::encode_to_vec(&response, bincode::config::standard()).unwrap();
-        let header = Header {
-            body_size: bytes.len(),
-        };
-
-        self.conn
-            .stream
-            .write_all(bytemuck::bytes_of(&header))
-            .await?;
-        self.conn.stream.write_all(&bytes).await?;
-        self.conn.stream.flush().await?;
+        let (header, bytes) = compression::encode(
+            bytes,
+            self.conn.codec,
+            self.conn.compression.threshold_bytes,
+        );
 
-        Ok(())
+        write_frame(&mut self.conn.stream, header, &bytes).await
     }
 
     pub async fn respond(self, response: Res) -> Result<()> {
@@ -371,4 +550,277 @@ mod tests {
             con_res?;
         }
     }
+    fn large_message() -> Message {
+        Message {
+            text: "a large and very compressible body ".repeat(100_000),
+            other: (0..1_000).map(|i| (format!("key {i}"), i as f32)).collect(),
+        }
+    }
+
+    fn zstd() -> CompressionConfig {
+        CompressionConfig {
+            codecs: vec![Codec::Zstd],
+            threshold_bytes: 1024,
+        }
+    }
+
+    /// Echo a message through a server and return it along with the negotiated codec.
+    fn round_trip(
+        server_compression: CompressionConfig,
+        client_compression: CompressionConfig,
+        msg: Message,
+    ) -> (Message, Option<Codec>) {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let addr = free_socket_addr();
+                let server: Server<Message, Message> =
+                    Server::bind_with_compression(addr, server_compression)
+                        .await
+                        .unwrap();
+
+                let svr_task = tokio::spawn(async move {
+                    let mut conn = server.accept().await.unwrap();
+                    let req = conn.request().await.unwrap();
+                    let body = req.body().clone();
+                    req.respond(body).await.unwrap();
+                });
+
+                let mut conn: Connection<Message, Message> = Connection::create_with_compression(
+                    addr,
+                    Duration::from_secs(30),
+                    client_compression,
+                )
+                .await
+                .unwrap();
+                let res = conn.send(&msg).await.unwrap();
+                svr_task.await.unwrap();
+
+                (res, conn.codec())
+            })
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let msg = large_message();
+
+        let (res, codec) = round_trip(zstd(), zstd(), msg.clone());
+        assert_eq!(codec, Some(Codec::Zstd));
+        assert_eq!(res, msg);
+
+        let (res, codec) = round_trip(
+            CompressionConfig {
+                codecs: vec![Codec::Lz4, Codec::Zstd],
+                threshold_bytes: 1024,
+            },
+            CompressionConfig {
+                codecs: vec![Codec::Lz4],
+                threshold_bytes: 1024,
+            },
+            msg.clone(),
+        );
+        assert_eq!(codec, Some(Codec::Lz4));
+        assert_eq!(res, msg);
+    }
+
+    #[test]
+    fn uncompressed_round_trip() {
+        let msg = large_message();
+
+        let (res, codec) = round_trip(zstd(), CompressionConfig::disabled(), msg.clone());
+        assert_eq!(codec, None);
+        assert_eq!(res, msg);
+
+        let (res, codec) = round_trip(CompressionConfig::disabled(), zstd(), msg.clone());
+        assert_eq!(codec, None);
+        assert_eq!(res, msg);
+    }
+
+    /// Frame a message the way peers without compression support do.
+    fn legacy_frame(msg: &Message) -> Vec<u8> {
+        let body = bincode::encode_to_vec(msg, bincode::config::standard()).unwrap();
+
+        let mut frame = body.len().to_ne_bytes().to_vec();
+        frame.extend(body);
+        frame
+    }
+
+    async fn read_legacy_frame(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+        let mut header = [0; std::mem::size_of::<usize>()];
+        stream.read_exact(&mut header).await?;
+        let body_size = usize::from_ne_bytes(header);
+
+        if body_size > MAX_BODY_SIZE_BYTES {
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+
+        let mut body = vec![0; body_size];
+        stream.read_exact(&mut body).await?;
+
+        Ok(body)
+    }
+
+    #[test]
+    fn legacy_server() {
+        let msg = large_message();
+
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let listener = TcpListener::bind(free_socket_addr()).await.unwrap();
+                let addr = listener.local_addr().unwrap();
+
+                // a server without compression support drops the connection when it
+                // gets the handshake and serves the reconnected client uncompressed.
+                let svr_task = tokio::spawn(async move {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    assert!(read_legacy_frame(&mut stream).await.is_err());
+                    drop(stream);
+
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    let body = read_legacy_frame(&mut stream).await.unwrap();
+                    let (req, _): (Message, _) =
+                        bincode::decode_from_slice(&body, bincode::config::standard()).unwrap();
+                    stream.write_all(&legacy_frame(&req)).await.unwrap();
+                });
+
+                let mut conn: Connection<Message, Message> =
+                    Connection::create_with_compression(addr, Duration::from_secs(30), zstd())
+                        .await
+                        .unwrap();
+                assert_eq!(conn.codec(), None);
+
+                assert_eq!(conn.send(&msg).await.unwrap(), msg);
+                svr_task.await.unwrap();
+            });
+    }
+
+    #[test]
+    fn negotiated_once() {
+        let msg = large_message();
+
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let addr = free_socket_addr();
+                let server: Server<Message, Message> =
+                    Server::bind_with_compression(addr, zstd()).await.unwrap();
+
+                let svr_task = tokio::spawn(async move {
+                    for _ in 0..2 {
+                        let mut conn = server.accept().await.unwrap();
+                        let req = conn.request().await.unwrap();
+                        let body = req.body().clone();
+                        req.respond(body).await.unwrap();
+                    }
+                });
+
+                let negotiation = Negotiation::default();
+
+                for _ in 0..2 {
+                    let mut conn: Connection<Message, Message> = Connection::create_negotiated(
+                        addr,
+                        Duration::from_secs(30),
+                        zstd(),
+                        &negotiation,
+                    )
+                    .await
+                    .unwrap();
+
+                    assert_eq!(conn.send(&msg).await.unwrap(), msg);
+                    assert_eq!(conn.codec(), Some(Codec::Zstd));
+                    assert_eq!(negotiation.get(), Some(Some(Codec::Zstd)));
+                }
+
+                svr_task.await.unwrap();
+            });
+    }
+
+    #[test]
+    fn legacy_server_is_detected_once() {
+        let msg = large_message();
+
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let listener = TcpListener::bind(free_socket_addr()).await.unwrap();
+                let addr = listener.local_addr().unwrap();
+
+                // only the first connection sends the handshake, which the server drops
+                let svr_task = tokio::spawn(async move {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    assert!(read_legacy_frame(&mut stream).await.is_err());
+                    drop(stream);
+
+                    for _ in 0..2 {
+                        let (mut stream, _) = listener.accept().await.unwrap();
+                        let body = read_legacy_frame(&mut stream).await.unwrap();
+                        let (req, _): (Message, _) =
+                            bincode::decode_from_slice(&body, bincode::config::standard()).unwrap();
+                        stream.write_all(&legacy_frame(&req)).await.unwrap();
+                    }
+                });
+
+                let negotiation = Negotiation::default();
+
+                for _ in 0..2 {
+                    let mut conn: Connection<Message, Message> = Connection::create_negotiated(
+                        addr,
+                        Duration::from_secs(30),
+                        zstd(),
+                        &negotiation,
+                    )
+                    .await
+                    .unwrap();
+
+                    assert_eq!(conn.codec(), None);
+                    assert_eq!(conn.send(&msg).await.unwrap(), msg);
+                }
+
+                assert_eq!(negotiation.get(), Some(None));
+                svr_task.await.unwrap();
+            });
+    }
+
+    #[test]
+    fn legacy_client() {
+        let msg = large_message();
+
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let addr = free_socket_addr();
+                let server: Server<Message, Message> =
+                    Server::bind_with_compression(addr, zstd()).await.unwrap();
+
+                let svr_task = tokio::spawn(async move {
+                    let mut conn = server.accept().await.unwrap();
+                    let req = conn.request().await.unwrap();
+                    let body = req.body().clone();
+                    req.respond(body).await.unwrap();
+                });
+
+                // a client without compression support never sends the handshake,
+                // so it gets an uncompressed response.
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                stream.write_all(&legacy_frame(&msg)).await.unwrap();
+
+                let body = read_legacy_frame(&mut stream).await.unwrap();
+                let (res, _): (Message, _) =
+                    bincode::decode_from_slice(&body, bincode::config::standard()).unwrap();
+                assert_eq!(res, msg);
+
+                svr_task.await.unwrap();
+            });
+    }
 }
@@ -17,10 +17,12 @@
 use futures::future::join_all;
 use rand::seq::IteratorRandom;
 
-use super::Result;
+use super::{CircuitBreaker, Result};
 use crate::distributed::{cluster::Cluster, retry_strategy::ExponentialBackoff, sonic};
 use std::{net::SocketAddr, ops::DerefMut, sync::Arc, time::Duration};
 
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[derive(Debug)]
 pub struct RemoteClient<S>
 where
@@ -28,6 +30,8 @@ where
 {
     addr: SocketAddr,
     pool: sonic::ConnectionPool<sonic::service::Connection<S>>,
+    timeout: Duration,
+    breaker: Option<Arc<CircuitBreaker>>,
     _phantom: std::marker::PhantomData<S>,
 }
 
@@ -36,7 +40,13 @@ where
     S: sonic::service::Service,
 {
     fn clone(&self) -> Self {
-        Self::create(self.addr)
+        Self {
+            addr: self.addr,
+            pool: sonic::ConnectionPool::new(self.addr).unwrap(),
+            timeout: self.timeout,
+            breaker: self.breaker.clone(),
+            _phantom: std::marker::PhantomData,
+        }
     }
 }
 
@@ -52,13 +62,61 @@ where
         Self {
             addr,
             pool: sonic::ConnectionPool::new(addr).unwrap(),
+            timeout: DEFAULT_TIMEOUT,
+            breaker: None,
             _phantom: std::marker::PhantomData,
         }
     }
 
+    /// The timeout of each attempt of [`RemoteClient::send`] and [`RemoteClient::batch_send`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Fail the requests right away while the breaker is open.
+    pub fn with_circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.breaker = Some(breaker);
+        self
+    }
+
     pub fn addr(&self) -> SocketAddr {
         self.addr
     }
+
+    /// Whether requests are currently sent to the peer, i.e. its circuit breaker is not open.
+    pub fn is_available(&self) -> bool {
+        self.breaker
+            .as_ref()
+            .map_or(true, |breaker| breaker.is_available())
+    }
+
+    async fn with_breaker<T, F>(&self, req: F) -> Result<T>
+    where
+        F: std::future::Future<Output = Result<T>>,
+    {
+        let Some(breaker) = &self.breaker else {
+            return req.await;
+        };
+
+        let Some(permit) = breaker.try_acquire() else {
+            return Err(sonic::Error::CircuitOpen);
+        };
+
+        // the permit releases the probe if the request is cancelled before it finishes
+        let res = req.await;
+
+        // the peer answered if the request failed on either end after it was received
+        permit.record(matches!(
+            res,
+            Ok(_)
+                | Err(sonic::Error::BadRequest
+                    | sonic::Error::BodyTooLarge { .. }
+                    | sonic::Error::Application(_))
+        ));
+
+        res
+    }
 }
 
 impl<S> RemoteClient<S>
@@ -75,7 +133,7 @@ where
     pub async fn send<R: sonic::service::Wrapper<S> + Clone>(&self, req: R) -> Result<R::Response> {
         self.send_with_timeout_retry(
             req,
-            Duration::from_secs(60),
+            self.timeout,
             ExponentialBackoff::from_millis(500).with_limit(Duration::from_secs(3)),
         )
         .await
@@ -87,7 +145,7 @@ where
     ) -> Result<Vec<R::Response>> {
         self.batch_send_with_timeout_retry(
             reqs,
-            Duration::from_secs(60),
+            self.timeout,
             ExponentialBackoff::from_millis(500).with_limit(Duration::from_secs(3)),
         )
         .await
@@ -98,8 +156,11 @@ where
         req: R,
         timeout: Duration,
     ) -> Result<R::Response> {
-        let mut conn = self.conn().await?;
-        conn.send_with_timeout(req, timeout).await
+        self.with_breaker(async {
+            let mut conn = self.conn().await?;
+            conn.send_with_timeout(req, timeout).await
+        })
+        .await
     }
 
     pub async fn batch_send_with_timeout<R: sonic::service::Wrapper<S> + Clone>(
@@ -107,8 +168,11 @@ where
         reqs: &[R],
         timeout: Duration,
     ) -> Result<Vec<R::Response>> {
-        let mut conn = self.conn().await?;
-        conn.batch_send_with_timeout(reqs, timeout).await
+        self.with_breaker(async {
+            let mut conn = self.conn().await?;
+            conn.batch_send_with_timeout(reqs, timeout).await
+        })
+        .await
     }
 
     pub async fn send_with_timeout_retry<R: sonic::service::Wrapper<S> + Clone>(
@@ -121,6 +185,7 @@ where
         for backoff in retry {
             match self.send_with_timeout(req.clone(), timeout).await {
                 Ok(r) => return Ok(r),
+                Err(sonic::Error::CircuitOpen) => return Err(sonic::Error::CircuitOpen),
                 Err(e) => {
                     tracing::error!("Failed to send request: {:?}", e);
                     er = Some(e);
@@ -142,6 +207,7 @@ where
         for backoff in retry {
             match self.batch_send_with_timeout(reqs, timeout).await {
                 Ok(r) => return Ok(r),
+                Err(sonic::Error::CircuitOpen) => return Err(sonic::Error::CircuitOpen),
                 Err(e) => {
                     tracing::error!("Failed to send request: {:?}", e);
                     er = Some(e);
@@ -164,9 +230,20 @@ impl<S> ReplicaSelector<S> for RandomReplicaSelector
 where
     S: sonic::service::Service,
 {
+    /// A random replica whose circuit breaker is not open, if there is one.
     fn select<'a>(&self, replicas: &'a [RemoteClient<S>]) -> Vec<&'a RemoteClient<S>> {
         let mut rng = rand::thread_rng();
-        replicas.iter().choose_multiple(&mut rng, 1)
+
+        let available = replicas
+            .iter()
+            .filter(|replica| replica.is_available())
+            .choose_multiple(&mut rng, 1);
+
+        if available.is_empty() {
+            replicas.iter().choose_multiple(&mut rng, 1)
+        } else {
+            available
+        }
     }
 }
 
@@ -274,7 +351,7 @@ where
     }
 }
 
-pub trait ShardIdentifier: PartialEq + Eq + Clone {}
+pub trait ShardIdentifier: PartialEq + Eq + PartialOrd + Ord + Clone {}
 
 impl ShardIdentifier for () {}
 
@@ -343,7 +420,10 @@ where
     S: sonic::service::Service,
     Id: ShardIdentifier,
 {
-    pub fn new(shards: Vec<Shard<S, Id>>) -> Self {
+    /// The shards are kept in the order of their ids, so results merged from several shards
+    /// are in the same order no matter the order the shards were discovered in.
+    pub fn new(mut shards: Vec<Shard<S, Id>>) -> Self {
+        shards.sort_by(|a, b| a.id.cmp(&b.id));
         Self { shards }
     }
 
@@ -351,6 +431,10 @@ where
         self.shards.is_empty()
     }
 
+    pub fn shard_ids(&self) -> Vec<Id> {
+        self.shards.iter().map(|shard| shard.id.clone()).collect()
+    }
+
     async fn send_single<Req, Sel>(
         &self,
         req: Req,
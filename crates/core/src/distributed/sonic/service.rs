@@ -99,6 +99,24 @@ impl<S: Service> Connection<S> {
         })
     }
 
+    /// Connect without waiting for the compression handshake if the codec has
+    /// already been negotiated with the server.
+    pub async fn create_negotiated(
+        server: impl ToSocketAddrs,
+        negotiation: &super::Negotiation,
+    ) -> Result<Connection<S>> {
+        Ok(Connection {
+            await_res: false,
+            inner: super::Connection::create_negotiated(
+                server,
+                Duration::from_secs(30),
+                super::CompressionConfig::default(),
+                negotiation,
+            )
+            .await?,
+        })
+    }
+
     pub async fn create_with_timeout(
         server: impl ToSocketAddrs,
         timeout: Duration,
@@ -0,0 +1,161 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Disambiguation between the entities that match a query.
+//!
+//! Many entities share a name, like the planet, the element and the god Mercury.
+//! The candidates are ranked by how much of their title is matched by the query, by their
+//! popularity (the number of links to their article from the rest of the dump) and by how
+//! many of the other query terms are found in their abstract. The confidence of a candidate
+//! is its share of the combined score of all the candidates.
+
+use std::{cmp::Ordering, collections::HashSet};
+
+use tantivy::DocAddress;
+use utoipa::ToSchema;
+
+use super::EntityMatch;
+
+const TITLE_WEIGHT: f32 = 2.0;
+const POPULARITY_WEIGHT: f32 = 1.0;
+const CONTEXT_WEIGHT: f32 = 2.0;
+
+/// An entity the query might refer to.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityCandidate {
+    /// Can be pinned on the search query to select the entity without disambiguation.
+    pub id: String,
+    pub title: String,
+    pub confidence: f32,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Clone)]
+pub struct DisambiguatedEntity {
+    pub best: EntityMatch,
+    pub confidence: f32,
+    /// The other candidates in order of confidence.
+    pub alternatives: Vec<EntityCandidate>,
+}
+
+pub(super) struct Candidate {
+    pub doc: DocAddress,
+    pub id: String,
+    pub title: String,
+    /// The score of the candidate from the search of the index.
+    pub score: f32,
+    pub title_terms: HashSet<String>,
+    pub abstract_terms: HashSet<String>,
+    pub inlinks: u64,
+}
+
+impl Candidate {
+    fn combined_score(&self, query_terms: &HashSet<String>, max_popularity: f32) -> f32 {
+        let title_match = fraction(
+            self.title_terms
+                .iter()
+                .filter(|term| query_terms.contains(*term))
+                .count(),
+            self.title_terms.len(),
+        );
+
+        let context_terms: Vec<_> = query_terms
+            .iter()
+            .filter(|term| !self.title_terms.contains(*term))
+            .collect();
+        let context = fraction(
+            context_terms
+                .iter()
+                .filter(|term| self.abstract_terms.contains(**term))
+                .count(),
+            context_terms.len(),
+        );
+
+        let popularity = if max_popularity > 0.0 {
+            popularity(self.inlinks) / max_popularity
+        } else {
+            0.0
+        };
+
+        TITLE_WEIGHT * title_match + POPULARITY_WEIGHT * popularity + CONTEXT_WEIGHT * context
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn fraction(count: usize, total: usize) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f32 / total as f32
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn popularity(inlinks: u64) -> f32 {
+    (inlinks as f32).ln_1p()
+}
+
+/// The candidates in order of confidence, together with their confidence.
+pub(super) fn rank(
+    query_terms: &HashSet<String>,
+    candidates: Vec<Candidate>,
+) -> Vec<(Candidate, f32)> {
+    let max_popularity = candidates
+        .iter()
+        .map(|candidate| popularity(candidate.inlinks))
+        .fold(0.0, f32::max);
+
+    let mut scored: Vec<_> = candidates
+        .into_iter()
+        .map(|candidate| {
+            let score = candidate.combined_score(query_terms, max_popularity);
+            (candidate, score)
+        })
+        .collect();
+
+    scored.sort_by(|(a, a_score), (b, b_score)| {
+        b_score
+            .partial_cmp(a_score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
+    let total: f32 = scored.iter().map(|(_, score)| score).sum();
+    let num_candidates = scored.len();
+
+    scored
+        .into_iter()
+        .map(|(candidate, score)| {
+            let confidence = if total > 0.0 {
+                score / total
+            } else {
+                fraction(1, num_candidates)
+            };
+
+            (candidate, confidence)
+        })
+        .collect()
+}
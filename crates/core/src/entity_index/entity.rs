@@ -26,6 +26,8 @@ pub struct Entity {
     pub page_abstract: Span,
     pub info: Vec<(String, Span)>,
     pub image: Option<String>,
+    /// The number of links to the article from the other articles in the dump.
+    pub inlinks: u64,
 }
 
 #[derive(
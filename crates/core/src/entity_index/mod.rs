@@ -26,7 +26,10 @@ use base64::{prelude::BASE64_STANDARD as BASE64_ENGINE, Engine};
 use tantivy::{
     collector::TopDocs,
     query::{BooleanQuery, BoostQuery, MoreLikeThisQuery, Occur, QueryClone, TermQuery},
-    schema::{BytesOptions, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value},
+    schema::{
+        BytesOptions, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, STORED,
+        STRING,
+    },
     tokenizer::Tokenizer,
     DocAddress, IndexReader, IndexWriter, Searcher, TantivyDocument, Term,
 };
@@ -38,12 +41,26 @@ use crate::{
     Result,
 };
 
-use self::entity::{Entity, Link, Span};
+pub use self::disambiguation::{DisambiguatedEntity, EntityCandidate};
+use self::{
+    disambiguation::Candidate,
+    entity::{Entity, Link, Span},
+};
+
+mod disambiguation;
 pub(crate) mod entity;
 
+/// The number of candidates returned by the disambiguation of an entity search.
+pub const NUM_CANDIDATES: usize = 4;
+
+/// The number of entities matching the query that are considered for disambiguation.
+const CANDIDATE_POOL_SIZE: usize = 20;
+
 fn schema() -> Schema {
     let mut builder = tantivy::schema::Schema::builder();
 
+    builder.add_text_field("id", STRING | STORED);
+
     builder.add_text_field(
         "title",
         TextOptions::default()
@@ -81,6 +98,7 @@ fn schema() -> Schema {
             .set_indexing_options(TextFieldIndexing::default())
             .set_stored(),
     );
+    builder.add_u64_field("inlinks", STORED);
 
     builder.build()
 }
@@ -88,6 +106,8 @@ fn schema() -> Schema {
 fn entity_to_tantivy(entity: Entity, schema: &tantivy::schema::Schema) -> TantivyDocument {
     let mut doc = TantivyDocument::new();
 
+    doc.add_text(schema.get_field("id").unwrap(), entity.article_url);
+
     doc.add_text(schema.get_field("title").unwrap(), entity.title);
     doc.add_text(
         schema.get_field("abstract").unwrap(),
@@ -112,12 +132,14 @@ fn entity_to_tantivy(entity: Entity, schema: &tantivy::schema::Schema) -> Tantiv
         schema.get_field("image").unwrap(),
         entity.image.unwrap_or_default(),
     );
+    doc.add_u64(schema.get_field("inlinks").unwrap(), entity.inlinks);
 
     doc
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Clone)]
 pub struct StoredEntity {
+    pub id: String,
     pub title: String,
     pub entity_abstract: String,
     pub image_id: Option<String>,
@@ -263,56 +285,174 @@ impl EntityIndex {
         }
     }
 
-    pub fn search(&self, query: &str) -> Option<EntityMatch> {
-        let searcher = self.reader.searcher();
+    /// The terms of the text as they are indexed, without stopwords and punctuation.
+    fn terms(&self, text: &str) -> Vec<String> {
+        let mut terms = Vec::new();
+        let mut tokenizer = Normal::default();
+        let mut stream = tokenizer.token_stream(text);
+
+        while let Some(token) = stream.next() {
+            if !self.stopwords.contains(&token.text)
+                && token.text.chars().any(char::is_alphanumeric)
+            {
+                terms.push(token.text.clone());
+            }
+        }
+
+        terms
+    }
 
+    /// Entities where at least one of the terms of the query is found in the title.
+    fn candidates(&self, searcher: &Searcher, query_terms: &[String]) -> Vec<Candidate> {
         let title = self.schema.get_field("title").unwrap();
         let entity_abstract = self.schema.get_field("abstract").unwrap();
 
         let mut term_queries = Vec::new();
-        let mut tokenizer = Normal::default();
-        let mut stream = tokenizer.token_stream(query);
-        while let Some(token) = stream.next() {
-            if self.stopwords.contains(&token.text) {
-                continue;
-            }
-
-            term_queries.push((
-                Occur::Must,
-                BoostQuery::new(
-                    TermQuery::new(
-                        Term::from_field_text(title, &token.text),
-                        IndexRecordOption::WithFreqsAndPositions,
-                    )
-                    .box_clone(),
-                    5.0,
-                )
-                .box_clone(),
-            ));
+        let mut title_queries = Vec::new();
 
-            term_queries.push((
-                Occur::Should,
+        for term in query_terms {
+            let title_query = BoostQuery::new(
                 TermQuery::new(
-                    Term::from_field_text(entity_abstract, &token.text),
+                    Term::from_field_text(title, term),
                     IndexRecordOption::WithFreqsAndPositions,
                 )
                 .box_clone(),
-            ));
+                5.0,
+            )
+            .box_clone();
+
+            let abstract_query = TermQuery::new(
+                Term::from_field_text(entity_abstract, term),
+                IndexRecordOption::WithFreqsAndPositions,
+            )
+            .box_clone();
+
+            title_queries.push((Occur::Should, title_query.box_clone()));
+            term_queries.push((Occur::Should, title_query));
+            term_queries.push((Occur::Should, abstract_query));
         }
 
+        if title_queries.is_empty() {
+            return Vec::new();
+        }
+
+        term_queries.push((Occur::Must, BooleanQuery::from(title_queries).box_clone()));
         let query = BooleanQuery::from(term_queries);
 
         searcher
-            .search(&query, &TopDocs::with_limit(1))
+            .search(&query, &TopDocs::with_limit(CANDIDATE_POOL_SIZE))
             .unwrap()
-            .first()
-            .map(|(score, doc_address)| {
-                let entity = self.retrieve_stored_entity(&searcher, *doc_address, true, true, true);
+            .into_iter()
+            .map(|(score, doc_address)| self.candidate(searcher, doc_address, score))
+            .collect()
+    }
+
+    fn candidate(&self, searcher: &Searcher, doc_address: DocAddress, score: f32) -> Candidate {
+        let doc: TantivyDocument = searcher.doc(doc_address).unwrap();
+        let text = |name: &str| {
+            doc.get_first(self.schema.get_field(name).unwrap())
+                .and_then(|val| val.as_str().map(|s| s.to_string()))
+                .unwrap_or_default()
+        };
+
+        let title = text("title");
+        let title_terms = self.terms(&title).into_iter().collect();
+        let abstract_terms = self.terms(&text("abstract")).into_iter().collect();
+
+        Candidate {
+            doc: doc_address,
+            id: text("id"),
+            title,
+            score,
+            title_terms,
+            abstract_terms,
+            inlinks: doc
+                .get_first(self.schema.get_field("inlinks").unwrap())
+                .and_then(|val| val.as_u64())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The entity the query most likely refers to, together with up to `k - 1` alternatives.
+    ///
+    /// A pinned entity bypasses the disambiguation and is returned with full confidence,
+    /// even if it doesn't match the query. The other candidates for the query are still
+    /// returned as alternatives.
+    pub fn disambiguate(
+        &self,
+        query: &str,
+        k: usize,
+        pinned: Option<&str>,
+    ) -> Option<DisambiguatedEntity> {
+        let searcher = self.reader.searcher();
+        let query_terms = self.terms(query);
+
+        let mut ranked = disambiguation::rank(
+            &query_terms.iter().cloned().collect(),
+            self.candidates(&searcher, &query_terms),
+        );
 
-                EntityMatch {
-                    entity,
-                    score: *score,
+        let (best, confidence) = match pinned {
+            Some(id) => {
+                ranked.retain(|(candidate, _)| candidate.id != id);
+                (self.get(id)?, 1.0)
+            }
+            None => {
+                if ranked.is_empty() {
+                    return None;
                 }
+
+                let (candidate, confidence) = ranked.remove(0);
+                let entity =
+                    self.retrieve_stored_entity(&searcher, candidate.doc, true, true, true);
+
+                (
+                    EntityMatch {
+                        entity,
+                        score: candidate.score,
+                    },
+                    confidence,
+                )
+            }
+        };
+
+        let alternatives = ranked
+            .into_iter()
+            .take(k.saturating_sub(1))
+            .map(|(candidate, confidence)| EntityCandidate {
+                id: candidate.id,
+                title: candidate.title,
+                confidence,
+            })
+            .collect();
+
+        Some(DisambiguatedEntity {
+            best,
+            confidence,
+            alternatives,
+        })
+    }
+
+    pub fn search(&self, query: &str) -> Option<EntityMatch> {
+        self.disambiguate(query, 1, None)
+            .map(|disambiguated| disambiguated.best)
+    }
+
+    /// The entity with the id, i.e. the url of its article.
+    pub fn get(&self, id: &str) -> Option<EntityMatch> {
+        let searcher = self.reader.searcher();
+        let query = TermQuery::new(
+            Term::from_field_text(self.schema.get_field("id").unwrap(), id),
+            IndexRecordOption::Basic,
+        );
+
+        searcher
+            .search(&query, &TopDocs::with_limit(1))
+            .unwrap()
+            .first()
+            .map(|(score, doc_address)| EntityMatch {
+                entity: self.retrieve_stored_entity(&searcher, *doc_address, true, true, true),
+                score: *score,
             })
     }
 
@@ -324,6 +464,7 @@ impl EntityIndex {
         decode_info: bool,
         get_links: bool,
     ) -> StoredEntity {
+        let id = self.schema.get_field("id").unwrap();
         let title = self.schema.get_field("title").unwrap();
         let entity_abstract = self.schema.get_field("abstract").unwrap();
         let info = self.schema.get_field("info").unwrap();
@@ -331,6 +472,11 @@ impl EntityIndex {
         let image_field = self.schema.get_field("image").unwrap();
 
         let doc: TantivyDocument = searcher.doc(doc_address).unwrap();
+        let id = doc
+            .get_first(id)
+            .and_then(|val| val.as_str().map(|s| s.to_string()))
+            .unwrap_or_default();
+
         let title = doc
             .get_first(title)
             .and_then(|val| val.as_str().map(|s| s.to_string()))
@@ -391,6 +537,7 @@ impl EntityIndex {
         };
 
         StoredEntity {
+            id,
             title,
             entity_abstract,
             image_id,
@@ -446,6 +593,7 @@ mod tests {
             },
             info: Vec::new(),
             image: None,
+            inlinks: 0,
         });
 
         index.commit();
@@ -476,6 +624,7 @@ mod tests {
             },
             info: Vec::new(),
             image: Some("test".to_string()),
+            inlinks: 0,
         });
 
         index.commit();
@@ -494,4 +643,131 @@ mod tests {
             .retrieve_image(&index.search("ashes").unwrap().entity.image_id.unwrap())
             .is_some());
     }
+
+    fn mercury_index() -> EntityIndex {
+        let mut index = EntityIndex::open(crate::gen_temp_path()).unwrap();
+        index.prepare_writer();
+
+        for (id, text, inlinks) in [
+            (
+                "Mercury_(planet)",
+                "Mercury is the smallest planet in the Solar System and the closest planet to the Sun, with an orbit of 88 days.",
+                500,
+            ),
+            (
+                "Mercury_(element)",
+                "Mercury is a chemical element with the symbol Hg. It is a heavy silvery metal that is liquid at room temperature.",
+                200,
+            ),
+            (
+                "Mercury_(mythology)",
+                "Mercury is a major god in Roman religion and mythology, the messenger of the gods.",
+                100,
+            ),
+        ] {
+            index.insert(Entity {
+                article_url: id.to_string(),
+                is_disambiguation: false,
+                title: id.replace('_', " "),
+                page_abstract: Span::new(text),
+                info: Vec::new(),
+                image: None,
+                inlinks,
+            });
+        }
+
+        index.commit();
+
+        index
+    }
+
+    fn candidate_ids(disambiguated: &DisambiguatedEntity) -> Vec<&str> {
+        std::iter::once(disambiguated.best.entity.id.as_str())
+            .chain(disambiguated.alternatives.iter().map(|c| c.id.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn disambiguation_by_context() {
+        let index = mercury_index();
+
+        let res = index.disambiguate("mercury", NUM_CANDIDATES, None).unwrap();
+        assert_eq!(
+            candidate_ids(&res),
+            vec![
+                "Mercury_(planet)",
+                "Mercury_(element)",
+                "Mercury_(mythology)"
+            ]
+        );
+
+        let total: f32 =
+            res.confidence + res.alternatives.iter().map(|c| c.confidence).sum::<f32>();
+        assert!((total - 1.0).abs() < 1e-4);
+        assert!(res.confidence > res.alternatives[0].confidence);
+        assert!(res.alternatives[0].confidence > res.alternatives[1].confidence);
+
+        let res = index
+            .disambiguate("mercury roman god", NUM_CANDIDATES, None)
+            .unwrap();
+        assert_eq!(
+            candidate_ids(&res),
+            vec![
+                "Mercury_(mythology)",
+                "Mercury_(planet)",
+                "Mercury_(element)"
+            ]
+        );
+
+        let res = index
+            .disambiguate("mercury metal", NUM_CANDIDATES, None)
+            .unwrap();
+        assert_eq!(
+            candidate_ids(&res),
+            vec![
+                "Mercury_(element)",
+                "Mercury_(planet)",
+                "Mercury_(mythology)"
+            ]
+        );
+
+        let res = index.disambiguate("mercury", 2, None).unwrap();
+        assert_eq!(
+            candidate_ids(&res),
+            vec!["Mercury_(planet)", "Mercury_(element)"]
+        );
+
+        assert_eq!(
+            index.search("mercury planet").unwrap().entity.title,
+            "Mercury (planet)"
+        );
+    }
+
+    #[test]
+    fn pinned_entity() {
+        let index = mercury_index();
+
+        let res = index
+            .disambiguate("mercury", NUM_CANDIDATES, Some("Mercury_(mythology)"))
+            .unwrap();
+        assert_eq!(
+            candidate_ids(&res),
+            vec![
+                "Mercury_(mythology)",
+                "Mercury_(planet)",
+                "Mercury_(element)"
+            ]
+        );
+        assert_eq!(res.confidence, 1.0);
+
+        let res = index
+            .disambiguate("hg", NUM_CANDIDATES, Some("Mercury_(element)"))
+            .unwrap();
+        assert_eq!(res.best.entity.title, "Mercury (element)");
+        assert!(res.alternatives.is_empty());
+
+        assert!(index
+            .disambiguate("mercury", NUM_CANDIDATES, Some("Venus"))
+            .is_none());
+    }
 }
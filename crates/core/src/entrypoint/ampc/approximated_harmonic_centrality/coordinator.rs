@@ -134,6 +134,14 @@ impl Finisher for ApproxCentralityFinish {
     fn is_finished(&self, dht: &ApproxCentralityTables) -> bool {
         dht.meta.get(()).unwrap().round > 0
     }
+
+    fn estimated_rounds_remaining(&self, _: usize, dht: &ApproxCentralityTables) -> Option<usize> {
+        if self.is_finished(dht) {
+            Some(0)
+        } else {
+            Some(1)
+        }
+    }
 }
 
 pub fn build(
@@ -233,12 +241,17 @@ pub fn run(config: ApproxHarmonicCoordinatorConfig) -> Result<()> {
         })
         .collect();
 
-    let coordinator = build(
+    let mut coordinator = build(
         &cluster.dht,
         cluster.workers.clone(),
         config.sample_rate,
         config.save_centralities_with_zero,
     );
+
+    if let Some(status_path) = &config.status_path {
+        coordinator = coordinator.with_status_file(status_path);
+    }
+
     let res = coordinator.run(jobs, ApproxCentralityFinish)?;
 
     let output_path = Path::new(&config.output_path);
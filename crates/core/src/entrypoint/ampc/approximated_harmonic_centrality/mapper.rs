@@ -16,12 +16,19 @@
 
 use indicatif::ParallelProgressIterator;
 use itertools::Itertools;
-use std::{cmp, collections::BTreeMap};
+use std::{
+    cmp,
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+};
 
 use super::{worker::ApproxCentralityWorker, ApproxCentralityJob, Mapper};
 use crate::{
-    ampc::dht::upsert, entrypoint::ampc::approximated_harmonic_centrality::DhtTable,
-    kahan_sum::KahanSum, webgraph, webpage::html::links::RelFlags,
+    ampc::dht::upsert::{self, WriteId},
+    entrypoint::ampc::approximated_harmonic_centrality::DhtTable,
+    kahan_sum::KahanSum,
+    webgraph,
+    webpage::html::links::RelFlags,
 };
 use rayon::prelude::*;
 
@@ -38,6 +45,7 @@ pub static SKIPPED_REL: once_cell::sync::Lazy<RelFlags> = once_cell::sync::Lazy:
         | RelFlags::SEARCH
         | RelFlags::LINK_TAG
         | RelFlags::SCRIPT_TAG
+        | RelFlags::REDIRECT
 });
 
 #[derive(Debug, Clone, bincode::Decode, bincode::Encode)]
@@ -93,6 +101,14 @@ impl Workers {
     }
 }
 
+/// Key of the `i`th batch of distances from `node`.
+fn batch_key(node: webgraph::NodeID, i: usize) -> u64 {
+    // the default hasher uses fixed keys, so all workers derive the same key
+    let mut hasher = DefaultHasher::new();
+    (node.as_u64(), i).hash(&mut hasher);
+    hasher.finish()
+}
+
 impl Mapper for ApproxCentralityMapper {
     type Job = ApproxCentralityJob;
 
@@ -131,14 +147,16 @@ impl Mapper for ApproxCentralityMapper {
 
                 tracing::info!("Sampling {} nodes", num_samples);
 
+                // a retried job samples the same nodes and writes the same batches,
+                // so the write ids of the batches that were already applied are skipped
                 let sampled = worker
                     .graph()
-                    .random_nodes_with_outgoing(num_samples as usize);
+                    .random_nodes_with_outgoing(num_samples as usize, job.shard.as_u64());
 
                 let pb = indicatif::ProgressBar::new(sampled.len() as u64);
 
                 sampled.into_par_iter().for_each(|node| {
-                    for chunk in workers
+                    for (i, chunk) in workers
                         .dijkstra(node, job.max_distance)
                         .into_iter()
                         .filter_map(|(n, d)| {
@@ -150,11 +168,14 @@ impl Mapper for ApproxCentralityMapper {
                         })
                         .chunks(BATCH_SIZE)
                         .into_iter()
+                        .enumerate()
                     {
                         let pairs: Vec<_> = chunk.collect();
-                        dht.next()
-                            .centrality
-                            .batch_upsert(upsert::KahanSumAdd, pairs);
+                        dht.next().centrality.batch_upsert_once(
+                            upsert::KahanSumAdd,
+                            WriteId::new(job.shard.as_u64(), batch_key(node, i)),
+                            pairs,
+                        );
                     }
 
                     pb.inc(1);
@@ -14,14 +14,15 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
+use std::{collections::BTreeMap, net::SocketAddr, str::FromStr, sync::Arc};
 
 use anyhow::bail;
+use futures::future::join_all;
 use openraft::error::InitializeError;
 use tracing::info;
 
 use crate::{
-    ampc::dht::{self, BasicNode, ShardId},
+    ampc::dht::{self, maintenance::NodeStats, BasicNode, ShardId, Table},
     config::{DhtConfig, GossipConfig},
     distributed::{
         cluster::Cluster,
@@ -65,12 +66,12 @@ pub async fn run<C: Into<Config>>(config: C) -> Result<()> {
         config.node_id,
         raft_config,
         network,
-        log_store,
+        log_store.clone(),
         state_machine_store.clone(),
     )
     .await?;
 
-    let server = dht::Server::new(raft.clone(), state_machine_store)
+    let server = dht::Server::new(raft.clone(), log_store, state_machine_store)
         .bind(config.host)
         .await?;
 
@@ -133,6 +134,153 @@ pub async fn run<C: Into<Config>>(config: C) -> Result<()> {
     }
 }
 
+/// A node of the DHT, given on the command line as `<shard>@<host:port>`.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeAddr {
+    pub shard: ShardId,
+    pub host: SocketAddr,
+}
+
+impl FromStr for NodeAddr {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let err = || crate::Error::ParsingError(format!("expected <shard>@<host:port>, got '{s}'"));
+        let (shard, host) = s.split_once('@').ok_or_else(err)?;
+
+        Ok(Self {
+            shard: ShardId::new(shard.parse().map_err(|_| err())?),
+            host: host.parse().map_err(|_| err())?,
+        })
+    }
+}
+
+async fn inspect_nodes(nodes: &[NodeAddr]) -> Result<Vec<(NodeAddr, NodeStats)>> {
+    let futures = nodes.iter().map(|node| async move {
+        let stats = dht::RemoteClient::new(node.host).inspect().await?;
+        Ok((*node, stats))
+    });
+
+    join_all(futures).await.into_iter().collect()
+}
+
+/// The tables of each node, followed by how the keys of each table are distributed across
+/// the shards. The keys of a shard are counted on its leader if it was inspected.
+fn render(nodes: &[(NodeAddr, NodeStats)]) -> String {
+    let mut res = String::new();
+
+    for (node, stats) in nodes {
+        res.push_str(&format!(
+            "shard {} node {} ({}), {}, {} log entries, snapshot at {}\n",
+            node.shard.as_u64(),
+            stats.id,
+            node.host,
+            if stats.is_leader() {
+                "leader"
+            } else {
+                "follower"
+            },
+            stats.log_entries,
+            stats
+                .snapshot_index
+                .map(|index| index.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+        ));
+
+        res.push_str(&format!(
+            "{:>24} {:>12} {:>14} {:>12} {:>14} {:>14}\n",
+            "table", "keys", "data bytes", "log entries", "log bytes", "size bytes"
+        ));
+
+        for table in &stats.tables {
+            res.push_str(&format!(
+                "{:>24} {:>12} {:>14} {:>12} {:>14} {:>14}\n",
+                table.table.as_str(),
+                table.num_keys,
+                table.data_bytes,
+                table.log_entries,
+                table.log_bytes,
+                table.size_bytes(),
+            ));
+        }
+
+        res.push('\n');
+    }
+
+    let mut shards: BTreeMap<ShardId, &NodeStats> = BTreeMap::new();
+    for (node, stats) in nodes {
+        let shard = shards.entry(node.shard).or_insert(stats);
+
+        if stats.is_leader() {
+            *shard = stats;
+        }
+    }
+
+    let mut tables: BTreeMap<&Table, Vec<(ShardId, u64)>> = BTreeMap::new();
+    for (shard, stats) in &shards {
+        for table in &stats.tables {
+            tables
+                .entry(&table.table)
+                .or_default()
+                .push((*shard, table.num_keys));
+        }
+    }
+
+    res.push_str("keys per shard\n");
+    for (table, shards) in tables {
+        let total: u64 = shards.iter().map(|(_, keys)| keys).sum();
+
+        res.push_str(&format!("{:>24} {:>12} total", table.as_str(), total));
+
+        for (shard, keys) in shards {
+            let share = if total == 0 {
+                0.0
+            } else {
+                keys as f64 / total as f64 * 100.0
+            };
+
+            res.push_str(&format!(", shard {}: {keys} ({share:.1}%)", shard.as_u64()));
+        }
+
+        res.push('\n');
+    }
+
+    res
+}
+
+pub async fn inspect(nodes: Vec<NodeAddr>) -> Result<()> {
+    let stats = inspect_nodes(&nodes).await?;
+    print!("{}", render(&stats));
+
+    Ok(())
+}
+
+/// Compact the nodes one at a time, so a shard doesn't have all its replicas
+/// busy building snapshots at the same time.
+pub async fn compact(nodes: Vec<NodeAddr>) -> Result<()> {
+    let mut res = Vec::with_capacity(nodes.len());
+
+    for node in nodes {
+        let client = dht::RemoteClient::new(node.host);
+        let before = client.inspect().await?;
+        let after = client.compact().await?;
+
+        info!(
+            "compacted node {} of shard {} from {} to {} bytes",
+            after.id,
+            node.shard.as_u64(),
+            before.size_bytes(),
+            after.size_bytes()
+        );
+
+        res.push((node, after));
+    }
+
+    print!("{}", render(&res));
+
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -201,7 +201,12 @@ pub fn run(config: HarmonicCoordinatorConfig) -> Result<()> {
         })
         .collect();
 
-    let coordinator = build(&cluster.dht, cluster.workers.clone());
+    let mut coordinator = build(&cluster.dht, cluster.workers.clone());
+
+    if let Some(status_path) = &config.status_path {
+        coordinator = coordinator.with_status_file(status_path);
+    }
+
     let res = coordinator.run(jobs, CentralityFinish)?;
 
     let num_nodes = res.counters.num_keys();
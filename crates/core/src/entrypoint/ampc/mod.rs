@@ -14,6 +14,35 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::{path::Path, time::Duration};
+
+use crate::{ampc::progress::StatusFile, Result};
+
 pub mod approximated_harmonic_centrality;
 pub mod dht;
 pub mod harmonic_centrality;
+
+/// Print the progress of a computation from the status file written by its coordinator.
+/// With `watch`, the table is redrawn every second until the computation has finished.
+pub fn status<P: AsRef<Path>>(path: P, watch: bool) -> Result<()> {
+    let file = StatusFile::new(path);
+
+    loop {
+        let progress = file.read()?;
+
+        if watch {
+            // clear the screen and move the cursor to the top
+            print!("\x1B[2J\x1B[H");
+        }
+
+        print!("{}", progress.render());
+
+        if !watch || progress.finished {
+            break;
+        }
+
+        std::thread::sleep(Duration::from_secs(1));
+    }
+
+    Ok(())
+}
@@ -20,7 +20,7 @@ use anyhow::Result;
 use tokio::net::TcpListener;
 
 use crate::{
-    api::{metrics_router, router, user_count, Counters},
+    api::{metrics_router, rate_limit::RateLimitCounters, router, user_count, Counters},
     config,
     metrics::Label,
 };
@@ -30,6 +30,7 @@ pub async fn run(config: config::ApiConfig) -> Result<()> {
     let search_counter_fail = crate::metrics::Counter::default();
     let explore_counter = crate::metrics::Counter::default();
     let daily_active_users = user_count::UserCount::new()?;
+    let rate_limited = RateLimitCounters::default();
 
     let mut registry = crate::metrics::PrometheusRegistry::default();
 
@@ -71,11 +72,35 @@ pub async fn run(config: config::ApiConfig) -> Result<()> {
         .unwrap();
     group.register(daily_active_users.metric(), vec![]);
 
+    let group = registry
+        .new_group(
+            "stract_rate_limited_requests".to_string(),
+            Some("Total number of requests rejected by the rate limiter.".to_string()),
+        )
+        .unwrap();
+
+    for (route, counter) in [
+        ("search", &rate_limited.search),
+        ("autosuggest", &rate_limited.autosuggest),
+        ("api", &rate_limited.api),
+    ] {
+        group.register(
+            counter.clone(),
+            vec![Label {
+                key: "route".to_string(),
+                val: route.to_string(),
+            }],
+        );
+    }
+
+    crate::distributed::sonic::compression::METRICS.register(&mut registry);
+
     let counters = Counters {
         search_counter_success,
         search_counter_fail,
         explore_counter,
         daily_active_users,
+        rate_limited,
     };
 
     let app = router(&config, counters).await?;
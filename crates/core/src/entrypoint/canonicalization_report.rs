@@ -0,0 +1,146 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Report how many nodes of an existing webgraph would get a different id
+//! if their urls were canonicalized with the current canonicalization rules.
+
+use std::path::Path;
+
+use tracing::info;
+use url::Url;
+
+use crate::{
+    webgraph::{Node, Webgraph, WebgraphBuilder},
+    Result,
+};
+
+const PROGRESS_INTERVAL: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CanonicalizationReport {
+    pub num_nodes: u64,
+    pub num_changed: u64,
+    /// Nodes that could not be parsed as a url and are therefore left as is.
+    pub num_unparsable: u64,
+    /// A sample of the nodes that would change as `(old, new)` pairs.
+    pub examples: Vec<(Node, Node)>,
+}
+
+fn recanonicalize(node: &Node) -> Option<Node> {
+    let url = Url::parse(&format!("http://{}", node.as_str())).ok()?;
+    Some(Node::from(&url))
+}
+
+pub fn report(graph: &Webgraph, max_examples: usize) -> CanonicalizationReport {
+    let mut report = CanonicalizationReport::default();
+
+    for (node, id) in graph.node_ids() {
+        report.num_nodes += 1;
+
+        match recanonicalize(&node) {
+            Some(new) => {
+                if new.id() != id {
+                    report.num_changed += 1;
+
+                    if report.examples.len() < max_examples {
+                        report.examples.push((node, new));
+                    }
+                }
+            }
+            None => report.num_unparsable += 1,
+        }
+
+        if report.num_nodes % PROGRESS_INTERVAL == 0 {
+            info!(
+                "checked {} nodes, {} would change id",
+                report.num_nodes, report.num_changed
+            );
+        }
+    }
+
+    report
+}
+
+pub fn run<P: AsRef<Path>>(graph_path: P, max_examples: usize) -> Result<()> {
+    let graph = WebgraphBuilder::new(graph_path).single_threaded().open();
+    let report = report(&graph, max_examples);
+
+    for (old, new) in &report.examples {
+        info!("{} -> {}", old.as_str(), new.as_str());
+    }
+
+    info!(
+        "{} of {} nodes would change id ({} could not be parsed)",
+        report.num_changed, report.num_nodes, report.num_unparsable
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        executor::Executor,
+        webgraph::{Compression, WebgraphWriter},
+        webpage::html::links::RelFlags,
+    };
+
+    use super::*;
+
+    #[test]
+    fn counts_changed_nodes() {
+        let mut writer = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        );
+
+        // names as they were produced before percent-escapes were normalized
+        // and `yclid` was considered a tracking parameter.
+        for from in ["a.com/%7euser", "c.com/?yclid=1", "d.com/page"] {
+            writer.insert(
+                Node::from_raw(from.to_string()),
+                Node::from("b.com/page"),
+                String::new(),
+                RelFlags::default(),
+            );
+        }
+        let graph = writer.finalize();
+
+        let mut res = report(&graph, 10);
+        assert_eq!(res.num_nodes, 4);
+        assert_eq!(res.num_changed, 2);
+        assert_eq!(res.num_unparsable, 0);
+
+        res.examples.sort();
+        assert_eq!(
+            res.examples,
+            vec![
+                (
+                    Node::from_raw("a.com/%7euser".to_string()),
+                    Node::from("a.com/~user")
+                ),
+                (
+                    Node::from_raw("c.com/?yclid=1".to_string()),
+                    Node::from("c.com")
+                ),
+            ]
+        );
+
+        assert_eq!(report(&graph, 1).examples.len(), 1);
+    }
+}
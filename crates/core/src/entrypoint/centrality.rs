@@ -15,20 +15,56 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use anyhow::Result;
-use std::{cmp::Reverse, path::Path};
+use std::{cmp::Reverse, fmt::Display, fs::File, io::BufWriter, path::Path, str::FromStr};
 
 use crate::{
     external_sort::ExternalSorter,
     webgraph::{
         centrality::{
-            approx_harmonic::ApproxHarmonic, harmonic::HarmonicCentrality, store_csv,
-            store_harmonic, TopNodes,
+            approx_harmonic::ApproxHarmonic,
+            arrow::{export_arrow, export_parquet},
+            export_csv,
+            harmonic::HarmonicCentrality,
+            store_csv, store_harmonic, TopNodes,
         },
         WebgraphBuilder,
     },
     SortableFloat,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CentralityExportFormat {
+    Csv,
+    /// An Arrow IPC file, see [`crate::webgraph::centrality::arrow`].
+    Arrow,
+    /// A Parquet file with the same columns as the Arrow IPC file.
+    Parquet,
+}
+
+impl Display for CentralityExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Csv => "csv",
+            Self::Arrow => "arrow",
+            Self::Parquet => "parquet",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for CentralityExportFormat {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "arrow" => Ok(Self::Arrow),
+            "parquet" => Ok(Self::Parquet),
+            _ => Err(crate::Error::UnknownCLIOption),
+        }
+    }
+}
+
 pub struct Centrality;
 
 impl Centrality {
@@ -97,4 +133,34 @@ impl Centrality {
 
         Ok(())
     }
+
+    /// Export the centralities computed by [`Self::build_harmonic`] or
+    /// [`Self::build_approx_harmonic`] into `centrality_path`, with the node ids resolved to
+    /// hosts using the webgraph the centralities were computed from.
+    pub fn export<P: AsRef<Path>>(
+        centrality_path: P,
+        webgraph_path: P,
+        output: P,
+        format: CentralityExportFormat,
+    ) -> Result<()> {
+        let store: speedy_kv::Db<crate::webgraph::NodeID, f64> =
+            speedy_kv::Db::open_or_create(centrality_path.as_ref().join("harmonic"))?;
+        let graph = WebgraphBuilder::new(webgraph_path).single_threaded().open();
+
+        let writer = BufWriter::new(File::create(output.as_ref())?);
+
+        let rows = match format {
+            CentralityExportFormat::Csv => export_csv(&store, &graph, writer)?,
+            CentralityExportFormat::Arrow => export_arrow(&store, &graph, writer)?,
+            CentralityExportFormat::Parquet => export_parquet(&store, &graph, writer)?,
+        };
+
+        tracing::info!(
+            "exported {} centralities to {}",
+            rows,
+            output.as_ref().display()
+        );
+
+        Ok(())
+    }
 }
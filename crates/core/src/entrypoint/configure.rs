@@ -24,10 +24,10 @@ use crate::config::{
     WebSpellConfig,
 };
 use crate::entrypoint::indexer::JobSettings;
-use crate::entrypoint::{dmoz_parser, indexer};
+use crate::entrypoint::{dmoz_parser, indexer, sample_crawl};
 use crate::Result;
 use std::fs::{self};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use super::{webgraph, Centrality, EntityIndexer};
 
@@ -74,7 +74,7 @@ fn download_files() {
         });
 }
 
-fn build_spellchecker() -> Result<()> {
+fn build_spellchecker(warc_path: &Path) -> Result<()> {
     debug!("Building spellchecker");
     let spellchecker_path = Path::new(DATA_PATH).join("web_spell");
 
@@ -84,11 +84,7 @@ fn build_spellchecker() -> Result<()> {
             output_path: spellchecker_path.to_str().unwrap().to_string(),
             warc_source: crate::config::WarcSource::Local(LocalConfig {
                 folder: ".".to_string(),
-                names: vec![Path::new(DATA_PATH)
-                    .join("sample.warc.gz")
-                    .to_str()
-                    .unwrap()
-                    .to_string()],
+                names: vec![warc_path.to_str().unwrap().to_string()],
             }),
             limit_warc_files: None,
             skip_warc_files: None,
@@ -98,7 +94,7 @@ fn build_spellchecker() -> Result<()> {
     Ok(())
 }
 
-fn create_webgraph() -> Result<()> {
+fn create_webgraph(warc_path: &Path) -> Result<()> {
     debug!("Creating webgraph");
     let out_path_host = Path::new(DATA_PATH).join("webgraph_host");
     let out_path_page = Path::new(DATA_PATH).join("webgraph_page");
@@ -111,8 +107,6 @@ fn create_webgraph() -> Result<()> {
         std::fs::remove_dir_all(&out_path_page)?;
     }
 
-    let warc_path = Path::new(DATA_PATH).join("sample.warc.gz");
-
     let job = webgraph::Job {
         config: webgraph::JobConfig::Local(crate::config::LocalConfig {
             folder: ".".to_string(),
@@ -152,7 +146,7 @@ fn calculate_centrality() {
     }
 }
 
-fn create_inverted_index() -> Result<()> {
+fn create_inverted_index(warc_path: &Path) -> Result<()> {
     debug!("Creating inverted index");
     let out_path = Path::new(DATA_PATH).join("index");
 
@@ -160,8 +154,6 @@ fn create_inverted_index() -> Result<()> {
         std::fs::remove_dir_all(&out_path)?;
     }
 
-    let warc_path = Path::new(DATA_PATH).join("sample.warc.gz");
-
     let job = indexer::Job {
         source_config: crate::config::WarcSource::Local(crate::config::LocalConfig {
             folder: ".".to_string(),
@@ -174,6 +166,9 @@ fn create_inverted_index() -> Result<()> {
             minimum_clean_words: None,
             batch_size: defaults::Indexing::batch_size(),
             autocommit_after_num_inserts: defaults::Indexing::autocommit_after_num_inserts(),
+            main_content_extraction: defaults::Indexing::main_content_extraction(),
+            freshness_buckets_days: defaults::Indexing::freshness_buckets_days(),
+            tokenization: Default::default(),
         },
     };
 
@@ -204,6 +199,10 @@ fn create_inverted_index() -> Result<()> {
         minimum_clean_words: None,
         batch_size: defaults::Indexing::batch_size(),
         autocommit_after_num_inserts: defaults::Indexing::autocommit_after_num_inserts(),
+        main_content_extraction: defaults::Indexing::main_content_extraction(),
+        freshness_buckets_days: defaults::Indexing::freshness_buckets_days(),
+        tokenization: Default::default(),
+        term_pruning: None,
         dual_encoder: Some(IndexerDualEncoderConfig {
             model_path: dual_encoder_path.to_str().unwrap().to_string(),
             page_centrality_rank_threshold: Some(100_000),
@@ -239,18 +238,45 @@ fn parse_topics() -> Result<()> {
     topics.save(Path::new(DATA_PATH).join("human_annotations"))
 }
 
-fn index_files() -> Result<()> {
-    create_webgraph()?;
+/// Sample the downloaded crawl into a single warc file.
+fn sample_downloaded_crawl(num_docs: usize) -> Result<PathBuf> {
+    debug!("Sampling crawl");
+    let out_path = Path::new(DATA_PATH).join("sample_crawl");
+
+    if out_path.exists() {
+        std::fs::remove_dir_all(&out_path)?;
+    }
+
+    let manifest = sample_crawl::sample(&sample_crawl::SampleConfig {
+        input_dirs: vec![Path::new(DATA_PATH).join("sample.warc.gz")],
+        out: out_path.clone(),
+        target_docs: num_docs,
+        strategy: sample_crawl::SampleStrategy::Uniform,
+        per_host_cap: 0,
+        centrality_store: None,
+        seed: 0,
+        docs_per_file: num_docs,
+    })?;
+
+    manifest
+        .warc_paths(&out_path)
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("the sample of the crawl is empty"))
+}
+
+fn index_files(warc_path: &Path) -> Result<()> {
+    create_webgraph(warc_path)?;
     calculate_centrality();
     parse_topics()?;
-    create_inverted_index()?;
+    create_inverted_index(warc_path)?;
     create_entity_index()?;
-    build_spellchecker()?;
+    build_spellchecker(warc_path)?;
 
     Ok(())
 }
 
-pub fn run(skip_download: bool) -> Result<()> {
+pub fn run(skip_download: bool, sample_docs: Option<usize>) -> Result<()> {
     let p = Path::new(DATA_PATH);
 
     if !p.exists() {
@@ -261,7 +287,12 @@ pub fn run(skip_download: bool) -> Result<()> {
         download_files();
     }
 
-    index_files()?;
+    let warc_path = match sample_docs {
+        Some(num_docs) => sample_downloaded_crawl(num_docs)?,
+        None => Path::new(DATA_PATH).join("sample.warc.gz"),
+    };
+
+    index_files(&warc_path)?;
 
     Ok(())
 }
@@ -18,8 +18,12 @@ use std::{net::SocketAddr, sync::Arc};
 
 use crate::{
     config,
-    crawler::{self, planner::CrawlPlanner, CrawlCoordinator, Crawler},
+    crawler::{
+        self, coordinator::LanguageBudget, domain_filter::DomainFilter, planner::CrawlPlanner,
+        robots_audit, CrawlCoordinator, Crawler,
+    },
     distributed::sonic::service::{sonic_service, Message},
+    host_languages::HostLanguageStore,
     Result,
 };
 
@@ -32,7 +36,24 @@ pub async fn worker(config: config::CrawlerConfig) -> Result<()> {
 }
 
 pub async fn coordinator(config: config::CrawlCoordinatorConfig) -> Result<()> {
-    let coordinator = Arc::new(CrawlCoordinator::new(config.job_queue)?);
+    let filter = DomainFilter::new(config.blocklist, config.allowlist);
+    let mut coordinator = CrawlCoordinator::new(config.job_queue, filter)?
+        .with_intake_rules(config.intake_rules)?
+        .with_retry(config.retry)
+        .with_crawl_space(config.crawl_space)
+        .with_max_depth(config.max_depth)
+        .with_max_pages_per_host(config.max_pages_per_host)
+        .with_rate_overrides(config.rate_overrides)?;
+
+    if let Some(path) = config.host_languages {
+        let budget = LanguageBudget::new(
+            HostLanguageStore::open(path)?,
+            config.language_budget_multipliers,
+        )?;
+        coordinator = coordinator.with_language_budget(budget);
+    }
+
+    let coordinator = Arc::new(coordinator);
 
     let addr: SocketAddr = config.host;
     let server = coordinator::CoordinatorService { coordinator }
@@ -82,15 +103,62 @@ pub async fn planner(config: config::CrawlPlannerConfig) -> Result<()> {
     Ok(())
 }
 
+/// Print the robots.txt evidence of every fetch of the host in the crawl directory,
+/// followed by the fetches the recorded robots.txt did not allow.
+pub fn audit(crawl_dir: &str, host: &str, user_agent: &str) -> Result<()> {
+    let report = robots_audit::audit(crawl_dir, host, user_agent)?;
+
+    for fetch in &report.fetches {
+        let (fetched_at, content_hash) = match &fetch.version {
+            Some(version) => (
+                version.fetched_at.to_string(),
+                version
+                    .content_hash
+                    .clone()
+                    .unwrap_or_else(|| "no robots.txt".to_string()),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+
+        println!(
+            "{:?}\t{}\t{}\t{}\t{}\t{}",
+            fetch.verdict,
+            fetch.url,
+            fetched_at,
+            content_hash,
+            fetch.rule.as_deref().unwrap_or("-"),
+            fetch.warc_file,
+        );
+    }
+
+    let disallowed: Vec<_> = report.disallowed().collect();
+    println!(
+        "{} fetches of {}, {} disallowed by the recorded robots.txt, {} without evidence",
+        report.fetches.len(),
+        host,
+        disallowed.len(),
+        report.missing_evidence().count()
+    );
+
+    for fetch in disallowed {
+        println!("disallowed: {} ({})", fetch.url, fetch.warc_file);
+    }
+
+    Ok(())
+}
+
 pub mod router {
-    use crate::crawler::Job;
+    use crate::crawler::{ContentFingerprint, FailedUrl, Job, ScheduledJob};
 
     use super::*;
     pub struct RouterService {
         pub router: crawler::Router,
     }
 
-    sonic_service!(RouterService, [NewJob]);
+    sonic_service!(
+        RouterService,
+        [NewJob, AdmitUrls, ReportFailed, ReportContent]
+    );
 
     #[derive(
         Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
@@ -98,16 +166,85 @@ pub mod router {
     pub struct NewJob {}
 
     impl Message<RouterService> for NewJob {
-        type Response = Option<Job>;
+        type Response = Option<ScheduledJob>;
 
         async fn handle(self, server: &RouterService) -> Self::Response {
             server.router.sample_job().await.ok().flatten()
         }
     }
+
+    /// Ask the coordinator of a job which of the urls the job wants to wander to may be
+    /// crawled. Responds with the admitted urls, or `None` if the coordinator couldn't be asked.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct AdmitUrls {
+        pub coordinator: SocketAddr,
+        pub job: Job,
+    }
+
+    impl Message<RouterService> for AdmitUrls {
+        type Response = Option<Job>;
+
+        async fn handle(self, server: &RouterService) -> Self::Response {
+            server
+                .router
+                .admit(self.coordinator, self.job)
+                .await
+                .map_err(|err| tracing::error!("failed to admit urls: {}", err))
+                .ok()
+        }
+    }
+
+    /// Report the urls of a job that failed with a transient error, so they can be retried.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct ReportFailed(pub Vec<FailedUrl>);
+
+    impl Message<RouterService> for ReportFailed {
+        type Response = bool;
+
+        async fn handle(self, server: &RouterService) -> Self::Response {
+            server
+                .router
+                .report_failed(self.0)
+                .await
+                .map_err(|err| tracing::error!("failed to report failed urls: {}", err))
+                .is_ok()
+        }
+    }
+
+    /// Report the fingerprints of the pages fetched by a job.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct ReportContent(pub Vec<ContentFingerprint>);
+
+    impl Message<RouterService> for ReportContent {
+        type Response = bool;
+
+        async fn handle(self, server: &RouterService) -> Self::Response {
+            server
+                .router
+                .report_content(self.0)
+                .await
+                .map_err(|err| tracing::error!("failed to report content: {}", err))
+                .is_ok()
+        }
+    }
 }
 
 pub mod coordinator {
-    use crate::crawler::Job;
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::{
+        config::CrawlRateOverride,
+        crawler::{
+            crawl_space::{CrawlSpace, CrawlSpaceOverride},
+            ContentFingerprint, FailedUrl, Job, SampledJob,
+        },
+    };
 
     use super::*;
 
@@ -115,7 +252,24 @@ pub mod coordinator {
         pub coordinator: Arc<CrawlCoordinator>,
     }
 
-    sonic_service!(CoordinatorService, [GetJob]);
+    sonic_service!(
+        CoordinatorService,
+        [
+            GetJob,
+            AdmitUrls,
+            AddBlocked,
+            RemoveBlocked,
+            SetAllowlist,
+            SetIntakeRules,
+            GetIntakeExclusions,
+            ReportFailed,
+            ReportContent,
+            GetCrawlSpaces,
+            SetCrawlSpaceOverride,
+            GetRateOverrides,
+            SetRateOverride
+        ]
+    );
 
     #[derive(
         Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
@@ -123,10 +277,222 @@ pub mod coordinator {
     pub struct GetJob {}
 
     impl Message<CoordinatorService> for GetJob {
-        type Response = Option<Job>;
+        type Response = Option<SampledJob>;
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            let job = server.coordinator.sample_job().ok().flatten()?;
+
+            Some(SampledJob {
+                rate_overrides: server.coordinator.rate_overrides_for(&job.domain),
+                max_depth: server.coordinator.max_depth(),
+                job,
+            })
+        }
+    }
+
+    /// Admit the urls a worker wants to wander to while crawling the job.
+    /// Responds with the job of the admitted urls.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct AdmitUrls(pub Job);
+
+    impl Message<CoordinatorService> for AdmitUrls {
+        type Response = Job;
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            let mut job = self.0;
+            server.coordinator.admit(&mut job);
+            job
+        }
+    }
+
+    /// Block domains from being crawled. Responds with the number of pending urls dropped.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct AddBlocked(pub Vec<String>);
+
+    impl Message<CoordinatorService> for AddBlocked {
+        type Response = Option<usize>;
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            server
+                .coordinator
+                .add_blocked(self.0)
+                .map_err(|err| tracing::error!("failed to update blocklist: {}", err))
+                .ok()
+        }
+    }
+
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct RemoveBlocked(pub Vec<String>);
+
+    impl Message<CoordinatorService> for RemoveBlocked {
+        type Response = bool;
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            server
+                .coordinator
+                .remove_blocked(self.0)
+                .map_err(|err| tracing::error!("failed to update blocklist: {}", err))
+                .is_ok()
+        }
+    }
+
+    /// Restrict the crawl to the domains in the allowlist, or remove the restriction with `None`.
+    /// Responds with the number of pending urls dropped.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct SetAllowlist(pub Option<Vec<String>>);
+
+    impl Message<CoordinatorService> for SetAllowlist {
+        type Response = Option<usize>;
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            server
+                .coordinator
+                .set_allowlist(self.0)
+                .map_err(|err| tracing::error!("failed to update allowlist: {}", err))
+                .ok()
+        }
+    }
+
+    /// Replace the rules that exclude urls as they are discovered.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct SetIntakeRules(pub config::CrawlIntakeRulesConfig);
+
+    impl Message<CoordinatorService> for SetIntakeRules {
+        type Response = bool;
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            server
+                .coordinator
+                .set_intake_rules(self.0)
+                .map_err(|err| tracing::error!("failed to update intake rules: {}", err))
+                .is_ok()
+        }
+    }
+
+    /// Responds with the number of urls excluded by each intake rule.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct GetIntakeExclusions {}
+
+    impl Message<CoordinatorService> for GetIntakeExclusions {
+        type Response = BTreeMap<String, u64>;
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            server.coordinator.intake_exclusions()
+        }
+    }
+
+    /// Schedule retries of urls that failed with a transient error.
+    /// Responds with the number of urls that exhausted their retries.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct ReportFailed(pub Vec<FailedUrl>);
+
+    impl Message<CoordinatorService> for ReportFailed {
+        type Response = Option<usize>;
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            server
+                .coordinator
+                .report_failed(self.0)
+                .map_err(|err| tracing::error!("failed to schedule retries: {}", err))
+                .ok()
+        }
+    }
+
+    /// Record the fingerprints of fetched pages for the detection of crawl spaces.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct ReportContent(pub Vec<ContentFingerprint>);
+
+    impl Message<CoordinatorService> for ReportContent {
+        type Response = ();
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            server.coordinator.report_content(self.0)
+        }
+    }
+
+    /// Responds with the crawl spaces that are currently throttled.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct GetCrawlSpaces {}
+
+    impl Message<CoordinatorService> for GetCrawlSpaces {
+        type Response = Vec<CrawlSpace>;
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            server.coordinator.crawl_spaces()
+        }
+    }
+
+    /// Override the detection of crawl spaces for a host, or remove the override with `None`.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct SetCrawlSpaceOverride {
+        pub host: String,
+        pub crawl_space_override: Option<CrawlSpaceOverride>,
+    }
+
+    impl Message<CoordinatorService> for SetCrawlSpaceOverride {
+        type Response = bool;
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            server
+                .coordinator
+                .set_crawl_space_override(self.host, self.crawl_space_override)
+                .map_err(|err| tracing::error!("failed to update crawl space overrides: {}", err))
+                .is_ok()
+        }
+    }
+
+    /// Responds with the negotiated crawl rates, keyed by host.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct GetRateOverrides {}
+
+    impl Message<CoordinatorService> for GetRateOverrides {
+        type Response = HashMap<String, CrawlRateOverride>;
+
+        async fn handle(self, server: &CoordinatorService) -> Self::Response {
+            server.coordinator.rate_overrides()
+        }
+    }
+
+    /// Set the negotiated crawl rate of a host, or remove it with `None`.
+    #[derive(
+        Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+    )]
+    pub struct SetRateOverride {
+        pub host: String,
+        pub rate_override: Option<CrawlRateOverride>,
+    }
+
+    impl Message<CoordinatorService> for SetRateOverride {
+        type Response = bool;
 
         async fn handle(self, server: &CoordinatorService) -> Self::Response {
-            server.coordinator.sample_job().ok().flatten()
+            server
+                .coordinator
+                .set_rate_override(self.host, self.rate_override)
+                .map_err(|err| tracing::error!("failed to update rate overrides: {}", err))
+                .is_ok()
         }
     }
 }
@@ -14,6 +14,8 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::collections::HashMap;
+
 use kuchiki::{traits::TendrilSink, NodeRef};
 use zimba::{Article, ArticleIterator, ZimFile};
 
@@ -125,6 +127,7 @@ fn article_to_entity(article: Article) -> Entity {
         page_abstract,
         image,
         info,
+        inlinks: 0,
     }
 }
 
@@ -188,11 +191,43 @@ fn node_into_span(node: &NodeRef) -> Span {
     span
 }
 
+/// The article a link points to.
+fn link_target(href: &str) -> &str {
+    let target = href.split('#').next().unwrap_or(href);
+    target.trim_start_matches("./")
+}
+
+/// The number of links to each article from the abstracts and infoboxes of the other
+/// articles. Links from disambiguation pages are not counted, as they link to every
+/// entity with the name.
+fn count_inlinks(zim: &ZimFile) -> Result<HashMap<String, u64>> {
+    let mut inlinks = HashMap::new();
+
+    for entity in EntityIterator::new(zim)?.filter(|e| !e.is_disambiguation) {
+        let links = entity
+            .page_abstract
+            .links
+            .iter()
+            .chain(entity.info.iter().flat_map(|(_, span)| span.links.iter()));
+
+        for link in links {
+            let target = link_target(&link.target);
+
+            if target != entity.article_url {
+                *inlinks.entry(target.to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    Ok(inlinks)
+}
+
 pub struct EntityIndexer;
 
 impl EntityIndexer {
     pub fn run(wikipedia_dump_path: String, output_path: String) -> Result<()> {
         let zim = ZimFile::open(wikipedia_dump_path)?;
+        let inlinks = count_inlinks(&zim)?;
         let mut index = EntityIndex::open(output_path)?;
         index.prepare_writer();
 
@@ -200,10 +235,12 @@ impl EntityIndexer {
 
         let mut inserts = 0;
 
-        for entity in EntityIterator::new(&zim)?
+        for mut entity in EntityIterator::new(&zim)?
             .filter(|e| !e.is_disambiguation)
             .filter(|e| !e.article_url.starts_with("Portal:"))
         {
+            entity.inlinks = inlinks.get(&entity.article_url).copied().unwrap_or(0);
+
             if let Some(image) = entity.image.as_ref() {
                 image_bloom.insert(image);
             }
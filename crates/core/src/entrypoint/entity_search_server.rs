@@ -59,12 +59,17 @@ impl SearchService {
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
 pub struct Search {
     pub query: String,
+    pub pinned_entity: Option<String>,
 }
 
 impl sonic::service::Message<SearchService> for Search {
-    type Response = Option<crate::entity_index::EntityMatch>;
+    type Response = Option<crate::entity_index::DisambiguatedEntity>;
     async fn handle(self, server: &SearchService) -> Self::Response {
-        server.index.search(&self.query)
+        server.index.disambiguate(
+            &self.query,
+            crate::entity_index::NUM_CANDIDATES,
+            self.pinned_entity.as_deref(),
+        )
     }
 }
 
@@ -0,0 +1,632 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Offline relevance evaluation of the ranking against a set of graded judgments.
+//!
+//! The judgments are a csv file with the columns `query,url,grade` where `grade` is
+//! `0` for irrelevant results and higher for more relevant results. Every query is
+//! searched against a local index and the results are scored using NDCG@10, MRR and recall@100.
+//! The mean of the metrics can be compared against a baseline to catch ranking regressions.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use optics::Optic;
+use tracing::info;
+use url::Url;
+
+use crate::{
+    index::Index,
+    searcher::{LocalSearcher, SearchQuery},
+    Result,
+};
+
+/// Bump this when the metrics change in a way that makes old baselines incomparable.
+pub const BASELINE_VERSION: u32 = 1;
+
+pub const NDCG_K: usize = 10;
+pub const RECALL_K: usize = 100;
+
+/// Metrics are allowed to drop this much below the baseline before it is considered a regression.
+const TOLERANCE: f64 = 1e-6;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Judgment {
+    pub query: String,
+    pub url: String,
+    pub grade: u8,
+}
+
+/// The graded urls for each query.
+#[derive(Debug, Clone, Default)]
+pub struct Judgments(BTreeMap<String, BTreeMap<String, u8>>);
+
+impl Judgments {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut judgments = Vec::new();
+
+        for judgment in reader.deserialize() {
+            judgments.push(judgment?);
+        }
+
+        Ok(Self::new(judgments))
+    }
+
+    pub fn new(judgments: Vec<Judgment>) -> Self {
+        let mut res: BTreeMap<String, BTreeMap<String, u8>> = BTreeMap::new();
+
+        for judgment in judgments {
+            res.entry(judgment.query.trim().to_string())
+                .or_default()
+                .insert(normalize_url(&judgment.url), judgment.grade);
+        }
+
+        Self(res)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The queries in lexicographic order, so the evaluation always runs in the same order.
+    pub fn queries(&self) -> impl Iterator<Item = (&str, &BTreeMap<String, u8>)> {
+        self.0
+            .iter()
+            .map(|(query, grades)| (query.as_str(), grades))
+    }
+}
+
+fn normalize_url(url: &str) -> String {
+    Url::parse(url.trim())
+        .map(|url| url.to_string())
+        .unwrap_or_else(|_| url.trim().to_string())
+}
+
+fn gain(grade: u8) -> f64 {
+    2f64.powi(grade as i32) - 1.0
+}
+
+fn dcg(grades: impl Iterator<Item = u8>) -> f64 {
+    grades
+        .enumerate()
+        .map(|(rank, grade)| gain(grade) / (rank as f64 + 2.0).log2())
+        .sum()
+}
+
+/// Normalized discounted cumulative gain of the top `k` results.
+/// Unjudged results have grade 0. Returns 0 if the query has no relevant results.
+pub fn ndcg_at_k(results: &[String], grades: &BTreeMap<String, u8>, k: usize) -> f64 {
+    let mut ideal: Vec<_> = grades.values().copied().collect();
+    ideal.sort_unstable_by(|a, b| b.cmp(a));
+
+    let ideal = dcg(ideal.into_iter().take(k));
+
+    if ideal == 0.0 {
+        return 0.0;
+    }
+
+    let actual = results
+        .iter()
+        .take(k)
+        .map(|url| grades.get(url).copied().unwrap_or(0));
+
+    dcg(actual) / ideal
+}
+
+/// The reciprocal rank of the first relevant result, or 0 if none of the results are relevant.
+pub fn reciprocal_rank(results: &[String], grades: &BTreeMap<String, u8>) -> f64 {
+    results
+        .iter()
+        .position(|url| grades.get(url).is_some_and(|grade| *grade > 0))
+        .map(|rank| 1.0 / (rank as f64 + 1.0))
+        .unwrap_or(0.0)
+}
+
+/// The fraction of relevant urls found in the top `k` results.
+/// Returns 0 if the query has no relevant results.
+pub fn recall_at_k(results: &[String], grades: &BTreeMap<String, u8>, k: usize) -> f64 {
+    let relevant: HashSet<_> = grades
+        .iter()
+        .filter(|(_, grade)| **grade > 0)
+        .map(|(url, _)| url)
+        .collect();
+
+    if relevant.is_empty() {
+        return 0.0;
+    }
+
+    let found = results
+        .iter()
+        .take(k)
+        .collect::<HashSet<_>>()
+        .intersection(&relevant)
+        .count();
+
+    found as f64 / relevant.len() as f64
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Metrics {
+    pub ndcg_at_10: f64,
+    pub mrr: f64,
+    pub recall_at_100: f64,
+}
+
+impl Metrics {
+    pub fn new(results: &[String], grades: &BTreeMap<String, u8>) -> Self {
+        Self {
+            ndcg_at_10: ndcg_at_k(results, grades, NDCG_K),
+            mrr: reciprocal_rank(results, grades),
+            recall_at_100: recall_at_k(results, grades, RECALL_K),
+        }
+    }
+
+    pub fn mean<'a>(metrics: impl Iterator<Item = &'a Metrics>) -> Self {
+        let mut res = Self::default();
+        let mut n = 0;
+
+        for m in metrics {
+            res.ndcg_at_10 += m.ndcg_at_10;
+            res.mrr += m.mrr;
+            res.recall_at_100 += m.recall_at_100;
+            n += 1;
+        }
+
+        if n > 0 {
+            res.ndcg_at_10 /= n as f64;
+            res.mrr /= n as f64;
+            res.recall_at_100 /= n as f64;
+        }
+
+        res
+    }
+
+    /// The names of the metrics that are below `baseline`.
+    pub fn regressions(&self, baseline: &Self) -> Vec<&'static str> {
+        [
+            ("ndcg@10", self.ndcg_at_10, baseline.ndcg_at_10),
+            ("mrr", self.mrr, baseline.mrr),
+            ("recall@100", self.recall_at_100, baseline.recall_at_100),
+        ]
+        .into_iter()
+        .filter(|(_, current, baseline)| *current < *baseline - TOLERANCE)
+        .map(|(name, _, _)| name)
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryEvaluation {
+    pub query: String,
+    pub num_results: usize,
+    pub metrics: Metrics,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub queries: Vec<QueryEvaluation>,
+    pub mean: Metrics,
+}
+
+impl Report {
+    fn log(&self) {
+        for query in &self.queries {
+            info!(
+                "{:?}: ndcg@10={:.4} mrr={:.4} recall@100={:.4} ({} results)",
+                query.query,
+                query.metrics.ndcg_at_10,
+                query.metrics.mrr,
+                query.metrics.recall_at_100,
+                query.num_results
+            );
+        }
+
+        info!(
+            "mean over {} queries: ndcg@10={:.4} mrr={:.4} recall@100={:.4}",
+            self.queries.len(),
+            self.mean.ndcg_at_10,
+            self.mean.mrr,
+            self.mean.recall_at_100
+        );
+    }
+}
+
+/// The metrics a ranking change is not allowed to go below.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Baseline {
+    pub version: u32,
+    /// The time (seconds since the unix epoch) the time dependent signals were computed relative to.
+    pub timestamp: usize,
+    pub metrics: Metrics,
+}
+
+impl Baseline {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let baseline: Self = serde_json::from_reader(file)?;
+
+        if baseline.version != BASELINE_VERSION {
+            return Err(anyhow!(
+                "baseline has version {} but the current version is {}. Run with --update-baseline to regenerate it",
+                baseline.version,
+                BASELINE_VERSION
+            ));
+        }
+
+        Ok(baseline)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+
+        Ok(())
+    }
+}
+
+/// Search every judged query and compute the metrics of the results.
+///
+/// The evaluation is deterministic: queries run in lexicographic order, time dependent
+/// signals are computed relative to `timestamp`, the segments are searched on a single thread
+/// and ties in the collector are broken by the url hash.
+pub fn evaluate(
+    searcher: &mut LocalSearcher<Index>,
+    judgments: &Judgments,
+    optic: Option<&Optic>,
+    timestamp: usize,
+) -> Result<Report> {
+    searcher.set_current_timestamp(timestamp);
+
+    let mut queries = Vec::with_capacity(judgments.len());
+
+    for (query, grades) in judgments.queries() {
+        let result = searcher.search(&SearchQuery {
+            query: query.to_string(),
+            num_results: RECALL_K,
+            optic: optic.cloned(),
+            ..Default::default()
+        })?;
+
+        let urls: Vec<_> = result
+            .webpages
+            .iter()
+            .map(|webpage| normalize_url(&webpage.url))
+            .collect();
+
+        queries.push(QueryEvaluation {
+            query: query.to_string(),
+            num_results: urls.len(),
+            metrics: Metrics::new(&urls, grades),
+        });
+    }
+
+    let mean = Metrics::mean(queries.iter().map(|q| &q.metrics));
+
+    Ok(Report { queries, mean })
+}
+
+pub fn run<P: AsRef<Path>>(
+    index_path: P,
+    judgments_path: P,
+    optic_path: Option<P>,
+    baseline_path: Option<P>,
+    update_baseline: bool,
+) -> Result<()> {
+    let judgments = Judgments::open(judgments_path)?;
+
+    if judgments.is_empty() {
+        return Err(anyhow!("no judgments found"));
+    }
+
+    let optic = match optic_path {
+        Some(path) => Some(Optic::parse(&std::fs::read_to_string(path)?)?),
+        None => None,
+    };
+
+    let baseline = match &baseline_path {
+        Some(path) if path.as_ref().exists() => Some(Baseline::open(path)?),
+        Some(_) if update_baseline => None,
+        Some(path) => {
+            return Err(anyhow!(
+                "baseline {:?} does not exist. Run with --update-baseline to create it",
+                path.as_ref()
+            ))
+        }
+        None => None,
+    };
+
+    // keep the timestamp of the baseline so the recency signals are comparable between runs.
+    let timestamp = baseline
+        .as_ref()
+        .map(|baseline| baseline.timestamp)
+        .unwrap_or_else(|| chrono::Utc::now().timestamp() as usize);
+
+    let mut searcher = LocalSearcher::new(Index::open(index_path)?);
+    let report = evaluate(&mut searcher, &judgments, optic.as_ref(), timestamp)?;
+    report.log();
+
+    if update_baseline {
+        let path = baseline_path.ok_or_else(|| anyhow!("--update-baseline requires --baseline"))?;
+
+        Baseline {
+            version: BASELINE_VERSION,
+            timestamp,
+            metrics: report.mean,
+        }
+        .save(&path)?;
+
+        info!("updated baseline {:?}", path.as_ref());
+        return Ok(());
+    }
+
+    if let Some(baseline) = baseline {
+        let regressions = report.mean.regressions(&baseline.metrics);
+
+        if !regressions.is_empty() {
+            return Err(anyhow!(
+                "{} dropped below the baseline (ndcg@10={:.4} mrr={:.4} recall@100={:.4})",
+                regressions.join(", "),
+                baseline.metrics.ndcg_at_10,
+                baseline.metrics.mrr,
+                baseline.metrics.recall_at_100
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::webpage::Webpage;
+
+    use super::*;
+
+    const FILLER: &str = "this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever";
+
+    const TIMESTAMP: usize = 1_700_000_000;
+
+    fn grades(grades: &[(&str, u8)]) -> BTreeMap<String, u8> {
+        grades
+            .iter()
+            .map(|(url, grade)| (url.to_string(), *grade))
+            .collect()
+    }
+
+    fn results(urls: &[&str]) -> Vec<String> {
+        urls.iter().map(|url| url.to_string()).collect()
+    }
+
+    #[derive(serde::Deserialize)]
+    struct FixturePage {
+        url: String,
+        title: String,
+        body: String,
+    }
+
+    fn fixture_index() -> Index {
+        let mut index = Index::temporary().expect("Unable to open index");
+        let mut reader =
+            csv::Reader::from_reader(include_str!("../../testcases/eval/pages.csv").as_bytes());
+
+        for page in reader.deserialize() {
+            let page: FixturePage = page.unwrap();
+            let html = format!(
+                r#"
+                <html>
+                    <head>
+                        <title>{}</title>
+                    </head>
+                    <body>
+                        {} {FILLER}
+                    </body>
+                </html>
+            "#,
+                page.title, page.body
+            );
+
+            index
+                .insert(&Webpage::test_parse(&html, &page.url).unwrap())
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().expect("failed to commit index");
+
+        index
+    }
+
+    fn fixture_judgments() -> Judgments {
+        let mut reader =
+            csv::Reader::from_reader(include_str!("../../testcases/eval/judgments.csv").as_bytes());
+
+        Judgments::new(reader.deserialize().map(|j| j.unwrap()).collect())
+    }
+
+    #[test]
+    fn ndcg() {
+        let grades = grades(&[("a", 3), ("b", 2), ("c", 0), ("d", 1)]);
+
+        // ideal: 7/log2(2) + 3/log2(3) + 1/log2(4) = 7 + 1.8928 + 0.5 = 9.3928
+        // actual: 3/log2(2) + 0 + 7/log2(4) + 0 + 1/log2(6) = 3 + 3.5 + 0.3869 = 6.8869
+        let res = ndcg_at_k(&results(&["b", "c", "a", "e", "d"]), &grades, 10);
+        assert!((res - 0.733_21).abs() < 1e-4, "{res}");
+
+        assert!((ndcg_at_k(&results(&["a", "b", "d"]), &grades, 10) - 1.0).abs() < 1e-9);
+
+        // only the first result is considered: 3 / 7
+        let res = ndcg_at_k(&results(&["b", "a"]), &grades, 1);
+        assert!((res - 3.0 / 7.0).abs() < 1e-9, "{res}");
+
+        assert_eq!(ndcg_at_k(&results(&["a"]), &BTreeMap::new(), 10), 0.0);
+    }
+
+    #[test]
+    fn mrr() {
+        let grades = grades(&[("a", 0), ("b", 2), ("c", 1)]);
+
+        assert_eq!(reciprocal_rank(&results(&["b", "c"]), &grades), 1.0);
+        assert_eq!(
+            reciprocal_rank(&results(&["a", "x", "c"]), &grades),
+            1.0 / 3.0
+        );
+        assert_eq!(reciprocal_rank(&results(&["a", "x"]), &grades), 0.0);
+    }
+
+    #[test]
+    fn recall() {
+        let grades = grades(&[("a", 1), ("b", 2), ("c", 0), ("d", 1)]);
+
+        assert_eq!(
+            recall_at_k(&results(&["a", "c", "x", "d"]), &grades, 100),
+            2.0 / 3.0
+        );
+        assert_eq!(
+            recall_at_k(&results(&["x", "a", "b"]), &grades, 2),
+            1.0 / 3.0
+        );
+        assert_eq!(
+            recall_at_k(&results(&["a"]), &grades(&[("a", 0)]), 100),
+            0.0
+        );
+    }
+
+    #[test]
+    fn mean_and_regressions() {
+        let mean = Metrics::mean(
+            [
+                Metrics {
+                    ndcg_at_10: 1.0,
+                    mrr: 1.0,
+                    recall_at_100: 0.5,
+                },
+                Metrics {
+                    ndcg_at_10: 0.5,
+                    mrr: 0.0,
+                    recall_at_100: 1.0,
+                },
+            ]
+            .iter(),
+        );
+
+        assert_eq!(
+            mean,
+            Metrics {
+                ndcg_at_10: 0.75,
+                mrr: 0.5,
+                recall_at_100: 0.75,
+            }
+        );
+
+        assert!(mean.regressions(&mean).is_empty());
+        assert_eq!(
+            mean.regressions(&Metrics {
+                ndcg_at_10: 0.7,
+                mrr: 0.6,
+                recall_at_100: 0.8,
+            }),
+            vec!["mrr", "recall@100"]
+        );
+    }
+
+    #[test]
+    fn judgments_normalize_urls() {
+        let judgments = Judgments::new(vec![
+            Judgment {
+                query: " aardvark ".to_string(),
+                url: "https://a.com".to_string(),
+                grade: 2,
+            },
+            Judgment {
+                query: "aardvark".to_string(),
+                url: "https://b.com/".to_string(),
+                grade: 0,
+            },
+        ]);
+
+        let queries: Vec<_> = judgments.queries().collect();
+        assert_eq!(queries.len(), 1);
+        assert_eq!(queries[0].0, "aardvark");
+        assert_eq!(queries[0].1.get("https://a.com/"), Some(&2));
+        assert_eq!(queries[0].1.get("https://b.com/"), Some(&0));
+    }
+
+    #[test]
+    fn fixture() {
+        let judgments = fixture_judgments();
+        let mut searcher = LocalSearcher::new(fixture_index());
+
+        let report = evaluate(&mut searcher, &judgments, None, TIMESTAMP).unwrap();
+        assert_eq!(report.queries.len(), judgments.len());
+
+        let aardvark = report
+            .queries
+            .iter()
+            .find(|q| q.query == "aardvark")
+            .unwrap();
+        assert_eq!(aardvark.metrics.mrr, 1.0);
+        assert_eq!(aardvark.metrics.ndcg_at_10, 1.0);
+        assert_eq!(aardvark.metrics.recall_at_100, 1.0);
+
+        for query in &report.queries {
+            assert_eq!(query.metrics.recall_at_100, 1.0, "{}", query.query);
+        }
+
+        let again = evaluate(&mut searcher, &judgments, None, TIMESTAMP).unwrap();
+        assert_eq!(report.mean, again.mean);
+
+        for (a, b) in report.queries.iter().zip(again.queries.iter()) {
+            assert_eq!(a.query, b.query);
+            assert_eq!(a.metrics, b.metrics);
+        }
+    }
+
+    #[test]
+    fn baseline_roundtrip() {
+        let path = crate::gen_temp_path().join("baseline.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        let baseline = Baseline {
+            version: BASELINE_VERSION,
+            timestamp: TIMESTAMP,
+            metrics: Metrics {
+                ndcg_at_10: 0.5,
+                mrr: 0.25,
+                recall_at_100: 1.0,
+            },
+        };
+        baseline.save(&path).unwrap();
+
+        let opened = Baseline::open(&path).unwrap();
+        assert_eq!(opened.timestamp, TIMESTAMP);
+        assert_eq!(opened.metrics, baseline.metrics);
+
+        Baseline {
+            version: BASELINE_VERSION + 1,
+            ..baseline
+        }
+        .save(&path)
+        .unwrap();
+        assert!(Baseline::open(&path).is_err());
+    }
+}
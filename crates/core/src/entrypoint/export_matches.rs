@@ -0,0 +1,331 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Export every document in the index that matches a query.
+//!
+//! Unlike the regular search path, the matches are not ranked or collected
+//! into a top-k list. The documents of each segment are streamed from the
+//! query's docset straight to the output, so the memory usage is independent
+//! of the number of matches.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use tantivy::{
+    query::{EnableScoring, Query as _},
+    DocSet, TantivyDocument, TERMINATED,
+};
+use tracing::{info, warn};
+
+use crate::{
+    index::Index, inverted_index::RetrievedWebpage, query::Query, searcher::SearchQuery, Result,
+};
+
+const PROGRESS_INTERVAL: u64 = 10_000;
+
+/// The snippet is generated at search time and is never part of the stored document.
+const EXCLUDED_FIELDS: &[&str] = &["snippet"];
+
+pub const DEFAULT_FIELDS: &[&str] = &["url", "title"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub num_matches: u64,
+    /// The export was stopped because the number of matches exceeded the cap.
+    pub truncated: bool,
+}
+
+fn available_fields() -> Vec<String> {
+    match serde_json::to_value(RetrievedWebpage::default()) {
+        Ok(serde_json::Value::Object(map)) => map
+            .into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| !EXCLUDED_FIELDS.contains(&key.as_str()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn validate_fields(fields: &[String]) -> Result<()> {
+    let available = available_fields();
+
+    for field in fields {
+        if !available.contains(field) {
+            return Err(anyhow!(
+                "unknown field '{field}'. Available fields are: {}",
+                available.join(", ")
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn project(webpage: RetrievedWebpage, fields: &[String]) -> Result<serde_json::Value> {
+    let mut all = match serde_json::to_value(webpage)? {
+        serde_json::Value::Object(map) => map,
+        _ => return Err(anyhow!("webpage should serialize to an object")),
+    };
+
+    let mut projected = serde_json::Map::new();
+    for field in fields {
+        if let Some(value) = all.remove(field) {
+            projected.insert(field.clone(), value);
+        }
+    }
+
+    Ok(serde_json::Value::Object(projected))
+}
+
+/// Write the requested stored fields of every document matching `query` as one json object per line.
+///
+/// If `max_matches` is set and the query has more matches, the export stops after
+/// `max_matches` documents have been written.
+pub fn export<W: Write>(
+    index: &Index,
+    query: &str,
+    fields: &[String],
+    max_matches: Option<u64>,
+    mut writer: W,
+) -> Result<ExportSummary> {
+    validate_fields(fields)?;
+
+    let ctx = index.inverted_index.local_search_ctx();
+    let query = Query::parse(
+        &ctx,
+        &SearchQuery {
+            query: query.to_string(),
+            ..Default::default()
+        },
+        &index.inverted_index,
+    )?;
+
+    let searcher = &ctx.tv_searcher;
+    let weight = query.weight(EnableScoring::disabled_from_searcher(searcher))?;
+
+    let mut summary = ExportSummary {
+        num_matches: 0,
+        truncated: false,
+    };
+
+    'segments: for (segment_ord, segment) in searcher.segment_readers().iter().enumerate() {
+        let alive = segment.alive_bitset();
+        let mut docs = weight.scorer(segment, 1.0)?;
+
+        let mut doc = docs.doc();
+        while doc != TERMINATED {
+            if alive.map_or(true, |alive| alive.is_alive(doc)) {
+                if max_matches.is_some_and(|max| summary.num_matches >= max) {
+                    summary.truncated = true;
+                    break 'segments;
+                }
+
+                let document: TantivyDocument =
+                    searcher.doc(tantivy::DocAddress::new(segment_ord as u32, doc))?;
+                let line = project(
+                    RetrievedWebpage::from_doc(document, index.inverted_index.field_mapping()),
+                    fields,
+                )?;
+
+                serde_json::to_writer(&mut writer, &line)?;
+                writer.write_all(b"\n")?;
+
+                summary.num_matches += 1;
+
+                if summary.num_matches % PROGRESS_INTERVAL == 0 {
+                    info!("exported {} matches", summary.num_matches);
+                }
+            }
+
+            doc = docs.advance();
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(summary)
+}
+
+pub fn run<P: AsRef<Path>>(
+    index_path: P,
+    query: &str,
+    output_path: P,
+    fields: Vec<String>,
+    max_matches: Option<u64>,
+) -> Result<()> {
+    let fields = if fields.is_empty() {
+        DEFAULT_FIELDS.iter().map(|f| f.to_string()).collect()
+    } else {
+        fields
+    };
+
+    let index = Index::open(index_path)?;
+    let writer = BufWriter::new(File::create(output_path)?);
+
+    let summary = export(&index, query, &fields, max_matches, writer)?;
+
+    if summary.truncated {
+        warn!(
+            "stopped after {} matches since the query has more matches than the cap",
+            summary.num_matches
+        );
+    } else {
+        info!("exported {} matches", summary.num_matches);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::webpage::Webpage;
+
+    use super::*;
+
+    const CONTENT: &str = "this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever";
+
+    fn page(url: &str, title: &str) -> Webpage {
+        Webpage::test_parse(
+            &format!(
+                r#"
+                <html>
+                    <head>
+                        <title>{title}</title>
+                    </head>
+                    <body>
+                        {CONTENT}
+                    </body>
+                </html>
+            "#
+            ),
+            url,
+        )
+        .unwrap()
+    }
+
+    fn index() -> Index {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        // commit in between to spread the pages over multiple segments
+        for segment in 0..3 {
+            for i in 0..5 {
+                index
+                    .insert(&page(
+                        &format!("https://www.a.com/{segment}/{i}"),
+                        "Website A",
+                    ))
+                    .unwrap();
+            }
+
+            index
+                .insert(&page(&format!("https://www.b.com/{segment}"), "Website B"))
+                .unwrap();
+
+            index.commit().unwrap();
+        }
+
+        index
+    }
+
+    fn exported(
+        index: &Index,
+        query: &str,
+        max_matches: Option<u64>,
+    ) -> (ExportSummary, Vec<serde_json::Value>) {
+        let mut out = Vec::new();
+        let fields = vec!["url".to_string(), "title".to_string()];
+        let summary = export(index, query, &fields, max_matches, &mut out).unwrap();
+
+        let lines = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        (summary, lines)
+    }
+
+    #[test]
+    fn every_match_exactly_once() {
+        let index = index();
+        assert!(index.inverted_index.num_segments() > 1);
+
+        let (summary, lines) = exported(&index, "example site:a.com", None);
+
+        assert!(!summary.truncated);
+        assert_eq!(summary.num_matches, 15);
+        assert_eq!(lines.len(), 15);
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for line in &lines {
+            assert_eq!(line["title"], "Website A");
+            assert_eq!(line.as_object().unwrap().len(), 2);
+
+            *counts
+                .entry(line["url"].as_str().unwrap().to_string())
+                .or_default() += 1;
+        }
+
+        for segment in 0..3 {
+            for i in 0..5 {
+                assert_eq!(
+                    counts.get(&format!("https://www.a.com/{segment}/{i}")),
+                    Some(&1)
+                );
+            }
+        }
+
+        let (summary, lines) = exported(&index, "example", None);
+        assert_eq!(summary.num_matches, 18);
+        assert_eq!(lines.len(), 18);
+    }
+
+    #[test]
+    fn cap() {
+        let index = index();
+
+        let (summary, lines) = exported(&index, "example", Some(7));
+        assert!(summary.truncated);
+        assert_eq!(summary.num_matches, 7);
+        assert_eq!(lines.len(), 7);
+
+        let (summary, lines) = exported(&index, "example", Some(18));
+        assert!(!summary.truncated);
+        assert_eq!(lines.len(), 18);
+    }
+
+    #[test]
+    fn unknown_field() {
+        let index = index();
+        let fields = vec!["url".to_string(), "not_a_field".to_string()];
+
+        assert!(export(&index, "example", &fields, None, Vec::new()).is_err());
+        assert!(export(
+            &index,
+            "example",
+            &["snippet".to_string()],
+            None,
+            Vec::new()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,37 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::path::Path;
+
+use tracing::info;
+
+use crate::{
+    host_languages::{self, HostLanguageStore},
+    index::Index,
+    Result,
+};
+
+pub fn run<P: AsRef<Path>>(index_path: P, output_path: P, min_pages: u64) -> Result<()> {
+    let index = Index::open(index_path)?;
+
+    info!("counting languages per host");
+    let languages = host_languages::compute(&index, min_pages)?;
+
+    let store = HostLanguageStore::build(output_path, languages)?;
+    info!("stored languages for {} hosts", store.len());
+
+    Ok(())
+}
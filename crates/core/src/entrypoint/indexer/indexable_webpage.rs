@@ -21,6 +21,10 @@ pub struct IndexableWebpage {
     pub url: String,
     pub body: String,
     pub fetch_time_ms: u64,
+    pub last_modified: Option<u64>,
+    pub sitemap_lastmod: Option<u64>,
+    /// Seconds since the unix epoch of when the page was fetched.
+    pub fetched_at: Option<u64>,
 }
 
 impl From<CrawlDatum> for IndexableWebpage {
@@ -29,6 +33,9 @@ impl From<CrawlDatum> for IndexableWebpage {
             url: datum.url.to_string(),
             body: datum.body,
             fetch_time_ms: datum.fetch_time_ms,
+            last_modified: datum.last_modified,
+            sitemap_lastmod: datum.sitemap_lastmod,
+            fetched_at: Some(datum.fetched_at),
         }
     }
 }
@@ -39,6 +46,9 @@ impl From<WarcRecord> for IndexableWebpage {
             url: record.request.url,
             body: record.response.body,
             fetch_time_ms: record.metadata.fetch_time_ms,
+            last_modified: record.metadata.last_modified,
+            sitemap_lastmod: record.metadata.sitemap_lastmod,
+            fetched_at: record.metadata.fetched_at,
         }
     }
 }
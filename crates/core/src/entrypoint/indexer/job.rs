@@ -24,7 +24,10 @@ use tracing::{info, trace, warn};
 use crate::config;
 use crate::entrypoint::download_all_warc_files;
 use crate::index::Index;
+use crate::inverted_index::TermCounts;
+use crate::schema::TextFieldEnum;
 use crate::warc::PayloadType;
+use crate::webpage::Webpage;
 
 use super::{IndexableWebpage, IndexingWorker};
 
@@ -36,35 +39,28 @@ pub struct Job {
     pub settings: JobSettings,
 }
 
-#[derive(
-    Debug, Clone, Copy, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
-)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
 pub struct JobSettings {
     pub host_centrality_threshold: Option<f64>,
     pub minimum_clean_words: Option<usize>,
     pub batch_size: usize,
     pub autocommit_after_num_inserts: usize,
+    pub main_content_extraction: bool,
+    pub freshness_buckets_days: Vec<u64>,
+    pub tokenization: config::TokenizationConfig,
 }
 
 impl Job {
-    pub fn process(&self, worker: &IndexingWorker) -> Index {
-        let name = self.warc_path.split('/').last().unwrap();
-
-        let mut has_host_centrality = false;
-        let mut has_page_centrality = false;
-        let mut has_backlinks = false;
-
-        info!("processing {}", name);
-
-        let mut index = Index::open(Path::new(&self.base_path).join(name)).unwrap();
-        index.prepare_writer().unwrap();
-
+    /// Prepare the pages of the warc file in batches. The pages whose url the worker has
+    /// already seen are skipped if `skip_seen` is set, and the others are marked as seen.
+    fn for_each_batch<F>(&self, worker: &IndexingWorker, skip_seen: bool, mut f: F)
+    where
+        F: FnMut(&[Webpage]),
+    {
         let paths = vec![self.warc_path.clone()];
         let warc_files = download_all_warc_files(&paths, &self.source_config);
         pin!(warc_files);
 
-        let mut num_inserts_since_commit = 0;
-
         for file in warc_files.by_ref() {
             let mut batch = Vec::with_capacity(self.settings.batch_size);
 
@@ -75,7 +71,7 @@ impl Job {
                     Some(payload_type) => matches!(payload_type, PayloadType::Html),
                     None => true,
                 })
-                .filter(|record| !worker.see(&record.request.url))
+                .filter(|record| !skip_seen || !worker.see(&record.request.url))
                 .chunks(self.settings.batch_size)
                 .into_iter()
             {
@@ -85,38 +81,84 @@ impl Job {
                     batch.push(IndexableWebpage::from(record));
                 }
 
-                let prepared = worker.prepare_webpages(&batch);
+                f(&worker.prepare_webpages(&batch));
+            }
+        }
+    }
+
+    /// Count the terms of the pages for the first pass of the term pruning. The urls are not
+    /// marked as seen, so the pages are still indexed in the second pass, but a page that was
+    /// crawled more than once is counted for every copy.
+    pub fn count_terms(
+        &self,
+        worker: &IndexingWorker,
+        fields: &[TextFieldEnum],
+        sketch_width: usize,
+    ) -> TermCounts {
+        let schema = crate::schema::create_schema();
+        let mut counts = TermCounts::new(fields, sketch_width);
+
+        self.for_each_batch(worker, false, |webpages| {
+            for webpage in webpages {
+                match webpage.as_tantivy(&schema) {
+                    Ok(doc) => counts.count(&doc, &schema),
+                    Err(err) => warn!("{:?}", err),
+                }
+            }
+        });
+
+        counts
+    }
 
-                for webpage in &prepared {
-                    if webpage.host_centrality > 0.0 {
-                        has_host_centrality = true;
-                    }
+    pub fn process(&self, worker: &IndexingWorker) -> Index {
+        let name = self.warc_path.split('/').last().unwrap();
 
-                    if webpage.page_centrality > 0.0 {
-                        has_page_centrality = true;
-                    }
+        let mut has_host_centrality = false;
+        let mut has_page_centrality = false;
+        let mut has_backlinks = false;
 
-                    if !webpage.backlink_labels.is_empty() {
-                        has_backlinks = true;
-                    }
-                    trace!("inserting webpage: {:?}", webpage.html.url());
-                    trace!("title = {:?}", webpage.html.title());
-                    trace!("text = {:?}", webpage.html.clean_text());
+        info!("processing {}", name);
 
-                    if let Err(err) = index.insert(webpage) {
-                        warn!("{:?}", err);
-                        panic!();
-                    }
+        let mut index = Index::open(Path::new(&self.base_path).join(name)).unwrap();
+        index.prepare_writer().unwrap();
+        index.set_freshness_buckets(self.settings.freshness_buckets_days.clone());
+
+        if let Some(pruning) = worker.term_pruning() {
+            index.inverted_index.set_term_pruning(pruning.clone());
+        }
 
-                    num_inserts_since_commit += 1;
+        let mut num_inserts_since_commit = 0;
+
+        self.for_each_batch(worker, true, |prepared| {
+            for webpage in prepared {
+                if webpage.host_centrality > 0.0 {
+                    has_host_centrality = true;
+                }
+
+                if webpage.page_centrality > 0.0 {
+                    has_page_centrality = true;
+                }
+
+                if !webpage.backlink_labels.is_empty() {
+                    has_backlinks = true;
                 }
+                trace!("inserting webpage: {:?}", webpage.html.url());
+                trace!("title = {:?}", webpage.html.title());
+                trace!("text = {:?}", webpage.html.clean_text());
 
-                if num_inserts_since_commit >= self.settings.autocommit_after_num_inserts {
-                    index.commit().unwrap();
-                    num_inserts_since_commit = 0;
+                if let Err(err) = index.insert(webpage) {
+                    warn!("{:?}", err);
+                    panic!();
                 }
+
+                num_inserts_since_commit += 1;
             }
-        }
+
+            if num_inserts_since_commit >= self.settings.autocommit_after_num_inserts {
+                index.commit().unwrap();
+                num_inserts_since_commit = 0;
+            }
+        });
         index.commit().unwrap();
 
         if !has_host_centrality {
@@ -19,6 +19,7 @@ pub mod job;
 pub mod worker;
 
 use rayon::prelude::*;
+use std::sync::Arc;
 use std::thread;
 
 use itertools::Itertools;
@@ -29,6 +30,7 @@ pub use crate::entrypoint::indexer::worker::IndexingWorker;
 
 use crate::config::{self, WarcSource};
 use crate::index::Index;
+use crate::inverted_index::TermCounts;
 use crate::Result;
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
@@ -45,10 +47,10 @@ pub fn run(config: &config::IndexerConfig) -> Result<()> {
 
     let job_config: WarcSource = config.warc_source.clone();
 
-    let worker = IndexingWorker::new(config.clone());
+    let mut worker = IndexingWorker::new(config.clone());
 
-    let indexes = warc_paths
-        .into_par_iter()
+    let jobs: Vec<_> = warc_paths
+        .into_iter()
         .skip(config.skip_warc_files.unwrap_or(0))
         .take(config.limit_warc_files.unwrap_or(usize::MAX))
         .map(|warc_path| Job {
@@ -60,8 +62,33 @@ pub fn run(config: &config::IndexerConfig) -> Result<()> {
                 minimum_clean_words: config.minimum_clean_words,
                 batch_size: config.batch_size,
                 autocommit_after_num_inserts: config.autocommit_after_num_inserts,
+                main_content_extraction: config.main_content_extraction,
+                freshness_buckets_days: config.freshness_buckets_days.clone(),
+                tokenization: config.tokenization.clone(),
             },
         })
+        .collect();
+
+    if let Some(pruning) = &config.term_pruning {
+        let fields = pruning.text_fields()?;
+
+        let counts = jobs
+            .par_iter()
+            .map(|job| job.count_terms(&worker, &fields, pruning.sketch_width))
+            .reduce(
+                || TermCounts::new(&fields, pruning.sketch_width),
+                TermCounts::merge,
+            );
+
+        tracing::info!(
+            "pruning the terms that occur fewer than {} times",
+            pruning.min_term_freq
+        );
+        worker.set_term_pruning(Arc::new(counts.into_pruning(pruning.min_term_freq)));
+    }
+
+    let indexes = jobs
+        .into_par_iter()
         .map(|job| {
             IndexPointer(
                 job.process(&worker)
@@ -80,6 +107,59 @@ pub fn run(config: &config::IndexerConfig) -> Result<()> {
     Ok(())
 }
 
+pub fn upgrade_schema(index_path: String, output_path: String) -> Result<()> {
+    let index = Index::upgrade_schema(&index_path, &output_path)?;
+
+    tracing::info!(
+        "upgraded {} to schema version {} at {}",
+        index_path,
+        index.schema_version(),
+        output_path
+    );
+
+    Ok(())
+}
+
+pub fn freshness(index_path: String) -> Result<()> {
+    let index = Index::open(index_path)?;
+    let freshness = index.freshness();
+
+    println!("{}", serde_json::to_string_pretty(&freshness)?);
+
+    Ok(())
+}
+
+pub fn resegment(index_path: String, num_segments: u64) -> Result<()> {
+    let mut index = Index::open(&index_path)?;
+    let before = index.inverted_index.num_segments();
+
+    if before <= num_segments as usize {
+        tracing::info!(
+            "{} already has {} segments, which is not more than {}",
+            index_path,
+            before,
+            num_segments
+        );
+        return Ok(());
+    }
+
+    tracing::info!(
+        "merging the {} segments of {} into {}",
+        before,
+        index_path,
+        num_segments
+    );
+    index.resegment(num_segments)?;
+
+    tracing::info!(
+        "{} now has {} segments",
+        index_path,
+        index.inverted_index.num_segments()
+    );
+
+    Ok(())
+}
+
 pub fn merge(indexes: Vec<IndexPointer>) -> Result<Index> {
     let num_indexes = indexes.len();
     let mut it = indexes.into_iter();
@@ -14,10 +14,10 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use itertools::Itertools;
 use std::path::Path;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use tracing::debug;
 
@@ -33,9 +33,10 @@ use crate::Result;
 
 use crate::human_website_annotations;
 use crate::index::Index;
+use crate::inverted_index::TermPruning;
 use crate::rake::RakeModel;
 use crate::ranking::SignalComputer;
-use crate::webgraph::{self, EdgeLimit, Node, NodeID};
+use crate::webgraph::{self, EdgeLimit, Node, NodeID, Redirects};
 use crate::webpage::{safety_classifier, Html, Webpage};
 
 pub struct Config {
@@ -84,11 +85,33 @@ pub(super) enum Webgraph {
 }
 
 impl Webgraph {
+    /// The nodes whose redirects resolve to each of the nodes.
+    fn batch_raw_redirect_sources(&self, ids: &[NodeID]) -> Vec<Vec<NodeID>> {
+        match self {
+            Self::Remote(webgraph) => crate::block_on(webgraph.batch_raw_redirect_sources(ids))
+                .unwrap_or_else(|_| vec![vec![]; ids.len()]),
+            Self::Local(webgraph) => ids
+                .iter()
+                .map(|id| webgraph.raw_redirect_sources(*id))
+                .collect(),
+        }
+    }
+
+    /// The labels of the backlinks of each node. Links to the pages that redirect to a node
+    /// are counted as links to the node itself.
     fn batch_raw_ingoing_edges_with_labels(
         &self,
         ids: Vec<NodeID>,
         limit: EdgeLimit,
     ) -> Vec<Vec<String>> {
+        let redirect_sources = self.batch_raw_redirect_sources(&ids);
+        let num_nodes = ids.len();
+
+        let mut ids = ids;
+        for sources in &redirect_sources {
+            ids.extend(sources);
+        }
+
         let edges = match self {
             Self::Remote(webgraph) => {
                 crate::block_on(webgraph.batch_raw_ingoing_edges_with_labels(&ids, limit))
@@ -105,7 +128,17 @@ impl Webgraph {
             }
         };
 
-        edges
+        let mut edges = edges.into_iter();
+        let mut resolved: Vec<_> = edges.by_ref().take(num_nodes).collect();
+        resolved.resize_with(num_nodes, Vec::new);
+
+        for (node_edges, sources) in resolved.iter_mut().zip(&redirect_sources) {
+            for _ in sources {
+                node_edges.extend(edges.next().unwrap_or_default());
+            }
+        }
+
+        resolved
             .into_iter()
             .map(|edges| {
                 edges
@@ -172,6 +205,7 @@ pub struct IndexingWorker {
     rake: RakeModel,
     dual_encoder: Option<DualEncoder>,
     seen_urls: Mutex<bloom::BytesBloomFilter<String>>,
+    term_pruning: Option<Arc<TermPruning>>,
 }
 
 impl IndexingWorker {
@@ -220,6 +254,7 @@ impl IndexingWorker {
                 }
             }),
             seen_urls: Mutex::new(bloom::BytesBloomFilter::new(10_000_000_000, 0.05)),
+            term_pruning: None,
         }
     }
 
@@ -252,6 +287,15 @@ impl IndexingWorker {
         self.job_settings = Some(job_settings);
     }
 
+    /// Leave the pruned terms out of the indexes of the jobs.
+    pub fn set_term_pruning(&mut self, pruning: Arc<TermPruning>) {
+        self.term_pruning = Some(pruning);
+    }
+
+    pub(super) fn term_pruning(&self) -> Option<&Arc<TermPruning>> {
+        self.term_pruning.as_ref()
+    }
+
     fn prepare(&self, page: &IndexableWebpage) -> Result<Webpage> {
         let html = match Html::parse_without_text(&page.body, &page.url) {
             Ok(html) => html,
@@ -288,8 +332,10 @@ impl IndexingWorker {
             .unwrap()
             .unwrap_or(u64::MAX);
 
-        if let Some(host_centrality_threshold) =
-            self.job_settings.and_then(|s| s.host_centrality_threshold)
+        if let Some(host_centrality_threshold) = self
+            .job_settings
+            .as_ref()
+            .and_then(|s| s.host_centrality_threshold)
         {
             if host_centrality < host_centrality_threshold {
                 return Err(anyhow::anyhow!("low host_centrality value"));
@@ -311,13 +357,27 @@ impl IndexingWorker {
     }
 
     fn parse_text(&self, page: &mut Webpage) -> Result<()> {
-        page.html.parse_text();
+        page.html.parse_text_with(
+            self.job_settings
+                .as_ref()
+                .map(|s| s.main_content_extraction)
+                .unwrap_or_else(crate::config::defaults::Indexing::main_content_extraction),
+        );
+
+        if let Some(settings) = self.job_settings.as_ref() {
+            let kind = settings.tokenization.tokenizer(page.html.lang());
+            page.html.set_tokenizer_kind(kind);
+        }
 
         if page.html.empty_all_text() {
             return Err(anyhow::anyhow!("empty all text"));
         }
 
-        if let Some(minimum_clean_words) = self.job_settings.and_then(|s| s.minimum_clean_words) {
+        if let Some(minimum_clean_words) = self
+            .job_settings
+            .as_ref()
+            .and_then(|s| s.minimum_clean_words)
+        {
             match page.html.clean_text() {
                 Some(clean_text) => {
                     if clean_text.split_whitespace().count() < minimum_clean_words {
@@ -481,11 +541,17 @@ impl IndexingWorker {
                 host_centrality: prepared.host_centrality,
                 host_centrality_rank: prepared.host_centrality_rank,
                 fetch_time_ms: page.fetch_time_ms,
+                last_modified: page.last_modified,
+                sitemap_lastmod: page.sitemap_lastmod,
                 pre_computed_score: 0.0,
                 node_id: prepared.node_id,
                 dmoz_description: prepared.dmoz_description,
                 safety_classification: prepared.safety_classification,
+                content_labels: prepared.content_labels,
                 inserted_at: Utc::now(),
+                fetched_at: page
+                    .fetched_at
+                    .and_then(|fetched_at| DateTime::from_timestamp(fetched_at as i64, 0)),
                 keywords: prepared.keywords,
                 title_embedding: None,   // set later
                 keyword_embedding: None, // set later
@@ -508,6 +574,7 @@ impl IndexingWorker {
 #[cfg(test)]
 mod tests {
     use crate::config::WarcSource;
+    use crate::webpage::html::links::RelFlags;
 
     use super::*;
 
@@ -534,9 +601,87 @@ mod tests {
             batch_size: 10,
             autocommit_after_num_inserts:
                 crate::config::defaults::Indexing::autocommit_after_num_inserts(),
+            main_content_extraction: crate::config::defaults::Indexing::main_content_extraction(),
+            freshness_buckets_days: crate::config::defaults::Indexing::freshness_buckets_days(),
+            tokenization: Default::default(),
+            term_pruning: None,
         })
     }
 
+    #[test]
+    fn backlinks_of_redirects() {
+        let graph_path = crate::gen_temp_path();
+        let mut writer = webgraph::WebgraphWriter::new(
+            &graph_path,
+            crate::executor::Executor::single_thread(),
+            webgraph::Compression::default(),
+            None,
+        );
+
+        for (from, to, label, rel) in [
+            ("x.com", "a.com", "link to a", RelFlags::default()),
+            ("a.com", "b.com", "", RelFlags::REDIRECT),
+            ("b.com", "c.com", "", RelFlags::REDIRECT),
+            ("y.com", "c.com", "link to c", RelFlags::default()),
+        ] {
+            writer.insert(Node::from(from), Node::from(to), label.to_string(), rel);
+        }
+
+        writer.commit();
+        writer.finalize();
+
+        let worker = IndexingWorker::new(IndexerConfig {
+            host_centrality_store_path: crate::gen_temp_path().to_str().unwrap().to_string(),
+            page_centrality_store_path: None,
+            page_webgraph: Some(IndexerGraphConfig::Local {
+                path: graph_path.to_str().unwrap().to_string(),
+            }),
+            topics_path: None,
+            safety_classifier_path: None,
+            dual_encoder: None,
+            output_path: crate::gen_temp_path().to_str().unwrap().to_string(),
+            limit_warc_files: None,
+            skip_warc_files: None,
+            warc_source: WarcSource::Local(crate::config::LocalConfig {
+                folder: crate::gen_temp_path().to_str().unwrap().to_string(),
+                names: vec!["".to_string()],
+            }),
+            host_centrality_threshold: None,
+            minimum_clean_words: None,
+            batch_size: 10,
+            autocommit_after_num_inserts:
+                crate::config::defaults::Indexing::autocommit_after_num_inserts(),
+            main_content_extraction: crate::config::defaults::Indexing::main_content_extraction(),
+            freshness_buckets_days: crate::config::defaults::Indexing::freshness_buckets_days(),
+            tokenization: Default::default(),
+            term_pruning: None,
+        });
+
+        let mut webpages = vec![
+            Webpage::test_parse(
+                "<html><head><title>C</title></head><body>Example</body></html>",
+                "https://c.com",
+            )
+            .unwrap(),
+            Webpage::test_parse(
+                "<html><head><title>Y</title></head><body>Example</body></html>",
+                "https://y.com",
+            )
+            .unwrap(),
+        ];
+
+        worker.set_backlink_labels(&mut webpages);
+
+        let mut labels = webpages[0].backlink_labels.clone();
+        labels.sort();
+
+        assert_eq!(
+            labels,
+            vec!["link to a".to_string(), "link to c".to_string()]
+        );
+        assert!(webpages[1].backlink_labels.is_empty());
+    }
+
     #[test]
     fn title_embeddings() {
         let data_path = Path::new("../../data/summarizer/dual_encoder");
@@ -552,12 +697,18 @@ mod tests {
                 body: "<html><head><title>Homemade Heart Brownie Recipe</title></head><body>Example</body></html>"
                     .to_string(),
                 fetch_time_ms: 0,
+                last_modified: None,
+                sitemap_lastmod: None,
+                fetched_at: None,
             },
             IndexableWebpage {
                 url: "https://b.com".to_string(),
                 body: "<html><head><title>How To Use an iMac as a Monitor for a PC</title></head><body>Example</body></html>"
                     .to_string(),
                 fetch_time_ms: 0,
+                last_modified: None,
+                sitemap_lastmod: None,
+                fetched_at: None,
             },
         ];
 
@@ -19,6 +19,7 @@ pub mod ampc;
 pub mod api;
 pub mod autosuggest_scrape;
 pub mod canonical;
+pub mod canonicalization_report;
 mod centrality;
 #[cfg(feature = "dev")]
 pub mod configure;
@@ -26,15 +27,20 @@ pub mod crawler;
 pub mod dmoz_parser;
 mod entity;
 pub mod entity_search_server;
+pub mod eval;
+pub mod export_matches;
 pub mod feed_indexer;
+pub mod host_languages;
 pub mod indexer;
 pub mod safety_classifier;
+pub mod sample_crawl;
 pub mod search_server;
 pub mod web_spell;
 mod webgraph;
+pub mod webgraph_diff;
 pub mod webgraph_server;
 
-pub use centrality::Centrality;
+pub use centrality::{Centrality, CentralityExportFormat};
 pub use entity::EntityIndexer;
 use tracing::{debug, log::error};
 pub use webgraph::Webgraph;
@@ -0,0 +1,538 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Sample crawl data into a small dataset that can be indexed on a laptop.
+//!
+//! The records of the warc files are streamed through a weighted reservoir (Efraimidis-Spirakis):
+//! every record gets the key `u^(1/w)` for a uniformly random `u` and its weight `w`, and the
+//! `target_docs` records with the largest keys are kept. With equal weights this is a uniform
+//! sample, and in any case only `target_docs` records are held in memory. The sample is written
+//! as warc files that the indexer and webgraph builders consume, together with a json manifest
+//! of the kept urls.
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    fmt::Display,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tracing::info;
+use url::Url;
+
+use crate::{
+    warc::{WarcFile, WarcRecord, WarcWriter},
+    webgraph::{Node, NodeID},
+    webpage::url_ext::UrlExt,
+    Result,
+};
+
+const PROGRESS_INTERVAL: u64 = 100_000;
+
+/// Weight of hosts that are not in the centrality store, so they are only
+/// sampled when there are not enough records from hosts that are.
+const MIN_WEIGHT: f64 = 1e-9;
+
+pub const DEFAULT_DOCS_PER_FILE: usize = 10_000;
+
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SampleStrategy {
+    Uniform,
+    /// Keep at most `per_host_cap` records of every host.
+    PerHostCap,
+    /// Sample records proportionally to the harmonic centrality of their host.
+    CentralityWeighted,
+}
+
+impl Display for SampleStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Uniform => "uniform",
+            Self::PerHostCap => "per-host-cap",
+            Self::CentralityWeighted => "centrality-weighted",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for SampleStrategy {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "uniform" => Ok(Self::Uniform),
+            "per-host-cap" => Ok(Self::PerHostCap),
+            "centrality-weighted" => Ok(Self::CentralityWeighted),
+            _ => Err(crate::Error::UnknownCLIOption),
+        }
+    }
+}
+
+pub struct SampleConfig {
+    pub input_dirs: Vec<PathBuf>,
+    pub out: PathBuf,
+    pub target_docs: usize,
+    pub strategy: SampleStrategy,
+    pub per_host_cap: usize,
+    /// The host centrality store, i.e. the output folder of the host centrality computation.
+    pub centrality_store: Option<PathBuf>,
+    pub seed: u64,
+    pub docs_per_file: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SampledFile {
+    pub name: String,
+    pub urls: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Manifest {
+    pub strategy: SampleStrategy,
+    pub seed: u64,
+    pub target_docs: usize,
+    pub input_files: Vec<String>,
+    pub num_scanned: u64,
+    pub num_kept: usize,
+    pub files: Vec<SampledFile>,
+}
+
+impl Manifest {
+    /// The paths of the sampled warc files in the output folder.
+    pub fn warc_paths(&self, out: &Path) -> Vec<PathBuf> {
+        self.files.iter().map(|file| out.join(&file.name)).collect()
+    }
+}
+
+struct Keyed {
+    key: f64,
+    /// Position of the record in the input, so the sample keeps the order of the input.
+    pos: u64,
+    record: WarcRecord,
+}
+
+impl PartialEq for Keyed {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Keyed {}
+
+impl PartialOrd for Keyed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Keyed {
+    // reversed so the binary heap pops the smallest key first. Ties are
+    // broken in favour of earlier records.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .key
+            .total_cmp(&self.key)
+            .then_with(|| self.pos.cmp(&other.pos))
+    }
+}
+
+struct Reservoir {
+    capacity: usize,
+    heap: BinaryHeap<Keyed>,
+    rng: StdRng,
+}
+
+impl Reservoir {
+    fn new(capacity: usize, seed: u64) -> Self {
+        Self {
+            capacity,
+            heap: BinaryHeap::with_capacity(capacity + 1),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn insert(&mut self, pos: u64, weight: f64, record: WarcRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let u: f64 = self.rng.gen();
+        let key = u.powf(1.0 / weight.max(MIN_WEIGHT));
+
+        if self.heap.len() < self.capacity {
+            self.heap.push(Keyed { key, pos, record });
+        } else if self.heap.peek().is_some_and(|min| key > min.key) {
+            self.heap.pop();
+            self.heap.push(Keyed { key, pos, record });
+        }
+    }
+
+    fn into_records(self) -> Vec<WarcRecord> {
+        let mut kept = self.heap.into_vec();
+        kept.sort_by_key(|keyed| keyed.pos);
+
+        kept.into_iter().map(|keyed| keyed.record).collect()
+    }
+}
+
+fn host(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.normalized_host().map(|host| host.to_string()))
+}
+
+/// The warc files in the folders (or the files themselves), sorted so the sample only depends on the seed.
+fn warc_files(input_dirs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for input in input_dirs {
+        if input.is_file() {
+            files.push(input.clone());
+            continue;
+        }
+
+        let mut dir_files: Vec<_> = std::fs::read_dir(input)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.ends_with(".warc.gz"))
+            })
+            .collect();
+
+        dir_files.sort();
+        files.extend(dir_files);
+    }
+
+    Ok(files)
+}
+
+fn write_sample(
+    out: &Path,
+    records: &[WarcRecord],
+    docs_per_file: usize,
+) -> Result<Vec<SampledFile>> {
+    let mut files = Vec::new();
+
+    for (i, chunk) in records.chunks(docs_per_file.max(1)).enumerate() {
+        let name = format!("sample-{i:05}.warc.gz");
+        let mut writer = WarcWriter::new();
+
+        for record in chunk {
+            writer.write(record)?;
+        }
+
+        std::fs::write(out.join(&name), writer.finish()?)?;
+
+        files.push(SampledFile {
+            name,
+            urls: chunk
+                .iter()
+                .map(|record| record.request.url.clone())
+                .collect(),
+        });
+    }
+
+    Ok(files)
+}
+
+pub fn sample(config: &SampleConfig) -> Result<Manifest> {
+    let centrality: Option<speedy_kv::Db<NodeID, f64>> = match config.strategy {
+        SampleStrategy::CentralityWeighted => {
+            let path = config.centrality_store.as_ref().ok_or_else(|| {
+                anyhow!("the centrality-weighted strategy needs the host centrality store")
+            })?;
+
+            Some(speedy_kv::Db::open_or_create(path.join("harmonic"))?)
+        }
+        _ => None,
+    };
+
+    let input_files = warc_files(&config.input_dirs)?;
+
+    let mut reservoir = Reservoir::new(config.target_docs, config.seed);
+    let mut num_per_host: HashMap<String, usize> = HashMap::new();
+    let mut num_scanned = 0;
+
+    for path in &input_files {
+        let warc = WarcFile::open(path)?;
+
+        for record in warc.records().flatten() {
+            let pos = num_scanned;
+            num_scanned += 1;
+
+            if num_scanned % PROGRESS_INTERVAL == 0 {
+                info!("scanned {num_scanned} records");
+            }
+
+            let weight = match config.strategy {
+                SampleStrategy::Uniform => 1.0,
+                SampleStrategy::PerHostCap => {
+                    let host = match host(&record.request.url) {
+                        Some(host) => host,
+                        None => continue,
+                    };
+
+                    let count = num_per_host.entry(host).or_default();
+                    if *count >= config.per_host_cap {
+                        continue;
+                    }
+                    *count += 1;
+
+                    1.0
+                }
+                SampleStrategy::CentralityWeighted => {
+                    let url = match Url::parse(&record.request.url) {
+                        Ok(url) => url,
+                        Err(_) => continue,
+                    };
+                    let host = Node::from(url).into_host().id();
+
+                    centrality
+                        .as_ref()
+                        .and_then(|db| db.get(&host).ok().flatten())
+                        .unwrap_or_default()
+                }
+            };
+
+            reservoir.insert(pos, weight, record);
+        }
+    }
+
+    let records = reservoir.into_records();
+
+    std::fs::create_dir_all(&config.out)?;
+    let files = write_sample(&config.out, &records, config.docs_per_file)?;
+
+    let manifest = Manifest {
+        strategy: config.strategy,
+        seed: config.seed,
+        target_docs: config.target_docs,
+        input_files: input_files
+            .iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect(),
+        num_scanned,
+        num_kept: records.len(),
+        files,
+    };
+
+    let mut writer = BufWriter::new(File::create(config.out.join(MANIFEST_NAME))?);
+    serde_json::to_writer_pretty(&mut writer, &manifest)?;
+    writer.flush()?;
+
+    Ok(manifest)
+}
+
+pub fn run(config: SampleConfig) -> Result<()> {
+    let manifest = sample(&config)?;
+
+    info!(
+        "kept {} of {} records in {} files",
+        manifest.num_kept,
+        manifest.num_scanned,
+        manifest.files.len()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::warc::{Metadata, Request, Response};
+
+    use super::*;
+
+    /// Host `i` has `10 * (i + 1)` records, spread over two files.
+    fn fixture() -> PathBuf {
+        let path = crate::gen_temp_path();
+        std::fs::create_dir_all(&path).unwrap();
+
+        let mut writers = [WarcWriter::new(), WarcWriter::new()];
+
+        for host in 0..5 {
+            for page in 0..10 * (host + 1) {
+                writers[page % 2]
+                    .write(&WarcRecord {
+                        request: Request {
+                            url: format!("https://host{host}.com/{page}"),
+                        },
+                        response: Response {
+                            body: format!("<html><body>page {page}</body></html>"),
+                            payload_type: None,
+                        },
+                        metadata: Metadata {
+                            fetch_time_ms: 1,
+                            redirected_from: None,
+                            last_modified: None,
+                            sitemap_lastmod: None,
+                            robots_version: None,
+                            robots_rule: None,
+                            fetched_at: None,
+                        },
+                    })
+                    .unwrap();
+            }
+        }
+
+        for (i, writer) in writers.into_iter().enumerate() {
+            std::fs::write(path.join(format!("{i}.warc.gz")), writer.finish().unwrap()).unwrap();
+        }
+
+        path
+    }
+
+    fn config(input: &Path, strategy: SampleStrategy, seed: u64) -> SampleConfig {
+        SampleConfig {
+            input_dirs: vec![input.to_path_buf()],
+            out: crate::gen_temp_path(),
+            target_docs: 40,
+            strategy,
+            per_host_cap: 5,
+            centrality_store: None,
+            seed,
+            docs_per_file: 15,
+        }
+    }
+
+    fn urls(manifest: &Manifest) -> Vec<String> {
+        manifest
+            .files
+            .iter()
+            .flat_map(|file| file.urls.clone())
+            .collect()
+    }
+
+    fn hosts(manifest: &Manifest) -> HashMap<String, usize> {
+        let mut hosts = HashMap::new();
+
+        for url in urls(manifest) {
+            *hosts.entry(host(&url).unwrap()).or_default() += 1;
+        }
+
+        hosts
+    }
+
+    #[test]
+    fn uniform() {
+        let input = fixture();
+        let config = config(&input, SampleStrategy::Uniform, 1);
+
+        let manifest = sample(&config).unwrap();
+
+        assert_eq!(manifest.num_scanned, 150);
+        assert_eq!(manifest.num_kept, 40);
+        assert_eq!(
+            manifest
+                .files
+                .iter()
+                .map(|file| file.urls.len())
+                .collect::<Vec<_>>(),
+            vec![15, 15, 10]
+        );
+
+        // the sampled warc files can be read back
+        let num_records: usize = manifest
+            .warc_paths(&config.out)
+            .iter()
+            .map(|path| WarcFile::open(path).unwrap().records().flatten().count())
+            .sum();
+        assert_eq!(num_records, 40);
+
+        let written: Manifest =
+            serde_json::from_reader(File::open(config.out.join(MANIFEST_NAME)).unwrap()).unwrap();
+        assert_eq!(written, manifest);
+    }
+
+    #[test]
+    fn deterministic() {
+        let input = fixture();
+
+        let config = |strategy, seed| SampleConfig {
+            target_docs: 10,
+            ..config(&input, strategy, seed)
+        };
+
+        for strategy in [SampleStrategy::Uniform, SampleStrategy::PerHostCap] {
+            let a = sample(&config(strategy, 7)).unwrap();
+            let b = sample(&config(strategy, 7)).unwrap();
+            let c = sample(&config(strategy, 8)).unwrap();
+
+            assert_eq!(urls(&a), urls(&b));
+            assert_ne!(urls(&a), urls(&c));
+        }
+    }
+
+    #[test]
+    fn per_host_cap() {
+        let input = fixture();
+
+        let manifest = sample(&config(&input, SampleStrategy::PerHostCap, 1)).unwrap();
+
+        // 5 hosts with at most 5 records each is fewer than the target
+        assert_eq!(manifest.num_kept, 25);
+        assert!(hosts(&manifest).values().all(|count| *count == 5));
+
+        let manifest = sample(&SampleConfig {
+            target_docs: 10,
+            ..config(&input, SampleStrategy::PerHostCap, 1)
+        })
+        .unwrap();
+
+        assert_eq!(manifest.num_kept, 10);
+        assert!(hosts(&manifest).values().all(|count| *count <= 5));
+    }
+
+    #[test]
+    fn centrality_weighted() {
+        let input = fixture();
+        let store = crate::gen_temp_path();
+
+        let mut db: speedy_kv::Db<NodeID, f64> =
+            speedy_kv::Db::open_or_create(store.join("harmonic")).unwrap();
+        db.insert(Node::from("host0.com").id(), 1_000.0).unwrap();
+        db.insert(Node::from("host1.com").id(), 1_000.0).unwrap();
+        db.insert(Node::from("host2.com").id(), 0.001).unwrap();
+        db.commit().unwrap();
+        drop(db);
+
+        assert!(sample(&config(&input, SampleStrategy::CentralityWeighted, 1)).is_err());
+
+        let manifest = sample(&SampleConfig {
+            centrality_store: Some(store),
+            ..config(&input, SampleStrategy::CentralityWeighted, 1)
+        })
+        .unwrap();
+
+        assert_eq!(manifest.num_kept, 40);
+
+        // the 30 records of the central hosts are always kept
+        let hosts = hosts(&manifest);
+        assert_eq!(hosts.get("host0.com"), Some(&10));
+        assert_eq!(hosts.get("host1.com"), Some(&20));
+    }
+}
@@ -15,6 +15,9 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use tracing::info;
 use url::Url;
@@ -24,17 +27,30 @@ use crate::{
     distributed::sonic::service::sonic_service,
     distributed::{
         cluster::Cluster,
-        member::{Member, Service},
+        member::{Member, Service, ShardId},
         sonic,
     },
+    freshness::FreshnessHistogram,
     index::Index,
-    inverted_index::{self, RetrievedWebpage},
+    inverted_index::{self, HostStats, RetrievedWebpage},
     models::dual_encoder::DualEncoder,
-    ranking::models::{lambdamart::LambdaMART, linear::LinearRegression},
-    searcher::{InitialWebsiteResult, LocalSearcher, SearchQuery},
+    query::{segmentation::Segmenter, synonyms::SynonymExpander},
+    query_log::QueryLogger,
+    ranking::{
+        models::{lambdamart::LambdaMART, linear::LinearRegression},
+        SignalBounds,
+    },
+    replication::{Manifest, Primary, Replica, ReplicatedIndex, SegmentSource},
+    searcher::{InitialWebsiteResult, LocalSearcher, SearchGuard, SearchQuery, SearchableIndex},
+    ttl_cache::TTLCache,
     Result,
 };
 
+/// The host statistics only change when the shard is re-indexed,
+/// so they can be cached for a long time.
+const HOST_STATS_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+const HOST_STATS_CACHE_SIZE: usize = 10_000;
+
 sonic_service!(
     SearchService,
     [
@@ -42,11 +58,53 @@ sonic_service!(
         Search,
         GetWebpage,
         GetHomepageDescriptions,
+        GetHostStats,
+        GetIndexMetadata,
+        GetReplicationManifest,
+        GetSegmentFileRange,
+        Count,
     ]
 );
 
+/// The search server a replica downloads the index from.
+struct RemotePrimary {
+    client: sonic::replication::RemoteClient<SearchService>,
+}
+
+impl RemotePrimary {
+    fn new(addr: SocketAddr) -> Self {
+        Self {
+            client: sonic::replication::RemoteClient::new(addr),
+        }
+    }
+}
+
+impl SegmentSource for RemotePrimary {
+    async fn manifest(&self) -> Result<Manifest> {
+        self.client
+            .send(GetReplicationManifest)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("the primary failed to create a manifest"))
+    }
+
+    async fn read_range(&self, path: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        self.client
+            .send(GetSegmentFileRange {
+                path: path.to_string(),
+                offset,
+                len,
+            })
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("the primary failed to read {path}"))
+    }
+}
+
 pub struct SearchService {
-    local_searcher: LocalSearcher<Index>,
+    local_searcher: LocalSearcher<ReplicatedIndex>,
+    replication: Arc<Primary>,
+    host_stats: Mutex<TTLCache<String, Option<HostStats>>>,
+    shard: ShardId,
+    query_log: Option<QueryLogger>,
     // dropping the handle leaves the cluster
     #[allow(unused)]
     cluster_handle: Cluster,
@@ -54,7 +112,63 @@ pub struct SearchService {
 
 impl SearchService {
     async fn new(config: config::SearchServerConfig) -> Result<Self> {
-        let search_index = Index::open(config.index_path)?;
+        let (search_index, replica) = match &config.replica_of {
+            Some(replica_config) => {
+                let mut replica = Replica::open(
+                    &config.index_path,
+                    RemotePrimary::new(replica_config.primary),
+                    replica_config.chunk_size_bytes,
+                )?;
+
+                if let Err(err) = replica.poll().await {
+                    tracing::error!(
+                        "failed to replicate the index from {}: {:?}",
+                        replica_config.primary,
+                        err
+                    );
+                }
+
+                (
+                    replica.index(),
+                    Some((replica, replica_config.poll_interval_sec)),
+                )
+            }
+            None if config.skip_corrupt_segments => (
+                ReplicatedIndex::new(Index::open_skip_corrupt_segments(&config.index_path)?),
+                None,
+            ),
+            None => (ReplicatedIndex::new(Index::open(&config.index_path)?), None),
+        };
+
+        {
+            let guard = search_index.guard();
+            let skipped_segments = guard.search_index().skipped_segments();
+
+            if !skipped_segments.is_empty() {
+                tracing::error!(
+                    "serving shard {:?} without the corrupt segments {}",
+                    config.shard,
+                    skipped_segments
+                        .iter()
+                        .map(|segment| segment.uuid_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            } else if guard.search_index().is_read_only() {
+                tracing::warn!(
+                    "serving shard {:?} with schema version {}. Fields added since are empty in its results",
+                    config.shard,
+                    guard.search_index().schema_version()
+                );
+            }
+
+            if !guard.search_index().inverted_index.has_embeddings() {
+                tracing::warn!(
+                    "shard {:?} has no title or keyword embeddings, so its results are not diversified",
+                    config.shard
+                );
+            }
+        }
 
         let mut local_searcher = LocalSearcher::new(search_index);
 
@@ -72,6 +186,63 @@ impl SearchService {
 
         local_searcher.set_collector_config(config.collector);
         local_searcher.set_snippet_config(config.snippet);
+        local_searcher.set_webpage_cache_capacity(config.webpage_cache_capacity);
+
+        if let Some(num_blocks) = config.store_block_cache_capacity {
+            local_searcher.set_store_block_cache_capacity(num_blocks)?;
+        }
+
+        local_searcher.set_retrieval_config(config.retrieval)?;
+
+        if !config.signal_bounds.is_empty() {
+            local_searcher.set_signal_bounds(SignalBounds::from_names(&config.signal_bounds)?);
+        }
+
+        if let Some(synonyms) = &config.synonyms {
+            local_searcher.set_synonyms(SynonymExpander::open(
+                &synonyms.path,
+                synonyms.boost as f32,
+            )?);
+        }
+
+        if let Some(segmentation) = &config.segmentation {
+            let segmenter = Segmenter::from_index(
+                local_searcher.index().guard().inverted_index(),
+                segmentation.min_doc_freq,
+                segmentation.boost as f32,
+            )?;
+            local_searcher.set_segmenter(segmenter);
+        }
+
+        match &config.warmup {
+            Some(warmup) => local_searcher.warmup(warmup).wait_ready(),
+            None => local_searcher
+                .index()
+                .guard()
+                .inverted_index()
+                .fastfield_reader()
+                .warm_all(),
+        }
+
+        if let Some((mut replica, poll_interval_sec)) = replica {
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(Duration::from_secs(poll_interval_sec)).await;
+
+                    match replica.poll().await {
+                        Ok(true) => info!("swapped in a new generation of the index"),
+                        Ok(false) => {}
+                        Err(err) => tracing::error!("failed to replicate the index: {:?}", err),
+                    }
+                }
+            });
+        }
+
+        let query_log = config
+            .query_log
+            .as_ref()
+            .map(QueryLogger::open)
+            .transpose()?;
 
         let cluster_handle = Cluster::join(
             Member {
@@ -88,6 +259,13 @@ impl SearchService {
 
         Ok(SearchService {
             local_searcher,
+            replication: Arc::new(Primary::default()),
+            host_stats: Mutex::new(TTLCache::with_ttl_and_max_size(
+                HOST_STATS_CACHE_TTL,
+                Some(HOST_STATS_CACHE_SIZE),
+            )),
+            shard: config.shard,
+            query_log,
             cluster_handle,
         })
     }
@@ -115,7 +293,30 @@ pub struct Search {
 impl sonic::service::Message<SearchService> for Search {
     type Response = Option<InitialWebsiteResult>;
     async fn handle(self, server: &SearchService) -> Self::Response {
-        server.local_searcher.search_initial(&self.query, true).ok()
+        let start = Instant::now();
+        let result = server.local_searcher.search_initial(&self.query, true).ok();
+
+        if let Some(query_log) = &server.query_log {
+            query_log.log(&query_log.event(
+                server.shard,
+                &self.query,
+                result.as_ref(),
+                start.elapsed(),
+            ));
+        }
+
+        result
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct Count {
+    pub query: SearchQuery,
+}
+impl sonic::service::Message<SearchService> for Count {
+    type Response = Option<u64>;
+    async fn handle(self, server: &SearchService) -> Self::Response {
+        server.local_searcher.count(&self.query).ok()
     }
 }
 
@@ -152,6 +353,123 @@ impl sonic::service::Message<SearchService> for GetHomepageDescriptions {
     }
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct GetHostStats {
+    pub host: String,
+}
+impl sonic::service::Message<SearchService> for GetHostStats {
+    type Response = Option<HostStats>;
+    async fn handle(self, server: &SearchService) -> Self::Response {
+        if let Some(stats) = server
+            .host_stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&self.host)
+        {
+            return stats.clone();
+        }
+
+        let stats = match server.local_searcher.host_stats(&self.host) {
+            Ok(stats) => stats,
+            Err(err) => {
+                tracing::error!("failed to compute stats for {}: {:?}", self.host, err);
+                return None;
+            }
+        };
+
+        server
+            .host_stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(self.host, stats.clone());
+
+        stats
+    }
+}
+
+/// The metadata of the index of the shard, for monitoring its health.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct IndexMetadata {
+    pub schema_version: u32,
+    pub read_only: bool,
+    pub num_skipped_segments: u64,
+    pub freshness: FreshnessHistogram,
+    /// The opstamp of the commit that is searched. It increases with every commit.
+    pub generation: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct GetIndexMetadata;
+impl sonic::service::Message<SearchService> for GetIndexMetadata {
+    type Response = IndexMetadata;
+    async fn handle(self, server: &SearchService) -> Self::Response {
+        let guard = server.local_searcher.index().guard();
+        let index = guard.search_index();
+
+        IndexMetadata {
+            schema_version: index.schema_version(),
+            read_only: index.is_read_only(),
+            num_skipped_segments: index.skipped_segments().len() as u64,
+            freshness: index.freshness(),
+            generation: index
+                .inverted_index
+                .load_metas()
+                .map(|metas| metas.opstamp)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct GetReplicationManifest;
+impl sonic::service::Message<SearchService> for GetReplicationManifest {
+    type Response = Option<Manifest>;
+    async fn handle(self, server: &SearchService) -> Self::Response {
+        let primary = Arc::clone(&server.replication);
+        let index = server.local_searcher.index().read();
+
+        // the checksums of new segments are computed from their files
+        match tokio::task::spawn_blocking(move || primary.manifest(&index))
+            .await
+            .unwrap_or_else(|err| Err(err.into()))
+        {
+            Ok(manifest) => Some(manifest),
+            Err(err) => {
+                tracing::error!("failed to create the replication manifest: {:?}", err);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct GetSegmentFileRange {
+    pub path: String,
+    pub offset: u64,
+    pub len: u64,
+}
+impl sonic::service::Message<SearchService> for GetSegmentFileRange {
+    type Response = Option<Vec<u8>>;
+    async fn handle(self, server: &SearchService) -> Self::Response {
+        let primary = Arc::clone(&server.replication);
+        let index = server.local_searcher.index().read();
+        let path = self.path.clone();
+
+        match tokio::task::spawn_blocking(move || {
+            primary.read_range(&index, &path, self.offset, self.len)
+        })
+        .await
+        .unwrap_or_else(|err| Err(err.into()))
+        {
+            Ok(bytes) => Some(bytes),
+            Err(err) => {
+                tracing::error!("failed to read {} for a replica: {:?}", self.path, err);
+                None
+            }
+        }
+    }
+}
+
 pub async fn run(config: config::SearchServerConfig) -> Result<()> {
     let addr = config.host;
     let server = SearchService::new(config).await?.bind(addr).await.unwrap();
@@ -17,14 +17,15 @@ use crate::{
     canon_index::CanonicalIndex,
     config::{self, WarcSource, WebgraphConstructConfig},
     entrypoint::download_all_warc_files,
+    warc::WarcRecord,
     webgraph::{self, Node, NodeID, WebgraphWriter},
-    webpage::{url_ext::UrlExt, Html},
+    webpage::{html::links::RelFlags, url_ext::UrlExt, Html},
     Result,
 };
 use itertools::Itertools;
 use url::Url;
 
-use std::{path::Path, sync::Arc};
+use std::{collections::HashSet, path::Path, sync::Arc};
 use tokio::pin;
 use tracing::{info, trace};
 
@@ -105,6 +106,41 @@ pub struct WebgraphWorker {
 }
 
 impl WebgraphWorker {
+    /// Redirects are stored as edges from the requested url to the url of the record,
+    /// so they can be resolved to their final destination.
+    fn insert_redirect(&mut self, record: &WarcRecord) {
+        let source = match record
+            .metadata
+            .redirected_from
+            .as_ref()
+            .and_then(|url| Url::parse(url).ok())
+        {
+            Some(source) => source,
+            None => return,
+        };
+
+        let destination = match Url::parse(&record.request.url) {
+            Ok(destination) => destination,
+            Err(_) => return,
+        };
+
+        let source = Node::from(source);
+        let destination = Node::from(destination);
+
+        if source.id() == destination.id() {
+            return;
+        }
+
+        trace!("inserting redirect {:?} -> {:?}", source, destination);
+        self.page_graph.insert_with_timestamp(
+            source,
+            destination,
+            String::new(),
+            RelFlags::REDIRECT,
+            record.metadata.fetch_time_ms / 1000,
+        );
+    }
+
     pub fn process_job(&mut self, job: &Job) {
         let name = job.warc_paths.first().unwrap().split('/').last().unwrap();
 
@@ -117,6 +153,8 @@ impl WebgraphWorker {
 
         for file in warc_files.by_ref() {
             for record in file.records().flatten() {
+                self.insert_redirect(&record);
+
                 let webpage =
                     match Html::parse_without_text(&record.response.body, &record.request.url) {
                         Ok(webpage) => webpage,
@@ -126,6 +164,15 @@ impl WebgraphWorker {
                         }
                     };
 
+                let discovered_at = record.metadata.fetch_time_ms / 1000;
+
+                let mut page = webpage.url().clone();
+                if let Some(index) = &self.canonical_index {
+                    page = canonical_or_self(index, page);
+                }
+                self.page_graph
+                    .record_crawl(&Node::from(page), discovered_at);
+
                 for mut link in webpage
                     .anchor_links()
                     .into_iter()
@@ -146,11 +193,12 @@ impl WebgraphWorker {
                     let mut destination = Node::from(destination);
 
                     trace!("inserting link {:?}", link);
-                    self.page_graph.insert(
+                    self.page_graph.insert_with_timestamp(
                         source.clone(),
                         destination.clone(),
                         link.text.clone(),
                         link.rel,
+                        discovered_at,
                     );
 
                     let dest_domain = link.destination.root_domain();
@@ -162,8 +210,13 @@ impl WebgraphWorker {
                         source = source.into_host();
                         destination = destination.into_host();
 
-                        self.host_graph
-                            .insert(source, destination, link.text, link.rel);
+                        self.host_graph.insert_with_timestamp(
+                            source,
+                            destination,
+                            link.text,
+                            link.rel,
+                            discovered_at,
+                        );
                     }
                 }
             }
@@ -179,15 +232,110 @@ impl WebgraphWorker {
 pub struct Webgraph {}
 
 impl Webgraph {
-    pub fn run(config: &WebgraphConstructConfig) -> Result<()> {
-        let warc_paths = config.warc_source.paths()?;
+    /// Build the host and page graphs from the warc files of the config.
+    ///
+    /// If the graphs already exist and `full` is not set, only the warc files that are not
+    /// in the graphs yet are parsed and their edges are added to the existing graphs. The
+    /// outgoing edges of the pages that were crawled again replace the edges from their
+    /// earlier crawls in the page graph. The edges of the host graph are aggregated over all
+    /// the pages of the hosts, so the host graph only gains edges.
+    pub fn run(config: &WebgraphConstructConfig, full: bool) -> Result<()> {
+        let warc_paths: Vec<_> = config
+            .warc_source
+            .paths()?
+            .into_iter()
+            .skip(config.skip_warc_files.unwrap_or(0))
+            .take(config.limit_warc_files.unwrap_or(usize::MAX))
+            .collect();
+
+        let existing = if full {
+            None
+        } else {
+            Self::open_existing(config)
+        };
+
+        let warc_paths = match &existing {
+            Some((_, page_graph)) => {
+                let applied: HashSet<_> = page_graph.applied_batches().iter().collect();
+
+                warc_paths
+                    .into_iter()
+                    .filter(|warc_path| !applied.contains(warc_path))
+                    .collect()
+            }
+            None => warc_paths,
+        };
+
+        if warc_paths.is_empty() {
+            info!("all the warc files are already in the webgraph");
+            return Ok(());
+        }
+
+        let (mut host_graph, mut page_graph) = Self::build(config, &warc_paths)?;
+
+        host_graph.add_applied_batches(warc_paths.clone());
+        page_graph.add_applied_batches(warc_paths);
+
+        match existing {
+            Some((mut existing_host_graph, mut existing_page_graph)) => {
+                info!("adding the edges to the existing webgraph");
 
+                existing_host_graph.merge(host_graph)?;
+                existing_page_graph.merge(page_graph)?;
+
+                existing_host_graph.merge_all_segments_superseding(Default::default())?;
+                existing_page_graph.merge_all_segments_superseding(Default::default())?;
+
+                existing_host_graph.optimize_read();
+                existing_page_graph.optimize_read();
+            }
+            None => {
+                if config.merge_all_segments {
+                    host_graph.optimize_read(); // save space in id2node db
+                    page_graph.optimize_read(); // save space in id2node db
+                    host_graph.merge_all_segments(Default::default())?;
+                    page_graph.merge_all_segments(Default::default())?;
+                } else {
+                    host_graph.refresh_centrality_quantiles();
+                    page_graph.refresh_centrality_quantiles();
+                }
+
+                host_graph.optimize_read();
+                page_graph.optimize_read();
+
+                crate::mv(host_graph.path(), &config.host_graph_base_path)?;
+                crate::mv(page_graph.path(), &config.page_graph_base_path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn open_existing(
+        config: &WebgraphConstructConfig,
+    ) -> Option<(webgraph::Webgraph, webgraph::Webgraph)> {
+        let exists = |path: &str| Path::new(path).join("metadata.json").exists();
+
+        if !exists(&config.host_graph_base_path) || !exists(&config.page_graph_base_path) {
+            return None;
+        }
+
+        Some((
+            webgraph::Webgraph::builder(&config.host_graph_base_path).open(),
+            webgraph::Webgraph::builder(&config.page_graph_base_path).open(),
+        ))
+    }
+
+    /// Build the graphs of the warc files in the worker folders of the graphs.
+    fn build(
+        config: &WebgraphConstructConfig,
+        warc_paths: &[String],
+    ) -> Result<(webgraph::Webgraph, webgraph::Webgraph)> {
         let job_config = JobConfig::from(config.warc_source.clone());
 
         let jobs: Vec<_> = warc_paths
-            .into_iter()
-            .skip(config.skip_warc_files.unwrap_or(0))
-            .take(config.limit_warc_files.unwrap_or(usize::MAX))
+            .iter()
+            .cloned()
             .chunks(config.batch_size.unwrap_or(1))
             .into_iter()
             .map(|warc_paths| Job {
@@ -232,8 +380,11 @@ impl Webgraph {
             let page_path = page_path.join(format!("worker_{i}"));
 
             let mut worker = WebgraphWorker {
-                host_graph: open_host_graph_writer(host_path, host_centrality_rank_store.clone()),
-                page_graph: open_page_graph_writer(page_path, host_centrality_rank_store.clone()),
+                host_graph: open_host_graph_writer(host_path, host_centrality_rank_store.clone())
+                    .with_max_label_length(config.max_label_length),
+                page_graph: open_page_graph_writer(page_path, host_centrality_rank_store.clone())
+                    .with_max_label_length(config.max_label_length)
+                    .with_crawl_times(),
                 canonical_index: canonical_index.clone(),
             };
 
@@ -266,19 +417,149 @@ impl Webgraph {
             page_graph.merge(other_page)?;
         }
 
-        if config.merge_all_segments {
-            host_graph.optimize_read(); // save space in id2node db
-            page_graph.optimize_read(); // save space in id2node db
-            host_graph.merge_all_segments(Default::default())?;
-            page_graph.merge_all_segments(Default::default())?;
+        Ok((host_graph, page_graph))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::LocalConfig,
+        warc::{Metadata, Request, Response, WarcWriter},
+        webgraph::{EdgeLimit, WebgraphBuilder},
+    };
+
+    use super::*;
+
+    fn write_batch(folder: &Path, name: &str, fetch_time_ms: u64, pages: &[(&str, &[&str])]) {
+        let mut writer = WarcWriter::new();
+
+        for (url, links) in pages {
+            let links: String = links
+                .iter()
+                .map(|link| format!(r#"<a href="{link}">{link}</a>"#))
+                .collect();
+
+            writer
+                .write(&WarcRecord {
+                    request: Request {
+                        url: url.to_string(),
+                    },
+                    response: Response {
+                        body: format!("<html><body>{links}</body></html>"),
+                        payload_type: None,
+                    },
+                    metadata: Metadata {
+                        fetch_time_ms,
+                        redirected_from: None,
+                        last_modified: None,
+                        sitemap_lastmod: None,
+                        robots_version: None,
+                        robots_rule: None,
+                        fetched_at: None,
+                    },
+                })
+                .unwrap();
         }
 
-        host_graph.optimize_read();
-        page_graph.optimize_read();
+        std::fs::write(folder.join(name), writer.finish().unwrap()).unwrap();
+    }
 
-        crate::mv(host_graph.path(), &config.host_graph_base_path)?;
-        crate::mv(page_graph.path(), &config.page_graph_base_path)?;
+    fn config(folder: &Path, graphs: &Path, names: &[&str]) -> WebgraphConstructConfig {
+        WebgraphConstructConfig {
+            host_graph_base_path: graphs.join("host").to_str().unwrap().to_string(),
+            page_graph_base_path: graphs.join("page").to_str().unwrap().to_string(),
+            warc_source: WarcSource::Local(LocalConfig {
+                folder: folder.to_str().unwrap().to_string(),
+                names: names.iter().map(|name| name.to_string()).collect(),
+            }),
+            limit_warc_files: None,
+            skip_warc_files: None,
+            batch_size: None,
+            canonical_index_path: None,
+            host_centrality_rank_store_path: None,
+            merge_all_segments: true,
+            max_label_length: config::defaults::Webgraph::max_label_length(),
+        }
+    }
 
-        Ok(())
+    fn outgoing(graph: &webgraph::Webgraph, node: &str) -> Vec<(Node, u64)> {
+        let mut edges: Vec<_> = graph
+            .outgoing_edges(Node::from(node), EdgeLimit::Unlimited)
+            .into_iter()
+            .map(|edge| (edge.to, edge.discovered_at))
+            .collect();
+        edges.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        edges
+    }
+
+    #[test]
+    fn incremental_build() {
+        let folder = crate::gen_temp_path();
+        std::fs::create_dir_all(&folder).unwrap();
+        let graphs = crate::gen_temp_path();
+
+        write_batch(
+            &folder,
+            "0.warc.gz",
+            1_000_000,
+            &[
+                ("https://a.com/", &["https://b.com/", "https://c.com/"]),
+                ("https://d.com/", &["https://e.com/"]),
+                ("https://g.com/", &["https://h.com/"]),
+            ],
+        );
+        Webgraph::run(&config(&folder, &graphs, &["0.warc.gz"]), false).unwrap();
+
+        // a.com is crawled again and no longer links to c.com
+        write_batch(
+            &folder,
+            "1.warc.gz",
+            2_000_000,
+            &[
+                ("https://a.com/", &["https://b.com/", "https://f.com/"]),
+                ("https://g.com/", &[]),
+            ],
+        );
+        let config = config(&folder, &graphs, &["0.warc.gz", "1.warc.gz"]);
+        Webgraph::run(&config, false).unwrap();
+
+        let page_graph = WebgraphBuilder::new(&config.page_graph_base_path).open();
+
+        assert_eq!(
+            outgoing(&page_graph, "https://a.com/"),
+            vec![
+                (Node::from("https://b.com/"), 1_000),
+                (Node::from("https://f.com/"), 2_000),
+            ]
+        );
+        assert!(page_graph
+            .ingoing_edges(Node::from("https://c.com/"), EdgeLimit::Unlimited)
+            .is_empty());
+        assert_eq!(
+            outgoing(&page_graph, "https://d.com/"),
+            vec![(Node::from("https://e.com/"), 1_000)]
+        );
+
+        // g.com no longer has any links
+        assert!(outgoing(&page_graph, "https://g.com/").is_empty());
+        assert_eq!(
+            page_graph.applied_batches(),
+            &["0.warc.gz".to_string(), "1.warc.gz".to_string()]
+        );
+
+        // the host graph only gains edges
+        let host_graph = WebgraphBuilder::new(&config.host_graph_base_path).open();
+        assert_eq!(outgoing(&host_graph, "a.com").len(), 3);
+        drop(page_graph);
+        drop(host_graph);
+
+        // the applied batches are skipped
+        std::fs::remove_file(folder.join("0.warc.gz")).unwrap();
+        Webgraph::run(&config, false).unwrap();
+
+        let page_graph = WebgraphBuilder::new(&config.page_graph_base_path).open();
+        assert_eq!(outgoing(&page_graph, "https://a.com/").len(), 2);
+        assert_eq!(outgoing(&page_graph, "https://d.com/").len(), 1);
     }
 }
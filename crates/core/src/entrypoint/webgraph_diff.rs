@@ -0,0 +1,278 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Compare the backlinks of hosts between two snapshots of a webgraph.
+//!
+//! The backlinks are read through the regular edge queries of each graph, so
+//! the graphs can have a different number of segments.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use tracing::info;
+
+use crate::{
+    webgraph::{EdgeLimit, Node, Webgraph, WebgraphBuilder},
+    Result,
+};
+
+/// A host linking to the host of the report, with the anchor texts of its links.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Backlink {
+    pub from_host: String,
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BacklinkDiff {
+    pub host: String,
+    /// Linking hosts that are only in the new graph.
+    pub gained: Vec<Backlink>,
+    /// Linking hosts that are only in the old graph.
+    pub lost: Vec<Backlink>,
+    /// Linking hosts that are in both graphs. The labels are from the new graph.
+    pub retained: Vec<Backlink>,
+}
+
+/// The hosts linking to `host` or any of its pages, keyed by linking host.
+/// Links between pages of the host itself are not backlinks and are skipped.
+fn backlinks(graph: &Webgraph, host: &Node) -> BTreeMap<String, BTreeSet<String>> {
+    let host_id = host.id();
+
+    let mut targets = graph.pages_by_host(&host_id);
+    targets.push(host_id);
+    targets.sort();
+    targets.dedup();
+
+    let mut res: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+
+    for target in targets {
+        for edge in graph.raw_ingoing_edges_with_labels(&target, EdgeLimit::Unlimited) {
+            let from_host = match graph.id2node(&edge.from) {
+                Some(node) => node.into_host(),
+                None => continue,
+            };
+
+            if from_host.as_str() == host.as_str() {
+                continue;
+            }
+
+            let labels = res.entry(from_host.as_str().to_string()).or_default();
+
+            if !edge.label.is_empty() {
+                labels.insert(edge.label);
+            }
+        }
+    }
+
+    res
+}
+
+fn to_backlinks(hosts: BTreeMap<String, BTreeSet<String>>) -> Vec<Backlink> {
+    hosts
+        .into_iter()
+        .map(|(from_host, labels)| Backlink {
+            from_host,
+            labels: labels.into_iter().collect(),
+        })
+        .collect()
+}
+
+pub fn diff(old: &Webgraph, new: &Webgraph, host: &str) -> BacklinkDiff {
+    let node = Node::from(host).into_host();
+
+    let mut old_backlinks = backlinks(old, &node);
+    let new_backlinks = backlinks(new, &node);
+
+    let mut gained = BTreeMap::new();
+    let mut retained = BTreeMap::new();
+
+    for (from_host, labels) in new_backlinks {
+        if old_backlinks.remove(&from_host).is_some() {
+            retained.insert(from_host, labels);
+        } else {
+            gained.insert(from_host, labels);
+        }
+    }
+
+    BacklinkDiff {
+        host: node.as_str().to_string(),
+        gained: to_backlinks(gained),
+        lost: to_backlinks(old_backlinks),
+        retained: to_backlinks(retained),
+    }
+}
+
+/// Diff several hosts against the same pair of graphs.
+pub fn diff_batch<S: AsRef<str>>(old: &Webgraph, new: &Webgraph, hosts: &[S]) -> Vec<BacklinkDiff> {
+    hosts
+        .iter()
+        .map(|host| {
+            let res = diff(old, new, host.as_ref());
+
+            info!(
+                "{}: {} gained, {} lost, {} retained",
+                res.host,
+                res.gained.len(),
+                res.lost.len(),
+                res.retained.len()
+            );
+
+            res
+        })
+        .collect()
+}
+
+/// Read the hosts of a batch from a file with one host per line.
+pub fn read_hosts<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// Diff the given hosts and write the report as json to `out`, or to stdout if no
+/// output file is given. A single host is written as an object and several
+/// hosts as an array.
+pub fn run<P: AsRef<Path>>(
+    old_path: P,
+    new_path: P,
+    hosts: Vec<String>,
+    out: Option<P>,
+) -> Result<()> {
+    let old = WebgraphBuilder::new(old_path).single_threaded().open();
+    let new = WebgraphBuilder::new(new_path).single_threaded().open();
+
+    let report = if hosts.len() == 1 {
+        serde_json::to_string_pretty(&diff(&old, &new, &hosts[0]))?
+    } else {
+        serde_json::to_string_pretty(&diff_batch(&old, &new, &hosts))?
+    };
+
+    match out {
+        Some(out) => std::fs::write(out, report)?,
+        None => println!("{report}"),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        executor::Executor,
+        webgraph::{Compression, WebgraphWriter},
+        webpage::html::links::RelFlags,
+    };
+
+    use super::*;
+
+    fn writer() -> WebgraphWriter {
+        WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        )
+    }
+
+    /// A graph with one segment per chunk of `segment_size` edges.
+    fn graph(edges: &[(&str, &str, &str)], segment_size: usize) -> Webgraph {
+        let mut segments = edges.chunks(segment_size).map(|chunk| {
+            let mut writer = writer();
+
+            for (from, to, label) in chunk {
+                writer.insert(
+                    Node::from(*from),
+                    Node::from(*to),
+                    label.to_string(),
+                    RelFlags::default(),
+                );
+            }
+
+            writer.finalize()
+        });
+
+        let mut graph = segments.next().unwrap();
+
+        for other in segments {
+            graph.merge(other).unwrap();
+        }
+
+        graph
+    }
+
+    fn backlink(from_host: &str, labels: &[&str]) -> Backlink {
+        Backlink {
+            from_host: from_host.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn gained_and_lost() {
+        let old = graph(
+            &[
+                ("a.com/1", "example.com/page", "a link"),
+                ("b.com/1", "example.com", "b link"),
+                ("c.com/1", "example.com/other", ""),
+                ("example.com/page", "example.com", "internal"),
+                ("a.com/1", "other.com", "unrelated"),
+            ],
+            5,
+        );
+
+        // same overlap, but spread over several segments.
+        let new = graph(
+            &[
+                ("a.com/2", "example.com/page", "new a link"),
+                ("b.com/1", "example.com", "b link"),
+                ("d.com/1", "example.com/page", "d link"),
+                ("d.com/2", "example.com", "another d link"),
+                ("other.com", "a.com/1", ""),
+            ],
+            2,
+        );
+        assert!(new.stats().num_segments > 1);
+
+        let res = diff(&old, &new, "example.com");
+
+        assert_eq!(res.host, "example.com");
+        assert_eq!(
+            res.gained,
+            vec![backlink("d.com", &["another d link", "d link"])]
+        );
+        assert_eq!(res.lost, vec![backlink("c.com", &[])]);
+        assert_eq!(
+            res.retained,
+            vec![
+                backlink("a.com", &["new a link"]),
+                backlink("b.com", &["b link"]),
+            ]
+        );
+
+        let batch = diff_batch(&old, &new, &["example.com", "a.com"]);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0], res);
+        assert_eq!(batch[1].gained, vec![backlink("other.com", &[])]);
+        assert!(batch[1].lost.is_empty());
+        assert!(batch[1].retained.is_empty());
+    }
+}
@@ -27,11 +27,24 @@ use crate::distributed::member::Member;
 use crate::distributed::member::Service;
 use crate::distributed::sonic::service::sonic_service;
 use crate::distributed::sonic::service::Message;
+use crate::host_languages::HostLanguageStore;
+use crate::webgraph::CappedCount;
+use crate::webgraph::CappedEdges;
+use crate::webgraph::CoLinkCounts;
+use crate::webgraph::CoLinkLimits;
 use crate::webgraph::Edge;
 use crate::webgraph::EdgeLimit;
+use crate::webgraph::EdgeQueryCap;
 use crate::webgraph::FullEdge;
+use crate::webgraph::LabelMatch;
+use crate::webgraph::LabelSearchQuery;
+use crate::webgraph::LinkAggregates;
 use crate::webgraph::Node;
 use crate::webgraph::NodeID;
+use crate::webgraph::QueryPriority;
+use crate::webgraph::RawNeighborhood;
+use crate::webgraph::Redirects;
+use crate::webgraph::TimeRange;
 use crate::webgraph::Webgraph;
 use crate::webgraph::WebgraphBuilder;
 use crate::Result;
@@ -46,6 +59,15 @@ pub struct ScoredHost {
 
 pub struct WebGraphService {
     graph: Arc<Webgraph>,
+    host_languages: Option<Arc<HostLanguageStore>>,
+    host_centrality: Option<Arc<speedy_kv::Db<NodeID, f64>>>,
+    edge_query_caps: config::EdgeQueryCapsConfig,
+}
+
+impl WebGraphService {
+    fn cap(&self, priority: QueryPriority) -> EdgeQueryCap {
+        EdgeQueryCap::from_config(&self.edge_query_caps, priority)
+    }
 }
 
 sonic_service!(
@@ -55,10 +77,22 @@ sonic_service!(
         IngoingEdges,
         OutgoingEdges,
         RawIngoingEdges,
+        IngoingEdgesInRange,
+        NumIngoingEdgesInRange,
         RawOutgoingEdges,
         RawIngoingEdgesWithLabels,
         RawOutgoingEdgesWithLabels,
-        PagesByHosts
+        PagesByHosts,
+        DominantLanguage,
+        NumIngoingEdges,
+        CentralityPercentile,
+        HostLinkAggregates,
+        HostCoLinkCounts,
+        ResolveRedirects,
+        RawRedirectSources,
+        Neighborhood,
+        EdgesBetween,
+        IngoingLabelSearch
     ]
 );
 
@@ -79,13 +113,16 @@ impl Message<WebGraphService> for GetNode {
 pub struct IngoingEdges {
     pub node: Node,
     pub limit: EdgeLimit,
+    pub priority: QueryPriority,
 }
 
 impl Message<WebGraphService> for IngoingEdges {
-    type Response = Vec<FullEdge>;
+    type Response = CappedEdges<FullEdge>;
 
     async fn handle(self, server: &WebGraphService) -> Self::Response {
-        server.graph.ingoing_edges(self.node, self.limit)
+        server
+            .graph
+            .ingoing_edges_capped(self.node, self.limit, server.cap(self.priority))
     }
 }
 
@@ -93,13 +130,16 @@ impl Message<WebGraphService> for IngoingEdges {
 pub struct OutgoingEdges {
     pub node: Node,
     pub limit: EdgeLimit,
+    pub priority: QueryPriority,
 }
 
 impl Message<WebGraphService> for OutgoingEdges {
-    type Response = Vec<FullEdge>;
+    type Response = CappedEdges<FullEdge>;
 
     async fn handle(self, server: &WebGraphService) -> Self::Response {
-        server.graph.outgoing_edges(self.node, self.limit)
+        server
+            .graph
+            .outgoing_edges_capped(self.node, self.limit, server.cap(self.priority))
     }
 }
 
@@ -107,13 +147,69 @@ impl Message<WebGraphService> for OutgoingEdges {
 pub struct RawIngoingEdges {
     pub node: NodeID,
     pub limit: EdgeLimit,
+    pub priority: QueryPriority,
 }
 
 impl Message<WebGraphService> for RawIngoingEdges {
-    type Response = Vec<Edge<()>>;
+    type Response = CappedEdges<Edge<()>>;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server
+            .graph
+            .raw_ingoing_edges_capped(&self.node, self.limit, server.cap(self.priority))
+    }
+}
+
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct IngoingEdgesInRange {
+    pub node: Node,
+    pub range: TimeRange,
+    pub limit: EdgeLimit,
+    pub priority: QueryPriority,
+}
+
+impl Message<WebGraphService> for IngoingEdgesInRange {
+    type Response = CappedEdges<FullEdge>;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server.graph.ingoing_edges_in_range_capped(
+            self.node,
+            self.range,
+            self.limit,
+            server.cap(self.priority),
+        )
+    }
+}
+
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct NumIngoingEdgesInRange {
+    pub node: NodeID,
+    pub range: TimeRange,
+    pub priority: QueryPriority,
+}
+
+impl Message<WebGraphService> for NumIngoingEdgesInRange {
+    type Response = CappedCount;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server.graph.num_ingoing_edges_in_range_capped(
+            &self.node,
+            self.range,
+            server.cap(self.priority),
+        )
+    }
+}
+
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct NumIngoingEdges {
+    pub node: NodeID,
+}
+
+impl Message<WebGraphService> for NumIngoingEdges {
+    type Response = usize;
 
     async fn handle(self, server: &WebGraphService) -> Self::Response {
-        server.graph.raw_ingoing_edges(&self.node, self.limit)
+        server.graph.num_ingoing_edges(&self.node)
     }
 }
 
@@ -121,13 +217,16 @@ impl Message<WebGraphService> for RawIngoingEdges {
 pub struct RawOutgoingEdges {
     pub node: NodeID,
     pub limit: EdgeLimit,
+    pub priority: QueryPriority,
 }
 
 impl Message<WebGraphService> for RawOutgoingEdges {
-    type Response = Vec<Edge<()>>;
+    type Response = CappedEdges<Edge<()>>;
 
     async fn handle(self, server: &WebGraphService) -> Self::Response {
-        server.graph.raw_outgoing_edges(&self.node, self.limit)
+        server
+            .graph
+            .raw_outgoing_edges_capped(&self.node, self.limit, server.cap(self.priority))
     }
 }
 
@@ -135,15 +234,18 @@ impl Message<WebGraphService> for RawOutgoingEdges {
 pub struct RawIngoingEdgesWithLabels {
     pub node: NodeID,
     pub limit: EdgeLimit,
+    pub priority: QueryPriority,
 }
 
 impl Message<WebGraphService> for RawIngoingEdgesWithLabels {
-    type Response = Vec<Edge<String>>;
+    type Response = CappedEdges<Edge<String>>;
 
     async fn handle(self, server: &WebGraphService) -> Self::Response {
-        server
-            .graph
-            .raw_ingoing_edges_with_labels(&self.node, self.limit)
+        server.graph.raw_ingoing_edges_with_labels_capped(
+            &self.node,
+            self.limit,
+            server.cap(self.priority),
+        )
     }
 }
 
@@ -151,15 +253,18 @@ impl Message<WebGraphService> for RawIngoingEdgesWithLabels {
 pub struct RawOutgoingEdgesWithLabels {
     pub node: NodeID,
     pub limit: EdgeLimit,
+    pub priority: QueryPriority,
 }
 
 impl Message<WebGraphService> for RawOutgoingEdgesWithLabels {
-    type Response = Vec<Edge<String>>;
+    type Response = CappedEdges<Edge<String>>;
 
     async fn handle(self, server: &WebGraphService) -> Self::Response {
-        server
-            .graph
-            .raw_outgoing_edges_with_labels(&self.node, self.limit)
+        server.graph.raw_outgoing_edges_with_labels_capped(
+            &self.node,
+            self.limit,
+            server.cap(self.priority),
+        )
     }
 }
 
@@ -180,12 +285,186 @@ impl Message<WebGraphService> for PagesByHosts {
     }
 }
 
+/// The ISO 639-3 code of the most common language of the pages on the host.
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct DominantLanguage {
+    pub node: NodeID,
+}
+
+impl Message<WebGraphService> for DominantLanguage {
+    type Response = Option<String>;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server
+            .host_languages
+            .as_ref()?
+            .dominant_language(&self.node)
+            .map(|lang| lang.code().to_string())
+    }
+}
+
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct CentralityPercentile {
+    pub rank: u64,
+}
+
+impl Message<WebGraphService> for CentralityPercentile {
+    type Response = Option<u8>;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server
+            .graph
+            .centrality_quantiles()
+            .map(|quantiles| quantiles.percentile(self.rank))
+    }
+}
+
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct HostLinkAggregates {
+    pub host: Node,
+    pub priority: QueryPriority,
+}
+
+impl Message<WebGraphService> for HostLinkAggregates {
+    type Response = LinkAggregates;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server
+            .graph
+            .link_aggregates(&self.host, server.cap(self.priority))
+    }
+}
+
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct HostCoLinkCounts {
+    pub host: Node,
+    pub limits: CoLinkLimits,
+    pub priority: QueryPriority,
+}
+
+impl Message<WebGraphService> for HostCoLinkCounts {
+    type Response = CoLinkCounts;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server
+            .graph
+            .co_link_counts(&self.host, self.limits, server.cap(self.priority))
+    }
+}
+
+/// The final destination of the redirects starting at the node. Mostly useful for debugging.
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct ResolveRedirects {
+    pub node: Node,
+}
+
+impl Message<WebGraphService> for ResolveRedirects {
+    type Response = Node;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server.graph.resolve_redirects(self.node)
+    }
+}
+
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct RawRedirectSources {
+    pub node: NodeID,
+}
+
+impl Message<WebGraphService> for RawRedirectSources {
+    type Response = Vec<NodeID>;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server.graph.raw_redirect_sources(self.node)
+    }
+}
+
+/// The most central backlinks and forwardlinks of the host on this shard, and the
+/// edges among them that are stored on this shard if `include_interconnections` is set.
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct Neighborhood {
+    pub host: Node,
+    pub per_direction_limit: usize,
+    pub include_interconnections: bool,
+    pub priority: QueryPriority,
+}
+
+impl Message<WebGraphService> for Neighborhood {
+    type Response = RawNeighborhood;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server.graph.raw_neighborhood(
+            &self.host,
+            self.per_direction_limit,
+            self.include_interconnections,
+            server.cap(self.priority),
+            server.host_centrality.as_deref(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct EdgesBetween {
+    pub nodes: Vec<NodeID>,
+    pub priority: QueryPriority,
+}
+
+impl Message<WebGraphService> for EdgesBetween {
+    type Response = CappedEdges<(NodeID, NodeID)>;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server
+            .graph
+            .edges_between(&self.nodes, server.cap(self.priority))
+    }
+}
+
+/// The ingoing edges of the node whose label matches the query, in the sort order of the
+/// graph. The matches of all the shards are ranked by [`LabelSearchQuery::rank`].
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct IngoingLabelSearch {
+    pub node: Node,
+    pub query: LabelSearchQuery,
+    pub priority: QueryPriority,
+}
+
+impl Message<WebGraphService> for IngoingLabelSearch {
+    type Response = CappedEdges<LabelMatch>;
+
+    async fn handle(self, server: &WebGraphService) -> Self::Response {
+        server
+            .graph
+            .match_ingoing_labels(self.node, &self.query, server.cap(self.priority))
+    }
+}
+
 pub async fn run(config: config::WebgraphServerConfig) -> Result<()> {
     let addr: SocketAddr = config.host;
 
-    let graph = Arc::new(WebgraphBuilder::new(config.graph_path).open());
-
-    let server = WebGraphService { graph }.bind(addr).await.unwrap();
+    let graph = Arc::new(
+        WebgraphBuilder::new(config.graph_path)
+            .edge_buffer_pool(config.edge_buffer_pool)
+            .strict_id2node(config.strict_id2node)
+            .open(),
+    );
+    let host_languages = match config.host_languages {
+        Some(path) => Some(Arc::new(HostLanguageStore::open(path)?)),
+        None => None,
+    };
+    let host_centrality = match config.host_centrality {
+        Some(path) => Some(Arc::new(speedy_kv::Db::open_or_create(path)?)),
+        None => None,
+    };
+
+    let server = WebGraphService {
+        graph,
+        host_languages,
+        host_centrality,
+        edge_query_caps: config.edge_query_caps,
+    }
+    .bind(addr)
+    .await
+    .unwrap();
 
     // dropping the handle leaves the cluster
     let _cluster = Arc::new(
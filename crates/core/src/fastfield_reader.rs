@@ -14,20 +14,45 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
 
+use once_cell::sync::OnceCell;
 use tantivy::{columnar::ColumnValues, index::SegmentId, DocId};
 
 use crate::{
+    config::WarmupConfig,
     enum_map::EnumMap,
     schema::{fast_field::FastField, DataType, FastFieldEnum, Field},
 };
 
-#[derive(Default, Clone)]
+/// A segment whose fast fields are read into memory the first time they are needed.
+struct LazySegment {
+    reader: tantivy::SegmentReader,
+    warm: OnceCell<Arc<SegmentReader>>,
+}
+
+impl LazySegment {
+    fn get(&self) -> &Arc<SegmentReader> {
+        self.warm
+            .get_or_init(|| Arc::new(SegmentReader::new(&self.reader)))
+    }
+}
+
+#[derive(Default)]
 struct InnerFastFieldReader {
-    segments: HashMap<SegmentId, Arc<SegmentReader>>,
+    segments: HashMap<SegmentId, LazySegment>,
+    priority: Vec<SegmentId>,
 }
 
+/// The fast fields of all segments of a searcher.
+///
+/// Segments are read into memory the first time they are used, so a query that hits a cold
+/// segment pays for warming it. Use [`FastFieldReader::warmup`] or [`FastFieldReader::warm_all`]
+/// to warm the segments ahead of the queries.
 #[derive(Default, Clone)]
 pub struct FastFieldReader {
     inner: Arc<InnerFastFieldReader>,
@@ -35,57 +60,164 @@ pub struct FastFieldReader {
 
 impl FastFieldReader {
     pub fn get_segment(&self, segment: &SegmentId) -> Arc<SegmentReader> {
-        Arc::clone(self.inner.segments.get(segment).unwrap())
+        Arc::clone(self.inner.segments.get(segment).unwrap().get())
     }
 
     pub fn borrow_segment(&self, segment: &SegmentId) -> &SegmentReader {
-        self.inner.segments.get(segment).unwrap()
+        self.inner.segments.get(segment).unwrap().get()
+    }
+
+    pub fn is_warm(&self, segment: &SegmentId) -> bool {
+        self.inner
+            .segments
+            .get(segment)
+            .map(|segment| segment.warm.get().is_some())
+            .unwrap_or(false)
+    }
+
+    /// The segments in the order they are warmed by [`FastFieldReader::warmup`].
+    ///
+    /// The largest segments come first since they hold most of the documents. Tantivy keeps no
+    /// creation time for a segment, but segments from later commits come later in the searcher,
+    /// so among segments of equal size the most recent ones come first.
+    pub fn segments_by_priority(&self) -> &[SegmentId] {
+        &self.inner.priority
+    }
+
+    pub fn warm_all(&self) {
+        for segment in self.inner.segments.values() {
+            segment.get();
+        }
+    }
+
+    /// Warm the `priority_segments` first segments of [`FastFieldReader::segments_by_priority`]
+    /// followed by the rest in a background thread. The returned handle signals when the priority
+    /// segments are warm, at which point the reader can serve queries without warming the largest
+    /// segments on the query path.
+    pub fn warmup(&self, config: &WarmupConfig) -> Warmup {
+        let warmup = Warmup::default();
+
+        let reader = self.clone();
+        let handle = warmup.clone();
+        let priority_segments = config.priority_segments;
+        let delay = Duration::from_millis(config.background_delay_ms);
+
+        std::thread::spawn(move || {
+            let (priority, rest) = reader
+                .segments_by_priority()
+                .split_at(priority_segments.min(reader.segments_by_priority().len()));
+
+            for segment in priority {
+                reader.borrow_segment(segment);
+            }
+
+            handle.update(|progress| progress.ready = true);
+
+            for segment in rest {
+                if !delay.is_zero() {
+                    std::thread::sleep(delay);
+                }
+
+                reader.borrow_segment(segment);
+            }
+
+            handle.update(|progress| progress.done = true);
+        });
+
+        warmup
     }
 }
 
 impl FastFieldReader {
     pub fn new(tv_searcher: &tantivy::Searcher) -> Self {
         let mut segments = HashMap::new();
+        let mut priority: Vec<_> = tv_searcher
+            .segment_readers()
+            .iter()
+            .rev()
+            .map(|reader| (reader.segment_id(), reader.max_doc()))
+            .collect();
+        priority.sort_by_key(|(_, max_doc)| std::cmp::Reverse(*max_doc));
 
         for reader in tv_searcher.segment_readers() {
-            let fastfield_readers = reader.fast_fields();
-
-            let mut u64s = EnumMap::new();
-            let mut bytes = EnumMap::new();
-
-            for field in Field::all().filter_map(|f| f.as_fast()) {
-                match field.data_type() {
-                    DataType::U64 => {
-                        let num_docs = reader.max_doc() as usize;
-                        let mut data = vec![0; num_docs];
-                        if let Ok(field_reader) = fastfield_readers.u64(field.name()) {
-                            for (doc, elem) in data.iter_mut().enumerate() {
-                                *elem = field_reader.values.get_val(doc as u32);
-                            }
-                        }
-
-                        u64s.insert(field, data);
-                    }
-                    DataType::Bytes => {
-                        if let Some(reader) = fastfield_readers.bytes(field.name()).ok().flatten() {
-                            bytes.insert(field, reader);
-                        }
-                    }
-                };
-            }
-
             segments.insert(
                 reader.segment_id(),
-                Arc::new(SegmentReader {
-                    field_readers: AllReaders { u64s, bytes },
-                }),
+                LazySegment {
+                    reader: reader.clone(),
+                    warm: OnceCell::new(),
+                },
             );
         }
 
         Self {
-            inner: Arc::new(InnerFastFieldReader { segments }),
+            inner: Arc::new(InnerFastFieldReader {
+                segments,
+                priority: priority.into_iter().map(|(segment, _)| segment).collect(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct WarmupProgress {
+    ready: bool,
+    done: bool,
+}
+
+#[derive(Default)]
+struct WarmupState {
+    progress: Mutex<WarmupProgress>,
+    changed: Condvar,
+}
+
+/// Handle to a warmup started by [`FastFieldReader::warmup`].
+#[derive(Default, Clone)]
+pub struct Warmup {
+    state: Arc<WarmupState>,
+}
+
+impl Warmup {
+    fn progress(&self) -> std::sync::MutexGuard<'_, WarmupProgress> {
+        self.state
+            .progress
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update(&self, f: impl FnOnce(&mut WarmupProgress)) {
+        f(&mut self.progress());
+        self.state.changed.notify_all();
+    }
+
+    fn wait(&self, f: impl Fn(&WarmupProgress) -> bool) {
+        let mut progress = self.progress();
+
+        while !f(&progress) {
+            progress = self
+                .state
+                .changed
+                .wait(progress)
+                .unwrap_or_else(|e| e.into_inner());
         }
     }
+
+    /// Whether the priority segments are warm.
+    pub fn is_ready(&self) -> bool {
+        self.progress().ready
+    }
+
+    /// Whether all segments are warm.
+    pub fn is_done(&self) -> bool {
+        self.progress().done
+    }
+
+    pub fn wait_ready(&self) {
+        self.wait(|progress| progress.ready);
+    }
+
+    pub fn wait_done(&self) {
+        self.wait(|progress| progress.done);
+    }
 }
 
 struct AllReaders {
@@ -190,6 +322,38 @@ pub struct SegmentReader {
 }
 
 impl SegmentReader {
+    fn new(reader: &tantivy::SegmentReader) -> Self {
+        let fastfield_readers = reader.fast_fields();
+
+        let mut u64s = EnumMap::new();
+        let mut bytes = EnumMap::new();
+
+        for field in Field::all().filter_map(|f| f.as_fast()) {
+            match field.data_type() {
+                DataType::U64 => {
+                    let num_docs = reader.max_doc() as usize;
+                    let mut data = vec![0; num_docs];
+                    if let Ok(field_reader) = fastfield_readers.u64(field.name()) {
+                        for (doc, elem) in data.iter_mut().enumerate() {
+                            *elem = field_reader.values.get_val(doc as u32);
+                        }
+                    }
+
+                    u64s.insert(field, data);
+                }
+                DataType::Bytes => {
+                    if let Some(reader) = fastfield_readers.bytes(field.name()).ok().flatten() {
+                        bytes.insert(field, reader);
+                    }
+                }
+            };
+        }
+
+        Self {
+            field_readers: AllReaders { u64s, bytes },
+        }
+    }
+
     pub fn get_field_reader(&self, doc: DocId) -> FieldReader<'_> {
         FieldReader {
             readers: &self.field_readers,
@@ -64,6 +64,8 @@ impl From<&Url> for Domain {
     bincode::Decode,
     PartialEq,
     Eq,
+    PartialOrd,
+    Ord,
     Hash,
 )]
 pub struct SplitId(#[bincode(with_serde)] uuid::Uuid);
@@ -0,0 +1,317 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! How recently the pages of an index were crawled.
+//!
+//! The index counts its pages by the day they were fetched by the crawler as they are
+//! inserted and stores the counts next to the index when it is committed, so the ages of
+//! the pages never have to be read from the index itself. The counts are grouped by the
+//! day the pages were inserted, so the pages that are deleted from the index by their
+//! insertion time are also removed from the counts. The counts of two indexes are summed
+//! when they are merged. A [`FreshnessHistogram`] with the number of pages crawled within
+//! the last 30, 90 and 365 days, or whatever buckets the index was configured with, is
+//! computed from the counts when it is requested.
+
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::{DateTime, Utc};
+use utoipa::ToSchema;
+
+use crate::Result;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+fn day(timestamp: DateTime<Utc>) -> i64 {
+    timestamp.timestamp().div_euclid(SECONDS_PER_DAY)
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct StoredCount {
+    buckets_days: Vec<u64>,
+    /// The number of pages fetched on each day since the unix epoch,
+    /// keyed by the day the pages were inserted.
+    days: BTreeMap<i64, BTreeMap<i64, u64>>,
+}
+
+#[derive(Debug)]
+pub struct FreshnessCount {
+    buckets_days: Vec<u64>,
+    days: BTreeMap<i64, BTreeMap<i64, u64>>,
+    path: String,
+}
+
+impl FreshnessCount {
+    /// Open the counts stored at `path`. A new count uses the default buckets.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let stored = if path.as_ref().exists() {
+            let json = std::fs::read_to_string(path.as_ref())?;
+            serde_json::from_str(&json)?
+        } else {
+            if let Some(parent) = path.as_ref().parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            StoredCount {
+                buckets_days: crate::config::defaults::Indexing::freshness_buckets_days(),
+                days: BTreeMap::new(),
+            }
+        };
+
+        let mut count = Self {
+            buckets_days: Vec::new(),
+            days: stored.days,
+            path: path.as_ref().to_str().unwrap().to_string(),
+        };
+        count.set_buckets(stored.buckets_days);
+
+        Ok(count)
+    }
+
+    /// The maximum ages in days of the pages in each bucket of the histogram.
+    pub fn set_buckets(&mut self, mut buckets_days: Vec<u64>) {
+        buckets_days.sort_unstable();
+        buckets_days.dedup();
+
+        self.buckets_days = buckets_days;
+    }
+
+    pub fn increment(&mut self, inserted_at: DateTime<Utc>, fetched_at: DateTime<Utc>) {
+        *self
+            .days
+            .entry(day(inserted_at))
+            .or_default()
+            .entry(day(fetched_at))
+            .or_insert(0) += 1;
+    }
+
+    /// Forget the pages inserted on the days before the day of the timestamp.
+    /// The pages inserted earlier on the same day are still counted.
+    pub fn delete_before(&mut self, timestamp: DateTime<Utc>) {
+        self.days = self.days.split_off(&day(timestamp));
+    }
+
+    pub fn commit(&self) -> Result<()> {
+        let json = serde_json::to_string(&StoredCount {
+            buckets_days: self.buckets_days.clone(),
+            days: self.days.clone(),
+        })?;
+
+        std::fs::write(&self.path, json)?;
+
+        Ok(())
+    }
+
+    /// Add the counts of the other index. The buckets of this index are kept.
+    pub fn merge(&mut self, other: Self) -> Result<()> {
+        for (inserted, fetched) in other.days {
+            let days = self.days.entry(inserted).or_default();
+
+            for (day, count) in fetched {
+                *days.entry(day).or_insert(0) += count;
+            }
+        }
+
+        if Path::new(&other.path).exists() {
+            std::fs::remove_file(other.path)?;
+        }
+
+        self.commit()
+    }
+
+    /// The histogram of the ages of the pages at the time `now`.
+    pub fn histogram(&self, now: DateTime<Utc>) -> FreshnessHistogram {
+        let today = day(now);
+
+        let mut buckets: Vec<_> = self
+            .buckets_days
+            .iter()
+            .map(|max_age_days| FreshnessBucket {
+                max_age_days: *max_age_days,
+                count: 0,
+            })
+            .collect();
+        let mut total = 0;
+
+        for (day, count) in self.days.values().flatten() {
+            let age = today.saturating_sub(*day).max(0) as u64;
+            total += count;
+
+            for bucket in buckets.iter_mut().rev() {
+                if age > bucket.max_age_days {
+                    break;
+                }
+
+                bucket.count += count;
+            }
+        }
+
+        FreshnessHistogram { total, buckets }
+    }
+}
+
+/// The number of pages crawled within the maximum age of each bucket.
+/// The buckets are cumulative, so a page that was crawled a week ago
+/// is counted in both the 30 and 90 days buckets.
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct FreshnessHistogram {
+    pub total: u64,
+    pub buckets: Vec<FreshnessBucket>,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct FreshnessBucket {
+    pub max_age_days: u64,
+    pub count: u64,
+}
+
+impl FreshnessHistogram {
+    /// The histogram of the pages of both histograms, like the histograms of the
+    /// shards combined into one for the whole cluster. Only the buckets that are in
+    /// both histograms are kept, as the counts of the other buckets are not known
+    /// for all the pages.
+    pub fn merge(self, other: Self) -> Self {
+        let buckets = self
+            .buckets
+            .into_iter()
+            .filter_map(|bucket| {
+                other
+                    .buckets
+                    .iter()
+                    .find(|b| b.max_age_days == bucket.max_age_days)
+                    .map(|b| FreshnessBucket {
+                        max_age_days: bucket.max_age_days,
+                        count: bucket.count + b.count,
+                    })
+            })
+            .collect();
+
+        Self {
+            total: self.total + other.total,
+            buckets,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::gen_temp_path;
+
+    use super::*;
+
+    fn histogram(buckets: &[(u64, u64)], total: u64) -> FreshnessHistogram {
+        FreshnessHistogram {
+            total,
+            buckets: buckets
+                .iter()
+                .map(|(max_age_days, count)| FreshnessBucket {
+                    max_age_days: *max_age_days,
+                    count: *count,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn cumulative_buckets() {
+        let now = Utc::now();
+        let mut count = FreshnessCount::open(gen_temp_path().join("freshness.json")).unwrap();
+
+        for age in [0, 10, 30, 31, 100, 400, 1000] {
+            count.increment(now, now - Duration::days(age));
+        }
+
+        assert_eq!(
+            count.histogram(now),
+            histogram(&[(30, 3), (90, 4), (365, 5)], 7)
+        );
+
+        count.set_buckets(vec![7, 1]);
+        assert_eq!(count.histogram(now), histogram(&[(1, 1), (7, 1)], 7));
+
+        count.delete_before(now - Duration::days(50));
+        assert_eq!(count.histogram(now).total, 7);
+
+        count.delete_before(now + Duration::days(1));
+        assert_eq!(count.histogram(now).total, 0);
+    }
+
+    #[test]
+    fn pages_are_deleted_by_insertion_day() {
+        let now = Utc::now();
+        let mut count = FreshnessCount::open(gen_temp_path().join("freshness.json")).unwrap();
+
+        // reindexed today from an old crawl
+        count.increment(now, now - Duration::days(100));
+        count.increment(now - Duration::days(60), now - Duration::days(60));
+
+        assert_eq!(
+            count.histogram(now),
+            histogram(&[(30, 0), (90, 1), (365, 2)], 2)
+        );
+
+        count.delete_before(now - Duration::days(50));
+        assert_eq!(
+            count.histogram(now),
+            histogram(&[(30, 0), (90, 0), (365, 1)], 1)
+        );
+    }
+
+    #[test]
+    fn reopen() {
+        let path = gen_temp_path().join("freshness.json");
+        let now = Utc::now();
+
+        let mut count = FreshnessCount::open(&path).unwrap();
+        count.set_buckets(vec![2]);
+        count.increment(now, now);
+        count.increment(now, now - Duration::days(5));
+        count.commit().unwrap();
+
+        let count = FreshnessCount::open(&path).unwrap();
+        assert_eq!(count.histogram(now), histogram(&[(2, 1)], 2));
+    }
+
+    #[test]
+    fn merge_histograms() {
+        let a = histogram(&[(30, 1), (90, 2)], 5);
+        let b = histogram(&[(30, 3), (365, 4)], 6);
+
+        assert_eq!(a.merge(b), histogram(&[(30, 4)], 11));
+    }
+}
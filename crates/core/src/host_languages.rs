@@ -0,0 +1,282 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Per host statistics of the languages of the pages in the index.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use tantivy::columnar::ColumnValues;
+use whatlang::Lang;
+
+use crate::{
+    index::Index,
+    schema::fast_field::{FastField, HostNodeID, Language},
+    webgraph::NodeID,
+    Result,
+};
+
+/// The languages of the pages on a host.
+#[derive(
+    Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+)]
+pub enum HostLanguages {
+    /// The host has too few pages in the index for the histogram to be meaningful.
+    Unknown,
+    /// The number of pages for each ISO 639-3 language code sorted by descending count.
+    /// Pages where no language was detected are not counted.
+    Histogram(Vec<(String, u64)>),
+}
+
+impl HostLanguages {
+    fn from_counts(counts: BTreeMap<u64, u64>, num_pages: u64, min_pages: u64) -> Self {
+        if num_pages < min_pages {
+            return Self::Unknown;
+        }
+
+        let mut histogram: Vec<_> = counts
+            .into_iter()
+            .filter_map(|(lang, count)| {
+                Language::decode(lang).map(|lang| (lang.code().to_string(), count))
+            })
+            .collect();
+
+        histogram.sort_by_key(|(code, count)| (Reverse(*count), code.clone()));
+
+        Self::Histogram(histogram)
+    }
+
+    /// The most common language on the host.
+    pub fn dominant(&self) -> Option<Lang> {
+        match self {
+            Self::Unknown => None,
+            Self::Histogram(histogram) => histogram
+                .first()
+                .and_then(|(code, _)| Lang::from_code(code)),
+        }
+    }
+}
+
+/// Count the languages of the pages for every host in the index.
+/// Hosts with fewer than `min_pages` pages are marked as [`HostLanguages::Unknown`].
+pub fn compute(index: &Index, min_pages: u64) -> Result<HashMap<NodeID, HostLanguages>> {
+    let searcher = index.inverted_index.tv_searcher();
+    let mut counts: HashMap<NodeID, (u64, BTreeMap<u64, u64>)> = HashMap::new();
+
+    for segment in searcher.segment_readers() {
+        let hosts = segment.fast_fields().u64(HostNodeID.name())?;
+        let languages = segment.fast_fields().u64(Language.name())?;
+
+        for doc in segment.doc_ids_alive() {
+            let host = hosts.values.get_val(doc);
+
+            // pages without a node id in the webgraph
+            if host == u64::MAX {
+                continue;
+            }
+
+            let (num_pages, langs) = counts.entry(NodeID::from(host)).or_default();
+            *num_pages += 1;
+
+            let lang = languages.values.get_val(doc);
+            if lang != 0 {
+                *langs.entry(lang).or_default() += 1;
+            }
+        }
+    }
+
+    Ok(counts
+        .into_iter()
+        .map(|(host, (num_pages, langs))| {
+            (
+                host,
+                HostLanguages::from_counts(langs, num_pages, min_pages),
+            )
+        })
+        .collect())
+}
+
+pub struct HostLanguageStore {
+    db: speedy_kv::Db<NodeID, HostLanguages>,
+}
+
+impl HostLanguageStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self {
+            db: speedy_kv::Db::open_or_create(path)?,
+        })
+    }
+
+    pub fn build<P: AsRef<Path>>(
+        path: P,
+        languages: impl IntoIterator<Item = (NodeID, HostLanguages)>,
+    ) -> Result<Self> {
+        let mut db = speedy_kv::Db::open_or_create(path)?;
+
+        for (host, langs) in languages {
+            db.insert(host, langs)?;
+
+            if db.uncommitted_inserts() >= 10_000_000 {
+                db.commit()?;
+            }
+        }
+
+        db.commit()?;
+        db.merge_all_segments()?;
+
+        Ok(Self { db })
+    }
+
+    pub fn get(&self, host: &NodeID) -> Option<HostLanguages> {
+        self.db.get(host).ok().flatten()
+    }
+
+    pub fn dominant_language(&self, host: &NodeID) -> Option<Lang> {
+        self.get(host).and_then(|langs| langs.dominant())
+    }
+
+    pub fn len(&self) -> usize {
+        self.db.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.db.is_empty()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{webgraph::Node, webpage::Webpage};
+
+    use super::*;
+
+    const ENGLISH: &str = "The quick brown fox jumps over the lazy dog while the children watch from the garden and laugh about the funny animals they saw in the park yesterday afternoon";
+    const GERMAN: &str = "Der schnelle braune Fuchs springt über den faulen Hund während die Kinder aus dem Garten zuschauen und über die lustigen Tiere lachen die sie gestern im Park gesehen haben";
+    const FRENCH: &str = "Le renard brun rapide saute par dessus le chien paresseux pendant que les enfants regardent depuis le jardin et rient des animaux amusants qu'ils ont vus hier au parc";
+
+    pub fn page(url: &str, text: &str) -> Webpage {
+        let mut webpage = Webpage::test_parse(
+            &format!(
+                r#"
+                <html>
+                    <head>
+                        <title>{text}</title>
+                    </head>
+                    <body>
+                        {text} {text}
+                    </body>
+                </html>
+            "#
+            ),
+            url,
+        )
+        .unwrap();
+
+        webpage.node_id = Some(Node::from(url).into_host().id());
+
+        webpage
+    }
+
+    /// Ten english and five german pages on `a.com` and a single french page on `b.com`.
+    pub fn index() -> Index {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for i in 0..10 {
+            index
+                .insert(&page(&format!("https://www.a.com/en/{i}"), ENGLISH))
+                .unwrap();
+        }
+
+        for i in 0..5 {
+            index
+                .insert(&page(&format!("https://www.a.com/de/{i}"), GERMAN))
+                .unwrap();
+        }
+
+        index.insert(&page("https://www.b.com/", FRENCH)).unwrap();
+
+        index.commit().unwrap();
+
+        index
+    }
+
+    fn host(url: &str) -> NodeID {
+        Node::from(url).into_host().id()
+    }
+
+    #[test]
+    fn language_encoding() {
+        for lang in [Lang::Eng, Lang::Deu, Lang::Dan, Lang::Jpn] {
+            let encoded = Language::encode(&lang);
+
+            assert_ne!(encoded, 0);
+            assert_eq!(Language::decode(encoded), Some(lang));
+        }
+
+        assert_eq!(Language::decode(0), None);
+    }
+
+    #[test]
+    fn histograms() {
+        let languages = compute(&index(), 3).unwrap();
+
+        assert_eq!(languages.len(), 2);
+        assert_eq!(
+            languages.get(&host("https://www.a.com/")),
+            Some(&HostLanguages::Histogram(vec![
+                ("eng".to_string(), 10),
+                ("deu".to_string(), 5)
+            ]))
+        );
+        assert_eq!(
+            languages.get(&host("https://www.b.com/")),
+            Some(&HostLanguages::Unknown)
+        );
+
+        let languages = compute(&index(), 1).unwrap();
+        assert_eq!(
+            languages
+                .get(&host("https://www.b.com/"))
+                .and_then(|l| l.dominant()),
+            Some(Lang::Fra)
+        );
+    }
+
+    #[test]
+    fn store() {
+        let path = crate::gen_temp_path();
+        let store = HostLanguageStore::build(&path, compute(&index(), 3).unwrap()).unwrap();
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(
+            store.dominant_language(&host("https://www.a.com/")),
+            Some(Lang::Eng)
+        );
+        assert_eq!(store.dominant_language(&host("https://www.b.com/")), None);
+        assert_eq!(store.get(&host("https://www.c.com/")), None);
+
+        drop(store);
+
+        let store = HostLanguageStore::open(&path).unwrap();
+        assert_eq!(
+            store.dominant_language(&host("https://www.a.com/")),
+            Some(Lang::Eng)
+        );
+    }
+}
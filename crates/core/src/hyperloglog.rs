@@ -4526,6 +4526,32 @@ impl<H: HyperLogLogHasher, const N: usize> HyperLogLog<N, H> {
         }
     }
 
+    /// Estimate the number of distinct items in either of the sketches.
+    #[must_use]
+    pub fn estimate_union(&self, other: &Self) -> usize {
+        let mut union = self.clone();
+        union.merge(other);
+
+        union.size()
+    }
+
+    /// Estimate the number of distinct items in both sketches using the
+    /// inclusion-exclusion principle `|A ∩ B| = |A| + |B| - |A ∪ B|`.
+    ///
+    /// The error of each of the three estimates is relative to the size of the set it estimates,
+    /// so the absolute error of the intersection grows with the size of the union and not the
+    /// intersection. When the sets are very different in size, or the intersection is small
+    /// compared to the union, the error can easily be larger than the intersection itself.
+    /// The estimate is clamped to `[0, min(|A|, |B|)]` so it is always a valid cardinality.
+    #[must_use]
+    pub fn estimate_intersection(&self, other: &Self) -> usize {
+        let a = self.size();
+        let b = other.size();
+        let union = self.estimate_union(other);
+
+        (a + b).saturating_sub(union).min(a.min(b))
+    }
+
     #[must_use]
     pub fn registers(&self) -> &[u8] {
         self.registers.as_ref()
@@ -4595,4 +4621,41 @@ mod tests {
             assert!((set.size() as f64 - f64::from(counter)).abs() <= 10.0);
         }
     }
+
+    #[test]
+    fn intersection_estimate() {
+        let sketch = |items: std::ops::Range<u64>| {
+            let mut set: HyperLogLog<4096> = HyperLogLog::default();
+
+            for item in items {
+                set.add(item);
+            }
+
+            set
+        };
+
+        let a = sketch(0..10_000);
+        let b = sketch(5_000..15_000);
+        let c = sketch(20_000..30_000);
+        let d = sketch(0..1_000);
+
+        let tolerance = |union: usize| 3.0 * a.relative_error() * union as f64;
+
+        // half overlapping
+        let union = a.estimate_union(&b);
+        assert!((union as f64 - 15_000.0).abs() <= tolerance(15_000));
+        assert!((a.estimate_intersection(&b) as f64 - 5_000.0).abs() <= tolerance(15_000));
+
+        // disjoint
+        assert!(a.estimate_intersection(&c) as f64 <= tolerance(20_000));
+
+        // subset
+        let intersection = a.estimate_intersection(&d);
+        assert!(intersection <= d.size());
+        assert!((intersection as f64 - 1_000.0).abs() <= tolerance(10_000));
+
+        // identical sketches has the same union as each of the sketches
+        assert_eq!(a.estimate_intersection(&a), a.size());
+        assert_eq!(a.estimate_intersection(&b), b.estimate_intersection(&a));
+    }
 }
@@ -17,13 +17,16 @@
 use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
+use tantivy::index::SegmentId;
 use tantivy::tokenizer::TokenizerManager;
 
 use crate::collector::MainCollector;
+use crate::freshness::{FreshnessCount, FreshnessHistogram};
 use crate::inverted_index::{self, InvertedIndex};
+use crate::object_store::ObjectStore;
 use crate::query::Query;
 use crate::search_ctx::Ctx;
 use crate::webgraph::NodeID;
@@ -31,37 +34,131 @@ use crate::webpage::region::{Region, RegionCount};
 use crate::webpage::Webpage;
 use crate::Result;
 
-const INVERTED_INDEX_SUBFOLDER_NAME: &str = "inverted_index";
-const REGION_COUNT_FILE_NAME: &str = "region_count.json";
+pub(crate) const INVERTED_INDEX_SUBFOLDER_NAME: &str = "inverted_index";
+pub(crate) const REGION_COUNT_FILE_NAME: &str = "region_count.json";
+pub(crate) const FRESHNESS_FILE_NAME: &str = "freshness.json";
 
 pub struct Index {
     pub inverted_index: InvertedIndex,
     pub region_count: Mutex<RegionCount>,
+    freshness: Mutex<FreshnessCount>,
     path: String,
+    object_store: Option<Arc<dyn ObjectStore>>,
 }
 
 impl Index {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_local(path, false)
+    }
+
+    /// Open the index without the segments that cannot be opened, so a partly corrupt
+    /// index can still serve the rest of its documents.
+    /// See [`InvertedIndex::open_skip_corrupt_segments`].
+    pub fn open_skip_corrupt_segments<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_local(path, true)
+    }
+
+    fn open_local<P: AsRef<Path>>(path: P, skip_corrupt_segments: bool) -> Result<Self> {
         if !path.as_ref().exists() {
             fs::create_dir_all(path.as_ref())?;
         }
 
-        let inverted_index =
-            InvertedIndex::open(path.as_ref().join(INVERTED_INDEX_SUBFOLDER_NAME))?;
+        let inverted_index_path = path.as_ref().join(INVERTED_INDEX_SUBFOLDER_NAME);
+        let inverted_index = if skip_corrupt_segments {
+            InvertedIndex::open_skip_corrupt_segments(inverted_index_path)?
+        } else {
+            InvertedIndex::open(inverted_index_path)?
+        };
 
         let region_count = RegionCount::open(path.as_ref().join(REGION_COUNT_FILE_NAME));
+        let freshness = FreshnessCount::open(path.as_ref().join(FRESHNESS_FILE_NAME))?;
 
         Ok(Self {
             inverted_index,
             region_count: Mutex::new(region_count),
+            freshness: Mutex::new(freshness),
             path: path.as_ref().to_str().unwrap().to_string(),
+            object_store: None,
         })
     }
 
+    /// Open the index stored in the object store or create it there if it doesn't exist.
+    /// The files of the index are cached in `cache_path` as they are read, and commits
+    /// are written to both. The layout in the store is the same as that of a local index,
+    /// so a local index can be uploaded with [`crate::object_store::upload_dir`].
+    pub fn open_object_store<P: AsRef<Path>>(
+        store: Arc<dyn ObjectStore>,
+        cache_path: P,
+    ) -> Result<Self> {
+        fs::create_dir_all(cache_path.as_ref())?;
+
+        let inverted_index = InvertedIndex::open_object_store(
+            Arc::clone(&store),
+            INVERTED_INDEX_SUBFOLDER_NAME,
+            cache_path.as_ref().join(INVERTED_INDEX_SUBFOLDER_NAME),
+        )?;
+
+        let region_count_path = cache_path.as_ref().join(REGION_COUNT_FILE_NAME);
+        if let Some(region_count) = store.get(REGION_COUNT_FILE_NAME)? {
+            fs::write(&region_count_path, region_count)?;
+        }
+        let region_count = RegionCount::open(region_count_path);
+
+        let freshness_path = cache_path.as_ref().join(FRESHNESS_FILE_NAME);
+        if let Some(freshness) = store.get(FRESHNESS_FILE_NAME)? {
+            fs::write(&freshness_path, freshness)?;
+        }
+        let freshness = FreshnessCount::open(freshness_path)?;
+
+        Ok(Self {
+            inverted_index,
+            region_count: Mutex::new(region_count),
+            freshness: Mutex::new(freshness),
+            path: cache_path.as_ref().to_str().unwrap().to_string(),
+            object_store: Some(store),
+        })
+    }
+
+    /// Rewrite the index at `from` with the current schema into a new index at `to`.
+    /// See [`InvertedIndex::upgrade_schema`] for the caveats.
+    pub fn upgrade_schema<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<Self> {
+        if to.as_ref().exists() {
+            return Err(anyhow::anyhow!("{} already exists", to.as_ref().display()));
+        }
+
+        fs::create_dir_all(to.as_ref())?;
+
+        InvertedIndex::upgrade_schema(
+            from.as_ref().join(INVERTED_INDEX_SUBFOLDER_NAME),
+            to.as_ref().join(INVERTED_INDEX_SUBFOLDER_NAME),
+        )?;
+
+        for file_name in [REGION_COUNT_FILE_NAME, FRESHNESS_FILE_NAME] {
+            let file = from.as_ref().join(file_name);
+            if file.exists() {
+                fs::copy(file, to.as_ref().join(file_name))?;
+            }
+        }
+
+        Self::open(to)
+    }
+
     pub fn path(&self) -> PathBuf {
         PathBuf::from(&self.path)
     }
 
+    pub fn schema_version(&self) -> u32 {
+        self.inverted_index.schema_version()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.inverted_index.is_read_only()
+    }
+
+    pub fn skipped_segments(&self) -> Vec<SegmentId> {
+        self.inverted_index.skipped_segments()
+    }
+
     pub fn set_auto_merge_policy(&mut self) {
         self.inverted_index.set_auto_merge_policy();
     }
@@ -70,6 +167,23 @@ impl Index {
         self.inverted_index.tokenizers()
     }
 
+    /// Set the maximum ages in days of the buckets of the freshness histogram.
+    /// The buckets are stored with the index on the next commit.
+    pub fn set_freshness_buckets(&self, buckets_days: Vec<u64>) {
+        self.freshness
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .set_buckets(buckets_days);
+    }
+
+    /// How recently the pages of the index were crawled.
+    pub fn freshness(&self) -> FreshnessHistogram {
+        self.freshness
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .histogram(chrono::Utc::now())
+    }
+
     #[cfg(test)]
     pub fn temporary() -> Result<Self> {
         let path = crate::gen_temp_path();
@@ -86,10 +200,26 @@ impl Index {
             reg.increment(&region);
         }
 
-        self.inverted_index.insert(webpage)
+        self.inverted_index.insert(webpage)?;
+
+        // pages from crawls that didn't record the fetch time count as fetched when inserted
+        self.freshness
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .increment(
+                webpage.inserted_at,
+                webpage.fetched_at.unwrap_or(webpage.inserted_at),
+            );
+
+        Ok(())
     }
 
     pub fn delete_all_before(&self, timestamp: SystemTime) -> Result<()> {
+        self.freshness
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .delete_before(timestamp.into());
+
         self.inverted_index
             .delete_all_before(tantivy::DateTime::from_utc(timestamp.into()))
     }
@@ -100,6 +230,17 @@ impl Index {
         let mut reg = self.region_count.lock().unwrap_or_else(|e| e.into_inner());
         reg.commit();
 
+        self.freshness
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .commit()?;
+
+        if let Some(store) = &self.object_store {
+            for file_name in [REGION_COUNT_FILE_NAME, FRESHNESS_FILE_NAME] {
+                store.put(file_name, &fs::read(self.path().join(file_name))?)?;
+            }
+        }
+
         Ok(())
     }
 
@@ -131,6 +272,13 @@ impl Index {
         self.inverted_index.retrieve_websites(websites, query)
     }
 
+    /// Merge the segments of the index down to `num_segments` segments for faster queries.
+    /// See [`InvertedIndex::resegment`].
+    pub fn resegment(&mut self, num_segments: u64) -> Result<()> {
+        self.inverted_index.resegment(num_segments)?;
+        self.commit()
+    }
+
     pub fn merge(self, other: Self) -> Self {
         let _ = self.inverted_index.merge(other.inverted_index);
 
@@ -145,6 +293,17 @@ impl Index {
 
         self_region_count.merge(other_region_count);
 
+        self.freshness
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .merge(
+                other
+                    .freshness
+                    .into_inner()
+                    .unwrap_or_else(|e| e.into_inner()),
+            )
+            .expect("failed to merge freshness counts");
+
         let mut res = Self::open(&self.path).expect("failed to open index");
         res.prepare_writer().expect("failed to prepare writer");
         res
@@ -158,6 +317,7 @@ impl Index {
 #[cfg(test)]
 mod tests {
     use crate::{
+        object_store::{tests::MemoryObjectStore, upload_dir},
         ranking,
         searcher::{LocalSearcher, SearchQuery},
     };
@@ -256,4 +416,304 @@ mod tests {
                 .unwrap())
             .all(|&v| v.value > 0.0));
     }
+
+    #[test]
+    fn object_store_roundtrip() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for (title, url) in [
+            ("Test website", "https://www.first.com"),
+            ("Another website", "https://www.second.com"),
+        ] {
+            index
+                .insert(
+                    &Webpage::test_parse(
+                        &format!(
+                            r#"
+            <html>
+                <head>
+                    <title>{title}</title>
+                </head>
+                <body>
+                    {CONTENT} {}
+                </body>
+            </html>
+            "#,
+                            crate::rand_words(100)
+                        ),
+                        url,
+                    )
+                    .unwrap(),
+                )
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let store = Arc::new(MemoryObjectStore::default());
+        upload_dir(store.as_ref(), "", index.path()).unwrap();
+
+        let cache_path = crate::gen_temp_path();
+        let index = Index::open_object_store(store.clone(), &cache_path).unwrap();
+
+        // the segments are fetched into the cache when they are opened
+        assert!(fs::read_dir(cache_path.join(INVERTED_INDEX_SUBFOLDER_NAME))
+            .unwrap()
+            .any(|entry| entry.unwrap().path().extension() == Some("idx".as_ref())));
+        assert!(store.gets().contains(&REGION_COUNT_FILE_NAME.to_string()));
+        assert!(store.gets().contains(&FRESHNESS_FILE_NAME.to_string()));
+
+        let searcher = LocalSearcher::from(index);
+        let res = searcher
+            .search(&SearchQuery {
+                query: "test".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(res.webpages.len(), 1);
+        assert_eq!(res.webpages[0].url, "https://www.first.com/");
+    }
+
+    #[test]
+    fn skip_corrupt_segments() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for url in ["https://www.first.com", "https://www.second.com"] {
+            index
+                .insert(
+                    &Webpage::test_parse(
+                        &format!(
+                            r#"
+            <html>
+                <head>
+                    <title>Test website</title>
+                </head>
+                <body>
+                    {CONTENT} {}
+                </body>
+            </html>
+            "#,
+                            crate::rand_words(100)
+                        ),
+                        url,
+                    )
+                    .unwrap(),
+                )
+                .expect("failed to insert webpage");
+
+            index.commit().unwrap();
+        }
+
+        assert_eq!(index.inverted_index.num_segments(), 2);
+
+        let corrupt = index.inverted_index.load_metas().unwrap().segments[0].id();
+        let path = index.path();
+        drop(index);
+
+        fs::write(
+            path.join(INVERTED_INDEX_SUBFOLDER_NAME)
+                .join(format!("{}.term", corrupt.uuid_string())),
+            b"corrupt\0\0\0\0",
+        )
+        .unwrap();
+
+        assert!(Index::open(&path).is_err());
+
+        let index = Index::open_skip_corrupt_segments(&path).unwrap();
+        assert_eq!(index.skipped_segments(), vec![corrupt]);
+        assert!(index.is_read_only());
+
+        let searcher = LocalSearcher::from(index);
+        let res = searcher
+            .search(&SearchQuery {
+                query: "test".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(res.webpages.len(), 1);
+    }
+
+    #[test]
+    fn resegment_into_single_segment() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for segment in 0..3 {
+            for page in 0..4 {
+                index
+                    .insert(
+                        &Webpage::test_parse(
+                            &format!(
+                                r#"
+            <html>
+                <head>
+                    <title>Test website {segment} {page}</title>
+                </head>
+                <body>
+                    {CONTENT} {}
+                </body>
+            </html>
+            "#,
+                                crate::rand_words(100)
+                            ),
+                            &format!("https://www.site{segment}.com/{page}"),
+                        )
+                        .unwrap(),
+                    )
+                    .expect("failed to insert webpage");
+            }
+
+            index.commit().unwrap();
+        }
+
+        assert_eq!(index.inverted_index.num_segments(), 3);
+        let path = index.path();
+
+        // the pages score the same, so their order depends on where they are in the index
+        let search = |index: Index| -> Vec<String> {
+            let mut urls: Vec<_> = LocalSearcher::from(index)
+                .search(&SearchQuery {
+                    query: "test website".to_string(),
+                    num_results: 20,
+                    ..Default::default()
+                })
+                .unwrap()
+                .webpages
+                .into_iter()
+                .map(|webpage| webpage.url)
+                .collect();
+            urls.sort();
+            urls
+        };
+
+        let before = search(index);
+        assert_eq!(before.len(), 12);
+
+        let mut index = Index::open(&path).unwrap();
+        // more segments than the index has is a no-op
+        index.resegment(5).unwrap();
+        assert_eq!(index.inverted_index.num_segments(), 3);
+
+        index.resegment(1).unwrap();
+        assert_eq!(index.inverted_index.num_segments(), 1);
+        drop(index);
+
+        let index = Index::open(&path).unwrap();
+        assert_eq!(index.inverted_index.num_segments(), 1);
+        assert_eq!(search(index), before);
+    }
+
+    #[test]
+    fn freshness_histogram() {
+        let crawled = |index: &Index, url: &str, age_days: i64| {
+            index
+                .insert(&Webpage {
+                    fetched_at: Some(chrono::Utc::now() - chrono::Duration::days(age_days)),
+                    ..Webpage::test_parse(
+                        &format!(
+                            r#"
+            <html>
+                <head>
+                    <title>Test website</title>
+                </head>
+                <body>
+                    {CONTENT} {}
+                </body>
+            </html>
+            "#,
+                            crate::rand_words(100)
+                        ),
+                        url,
+                    )
+                    .unwrap()
+                })
+                .expect("failed to insert webpage");
+        };
+
+        let buckets = |histogram: FreshnessHistogram| -> Vec<(u64, u64)> {
+            histogram
+                .buckets
+                .into_iter()
+                .map(|bucket| (bucket.max_age_days, bucket.count))
+                .collect()
+        };
+
+        let mut a = Index::temporary().expect("Unable to open index");
+        crawled(&a, "https://www.a.com/1", 1);
+        crawled(&a, "https://www.a.com/2", 45);
+        crawled(&a, "https://www.a.com/3", 200);
+        crawled(&a, "https://www.a.com/4", 500);
+        a.commit().unwrap();
+
+        assert_eq!(a.freshness().total, 4);
+        assert_eq!(buckets(a.freshness()), vec![(30, 1), (90, 2), (365, 3)]);
+
+        let path = a.path();
+        drop(a);
+        let a = Index::open(&path).unwrap();
+        assert_eq!(buckets(a.freshness()), vec![(30, 1), (90, 2), (365, 3)]);
+
+        let mut b = Index::temporary().expect("Unable to open index");
+        crawled(&b, "https://www.b.com/1", 0);
+        crawled(&b, "https://www.b.com/2", 10);
+        crawled(&b, "https://www.b.com/3", 80);
+        b.commit().unwrap();
+
+        let expected = a.freshness().merge(b.freshness());
+        let merged = a.merge(b);
+
+        assert_eq!(merged.freshness(), expected);
+        assert_eq!(merged.freshness().total, 7);
+        assert_eq!(
+            buckets(merged.freshness()),
+            vec![(30, 3), (90, 5), (365, 6)]
+        );
+    }
+
+    #[test]
+    fn configured_freshness_buckets() {
+        let mut index = Index::temporary().expect("Unable to open index");
+        index.set_freshness_buckets(vec![7, 1]);
+
+        index
+            .insert(&Webpage {
+                fetched_at: Some(chrono::Utc::now() - chrono::Duration::days(3)),
+                ..Webpage::test_parse(
+                    &format!(
+                        r#"
+            <html>
+                <head>
+                    <title>Test website</title>
+                </head>
+                <body>
+                    {CONTENT} {}
+                </body>
+            </html>
+            "#,
+                        crate::rand_words(100)
+                    ),
+                    "https://www.first.com",
+                )
+                .unwrap()
+            })
+            .expect("failed to insert webpage");
+        index.commit().unwrap();
+
+        let path = index.path();
+        drop(index);
+
+        let index = Index::open(path).unwrap();
+        let histogram = index.freshness();
+
+        assert_eq!(histogram.total, 1);
+        assert_eq!(
+            histogram
+                .buckets
+                .iter()
+                .map(|bucket| (bucket.max_age_days, bucket.count))
+                .collect::<Vec<_>>(),
+            vec![(1, 0), (7, 1)]
+        );
+    }
 }
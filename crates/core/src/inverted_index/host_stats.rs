@@ -0,0 +1,266 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Statistics of all the pages of a single host.
+//!
+//! The pages of a host are found from the postings of the host in the site field, which are
+//! streamed once. The earliest insertion and the centrality rank are read from the fast fields
+//! of every page, and the keywords from the stored documents of a sample of the pages.
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use tantivy::schema::IndexRecordOption;
+use tantivy::{DocSet, TantivyDocument, TERMINATED};
+
+use crate::schema::{fast_field, text_field, Field, TextFieldEnum};
+use crate::Result;
+
+use super::{InvertedIndex, RetrievedWebpage};
+
+/// Number of pages of the host whose keywords are counted.
+const KEYWORD_SAMPLE_SIZE: usize = 64;
+
+/// Number of keywords kept for each host.
+const NUM_KEYWORDS: usize = 16;
+
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct HostStats {
+    pub num_pages: u64,
+    /// Seconds since the unix epoch of the earliest insertion of a page from the host.
+    /// Indexes built before the insertions were kept in a fast field don't have it.
+    pub first_indexed: Option<i64>,
+    pub centrality_rank: Option<u64>,
+    /// The most common keywords of the sampled pages with their number of occurrences.
+    pub keywords: Vec<(String, u64)>,
+}
+
+impl HostStats {
+    /// Combine the statistics of the same host from different shards.
+    pub fn merge(self, other: Self) -> Self {
+        let mut keywords: HashMap<String, u64> = HashMap::new();
+
+        for (keyword, count) in self.keywords.into_iter().chain(other.keywords) {
+            *keywords.entry(keyword).or_default() += count;
+        }
+
+        Self {
+            num_pages: self.num_pages + other.num_pages,
+            first_indexed: self
+                .first_indexed
+                .into_iter()
+                .chain(other.first_indexed)
+                .min(),
+            centrality_rank: self
+                .centrality_rank
+                .into_iter()
+                .chain(other.centrality_rank)
+                .min(),
+            keywords: top_keywords(keywords),
+        }
+    }
+}
+
+fn top_keywords(counts: HashMap<String, u64>) -> Vec<(String, u64)> {
+    counts
+        .into_iter()
+        .sorted_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)))
+        .take(NUM_KEYWORDS)
+        .collect()
+}
+
+impl InvertedIndex {
+    /// Returns `None` if the index has no pages from the host.
+    pub fn host_stats(&self, host: &str) -> Result<Option<HostStats>> {
+        let tv_searcher = self.reader.searcher();
+        let schema = tv_searcher.schema();
+
+        let site_field = schema
+            .get_field(Field::Text(TextFieldEnum::from(text_field::SiteNoTokenizer)).name())?;
+
+        let site = tantivy::Term::from_field_text(site_field, host.trim_start_matches("www."));
+
+        let mut stats = HostStats::default();
+        let mut keywords: HashMap<String, u64> = HashMap::new();
+        let mut num_sampled = 0;
+
+        for (segment_ord, segment) in tv_searcher.segment_readers().iter().enumerate() {
+            let Some(mut postings) = segment
+                .inverted_index(site_field)?
+                .read_postings(&site, IndexRecordOption::Basic)?
+            else {
+                continue;
+            };
+
+            let fastfield_reader = self.fastfield_reader.borrow_segment(&segment.segment_id());
+
+            while postings.doc() != TERMINATED {
+                let doc = postings.doc();
+                postings.advance();
+
+                if !segment
+                    .alive_bitset()
+                    .map_or(true, |alive| alive.is_alive(doc))
+                {
+                    continue;
+                }
+
+                stats.num_pages += 1;
+
+                let fields = fastfield_reader.get_field_reader(doc);
+                let rank: Option<u64> = fields
+                    .get(fast_field::HostCentralityRank.into())
+                    .and_then(|value| value.as_u64());
+                stats.centrality_rank = stats.centrality_rank.into_iter().chain(rank).min();
+
+                // 0 for the segments that were indexed before the field was added
+                let inserted_at = fields
+                    .get(fast_field::InsertedAt.into())
+                    .and_then(|value| value.as_u64())
+                    .filter(|inserted_at| *inserted_at > 0)
+                    .map(|inserted_at| inserted_at as i64);
+                stats.first_indexed = stats.first_indexed.into_iter().chain(inserted_at).min();
+
+                if num_sampled < KEYWORD_SAMPLE_SIZE {
+                    let doc: TantivyDocument =
+                        tv_searcher.doc(tantivy::DocAddress::new(segment_ord as u32, doc))?;
+
+                    for keyword in RetrievedWebpage::from_doc(doc, &self.fields).keywords {
+                        if !keyword.is_empty() {
+                            *keywords.entry(keyword).or_default() += 1;
+                        }
+                    }
+
+                    num_sampled += 1;
+                }
+            }
+        }
+
+        if stats.num_pages == 0 {
+            return Ok(None);
+        }
+
+        stats.keywords = top_keywords(keywords);
+
+        Ok(Some(stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{index::Index, webpage::Webpage};
+
+    use super::*;
+
+    fn webpage(url: &str, inserted_at: i64, keywords: &[&str]) -> Webpage {
+        let mut webpage = Webpage::test_parse(
+            &format!(
+                r#"
+                <html>
+                    <head>
+                        <title>Test website</title>
+                    </head>
+                    <body>
+                        {}
+                    </body>
+                </html>
+                "#,
+                crate::rand_words(100)
+            ),
+            url,
+        )
+        .unwrap();
+
+        webpage.inserted_at = chrono::DateTime::from_timestamp(inserted_at, 0).unwrap();
+        webpage.host_centrality_rank = 42;
+        webpage.keywords = keywords.iter().map(|k| k.to_string()).collect();
+
+        webpage
+    }
+
+    #[test]
+    fn host_stats() {
+        let mut index = Index::temporary().unwrap();
+
+        index
+            .insert(&webpage("https://www.a.com/1", 2_000, &["rust", "search"]))
+            .unwrap();
+        index.commit().unwrap();
+        index
+            .insert(&webpage("https://www.a.com/2", 1_000, &["rust"]))
+            .unwrap();
+        index
+            .insert(&webpage("https://b.com/", 500, &["cooking"]))
+            .unwrap();
+        index.commit().unwrap();
+
+        let stats = index.inverted_index.host_stats("a.com").unwrap().unwrap();
+
+        assert_eq!(stats.num_pages, 2);
+        assert_eq!(stats.first_indexed, Some(1_000));
+        assert_eq!(stats.centrality_rank, Some(42));
+        assert_eq!(
+            stats.keywords,
+            vec![("rust".to_string(), 2), ("search".to_string(), 1)]
+        );
+
+        assert_eq!(
+            index
+                .inverted_index
+                .host_stats("www.a.com")
+                .unwrap()
+                .unwrap()
+                .num_pages,
+            2
+        );
+        assert!(index.inverted_index.host_stats("c.com").unwrap().is_none());
+    }
+
+    #[test]
+    fn merge() {
+        let a = HostStats {
+            num_pages: 2,
+            first_indexed: Some(10),
+            centrality_rank: None,
+            keywords: vec![("rust".to_string(), 2)],
+        };
+        let b = HostStats {
+            num_pages: 3,
+            first_indexed: Some(5),
+            centrality_rank: Some(7),
+            keywords: vec![("rust".to_string(), 1), ("search".to_string(), 2)],
+        };
+
+        assert_eq!(
+            a.merge(b),
+            HostStats {
+                num_pages: 5,
+                first_indexed: Some(5),
+                centrality_rank: Some(7),
+                keywords: vec![("rust".to_string(), 3), ("search".to_string(), 2)],
+            }
+        );
+    }
+}
@@ -50,6 +50,19 @@ pub fn merge_tantivy_segments<P: AsRef<Path>>(
 
     let num_segments = (max_num_segments + 1) / 2; // ceil(num_segments/2)
 
+    merge_into_segments(writer, segments, base_path, num_segments, true)
+}
+
+/// Merge the segments into `num_segments` segments with about the same number of documents.
+/// A segment that is alone in its merge is still rewritten if `rewrite_single`, which purges
+/// its deleted documents, and is otherwise left as it is.
+fn merge_into_segments<P: AsRef<Path>>(
+    writer: &mut IndexWriter,
+    mut segments: Vec<SegmentMeta>,
+    base_path: P,
+    num_segments: u64,
+    rewrite_single: bool,
+) -> Result<()> {
     let mut merge_segments = Vec::new();
 
     for _ in 0..num_segments {
@@ -71,13 +84,28 @@ pub fn merge_tantivy_segments<P: AsRef<Path>>(
         best_candidate.segments.push(segment);
     }
 
-    for merge in merge_segments
+    let merges: Vec<_> = merge_segments
         .into_iter()
-        .filter(|merge| !merge.segments.is_empty())
-    {
+        .filter(|merge| match merge.segments.len() {
+            0 => false,
+            1 => rewrite_single,
+            _ => true,
+        })
+        .collect();
+    let num_merges = merges.len();
+
+    for (i, merge) in merges.into_iter().enumerate() {
         let segment_ids: Vec<_> = merge.segments.iter().map(|segment| segment.id()).collect();
         writer.merge(&segment_ids[..]).wait()?;
 
+        tracing::info!(
+            "merged {} segments with {} documents ({}/{})",
+            segment_ids.len(),
+            merge.num_docs,
+            i + 1,
+            num_merges
+        );
+
         for segment in merge.segments {
             for file in segment.list_files() {
                 std::fs::remove_file(base_path.as_ref().join(file)).ok();
@@ -94,6 +122,22 @@ impl InvertedIndex {
             return Ok(());
         }
 
+        if self.read_only && !self.skipped_segments().is_empty() {
+            return Err(anyhow::anyhow!(
+                "index at {} was opened without its corrupt segments and is read-only",
+                self.path
+            ));
+        }
+
+        if self.read_only {
+            return Err(anyhow::anyhow!(
+                "index at {} has schema version {} and is read-only. Upgrade it to version {} before writing to it",
+                self.path,
+                self.schema_version,
+                crate::schema::SCHEMA_VERSION
+            ));
+        }
+
         let writer = self
             .tantivy_index
             .writer_with_num_threads(1, 1_000_000_000)?;
@@ -115,10 +159,16 @@ impl InvertedIndex {
     }
 
     pub fn insert(&self, webpage: &Webpage) -> Result<()> {
+        let mut doc = webpage.as_tantivy(&self.schema)?;
+
+        if let Some(pruning) = &self.term_pruning {
+            doc = pruning.prune(doc, &self.schema);
+        }
+
         self.writer
             .as_ref()
             .expect("writer has not been prepared")
-            .add_document(webpage.as_tantivy(&self.schema)?)?;
+            .add_document(doc)?;
         Ok(())
     }
 
@@ -130,6 +180,13 @@ impl InvertedIndex {
             .commit()?;
         self.reader.reload()?;
         self.fastfield_reader = FastFieldReader::new(&self.reader.searcher());
+        // the commit already pays for reloading the searcher, so queries
+        // after a commit do not have to warm the segments.
+        self.fastfield_reader.warm_all();
+
+        if let Some(cache) = &self.webpage_cache {
+            cache.clear();
+        }
 
         Ok(())
     }
@@ -174,6 +231,30 @@ impl InvertedIndex {
         Ok(())
     }
 
+    /// Merge the segments into `num_segments` segments with about the same number of
+    /// documents. The segments are left as they are if there are no more than that.
+    #[allow(clippy::missing_panics_doc)] // cannot panic as writer is prepared
+    pub fn resegment(&mut self, num_segments: u64) -> Result<()> {
+        if num_segments == 0 {
+            return Err(anyhow::anyhow!("an index must have at least one segment"));
+        }
+
+        self.prepare_writer()?;
+        let segments = self.tantivy_index.load_metas()?.segments;
+
+        if segments.len() <= num_segments as usize {
+            return Ok(());
+        }
+
+        merge_into_segments(
+            self.writer.as_mut().expect("writer has not been prepared"),
+            segments,
+            Path::new(&self.path),
+            num_segments,
+            false,
+        )
+    }
+
     #[must_use]
     pub fn merge(mut self, mut other: InvertedIndex) -> Self {
         self.prepare_writer().expect("failed to prepare writer");
@@ -26,37 +26,55 @@
 //! This allows us to perform more advanced queries than just term lookups,
 //! but the principle is the same.
 
+mod host_stats;
 mod indexing;
+mod retrieval;
+mod schema_version;
 mod search;
+mod term_pruning;
+mod webpage_cache;
 
+pub use host_stats::HostStats;
 pub use indexing::merge_tantivy_segments;
+pub(crate) use schema_version::SCHEMA_VERSION_FILE;
+pub use term_pruning::{TermCounts, TermPruning};
+pub use webpage_cache::WebpageCache;
+
+use retrieval::RetrievalPool;
 
 use chrono::{DateTime, NaiveDateTime};
 
 use tantivy::directory::MmapDirectory;
 
 use tantivy::schema::{Schema, Value};
+use tantivy::store::SharedBlockCache;
 use tantivy::tokenizer::TokenizerManager;
 use tantivy::{IndexReader, IndexWriter, TantivyDocument};
 
 use crate::collector::{approx_count, Hashes};
-use crate::config::SnippetConfig;
+use crate::config::{RetrievalConfig, SnippetConfig};
 use crate::fastfield_reader::FastFieldReader;
+use crate::object_store::{ObjectStore, ObjectStoreDirectory};
 
 use crate::ranking::initial::Score;
 
 use crate::schema::text_field::TextField;
-use crate::schema::{fast_field, text_field, FastFieldEnum, Field, TextFieldEnum};
+use crate::schema::{
+    fast_field, text_field, FastFieldEnum, Field, FieldMapping, TextFieldEnum, SCHEMA_VERSION,
+};
 use crate::snippet::TextSnippet;
 use crate::tokenizer::{
     BigramTokenizer, Identity, JsonField, Stemmed, TrigramTokenizer, UrlTokenizer,
 };
+use crate::webpage::content_labels::ContentLabels;
 use crate::webpage::region::Region;
 
 use crate::webpage::schema_org;
+use crate::webpage::title::TitleQuality;
 use crate::Result;
 use crate::{schema::create_schema, tokenizer::Tokenizer};
 use std::fs;
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::sync::Arc;
 
@@ -84,6 +102,8 @@ pub struct WebpagePointer {
     Clone,
     Copy,
     PartialEq,
+    Eq,
+    Hash,
 )]
 pub struct DocAddress {
     pub segment: u32,
@@ -139,34 +159,145 @@ pub struct InvertedIndex {
     schema: Arc<Schema>,
     snippet_config: SnippetConfig,
     fastfield_reader: FastFieldReader,
+    webpage_cache: Option<Arc<WebpageCache>>,
+    store_block_cache: Option<SharedBlockCache>,
+    retrieval: Option<Arc<RetrievalPool>>,
+    fields: Arc<FieldMapping>,
+    schema_version: u32,
+    read_only: bool,
+    skip_corrupt_segments: bool,
+    term_pruning: Option<Arc<TermPruning>>,
+    #[cfg(test)]
+    slow_reads: Arc<std::collections::HashMap<DocAddress, std::time::Duration>>,
+}
+
+fn index_settings() -> tantivy::IndexSettings {
+    tantivy::IndexSettings {
+        sort_by_field: Some(tantivy::IndexSortByField {
+            field: Field::Fast(FastFieldEnum::from(fast_field::PreComputedScore))
+                .name()
+                .to_string(),
+            order: tantivy::Order::Desc,
+        }),
+        ..Default::default()
+    }
+}
+
+fn create_tantivy_index<P: AsRef<Path>>(path: P, schema: Schema) -> Result<tantivy::Index> {
+    fs::create_dir_all(&path)?;
+    let mmap_directory = MmapDirectory::open(&path)?;
+
+    let index = tantivy::Index::create(mmap_directory, schema, index_settings())?;
+
+    Ok(index)
 }
 
 impl InvertedIndex {
+    /// Open the index at `path` or create it if it doesn't exist.
+    ///
+    /// Indexes created with an older schema version are opened read-only.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let schema = create_schema();
+        Self::open_local(path, false)
+    }
 
+    /// Like [`InvertedIndex::open`], but the segments that cannot be opened are logged and
+    /// left out instead of failing to open the index. The skipped segments are listed by
+    /// [`InvertedIndex::skipped_segments`], and the index is opened read-only if there are any
+    /// so the corrupt segments are not merged.
+    pub fn open_skip_corrupt_segments<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_local(path, true)
+    }
+
+    fn open_local<P: AsRef<Path>>(path: P, skip_corrupt_segments: bool) -> Result<Self> {
         let tantivy_index = if path.as_ref().exists() {
             let mmap_directory = MmapDirectory::open(&path)?;
             tantivy::Index::open(mmap_directory)?
         } else {
-            let index_settings = tantivy::IndexSettings {
-                sort_by_field: Some(tantivy::IndexSortByField {
-                    field: Field::Fast(FastFieldEnum::from(fast_field::PreComputedScore))
-                        .name()
-                        .to_string(),
-                    order: tantivy::Order::Desc,
-                }),
-                ..Default::default()
-            };
+            let index = create_tantivy_index(&path, create_schema())?;
+            schema_version::write(&path, SCHEMA_VERSION)?;
+            index
+        };
 
-            fs::create_dir_all(&path)?;
-            let mmap_directory = MmapDirectory::open(&path)?;
-            tantivy::Index::create(mmap_directory, schema.clone(), index_settings)?
+        Self::from_tantivy_index(path, tantivy_index, skip_corrupt_segments)
+    }
+
+    /// Open the index stored in the object store under `prefix` or create it there if it
+    /// doesn't exist. The files of the index are cached in `cache_path` as they are read.
+    pub fn open_object_store<P: AsRef<Path>>(
+        store: Arc<dyn ObjectStore>,
+        prefix: &str,
+        cache_path: P,
+    ) -> Result<Self> {
+        let directory = ObjectStoreDirectory::open(store, prefix, &cache_path)?;
+        let schema_version_file = Path::new(schema_version::SCHEMA_VERSION_FILE);
+        directory.fetch(schema_version_file)?;
+
+        let tantivy_index = if tantivy::Index::exists(&directory)? {
+            tantivy::Index::open(directory.clone())?
+        } else {
+            let index =
+                tantivy::Index::create(directory.clone(), create_schema(), index_settings())?;
+            schema_version::write(&cache_path, SCHEMA_VERSION)?;
+            index
+        };
+
+        let index = Self::from_tantivy_index(&cache_path, tantivy_index, false)?;
+
+        if cache_path.as_ref().join(schema_version_file).exists() {
+            directory.upload(schema_version_file)?;
+        }
+
+        Ok(index)
+    }
+
+    fn from_tantivy_index<P: AsRef<Path>>(
+        path: P,
+        tantivy_index: tantivy::Index,
+        skip_corrupt_segments: bool,
+    ) -> Result<Self> {
+        let schema = tantivy_index.schema();
+        let fields = FieldMapping::new(&schema);
+        let mut version = schema_version::read(&path)?;
+
+        let mut read_only = match schema_version::check(version, &schema, &fields)? {
+            schema_version::Compatibility::Current => {
+                if version != SCHEMA_VERSION {
+                    // the schema is unchanged since the version of the index
+                    schema_version::write(&path, SCHEMA_VERSION)?;
+                    version = SCHEMA_VERSION;
+                }
+
+                false
+            }
+            schema_version::Compatibility::ReadOnly => {
+                tracing::warn!(
+                    "index at {} has schema version {} and is missing {} fields. It is opened read-only",
+                    path.as_ref().display(),
+                    version,
+                    fields.missing().len()
+                );
+
+                true
+            }
         };
 
         register_tokenizers(tantivy_index.tokenizers());
 
-        let reader: IndexReader = tantivy_index.reader_builder().try_into()?;
+        let reader: IndexReader = tantivy_index
+            .reader_builder()
+            .skip_corrupt_segments(skip_corrupt_segments)
+            .try_into()?;
+
+        let skipped_segments = reader.skipped_segments();
+        if !skipped_segments.is_empty() {
+            tracing::error!(
+                "index at {} is missing {} corrupt segments. It is opened read-only",
+                path.as_ref().display(),
+                skipped_segments.len()
+            );
+
+            read_only = true;
+        }
 
         let fastfield_reader = FastFieldReader::new(&reader.searcher());
 
@@ -178,9 +309,73 @@ impl InvertedIndex {
             tantivy_index,
             snippet_config: SnippetConfig::default(),
             fastfield_reader,
+            webpage_cache: None,
+            store_block_cache: None,
+            retrieval: None,
+            fields: Arc::new(fields),
+            schema_version: version,
+            read_only,
+            skip_corrupt_segments,
+            term_pruning: None,
+            #[cfg(test)]
+            slow_reads: Arc::new(std::collections::HashMap::new()),
         })
     }
 
+    /// Rewrite the index at `from` with the current schema into a new index at `to`.
+    ///
+    /// The documents are rebuilt from their stored fields, so fields that are indexed but not
+    /// stored in the old index are empty for the upgraded documents. Rankings that depend on
+    /// those fields are only fully restored by rebuilding the index from the crawl.
+    pub fn upgrade_schema<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<Self> {
+        if !from.as_ref().exists() {
+            return Err(anyhow::anyhow!(
+                "{} does not exist",
+                from.as_ref().display()
+            ));
+        }
+
+        if to.as_ref().exists() {
+            return Err(anyhow::anyhow!("{} already exists", to.as_ref().display()));
+        }
+
+        let old = Self::open(from)?;
+        let mut new = Self::open(to)?;
+        new.prepare_writer()?;
+
+        let searcher = old.reader.searcher();
+
+        for (segment_ord, segment) in searcher.segment_readers().iter().enumerate() {
+            for doc in segment.doc_ids_alive() {
+                let doc: TantivyDocument =
+                    searcher.doc(tantivy::DocAddress::new(segment_ord as u32, doc))?;
+                let doc = crate::schema::remap_document(&doc, &old.schema, &new.schema);
+
+                new.writer
+                    .as_ref()
+                    .expect("writer has not been prepared")
+                    .add_document(doc)?;
+            }
+        }
+
+        new.commit()?;
+
+        Ok(new)
+    }
+
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    /// Indexes with an older schema can be searched but not written to.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn field_mapping(&self) -> &FieldMapping {
+        &self.fields
+    }
+
     pub fn fastfield_reader(&self) -> FastFieldReader {
         self.fastfield_reader.clone()
     }
@@ -189,6 +384,66 @@ impl InvertedIndex {
         self.snippet_config = config;
     }
 
+    /// Leave the pruned terms out of the documents that are inserted from now on.
+    pub fn set_term_pruning(&mut self, pruning: Arc<TermPruning>) {
+        self.term_pruning = Some(pruning);
+    }
+
+    /// Keep up to `capacity` of the most recently retrieved webpages in memory.
+    /// A capacity of 0 disables the cache.
+    pub fn set_webpage_cache_capacity(&mut self, capacity: usize) {
+        self.webpage_cache = NonZeroUsize::new(capacity)
+            .map(WebpageCache::new)
+            .map(Arc::new);
+    }
+
+    pub fn webpage_cache(&self) -> Option<&WebpageCache> {
+        self.webpage_cache.as_deref()
+    }
+
+    /// Read the documents of the results in parallel, and return the documents that take
+    /// longer than the timeout to read with only their url and title.
+    pub fn set_retrieval_config(&mut self, config: &RetrievalConfig) -> Result<()> {
+        self.retrieval = RetrievalPool::new(config)?.map(Arc::new);
+
+        Ok(())
+    }
+
+    /// Make the reads of the document at `address` from the store take `delay` longer.
+    #[cfg(test)]
+    pub(crate) fn slow_down_read(&mut self, address: DocAddress, delay: std::time::Duration) {
+        Arc::make_mut(&mut self.slow_reads).insert(address, delay);
+    }
+
+    /// Cache up to `num_blocks` decompressed doc store blocks in a single cache that is shared
+    /// by all segments and kept when the index is reloaded, instead of a cache for every
+    /// segment that only lives as long as the searcher of the segment. A capacity of 0
+    /// disables the caching of blocks.
+    pub fn set_store_block_cache_capacity(&mut self, num_blocks: usize) -> Result<()> {
+        let builder = self
+            .tantivy_index
+            .reader_builder()
+            .skip_corrupt_segments(self.skip_corrupt_segments);
+
+        let (builder, cache) = match NonZeroUsize::new(num_blocks) {
+            Some(num_blocks) => {
+                let cache = SharedBlockCache::new(num_blocks);
+                (builder.doc_store_shared_cache(cache.clone()), Some(cache))
+            }
+            None => (builder.doc_store_cache_num_blocks(0), None),
+        };
+
+        self.reader = builder.try_into()?;
+        self.fastfield_reader = FastFieldReader::new(&self.reader.searcher());
+        self.store_block_cache = cache;
+
+        Ok(())
+    }
+
+    pub fn store_block_cache(&self) -> Option<&SharedBlockCache> {
+        self.store_block_cache.as_ref()
+    }
+
     pub fn tokenizers(&self) -> &TokenizerManager {
         self.tantivy_index.tokenizers()
     }
@@ -197,10 +452,54 @@ impl InvertedIndex {
         Arc::clone(&self.schema)
     }
 
+    /// The segments left out of the searcher because they could not be opened,
+    /// see [`InvertedIndex::open_skip_corrupt_segments`].
+    pub fn skipped_segments(&self) -> Vec<tantivy::index::SegmentId> {
+        self.reader.skipped_segments()
+    }
+
     pub fn num_segments(&self) -> usize {
         self.tantivy_index.searchable_segments().unwrap().len()
     }
 
+    /// Whether any page has a title or keyword embedding. The results are diversified by
+    /// their embeddings, so the results of an index without them keep their order.
+    pub fn has_embeddings(&self) -> bool {
+        let fields = [
+            Field::Fast(FastFieldEnum::from(fast_field::TitleEmbeddings)),
+            Field::Fast(FastFieldEnum::from(fast_field::KeywordEmbeddings)),
+        ];
+
+        self.reader
+            .searcher()
+            .segment_readers()
+            .iter()
+            .any(|segment| {
+                fields.iter().any(|field| {
+                    // the pages without an embedding have the empty value, which sorts first
+                    segment
+                        .fast_fields()
+                        .bytes(field.name())
+                        .ok()
+                        .flatten()
+                        .is_some_and(|column| {
+                            let mut last = Vec::new();
+
+                            column.num_terms() > 0
+                                && column
+                                    .ord_to_bytes(column.num_terms() as u64 - 1, &mut last)
+                                    .unwrap_or(false)
+                                && !last.is_empty()
+                        })
+                })
+            })
+    }
+
+    /// The metadata of the last commit, which lists the segments a new reader would see.
+    pub fn load_metas(&self) -> Result<tantivy::IndexMeta> {
+        Ok(self.tantivy_index.load_metas()?)
+    }
+
     #[cfg(test)]
     pub fn temporary() -> Result<Self> {
         let path = crate::gen_temp_path();
@@ -242,8 +541,14 @@ pub struct RetrievedWebpage {
     pub region: Region,
     pub likely_has_ads: bool,
     pub likely_has_paywall: bool,
+    pub content_labels: ContentLabels,
     pub recipe_first_ingredient_tag_id: Option<String>,
     pub keywords: Vec<String>,
+    /// The document could not be read in time, so only the url and title are set.
+    pub degraded: bool,
+    /// The title of the page was missing or junk, so the title was synthesized from other
+    /// sources. See [`crate::webpage::title`].
+    pub title_synthesized: bool,
 }
 impl RetrievedWebpage {
     pub fn description(&self) -> Option<&String> {
@@ -258,12 +563,15 @@ fn str_value(name: &str, value: &tantivy::schema::document::CompactDocValue) ->
         .to_string()
 }
 
-impl From<TantivyDocument> for RetrievedWebpage {
-    fn from(doc: TantivyDocument) -> Self {
+impl RetrievedWebpage {
+    /// Fields that are missing from the index keep their default value.
+    pub fn from_doc(doc: TantivyDocument, fields: &FieldMapping) -> Self {
         let mut webpage = RetrievedWebpage::default();
+        let mut title_quality = TitleQuality::Good;
+        let mut synthesized_title = String::new();
 
         for (field, value) in doc.field_values() {
-            match Field::get(field.field_id() as usize) {
+            match fields.get(field) {
                 Some(Field::Text(TextFieldEnum::Title(_))) => {
                     webpage.title = str_value(text_field::Title.name(), &value);
                 }
@@ -310,6 +618,10 @@ impl From<TantivyDocument> for RetrievedWebpage {
                 Some(Field::Fast(FastFieldEnum::LikelyHasPaywall(_))) => {
                     webpage.likely_has_paywall = value.as_u64().unwrap_or_default() != 0;
                 }
+                Some(Field::Fast(FastFieldEnum::ContentLabelFlags(_))) => {
+                    webpage.content_labels =
+                        ContentLabels::from_flags(value.as_u64().unwrap_or_default());
+                }
                 Some(Field::Text(TextFieldEnum::RecipeFirstIngredientTagId(_))) => {
                     let tag_id = str_value(text_field::RecipeFirstIngredientTagId.name(), &value);
                     if !tag_id.is_empty() {
@@ -320,10 +632,21 @@ impl From<TantivyDocument> for RetrievedWebpage {
                     let keywords = str_value(text_field::Keywords.name(), &value);
                     webpage.keywords = keywords.split('\n').map(|s| s.to_string()).collect();
                 }
+                Some(Field::Fast(FastFieldEnum::TitleQuality(_))) => {
+                    title_quality = TitleQuality::from_u64(value.as_u64().unwrap_or_default());
+                }
+                Some(Field::Text(TextFieldEnum::SynthesizedTitle(_))) => {
+                    synthesized_title = str_value(text_field::SynthesizedTitle.name(), &value);
+                }
                 _ => {}
             }
         }
 
+        if !title_quality.is_good() && !synthesized_title.is_empty() {
+            webpage.title = synthesized_title;
+            webpage.title_synthesized = true;
+        }
+
         webpage
     }
 }
@@ -426,6 +749,65 @@ mod tests {
         assert_eq!(result.documents[0].url, "https://www.example.com/");
     }
 
+    #[test]
+    fn last_modified_is_stored() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        index
+            .insert(&Webpage {
+                html: Html::parse(
+                    &format!(
+                        r#"
+                        <html>
+                            <head>
+                                <title>Test website</title>
+                                <meta property="og:updated_time" content="2022-06-22T19:37:34+00:00" />
+                            </head>
+                            <body>
+                                {CONTENT}
+                            </body>
+                        </html>
+                    "#
+                    ),
+                    "https://www.example.com",
+                )
+                .unwrap(),
+                // Wed, 21 Oct 2015 07:28:00 GMT
+                last_modified: Some(1_445_412_480),
+                sitemap_lastmod: Some(1_700_000_000),
+                ..Default::default()
+            })
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+        let ctx = index.local_search_ctx();
+
+        let query = Query::parse(
+            &ctx,
+            &SearchQuery {
+                query: "test".to_string(),
+                ..Default::default()
+            },
+            &index,
+        )
+        .expect("Failed to parse query");
+
+        let ranker = Ranker::new(
+            SignalComputer::new(Some(&query)),
+            ctx.fastfield_reader.clone(),
+            CollectorConfig::default(),
+        );
+
+        let result =
+            search(&index, &query, &ctx, ranker.collector(ctx.clone())).expect("Search failed");
+        assert_eq!(result.documents.len(), 1);
+
+        // the header takes precedence over the metadata of the page and the sitemap
+        assert_eq!(
+            result.documents[0].updated_time,
+            DateTime::from_timestamp(1_445_412_480, 0).map(|dt| dt.naive_utc())
+        );
+    }
+
     #[test]
     fn document_not_matching() {
         let mut index = InvertedIndex::temporary().expect("Unable to open index");
@@ -1043,6 +1425,183 @@ mod tests {
         assert_eq!(webpage.url, "https://www.example.com/".to_string());
     }
 
+    #[test]
+    fn webpage_cache() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+        index.set_webpage_cache_capacity(10);
+
+        index
+            .insert(
+                &Webpage::test_parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Test website</title>
+                        </head>
+                        <body>
+                            {CONTENT} test
+                        </body>
+                    </html>
+                "#
+                    ),
+                    "https://www.a.com",
+                )
+                .unwrap(),
+            )
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        let first = index.get_webpage("https://www.a.com").unwrap();
+        let second = index.get_webpage("https://www.a.com").unwrap();
+        assert_eq!(first.url, second.url);
+
+        let cache = index.webpage_cache().unwrap();
+        assert_eq!(cache.misses(), 1);
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.len(), 1);
+
+        index
+            .insert(
+                &Webpage::test_parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Other website</title>
+                        </head>
+                        <body>
+                            {CONTENT} test
+                        </body>
+                    </html>
+                "#
+                    ),
+                    "https://www.b.com",
+                )
+                .unwrap(),
+            )
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        assert!(index.webpage_cache().unwrap().is_empty());
+
+        let webpage = index.get_webpage("https://www.a.com").unwrap();
+        assert_eq!(webpage.title, "Test website".to_string());
+
+        let cache = index.webpage_cache().unwrap();
+        assert_eq!(cache.misses(), 2);
+        assert_eq!(cache.hits(), 1);
+    }
+
+    #[test]
+    fn slow_reads_are_degraded() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        for i in 0..5 {
+            index
+                .insert(
+                    &Webpage::test_parse(
+                        &format!(
+                            r#"
+                    <html>
+                        <head>
+                            <title>Website {i}</title>
+                        </head>
+                        <body>
+                            {CONTENT} test
+                        </body>
+                    </html>
+                "#
+                        ),
+                        &format!("https://www.{i}.com"),
+                    )
+                    .unwrap(),
+                )
+                .expect("failed to insert webpage");
+        }
+        index.commit().expect("failed to commit index");
+
+        let ctx = index.local_search_ctx();
+        let query = Query::parse(
+            &ctx,
+            &SearchQuery {
+                query: "test".to_string(),
+                ..Default::default()
+            },
+            &index,
+        )
+        .expect("Failed to parse query");
+        let ranker = Ranker::new(
+            SignalComputer::new(Some(&query)),
+            ctx.fastfield_reader.clone(),
+            CollectorConfig::default(),
+        );
+
+        let mut pointers = index
+            .search_initial(&query, &ctx, ranker.collector(ctx.clone()))
+            .unwrap()
+            .top_websites;
+        assert_eq!(pointers.len(), 5);
+        pointers.reverse();
+
+        let urls = index.retrieve_urls(&pointers).unwrap();
+
+        index
+            .set_retrieval_config(&RetrievalConfig {
+                threads: 4,
+                timeout_ms: 200,
+                max_queued_reads: 5,
+            })
+            .unwrap();
+        index.slow_down_read(pointers[2].address, std::time::Duration::from_secs(5));
+
+        let start = std::time::Instant::now();
+        let webpages = index.retrieve_websites(&pointers, &query).unwrap();
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+
+        assert_eq!(
+            webpages
+                .iter()
+                .map(|page| page.url.clone())
+                .collect::<Vec<_>>(),
+            urls
+        );
+
+        for (i, page) in webpages.iter().enumerate() {
+            if i == 2 {
+                assert!(page.degraded);
+                assert!(page.snippet.fragments.is_empty());
+                assert!(page.body.is_empty());
+            } else {
+                assert!(!page.degraded);
+                assert!(!page.snippet.fragments.is_empty());
+            }
+
+            let host = Url::parse(&page.url)
+                .unwrap()
+                .host_str()
+                .unwrap()
+                .to_string();
+            assert_eq!(
+                page.title,
+                format!(
+                    "Website {}",
+                    host.trim_start_matches("www.").trim_end_matches(".com")
+                )
+            );
+        }
+
+        // the slow read still takes a slot of the queue, so the last page is shed
+        let webpages = index.retrieve_websites(&pointers, &query).unwrap();
+        let degraded: Vec<_> = webpages
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| page.degraded)
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(degraded, vec![2, 4]);
+    }
+
     #[test]
     fn get_homepage() {
         let mut index = InvertedIndex::temporary().expect("Unable to open index");
@@ -1261,6 +1820,41 @@ mod tests {
         assert!(ranking_websites[1].title_embedding().is_none());
     }
 
+    #[test]
+    fn has_embeddings() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+        assert!(!index.has_embeddings());
+
+        let mut webpage = Webpage::test_parse(
+            &format!(
+                r#"
+                <html>
+                    <head>
+                        <title>Test website</title>
+                    </head>
+                    <body>
+                        {CONTENT} test
+                    </body>
+                </html>
+            "#,
+                CONTENT = crate::rand_words(100)
+            ),
+            "https://www.a.com",
+        )
+        .unwrap();
+
+        index.insert(&webpage).unwrap();
+        index.commit().expect("failed to commit index");
+        assert!(!index.has_embeddings());
+
+        webpage.title_embedding =
+            Some(Tensor::rand(0.0, 1.0, &[2, 2], &candle_core::Device::Cpu).unwrap());
+
+        index.insert(&webpage).unwrap();
+        index.commit().expect("failed to commit index");
+        assert!(index.has_embeddings());
+    }
+
     #[test]
     fn test_approximate_count() {
         let mut index = InvertedIndex::temporary().expect("Unable to open index");
@@ -0,0 +1,217 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reading the documents of a page of results from the doc store.
+//!
+//! The documents are read in parallel by a small pool of threads. A document that is not
+//! read within the timeout, e.g. because its block of the store must be read from a cold
+//! disk, is returned as a degraded webpage with only the url and title from the fast field
+//! columns, so a single slow block doesn't hold back the whole page. The slow read is left
+//! to finish in the background, where it still fills the caches for the next request.
+//! The number of queued and running reads is bounded, so when the disk can't keep up the
+//! documents that don't fit in the queue are shed and returned degraded right away instead
+//! of piling up behind the slow reads.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use tantivy::TantivyDocument;
+
+use super::{DocAddress, RetrievedWebpage, WebpageCache};
+use crate::{
+    config::RetrievalConfig,
+    schema::{fast_field, fast_field::FastField, FieldMapping},
+    Result,
+};
+
+/// Everything needed to read documents from the store, so a read can outlive the request.
+#[derive(Clone)]
+pub(super) struct DocReader {
+    pub searcher: tantivy::Searcher,
+    pub fields: Arc<FieldMapping>,
+    pub webpage_cache: Option<Arc<WebpageCache>>,
+    #[cfg(test)]
+    pub slow_reads: Arc<std::collections::HashMap<DocAddress, Duration>>,
+}
+
+impl DocReader {
+    pub fn read(&self, address: DocAddress) -> Result<RetrievedWebpage> {
+        let read = || {
+            #[cfg(test)]
+            if let Some(delay) = self.slow_reads.get(&address) {
+                std::thread::sleep(*delay);
+            }
+
+            let doc: TantivyDocument = self.searcher.doc(address.into())?;
+            Ok(RetrievedWebpage::from_doc(doc, &self.fields))
+        };
+
+        match &self.webpage_cache {
+            Some(cache) => {
+                cache.get_or_insert_with(self.searcher.generation().generation_id(), address, read)
+            }
+            None => read(),
+        }
+    }
+
+    fn column(&self, address: DocAddress, field: impl FastField) -> Option<String> {
+        let column = self
+            .searcher
+            .segment_reader(address.segment)
+            .fast_fields()
+            .bytes(field.name())
+            .ok()
+            .flatten()?;
+
+        // documents upgraded from an older schema have no value in the column
+        let ord = column.term_ords(address.doc_id).next()?;
+
+        let mut bytes = Vec::new();
+        column.ord_to_bytes(ord, &mut bytes).ok()?;
+
+        String::from_utf8(bytes).ok()
+    }
+
+    /// The page with only the url and title from the columns, or `None` if the
+    /// index has no url column.
+    pub fn read_degraded(&self, address: DocAddress) -> Option<RetrievedWebpage> {
+        Some(RetrievedWebpage {
+            url: self.column(address, fast_field::RawUrl)?,
+            title: self
+                .column(address, fast_field::RawTitle)
+                .unwrap_or_default(),
+            degraded: true,
+            ..Default::default()
+        })
+    }
+}
+
+/// A slot of a read in the queue of the pool, which is freed when the read is done.
+struct QueuedRead {
+    queued: Arc<AtomicUsize>,
+}
+
+impl Drop for QueuedRead {
+    fn drop(&mut self) {
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub struct RetrievalPool {
+    pool: ThreadPool,
+    timeout: Duration,
+    queued: Arc<AtomicUsize>,
+    max_queued: usize,
+}
+
+impl RetrievalPool {
+    /// `None` if the config has no threads, in which case the documents are read sequentially.
+    pub fn new(config: &RetrievalConfig) -> Result<Option<Self>> {
+        if config.threads == 0 {
+            return Ok(None);
+        }
+
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(config.threads)
+            .thread_name(|num| format!("retrieval-{num}"))
+            .build()?;
+
+        Ok(Some(Self {
+            pool,
+            timeout: Duration::from_millis(config.timeout_ms),
+            queued: Arc::new(AtomicUsize::new(0)),
+            max_queued: config.max_queued_reads,
+        }))
+    }
+
+    fn try_queue(&self) -> Option<QueuedRead> {
+        self.queued
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |queued| {
+                (queued < self.max_queued).then_some(queued + 1)
+            })
+            .ok()?;
+
+        Some(QueuedRead {
+            queued: Arc::clone(&self.queued),
+        })
+    }
+
+    /// Read the documents in parallel. The pages are in the same order as the addresses,
+    /// and the pages that were not read before the timeout or were shed because the queue
+    /// of the pool is full are `None`.
+    pub(super) fn read(
+        &self,
+        reader: &DocReader,
+        addresses: &[DocAddress],
+    ) -> Vec<Option<Result<RetrievedWebpage>>> {
+        let deadline = Instant::now() + self.timeout;
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut remaining = 0;
+
+        for (idx, address) in addresses.iter().copied().enumerate() {
+            let Some(slot) = self.try_queue() else {
+                continue;
+            };
+
+            let tx = tx.clone();
+            let reader = reader.clone();
+
+            self.pool.spawn(move || {
+                let page = reader.read(address);
+                drop(slot);
+
+                // the receiver is gone if the request stopped waiting for the read
+                tx.send((idx, page)).ok();
+            });
+
+            remaining += 1;
+        }
+        drop(tx);
+
+        if remaining < addresses.len() {
+            tracing::warn!(
+                "{} documents were shed as the retrieval queue is full",
+                addresses.len() - remaining
+            );
+        }
+
+        let mut pages: Vec<_> = addresses.iter().map(|_| None).collect();
+
+        while remaining > 0 {
+            match rx.recv_deadline(deadline) {
+                Ok((idx, page)) => {
+                    pages[idx] = Some(page);
+                    remaining -= 1;
+                }
+                Err(_) => {
+                    tracing::warn!(
+                        "{remaining} documents were not read within {:?}",
+                        self.timeout
+                    );
+                    break;
+                }
+            }
+        }
+
+        pages
+    }
+}
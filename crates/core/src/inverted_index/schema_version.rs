@@ -0,0 +1,286 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The schema version of an index is stored next to the tantivy metadata.
+//!
+//! An index with an older schema can still be searched as long as the schema has only gained
+//! fields since, in which case the missing fields resolve to typed defaults. Such an index is
+//! read-only, since new documents would not fit its schema, until it has been upgraded with
+//! [`super::InvertedIndex::upgrade_schema`].
+
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use crate::schema::{FieldMapping, MIN_SUPPORTED_SCHEMA_VERSION, SCHEMA_VERSION};
+use crate::Result;
+
+pub(crate) const SCHEMA_VERSION_FILE: &str = "schema_version.json";
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SchemaVersionFile {
+    version: u32,
+}
+
+/// Indexes without a version file were created before the schema was versioned.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<u32> {
+    let path = path.as_ref().join(SCHEMA_VERSION_FILE);
+
+    if !path.exists() {
+        return Ok(0);
+    }
+
+    let file: SchemaVersionFile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+
+    Ok(file.version)
+}
+
+pub fn write<P: AsRef<Path>>(path: P, version: u32) -> Result<()> {
+    std::fs::write(
+        path.as_ref().join(SCHEMA_VERSION_FILE),
+        serde_json::to_string(&SchemaVersionFile { version })?,
+    )?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compatibility {
+    Current,
+    ReadOnly,
+}
+
+/// Decide whether an index with the schema and version can be opened by this version of the code.
+pub fn check(
+    version: u32,
+    schema: &tantivy::schema::Schema,
+    fields: &FieldMapping,
+) -> Result<Compatibility> {
+    if version > SCHEMA_VERSION {
+        return Err(anyhow!(
+            "index has schema version {version} which is newer than the supported version {SCHEMA_VERSION}"
+        ));
+    }
+
+    if version < MIN_SUPPORTED_SCHEMA_VERSION {
+        return Err(anyhow!(
+            "index has schema version {version} which is older than the oldest supported version {MIN_SUPPORTED_SCHEMA_VERSION}. The index must be rebuilt"
+        ));
+    }
+
+    let current = crate::schema::create_schema();
+
+    for (field, entry) in schema.fields() {
+        match current.get_field(entry.name()) {
+            Ok(current_field) if current.get_field_entry(current_field) == entry => {}
+            Ok(_) => {
+                return Err(anyhow!(
+                    "the options of field '{}' have changed since schema version {version}. The index must be rebuilt",
+                    schema.get_field_name(field)
+                ))
+            }
+            Err(_) => {
+                return Err(anyhow!(
+                    "field '{}' has been removed since schema version {version}. The index must be rebuilt",
+                    schema.get_field_name(field)
+                ))
+            }
+        }
+    }
+
+    if fields.is_current() {
+        Ok(Compatibility::Current)
+    } else {
+        Ok(Compatibility::ReadOnly)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        index::Index,
+        schema::{fast_field, remap_document, schema_from_fields, text_field, Field},
+        searcher::{LocalSearcher, SearchQuery},
+        webpage::Webpage,
+    };
+
+    use super::*;
+
+    fn removed_fields() -> Vec<Field> {
+        vec![
+            Field::Text(text_field::Keywords.into()),
+            Field::Fast(fast_field::LinkDensity.into()),
+            Field::Fast(fast_field::RawUrl.into()),
+            Field::Fast(fast_field::RawTitle.into()),
+            Field::Fast(fast_field::TitleQuality.into()),
+            Field::Text(text_field::SynthesizedTitle.into()),
+        ]
+    }
+
+    fn webpage(title: &str, url: &str) -> Webpage {
+        Webpage::test_parse(
+            &format!(
+                r#"
+                <html>
+                    <head>
+                        <title>{title}</title>
+                    </head>
+                    <body>
+                        {}
+                    </body>
+                </html>
+                "#,
+                crate::rand_words(100)
+            ),
+            url,
+        )
+        .unwrap()
+    }
+
+    /// Create an index as it would have been written by the oldest supported
+    /// version of the code if its schema did not have the removed fields yet.
+    fn previous_version_index() -> std::path::PathBuf {
+        let path = crate::gen_temp_path();
+        let inverted_index_path = path.join("inverted_index");
+
+        let current = crate::schema::create_schema();
+        let removed = removed_fields();
+        let schema = schema_from_fields(Field::all().filter(|field| !removed.contains(field)));
+
+        let index =
+            super::super::create_tantivy_index(&inverted_index_path, schema.clone()).unwrap();
+        let mut writer: tantivy::IndexWriter =
+            index.writer_with_num_threads(1, 50_000_000).unwrap();
+
+        let doc = webpage("Test website", "https://www.example.com")
+            .as_tantivy(&current)
+            .unwrap();
+        writer
+            .add_document(remap_document(&doc, &current, &schema))
+            .unwrap();
+        writer.commit().unwrap();
+        writer.wait_merging_threads().unwrap();
+
+        write(&inverted_index_path, MIN_SUPPORTED_SCHEMA_VERSION).unwrap();
+
+        path
+    }
+
+    fn search(index: Index, query: &str) -> Vec<String> {
+        LocalSearcher::from(index)
+            .search(&SearchQuery {
+                query: query.to_string(),
+                ..Default::default()
+            })
+            .unwrap()
+            .webpages
+            .into_iter()
+            .map(|webpage| webpage.url)
+            .collect()
+    }
+
+    #[test]
+    fn open_previous_version() {
+        let path = previous_version_index();
+        let mut index = Index::open(&path).unwrap();
+
+        assert!(index.is_read_only());
+        assert_eq!(index.schema_version(), MIN_SUPPORTED_SCHEMA_VERSION);
+        assert_eq!(
+            index.inverted_index.field_mapping().missing(),
+            removed_fields()
+        );
+        assert!(index.prepare_writer().is_err());
+
+        let webpage = index
+            .inverted_index
+            .get_webpage("https://www.example.com/")
+            .unwrap();
+        assert_eq!(webpage.title, "Test website");
+        assert!(webpage.keywords.is_empty());
+
+        assert_eq!(search(index, "test"), vec!["https://www.example.com/"]);
+    }
+
+    #[test]
+    fn upgrade() {
+        let path = previous_version_index();
+        let upgraded_path = crate::gen_temp_path();
+
+        let mut index = Index::upgrade_schema(&path, &upgraded_path).unwrap();
+
+        assert!(!index.is_read_only());
+        assert_eq!(index.schema_version(), SCHEMA_VERSION);
+        assert!(index.inverted_index.field_mapping().is_current());
+
+        index.prepare_writer().unwrap();
+        index
+            .insert(&webpage("Another test website", "https://www.another.com"))
+            .unwrap();
+        index.commit().unwrap();
+
+        let mut urls = search(index, "test");
+        urls.sort();
+        assert_eq!(
+            urls,
+            vec!["https://www.another.com/", "https://www.example.com/"]
+        );
+
+        let index = Index::open(&upgraded_path).unwrap();
+        assert!(!index.is_read_only());
+
+        // the original index is left untouched
+        assert!(Index::open(&path).unwrap().is_read_only());
+        assert!(Index::upgrade_schema(&path, &upgraded_path).is_err());
+    }
+
+    #[test]
+    fn unversioned_index() {
+        let path = crate::gen_temp_path();
+        drop(Index::open(&path).unwrap());
+
+        // the text was tokenized differently before the schema was versioned,
+        // even if the fields are the same
+        std::fs::remove_file(path.join("inverted_index").join(SCHEMA_VERSION_FILE)).unwrap();
+        assert_eq!(read(path.join("inverted_index")).unwrap(), 0);
+
+        assert!(Index::open(&path).is_err());
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let path = crate::gen_temp_path();
+        drop(Index::open(&path).unwrap());
+
+        write(
+            path.join("inverted_index"),
+            MIN_SUPPORTED_SCHEMA_VERSION - 1,
+        )
+        .unwrap();
+
+        assert!(Index::open(&path).is_err());
+    }
+
+    #[test]
+    fn newer_version() {
+        let path = crate::gen_temp_path();
+        drop(Index::open(&path).unwrap());
+
+        write(path.join("inverted_index"), SCHEMA_VERSION + 1).unwrap();
+
+        assert!(Index::open(&path).is_err());
+    }
+}
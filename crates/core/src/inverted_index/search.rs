@@ -14,6 +14,9 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>
 
+use std::sync::Arc;
+
+use super::retrieval::DocReader;
 use super::{DocAddress, InitialSearchResult, InvertedIndex, RetrievedWebpage, WebpagePointer};
 use itertools::Itertools;
 use tantivy::collector::Count;
@@ -24,7 +27,7 @@ use tantivy::TantivyDocument;
 use url::Url;
 
 use crate::collector::approx_count::ApproxCount;
-use crate::collector::{approx_count, MainCollector};
+use crate::collector::{approx_count, SegmentTiming, TimedCollector, TopDocsCollector};
 
 use crate::fastfield_reader::FastFieldReader;
 use crate::highlighted::HighlightedFragment;
@@ -41,16 +44,48 @@ use crate::webgraph::NodeID;
 use crate::webpage::url_ext::UrlExt;
 use crate::Result;
 
+/// Search the segments of the index, timing each of them if `segment_timings` is set.
+fn search<C: tantivy::collector::Collector>(
+    ctx: &Ctx,
+    query: &dyn tantivy::query::Query,
+    collector: &C,
+    segment_timings: Option<&mut Vec<SegmentTiming>>,
+) -> Result<C::Fruit> {
+    match segment_timings {
+        Some(segment_timings) => {
+            let (fruit, timings) = ctx
+                .tv_searcher
+                .search(query, &TimedCollector::new(collector))?;
+            *segment_timings = timings;
+
+            Ok(fruit)
+        }
+        None => Ok(ctx.tv_searcher.search(query, collector)?),
+    }
+}
+
 impl InvertedIndex {
     pub fn search_initial(
         &self,
         query: &Query,
         ctx: &Ctx,
-        collector: MainCollector,
+        collector: impl TopDocsCollector,
+    ) -> Result<InitialSearchResult> {
+        self.search_initial_timed(query, ctx, collector, None)
+    }
+
+    /// Like [`Self::search_initial`], but records how long the collection of each segment
+    /// took in `segment_timings` if it is set.
+    pub fn search_initial_timed(
+        &self,
+        query: &Query,
+        ctx: &Ctx,
+        collector: impl TopDocsCollector,
+        segment_timings: Option<&mut Vec<SegmentTiming>>,
     ) -> Result<InitialSearchResult> {
         if query.count_results_exact() {
             let collector = (Count, collector);
-            let (count, pointers) = ctx.tv_searcher.search(query, &collector)?;
+            let (count, pointers) = search(ctx, query, &collector, segment_timings)?;
 
             return Ok(InitialSearchResult {
                 num_websites: approx_count::Count::Exact(count as u64),
@@ -65,9 +100,11 @@ impl InvertedIndex {
             let docs_per_segment = (limit.total_docs / limit.segments) as u64;
             query = Box::new(ShortCircuitQuery::new(query, docs_per_segment));
 
-            let (count, pointers) = ctx.tv_searcher.search(
+            let (count, pointers) = search(
+                ctx,
                 &query,
                 &(ApproxCount::new(docs_per_segment, simple_terms), collector),
+                segment_timings,
             )?;
 
             Ok(InitialSearchResult {
@@ -75,7 +112,7 @@ impl InvertedIndex {
                 top_websites: pointers,
             })
         } else {
-            let (count, pointers) = ctx.tv_searcher.search(&query, &(Count, collector))?;
+            let (count, pointers) = search(ctx, &query, &(Count, collector), segment_timings)?;
 
             Ok(InitialSearchResult {
                 num_websites: approx_count::Count::Approximate(count as u64),
@@ -84,6 +121,11 @@ impl InvertedIndex {
         }
     }
 
+    /// The number of documents that match the query, without ranking or retrieving any of them.
+    pub fn count(&self, query: &Query, ctx: &Ctx) -> Result<u64> {
+        Ok(search(ctx, query, &Count, None)? as u64)
+    }
+
     pub fn local_search_ctx(&self) -> Ctx {
         let tv_searcher = self.tv_searcher();
         Ctx {
@@ -154,12 +196,18 @@ impl InvertedIndex {
         let searcher = self.reader.searcher();
         let doc: TantivyDocument = searcher.doc(website.address.into())?;
 
-        let field = self
+        let field = match self
             .schema()
             .get_field(Field::Fast(FastFieldEnum::from(fast_field::HostNodeID)).name())
-            .unwrap();
+        {
+            Ok(field) => field,
+            Err(_) => return Ok(None),
+        };
 
-        let id = doc.get_first(field).unwrap().as_u64().unwrap();
+        let id = doc
+            .get_first(field)
+            .and_then(|value| value.as_u64())
+            .unwrap_or(u64::MAX);
 
         if id == u64::MAX {
             Ok(None)
@@ -168,22 +216,75 @@ impl InvertedIndex {
         }
     }
 
+    /// The canonical url hashes of the webpages in the same order as the pointers, or `None`
+    /// for the webpages of segments indexed before the hash was added.
+    /// See [`fast_field::CanonicalUrlHash`].
+    pub fn canonical_url_hashes(&self, websites: &[WebpagePointer]) -> Vec<Option<u64>> {
+        let tv_searcher = self.reader.searcher();
+
+        websites
+            .iter()
+            .map(|website| {
+                let segment = tv_searcher
+                    .segment_reader(website.address.segment)
+                    .segment_id();
+
+                self.fastfield_reader
+                    .borrow_segment(&segment)
+                    .get_field_reader(website.address.doc_id)
+                    .get(fast_field::CanonicalUrlHash.into())
+                    .and_then(|value| value.as_u64())
+                    .filter(|hash| *hash != 0)
+            })
+            .collect()
+    }
+
+    /// The urls of the webpages in the same order as the pointers.
+    pub fn retrieve_urls(&self, websites: &[WebpagePointer]) -> Result<Vec<String>> {
+        let tv_searcher = self.reader.searcher();
+
+        websites
+            .iter()
+            .map(|website| {
+                self.retrieve_doc(website.address, &tv_searcher)
+                    .map(|page| page.url)
+            })
+            .collect()
+    }
+
     pub fn retrieve_websites(
         &self,
         websites: &[WebpagePointer],
         query: &Query,
     ) -> Result<Vec<RetrievedWebpage>> {
-        let tv_searcher = self.reader.searcher();
-        let mut webpages: Vec<RetrievedWebpage> = websites
+        let reader = self.doc_reader(&self.reader.searcher());
+        let addresses: Vec<_> = websites.iter().map(|website| website.address).collect();
+
+        let pages = match &self.retrieval {
+            Some(pool) => pool.read(&reader, &addresses),
+            None => addresses
+                .iter()
+                .map(|address| Some(reader.read(*address)))
+                .collect(),
+        };
+
+        let mut webpages: Vec<RetrievedWebpage> = addresses
             .iter()
-            .map(|website| self.retrieve_doc(website.address, &tv_searcher))
-            .filter_map(|res| res.ok())
+            .zip(pages)
+            .filter_map(|(address, page)| match page {
+                Some(page) => page.ok(),
+                None => reader.read_degraded(*address),
+            })
             .collect();
 
-        for (url, page) in webpages.iter_mut().filter_map(|page| {
-            let url = Url::parse(&page.url).ok()?;
-            Some((url, page))
-        }) {
+        for (url, page) in webpages
+            .iter_mut()
+            .filter(|page| !page.degraded)
+            .filter_map(|page| {
+                let url = Url::parse(&page.url).ok()?;
+                Some((url, page))
+            })
+        {
             if query.simple_terms().is_empty() {
                 let snippet = if let Some(description) = page.description.as_deref() {
                     let snip = description
@@ -245,13 +346,22 @@ impl InvertedIndex {
         Ok(webpages)
     }
 
+    fn doc_reader(&self, searcher: &tantivy::Searcher) -> DocReader {
+        DocReader {
+            searcher: searcher.clone(),
+            fields: Arc::clone(&self.fields),
+            webpage_cache: self.webpage_cache.clone(),
+            #[cfg(test)]
+            slow_reads: Arc::clone(&self.slow_reads),
+        }
+    }
+
     fn retrieve_doc(
         &self,
         doc_address: DocAddress,
         searcher: &tantivy::Searcher,
     ) -> Result<RetrievedWebpage> {
-        let doc: TantivyDocument = searcher.doc(doc_address.into())?;
-        Ok(RetrievedWebpage::from(doc))
+        self.doc_reader(searcher).read(doc_address)
     }
 
     pub(crate) fn get_webpage(&self, url: &str) -> Option<RetrievedWebpage> {
@@ -260,7 +370,7 @@ impl InvertedIndex {
         let field = tv_searcher
             .schema()
             .get_field(Field::Text(TextFieldEnum::from(text_field::UrlNoTokenizer)).name())
-            .unwrap();
+            .ok()?;
 
         let term = tantivy::Term::from_field_text(field, url.as_str());
 
@@ -281,7 +391,7 @@ impl InvertedIndex {
             .get_field(
                 Field::Text(TextFieldEnum::from(text_field::SiteIfHomepageNoTokenizer)).name(),
             )
-            .unwrap();
+            .ok()?;
 
         let host = url.normalized_host().unwrap_or_default();
 
@@ -0,0 +1,361 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Corpus-wide pruning of rare terms.
+//!
+//! Most of the terms of noisy fields, like the n-gram fields, occur only a few times in the
+//! whole corpus. They make up a large part of the term dictionaries of the fields but are
+//! hardly ever searched for. The indexer can therefore make two passes over the corpus: the
+//! first counts how often each term of the pruned fields occurs, and the second indexes the
+//! pages without the terms that occurred fewer than the minimum number of times.
+//!
+//! The terms are counted in a count-min sketch per field, so the memory of the counts is
+//! fixed however many distinct terms the corpus has. The sketch never counts a term fewer
+//! times than it occurred, so a term that is frequent enough is never pruned, but a rare
+//! term whose counters are shared with frequent terms may be kept.
+//!
+//! A pruned term can no longer be matched in the pruned fields. A query for a rare word only
+//! finds the pages that have the word in one of the other fields, and a phrase with a rare
+//! word never matches the pruned fields. The positions of the other terms are unchanged.
+
+use std::{collections::HashMap, hash::BuildHasher};
+
+use tantivy::{
+    schema::{FieldType, Schema, Value},
+    tokenizer::{PreTokenizedString, TokenStream, TokenizerManager},
+    TantivyDocument,
+};
+
+use crate::schema::{text_field::TextField, TextFieldEnum};
+
+fn tokenizers() -> TokenizerManager {
+    let tokenizers = TokenizerManager::default();
+    super::register_tokenizers(&tokenizers);
+    tokenizers
+}
+
+/// The tokens a value of the field is indexed with. Values that are not pre-tokenized are
+/// tokenized by the tokenizer of the field, like tantivy does when the value is indexed.
+fn tokens<'a, V: Value<'a>>(
+    value: V,
+    field: tantivy::schema::Field,
+    schema: &Schema,
+    tokenizers: &TokenizerManager,
+) -> Option<PreTokenizedString> {
+    if let Some(pre_tokenized) = value.as_pre_tokenized_text() {
+        return Some(*pre_tokenized);
+    }
+
+    let text = value.as_str()?;
+    let tokenizer = match schema.get_field_entry(field).field_type() {
+        FieldType::Str(options) => options.get_indexing_options()?.tokenizer(),
+        _ => return None,
+    };
+    let mut tokenizer = tokenizers.get(tokenizer)?;
+
+    let mut tokens = Vec::new();
+    let mut stream = tokenizer.token_stream(text);
+    while let Some(token) = stream.next() {
+        tokens.push(token.clone());
+    }
+
+    Some(PreTokenizedString {
+        text: text.to_string(),
+        tokens,
+    })
+}
+
+const SKETCH_DEPTH: usize = 4;
+
+/// A count-min sketch of how often the terms occur.
+struct Sketch {
+    width: usize,
+    counters: Vec<u32>,
+}
+
+impl Sketch {
+    fn new(width: usize) -> Self {
+        let width = width.max(1);
+
+        Self {
+            width,
+            counters: vec![0; width * SKETCH_DEPTH],
+        }
+    }
+
+    /// The counter of the term in each row of the sketch.
+    fn cells(&self, term: &str) -> impl Iterator<Item = usize> {
+        let hash = ahash::RandomState::with_seeds(1, 2, 3, 4).hash_one(term);
+        let (h1, h2) = (hash, hash.rotate_left(32) | 1);
+        let width = self.width;
+
+        (0..SKETCH_DEPTH).map(move |row| {
+            let col = h1.wrapping_add((row as u64).wrapping_mul(h2)) % width as u64;
+            row * width + col as usize
+        })
+    }
+
+    fn add(&mut self, term: &str) {
+        for cell in self.cells(term).collect::<Vec<_>>() {
+            self.counters[cell] = self.counters[cell].saturating_add(1);
+        }
+    }
+
+    /// At least the number of times the term was added.
+    fn estimate(&self, term: &str) -> u64 {
+        self.cells(term)
+            .map(|cell| self.counters[cell] as u64)
+            .min()
+            .unwrap_or_default()
+    }
+
+    fn merge(&mut self, other: &Self) {
+        assert_eq!(self.width, other.width);
+
+        for (counter, other) in self.counters.iter_mut().zip(&other.counters) {
+            *counter = counter.saturating_add(*other);
+        }
+    }
+}
+
+/// How often the terms of the fields occur in the documents that have been counted.
+pub struct TermCounts {
+    counts: HashMap<TextFieldEnum, Sketch>,
+    tokenizers: TokenizerManager,
+}
+
+impl TermCounts {
+    /// The counts of the terms of the fields in sketches with `sketch_width` counters per row.
+    pub fn new(fields: &[TextFieldEnum], sketch_width: usize) -> Self {
+        Self {
+            counts: fields
+                .iter()
+                .map(|field| (*field, Sketch::new(sketch_width)))
+                .collect(),
+            tokenizers: tokenizers(),
+        }
+    }
+
+    pub fn count(&mut self, doc: &TantivyDocument, schema: &Schema) {
+        for (field, counts) in self.counts.iter_mut() {
+            let Some(tantivy_field) = field.tantivy_field(schema) else {
+                continue;
+            };
+
+            for value in doc.get_all(tantivy_field) {
+                if let Some(tokens) = tokens(value, tantivy_field, schema, &self.tokenizers) {
+                    for token in tokens.tokens {
+                        counts.add(&token.text);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Add the counts of the other terms. The counts must be of the same fields and have
+    /// sketches of the same width.
+    pub fn merge(mut self, other: Self) -> Self {
+        for (field, other_counts) in other.counts {
+            match self.counts.get_mut(&field) {
+                Some(counts) => counts.merge(&other_counts),
+                None => {
+                    self.counts.insert(field, other_counts);
+                }
+            }
+        }
+
+        self
+    }
+
+    /// Prune the terms that occurred fewer than `min_term_freq` times.
+    pub fn into_pruning(self, min_term_freq: u64) -> TermPruning {
+        TermPruning {
+            counts: self.counts,
+            min_term_freq,
+            tokenizers: self.tokenizers,
+        }
+    }
+}
+
+/// The terms that are left out when the documents are indexed.
+pub struct TermPruning {
+    counts: HashMap<TextFieldEnum, Sketch>,
+    min_term_freq: u64,
+    tokenizers: TokenizerManager,
+}
+
+impl TermPruning {
+    pub fn is_pruned(&self, field: TextFieldEnum, term: &str) -> bool {
+        self.counts
+            .get(&field)
+            .is_some_and(|counts| counts.estimate(term) < self.min_term_freq)
+    }
+
+    /// Remove the pruned terms from the values of the pruned fields. The values keep their
+    /// text, so the stored fields are not affected.
+    pub fn prune(&self, doc: TantivyDocument, schema: &Schema) -> TantivyDocument {
+        let pruned: HashMap<_, _> = self
+            .counts
+            .iter()
+            .filter_map(|(field, counts)| Some((field.tantivy_field(schema)?, counts)))
+            .collect();
+
+        let mut res = TantivyDocument::new();
+
+        for (field, value) in doc.field_values() {
+            let tokens = pruned
+                .get(&field)
+                .and_then(|_| tokens(value, field, schema, &self.tokenizers));
+
+            match tokens {
+                Some(mut tokens) => {
+                    let counts = pruned[&field];
+                    tokens
+                        .tokens
+                        .retain(|token| counts.estimate(&token.text) >= self.min_term_freq);
+                    res.add_pre_tokenized_text(field, tokens);
+                }
+                None => res.add_field_value(field, value),
+            }
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::TermPruningConfig,
+        index::Index,
+        schema::{text_field, Field},
+        searcher::{LocalSearcher, SearchQuery},
+        webpage::Webpage,
+    };
+
+    use super::*;
+
+    #[test]
+    fn sketch_never_undercounts() {
+        let mut sketch = Sketch::new(64);
+        let terms: Vec<_> = (0..1_000).map(|i| format!("term{i}")).collect();
+
+        for (i, term) in terms.iter().enumerate() {
+            for _ in 0..i % 5 {
+                sketch.add(term);
+            }
+        }
+
+        let mut merged = Sketch::new(64);
+        merged.merge(&sketch);
+        merged.merge(&sketch);
+
+        for (i, term) in terms.iter().enumerate() {
+            assert!(sketch.estimate(term) >= (i % 5) as u64);
+            assert!(merged.estimate(term) >= 2 * (i % 5) as u64);
+        }
+    }
+
+    fn webpage(url: &str, body: &str) -> Webpage {
+        Webpage::test_parse(
+            &format!(
+                r#"
+                <html>
+                    <head>
+                        <title>Example page</title>
+                    </head>
+                    <body>
+                        {body} {}
+                    </body>
+                </html>
+                "#,
+                crate::rand_words(100)
+            ),
+            url,
+        )
+        .unwrap()
+    }
+
+    fn search(searcher: &LocalSearcher<Index>, query: &str) -> Vec<String> {
+        let mut urls: Vec<_> = searcher
+            .search(&SearchQuery {
+                query: query.to_string(),
+                ..Default::default()
+            })
+            .unwrap()
+            .webpages
+            .into_iter()
+            .map(|webpage| webpage.url)
+            .collect();
+
+        urls.sort();
+        urls
+    }
+
+    #[test]
+    fn rare_terms_are_not_searchable() {
+        let config = TermPruningConfig {
+            min_term_freq: 2,
+            fields: Field::all()
+                .filter_map(|field| field.as_text())
+                .filter(TextField::is_searchable)
+                .map(|field| field.name().to_string())
+                .collect(),
+            sketch_width: 1 << 16,
+        };
+
+        let pages = vec![
+            webpage("https://www.a.com/", "frequentword hapaxword"),
+            webpage("https://www.b.com/", "frequentword"),
+            webpage("https://www.c.com/", "frequentword"),
+        ];
+
+        let schema = crate::schema::create_schema();
+        let mut counts = TermCounts::new(&config.text_fields().unwrap(), config.sketch_width);
+        for page in &pages {
+            counts.count(&page.as_tantivy(&schema).unwrap(), &schema);
+        }
+        let pruning = counts.into_pruning(config.min_term_freq);
+        assert!(pruning.is_pruned(text_field::AllBody.into(), "hapaxword"));
+        assert!(!pruning.is_pruned(text_field::AllBody.into(), "frequentword"));
+
+        let mut index = Index::temporary().unwrap();
+        index
+            .inverted_index
+            .set_term_pruning(std::sync::Arc::new(pruning));
+
+        for page in &pages {
+            index.insert(page).unwrap();
+        }
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index);
+
+        assert!(search(&searcher, "hapaxword").is_empty());
+        assert_eq!(
+            search(&searcher, "frequentword"),
+            vec![
+                "https://www.a.com/".to_string(),
+                "https://www.b.com/".to_string(),
+                "https://www.c.com/".to_string(),
+            ]
+        );
+        assert_eq!(
+            search(&searcher, "frequentword hapaxword"),
+            Vec::<String>::new()
+        );
+    }
+}
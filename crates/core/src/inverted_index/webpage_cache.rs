@@ -0,0 +1,122 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use lru::LruCache;
+
+use super::{DocAddress, RetrievedWebpage};
+
+struct Inner {
+    generation: u64,
+    pages: LruCache<DocAddress, RetrievedWebpage>,
+}
+
+/// A bounded cache of the documents read from the doc store.
+///
+/// Doc addresses are only valid for a single generation of the index reader, so the
+/// cache is cleared whenever it is accessed with a different generation than the one
+/// the cached documents were read from.
+pub struct WebpageCache {
+    inner: Mutex<Inner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl WebpageCache {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                generation: 0,
+                pages: LruCache::new(capacity),
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get_or_insert_with<F>(
+        &self,
+        generation: u64,
+        address: DocAddress,
+        retrieve: F,
+    ) -> crate::Result<RetrievedWebpage>
+    where
+        F: FnOnce() -> crate::Result<RetrievedWebpage>,
+    {
+        {
+            let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+            if inner.generation != generation {
+                inner.pages.clear();
+                inner.generation = generation;
+            }
+
+            if let Some(page) = inner.pages.get(&address) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(page.clone());
+            }
+        }
+
+        // the lock is not held while reading from the store so other
+        // requests are not blocked by slow reads.
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let page = retrieve()?;
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if inner.generation == generation {
+            inner.pages.put(address, page.clone());
+        }
+
+        Ok(page)
+    }
+
+    pub fn clear(&self) {
+        self.inner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pages
+            .clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pages
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of documents served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of documents that had to be read from the doc store.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
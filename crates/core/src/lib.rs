@@ -46,6 +46,7 @@ pub mod autosuggest;
 pub mod bangs;
 mod bincode_utils;
 pub mod canon_index;
+mod collation;
 mod collector;
 pub mod config;
 pub mod crawler;
@@ -56,7 +57,9 @@ pub mod executor;
 mod external_sort;
 mod fastfield_reader;
 pub mod feed;
+mod freshness;
 mod highlighted;
+pub mod host_languages;
 mod human_website_annotations;
 pub mod hyperloglog;
 pub mod image_store;
@@ -69,10 +72,13 @@ mod live_index;
 mod metrics;
 mod models;
 pub mod naive_bayes;
+pub mod object_store;
 pub mod prehashed;
 pub mod query;
+mod query_log;
 mod rake;
 pub mod ranking;
+pub mod replication;
 mod schema;
 mod search_ctx;
 mod search_prettifier;
@@ -82,6 +88,8 @@ pub mod similar_hosts;
 mod snippet;
 mod stopwords;
 pub mod summarizer;
+#[cfg(test)]
+pub mod test_fixtures;
 mod tokenizer;
 #[allow(unused)]
 mod ttl_cache;
@@ -120,6 +128,12 @@ pub enum Error {
     #[error("Query cannot be completely empty")]
     EmptyQuery,
 
+    #[error("Query must search at least one field")]
+    EmptySearchFields,
+
+    #[error("Unknown or unsearchable field: {0}")]
+    UnknownSearchField(String),
+
     #[error("Unknown region")]
     UnknownRegion,
 
@@ -73,9 +73,18 @@ impl Crawler {
             urls: urls
                 .clone()
                 .into_iter()
-                .map(|url| RetrieableUrl::from(WeightedUrl { url, weight: 1.0 }))
+                .map(|url| {
+                    RetrieableUrl::from(WeightedUrl {
+                        url,
+                        weight: 1.0,
+                        depth: 0,
+                    })
+                })
                 .collect(),
             wandering_urls: 0,
+            rate_overrides: Default::default(),
+            max_depth: None,
+            coordinator: None,
         };
 
         let executor = JobExecutor::new(
@@ -74,11 +74,15 @@ impl From<&LiveIndexConfig> for CrawlerConfig {
             s3: crate::config::S3Config {
                 bucket: String::new(),
                 folder: String::new(),
-                access_key: String::new(),
-                secret_key: String::new(),
-                endpoint: String::new(),
+                access_key: None,
+                secret_key: None,
+                anonymous: false,
+                endpoint: None,
+                region: None,
+                path_style: true,
             },
             router_hosts: Vec::new(),
+            artifact_manifest: None,
         }
     }
 }
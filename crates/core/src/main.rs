@@ -20,6 +20,7 @@ use std::fs;
 use std::path::Path;
 use stract::config;
 use stract::entrypoint::autosuggest_scrape::{self, Gl};
+use stract::entrypoint::sample_crawl::{self, SampleStrategy};
 
 #[cfg(feature = "dev")]
 use stract::entrypoint::configure;
@@ -113,6 +114,10 @@ enum Commands {
     Configure {
         #[clap(long)]
         skip_download: bool,
+
+        /// Build the dev index from a uniform sample of this many pages of the downloaded crawl.
+        #[clap(long)]
+        sample_docs: Option<usize>,
     },
 
     // Commands for the live index.
@@ -131,6 +136,60 @@ enum Commands {
         #[clap(subcommand)]
         options: AmpcOptions,
     },
+
+    /// Inspect and maintain the tables of a running distributed hash table (DHT).
+    Dht {
+        #[clap(subcommand)]
+        options: DhtOptions,
+    },
+
+    /// Evaluate the ranking of a local index against a set of graded judgments.
+    Eval {
+        index_path: String,
+        judgments_path: String,
+
+        #[clap(long)]
+        optic: Option<String>,
+
+        /// Fail if the metrics drop below the ones in this baseline file.
+        #[clap(long)]
+        baseline: Option<String>,
+
+        /// Overwrite the baseline with the metrics of this run.
+        #[clap(long)]
+        update_baseline: bool,
+    },
+
+    /// Sample warc files from the crawl into a small dataset for building a dev index.
+    SampleCrawl {
+        /// Folders with warc files (or the warc files themselves).
+        #[clap(required = true)]
+        input_dirs: Vec<String>,
+
+        #[clap(long)]
+        out: String,
+
+        #[clap(long)]
+        target_docs: usize,
+
+        /// One of `uniform`, `per-host-cap` or `centrality-weighted`.
+        #[clap(long, default_value = "uniform")]
+        strategy: SampleStrategy,
+
+        /// Maximum number of pages from each host for the `per-host-cap` strategy.
+        #[clap(long, default_value_t = 10)]
+        per_host_cap: usize,
+
+        /// The host centrality folder for the `centrality-weighted` strategy.
+        #[clap(long)]
+        centrality_store: Option<String>,
+
+        #[clap(long, default_value_t = 0)]
+        seed: u64,
+
+        #[clap(long, default_value_t = sample_crawl::DEFAULT_DOCS_PER_FILE)]
+        docs_per_file: usize,
+    },
 }
 
 #[derive(Subcommand)]
@@ -153,6 +212,34 @@ enum AmpcOptions {
     /// Start a coordinator to distribute the approximation of the harmonic centrality computation.
     /// Workers needs to be started before the coordinator.
     ApproxHarmonicCoordinator { config_path: String },
+
+    /// Show the progress of a computation from the status file of its coordinator.
+    Status {
+        status_path: String,
+
+        /// Keep refreshing the status until the computation has finished.
+        #[clap(long)]
+        watch: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DhtOptions {
+    /// Print the size and number of keys of the tables on each node, and how the keys
+    /// are distributed across the shards.
+    Inspect {
+        /// The nodes to inspect as `<shard>@<host:port>`.
+        #[clap(required = true)]
+        nodes: Vec<entrypoint::ampc::dht::NodeAddr>,
+    },
+
+    /// Snapshot the tables of each node and purge the writes from its log that are
+    /// part of the snapshot. This reclaims the space of overwritten keys and dropped tables.
+    Compact {
+        /// The nodes to compact as `<shard>@<host:port>`.
+        #[clap(required = true)]
+        nodes: Vec<entrypoint::ampc::dht::NodeAddr>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -180,6 +267,20 @@ enum Crawler {
 
     /// Create a crawl plan.
     Plan { config_path: String },
+
+    /// Reconstruct the robots.txt evidence of the fetches of a host from a directory with
+    /// the downloaded WARC files and their `.robots.jsonl` files, and report the fetches
+    /// that the recorded robots.txt did not allow.
+    Audit {
+        crawl_dir: String,
+
+        #[clap(long)]
+        host: String,
+
+        /// The user agent token the crawler identified as.
+        #[clap(long)]
+        user_agent: String,
+    },
 }
 
 /// Commands to train or run inference on the classifier that predicts if a webpage is NSFW or SFW.
@@ -207,12 +308,32 @@ enum CentralityMode {
         webgraph_path: String,
         output_path: String,
     },
+    /// Export computed centralities as `(node_id, host, score)` rows, ordered by
+    /// descending score.
+    Export {
+        /// The output folder of the host or page centrality computation.
+        centrality_path: String,
+        /// The webgraph the centralities were computed from.
+        webgraph_path: String,
+        output_path: String,
+
+        /// Either `csv`, `arrow` for an Arrow IPC file or `parquet`.
+        #[clap(long, default_value = "csv")]
+        format: entrypoint::CentralityExportFormat,
+    },
 }
 
 #[derive(Subcommand)]
 enum WebgraphOptions {
-    /// Create a new webgraph.
-    Create { config_path: String },
+    /// Create a new webgraph, or add the edges of the warc files that are not in the
+    /// webgraph yet if it already exists.
+    Create {
+        config_path: String,
+
+        /// Build the webgraph from all the warc files, even if it already exists.
+        #[clap(long)]
+        full: bool,
+    },
 
     /// Merge multiple webgraphs into a single graph.
     Merge {
@@ -223,6 +344,55 @@ enum WebgraphOptions {
         merge_all_segments: bool,
     },
 
+    /// Count the nodes that would get a different id if their urls were canonicalized
+    /// with the current canonicalization rules.
+    CanonicalizationReport {
+        path: String,
+
+        /// Number of changed nodes to print.
+        #[clap(long, default_value_t = 20)]
+        examples: usize,
+    },
+
+    /// Recompute the quantiles of the linking hosts' centrality used by the backlink
+    /// centrality filter. Graphs are updated automatically when built or merged,
+    /// so this is only needed for graphs built before the quantiles were introduced.
+    RefreshCentralityQuantiles { path: String },
+
+    /// Compare the backlinks of hosts between two webgraphs and write the
+    /// gained, lost and retained linking hosts as json.
+    Diff {
+        old_graph: String,
+        new_graph: String,
+
+        /// Host to compare.
+        #[clap(long, required_unless_present = "hosts_file")]
+        host: Option<String>,
+
+        /// File with one host per line to compare in a single run.
+        #[clap(long, conflicts_with = "host")]
+        hosts_file: Option<String>,
+
+        /// Write the report to this file instead of stdout.
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// Write every edge of the webgraph as `(from_id, to_id, rel_flags, sort_score, label)`
+    /// rows for offline processing.
+    ExportEdges {
+        path: String,
+        output_path: String,
+
+        /// Either `tsv` or `binary` for length-prefixed bincode records.
+        #[clap(long, default_value = "tsv")]
+        format: stract::webgraph::EdgeExportFormat,
+
+        /// Add the hosts of the nodes as `from_host` and `to_host` columns.
+        #[clap(long)]
+        resolve_hosts: bool,
+    },
+
     /// Deploy the webgraph server. The webgraph server is responsible for serving the webgraph to the search servers.
     /// This is e.g. used to find similar sites etc.
     Server { config_path: String },
@@ -241,6 +411,29 @@ enum IndexingOptions {
         paths: Vec<String>,
     },
 
+    /// Rewrite a search index created with an older schema to the current schema.
+    /// Indexes with an older schema can be searched but are read-only until upgraded.
+    UpgradeSearch {
+        index_path: String,
+        output_path: String,
+    },
+
+    /// Merge the segments of a search index down to a number of segments for faster queries.
+    /// Indexes that have no more segments than that are left as they are.
+    Resegment {
+        #[clap(long)]
+        index: String,
+
+        #[clap(long)]
+        num_segments: u64,
+    },
+
+    /// Print how many pages of the search index were crawled within the
+    /// maximum age of each bucket of its freshness histogram.
+    Freshness {
+        index_path: String,
+    },
+
     /// Create the entity index. Used in the sidebar of the search UI.
     Entity {
         wikipedia_dump_path: String,
@@ -256,6 +449,36 @@ enum IndexingOptions {
     Canonical {
         config_path: String,
     },
+
+    /// Count the languages of the pages on each host in the search index.
+    /// Used by the crawl coordinator to adjust the crawl budget per language.
+    HostLanguages {
+        index_path: String,
+        output_path: String,
+
+        /// Hosts with fewer pages than this get an unknown language.
+        #[clap(long, default_value_t = 10)]
+        min_pages: u64,
+    },
+
+    /// Export every page in the search index that matches a query as json lines.
+    ExportMatches {
+        index_path: String,
+
+        #[clap(long)]
+        query: String,
+
+        #[clap(long)]
+        out: String,
+
+        /// The stored fields to export for each match. Defaults to the url and title.
+        #[clap(long, value_delimiter = ',')]
+        fields: Vec<String>,
+
+        /// Stop the export if the query matches more pages than this.
+        #[clap(long)]
+        max_matches: Option<u64>,
+    },
 }
 
 fn load_toml_config<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> T {
@@ -304,10 +527,33 @@ fn main() -> Result<()> {
                     .collect::<Vec<_>>();
                 entrypoint::indexer::merge(pointers)?;
             }
+            IndexingOptions::UpgradeSearch {
+                index_path,
+                output_path,
+            } => entrypoint::indexer::upgrade_schema(index_path, output_path)?,
+            IndexingOptions::Resegment {
+                index,
+                num_segments,
+            } => entrypoint::indexer::resegment(index, num_segments)?,
+            IndexingOptions::Freshness { index_path } => {
+                entrypoint::indexer::freshness(index_path)?
+            }
             IndexingOptions::Canonical { config_path } => {
                 let config: config::CanonicalIndexConfig = load_toml_config(config_path);
                 entrypoint::canonical::create(config)?;
             }
+            IndexingOptions::HostLanguages {
+                index_path,
+                output_path,
+                min_pages,
+            } => entrypoint::host_languages::run(index_path, output_path, min_pages)?,
+            IndexingOptions::ExportMatches {
+                index_path,
+                query,
+                out,
+                fields,
+                max_matches,
+            } => entrypoint::export_matches::run(index_path, &query, out, fields, max_matches)?,
         },
         Commands::Centrality { mode } => {
             match mode {
@@ -321,13 +567,24 @@ fn main() -> Result<()> {
                     webgraph_path,
                     output_path,
                 } => entrypoint::Centrality::build_approx_harmonic(webgraph_path, output_path)?,
+                CentralityMode::Export {
+                    centrality_path,
+                    webgraph_path,
+                    output_path,
+                    format,
+                } => entrypoint::Centrality::export(
+                    centrality_path,
+                    webgraph_path,
+                    output_path,
+                    format,
+                )?,
             }
             tracing::info!("Done");
         }
         Commands::Webgraph { options } => match options {
-            WebgraphOptions::Create { config_path } => {
+            WebgraphOptions::Create { config_path, full } => {
                 let config = load_toml_config(config_path);
-                entrypoint::Webgraph::run(&config)?;
+                entrypoint::Webgraph::run(&config, full)?;
             }
             WebgraphOptions::Merge {
                 mut paths,
@@ -345,10 +602,48 @@ fn main() -> Result<()> {
                 if merge_all_segments {
                     webgraph.optimize_read(); // save space in id2node db
                     webgraph.merge_all_segments(Default::default())?;
+                } else {
+                    webgraph.refresh_centrality_quantiles();
                 }
 
                 webgraph.optimize_read();
             }
+            WebgraphOptions::CanonicalizationReport { path, examples } => {
+                entrypoint::canonicalization_report::run(path, examples)?;
+            }
+            WebgraphOptions::RefreshCentralityQuantiles { path } => {
+                WebgraphBuilder::new(path)
+                    .single_threaded()
+                    .open()
+                    .refresh_centrality_quantiles();
+            }
+            WebgraphOptions::Diff {
+                old_graph,
+                new_graph,
+                host,
+                hosts_file,
+                out,
+            } => {
+                let hosts = match (host, hosts_file) {
+                    (Some(host), _) => vec![host],
+                    (None, Some(hosts_file)) => entrypoint::webgraph_diff::read_hosts(hosts_file)?,
+                    (None, None) => unreachable!("clap requires a host or a hosts file"),
+                };
+
+                entrypoint::webgraph_diff::run(old_graph, new_graph, hosts, out)?;
+            }
+            WebgraphOptions::ExportEdges {
+                path,
+                output_path,
+                format,
+                resolve_hosts,
+            } => {
+                let graph = WebgraphBuilder::new(path).open();
+                let writer = std::io::BufWriter::new(fs::File::create(output_path)?);
+                let num_edges = graph.export_edges(writer, format, resolve_hosts)?;
+
+                tracing::info!("exported {num_edges} edges");
+            }
             WebgraphOptions::Server { config_path } => {
                 let config: config::WebgraphServerConfig = load_toml_config(config_path);
 
@@ -391,8 +686,11 @@ fn main() -> Result<()> {
             autosuggest_scrape::run(queries_to_scrape, gl, ms_sleep_between_req, output_dir)?;
         }
         #[cfg(feature = "dev")]
-        Commands::Configure { skip_download } => {
-            configure::run(skip_download)?;
+        Commands::Configure {
+            skip_download,
+            sample_docs,
+        } => {
+            configure::run(skip_download, sample_docs)?;
         }
         Commands::DmozParser {
             dmoz_file,
@@ -431,6 +729,11 @@ fn main() -> Result<()> {
                     .build()?
                     .block_on(entrypoint::crawler::planner(config))?;
             }
+            Crawler::Audit {
+                crawl_dir,
+                host,
+                user_agent,
+            } => entrypoint::crawler::audit(&crawl_dir, &host, &user_agent)?,
         },
         Commands::SafetyClassifier { options } => match options {
             SafetyClassifierOptions::Train {
@@ -485,7 +788,50 @@ fn main() -> Result<()> {
                 let config: config::ApproxHarmonicCoordinatorConfig = load_toml_config(config_path);
                 entrypoint::ampc::approximated_harmonic_centrality::coordinator::run(config)?;
             }
+            AmpcOptions::Status { status_path, watch } => {
+                entrypoint::ampc::status(status_path, watch)?;
+            }
         },
+        Commands::Dht { options } => {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+
+            match options {
+                DhtOptions::Inspect { nodes } => {
+                    rt.block_on(entrypoint::ampc::dht::inspect(nodes))?
+                }
+                DhtOptions::Compact { nodes } => {
+                    rt.block_on(entrypoint::ampc::dht::compact(nodes))?
+                }
+            }
+        }
+        Commands::Eval {
+            index_path,
+            judgments_path,
+            optic,
+            baseline,
+            update_baseline,
+        } => entrypoint::eval::run(index_path, judgments_path, optic, baseline, update_baseline)?,
+        Commands::SampleCrawl {
+            input_dirs,
+            out,
+            target_docs,
+            strategy,
+            per_host_cap,
+            centrality_store,
+            seed,
+            docs_per_file,
+        } => sample_crawl::run(sample_crawl::SampleConfig {
+            input_dirs: input_dirs.into_iter().map(Into::into).collect(),
+            out: out.into(),
+            target_docs,
+            strategy,
+            per_host_cap,
+            centrality_store: centrality_store.map(Into::into),
+            seed,
+            docs_per_file,
+        })?,
     }
 
     Ok(())
@@ -40,9 +40,17 @@ impl Counter {
         self.0.fetch_add(1, Ordering::SeqCst);
     }
 
+    pub fn add(&self, val: u64) {
+        self.0.fetch_add(val, Ordering::SeqCst);
+    }
+
     pub fn store(&self, val: u64) {
         self.0.store(val, Ordering::SeqCst);
     }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
 }
 
 pub enum PrometheusMetric {
@@ -0,0 +1,650 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Object stores (e.g. S3) as the backend of an index.
+//!
+//! [`ObjectStoreDirectory`] is a tantivy directory where the object store holds the files of
+//! the index and a local directory caches them. A file is downloaded the first time it is
+//! opened, so only the segment components that are actually read are fetched, and it is then
+//! memory mapped from the cache like any local index. Written files are uploaded once they
+//! are finished.
+//!
+//! The tantivy lock files are only kept in the cache, so nothing stops two machines from
+//! writing to the same index at once. Changes made by other machines are seen when the index
+//! is reloaded, but they do not trigger the watchers of the directory.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tantivy::directory::error::{DeleteError, OpenReadError, OpenWriteError};
+use tantivy::directory::{
+    AntiCallToken, Directory, FileHandle, MmapDirectory, TerminatingWrite, WatchCallback,
+    WatchHandle, WritePtr, INDEX_WRITER_LOCK, META_LOCK,
+};
+
+use crate::config::S3Config;
+use crate::Result;
+
+/// What the store knows about an object without downloading it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectMeta {
+    pub len: u64,
+    /// The md5 digest of the content for objects uploaded in a single part.
+    pub etag: Option<String>,
+}
+
+/// The files of an index can be large, so they are streamed to and from the store with
+/// [`ObjectStore::get_to`] and [`ObjectStore::put_from`]. [`ObjectStore::get`] and
+/// [`ObjectStore::put`] are meant for small objects, like the metadata of the index.
+pub trait ObjectStore: fmt::Debug + Send + Sync + 'static {
+    /// Write the object with the key to `writer`. Returns `false` if there is no object
+    /// with the key, in which case anything written to `writer` must be discarded.
+    fn get_to(&self, key: &str, writer: &mut (dyn Write + Send)) -> io::Result<bool>;
+
+    /// Upload the object from `reader` until it is exhausted.
+    fn put_from(&self, key: &str, reader: &mut (dyn Read + Send)) -> io::Result<()>;
+
+    /// Returns `None` if there is no object with the key.
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let mut data = Vec::new();
+        Ok(self.get_to(key, &mut data)?.then_some(data))
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        self.put_from(key, &mut &data[..])
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()>;
+
+    fn exists(&self, key: &str) -> io::Result<bool>;
+
+    /// Returns `None` if there is no object with the key.
+    fn head(&self, key: &str) -> io::Result<Option<ObjectMeta>>;
+}
+
+/// A bucket in an S3 compatible object store. The keys are relative to the folder of the config.
+pub struct S3ObjectStore {
+    bucket: Box<s3::Bucket>,
+    folder: String,
+}
+
+impl fmt::Debug for S3ObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3ObjectStore")
+            .field("bucket", &self.bucket.name())
+            .field("folder", &self.folder)
+            .finish()
+    }
+}
+
+impl S3ObjectStore {
+    pub fn new(config: &S3Config) -> Result<Self> {
+        let bucket = config
+            .bucket()?
+            .with_request_timeout(Duration::from_secs(30 * 60))?;
+
+        Ok(Self {
+            bucket,
+            folder: config.folder.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn key(&self, key: &str) -> String {
+        if self.folder.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{key}", self.folder)
+        }
+    }
+}
+
+fn s3_error(err: s3::error::S3Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+impl ObjectStore for S3ObjectStore {
+    fn get_to(&self, key: &str, mut writer: &mut (dyn Write + Send)) -> io::Result<bool> {
+        match self
+            .bucket
+            .get_object_to_writer_blocking(self.key(key), &mut writer)
+        {
+            Ok(404) | Err(s3::error::S3Error::HttpFailWithBody(404, _)) => Ok(false),
+            Ok(code) if (200..300).contains(&code) => Ok(true),
+            Ok(code) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("unexpected status code {code} for {key}"),
+            )),
+            Err(err) => Err(s3_error(err)),
+        }
+    }
+
+    fn put_from(&self, key: &str, mut reader: &mut (dyn Read + Send)) -> io::Result<()> {
+        self.bucket
+            .put_object_stream_blocking(&mut reader, self.key(key))
+            .map_err(s3_error)?;
+
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match self.bucket.get_object_blocking(self.key(key)) {
+            Ok(res) if res.status_code() == 404 => Ok(None),
+            Ok(res) => Ok(Some(res.bytes().to_vec())),
+            Err(s3::error::S3Error::HttpFailWithBody(404, _)) => Ok(None),
+            Err(err) => Err(s3_error(err)),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        self.bucket
+            .put_object_blocking(self.key(key), data)
+            .map_err(s3_error)?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        self.bucket
+            .delete_object_blocking(self.key(key))
+            .map_err(s3_error)?;
+
+        Ok(())
+    }
+
+    fn exists(&self, key: &str) -> io::Result<bool> {
+        match self.bucket.head_object_blocking(self.key(key)) {
+            Ok((_, code)) => Ok(code != 404),
+            Err(s3::error::S3Error::HttpFailWithBody(404, _)) => Ok(false),
+            Err(err) => Err(s3_error(err)),
+        }
+    }
+
+    fn head(&self, key: &str) -> io::Result<Option<ObjectMeta>> {
+        match self.bucket.head_object_blocking(self.key(key)) {
+            Ok((_, 404)) | Err(s3::error::S3Error::HttpFailWithBody(404, _)) => Ok(None),
+            Ok((head, _)) => Ok(Some(ObjectMeta {
+                len: head.content_length.unwrap_or_default().max(0) as u64,
+                etag: head.e_tag,
+            })),
+            Err(err) => Err(s3_error(err)),
+        }
+    }
+}
+
+/// Upload every file in the local directory to the store, keyed by `prefix` and their path
+/// relative to the directory. Lock files are skipped.
+pub fn upload_dir<P: AsRef<Path>>(store: &dyn ObjectStore, prefix: &str, path: P) -> Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let key = join_key(prefix, &name);
+
+        if entry.file_type()?.is_dir() {
+            upload_dir(store, &key, entry.path())?;
+        } else if !is_lock_file(Path::new(&name)) {
+            store.put_from(&key, &mut BufReader::new(fs::File::open(entry.path())?))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn join_key(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{name}", prefix.trim_end_matches('/'))
+    }
+}
+
+fn is_lock_file(path: &Path) -> bool {
+    path == INDEX_WRITER_LOCK.filepath || path == META_LOCK.filepath
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectStoreDirectory {
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+    cache_path: PathBuf,
+    cache: MmapDirectory,
+}
+
+impl ObjectStoreDirectory {
+    /// The files of the directory are the objects in the store with keys under `prefix`.
+    pub fn open<P: AsRef<Path>>(
+        store: Arc<dyn ObjectStore>,
+        prefix: &str,
+        cache_path: P,
+    ) -> Result<Self> {
+        fs::create_dir_all(&cache_path)?;
+
+        Ok(Self {
+            store,
+            prefix: prefix.trim_end_matches('/').to_string(),
+            cache_path: cache_path.as_ref().to_path_buf(),
+            cache: MmapDirectory::open(&cache_path)?,
+        })
+    }
+
+    fn key(&self, path: &Path) -> String {
+        join_key(&self.prefix, &path.to_string_lossy())
+    }
+
+    /// Download the file to the cache unless it is already cached.
+    /// Returns whether the file exists.
+    pub fn fetch(&self, path: &Path) -> io::Result<bool> {
+        let cached = self.cache_path.join(path);
+
+        if cached.exists() {
+            return Ok(true);
+        }
+
+        // every download has its own temporary file that is renamed into place, so a failed
+        // download never looks cached and concurrent downloads of the file don't mix.
+        let dir = cached.parent().unwrap_or(&self.cache_path);
+        let mut writer = BufWriter::new(tempfile::NamedTempFile::new_in(dir)?);
+
+        if !self.store.get_to(&self.key(path), &mut writer)? {
+            return Ok(false);
+        }
+
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .persist(cached)
+            .map_err(|err| err.error)?;
+
+        Ok(true)
+    }
+
+    /// Upload a file that was written to the cache outside of the directory,
+    /// unless the store already has the same content.
+    ///
+    /// The content is compared by the length and the ETag of the stored object, so objects
+    /// uploaded in multiple parts, whose ETag is not the md5 digest, are always uploaded again.
+    pub fn upload(&self, path: &Path) -> io::Result<()> {
+        let key = self.key(path);
+        let mut file = fs::File::open(self.cache_path.join(path))?;
+
+        let same = match self.store.head(&key)? {
+            Some(ObjectMeta {
+                len,
+                etag: Some(etag),
+            }) if len == file.metadata()?.len() => etag.trim_matches('"') == md5_hex(&mut file)?,
+            _ => false,
+        };
+
+        if !same {
+            file.seek(SeekFrom::Start(0))?;
+            self.store.put_from(&key, &mut BufReader::new(file))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn md5_hex(reader: &mut impl Read) -> io::Result<String> {
+    let mut context = md5::Context::new();
+    io::copy(reader, &mut context)?;
+
+    Ok(format!("{:x}", context.compute()))
+}
+
+/// Writes to the cache and uploads the file when the write is terminated.
+struct UploadingWriter {
+    inner: WritePtr,
+    store: Arc<dyn ObjectStore>,
+    key: String,
+    cached: PathBuf,
+}
+
+impl Write for UploadingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl TerminatingWrite for UploadingWriter {
+    fn terminate_ref(&mut self, token: AntiCallToken) -> io::Result<()> {
+        self.inner.terminate_ref(token)?;
+        self.store.put_from(
+            &self.key,
+            &mut BufReader::new(fs::File::open(&self.cached)?),
+        )
+    }
+}
+
+impl Directory for ObjectStoreDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        if !is_lock_file(path)
+            && !self
+                .fetch(path)
+                .map_err(|e| OpenReadError::wrap_io_error(e, path.to_path_buf()))?
+        {
+            return Err(OpenReadError::FileDoesNotExist(path.to_path_buf()));
+        }
+
+        self.cache.get_file_handle(path)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        if is_lock_file(path) {
+            return self.cache.delete(path);
+        }
+
+        match self.cache.delete(path) {
+            Ok(()) | Err(DeleteError::FileDoesNotExist(_)) => {}
+            Err(err) => return Err(err),
+        }
+
+        self.store
+            .delete(&self.key(path))
+            .map_err(|io_error| DeleteError::IoError {
+                io_error: Arc::new(io_error),
+                filepath: path.to_path_buf(),
+            })
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        if self.cache.exists(path)? {
+            return Ok(true);
+        }
+
+        if is_lock_file(path) {
+            return Ok(false);
+        }
+
+        self.store
+            .exists(&self.key(path))
+            .map_err(|e| OpenReadError::wrap_io_error(e, path.to_path_buf()))
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        if is_lock_file(path) {
+            return self.cache.open_write(path);
+        }
+
+        let key = self.key(path);
+
+        if self
+            .store
+            .exists(&key)
+            .map_err(|e| OpenWriteError::wrap_io_error(e, path.to_path_buf()))?
+        {
+            return Err(OpenWriteError::FileAlreadyExists(path.to_path_buf()));
+        }
+
+        Ok(BufWriter::new(Box::new(UploadingWriter {
+            inner: self.cache.open_write(path)?,
+            store: Arc::clone(&self.store),
+            key,
+            cached: self.cache_path.join(path),
+        })))
+    }
+
+    /// Atomic files such as the index metadata change between commits, so they are
+    /// always read from the store.
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        if is_lock_file(path) {
+            return self.cache.atomic_read(path);
+        }
+
+        self.store
+            .get(&self.key(path))
+            .map_err(|e| OpenReadError::wrap_io_error(e, path.to_path_buf()))?
+            .ok_or_else(|| OpenReadError::FileDoesNotExist(path.to_path_buf()))
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.cache.atomic_write(path, data)?;
+
+        if !is_lock_file(path) {
+            self.store.put(&self.key(path), data)?;
+        }
+
+        Ok(())
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        self.cache.sync_directory()
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> tantivy::Result<WatchHandle> {
+        self.cache.watch(watch_callback)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// An object store in memory that counts the objects that are read and written.
+    #[derive(Debug, Default)]
+    pub struct MemoryObjectStore {
+        objects: Mutex<HashMap<String, Vec<u8>>>,
+        gets: Mutex<Vec<String>>,
+        puts: Mutex<Vec<String>>,
+    }
+
+    impl MemoryObjectStore {
+        pub fn keys(&self) -> Vec<String> {
+            let mut keys: Vec<_> = self
+                .objects
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .keys()
+                .cloned()
+                .collect();
+            keys.sort();
+            keys
+        }
+
+        pub fn gets(&self) -> Vec<String> {
+            self.gets.lock().unwrap_or_else(|e| e.into_inner()).clone()
+        }
+
+        pub fn puts(&self) -> Vec<String> {
+            self.puts.lock().unwrap_or_else(|e| e.into_inner()).clone()
+        }
+    }
+
+    impl ObjectStore for MemoryObjectStore {
+        fn get_to(&self, key: &str, writer: &mut (dyn Write + Send)) -> io::Result<bool> {
+            self.gets
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(key.to_string());
+
+            let data = self
+                .objects
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(key)
+                .cloned();
+
+            match data {
+                Some(data) => {
+                    // written in chunks like a download
+                    for chunk in data.chunks(3) {
+                        writer.write_all(chunk)?;
+                    }
+
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        }
+
+        fn put_from(&self, key: &str, reader: &mut (dyn Read + Send)) -> io::Result<()> {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+
+            self.puts
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(key.to_string());
+            self.objects
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key.to_string(), data);
+
+            Ok(())
+        }
+
+        fn delete(&self, key: &str) -> io::Result<()> {
+            self.objects
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(key);
+
+            Ok(())
+        }
+
+        fn exists(&self, key: &str) -> io::Result<bool> {
+            Ok(self
+                .objects
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .contains_key(key))
+        }
+
+        fn head(&self, key: &str) -> io::Result<Option<ObjectMeta>> {
+            Ok(self
+                .objects
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(key)
+                .map(|data| ObjectMeta {
+                    len: data.len() as u64,
+                    etag: Some(format!("\"{:x}\"", md5::compute(data))),
+                }))
+        }
+    }
+
+    #[test]
+    fn lazy_fetch() {
+        let store = Arc::new(MemoryObjectStore::default());
+        store.put("index/a.idx", b"a").unwrap();
+        store.put("index/b.idx", b"b").unwrap();
+
+        let cache = crate::gen_temp_path();
+        let directory = ObjectStoreDirectory::open(store.clone(), "index", &cache).unwrap();
+
+        let file = directory.open_read(Path::new("a.idx")).unwrap();
+        assert_eq!(file.read_bytes().unwrap().as_slice(), b"a");
+        assert!(cache.join("a.idx").exists());
+        assert!(!cache.join("b.idx").exists());
+
+        // cached files are not fetched again
+        directory.open_read(Path::new("a.idx")).unwrap();
+        assert_eq!(store.gets(), vec!["index/a.idx".to_string()]);
+
+        assert!(matches!(
+            directory.open_read(Path::new("c.idx")),
+            Err(OpenReadError::FileDoesNotExist(_))
+        ));
+    }
+
+    #[test]
+    fn writes_are_uploaded() {
+        let store = Arc::new(MemoryObjectStore::default());
+        let directory =
+            ObjectStoreDirectory::open(store.clone(), "index", crate::gen_temp_path()).unwrap();
+
+        let mut writer = directory.open_write(Path::new("a.idx")).unwrap();
+        writer.write_all(b"hello").unwrap();
+        assert!(!store.exists("index/a.idx").unwrap());
+        writer.terminate().unwrap();
+
+        assert_eq!(store.get("index/a.idx").unwrap(), Some(b"hello".to_vec()));
+        assert!(matches!(
+            directory.open_write(Path::new("a.idx")),
+            Err(OpenWriteError::FileAlreadyExists(_))
+        ));
+
+        directory
+            .atomic_write(Path::new("meta.json"), b"{}")
+            .unwrap();
+        assert_eq!(
+            directory.atomic_read(Path::new("meta.json")).unwrap(),
+            b"{}".to_vec()
+        );
+
+        directory.delete(Path::new("a.idx")).unwrap();
+        assert_eq!(store.keys(), vec!["index/meta.json".to_string()]);
+    }
+
+    #[test]
+    fn missing_files_are_not_cached() {
+        let store = Arc::new(MemoryObjectStore::default());
+        let cache = crate::gen_temp_path();
+        let directory = ObjectStoreDirectory::open(store.clone(), "index", &cache).unwrap();
+
+        assert!(!directory.fetch(Path::new("a.idx")).unwrap());
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn concurrent_fetches() {
+        let store = Arc::new(MemoryObjectStore::default());
+        store.put("index/a.idx", &[7; 4096]).unwrap();
+
+        let cache = crate::gen_temp_path();
+        let directory = ObjectStoreDirectory::open(store.clone(), "index", &cache).unwrap();
+
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| assert!(directory.fetch(Path::new("a.idx")).unwrap()));
+            }
+        });
+
+        assert_eq!(fs::read(cache.join("a.idx")).unwrap(), vec![7; 4096]);
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn unchanged_files_are_not_uploaded() {
+        let store = Arc::new(MemoryObjectStore::default());
+        let cache = crate::gen_temp_path();
+        let directory = ObjectStoreDirectory::open(store.clone(), "index", &cache).unwrap();
+
+        fs::write(cache.join("version"), b"version 1").unwrap();
+        directory.upload(Path::new("version")).unwrap();
+        directory.upload(Path::new("version")).unwrap();
+        assert_eq!(store.puts(), vec!["index/version".to_string()]);
+
+        // a prefix of the stored content and a longer content both differ from it
+        for content in [&b"version"[..], b"version 10", b"version 2"] {
+            fs::write(cache.join("version"), content).unwrap();
+            directory.upload(Path::new("version")).unwrap();
+            assert_eq!(store.get("index/version").unwrap(), Some(content.to_vec()));
+        }
+
+        assert_eq!(store.puts().len(), 4);
+        assert!(store.gets().is_empty());
+    }
+}
@@ -14,20 +14,26 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::{collections::HashSet, sync::Arc};
+
 use crate::{
+    api::search::SafeSearch,
     inverted_index::InvertedIndex,
+    prehashed::{hash, Prehashed},
     query::parser::TermCompound,
     ranking::SignalCoefficient,
-    schema::text_field,
+    schema::{text_field, Field, TextFieldEnum},
     search_ctx::Ctx,
     searcher::SearchQuery,
-    webpage::{region::Region, safety_classifier},
+    webpage::{content_labels::ContentLabel, region::Region, safety_classifier},
     Error, Result,
 };
 
-use optics::{HostRankings, Optic};
+use itertools::Itertools;
+use optics::{HostRankings, MatchLocation, Matching, Optic, PatternPart};
 
 use tantivy::query::{BooleanQuery, Occur, QueryClone};
+use url::Url;
 
 mod const_query;
 pub mod intersection;
@@ -35,50 +41,119 @@ pub mod optic;
 pub mod parser;
 mod pattern_query;
 mod plan;
+pub mod segmentation;
 pub mod shortcircuit;
+pub mod synonyms;
 pub mod union;
 
-use self::{optic::AsMultipleTantivyQuery, parser::SimpleOrPhrase};
+use self::{
+    optic::{AsMultipleTantivyQuery, AsTantivyQuery},
+    parser::SimpleOrPhrase,
+    segmentation::Segmenter,
+    synonyms::SynonymExpander,
+    union::UnionQuery,
+};
 use parser::Term;
 
 pub const MAX_TERMS_FOR_NGRAM_LOOKUPS: usize = 16;
 
+/// Allowlists with more hosts than this are not compiled into the query, since every host
+/// adds a clause. The collector instead drops the results from hosts outside the allowlist.
+pub const MAX_COMPILED_RESTRICT_HOSTS: usize = 64;
+
 #[derive(Debug)]
 pub struct Query {
     simple_terms_text: Vec<String>,
+    expanded_terms: Vec<String>,
+    expansion_boost: f32,
+    segmented_terms: Vec<String>,
+    segmentation_boost: f32,
+    navigational: Option<Url>,
+    phrases: Vec<Vec<String>>,
     tantivy_query: Box<dyn tantivy::query::Query>,
     host_rankings: HostRankings,
     offset: usize,
     region: Option<Region>,
     optics: Vec<Optic>,
+    debug_optic: Option<Optic>,
     top_n: usize,
     count_results_exact: bool,
     signal_coefficients: SignalCoefficient,
     lang: Option<whatlang::Lang>,
+    host_filter: Option<Arc<HashSet<Prehashed>>>,
+    verbatim: bool,
 }
 
 impl Clone for Query {
     fn clone(&self) -> Self {
         Self {
             simple_terms_text: self.simple_terms_text.clone(),
+            expanded_terms: self.expanded_terms.clone(),
+            expansion_boost: self.expansion_boost,
+            segmented_terms: self.segmented_terms.clone(),
+            segmentation_boost: self.segmentation_boost,
+            navigational: self.navigational.clone(),
+            phrases: self.phrases.clone(),
             tantivy_query: self.tantivy_query.box_clone(),
             host_rankings: self.host_rankings.clone(),
             offset: self.offset,
             region: self.region,
             optics: self.optics.clone(),
+            debug_optic: self.debug_optic.clone(),
             top_n: self.top_n,
             count_results_exact: self.count_results_exact,
             signal_coefficients: self.signal_coefficients.clone(),
             lang: self.lang,
+            host_filter: self.host_filter.clone(),
+            verbatim: self.verbatim,
         }
     }
 }
 
+/// The searchable text fields with the given names.
+fn search_fields(names: &[String]) -> Result<Vec<TextFieldEnum>> {
+    if names.is_empty() {
+        return Err(Error::EmptySearchFields.into());
+    }
+
+    names
+        .iter()
+        .map(|name| {
+            Field::from_name(name)
+                .filter(|field| field.is_searchable())
+                .and_then(|field| field.as_text())
+                .ok_or_else(|| Error::UnknownSearchField(name.clone()).into())
+        })
+        .collect()
+}
+
+fn normalized_host(host: &str) -> String {
+    let host = host.trim().to_lowercase();
+    host.strip_prefix("www.").unwrap_or(&host).to_string()
+}
+
 impl Query {
     pub fn parse(ctx: &Ctx, query: &SearchQuery, index: &InvertedIndex) -> Result<Query> {
+        Self::parse_with_expansions(ctx, query, index, None, None)
+    }
+
+    /// Parse the query where the plain terms also match their synonyms and segmentation.
+    pub fn parse_with_expansions(
+        ctx: &Ctx,
+        query: &SearchQuery,
+        index: &InvertedIndex,
+        synonyms: Option<&SynonymExpander>,
+        segmenter: Option<&Segmenter>,
+    ) -> Result<Query> {
         let lang = whatlang::detect_lang(&query.query);
 
-        let parsed_terms = parser::truncate(parser::parse(&query.query)?);
+        let fields = query
+            .search_fields
+            .as_deref()
+            .map(search_fields)
+            .transpose()?;
+
+        let mut parsed_terms = parser::truncate(parser::parse(&query.query)?);
 
         if parsed_terms.is_empty() {
             tracing::error!("No terms found in query");
@@ -104,17 +179,93 @@ impl Query {
             })
             .collect();
 
-        let mut plan = plan::initial(parsed_terms).expect("terms are not empty and not all bangs");
+        let phrases: Vec<Vec<String>> = parsed_terms
+            .iter()
+            .filter_map(|term| match term {
+                Term::SimpleOrPhrase(SimpleOrPhrase::Phrase(phrase))
+                | Term::Body(SimpleOrPhrase::Phrase(phrase)) => Some(phrase.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let verbatim = query.verbatim || query.optic.as_ref().is_some_and(|optic| optic.verbatim);
+
+        // verbatim terms are searched like quoted phrases, which only match
+        // the exact words in the fields with positions
+        let (synonyms, segmenter) = if verbatim {
+            parsed_terms = parsed_terms.into_iter().map(Term::verbatim).collect();
+            (None, None)
+        } else {
+            (synonyms, segmenter)
+        };
+
+        let expanded_terms: Vec<String> = synonyms
+            .map(|expander| {
+                parsed_terms
+                    .iter()
+                    .filter_map(|term| match term {
+                        Term::SimpleOrPhrase(SimpleOrPhrase::Simple(s)) => {
+                            Some(expander.synonyms(s.as_str()))
+                        }
+                        _ => None,
+                    })
+                    .flatten()
+                    .unique()
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let segmented_terms: Vec<String> = segmenter
+            .map(|segmenter| {
+                parsed_terms
+                    .iter()
+                    .filter_map(|term| match term {
+                        Term::SimpleOrPhrase(SimpleOrPhrase::Simple(s)) => {
+                            segmenter.segment(s.as_str())
+                        }
+                        _ => None,
+                    })
+                    .flat_map(|segmentation| segmentation.terms())
+                    .unique()
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // a query of only a domain is likely a search for the site
+        let navigational = match (parsed_terms.as_slice(), segmenter) {
+            ([Term::SimpleOrPhrase(SimpleOrPhrase::Simple(s))], Some(_)) => {
+                segmentation::domain(s.as_str())
+            }
+            _ => None,
+        };
+
+        let mut plan = plan::initial_with_expansions(
+            parsed_terms,
+            synonyms,
+            segmenter,
+            fields.as_deref(),
+        )
+        .expect("terms are not empty and not all bangs");
 
         let schema = index.schema();
 
-        if query.safe_search {
+        if query.safe_search == SafeSearch::Strict {
             plan = plan.and(plan::Node::Not(Box::new(plan::Node::Term(
                 plan::Term::new(
                     parser::SimpleTerm::from(safety_classifier::Label::NSFW.to_string()).into(),
                     text_field::SafetyClassification.into(),
                 ),
             ))));
+
+            for label in ContentLabel::ALL {
+                plan = plan.and(plan::Node::Not(Box::new(plan::Node::Term(
+                    plan::Term::new(
+                        parser::SimpleTerm::from(label.to_string()).into(),
+                        text_field::ContentLabels.into(),
+                    ),
+                ))));
+            }
         }
 
         let mut tantivy_query = plan
@@ -122,14 +273,25 @@ impl Query {
             .as_tantivy(lang.as_ref(), &schema)
             .expect("there should at least be one field in the index");
 
-        let mut optics = Vec::new();
-        if let Some(site_rankigns_optic) = query.host_rankings.clone().map(|sr| sr.into_optic()) {
-            optics.push(site_rankigns_optic);
-        }
+        // the host rankings of the user and of the optic are applied once, where the
+        // rankings of the user win over the ones of the optic
+        let host_rankings = HostRankings::merge_user(
+            query.host_rankings.clone().unwrap_or_default(),
+            query
+                .optic
+                .as_ref()
+                .map(|optic| optic.host_rankings.clone())
+                .unwrap_or_default(),
+        );
 
-        if let Some(optic) = &query.optic {
-            optics.push(optic.clone());
-        }
+        let optics = match &query.optic {
+            Some(optic) => vec![Optic {
+                host_rankings: host_rankings.clone(),
+                ..optic.clone()
+            }],
+            None if query.host_rankings.is_some() => vec![host_rankings.clone().into_optic()],
+            None => Vec::new(),
+        };
 
         for optic in &optics {
             let mut subqueries = vec![(Occur::Must, tantivy_query.box_clone())];
@@ -137,35 +299,126 @@ impl Query {
             tantivy_query = Box::new(BooleanQuery::new(subqueries));
         }
 
+        let restrict_hosts: Vec<_> = query
+            .restrict_hosts
+            .iter()
+            .map(|host| normalized_host(host))
+            .filter(|host| !host.is_empty())
+            .unique()
+            .collect();
+
+        let mut host_filter = None;
+
+        if restrict_hosts.len() > MAX_COMPILED_RESTRICT_HOSTS {
+            host_filter = Some(Arc::new(restrict_hosts.iter().map(hash).collect()));
+        } else if !restrict_hosts.is_empty() {
+            let allowed = UnionQuery::from(
+                restrict_hosts
+                    .into_iter()
+                    .map(|host| {
+                        Matching {
+                            pattern: vec![
+                                PatternPart::Anchor,
+                                PatternPart::Raw(host),
+                                PatternPart::Anchor,
+                            ],
+                            location: MatchLocation::Site,
+                        }
+                        .as_tantivy(&schema, &ctx.fastfield_reader)
+                    })
+                    .collect_vec(),
+            );
+
+            tantivy_query = Box::new(BooleanQuery::new(vec![
+                (Occur::Must, tantivy_query),
+                (Occur::Must, allowed.box_clone()),
+            ]));
+        }
+
         Ok(Query {
-            host_rankings: optics.iter().fold(HostRankings::default(), |mut acc, el| {
-                acc.merge_into(el.host_rankings.clone());
-                acc
-            }),
+            host_rankings,
             simple_terms_text,
+            expanded_terms,
+            expansion_boost: synonyms.map(|expander| expander.boost()).unwrap_or(1.0),
+            segmented_terms,
+            segmentation_boost: segmenter.map(|segmenter| segmenter.boost()).unwrap_or(1.0),
+            navigational,
+            phrases,
             tantivy_query,
             optics,
+            debug_optic: if query.optic_debug {
+                query.optic.clone()
+            } else {
+                None
+            },
             offset: query.num_results * query.page,
             region: query.selected_region,
             top_n: query.num_results,
             count_results_exact: query.count_results_exact,
             signal_coefficients: query.signal_coefficients(),
             lang,
+            host_filter,
+            verbatim,
         })
     }
 
+    /// Site hashes of the allowed hosts if the allowlist was too large to be compiled into the query.
+    pub fn host_filter(&self) -> Option<&Arc<HashSet<Prehashed>>> {
+        self.host_filter.as_ref()
+    }
+
     pub fn count_results_exact(&self) -> bool {
         self.count_results_exact
     }
 
+    /// Whether the terms of the query are only searched for as the exact words.
+    pub fn is_verbatim(&self) -> bool {
+        self.verbatim
+    }
+
     pub fn simple_terms(&self) -> &[String] {
         &self.simple_terms_text
     }
 
+    /// The synonyms the terms of the query were expanded with.
+    pub fn expanded_terms(&self) -> &[String] {
+        &self.expanded_terms
+    }
+
+    /// The weight of the expanded terms relative to the terms of the query.
+    pub fn expansion_boost(&self) -> f32 {
+        self.expansion_boost
+    }
+
+    /// The hosts and words the terms of the query were segmented into.
+    pub fn segmented_terms(&self) -> &[String] {
+        &self.segmented_terms
+    }
+
+    /// The weight of the segmented terms relative to the terms of the query.
+    pub fn segmentation_boost(&self) -> f32 {
+        self.segmentation_boost
+    }
+
+    /// The url the query names if it is only a domain.
+    pub fn navigational(&self) -> Option<&Url> {
+        self.navigational.as_ref()
+    }
+
+    /// The quoted phrases of the query.
+    pub fn phrases(&self) -> &[Vec<String>] {
+        &self.phrases
+    }
+
     pub fn optics(&self) -> &[Optic] {
         &self.optics
     }
 
+    /// The user supplied optic if rule matches should be recorded for debugging.
+    pub fn debug_optic(&self) -> Option<&Optic> {
+        self.debug_optic.as_ref()
+    }
+
     pub fn num_results(&self) -> usize {
         self.top_n
     }
@@ -982,6 +1235,90 @@ mod tests {
         assert_eq!(result.webpages.len(), 2);
     }
 
+    #[test]
+    fn verbatim_query() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for (body, url) in [
+            ("The dog is running in the park", "https://www.first.com"),
+            ("The dog runs in the park", "https://www.second.com"),
+            ("The doghouse is in the park", "https://www.third.com"),
+        ] {
+            index
+                .insert(
+                    &Webpage::test_parse(
+                        &format!(
+                            r#"
+                        <html>
+                            <head>
+                                <title>Test website</title>
+                            </head>
+                            <body>
+                                {body} {}
+                            </body>
+                        </html>
+                    "#,
+                            rand_words(1000)
+                        ),
+                        url,
+                    )
+                    .unwrap(),
+                )
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+
+        let urls = |query: SearchQuery| -> Vec<String> {
+            let mut urls: Vec<_> = searcher
+                .search(&query)
+                .expect("Search failed")
+                .webpages
+                .into_iter()
+                .map(|webpage| webpage.url)
+                .collect();
+            urls.sort();
+            urls
+        };
+
+        // the stemmed fields match the other forms of the word
+        let query = SearchQuery {
+            query: "running".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            urls(query.clone()),
+            vec!["https://www.first.com/", "https://www.second.com/"]
+        );
+
+        let verbatim = SearchQuery {
+            verbatim: true,
+            ..query
+        };
+        assert_eq!(urls(verbatim), vec!["https://www.first.com/"]);
+
+        // or the compound of the words
+        let query = SearchQuery {
+            query: "dog house".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(urls(query.clone()), vec!["https://www.third.com/"]);
+
+        let verbatim = SearchQuery {
+            verbatim: true,
+            ..query
+        };
+        assert!(urls(verbatim).is_empty());
+
+        let optic = SearchQuery {
+            query: "running".to_string(),
+            optic: Some(Optic::parse("Verbatim;").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(urls(optic), vec!["https://www.first.com/"]);
+    }
+
     #[test]
     fn deduplicate_terms() {
         let a = parser::parse("the the the the the").unwrap();
@@ -1049,7 +1386,7 @@ mod tests {
 
         let query = SearchQuery {
             query: "test".to_string(),
-            safe_search: false,
+            safe_search: SafeSearch::Off,
             ..Default::default()
         };
 
@@ -1058,7 +1395,7 @@ mod tests {
 
         let query = SearchQuery {
             query: "test".to_string(),
-            safe_search: true,
+            safe_search: SafeSearch::Strict,
             ..Default::default()
         };
 
@@ -1068,6 +1405,205 @@ mod tests {
         assert_eq!(result.webpages[0].url, "https://www.sfw.com/");
     }
 
+    #[test]
+    fn content_warnings() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for (host, safety, labels) in [
+            ("plain", safety_classifier::Label::SFW, vec![]),
+            (
+                "news",
+                safety_classifier::Label::SFW,
+                vec![ContentLabel::Violence],
+            ),
+            ("adult", safety_classifier::Label::NSFW, vec![]),
+        ] {
+            let mut webpage = Webpage::test_parse(
+                &format!(
+                    r#"
+                <html>
+                    <head>
+                        <title>Test website</title>
+                    </head>
+                    <body>
+                        This is a test website {}
+                    </body>
+                </html>
+            "#,
+                    rand_words(1000)
+                ),
+                &format!("https://www.{host}.com"),
+            )
+            .unwrap();
+
+            webpage.safety_classification = Some(safety);
+            webpage.content_labels = labels.into_iter().collect();
+
+            index.insert(&webpage).expect("failed to insert webpage");
+        }
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+
+        let search = |safe_search: SafeSearch, optic: Option<&str>| {
+            let mut webpages = searcher
+                .search(&SearchQuery {
+                    query: "test".to_string(),
+                    safe_search,
+                    optic: optic.map(|optic| Optic::parse(optic).unwrap()),
+                    ..Default::default()
+                })
+                .expect("Search failed")
+                .webpages
+                .into_iter()
+                .map(|webpage| (webpage.url, webpage.content_labels, webpage.content_warning))
+                .collect::<Vec<_>>();
+
+            webpages.sort();
+            webpages
+        };
+
+        assert_eq!(
+            search(SafeSearch::Off, None),
+            vec![
+                (
+                    "https://www.adult.com/".to_string(),
+                    vec![ContentLabel::Adult],
+                    false
+                ),
+                (
+                    "https://www.news.com/".to_string(),
+                    vec![ContentLabel::Violence],
+                    false
+                ),
+                ("https://www.plain.com/".to_string(), vec![], false),
+            ]
+        );
+
+        assert_eq!(
+            search(SafeSearch::Warn, None),
+            vec![
+                (
+                    "https://www.adult.com/".to_string(),
+                    vec![ContentLabel::Adult],
+                    true
+                ),
+                (
+                    "https://www.news.com/".to_string(),
+                    vec![ContentLabel::Violence],
+                    true
+                ),
+                ("https://www.plain.com/".to_string(), vec![], false),
+            ]
+        );
+
+        assert_eq!(
+            search(SafeSearch::Strict, None),
+            vec![("https://www.plain.com/".to_string(), vec![], false)]
+        );
+
+        // optics can leave out specific labels regardless of the mode
+        assert_eq!(
+            search(
+                SafeSearch::Warn,
+                Some(r#"Rule { Matches { ContentLabel("violence") }, Action(Discard) }"#)
+            ),
+            vec![
+                (
+                    "https://www.adult.com/".to_string(),
+                    vec![ContentLabel::Adult],
+                    true
+                ),
+                ("https://www.plain.com/".to_string(), vec![], false),
+            ]
+        );
+    }
+
+    #[test]
+    fn restrict_hosts() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for url in [
+            "https://www.a.com/",
+            "https://blog.a.com/",
+            "https://b.com/page",
+            "https://c.com/",
+        ] {
+            let webpage = Webpage::test_parse(
+                &format!(
+                    r#"
+                    <html>
+                        <head>
+                            <title>Test website</title>
+                        </head>
+                        <body>
+                            This is a test website {}
+                        </body>
+                    </html>
+                "#,
+                    rand_words(1000)
+                ),
+                url,
+            )
+            .unwrap();
+
+            index.insert(&webpage).expect("failed to insert webpage");
+        }
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+
+        let search = |restrict_hosts: Vec<String>| {
+            let mut urls: Vec<_> = searcher
+                .search(&SearchQuery {
+                    query: "test".to_string(),
+                    restrict_hosts,
+                    ..Default::default()
+                })
+                .expect("Search failed")
+                .webpages
+                .into_iter()
+                .map(|webpage| webpage.url)
+                .collect();
+            urls.sort();
+            urls
+        };
+
+        assert_eq!(search(vec![]).len(), 4);
+
+        let expected = vec![
+            "https://c.com/".to_string(),
+            "https://www.a.com/".to_string(),
+        ];
+        assert_eq!(
+            search(vec!["a.com".to_string(), "C.com".to_string()]),
+            expected
+        );
+
+        // too many hosts to compile into the query, so they are filtered while collecting.
+        let mut many: Vec<_> = (0..MAX_COMPILED_RESTRICT_HOSTS)
+            .map(|i| format!("filler{i}.com"))
+            .collect();
+        many.push("www.a.com".to_string());
+        many.push("c.com".to_string());
+
+        let inverted_index = &searcher.index().inverted_index;
+        assert!(Query::parse(
+            &inverted_index.local_search_ctx(),
+            &SearchQuery {
+                query: "test".to_string(),
+                restrict_hosts: many.clone(),
+                ..Default::default()
+            },
+            inverted_index,
+        )
+        .unwrap()
+        .host_filter()
+        .is_some());
+
+        assert_eq!(search(many), expected);
+    }
+
     #[test]
     fn suffix_domain_prefix_path_site_operator() {
         let mut index = Index::temporary().expect("Unable to open index");
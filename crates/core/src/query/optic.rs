@@ -21,8 +21,13 @@ use tantivy::{
     query::{BooleanQuery, Occur, QueryClone},
     schema::Schema,
 };
+use utoipa::ToSchema;
 
-use crate::{fastfield_reader::FastFieldReader, schema::text_field, webpage::schema_org};
+use crate::{
+    fastfield_reader::FastFieldReader,
+    schema::text_field::{self, TextField},
+    webpage::schema_org,
+};
 
 use super::{const_query::ConstQuery, pattern_query::PatternQuery, union::UnionQuery};
 
@@ -83,6 +88,80 @@ impl AsMultipleTantivyQuery for Optic {
     }
 }
 
+/// A rule from the optic that matched a result.
+/// Only recorded when the search is performed with `optic_debug` enabled.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct OpticRuleMatch {
+    /// Index of the rule in the optic.
+    pub rule: usize,
+    pub action: String,
+    pub score_delta: f64,
+}
+
+impl OpticRuleMatch {
+    pub fn new(rule: usize, action: &Action) -> Self {
+        let score_delta = match action {
+            Action::Boost(boost) => *boost as f64,
+            Action::Downrank(boost) => *boost as f64 * -1.0,
+            Action::Discard => 0.0,
+        };
+
+        Self {
+            rule,
+            action: action.to_string(),
+            score_delta,
+        }
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct OpticDebugSummary {
+    /// Indices of the rules that did not match any of the returned results.
+    pub unmatched_rules: Vec<usize>,
+}
+
+impl OpticDebugSummary {
+    pub fn new<'a>(optic: &Optic, matches: impl Iterator<Item = &'a [OpticRuleMatch]>) -> Self {
+        let mut matched = vec![false; optic.rules.len()];
+
+        for rule_match in matches.flatten() {
+            if let Some(m) = matched.get_mut(rule_match.rule) {
+                *m = true;
+            }
+        }
+
+        Self {
+            unmatched_rules: matched
+                .into_iter()
+                .enumerate()
+                .filter(|(_, m)| !m)
+                .map(|(rule, _)| rule)
+                .collect(),
+        }
+    }
+}
+
 pub struct SearchableRule {
     pub query: Box<dyn tantivy::query::Query>,
     pub boost: f64,
@@ -268,6 +347,29 @@ impl AsTantivyQuery for Matching {
                     1.0,
                 ))
             }
+            MatchLocation::ContentLabel => {
+                let label = self
+                    .pattern
+                    .iter()
+                    .filter_map(|part| match part {
+                        PatternPart::Raw(s) => Some(s.as_str()),
+                        _ => None,
+                    })
+                    .join(" ")
+                    .to_lowercase();
+
+                match text_field::ContentLabels.tantivy_field(schema) {
+                    Some(field) => Box::new(ConstQuery::new(
+                        Box::new(tantivy::query::TermQuery::new(
+                            tantivy::Term::from_field_text(field, &label),
+                            tantivy::schema::IndexRecordOption::Basic,
+                        )),
+                        1.0,
+                    )),
+                    // the index was created before the content labels were added
+                    None => Box::new(tantivy::query::EmptyQuery),
+                }
+            }
         }
     }
 }
@@ -276,6 +378,7 @@ impl AsTantivyQuery for Matching {
 mod tests {
     use optics::{HostRankings, Optic};
 
+    use super::{OpticDebugSummary, OpticRuleMatch};
     use crate::{
         bangs::Bangs,
         enum_map, gen_temp_path,
@@ -1941,4 +2044,121 @@ mod tests {
         assert_eq!(res.len(), 1);
         assert_eq!(res[0].url, "https://a-third-example.com/");
     }
+
+    #[test]
+    fn optic_debug_rule_matches() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(&Webpage {
+                html: Html::parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Website A</title>
+                        </head>
+                        <body>
+                            {CONTENT} {}
+                        </body>
+                    </html>
+                "#,
+                        crate::rand_words(100)
+                    ),
+                    "https://www.a.com",
+                )
+                .unwrap(),
+                fetch_time_ms: 500,
+                ..Default::default()
+            })
+            .expect("failed to insert webpage");
+        index
+            .insert(&Webpage {
+                html: Html::parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Website B</title>
+                        </head>
+                        <body>
+                            {CONTENT} {}
+                        </body>
+                    </html>
+                "#,
+                        crate::rand_words(100)
+                    ),
+                    "https://www.b.com",
+                )
+                .unwrap(),
+                fetch_time_ms: 500,
+                ..Default::default()
+            })
+            .expect("failed to insert webpage");
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+
+        let res = searcher
+            .search(&SearchQuery {
+                query: "website".to_string(),
+                optic: Some(
+                    Optic::parse(
+                        r#"
+                        Rule {
+                            Matches {
+                                Domain("a.com")
+                            },
+                            Action(Boost(100))
+                        };
+                        Rule {
+                            Matches {
+                                Domain("c.com")
+                            },
+                            Action(Downrank(2))
+                        }
+                    "#,
+                    )
+                    .unwrap(),
+                ),
+                optic_debug: true,
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(res.webpages.len(), 2);
+        assert_eq!(res.webpages[0].url, "https://www.a.com/");
+        assert_eq!(
+            res.webpages[0].optic_rule_matches,
+            Some(vec![OpticRuleMatch {
+                rule: 0,
+                action: "Action(Boost(100))".to_string(),
+                score_delta: 100.0,
+            }])
+        );
+
+        assert_eq!(res.webpages[1].url, "https://www.b.com/");
+        assert_eq!(res.webpages[1].optic_rule_matches, Some(vec![]));
+
+        assert_eq!(
+            res.optic_debug,
+            Some(OpticDebugSummary {
+                unmatched_rules: vec![1]
+            })
+        );
+
+        let res = searcher
+            .search(&SearchQuery {
+                query: "website".to_string(),
+                optic: Some(
+                    Optic::parse(r#"Rule { Matches { Domain("a.com") }, Action(Boost(100)) }"#)
+                        .unwrap(),
+                ),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert!(res.optic_debug.is_none());
+        assert!(res.webpages.iter().all(|w| w.optic_rule_matches.is_none()));
+    }
 }
@@ -52,6 +52,19 @@ impl SimpleOrPhrase {
 }
 
 impl SimpleOrPhrase {
+    fn verbatim(self) -> SimpleOrPhrase {
+        match self {
+            SimpleOrPhrase::Simple(simple) => SimpleOrPhrase::Phrase(
+                simple
+                    .as_str()
+                    .split_whitespace()
+                    .map(|s| s.to_string())
+                    .collect(),
+            ),
+            SimpleOrPhrase::Phrase(phrase) => SimpleOrPhrase::Phrase(phrase),
+        }
+    }
+
     pub fn as_string(&self) -> String {
         match self {
             SimpleOrPhrase::Simple(simple) => simple.as_str().to_string(),
@@ -129,6 +142,19 @@ impl Term {
         }
     }
 
+    /// Search for the term like it was quoted, so it only matches the exact words
+    /// and is not stemmed, expanded with synonyms or combined into compounds.
+    pub fn verbatim(self) -> Term {
+        match self {
+            Term::SimpleOrPhrase(s) => Term::SimpleOrPhrase(s.verbatim()),
+            Term::Title(s) => Term::Title(s.verbatim()),
+            Term::Body(s) => Term::Body(s.verbatim()),
+            Term::Url(s) => Term::Url(s.verbatim()),
+            Term::Not(n) => Term::Not(Box::new(n.verbatim())),
+            Term::Site(_) | Term::LinkTo(_) | Term::PossibleBang { .. } => self,
+        }
+    }
+
     pub fn truncate(self) -> Term {
         match self {
             Term::SimpleOrPhrase(s) => Term::SimpleOrPhrase(s.truncate()),
@@ -45,7 +45,10 @@ impl FastSiteDomainPatternWeight {
         &self,
         reader: &tantivy::SegmentReader,
     ) -> tantivy::Result<Option<FastSiteDomainPatternScorer>> {
-        let field_no_tokenizer = match Field::get(self.field.field_id() as usize) {
+        // the field ids of indexes with an older schema differ from the current schema
+        let field = Field::from_name(reader.schema().get_field_name(self.field));
+
+        let field_no_tokenizer = match field {
             Some(Field::Text(TextFieldEnum::UrlForSiteOperator(_))) => {
                 Field::Text(text_field::SiteNoTokenizer.into())
             }
@@ -126,7 +129,9 @@ impl PatternWeight {
             return Ok(None);
         }
 
-        let num_tokens_fastfield = match Field::get(self.field.field_id() as usize) {
+        let field = Field::from_name(reader.schema().get_field_name(self.field));
+
+        let num_tokens_fastfield = match field {
             Some(Field::Text(TextFieldEnum::Title(_))) => Ok(fast_field::NumTitleTokens.into()),
             Some(Field::Text(TextFieldEnum::CleanBody(_))) => {
                 Ok(fast_field::NumCleanBodyTokens.into())
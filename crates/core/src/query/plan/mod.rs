@@ -20,10 +20,15 @@ mod node;
 
 pub use node::Node;
 
-use crate::schema::{self, text_field::TextField, TextFieldEnum};
+use crate::{
+    schema::{self, text_field::TextField, TextFieldEnum},
+    webpage::url_ext::UrlExt,
+};
 
 use super::{
     parser::{SimpleOrPhrase, SimpleTerm},
+    segmentation::{Segmentation, Segmenter},
+    synonyms::SynonymExpander,
     MAX_TERMS_FOR_NGRAM_LOOKUPS,
 };
 
@@ -233,6 +238,17 @@ fn sliding_window(window_size: usize, i: usize) -> impl Iterator<Item = (usize,
 }
 
 pub fn initial(terms: Vec<super::Term>) -> Option<Node> {
+    initial_with_expansions(terms, None, None, None)
+}
+
+/// Like [`initial`], but the simple terms also match their synonyms and segmentation
+/// and the plain terms and phrases are only searched in `fields` if they are given.
+pub fn initial_with_expansions(
+    terms: Vec<super::Term>,
+    synonyms: Option<&SynonymExpander>,
+    segmenter: Option<&Segmenter>,
+    fields: Option<&[TextFieldEnum]>,
+) -> Option<Node> {
     let mut nodes = Vec::new();
     let terms_for_adjacent = terms.clone();
 
@@ -264,14 +280,63 @@ pub fn initial(terms: Vec<super::Term>) -> Option<Node> {
             }
         }
 
-        let node = Node::from_term(term);
+        let term_synonyms = match (&term, synonyms) {
+            (super::Term::SimpleOrPhrase(SimpleOrPhrase::Simple(s)), Some(expander)) => {
+                expander.synonyms(s.as_str()).to_vec()
+            }
+            _ => Vec::new(),
+        };
+
+        let segmentation = match (&term, segmenter) {
+            (super::Term::SimpleOrPhrase(SimpleOrPhrase::Simple(s)), Some(segmenter)) => {
+                segmenter.segment(s.as_str())
+            }
+            _ => None,
+        };
+
+        let mut node = term_synonyms
+            .into_iter()
+            .map(|synonym| {
+                Node::from_term_in_fields(
+                    super::Term::SimpleOrPhrase(SimpleOrPhrase::Simple(SimpleTerm::from(synonym))),
+                    fields,
+                )
+            })
+            .fold(Node::from_term_in_fields(term, fields), |node, synonym| {
+                node.or(synonym)
+            });
+
+        match segmentation {
+            // the pages of the host would match regardless of the search fields
+            Some(Segmentation::Domain(url)) if fields.is_none() => {
+                if let Some(host) = url.normalized_host() {
+                    node = node.or(Node::from_term(super::Term::Site(host.to_string())));
+                }
+            }
+            Some(Segmentation::Words(words)) => {
+                if let Some(words) = words
+                    .into_iter()
+                    .map(|word| {
+                        Node::from_term_in_fields(
+                            super::Term::SimpleOrPhrase(SimpleOrPhrase::Simple(SimpleTerm::from(
+                                word,
+                            ))),
+                            fields,
+                        )
+                    })
+                    .reduce(|left, right| left.and(right))
+                {
+                    node = node.or(words);
+                }
+            }
+            _ => {}
+        }
 
         if !adjacent.is_empty() {
             match adjacent
                 .into_iter()
                 .flat_map(|compound| {
-                    TextFieldEnum::all()
-                        .filter(|f| f.is_searchable())
+                    node::searchable_fields(fields)
                         .filter(|f| f.is_compound_searchable())
                         .map(move |field| {
                             let compound_text: String = compound
@@ -73,6 +73,15 @@ impl Node {
     }
 }
 
+/// The fields plain terms and phrases are searched in, optionally restricted to `fields`.
+pub(super) fn searchable_fields(
+    fields: Option<&[TextFieldEnum]>,
+) -> impl Iterator<Item = TextFieldEnum> + '_ {
+    TextFieldEnum::all()
+        .filter(|f| f.is_searchable())
+        .filter(move |f| fields.map_or(true, |fields| fields.contains(f)))
+}
+
 impl Node {
     fn into_non_compacted_query(self) -> super::Query {
         match self {
@@ -103,10 +112,15 @@ impl Node {
     }
 
     pub fn from_term(term: ParserTerm) -> Self {
+        Self::from_term_in_fields(term, None)
+    }
+
+    /// Like [`Node::from_term`], but plain terms and phrases only match in `fields`.
+    /// Terms for a specific field like `intitle:` are not restricted.
+    pub fn from_term_in_fields(term: ParserTerm, fields: Option<&[TextFieldEnum]>) -> Self {
         match term {
             ParserTerm::SimpleOrPhrase(s) => match s {
-                SimpleOrPhrase::Simple(term) => TextFieldEnum::all()
-                    .filter(|f| f.is_searchable())
+                SimpleOrPhrase::Simple(term) => searchable_fields(fields)
                     .map(|field| {
                         Node::Term(Term {
                             text: SimpleOrPhrase::Simple(term.clone()),
@@ -115,17 +129,28 @@ impl Node {
                     })
                     .reduce(|left, right| left.or(right))
                     .expect("fields should not be empty"),
-                SimpleOrPhrase::Phrase(p) => TextFieldEnum::all()
-                    .filter(|f| f.is_searchable())
-                    .filter(|f| f.is_phrase_searchable())
-                    .map(|field| {
-                        Node::Term(Term {
-                            text: SimpleOrPhrase::Phrase(p.clone()),
-                            field,
+                SimpleOrPhrase::Phrase(p) => {
+                    match searchable_fields(fields)
+                        .filter(|f| f.is_phrase_searchable())
+                        .map(|field| {
+                            Node::Term(Term {
+                                text: SimpleOrPhrase::Phrase(p.clone()),
+                                field,
+                            })
                         })
-                    })
-                    .reduce(|left, right| left.or(right))
-                    .expect("fields should not be empty"),
+                        .reduce(|left, right| left.or(right))
+                    {
+                        Some(node) => node,
+                        // none of the fields have positions, so the words
+                        // of the phrase must all match instead
+                        None => Node::from_term_in_fields(
+                            ParserTerm::SimpleOrPhrase(SimpleOrPhrase::Simple(SimpleTerm::from(
+                                p.join(" "),
+                            ))),
+                            fields,
+                        ),
+                    }
+                }
             },
             ParserTerm::Site(s) => Node::Term(Term {
                 text: SimpleOrPhrase::Simple(SimpleTerm::from(s)),
@@ -154,8 +179,7 @@ impl Node {
 
                 let s = SimpleTerm::from(s);
 
-                TextFieldEnum::all()
-                    .filter(|f| f.is_searchable())
+                searchable_fields(fields)
                     .map(|field| {
                         Node::Term(Term {
                             text: SimpleOrPhrase::Simple(s.clone()),
@@ -165,7 +189,7 @@ impl Node {
                     .reduce(|left, right| left.or(right))
                     .expect("fields should not be empty")
             }
-            ParserTerm::Not(n) => Node::Not(Box::new(Node::from_term(*n))),
+            ParserTerm::Not(n) => Node::Not(Box::new(Node::from_term_in_fields(*n, fields))),
         }
     }
 
@@ -0,0 +1,297 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Segmentation of query terms that are written without spaces.
+//!
+//! A plain term that looks like a domain, like `openai.com/pricing`, also matches the
+//! pages of its host, and a query of only such a term suggests a redirect to the url if
+//! the host has a homepage in the index. A plain term of only letters, like `rustasyncbook`,
+//! is split into the most likely sequence of words of the dictionary, so it also matches
+//! `rust async book`. The dictionary is the words of the body of the indexed pages
+//! weighted by the number of pages they are in.
+//!
+//! The original term is still searched for, and the host or words it is split into
+//! contribute to the text signals with a lower weight than the term. Phrases and
+//! verbatim queries are not segmented.
+
+use std::collections::HashMap;
+
+use url::Url;
+
+use crate::{
+    inverted_index::InvertedIndex,
+    schema::text_field::{self, TextField},
+    webpage::url_ext::UrlExt,
+    Result,
+};
+
+/// Shorter terms are not split, as they are mostly words or abbreviations.
+const MIN_SEGMENTED_CHARS: usize = 6;
+const MIN_WORD_CHARS: usize = 2;
+const MAX_WORD_CHARS: usize = 24;
+/// Subtracted from the log probability of a segmentation for each of its words. Splitting a
+/// word into more common shorter words, like `together` into `to get her`, must be much more
+/// likely than the word itself.
+const WORD_PENALTY: f64 = 2.0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segmentation {
+    /// The term names a page of the host.
+    Domain(Url),
+    Words(Vec<String>),
+}
+
+impl Segmentation {
+    /// The terms the segmented term is expanded with.
+    pub fn terms(&self) -> Vec<String> {
+        match self {
+            Segmentation::Domain(url) => url
+                .normalized_host()
+                .map(|host| vec![host.to_string()])
+                .unwrap_or_default(),
+            Segmentation::Words(words) => words.clone(),
+        }
+    }
+}
+
+fn is_word(word: &str) -> bool {
+    (MIN_WORD_CHARS..=MAX_WORD_CHARS).contains(&word.len())
+        && word.chars().all(|c| c.is_ascii_lowercase())
+}
+
+/// The url the term names if it is a host with a known top level domain,
+/// optionally with a scheme and followed by a path.
+pub fn domain(term: &str) -> Option<Url> {
+    if !term.contains('.') || term.contains('@') {
+        return None;
+    }
+
+    let url = if term.starts_with("http://") || term.starts_with("https://") {
+        Url::parse(term)
+    } else {
+        Url::parse(&format!("https://{term}"))
+    }
+    .ok()?;
+
+    if !matches!(url.host(), Some(url::Host::Domain(_))) || !url.has_known_tld() {
+        return None;
+    }
+
+    // the host must be more than a suffix like `co.uk`
+    url.icann_domain()?;
+
+    Some(url)
+}
+
+#[derive(Debug, Clone)]
+pub struct Segmenter {
+    words: HashMap<String, u64>,
+    total: u64,
+    boost: f32,
+}
+
+impl Segmenter {
+    /// The words of the dictionary and how often they occur.
+    pub fn new<I, W>(words: I, boost: f32) -> Self
+    where
+        I: IntoIterator<Item = (W, u64)>,
+        W: AsRef<str>,
+    {
+        let mut dictionary: HashMap<String, u64> = HashMap::new();
+
+        for (word, freq) in words {
+            let word = word.as_ref().trim().to_lowercase();
+
+            if freq > 0 && is_word(&word) {
+                *dictionary.entry(word).or_default() += freq;
+            }
+        }
+
+        let total = dictionary.values().sum::<u64>().max(1);
+
+        Self {
+            words: dictionary,
+            total,
+            boost,
+        }
+    }
+
+    /// Build the dictionary from the terms of the body of the index. The words that are in
+    /// fewer than `min_doc_freq` documents are left out.
+    pub fn from_index(index: &InvertedIndex, min_doc_freq: u64, boost: f32) -> Result<Self> {
+        let searcher = index.tv_searcher();
+        let mut freqs: HashMap<String, u64> = HashMap::new();
+
+        if let Some(field) = text_field::CleanBody.tantivy_field(searcher.schema()) {
+            for segment in searcher.segment_readers() {
+                let inverted_index = segment.inverted_index(field)?;
+                let mut stream = inverted_index.terms().stream()?;
+
+                while stream.advance() {
+                    if let Ok(word) = std::str::from_utf8(stream.key()) {
+                        if is_word(word) {
+                            *freqs.entry(word.to_string()).or_default() +=
+                                stream.value().doc_freq as u64;
+                        }
+                    }
+                }
+            }
+        }
+
+        freqs.retain(|_, freq| *freq >= min_doc_freq);
+
+        Ok(Self::new(freqs, boost))
+    }
+
+    /// The weight of the terms of a segmentation relative to the term it is from.
+    pub fn boost(&self) -> f32 {
+        self.boost
+    }
+
+    fn log_prob(&self, word: &str) -> Option<f64> {
+        self.words
+            .get(word)
+            .map(|freq| (*freq as f64 / self.total as f64).ln())
+    }
+
+    /// Split the term into the sequence of words of the dictionary with the highest
+    /// probability, where each word is penalized by [`WORD_PENALTY`]. Terms that are more
+    /// likely a single word are not split.
+    pub fn words(&self, term: &str) -> Option<Vec<String>> {
+        let term = term.to_lowercase();
+
+        if term.len() < MIN_SEGMENTED_CHARS || !term.chars().all(|c| c.is_ascii_lowercase()) {
+            return None;
+        }
+
+        // the log probability of the best segmentation of `term[..i]`
+        // and where the last word of it starts
+        let mut best: Vec<Option<(f64, usize)>> = vec![None; term.len() + 1];
+        best[0] = Some((0.0, 0));
+
+        for end in MIN_WORD_CHARS..=term.len() {
+            for start in end.saturating_sub(MAX_WORD_CHARS)..=end - MIN_WORD_CHARS {
+                let (Some((prefix, _)), Some(word)) =
+                    (best[start], self.log_prob(&term[start..end]))
+                else {
+                    continue;
+                };
+
+                let score = prefix + word - WORD_PENALTY;
+
+                if best[end].map_or(true, |(best_score, _)| score > best_score) {
+                    best[end] = Some((score, start));
+                }
+            }
+        }
+
+        let mut words = Vec::new();
+        let mut end = term.len();
+
+        while end > 0 {
+            let (_, start) = best[end]?;
+            words.push(term[start..end].to_string());
+            end = start;
+        }
+
+        words.reverse();
+
+        (words.len() > 1).then_some(words)
+    }
+
+    pub fn segment(&self, term: &str) -> Option<Segmentation> {
+        domain(term)
+            .map(Segmentation::Domain)
+            .or_else(|| self.words(term).map(Segmentation::Words))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segmenter() -> Segmenter {
+        Segmenter::new(
+            [
+                ("rust", 50),
+                ("async", 20),
+                ("book", 80),
+                ("bookstore", 30),
+                ("store", 60),
+                ("the", 1000),
+            ],
+            0.5,
+        )
+    }
+
+    #[test]
+    fn concatenated_words_are_split() {
+        let segmenter = segmenter();
+
+        assert_eq!(
+            segmenter.words("RustAsyncBook"),
+            Some(vec![
+                "rust".to_string(),
+                "async".to_string(),
+                "book".to_string()
+            ])
+        );
+        assert_eq!(
+            segmenter.words("thebookstore"),
+            Some(vec!["the".to_string(), "bookstore".to_string()])
+        );
+
+        assert_eq!(segmenter.words("bookstore"), None);
+        assert_eq!(segmenter.words("rustxbook"), None);
+        assert_eq!(segmenter.words("rust-book"), None);
+        assert_eq!(segmenter.words("rust"), None);
+    }
+
+    #[test]
+    fn words_are_not_split_into_common_words() {
+        let segmenter = Segmenter::new(
+            [("to", 1000), ("get", 300), ("her", 300), ("together", 20)],
+            0.5,
+        );
+
+        // the three words are more likely than the word without the penalty
+        let log_prob = |word: &str| segmenter.log_prob(word).unwrap();
+        assert!(log_prob("to") + log_prob("get") + log_prob("her") > log_prob("together"));
+
+        assert_eq!(segmenter.words("together"), None);
+        assert_eq!(
+            segmenter.words("togetherto"),
+            Some(vec!["together".to_string(), "to".to_string()])
+        );
+    }
+
+    #[test]
+    fn domains() {
+        let url = domain("openai.com/pricing").unwrap();
+        assert_eq!(url.host_str(), Some("openai.com"));
+        assert_eq!(url.path(), "/pricing");
+
+        assert_eq!(
+            segmenter().segment("www.example.co.uk").unwrap().terms(),
+            vec!["example.co.uk".to_string()]
+        );
+
+        assert!(domain("node.js").is_none());
+        assert!(domain("3.14").is_none());
+        assert!(domain("me@example.com").is_none());
+        assert!(domain("example").is_none());
+    }
+}
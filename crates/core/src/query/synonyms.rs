@@ -0,0 +1,123 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Expansion of query terms with their synonyms.
+//!
+//! A term of the query with synonyms matches documents with either the term or one of
+//! the synonyms, so `car` is searched as `car OR automobile`. The synonyms contribute
+//! to the text signals of the ranking with a lower weight than the terms of the query.
+//! Only plain terms are expanded, so phrases and terms for a specific field like
+//! `intitle:` are searched as they are written.
+
+use std::{collections::HashMap, path::Path};
+
+use itertools::Itertools;
+
+use crate::Result;
+
+#[derive(Debug, Clone)]
+pub struct SynonymExpander {
+    synonyms: HashMap<String, Vec<String>>,
+    boost: f32,
+}
+
+impl SynonymExpander {
+    /// Every word in a group is a synonym of the other words in the group.
+    pub fn new<G, W>(groups: G, boost: f32) -> Self
+    where
+        G: IntoIterator<Item = W>,
+        W: IntoIterator,
+        W::Item: AsRef<str>,
+    {
+        let mut synonyms: HashMap<String, Vec<String>> = HashMap::new();
+
+        for group in groups {
+            let group: Vec<String> = group
+                .into_iter()
+                .map(|word| word.as_ref().trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .unique()
+                .collect();
+
+            for word in &group {
+                let entry = synonyms.entry(word.clone()).or_default();
+
+                for synonym in &group {
+                    if synonym != word && !entry.contains(synonym) {
+                        entry.push(synonym.clone());
+                    }
+                }
+            }
+        }
+
+        synonyms.retain(|_, synonyms| !synonyms.is_empty());
+
+        Self { synonyms, boost }
+    }
+
+    /// Read the synonyms from a file with a comma separated group of synonyms
+    /// on each line. Empty lines and lines starting with `#` are ignored.
+    pub fn open<P: AsRef<Path>>(path: P, boost: f32) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+
+        let groups = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.split(',').collect::<Vec<_>>());
+
+        Ok(Self::new(groups, boost))
+    }
+
+    pub fn synonyms(&self, term: &str) -> &[String] {
+        self.synonyms
+            .get(&term.to_lowercase())
+            .map(|synonyms| synonyms.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The weight of a synonym relative to the term it expands.
+    pub fn boost(&self) -> f32 {
+        self.boost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_are_symmetric() {
+        let path = crate::gen_temp_path().join("synonyms.txt");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(
+            &path,
+            "# vehicles\ncar, automobile, Auto\n\ncar, motorcar\nsingle\n",
+        )
+        .unwrap();
+
+        let expander = SynonymExpander::open(&path, 0.5).unwrap();
+
+        assert_eq!(
+            expander.synonyms("Car"),
+            &["automobile", "auto", "motorcar"]
+        );
+        assert_eq!(expander.synonyms("auto"), &["car", "automobile"]);
+        assert!(expander.synonyms("single").is_empty());
+        assert!(expander.synonyms("vehicles").is_empty());
+        assert_eq!(expander.boost(), 0.5);
+    }
+}
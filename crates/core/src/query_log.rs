@@ -0,0 +1,304 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Structured events of the queries that a search server answers, for analytics.
+//!
+//! The queries of the users are redacted before the events are written to the sink, so
+//! unless the [`QueryRedaction::Raw`] mode is configured, what was searched for is never
+//! stored. The hashed mode logs an HMAC-SHA256 of the query keyed by a secret instead,
+//! which is enough to count how often the same query is searched for, and the token count
+//! mode only logs the number of terms of the query.
+//!
+//! The events are written to the sink by a thread of the logger, so the search
+//! handlers never wait for the sink. Events are dropped if the thread falls behind.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    sync::Mutex,
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::bail;
+use ring::hmac;
+
+use crate::{
+    collector::approx_count::Count,
+    config::{QueryLogConfig, QueryLogSink, QueryRedaction},
+    distributed::member::ShardId,
+    searcher::{InitialWebsiteResult, SearchQuery},
+    Result,
+};
+
+/// Where the events are written to, one json object per line.
+pub trait Sink: Send + Sync {
+    fn write_line(&self, line: &str) -> Result<()>;
+}
+
+pub struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn write_line(&self, line: &str) -> Result<()> {
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout, "{line}")?;
+
+        Ok(())
+    }
+}
+
+pub struct FileSink {
+    file: Mutex<File>,
+}
+
+impl FileSink {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl Sink for FileSink {
+    fn write_line(&self, line: &str) -> Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+
+        // the line is written in one call so lines of concurrent queries don't interleave
+        file.write_all(format!("{line}\n").as_bytes())?;
+
+        Ok(())
+    }
+}
+
+/// Number of events that can wait to be written before new events are dropped.
+const QUEUE_SIZE: usize = 4_096;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct QueryEvent {
+    pub timestamp_ms: u64,
+    pub shard: u64,
+    /// The query if the redaction mode is [`QueryRedaction::Raw`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// The HMAC of the query if the redaction mode is [`QueryRedaction::Hashed`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_hash: Option<String>,
+    pub num_tokens: usize,
+    pub page: usize,
+    /// The number of matching pages on the shard, or `None` if the search failed.
+    pub num_results: Option<u64>,
+    pub latency_ms: u64,
+}
+
+pub struct QueryLogger {
+    redaction: QueryRedaction,
+    key: hmac::Key,
+    lines: Option<crossbeam_channel::Sender<String>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl QueryLogger {
+    pub fn open(config: &QueryLogConfig) -> Result<Self> {
+        let sink: Box<dyn Sink> = match &config.sink {
+            QueryLogSink::Stdout => Box::new(StdoutSink),
+            QueryLogSink::File { path } => Box::new(FileSink::open(path)?),
+        };
+
+        Self::new(config.redaction, &config.secret, sink)
+    }
+
+    /// The hashed mode needs a non-empty secret, as the hashes of common queries
+    /// could otherwise be looked up in a table of precomputed hashes.
+    pub fn new(redaction: QueryRedaction, secret: &str, sink: Box<dyn Sink>) -> Result<Self> {
+        if redaction == QueryRedaction::Hashed && secret.is_empty() {
+            bail!("the hashed query log needs a secret");
+        }
+
+        let (tx, rx) = crossbeam_channel::bounded::<String>(QUEUE_SIZE);
+
+        let writer = std::thread::Builder::new()
+            .name("query-log".to_string())
+            .spawn(move || {
+                for line in rx {
+                    if let Err(err) = sink.write_line(&line) {
+                        tracing::error!("failed to log query: {:?}", err);
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            redaction,
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+            lines: Some(tx),
+            writer: Some(writer),
+        })
+    }
+
+    fn hash(&self, query: &str) -> String {
+        hmac::sign(&self.key, query.as_bytes())
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    pub fn event(
+        &self,
+        shard: ShardId,
+        query: &SearchQuery,
+        result: Option<&InitialWebsiteResult>,
+        latency: Duration,
+    ) -> QueryEvent {
+        let (raw, query_hash) = match self.redaction {
+            QueryRedaction::Raw => (Some(query.query.clone()), None),
+            QueryRedaction::Hashed => (None, Some(self.hash(&query.query))),
+            QueryRedaction::TokenCount => (None, None),
+        };
+
+        QueryEvent {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            shard: shard.as_u64(),
+            query: raw,
+            query_hash,
+            num_tokens: query.query.split_whitespace().count(),
+            page: query.page,
+            num_results: result.map(|result| match result.num_websites {
+                Count::Exact(count) | Count::Approximate(count) => count,
+            }),
+            latency_ms: latency.as_millis() as u64,
+        }
+    }
+
+    /// Queue the event to be written to the sink.
+    pub fn log(&self, event: &QueryEvent) {
+        let Some(lines) = &self.lines else {
+            return;
+        };
+
+        match serde_json::to_string(event) {
+            Ok(line) => {
+                if lines.try_send(line).is_err() {
+                    tracing::warn!("query log is full, dropping event");
+                }
+            }
+            Err(err) => tracing::error!("failed to log query: {:?}", err),
+        }
+    }
+}
+
+impl Drop for QueryLogger {
+    /// Write the queued events before the logger is gone.
+    fn drop(&mut self) {
+        self.lines.take();
+
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[derive(Default, Clone)]
+    struct MemorySink {
+        lines: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Sink for MemorySink {
+        fn write_line(&self, line: &str) -> Result<()> {
+            self.lines.lock().unwrap().push(line.to_string());
+            Ok(())
+        }
+    }
+
+    fn log(redaction: QueryRedaction, query: &str) -> (QueryEvent, String) {
+        let sink = MemorySink::default();
+        let logger = QueryLogger::new(redaction, "secret", Box::new(sink.clone())).unwrap();
+
+        let query = SearchQuery {
+            query: query.to_string(),
+            ..Default::default()
+        };
+        let event = logger.event(ShardId::new(1), &query, None, Duration::from_millis(12));
+        logger.log(&event);
+        drop(logger);
+
+        let lines = sink.lines.lock().unwrap().clone();
+        assert_eq!(lines.len(), 1);
+
+        (event, lines[0].clone())
+    }
+
+    #[test]
+    fn hashed_query_is_not_logged() {
+        let query = "symptoms of rare disease";
+        let (event, line) = log(QueryRedaction::Hashed, query);
+
+        assert!(!line.contains(query));
+        for term in query.split_whitespace() {
+            assert!(!line.contains(term));
+        }
+
+        assert_eq!(event.query, None);
+        assert_eq!(event.num_tokens, 4);
+        assert_eq!(event.latency_ms, 12);
+        assert_eq!(serde_json::from_str::<QueryEvent>(&line).unwrap(), event);
+
+        // the same query has the same hash, so it can still be counted
+        assert_eq!(
+            log(QueryRedaction::Hashed, query).0.query_hash,
+            event.query_hash
+        );
+        assert_ne!(
+            log(QueryRedaction::Hashed, "another query").0.query_hash,
+            event.query_hash
+        );
+
+        assert_eq!(event.query_hash.unwrap().len(), 64);
+    }
+
+    #[test]
+    fn hashed_mode_needs_a_secret() {
+        let sink = || Box::new(MemorySink::default());
+
+        assert!(QueryLogger::new(QueryRedaction::Hashed, "", sink()).is_err());
+        assert!(QueryLogger::new(QueryRedaction::TokenCount, "", sink()).is_ok());
+    }
+
+    #[test]
+    fn redaction_modes() {
+        let query = "symptoms of rare disease";
+
+        let (event, line) = log(QueryRedaction::TokenCount, query);
+        assert!(!line.contains("symptoms"));
+        assert_eq!(event.query, None);
+        assert_eq!(event.query_hash, None);
+        assert_eq!(event.num_tokens, 4);
+
+        let (event, line) = log(QueryRedaction::Raw, query);
+        assert!(line.contains(query));
+        assert_eq!(event.query.as_deref(), Some(query));
+    }
+}
@@ -105,6 +105,43 @@ impl Posting {
 
         count
     }
+
+    /// The weighted jaccard similarity `sum(min(a, b)) / sum(max(a, b))` of the weights of
+    /// the ranks, where a rank that is not in a posting has weight 0.
+    fn weighted_jaccard(&self, weights: &[f64], other: &Self, other_weights: &[f64]) -> f64 {
+        let mut i = 0;
+        let mut j = 0;
+
+        let mut min_sum = 0.0;
+        let mut max_sum = 0.0;
+
+        while i < self.ranks.len() && j < other.ranks.len() {
+            match self.ranks[i].cmp(&other.ranks[j]) {
+                std::cmp::Ordering::Equal => {
+                    min_sum += weights[i].min(other_weights[j]);
+                    max_sum += weights[i].max(other_weights[j]);
+                    i += 1;
+                    j += 1;
+                }
+                std::cmp::Ordering::Less => {
+                    max_sum += weights[i];
+                    i += 1;
+                }
+                std::cmp::Ordering::Greater => {
+                    max_sum += other_weights[j];
+                    j += 1;
+                }
+            }
+        }
+
+        max_sum += weights[i..].iter().sum::<f64>() + other_weights[j..].iter().sum::<f64>();
+
+        if max_sum == 0.0 {
+            0.0
+        } else {
+            min_sum / max_sum
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Debug, Clone)]
@@ -112,6 +149,9 @@ pub struct BitVec {
     bloom: VeryJankyBloomFilter,
     posting: Posting,
     sqrt_len: f64,
+    link_weight: f64,
+    /// The weights of the ranks of the posting, if the vector is weighted.
+    weights: Option<Vec<f64>>,
 }
 
 impl Default for BitVec {
@@ -137,6 +177,28 @@ impl BitVec {
             .collect()
     }
 
+    /// Build weighted vectors where each ingoing node has the weight given by `weight`,
+    /// e.g. its centrality.
+    pub async fn batch_new_weighted_for<G, F>(nodes: &[NodeID], graph: &G, weight: F) -> Vec<Self>
+    where
+        G: Graph,
+        F: Fn(&NodeID) -> f64,
+    {
+        let ingoing = graph.batch_ingoing(nodes).await;
+
+        ingoing
+            .into_iter()
+            .map(|nodes| {
+                Self::weighted(
+                    nodes
+                        .into_iter()
+                        .map(|n| (n.as_u64(), weight(&n)))
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
     pub fn new(mut ranks: Vec<u64>) -> Self {
         ranks.sort();
         ranks.dedup();
@@ -155,14 +217,57 @@ impl BitVec {
             bloom,
             posting,
             sqrt_len: (len as f64).sqrt(),
+            link_weight: 1.0,
+            weights: None,
         }
     }
 
+    /// A vector where each rank has a weight. The similarity of two weighted vectors is
+    /// their weighted jaccard similarity, so the shared ranks with high weights count more
+    /// than the ones with low weights. Negative weights are treated as 0, and the highest
+    /// weight is kept for a rank that is given more than once.
+    pub fn weighted(mut ranks: Vec<(u64, f64)>) -> Self {
+        ranks.sort_by(|(a, a_weight), (b, b_weight)| a.cmp(b).then(b_weight.total_cmp(a_weight)));
+        ranks.dedup_by_key(|(rank, _)| *rank);
+
+        let (ranks, weights): (Vec<_>, Vec<_>) = ranks
+            .into_iter()
+            .map(|(rank, weight)| (rank, weight.max(0.0)))
+            .unzip();
+
+        let mut vec = Self::new(ranks);
+        vec.weights = Some(weights);
+        vec
+    }
+
+    pub fn is_weighted(&self) -> bool {
+        self.weights.is_some()
+    }
+
+    /// Scale the similarities to the vector by the weight of the links it was built from,
+    /// e.g. the mean weight of their rel flags.
+    pub fn with_link_weight(mut self, link_weight: f64) -> Self {
+        self.link_weight = link_weight.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn link_weight(&self) -> f64 {
+        self.link_weight
+    }
+
+    /// The similarity of the vectors. This is the weighted jaccard similarity if both
+    /// vectors are weighted, and otherwise the cosine similarity of the bits.
     pub fn sim(&self, other: &Self) -> f64 {
         if self.sqrt_len == 0.0 || other.sqrt_len == 0.0 {
             return 0.0;
         }
 
+        if let (Some(weights), Some(other_weights)) = (&self.weights, &other.weights) {
+            return self
+                .posting
+                .weighted_jaccard(weights, &other.posting, other_weights);
+        }
+
         let max_bloom_ones = self.bloom.ones().max(other.bloom.ones());
         let intersect_bloom_ones = self.bloom.intersect_ones(&other.bloom);
 
@@ -260,6 +365,35 @@ mod tests {
         assert_eq!(sim, 0.0);
     }
 
+    #[test]
+    fn weighted_jaccard() {
+        let a = BitVec::weighted(vec![(1, 4.0), (2, 1.0), (3, 1.0)]);
+        let b = BitVec::weighted(vec![(1, 2.0), (3, 1.0), (4, 2.0)]);
+
+        // min: 2 + 1, max: 4 + 1 + 1 + 2
+        assert!((a.sim(&b) - 3.0 / 8.0).abs() < 1e-9);
+        assert_eq!(a.sim(&b), b.sim(&a));
+        assert!((a.sim(&a) - 1.0).abs() < 1e-9);
+
+        // a shared neighbor with a high weight makes the vectors more similar
+        let hub = BitVec::weighted(vec![(1, 10.0), (5, 1.0)]);
+        let leaf = BitVec::weighted(vec![(2, 1.0), (5, 1.0)]);
+        let liked = BitVec::weighted(vec![(1, 10.0), (2, 1.0)]);
+        assert!(liked.sim(&hub) > liked.sim(&leaf));
+
+        // unweighted if either vector is
+        let unweighted = BitVec::new(vec![1, 3, 4]);
+        assert_eq!(
+            a.sim(&unweighted),
+            BitVec::new(vec![1, 2, 3]).sim(&unweighted)
+        );
+
+        assert_eq!(
+            BitVec::weighted(vec![(1, 0.0)]).sim(&BitVec::weighted(vec![(1, 0.0)])),
+            0.0
+        );
+    }
+
     #[test]
     fn low_sim() {
         let a: Vec<_> = repeat(false)
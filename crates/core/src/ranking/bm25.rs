@@ -93,6 +93,15 @@ impl MultiBm25Weight {
         Ok(Self { weights })
     }
 
+    /// Scale the weight of each term by its boost.
+    pub fn with_boosts(mut self, boosts: &[Score]) -> Self {
+        for (weight, boost) in self.weights.iter_mut().zip_eq(boosts) {
+            weight.weight *= boost;
+        }
+
+        self
+    }
+
     #[inline]
     pub fn score(&self, stats: impl Iterator<Item = (u8, u32)>) -> Score {
         stats
@@ -91,6 +91,15 @@ impl MultiBm25FWeight {
         Self { weights }
     }
 
+    /// Scale the weight of each term by its boost.
+    pub fn with_boosts(mut self, boosts: &[Score]) -> Self {
+        for (weight, boost) in self.weights.iter_mut().zip_eq(boosts) {
+            weight.weight *= boost;
+        }
+
+        self
+    }
+
     #[inline]
     pub fn score(&self, coefficient: Score, stats: impl Iterator<Item = (u8, u32)>) -> Score {
         stats
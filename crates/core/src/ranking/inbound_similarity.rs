@@ -14,24 +14,148 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use fnv::FnvHashMap as HashMap;
+use std::{collections::HashSet, num::NonZeroUsize};
+
 use itertools::Itertools;
+use lru::LruCache;
+use optics::HostRankings;
 
-use crate::webgraph::NodeID;
+use crate::{
+    config::RelFlagWeights,
+    webgraph::{Edge, Node, NodeID},
+};
 
 use super::bitvec_similarity;
 
+/// The inbound vector of a node from its ingoing edges. The similarities to the node
+/// are scaled by the mean weight of the rel flags of the edges, so a node that is
+/// mostly linked with e.g. nofollow links is less similar to the liked hosts.
+pub fn inbound_vec(edges: &[Edge<()>], weights: &RelFlagWeights) -> bitvec_similarity::BitVec {
+    bitvec_similarity::BitVec::new(edges.iter().map(|edge| edge.from.as_u64()).collect())
+        .with_link_weight(link_weight(edges, weights))
+}
+
+/// Like [`inbound_vec`], but each linking node is weighted by its centrality.
+/// The vector is only weighted in its similarity to other weighted vectors, like the
+/// ones of a scorer from [`Scorer::new_weighted`].
+pub fn weighted_inbound_vec<F>(
+    edges: &[Edge<()>],
+    weights: &RelFlagWeights,
+    centrality: F,
+) -> bitvec_similarity::BitVec
+where
+    F: Fn(&NodeID) -> f64,
+{
+    bitvec_similarity::BitVec::weighted(
+        edges
+            .iter()
+            .map(|edge| (edge.from.as_u64(), centrality(&edge.from)))
+            .collect(),
+    )
+    .with_link_weight(link_weight(edges, weights))
+}
+
+fn link_weight(edges: &[Edge<()>], weights: &RelFlagWeights) -> f64 {
+    if edges.is_empty() {
+        1.0
+    } else {
+        edges
+            .iter()
+            .map(|edge| weights.weight(edge.rel))
+            .sum::<f64>()
+            / edges.len() as f64
+    }
+}
+
+/// A liked or disliked host of a scorer and how much its similarity counts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Seed {
+    pub node: NodeID,
+    pub weight: f64,
+}
+
+/// The liked and disliked hosts of a scorer.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Seeds {
+    pub liked: Vec<Seed>,
+    pub disliked: Vec<Seed>,
+}
+
+impl Seeds {
+    /// Seeds where all the hosts count the same.
+    pub fn new(liked: &[NodeID], disliked: &[NodeID]) -> Self {
+        let seeds = |nodes: &[NodeID]| {
+            nodes
+                .iter()
+                .map(|node| Seed {
+                    node: *node,
+                    weight: 1.0,
+                })
+                .collect()
+        };
+
+        Self {
+            liked: seeds(liked),
+            disliked: seeds(disliked),
+        }
+    }
+
+    /// The union of the host rankings of the user and the ones declared by the optic,
+    /// where the hosts of the optic have a weight of `optic_weight` and the hosts of the
+    /// user a weight of 1. A host that is ranked by both keeps the ranking of the user,
+    /// so a host the user likes is never disliked because the optic dislikes it.
+    pub fn merge(user: &HostRankings, optic: &HostRankings, optic_weight: f64) -> Self {
+        let mut seeds = Self::default();
+        let mut seen = HashSet::new();
+
+        for (rankings, weight) in [(user, 1.0), (optic, optic_weight.max(0.0))] {
+            let ranked = rankings
+                .liked
+                .iter()
+                .map(|host| (host, true))
+                .chain(rankings.disliked.iter().map(|host| (host, false)));
+
+            for (host, liked) in ranked {
+                let node = Node::from(host.as_str()).into_host().id();
+
+                if !seen.insert(node) {
+                    continue;
+                }
+
+                let seed = Seed { node, weight };
+                if liked {
+                    seeds.liked.push(seed);
+                } else {
+                    seeds.disliked.push(seed);
+                }
+            }
+        }
+
+        seeds
+    }
+
+    fn liked_nodes(&self) -> Vec<NodeID> {
+        self.liked.iter().map(|seed| seed.node).collect()
+    }
+
+    fn disliked_nodes(&self) -> Vec<NodeID> {
+        self.disliked.iter().map(|seed| seed.node).collect()
+    }
+}
+
 #[derive(Clone)]
 struct NodeScorer {
     node: NodeID,
+    weight: f64,
     inbound: bitvec_similarity::BitVec,
     self_score: f64,
 }
 
 impl NodeScorer {
-    fn new(node: NodeID, inbound: bitvec_similarity::BitVec) -> Self {
+    fn new(seed: &Seed, inbound: bitvec_similarity::BitVec) -> Self {
         Self {
-            node,
+            node: seed.node,
+            weight: seed.weight,
             inbound,
             self_score: 1.0,
         }
@@ -45,16 +169,21 @@ impl NodeScorer {
         if self.node == *other {
             self.self_score
         } else {
-            self.inbound.sim(other_inbound)
+            self.inbound.sim(other_inbound) * other_inbound.link_weight()
         }
     }
 }
 
+/// Number of scored nodes to keep in the cache if nothing else is specified.
+pub const DEFAULT_CACHE_CAPACITY: usize = 1_024;
+
 #[derive(Clone)]
 pub struct Scorer {
     liked: Vec<NodeScorer>,
     disliked: Vec<NodeScorer>,
-    cache: HashMap<NodeID, f64>,
+    cache: LruCache<NodeID, f64>,
+    cache_hits: u64,
+    cache_misses: u64,
     normalized: bool,
 }
 
@@ -63,54 +192,157 @@ impl Scorer {
         Self {
             liked: Vec::new(),
             disliked: Vec::new(),
-            cache: HashMap::default(),
+            cache: LruCache::new(Self::cache_capacity(DEFAULT_CACHE_CAPACITY)),
+            cache_hits: 0,
+            cache_misses: 0,
             normalized: false,
         }
     }
 
+    /// Create a scorer for the liked and disliked hosts.
+    ///
+    /// At most `cache_capacity` scored nodes are cached. The least recently
+    /// used nodes are evicted once the cache is full, which only means they
+    /// will be re-scored if they are seen again.
     pub async fn new<G: bitvec_similarity::Graph>(
         graph: &G,
         liked_hosts: &[NodeID],
         disliked_hosts: &[NodeID],
         normalized: bool,
+        cache_capacity: usize,
     ) -> Scorer {
-        let liked = bitvec_similarity::BitVec::batch_new_for(liked_hosts, graph).await;
-        let disliked = bitvec_similarity::BitVec::batch_new_for(disliked_hosts, graph).await;
+        Self::with_seeds(
+            graph,
+            &Seeds::new(liked_hosts, disliked_hosts),
+            normalized,
+            cache_capacity,
+        )
+        .await
+    }
 
-        let liked: Vec<_> = liked_hosts
+    /// Like [`Scorer::new`], but the similarity to each of the liked and disliked hosts
+    /// is multiplied by the weight of its seed.
+    pub async fn with_seeds<G: bitvec_similarity::Graph>(
+        graph: &G,
+        seeds: &Seeds,
+        normalized: bool,
+        cache_capacity: usize,
+    ) -> Scorer {
+        let liked = bitvec_similarity::BitVec::batch_new_for(&seeds.liked_nodes(), graph).await;
+        let disliked =
+            bitvec_similarity::BitVec::batch_new_for(&seeds.disliked_nodes(), graph).await;
+
+        Self::from_inbound(seeds, liked, disliked, normalized, cache_capacity)
+    }
+
+    /// Like [`Scorer::new`], but the linking nodes of the liked and disliked hosts are
+    /// weighted by their centrality, so sharing a link from a central hub counts more than
+    /// sharing one from an obscure node. The scored nodes must have weighted inbound
+    /// vectors, e.g. from [`weighted_inbound_vec`] with the same centralities.
+    pub async fn new_weighted<G, F>(
+        graph: &G,
+        liked_hosts: &[NodeID],
+        disliked_hosts: &[NodeID],
+        normalized: bool,
+        cache_capacity: usize,
+        centrality: F,
+    ) -> Scorer
+    where
+        G: bitvec_similarity::Graph,
+        F: Fn(&NodeID) -> f64,
+    {
+        Self::with_weighted_seeds(
+            graph,
+            &Seeds::new(liked_hosts, disliked_hosts),
+            normalized,
+            cache_capacity,
+            centrality,
+        )
+        .await
+    }
+
+    /// Like [`Scorer::new_weighted`], but the similarity to each of the liked and disliked
+    /// hosts is multiplied by the weight of its seed.
+    pub async fn with_weighted_seeds<G, F>(
+        graph: &G,
+        seeds: &Seeds,
+        normalized: bool,
+        cache_capacity: usize,
+        centrality: F,
+    ) -> Scorer
+    where
+        G: bitvec_similarity::Graph,
+        F: Fn(&NodeID) -> f64,
+    {
+        let liked = bitvec_similarity::BitVec::batch_new_weighted_for(
+            &seeds.liked_nodes(),
+            graph,
+            &centrality,
+        )
+        .await;
+        let disliked = bitvec_similarity::BitVec::batch_new_weighted_for(
+            &seeds.disliked_nodes(),
+            graph,
+            &centrality,
+        )
+        .await;
+
+        Self::from_inbound(seeds, liked, disliked, normalized, cache_capacity)
+    }
+
+    fn from_inbound(
+        seeds: &Seeds,
+        liked: Vec<bitvec_similarity::BitVec>,
+        disliked: Vec<bitvec_similarity::BitVec>,
+        normalized: bool,
+        cache_capacity: usize,
+    ) -> Scorer {
+        let liked: Vec<_> = seeds
+            .liked
             .iter()
             .zip_eq(liked)
-            .map(|(node, inbound)| NodeScorer::new(*node, inbound))
+            .map(|(seed, inbound)| NodeScorer::new(seed, inbound))
             .collect();
 
-        let disliked: Vec<_> = disliked_hosts
+        let disliked: Vec<_> = seeds
+            .disliked
             .iter()
             .zip_eq(disliked)
-            .map(|(node, inbound)| NodeScorer::new(*node, inbound))
+            .map(|(seed, inbound)| NodeScorer::new(seed, inbound))
             .collect();
 
         Scorer {
             liked,
             disliked,
-            cache: HashMap::default(),
+            cache: LruCache::new(Self::cache_capacity(cache_capacity)),
+            cache_hits: 0,
+            cache_misses: 0,
             normalized,
         }
     }
+
+    fn cache_capacity(capacity: usize) -> NonZeroUsize {
+        NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN)
+    }
+
     fn calculate_score(&self, node: &NodeID, inbound: &bitvec_similarity::BitVec) -> f64 {
-        let s = (self.disliked.len() as f64)
+        let disliked_weight: f64 = self.disliked.iter().map(|disliked| disliked.weight).sum();
+        let liked_weight: f64 = self.liked.iter().map(|liked| liked.weight).sum();
+
+        let s = disliked_weight
             + (self
                 .liked
                 .iter()
-                .map(|liked| liked.sim(node, inbound))
+                .map(|liked| liked.weight * liked.sim(node, inbound))
                 .sum::<f64>()
                 - self
                     .disliked
                     .iter()
-                    .map(|disliked| disliked.sim(node, inbound))
+                    .map(|disliked| disliked.weight * disliked.sim(node, inbound))
                     .sum::<f64>());
 
-        if self.normalized {
-            s / self.liked.len().max(1) as f64
+        if self.normalized && liked_weight > 0.0 {
+            s / liked_weight
         } else {
             s
         }
@@ -118,14 +350,28 @@ impl Scorer {
     }
     pub fn score(&mut self, node: &NodeID, inbound: &bitvec_similarity::BitVec) -> f64 {
         if let Some(cached) = self.cache.get(node) {
+            self.cache_hits += 1;
             return *cached;
         }
 
+        self.cache_misses += 1;
         let score = self.calculate_score(node, inbound);
-        self.cache.insert(*node, score);
+        self.cache.put(*node, score);
         score
     }
 
+    pub fn cache_hits(&self) -> u64 {
+        self.cache_hits
+    }
+
+    pub fn cache_misses(&self) -> u64 {
+        self.cache_misses
+    }
+
+    pub fn cache_len(&self) -> usize {
+        self.cache.len()
+    }
+
     pub fn set_self_score(&mut self, self_score: f64) {
         for scorer in self.liked.iter_mut() {
             scorer.set_self_score(self_score);
@@ -139,17 +385,15 @@ impl Scorer {
 
 #[cfg(test)]
 mod tests {
-    use optics::HostRankings;
-
     use crate::{
         bangs::Bangs,
-        gen_temp_path,
         index::Index,
         rand_words,
         searcher::{
             api::ApiSearcher, live::LiveSearcher, LocalSearchClient, LocalSearcher, SearchQuery,
         },
-        webgraph::{EdgeLimit, Node, Webgraph, WebgraphWriter},
+        test_fixtures::GraphFixture,
+        webgraph::{EdgeLimit, Node, Webgraph},
         webpage::{html::links::RelFlags, Html, Webpage},
     };
 
@@ -165,110 +409,320 @@ mod tests {
         )
     }
 
+    fn edges(edges: &[(&str, &str)]) -> Vec<(Node, Node, String)> {
+        edges
+            .iter()
+            .map(|(from, to)| (Node::from(*from), Node::from(*to), String::new()))
+            .collect()
+    }
+
     #[tokio::test]
     async fn it_favors_liked_hosts() {
-        let mut wrt = WebgraphWriter::new(
-            gen_temp_path(),
-            crate::executor::Executor::single_thread(),
-            crate::webgraph::Compression::default(),
-            None,
-        );
+        let graph = GraphFixture::new(0)
+            .edges(edges(&[
+                ("a.com", "b.com"),
+                ("c.com", "d.com"),
+                ("a.com", "e.com"),
+                ("z.com", "a.com"),
+                ("z.com", "b.com"),
+                ("z.com", "c.com"),
+                ("z.com", "d.com"),
+                ("z.com", "e.com"),
+            ]))
+            .build()
+            .into_graph();
+
+        let mut scorer = Scorer::new(
+            &graph,
+            &[Node::from("b.com").id()],
+            &[],
+            false,
+            DEFAULT_CACHE_CAPACITY,
+        )
+        .await;
+        let e = Node::from("e.com").id();
+        let d = Node::from("d.com").id();
 
-        wrt.insert(
-            Node::from("a.com"),
-            Node::from("b.com"),
-            String::new(),
-            RelFlags::default(),
-        );
-        wrt.insert(
-            Node::from("c.com"),
-            Node::from("d.com"),
-            String::new(),
-            RelFlags::default(),
-        );
-        wrt.insert(
-            Node::from("a.com"),
-            Node::from("e.com"),
-            String::new(),
-            RelFlags::default(),
-        );
+        assert!(scorer.score(&e, &inbound(&graph, &e)) > scorer.score(&d, &inbound(&graph, &d)));
+    }
 
-        wrt.insert(
-            Node::from("z.com"),
-            Node::from("a.com"),
-            String::new(),
-            RelFlags::default(),
-        );
-        wrt.insert(
-            Node::from("z.com"),
-            Node::from("b.com"),
-            String::new(),
-            RelFlags::default(),
-        );
-        wrt.insert(
-            Node::from("z.com"),
-            Node::from("c.com"),
-            String::new(),
-            RelFlags::default(),
+    #[tokio::test]
+    async fn nofollow_links_are_less_similar() {
+        let linkers = ["x.com", "y.com", "z.com"];
+        let mut fixture = GraphFixture::new(0).edges(linkers.iter().flat_map(|linker| {
+            ["liked.com", "follow.com", "nofollow.com"]
+                .into_iter()
+                .map(move |to| (Node::from(*linker), Node::from(to), String::new()))
+        }));
+
+        for linker in linkers {
+            fixture = fixture.rel(
+                &Node::from(linker),
+                &Node::from("nofollow.com"),
+                RelFlags::NOFOLLOW,
+            );
+        }
+
+        let graph = fixture.build().into_graph();
+
+        let liked = [Node::from("liked.com").id()];
+        let follow = Node::from("follow.com").id();
+        let nofollow = Node::from("nofollow.com").id();
+
+        let score = |weights: RelFlagWeights| {
+            let graph = &graph;
+            async move {
+                let mut scorer =
+                    Scorer::new(graph, &liked, &[], false, DEFAULT_CACHE_CAPACITY).await;
+
+                [follow, nofollow].map(|node| {
+                    let edges = graph.raw_ingoing_edges(&node, EdgeLimit::Unlimited);
+                    scorer.score(&node, &inbound_vec(&edges, &weights))
+                })
+            }
+        };
+
+        let [follow_score, nofollow_score] = score(RelFlagWeights::default()).await;
+        assert!(nofollow_score > 0.0);
+        assert!(follow_score > nofollow_score);
+
+        let [follow_score, nofollow_score] = score(RelFlagWeights {
+            nofollow: 1.0,
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(follow_score, nofollow_score);
+    }
+
+    #[tokio::test]
+    async fn central_linkers_are_more_similar() {
+        // hub.com is linked by many hosts and leaf.com by none. both link to the liked host,
+        // and each of them links to one of the candidates.
+        let fixture = GraphFixture::new(0)
+            .edges(edges(&[
+                ("hub.com", "liked.com"),
+                ("leaf.com", "liked.com"),
+                ("hub.com", "a.com"),
+                ("leaf.com", "b.com"),
+            ]))
+            .edges((0..10).map(|i| {
+                (
+                    Node::from(format!("fan{i}.com")),
+                    Node::from("hub.com"),
+                    String::new(),
+                )
+            }))
+            .build();
+        let graph = fixture.graph();
+
+        let centrality = |id: &NodeID| {
+            fixture
+                .centrality_store()
+                .get(id)
+                .unwrap()
+                .unwrap_or_default()
+        };
+        assert!(centrality(&Node::from("hub.com").id()) > centrality(&Node::from("leaf.com").id()));
+
+        let liked = [Node::from("liked.com").id()];
+        let a = Node::from("a.com").id();
+        let b = Node::from("b.com").id();
+        let edges = |node: &NodeID| graph.raw_ingoing_edges(node, EdgeLimit::Unlimited);
+
+        let mut scorer = Scorer::new(graph, &liked, &[], false, DEFAULT_CACHE_CAPACITY).await;
+        assert_eq!(
+            scorer.score(&a, &inbound(graph, &a)),
+            scorer.score(&b, &inbound(graph, &b))
         );
-        wrt.insert(
-            Node::from("z.com"),
-            Node::from("d.com"),
-            String::new(),
-            RelFlags::default(),
+
+        let mut scorer = Scorer::new_weighted(
+            graph,
+            &liked,
+            &[],
+            false,
+            DEFAULT_CACHE_CAPACITY,
+            centrality,
+        )
+        .await;
+        let weights = RelFlagWeights::default();
+        let a_score = scorer.score(&a, &weighted_inbound_vec(&edges(&a), &weights, centrality));
+        let b_score = scorer.score(&b, &weighted_inbound_vec(&edges(&b), &weights, centrality));
+
+        assert!(a_score > b_score);
+    }
+
+    fn seed(host: &str, weight: f64) -> Seed {
+        Seed {
+            node: Node::from(host).into_host().id(),
+            weight,
+        }
+    }
+
+    fn rankings(liked: &[&str], disliked: &[&str]) -> HostRankings {
+        HostRankings {
+            liked: liked.iter().map(|host| host.to_string()).collect(),
+            disliked: disliked.iter().map(|host| host.to_string()).collect(),
+            blocked: vec![],
+        }
+    }
+
+    #[test]
+    fn seeds_are_union_of_rankings() {
+        let seeds = Seeds::merge(
+            &rankings(&["a.com"], &["b.com"]),
+            &rankings(&["c.com"], &["d.com"]),
+            0.5,
         );
-        wrt.insert(
-            Node::from("z.com"),
-            Node::from("e.com"),
-            String::new(),
-            RelFlags::default(),
+
+        assert_eq!(seeds.liked, vec![seed("a.com", 1.0), seed("c.com", 0.5)]);
+        assert_eq!(seeds.disliked, vec![seed("b.com", 1.0), seed("d.com", 0.5)]);
+    }
+
+    #[test]
+    fn user_rankings_win_conflicts() {
+        let seeds = Seeds::merge(
+            &rankings(&["a.com"], &["c.com"]),
+            &rankings(&["b.com", "c.com"], &["a.com"]),
+            2.0,
         );
 
-        let graph = wrt.finalize();
+        assert_eq!(seeds.liked, vec![seed("a.com", 1.0), seed("b.com", 2.0)]);
+        assert_eq!(seeds.disliked, vec![seed("c.com", 1.0)]);
+    }
 
-        let mut scorer = Scorer::new(&graph, &[Node::from("b.com").id()], &[], false).await;
-        let e = Node::from("e.com").id();
-        let d = Node::from("d.com").id();
+    #[tokio::test]
+    async fn seed_weights_shift_scores() {
+        let graph = GraphFixture::new(0)
+            .edges(edges(&[
+                ("x.com", "liked1.com"),
+                ("x.com", "a.com"),
+                ("y.com", "liked2.com"),
+                ("y.com", "b.com"),
+            ]))
+            .build()
+            .into_graph();
+
+        let a = Node::from("a.com").id();
+        let b = Node::from("b.com").id();
+
+        let scores = |weight: f64| {
+            let graph = &graph;
+            async move {
+                let seeds = Seeds {
+                    liked: vec![seed("liked1.com", 1.0), seed("liked2.com", weight)],
+                    disliked: vec![],
+                };
+                let mut scorer =
+                    Scorer::with_seeds(graph, &seeds, false, DEFAULT_CACHE_CAPACITY).await;
 
-        assert!(scorer.score(&e, &inbound(&graph, &e)) > scorer.score(&d, &inbound(&graph, &d)));
+                [a, b].map(|node| scorer.score(&node, &inbound(graph, &node)))
+            }
+        };
+
+        let [a_score, b_score] = scores(1.0).await;
+        assert!(a_score > 0.0);
+        assert_eq!(a_score, b_score);
+
+        let [a_score, b_score] = scores(0.25).await;
+        assert!(a_score > b_score);
+
+        let [a_score, b_score] = scores(2.0).await;
+        assert!(a_score < b_score);
     }
 
     #[tokio::test]
-    #[allow(clippy::too_many_lines)]
-    async fn it_ranks_search_results() {
-        let mut wrt = WebgraphWriter::new(
-            crate::gen_temp_path(),
-            crate::executor::Executor::single_thread(),
-            crate::webgraph::Compression::default(),
-            None,
-        );
+    async fn cache_is_bounded() {
+        let graph = GraphFixture::new(0)
+            .edges((0..20).map(|i| {
+                (
+                    Node::from("a.com"),
+                    Node::from(format!("{i}.com")),
+                    String::new(),
+                )
+            }))
+            .build()
+            .into_graph();
 
-        wrt.insert(
-            Node::from("b.com"),
-            Node::from("a.com"),
-            String::new(),
-            RelFlags::default(),
-        );
-        wrt.insert(
-            Node::from("c.com"),
-            Node::from("d.com"),
-            String::new(),
-            RelFlags::default(),
-        );
-        wrt.insert(
-            Node::from("b.com"),
-            Node::from("e.com"),
-            String::new(),
-            RelFlags::default(),
-        );
-        wrt.insert(
-            Node::from("c.com"),
-            Node::from("b.com"),
-            String::new(),
-            RelFlags::default(),
-        );
+        const CAPACITY: usize = 4;
+
+        let liked = [Node::from("0.com").id()];
+        let mut bounded = Scorer::new(&graph, &liked, &[], false, CAPACITY).await;
+        let mut unbounded = Scorer::new(&graph, &liked, &[], false, 1_000).await;
 
-        let graph = wrt.finalize();
+        let nodes: Vec<_> = (0..20)
+            .map(|i| Node::from(format!("{i}.com")).id())
+            .collect();
+
+        for _ in 0..2 {
+            for node in &nodes {
+                let inbound = inbound(&graph, node);
+                assert_eq!(
+                    bounded.score(node, &inbound),
+                    unbounded.score(node, &inbound)
+                );
+                assert!(bounded.cache_len() <= CAPACITY);
+            }
+        }
+
+        assert_eq!(bounded.cache_len(), CAPACITY);
+        assert_eq!(bounded.cache_hits(), 0);
+        assert_eq!(bounded.cache_misses(), 40);
+
+        assert_eq!(unbounded.cache_len(), nodes.len());
+        assert_eq!(unbounded.cache_hits(), 20);
+        assert_eq!(unbounded.cache_misses(), 20);
+
+        let node = &nodes[19];
+        bounded.score(node, &inbound(&graph, node));
+        assert_eq!(bounded.cache_hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn scores_are_deterministic() {
+        let build = || {
+            GraphFixture::new(11)
+                .hosts(30)
+                .link_density(0.1)
+                .segments(3)
+                .build()
+        };
+        let (a, b) = (build(), build());
+
+        let liked: Vec<_> = a.nodes().iter().take(3).map(|node| node.id()).collect();
+
+        let mut scores = Vec::new();
+        for graph in [&a, &b] {
+            let mut scorer =
+                Scorer::new(graph.graph(), &liked, &[], false, DEFAULT_CACHE_CAPACITY).await;
+
+            scores.push(
+                graph
+                    .nodes()
+                    .iter()
+                    .map(|node| {
+                        let id = node.id();
+                        (id, scorer.score(&id, &inbound(graph.graph(), &id)))
+                    })
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        assert_eq!(scores[0], scores[1]);
+        assert!(scores[0].iter().any(|(_, score)| *score > 0.0));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::too_many_lines)]
+    async fn it_ranks_search_results() {
+        let graph = GraphFixture::new(0)
+            .edges(edges(&[
+                ("b.com", "a.com"),
+                ("c.com", "d.com"),
+                ("b.com", "e.com"),
+                ("c.com", "b.com"),
+            ]))
+            .build()
+            .into_graph();
 
         let mut index = Index::temporary().expect("Unable to open index");
 
@@ -24,12 +24,15 @@ pub mod optics;
 pub mod pipeline;
 pub mod signal;
 
+use std::{collections::HashSet, sync::Arc};
+
 use initial::InitialScoreTweaker;
 
 use crate::{
-    collector::{MainCollector, MaxDocsConsidered, TopDocs},
+    collector::{MainCollector, MaxDocsConsidered, SearchCursor, TopDocs},
     config::CollectorConfig,
     fastfield_reader::FastFieldReader,
+    prehashed::Prehashed,
     search_ctx::Ctx,
     searcher::NUM_RESULTS_PER_PAGE,
 };
@@ -45,6 +48,8 @@ pub struct Ranker {
     de_rank_similar: bool,
     num_results: Option<usize>,
     collector_config: CollectorConfig,
+    host_filter: Option<Arc<HashSet<Prehashed>>>,
+    search_after: Option<SearchCursor>,
 }
 
 impl Ranker {
@@ -61,6 +66,8 @@ impl Ranker {
             fastfield_reader,
             num_results: None,
             collector_config,
+            host_filter: None,
+            search_after: None,
         }
     }
 
@@ -82,6 +89,18 @@ impl Ranker {
         self
     }
 
+    /// Only collect documents whose site hash is in the filter.
+    pub fn with_host_filter(mut self, host_filter: Arc<HashSet<Prehashed>>) -> Self {
+        self.host_filter = Some(host_filter);
+        self
+    }
+
+    /// Collect the page of results after the cursor instead of skipping an offset.
+    pub fn with_search_after(mut self, cursor: SearchCursor) -> Self {
+        self.search_after = Some(cursor);
+        self
+    }
+
     pub fn de_rank_similar(&mut self, de_rank_similar: bool) {
         self.de_rank_similar = de_rank_similar;
     }
@@ -113,6 +132,14 @@ impl Ranker {
             collector = collector.and_max_docs(max_docs.clone());
         }
 
+        if let Some(host_filter) = &self.host_filter {
+            collector = collector.and_host_filter(Arc::clone(host_filter));
+        }
+
+        if let Some(cursor) = self.search_after {
+            collector = collector.and_search_after(cursor);
+        }
+
         collector = collector.and_collector_config(self.collector_config.clone());
 
         collector.main_collector(score_tweaker)
@@ -129,7 +156,9 @@ mod tests {
         entrypoint::indexer::IndexingWorker,
         index::Index,
         models::dual_encoder::DualEncoder,
+        ranking::{HostCentrality, SignalBound, SignalBounds, SignalEnum, SignalEnumDiscriminants},
         searcher::{LocalSearcher, SearchQuery},
+        webgraph::Node,
         webpage::{Html, Webpage},
     };
 
@@ -202,6 +231,183 @@ mod tests {
         assert_eq!(result.webpages[1].url, "https://www.a.com/");
     }
 
+    #[test]
+    fn history_boost() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for (url, title, host_centrality) in [
+            ("https://www.a.com", "Website A", 0.0),
+            ("https://www.b.com", "Website B", 0.0),
+            ("https://www.c.com", "Website C", 1000.0),
+        ] {
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        &format!(
+                            r#"
+                    <html>
+                        <head>
+                            <title>{title}</title>
+                        </head>
+                        <body>
+                            {CONTENT}
+                        </body>
+                    </html>
+                "#
+                        ),
+                        url,
+                    )
+                    .unwrap(),
+                    host_centrality,
+                    fetch_time_ms: 500,
+                    node_id: Some(Node::from(url).into_host().id()),
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+
+        let search = |boosted_hosts: &[&str]| {
+            let result = searcher
+                .search(&SearchQuery {
+                    query: "example".to_string(),
+                    return_ranking_signals: true,
+                    boosted_hosts: boosted_hosts.iter().map(|host| host.to_string()).collect(),
+                    ..Default::default()
+                })
+                .expect("Search failed");
+
+            result
+                .webpages
+                .into_iter()
+                .map(|webpage| (webpage.url, webpage.history_boost))
+                .collect::<Vec<_>>()
+        };
+
+        let organic = search(&[]);
+        assert_eq!(organic.len(), 3);
+        assert_eq!(organic[0].0, "https://www.c.com/");
+        assert!(organic.iter().all(|(_, boost)| boost.is_none()));
+
+        // the boost reorders the results of similar relevance, but is too small
+        // to overtake the much more central host.
+        assert_eq!(
+            search(&["b.com"]),
+            vec![
+                ("https://www.c.com/".to_string(), None),
+                ("https://www.b.com/".to_string(), Some(1.25)),
+                ("https://www.a.com/".to_string(), None),
+            ]
+        );
+        assert_eq!(
+            search(&["www.a.com"]),
+            vec![
+                ("https://www.c.com/".to_string(), None),
+                ("https://www.a.com/".to_string(), Some(1.25)),
+                ("https://www.b.com/".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn signal_bounds() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(&Webpage {
+                html: Html::parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Website A</title>
+                        </head>
+                        <body>
+                            {CONTENT} {}
+                            example example example
+                        </body>
+                    </html>
+                "#,
+                        crate::rand_words(100)
+                    ),
+                    "https://www.a.com",
+                )
+                .unwrap(),
+                fetch_time_ms: 500,
+                ..Default::default()
+            })
+            .expect("failed to insert webpage");
+        index
+            .insert(&Webpage {
+                html: Html::parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Website B</title>
+                        </head>
+                        <body>
+                            {CONTENT} {}
+                        </body>
+                    </html>
+                "#,
+                        crate::rand_words(100)
+                    ),
+                    "https://www.b.com",
+                )
+                .unwrap(),
+                host_centrality: 1000.0,
+                fetch_time_ms: 500,
+                ..Default::default()
+            })
+            .expect("failed to insert webpage");
+
+        index.commit().expect("failed to commit index");
+        let mut searcher = LocalSearcher::from(index);
+        let query = SearchQuery {
+            query: "example".to_string(),
+            return_ranking_signals: true,
+            ..Default::default()
+        };
+
+        let result = searcher.search(&query).expect("Search failed");
+        assert_eq!(result.webpages.len(), 2);
+        assert_eq!(result.webpages[0].url, "https://www.b.com/");
+
+        searcher.set_signal_bounds(SignalBounds::new(
+            [(
+                SignalEnum::from(HostCentrality),
+                SignalBound {
+                    floor: None,
+                    ceiling: Some(0.001),
+                },
+            )]
+            .into_iter(),
+        ));
+
+        let result = searcher.search(&query).expect("Search failed");
+        assert_eq!(result.webpages.len(), 2);
+        assert_eq!(result.webpages[0].url, "https://www.a.com/");
+        assert_eq!(result.webpages[1].url, "https://www.b.com/");
+
+        let signals = result.webpages[1].ranking_signals.as_ref().unwrap();
+        let centrality = signals
+            .get(&SignalEnumDiscriminants::HostCentrality)
+            .unwrap();
+        assert_eq!(centrality.value, 0.001);
+        assert!((centrality.unclamped_value.unwrap() - 1000.0).abs() < 1e-6);
+
+        let signals = result.webpages[0].ranking_signals.as_ref().unwrap();
+        assert_eq!(
+            signals
+                .get(&SignalEnumDiscriminants::HostCentrality)
+                .and_then(|score| score.unclamped_value),
+            None
+        );
+    }
+
     #[test]
     fn page_centrality_ranking() {
         let mut index = Index::temporary().expect("Unable to open index");
@@ -646,6 +852,76 @@ mod tests {
         assert_eq!(result.webpages[1].url, "https://www.second.com/");
     }
 
+    #[test]
+    fn term_proximity_ranking() {
+        let mut index = Index::temporary().expect("Unable to open index");
+        let filler = (0..50).map(|i| format!("filler{i}")).collect::<Vec<_>>();
+        let filler = filler.join(" ");
+
+        for (url, body) in [
+            ("https://www.far.com", format!("quantum {filler} banana")),
+            ("https://www.near.com", format!("quantum banana {filler}")),
+        ] {
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        &format!(
+                            r#"
+                            <html>
+                                <head>
+                                    <title>Test website</title>
+                                </head>
+                                <body>
+                                    {body}
+                                </body>
+                            </html>
+                        "#
+                        ),
+                        url,
+                    )
+                    .unwrap(),
+                    host_centrality: 1.0,
+                    fetch_time_ms: 500,
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::new(index);
+
+        let search = |query: &str| {
+            searcher
+                .search(&SearchQuery {
+                    query: query.to_string(),
+                    proximity_weight: Some(100.0),
+                    return_ranking_signals: true,
+                    ..Default::default()
+                })
+                .expect("Search failed")
+                .webpages
+        };
+        let proximity = |webpage: &crate::search_prettifier::DisplayedWebpage| {
+            webpage
+                .ranking_signals
+                .as_ref()
+                .unwrap()
+                .get(&SignalEnumDiscriminants::TermProximity)
+                .map(|score| score.value)
+        };
+
+        let res = search("quantum banana");
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].url, "https://www.near.com/");
+        assert_eq!(res[1].url, "https://www.far.com/");
+        assert_eq!(proximity(&res[0]), Some(1.0));
+        assert!(proximity(&res[1]).unwrap() < proximity(&res[0]).unwrap());
+
+        let res = search("quantum");
+        assert_eq!(res.len(), 2);
+        assert!(res.iter().all(|webpage| proximity(webpage).is_none()));
+    }
+
     #[test]
     fn num_slashes_and_digits() {
         let mut index = Index::temporary().expect("Unable to open index");
@@ -766,6 +1042,10 @@ mod tests {
             batch_size: 10,
             autocommit_after_num_inserts:
                 crate::config::defaults::Indexing::autocommit_after_num_inserts(),
+            main_content_extraction: crate::config::defaults::Indexing::main_content_extraction(),
+            freshness_buckets_days: crate::config::defaults::Indexing::freshness_buckets_days(),
+            tokenization: Default::default(),
+            term_pruning: None,
         })
     }
 
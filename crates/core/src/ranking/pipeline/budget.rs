@@ -0,0 +1,337 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Latency budget of the scorers in the ranking pipeline.
+//!
+//! The budget is split between the scorers as they run: a scorer gets an equal slice of
+//! what remains of the budget among itself and the scorers that have not run yet. A scorer
+//! whose slice is smaller than the time it is expected to need only scores the top candidates
+//! that fit within the slice, except for the optional scorers that are skipped instead.
+//! The recall stage itself is not part of the budget, so results are always returned.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use utoipa::ToSchema;
+
+use crate::config::LatencyBudgetConfig;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum PipelineStage {
+    TitleEmbeddings,
+    KeywordEmbeddings,
+    InboundSimilarity,
+    CrossEncoder,
+}
+
+impl PipelineStage {
+    /// Optional stages are skipped rather than scoring only some of the candidates,
+    /// as a signal that only some of the candidates have would skew their ranking.
+    pub fn is_optional(&self) -> bool {
+        matches!(self, Self::TitleEmbeddings | Self::KeywordEmbeddings)
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct DegradedStage {
+    pub stage: PipelineStage,
+    pub num_candidates: usize,
+    /// The number of top candidates the stage scored. `0` if the stage was skipped.
+    pub num_scored: usize,
+}
+
+struct BudgetState {
+    pending: Vec<PipelineStage>,
+    degraded: Vec<DegradedStage>,
+}
+
+pub struct LatencyBudget {
+    deadline: Instant,
+    stage_time: Duration,
+    min_top_k: usize,
+    state: Mutex<BudgetState>,
+}
+
+impl LatencyBudget {
+    /// The budget starts when it is created. `stages` are the stages that are expected
+    /// to run within the budget, so the first stages leave time for the later ones.
+    pub fn new(
+        budget: Duration,
+        config: &LatencyBudgetConfig,
+        stages: impl IntoIterator<Item = PipelineStage>,
+    ) -> Self {
+        Self {
+            deadline: Instant::now() + budget,
+            stage_time: Duration::from_millis(config.stage_ms),
+            min_top_k: config.min_top_k,
+            state: Mutex::new(BudgetState {
+                pending: stages.into_iter().collect(),
+                degraded: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    /// The slice of the remaining budget for the stage and the number of its top
+    /// candidates it can score within the slice.
+    fn plan(&self, stage: PipelineStage, num_candidates: usize) -> (Duration, usize) {
+        let num_stages = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.pending.retain(|pending| *pending != stage);
+            state.pending.len() + 1
+        };
+
+        let slice = self.remaining() / num_stages as u32;
+
+        if slice >= self.stage_time || num_candidates == 0 {
+            return (slice, num_candidates);
+        }
+
+        if stage.is_optional() {
+            return (slice, 0);
+        }
+
+        let fits = (num_candidates as f64 * slice.as_secs_f64() / self.stage_time.as_secs_f64())
+            .ceil() as usize;
+
+        (slice, fits.max(self.min_top_k).min(num_candidates))
+    }
+
+    /// Run the scorer of the stage on as many of the top webpages as fit within its slice
+    /// of the budget. The webpages must be sorted by their score so far.
+    pub fn run<T>(&self, stage: PipelineStage, webpages: &mut [T], score: impl FnOnce(&mut [T])) {
+        let num_candidates = webpages.len();
+        let (slice, top_k) = self.plan(stage, num_candidates);
+
+        if top_k < num_candidates {
+            self.state
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .degraded
+                .push(DegradedStage {
+                    stage,
+                    num_candidates,
+                    num_scored: top_k,
+                });
+        }
+
+        if top_k == 0 {
+            return;
+        }
+
+        let start = Instant::now();
+        score(&mut webpages[..top_k]);
+        let elapsed = start.elapsed();
+
+        if elapsed > slice {
+            tracing::debug!(
+                ?stage,
+                ?elapsed,
+                ?slice,
+                "ranking stage exceeded its slice of the latency budget"
+            );
+        }
+    }
+
+    /// The stages that were skipped or scored fewer candidates, in the order they ran.
+    pub fn degraded_stages(&self) -> Vec<DegradedStage> {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .degraded
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::{
+        config::CollectorConfig,
+        ranking::pipeline::{
+            scorers::MultiScorer, tests::sample_websites, LocalRecallRankingWebpage,
+            RankingPipeline, RankingStage, Scorer,
+        },
+    };
+
+    use super::*;
+
+    struct DelayScorer {
+        stage: PipelineStage,
+        delay: Duration,
+        num_scored: Arc<AtomicUsize>,
+    }
+
+    impl Scorer<LocalRecallRankingWebpage> for DelayScorer {
+        fn score(&self, webpages: &mut [LocalRecallRankingWebpage]) {
+            std::thread::sleep(self.delay);
+            self.num_scored.fetch_add(webpages.len(), Ordering::SeqCst);
+        }
+
+        fn score_within_budget(
+            &self,
+            webpages: &mut [LocalRecallRankingWebpage],
+            budget: &LatencyBudget,
+        ) {
+            budget.run(self.stage, webpages, |webpages| self.score(webpages));
+        }
+    }
+
+    fn scorer(stage: PipelineStage, delay_ms: u64) -> (DelayScorer, Arc<AtomicUsize>) {
+        let num_scored = Arc::new(AtomicUsize::new(0));
+
+        (
+            DelayScorer {
+                stage,
+                delay: Duration::from_millis(delay_ms),
+                num_scored: Arc::clone(&num_scored),
+            },
+            num_scored,
+        )
+    }
+
+    fn budget(budget_ms: u64) -> Arc<LatencyBudget> {
+        Arc::new(LatencyBudget::new(
+            Duration::from_millis(budget_ms),
+            &LatencyBudgetConfig {
+                budget_ms: Some(budget_ms),
+                stage_ms: 20,
+                min_top_k: 10,
+            },
+            [
+                PipelineStage::InboundSimilarity,
+                PipelineStage::TitleEmbeddings,
+            ],
+        ))
+    }
+
+    fn pipeline(
+        scorers: Vec<Box<dyn Scorer<LocalRecallRankingWebpage>>>,
+        budget: Arc<LatencyBudget>,
+    ) -> RankingPipeline<LocalRecallRankingWebpage> {
+        RankingPipeline {
+            stage: RankingStage {
+                scorer: Box::new(MultiScorer::new(scorers)),
+                stage_top_n: 20,
+                derank_similar: true,
+                diversity: None,
+                model: None,
+                coefficients: Default::default(),
+                budget: None,
+                history: None,
+            },
+            page: 0,
+            top_n: 20,
+            collector_config: CollectorConfig::default(),
+        }
+        .with_budget(budget)
+    }
+
+    #[test]
+    fn embeddings_skipped_when_budget_is_tight() {
+        let (inbound, num_inbound) = scorer(PipelineStage::InboundSimilarity, 50);
+        let (embeddings, num_embeddings) = scorer(PipelineStage::TitleEmbeddings, 0);
+        let budget = budget(60);
+
+        let res = pipeline(
+            vec![Box::new(inbound), Box::new(embeddings)],
+            Arc::clone(&budget),
+        )
+        .apply(sample_websites(21));
+
+        assert_eq!(res.len(), 20);
+        assert_eq!(num_inbound.load(Ordering::SeqCst), 20);
+        assert_eq!(num_embeddings.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            budget.degraded_stages(),
+            vec![DegradedStage {
+                stage: PipelineStage::TitleEmbeddings,
+                num_candidates: 20,
+                num_scored: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn required_stage_shrinks() {
+        let (embeddings, _) = scorer(PipelineStage::TitleEmbeddings, 60);
+        let (inbound, num_inbound) = scorer(PipelineStage::InboundSimilarity, 0);
+        let budget = budget(50);
+
+        let res = pipeline(
+            vec![Box::new(embeddings), Box::new(inbound)],
+            Arc::clone(&budget),
+        )
+        .apply(sample_websites(21));
+
+        assert_eq!(res.len(), 20);
+        assert_eq!(num_inbound.load(Ordering::SeqCst), 10);
+        assert_eq!(
+            budget.degraded_stages(),
+            vec![DegradedStage {
+                stage: PipelineStage::InboundSimilarity,
+                num_candidates: 20,
+                num_scored: 10,
+            }]
+        );
+    }
+
+    #[test]
+    fn generous_budget() {
+        let (inbound, num_inbound) = scorer(PipelineStage::InboundSimilarity, 1);
+        let (embeddings, num_embeddings) = scorer(PipelineStage::TitleEmbeddings, 1);
+        let budget = budget(10_000);
+
+        let res = pipeline(
+            vec![Box::new(inbound), Box::new(embeddings)],
+            Arc::clone(&budget),
+        )
+        .apply(sample_websites(21));
+
+        assert_eq!(res.len(), 20);
+        assert_eq!(num_inbound.load(Ordering::SeqCst), 20);
+        assert_eq!(num_embeddings.load(Ordering::SeqCst), 20);
+        assert!(budget.degraded_stages().is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::config::DiversityConfig;
+
+use super::{stages::StoredEmbeddings, RankableWebpage};
+
+/// A cheap representation of the topic of a webpage.
+#[derive(Debug, Clone)]
+pub struct Fingerprint(Vec<f32>);
+
+impl Fingerprint {
+    pub fn new(mut vec: Vec<f32>) -> Self {
+        let norm = vec.iter().map(|v| v * v).sum::<f32>().sqrt();
+
+        if norm > 0.0 {
+            for v in &mut vec {
+                *v /= norm;
+            }
+        }
+
+        Self(vec)
+    }
+
+    /// The embeddings are stored as little endian bf16.
+    pub fn from_embedding(embedding: &StoredEmbeddings) -> Self {
+        let vec = embedding
+            .as_slice()
+            .chunks_exact(2)
+            .map(|b| f32::from_bits((u16::from_le_bytes([b[0], b[1]]) as u32) << 16))
+            .collect();
+
+        Self::new(vec)
+    }
+
+    /// Cosine similarity between the two fingerprints.
+    pub fn similarity(&self, other: &Self) -> f64 {
+        if self.0.len() != other.0.len() {
+            return 0.0;
+        }
+
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .map(|(a, b)| (a * b) as f64)
+            .sum()
+    }
+}
+
+/// Greedily re-rank the top results in the style of maximal marginal relevance. Each position
+/// is filled by the result maximising `lambda * relevance - (1 - lambda) * redundancy`, where
+/// redundancy is the summed similarity to the results already chosen. Similarities below
+/// the configured threshold are ignored, so results that are already diverse keep their order.
+///
+/// `webpages` must be sorted by descending score. Results without a fingerprint are only
+/// ranked by their relevance.
+pub fn diversify<T: RankableWebpage>(mut webpages: Vec<T>, config: &DiversityConfig) -> Vec<T> {
+    if !config.is_enabled() || webpages.len() <= 1 {
+        return webpages;
+    }
+
+    let rest = webpages.split_off(config.top_n.min(webpages.len()));
+    let fingerprints: Vec<_> = webpages.iter().map(|w| w.fingerprint()).collect();
+
+    if fingerprints.iter().all(Option::is_none) {
+        webpages.extend(rest);
+        return webpages;
+    }
+
+    // normalise the scores so lambda does not depend on the scale of the ranking signals
+    let max = webpages.first().map(|w| w.score()).unwrap_or_default();
+    let min = webpages.last().map(|w| w.score()).unwrap_or_default();
+    let relevance: Vec<_> = webpages
+        .iter()
+        .map(|w| {
+            if max > min {
+                (w.score() - min) / (max - min)
+            } else {
+                1.0
+            }
+        })
+        .collect();
+
+    let mut redundancy = vec![0.0; webpages.len()];
+    let mut remaining: Vec<usize> = (0..webpages.len()).collect();
+    let mut order = Vec::with_capacity(webpages.len());
+
+    for pos in 0..webpages.len() {
+        let chosen = if remaining[0] + config.max_displacement <= pos {
+            // the best remaining result cannot be pushed down any further.
+            0
+        } else {
+            remaining
+                .iter()
+                .enumerate()
+                .take_while(|(_, rank)| **rank <= pos + config.max_displacement)
+                .map(|(i, rank)| {
+                    let mmr = config.lambda * relevance[*rank]
+                        - (1.0 - config.lambda) * redundancy[*rank];
+                    (i, mmr)
+                })
+                .max_by(|(a_idx, a), (b_idx, b)| a.total_cmp(b).then(b_idx.cmp(a_idx)))
+                .map(|(i, _)| i)
+                .unwrap_or(0)
+        };
+
+        let selected = remaining.remove(chosen);
+        order.push(selected);
+
+        if let Some(selected) = &fingerprints[selected] {
+            for rank in &remaining {
+                if let Some(other) = &fingerprints[*rank] {
+                    let similarity = selected.similarity(other);
+
+                    if similarity >= config.similarity_threshold {
+                        redundancy[*rank] += similarity;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut webpages: Vec<_> = webpages.into_iter().map(Some).collect();
+
+    order
+        .into_iter()
+        .filter_map(|rank| webpages[rank].take())
+        .chain(rest)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        collector::{self, Hashes},
+        enum_map::EnumMap,
+        prehashed::Prehashed,
+        ranking::SignalEnum,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    struct Doc {
+        id: usize,
+        cluster: usize,
+        score: f64,
+        fingerprint: Vec<f32>,
+        signals: EnumMap<SignalEnum, f64>,
+    }
+
+    impl Doc {
+        fn new(id: usize, cluster: usize, score: f64, fingerprint: Vec<f32>) -> Self {
+            Self {
+                id,
+                cluster,
+                score,
+                fingerprint,
+                signals: EnumMap::new(),
+            }
+        }
+    }
+
+    impl collector::Doc for Doc {
+        fn score(&self) -> f64 {
+            self.score
+        }
+
+        fn hashes(&self) -> Hashes {
+            Hashes {
+                site: Prehashed(0),
+                title: Prehashed(0),
+                url: Prehashed(0),
+                url_without_tld: Prehashed(0),
+                simhash: 0,
+            }
+        }
+    }
+
+    impl RankableWebpage for Doc {
+        fn set_score(&mut self, score: f64) {
+            self.score = score;
+        }
+
+        fn boost(&self) -> Option<f64> {
+            None
+        }
+
+        fn signals(&self) -> &EnumMap<SignalEnum, f64> {
+            &self.signals
+        }
+
+        fn fingerprint(&self) -> Option<Fingerprint> {
+            Some(Fingerprint::new(self.fingerprint.clone()))
+        }
+    }
+
+    /// Two clusters of 10 documents where every document about cars
+    /// is more relevant than the documents about animals.
+    fn clusters() -> Vec<Doc> {
+        let cars = (0..10).map(|i| Doc::new(i, 0, 1.0 - i as f64 * 0.01, vec![1.0, 0.1]));
+        let animals = (0..10).map(|i| Doc::new(10 + i, 1, 0.8 - i as f64 * 0.01, vec![0.1, 1.0]));
+
+        cars.chain(animals).collect()
+    }
+
+    fn config(lambda: f64) -> DiversityConfig {
+        DiversityConfig {
+            lambda,
+            top_n: 20,
+            max_displacement: 10,
+            similarity_threshold: 0.5,
+        }
+    }
+
+    #[test]
+    fn interleaves_clusters() {
+        let res = diversify(clusters(), &config(0.5));
+        let top: Vec<_> = res.iter().take(10).map(|d| d.cluster).collect();
+
+        assert_eq!(top, vec![0, 1, 0, 1, 0, 1, 0, 1, 0, 1]);
+        assert_eq!(res.len(), 20);
+    }
+
+    #[test]
+    fn disabled() {
+        let res = diversify(clusters(), &config(1.0));
+        let top: Vec<_> = res.iter().take(10).map(|d| d.cluster).collect();
+
+        assert_eq!(top, vec![0; 10]);
+        assert_eq!(
+            res.iter().map(|d| d.id).collect::<Vec<_>>(),
+            (0..20).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn already_diverse() {
+        let docs: Vec<_> = (0..10)
+            .map(|i| {
+                let mut fingerprint = vec![0.0; 10];
+                fingerprint[i] = 1.0;
+                Doc::new(i, i, 1.0 - i as f64 * 0.01, fingerprint)
+            })
+            .collect();
+
+        let res = diversify(docs, &config(0.5));
+
+        assert_eq!(
+            res.iter().map(|d| d.id).collect::<Vec<_>>(),
+            (0..10).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn max_displacement() {
+        let config = DiversityConfig {
+            max_displacement: 2,
+            ..config(0.0)
+        };
+
+        let res = diversify(clusters(), &config);
+
+        for (pos, doc) in res.iter().enumerate() {
+            assert!(
+                pos.abs_diff(doc.id) <= 2,
+                "doc {} moved from {} to {}",
+                doc.id,
+                doc.id,
+                pos
+            );
+        }
+
+        assert!(res.iter().take(10).any(|d| d.cluster == 1));
+    }
+
+    #[test]
+    fn embedding_fingerprint() {
+        let to_embedding = |v: &[f32]| {
+            StoredEmbeddings::new_testing(
+                v.iter()
+                    .flat_map(|f| ((f.to_bits() >> 16) as u16).to_le_bytes())
+                    .collect(),
+            )
+        };
+
+        let a = Fingerprint::from_embedding(&to_embedding(&[1.0, 0.0, 1.0]));
+        let b = Fingerprint::from_embedding(&to_embedding(&[2.0, 0.0, 2.0]));
+        let c = Fingerprint::from_embedding(&to_embedding(&[0.0, 1.0, 0.0]));
+
+        assert!((a.similarity(&b) - 1.0).abs() < 1e-3);
+        assert!(a.similarity(&c).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,221 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Personalization from the click history of the user. The history is kept by the client,
+//! which sends the hosts the user visits the most along with the query, so nothing about
+//! the user is stored on the server.
+//!
+//! Results from those hosts get a multiplicative boost that is capped, so it only reorders
+//! results that are already close in relevance. The boost has diminishing returns within a
+//! host: every further result from the same host gets half the boost of the one before it.
+
+use fnv::{FnvHashMap, FnvHashSet};
+
+use crate::webgraph::{Node, NodeID};
+
+use super::RankableWebpage;
+
+/// Queries with more boosted hosts than this are not personalized at all, as such a long
+/// list is more likely an attempt at gaming the ranking than a real history.
+pub const MAX_BOOSTED_HOSTS: usize = 32;
+
+/// The score of a result is multiplied by at most `1 + MAX_BOOST`.
+const MAX_BOOST: f64 = 0.25;
+
+/// How much the boost decays for every higher scoring result from the same host.
+const BOOST_DECAY: f64 = 0.5;
+
+pub struct HistoryBoost {
+    hosts: FnvHashSet<NodeID>,
+}
+
+impl HistoryBoost {
+    /// Returns `None` if there are no hosts to boost or too many of them.
+    pub fn new(hosts: &[String]) -> Option<Self> {
+        if hosts.len() > MAX_BOOSTED_HOSTS {
+            return None;
+        }
+
+        let hosts: FnvHashSet<_> = hosts
+            .iter()
+            .map(|host| Node::from(host.trim()).into_host())
+            .filter(|host| !host.as_str().is_empty())
+            .map(|host| host.id())
+            .collect();
+
+        if hosts.is_empty() {
+            None
+        } else {
+            Some(Self { hosts })
+        }
+    }
+
+    /// Boost the webpages from the boosted hosts. The boost of a webpage depends on how many
+    /// webpages from its host score higher, so the scores must otherwise be final.
+    pub fn apply<T: RankableWebpage>(&self, webpages: &mut [T]) {
+        let mut order: Vec<_> = (0..webpages.len()).collect();
+        order.sort_by(|a, b| webpages[*b].score().total_cmp(&webpages[*a].score()));
+
+        let mut num_boosted: FnvHashMap<NodeID, i32> = FnvHashMap::default();
+
+        for idx in order {
+            let webpage = &mut webpages[idx];
+
+            let host = match webpage.host() {
+                Some(host) if self.hosts.contains(&host) => host,
+                _ => continue,
+            };
+
+            let num = num_boosted.entry(host).or_default();
+            let boost = 1.0 + MAX_BOOST * BOOST_DECAY.powi(*num);
+            *num += 1;
+
+            let score = webpage.score() * boost;
+            webpage.set_score(score);
+            webpage.set_history_boost(boost);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        collector::{self, Hashes},
+        enum_map::EnumMap,
+        prehashed::Prehashed,
+        ranking::SignalEnum,
+    };
+
+    use super::*;
+
+    struct Doc {
+        id: usize,
+        host: NodeID,
+        score: f64,
+        history_boost: Option<f64>,
+        signals: EnumMap<SignalEnum, f64>,
+    }
+
+    impl Doc {
+        fn new(id: usize, host: &str, score: f64) -> Self {
+            Self {
+                id,
+                host: Node::from(host).into_host().id(),
+                score,
+                history_boost: None,
+                signals: EnumMap::new(),
+            }
+        }
+    }
+
+    impl collector::Doc for Doc {
+        fn score(&self) -> f64 {
+            self.score
+        }
+
+        fn hashes(&self) -> Hashes {
+            Hashes {
+                site: Prehashed(0),
+                title: Prehashed(0),
+                url: Prehashed(0),
+                url_without_tld: Prehashed(0),
+                simhash: 0,
+            }
+        }
+    }
+
+    impl RankableWebpage for Doc {
+        fn set_score(&mut self, score: f64) {
+            self.score = score;
+        }
+
+        fn boost(&self) -> Option<f64> {
+            None
+        }
+
+        fn signals(&self) -> &EnumMap<SignalEnum, f64> {
+            &self.signals
+        }
+
+        fn host(&self) -> Option<NodeID> {
+            Some(self.host)
+        }
+
+        fn set_history_boost(&mut self, boost: f64) {
+            self.history_boost = Some(boost);
+        }
+    }
+
+    fn ranked(mut docs: Vec<Doc>, hosts: &[&str]) -> Vec<usize> {
+        let hosts: Vec<_> = hosts.iter().map(|host| host.to_string()).collect();
+
+        if let Some(history) = HistoryBoost::new(&hosts) {
+            history.apply(&mut docs);
+        }
+
+        docs.sort_by(|a, b| b.score.total_cmp(&a.score));
+        docs.into_iter().map(|doc| doc.id).collect()
+    }
+
+    fn docs() -> Vec<Doc> {
+        vec![
+            Doc::new(0, "a.com", 1.0),
+            Doc::new(1, "b.com", 0.9),
+            Doc::new(2, "c.com", 0.5),
+        ]
+    }
+
+    #[test]
+    fn reorders_within_cap() {
+        assert_eq!(ranked(docs(), &[]), vec![0, 1, 2]);
+        assert_eq!(ranked(docs(), &["www.b.com"]), vec![1, 0, 2]);
+
+        // the organic relevance of a.com is too far ahead to be overtaken
+        assert_eq!(ranked(docs(), &["c.com"]), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn diminishing_returns_per_host() {
+        let mut docs = vec![
+            Doc::new(0, "a.com", 1.0),
+            Doc::new(1, "a.com", 0.9),
+            Doc::new(2, "a.com", 0.8),
+            Doc::new(3, "b.com", 0.85),
+        ];
+
+        HistoryBoost::new(&["a.com".to_string()])
+            .unwrap()
+            .apply(&mut docs);
+
+        let boosts: Vec<_> = docs.iter().map(|doc| doc.history_boost).collect();
+        assert_eq!(boosts, vec![Some(1.25), Some(1.125), Some(1.0625), None]);
+        assert!(docs.iter().all(|doc| doc.score <= 1.25));
+    }
+
+    #[test]
+    fn too_many_hosts_are_ignored() {
+        let mut hosts: Vec<_> = (0..MAX_BOOSTED_HOSTS).map(|i| format!("{i}.com")).collect();
+        hosts[0] = "b.com".to_string();
+
+        assert!(HistoryBoost::new(&hosts).is_some());
+
+        hosts.push("c.com".to_string());
+        assert!(HistoryBoost::new(&hosts).is_none());
+
+        let hosts: Vec<_> = hosts.iter().map(|host| host.as_str()).collect();
+        assert_eq!(ranked(docs(), &hosts), vec![0, 1, 2]);
+    }
+}
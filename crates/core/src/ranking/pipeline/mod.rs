@@ -18,9 +18,10 @@ use std::sync::Arc;
 
 use crate::{
     collector::{self, BucketCollector},
-    config::CollectorConfig,
+    config::{CollectorConfig, DiversityConfig},
     enum_map::EnumMap,
-    searcher::SearchQuery,
+    searcher::{timings::Recorder, SearchQuery},
+    webgraph::NodeID,
 };
 
 use super::{
@@ -28,9 +29,13 @@ use super::{
     SignalCoefficient, SignalEnum, SignalScore,
 };
 
+mod budget;
+pub mod diversity;
+pub mod history;
 mod scorers;
 mod stages;
 
+pub use budget::{DegradedStage, LatencyBudget, PipelineStage};
 pub use scorers::{ReRanker, Recall, Scorer};
 pub use stages::{LocalRecallRankingWebpage, PrecisionRankingWebpage, RecallRankingWebpage};
 
@@ -39,6 +44,19 @@ pub trait RankableWebpage: collector::Doc + Send + Sync {
     fn boost(&self) -> Option<f64>;
     fn signals(&self) -> &EnumMap<SignalEnum, f64>;
 
+    /// The topic of the webpage if it is known. Used to diversify the results.
+    fn fingerprint(&self) -> Option<diversity::Fingerprint> {
+        None
+    }
+
+    /// The host of the webpage if it is known. Used to personalize the results.
+    fn host(&self) -> Option<NodeID> {
+        None
+    }
+
+    /// Record the boost from the click history of the user, so it can be explained.
+    fn set_history_boost(&mut self, _boost: f64) {}
+
     fn boost_score(&mut self) {
         if let Some(boost) = self.boost() {
             if boost != 0.0 {
@@ -59,8 +77,11 @@ struct RankingStage<T> {
     scorer: Box<dyn Scorer<T>>,
     stage_top_n: usize,
     derank_similar: bool,
+    diversity: Option<DiversityConfig>,
     model: Option<Arc<LambdaMART>>,
     coefficients: SignalCoefficient,
+    budget: Option<Arc<LatencyBudget>>,
+    history: Option<history::HistoryBoost>,
 }
 
 impl<T: RankableWebpage> RankingStage<T> {
@@ -70,6 +91,7 @@ impl<T: RankableWebpage> RankingStage<T> {
         top_n: usize,
         offset: usize,
         collector_config: CollectorConfig,
+        timings: &mut Recorder,
     ) -> Vec<T> {
         let mut websites = websites
             .into_iter()
@@ -77,22 +99,41 @@ impl<T: RankableWebpage> RankingStage<T> {
             .take(self.stage_top_n.max(top_n))
             .collect::<Vec<_>>();
 
-        self.scorer.score(&mut websites);
+        timings.time("score", || match &self.budget {
+            Some(budget) => self.scorer.score_within_budget(&mut websites, budget),
+            None => self.scorer.score(&mut websites),
+        });
 
+        let start = timings.start();
         let mut collector =
             BucketCollector::new(self.stage_top_n.max(top_n) + offset, collector_config);
 
-        for mut website in websites {
+        for website in &mut websites {
             website.set_score(self.calculate_score(website.signals()));
             website.boost_score();
+        }
+
+        if let Some(history) = &self.history {
+            history.apply(&mut websites);
+        }
+
+        for website in websites {
             collector.insert(website);
         }
 
-        collector
-            .into_sorted_vec(self.derank_similar)
-            .into_iter()
-            .take(top_n)
-            .collect()
+        let websites = collector.into_sorted_vec(self.derank_similar);
+        timings.record("sort", start);
+
+        // only the first page is diversified, as later pages can't know
+        // which results were moved across the page boundary.
+        let websites = match &self.diversity {
+            Some(config) if offset == 0 => {
+                timings.time("diversify", || diversity::diversify(websites, config))
+            }
+            _ => websites,
+        };
+
+        websites.into_iter().take(top_n).collect()
     }
 
     fn calculate_score(&self, signals: &EnumMap<SignalEnum, f64>) -> f64 {
@@ -119,6 +160,7 @@ impl<T: RankableWebpage> RankingStage<T> {
         self.scorer.set_query_info(query);
 
         self.coefficients = query.signal_coefficients();
+        self.history = history::HistoryBoost::new(&query.boosted_hosts);
     }
 }
 
@@ -139,11 +181,29 @@ impl<T: RankableWebpage> RankingPipeline<T> {
         query.page = 0;
     }
 
+    /// Diversify the topics of the top results. The pipeline must consider at least
+    /// `diversity.top_n` results for results further down to be moved up.
+    pub fn with_diversity(mut self, diversity: DiversityConfig) -> Self {
+        self.stage.diversity = Some(diversity);
+        self
+    }
+
+    /// Score within the latency budget. The budget is shared with the other pipelines of the query.
+    pub fn with_budget(mut self, budget: Arc<LatencyBudget>) -> Self {
+        self.stage.budget = Some(budget);
+        self
+    }
+
     pub fn offset(&self) -> usize {
         self.top_n * self.page
     }
 
     pub fn apply(self, websites: Vec<T>) -> Vec<T> {
+        self.apply_timed(websites, &mut Recorder::disabled())
+    }
+
+    /// Like [`Self::apply`], but records the time of each step of the pipeline in `timings`.
+    pub fn apply_timed(self, websites: Vec<T>, timings: &mut Recorder) -> Vec<T> {
         if websites.len() <= 1 {
             return websites;
         }
@@ -153,6 +213,7 @@ impl<T: RankableWebpage> RankingPipeline<T> {
             self.top_n,
             self.offset(),
             self.collector_config.clone(),
+            timings,
         )
     }
 
@@ -178,7 +239,7 @@ mod tests {
 
     use super::*;
 
-    fn sample_websites(n: usize) -> Vec<LocalRecallRankingWebpage> {
+    pub(super) fn sample_websites(n: usize) -> Vec<LocalRecallRankingWebpage> {
         (0..n)
             .map(|i| -> LocalRecallRankingWebpage {
                 let pointer = WebpagePointer {
@@ -22,7 +22,10 @@ use crate::{
     models::dual_encoder::DualEncoder,
     ranking::{
         self,
-        pipeline::{stages::StoredEmbeddings, LocalRecallRankingWebpage, RankableWebpage},
+        pipeline::{
+            stages::StoredEmbeddings, LatencyBudget, LocalRecallRankingWebpage, PipelineStage,
+            RankableWebpage,
+        },
         SignalEnum,
     },
     searcher::{api::ScoredWebpagePointer, SearchQuery},
@@ -150,6 +153,15 @@ impl<W: RankableWebpage, E: EmbeddingSignal<W>> Scorer<W> for EmbeddingScorer<W,
     fn set_query_info(&mut self, query: &SearchQuery) {
         self.query = Some(query.query.clone());
     }
+
+    /// Without a dual encoder nothing is scored, so the scorer is not part of the budget.
+    fn score_within_budget(&self, webpages: &mut [W], budget: &LatencyBudget) {
+        if self.dual_encoder.is_none() {
+            return;
+        }
+
+        budget.run(E::stage(), webpages, |webpages| self.score(webpages));
+    }
 }
 
 pub struct TitleEmbeddings;
@@ -157,6 +169,7 @@ pub struct KeywordEmbeddings;
 
 pub trait EmbeddingSignal<W>: Send + Sync {
     fn signal() -> SignalEnum;
+    fn stage() -> PipelineStage;
     fn has_embedding(webpage: &W) -> bool;
     fn embedding(webpage: &W, hidden_size: usize) -> Option<Embedding>;
     fn insert_signal(webpage: &mut W, score: f64);
@@ -167,6 +180,10 @@ impl EmbeddingSignal<ScoredWebpagePointer> for TitleEmbeddings {
         ranking::signal::TitleEmbeddingSimilarity.into()
     }
 
+    fn stage() -> PipelineStage {
+        PipelineStage::TitleEmbeddings
+    }
+
     fn has_embedding(webpage: &ScoredWebpagePointer) -> bool {
         webpage.as_ranking().title_embedding().is_some()
     }
@@ -186,6 +203,10 @@ impl EmbeddingSignal<LocalRecallRankingWebpage> for TitleEmbeddings {
         ranking::signal::TitleEmbeddingSimilarity.into()
     }
 
+    fn stage() -> PipelineStage {
+        PipelineStage::TitleEmbeddings
+    }
+
     fn has_embedding(webpage: &LocalRecallRankingWebpage) -> bool {
         webpage.title_embedding().is_some()
     }
@@ -206,6 +227,10 @@ impl EmbeddingSignal<ScoredWebpagePointer> for KeywordEmbeddings {
         ranking::signal::KeywordEmbeddingSimilarity.into()
     }
 
+    fn stage() -> PipelineStage {
+        PipelineStage::KeywordEmbeddings
+    }
+
     fn has_embedding(webpage: &ScoredWebpagePointer) -> bool {
         webpage.as_ranking().keyword_embedding().is_some()
     }
@@ -225,6 +250,10 @@ impl EmbeddingSignal<LocalRecallRankingWebpage> for KeywordEmbeddings {
         ranking::signal::KeywordEmbeddingSimilarity.into()
     }
 
+    fn stage() -> PipelineStage {
+        PipelineStage::KeywordEmbeddings
+    }
+
     fn has_embedding(webpage: &LocalRecallRankingWebpage) -> bool {
         webpage.keyword_embedding().is_some()
     }
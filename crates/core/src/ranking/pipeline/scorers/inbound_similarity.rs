@@ -17,7 +17,11 @@
 use std::sync::Mutex;
 
 use crate::{
-    ranking::{inbound_similarity, signal},
+    ranking::{
+        inbound_similarity,
+        pipeline::{LatencyBudget, PipelineStage},
+        signal,
+    },
     searcher::api::ScoredWebpagePointer,
 };
 
@@ -50,4 +54,10 @@ impl Scorer<ScoredWebpagePointer> for InboundScorer {
                 .insert(signal::InboundSimilarity.into(), score);
         }
     }
+
+    fn score_within_budget(&self, webpages: &mut [ScoredWebpagePointer], budget: &LatencyBudget) {
+        budget.run(PipelineStage::InboundSimilarity, webpages, |webpages| {
+            self.score(webpages)
+        });
+    }
 }
@@ -24,11 +24,17 @@ pub use reranker::ReRanker;
 
 use crate::searcher::SearchQuery;
 
-use super::RankableWebpage;
+use super::{LatencyBudget, RankableWebpage};
 
 pub trait Scorer<T: RankableWebpage>: Send + Sync {
     fn score(&self, webpages: &mut [T]);
     fn set_query_info(&mut self, _query: &SearchQuery) {}
+
+    /// Scorers that are a stage of the latency budget only score
+    /// the candidates that fit within their slice of it.
+    fn score_within_budget(&self, webpages: &mut [T], _budget: &LatencyBudget) {
+        self.score(webpages);
+    }
 }
 
 pub struct IdentityScorer;
@@ -59,4 +65,10 @@ impl<T: RankableWebpage> Scorer<T> for MultiScorer<T> {
             scorer.set_query_info(query);
         }
     }
+
+    fn score_within_budget(&self, webpages: &mut [T], budget: &LatencyBudget) {
+        for scorer in &self.scorers {
+            scorer.score_within_budget(webpages, budget);
+        }
+    }
 }
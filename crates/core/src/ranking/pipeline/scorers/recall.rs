@@ -20,7 +20,7 @@ use crate::{
     models::dual_encoder::DualEncoder,
     ranking::{
         inbound_similarity,
-        pipeline::{LocalRecallRankingWebpage, RankableWebpage},
+        pipeline::{LatencyBudget, LocalRecallRankingWebpage, RankableWebpage},
     },
     searcher::{
         api::{self},
@@ -64,6 +64,14 @@ impl Scorer<api::ScoredWebpagePointer> for Recall<api::ScoredWebpagePointer> {
     fn score(&self, webpages: &mut [api::ScoredWebpagePointer]) {
         self.scorer.score(webpages);
     }
+
+    fn score_within_budget(
+        &self,
+        webpages: &mut [api::ScoredWebpagePointer],
+        budget: &LatencyBudget,
+    ) {
+        self.scorer.score_within_budget(webpages, budget);
+    }
 }
 
 impl Recall<LocalRecallRankingWebpage> {
@@ -89,6 +97,14 @@ impl Scorer<LocalRecallRankingWebpage> for Recall<LocalRecallRankingWebpage> {
         self.scorer.score(webpages);
     }
 
+    fn score_within_budget(
+        &self,
+        webpages: &mut [LocalRecallRankingWebpage],
+        budget: &LatencyBudget,
+    ) {
+        self.scorer.score_within_budget(webpages, budget);
+    }
+
     fn set_query_info(&mut self, query: &SearchQuery) {
         self.scorer.set_query_info(query);
     }
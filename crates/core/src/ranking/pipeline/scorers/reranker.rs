@@ -21,7 +21,7 @@ use crate::{
     searcher::SearchQuery,
 };
 
-use crate::ranking::pipeline::{PrecisionRankingWebpage, Scorer};
+use crate::ranking::pipeline::{LatencyBudget, PipelineStage, PrecisionRankingWebpage, Scorer};
 
 pub struct ReRanker<M: CrossEncoder> {
     crossencoder: Arc<M>,
@@ -72,4 +72,14 @@ impl<M: CrossEncoder> Scorer<PrecisionRankingWebpage> for ReRanker<M> {
     fn set_query_info(&mut self, query: &SearchQuery) {
         self.query = Some(query.clone());
     }
+
+    fn score_within_budget(
+        &self,
+        webpages: &mut [PrecisionRankingWebpage],
+        budget: &LatencyBudget,
+    ) {
+        budget.run(PipelineStage::CrossEncoder, webpages, |webpages| {
+            self.score(webpages)
+        });
+    }
 }
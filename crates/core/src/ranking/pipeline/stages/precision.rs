@@ -24,12 +24,13 @@ use crate::{
     ranking::{
         models::{cross_encoder::CrossEncoder, lambdamart::LambdaMART},
         pipeline::{
-            scorers::IdentityScorer, RankableWebpage, RankingPipeline, RankingStage, ReRanker,
-            Scorer,
+            diversity::Fingerprint, scorers::IdentityScorer, RankableWebpage, RankingPipeline,
+            RankingStage, ReRanker, Scorer,
         },
         SignalEnum,
     },
     searcher::SearchQuery,
+    webgraph::NodeID,
     Result,
 };
 
@@ -77,6 +78,18 @@ impl RankableWebpage for PrecisionRankingWebpage {
     fn signals(&self) -> &EnumMap<SignalEnum, f64> {
         self.ranking.signals()
     }
+
+    fn fingerprint(&self) -> Option<Fingerprint> {
+        self.ranking.fingerprint()
+    }
+
+    fn host(&self) -> Option<NodeID> {
+        RankableWebpage::host(&self.ranking)
+    }
+
+    fn set_history_boost(&mut self, boost: f64) {
+        self.ranking.set_history_boost(boost);
+    }
 }
 
 impl PrecisionRankingWebpage {
@@ -110,8 +123,11 @@ impl RankingPipeline<PrecisionRankingWebpage> {
             scorer,
             stage_top_n: top_n_considered,
             derank_similar: true,
+            diversity: None,
             model: lambda,
             coefficients: Default::default(),
+            budget: None,
+            history: None,
         };
 
         Ok(Self {
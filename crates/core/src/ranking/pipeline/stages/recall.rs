@@ -23,10 +23,13 @@ use crate::{
     fastfield_reader,
     inverted_index::WebpagePointer,
     models::dual_encoder::DualEncoder,
+    query::optic::OpticRuleMatch,
     ranking::{
         bitvec_similarity, inbound_similarity,
         models::lambdamart::LambdaMART,
-        pipeline::{RankableWebpage, RankingPipeline, RankingStage, Recall, Scorer},
+        pipeline::{
+            diversity::Fingerprint, RankableWebpage, RankingPipeline, RankingStage, Recall, Scorer,
+        },
         SignalComputer, SignalEnum,
     },
     schema::fast_field,
@@ -38,6 +41,11 @@ use crate::{
 pub struct StoredEmbeddings(Vec<u8>);
 
 impl StoredEmbeddings {
+    #[cfg(test)]
+    pub fn new_testing(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         &self.0
     }
@@ -80,10 +88,18 @@ impl RecallRankingWebpage {
         self.local.mut_signals()
     }
 
+    pub fn unclamped_signals(&self) -> &EnumMap<SignalEnum, f64> {
+        self.local.unclamped_signals()
+    }
+
     pub fn boost(&self) -> Option<f64> {
         self.local.boost()
     }
 
+    pub fn history_boost(&self) -> Option<f64> {
+        self.local.history_boost()
+    }
+
     pub fn set_score(&mut self, score: f64) {
         self.local.set_score(score)
     }
@@ -95,6 +111,10 @@ impl RecallRankingWebpage {
     pub fn host_id(&self) -> &webgraph::NodeID {
         self.local.host_id()
     }
+
+    pub fn optic_rule_matches(&self) -> &[OpticRuleMatch] {
+        self.local.optic_rule_matches()
+    }
 }
 
 impl collector::Doc for RecallRankingWebpage {
@@ -119,13 +139,30 @@ impl RankableWebpage for RecallRankingWebpage {
     fn signals(&self) -> &EnumMap<SignalEnum, f64> {
         self.local.signals()
     }
+
+    fn fingerprint(&self) -> Option<Fingerprint> {
+        self.local.fingerprint()
+    }
+
+    fn host(&self) -> Option<webgraph::NodeID> {
+        RankableWebpage::host(&self.local)
+    }
+
+    fn set_history_boost(&mut self, boost: f64) {
+        self.local.set_history_boost(boost);
+    }
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
 pub struct LocalRecallRankingWebpage {
     pointer: WebpagePointer,
     signals: EnumMap<SignalEnum, f64>,
+    /// The values of the signals in `signals` that were clamped by the signal bounds.
+    unclamped_signals: EnumMap<SignalEnum, f64>,
     optic_boost: Option<f64>,
+    /// The boost from the click history of the user, see [`crate::ranking::pipeline::history`].
+    history_boost: Option<f64>,
+    optic_rule_matches: Vec<OpticRuleMatch>,
     title_embedding: Option<StoredEmbeddings>,
     keyword_embedding: Option<StoredEmbeddings>,
     score: f64,
@@ -142,7 +179,10 @@ impl LocalRecallRankingWebpage {
         LocalRecallRankingWebpage {
             pointer,
             signals,
+            unclamped_signals: EnumMap::new(),
             optic_boost: None,
+            history_boost: None,
+            optic_rule_matches: Vec::new(),
             title_embedding: None,
             keyword_embedding: None,
             score,
@@ -177,8 +217,11 @@ impl LocalRecallRankingWebpage {
 
         let mut res = LocalRecallRankingWebpage {
             signals: EnumMap::new(),
+            unclamped_signals: EnumMap::new(),
             score: pointer.score.total,
             optic_boost: None,
+            history_boost: None,
+            optic_rule_matches: Vec::new(),
             pointer: pointer.clone(),
             title_embedding: title_embedding.map(StoredEmbeddings),
             keyword_embedding: keyword_embedding.map(StoredEmbeddings),
@@ -188,12 +231,19 @@ impl LocalRecallRankingWebpage {
         for computed_signal in computer.compute_signals(pointer.address.doc_id).flatten() {
             res.signals
                 .insert(computed_signal.signal, computed_signal.score);
+
+            if let Some(unclamped) = computed_signal.unclamped_score {
+                res.unclamped_signals
+                    .insert(computed_signal.signal, unclamped);
+            }
         }
 
         if let Some(boost) = computer.boosts(pointer.address.doc_id) {
             res.optic_boost = Some(boost);
         }
 
+        res.optic_rule_matches = computer.optic_rule_matches(pointer.address.doc_id);
+
         res
     }
 
@@ -221,10 +271,18 @@ impl LocalRecallRankingWebpage {
         &mut self.signals
     }
 
+    pub fn unclamped_signals(&self) -> &EnumMap<SignalEnum, f64> {
+        &self.unclamped_signals
+    }
+
     pub fn boost(&self) -> Option<f64> {
         self.optic_boost
     }
 
+    pub fn history_boost(&self) -> Option<f64> {
+        self.history_boost
+    }
+
     pub fn set_score(&mut self, score: f64) {
         self.score = score;
     }
@@ -232,6 +290,10 @@ impl LocalRecallRankingWebpage {
     pub fn host_id(&self) -> &webgraph::NodeID {
         &self.host_id
     }
+
+    pub fn optic_rule_matches(&self) -> &[OpticRuleMatch] {
+        &self.optic_rule_matches
+    }
 }
 
 impl RankableWebpage for LocalRecallRankingWebpage {
@@ -246,6 +308,21 @@ impl RankableWebpage for LocalRecallRankingWebpage {
     fn signals(&self) -> &EnumMap<SignalEnum, f64> {
         &self.signals
     }
+
+    fn fingerprint(&self) -> Option<Fingerprint> {
+        self.keyword_embedding
+            .as_ref()
+            .or(self.title_embedding.as_ref())
+            .map(Fingerprint::from_embedding)
+    }
+
+    fn host(&self) -> Option<webgraph::NodeID> {
+        Some(self.host_id)
+    }
+
+    fn set_history_boost(&mut self, boost: f64) {
+        self.history_boost = Some(boost);
+    }
 }
 
 impl collector::Doc for LocalRecallRankingWebpage {
@@ -270,8 +347,11 @@ impl RankingPipeline<LocalRecallRankingWebpage> {
                 as Box<dyn Scorer<LocalRecallRankingWebpage>>,
             stage_top_n,
             derank_similar: true,
+            diversity: None,
             model: lambdamart,
             coefficients: Default::default(),
+            budget: None,
+            history: None,
         };
 
         Self {
@@ -312,8 +392,11 @@ impl RankingPipeline<api::ScoredWebpagePointer> {
             )),
             stage_top_n,
             derank_similar: true,
+            diversity: None,
             model: lambdamart,
             coefficients: Default::default(),
+            budget: None,
+            history: None,
         };
 
         Self {
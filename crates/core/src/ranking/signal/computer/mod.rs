@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::query::optic::AsSearchableRule;
+use crate::query::optic::{AsSearchableRule, OpticRuleMatch};
 use crate::query::{Query, MAX_TERMS_FOR_NGRAM_LOOKUPS};
 use crate::ranking::bm25f::MultiBm25FWeight;
 use crate::schema::text_field::TextField;
@@ -39,7 +39,7 @@ use crate::webpage::region::RegionCount;
 use crate::ranking::bm25::MultiBm25Weight;
 use crate::ranking::models::linear::LinearRegression;
 
-use super::{ComputedSignal, Signal, SignalCoefficient, SignalEnum};
+use super::{ComputedSignal, Signal, SignalBounds, SignalCoefficient, SignalEnum};
 
 mod order;
 pub use order::SignalComputeOrder;
@@ -47,6 +47,10 @@ pub use order::SignalComputeOrder;
 #[derive(Clone)]
 pub struct TextFieldData {
     postings: Vec<SegmentPostings>,
+    /// The indices of the postings of the distinct terms of the query, not the synonyms.
+    query_postings: Vec<usize>,
+    /// Number of distinct terms of the query in the field, including those without postings.
+    num_query_terms: usize,
     bm25: MultiBm25Weight,
     bm25f: MultiBm25FWeight,
     fieldnorm_reader: FieldNormReader,
@@ -108,7 +112,72 @@ impl TextFieldData {
             }),
         ) as f64
     }
+
+    /// How close together the terms of the query are in the field of the document.
+    /// `None` if the query has fewer than two terms, as there is nothing to be close to.
+    pub fn proximity(&mut self, doc: DocId) -> Option<f64> {
+        if self.num_query_terms < 2 {
+            return None;
+        }
+
+        let mut positions = Vec::new();
+        let mut term_positions = Vec::new();
+
+        for (term, i) in self.query_postings.iter().enumerate() {
+            let posting = &mut self.postings[*i];
+
+            if posting.doc() == doc || (posting.doc() < doc && posting.seek(doc) == doc) {
+                posting.positions(&mut term_positions);
+                positions.extend(term_positions.iter().map(|pos| (*pos, term)));
+            }
+        }
+
+        Some(proximity(positions, self.num_query_terms))
+    }
+}
+
+/// The proximity of the terms from their positions in a field. The score is 1 if all the
+/// terms are next to each other and decreases with the length of the shortest span of the
+/// field that contains all the terms that are in the field. It is further scaled by the
+/// fraction of the terms that are in the field, and is 0 if fewer than two of them are.
+fn proximity(mut positions: Vec<(u32, usize)>, num_terms: usize) -> f64 {
+    positions.sort_unstable();
+
+    let num_present = positions.iter().map(|(_, term)| *term).unique().count();
+    if num_present < 2 {
+        return 0.0;
+    }
+
+    let mut counts = vec![0; num_terms];
+    let mut covered = 0;
+    let mut start = 0;
+    let mut shortest = u32::MAX;
+
+    for &(end, term) in &positions {
+        if counts[term] == 0 {
+            covered += 1;
+        }
+        counts[term] += 1;
+
+        while covered == num_present {
+            shortest = shortest.min(end - positions[start].0 + 1);
+
+            let (_, term) = positions[start];
+            counts[term] -= 1;
+            if counts[term] == 0 {
+                covered -= 1;
+            }
+            start += 1;
+        }
+    }
+
+    let gaps = (num_present - 1) as f64;
+    let closeness = gaps / (shortest as f64 - 1.0).max(gaps);
+    let coverage = gaps / (num_terms - 1) as f64;
+
+    closeness * coverage
 }
+
 pub struct RuleBoost {
     docset: Box<dyn Scorer>,
     boost: f64,
@@ -118,9 +187,16 @@ pub struct OpticBoosts {
     rules: Vec<RuleBoost>,
 }
 
+pub struct RuleMatcher {
+    docset: Box<dyn Scorer>,
+    rule: usize,
+    action: optics::Action,
+}
+
 pub struct SegmentReader {
     text_fields: EnumMap<TextFieldEnum, TextFieldData>,
     optic_boosts: OpticBoosts,
+    optic_rule_matchers: Vec<RuleMatcher>,
     fastfield_reader: Arc<fastfield_reader::SegmentReader>,
 }
 
@@ -137,9 +213,15 @@ impl SegmentReader {
 #[derive(Clone)]
 pub struct QueryData {
     simple_terms: Vec<String>,
+    expanded_terms: Vec<String>,
+    expansion_boost: f32,
+    segmented_terms: Vec<String>,
+    segmentation_boost: f32,
     optic_rules: Vec<optics::Rule>,
+    debug_optic_rules: Vec<optics::Rule>,
     selected_region: Option<crate::webpage::Region>,
     lang: Option<whatlang::Lang>,
+    verbatim: bool,
 }
 impl QueryData {
     pub fn selected_region(&self) -> Option<crate::webpage::Region> {
@@ -156,6 +238,7 @@ pub struct SignalComputer {
     region_count: Option<Arc<RegionCount>>,
     current_timestamp: Option<usize>,
     linear_regression: Option<Arc<LinearRegression>>,
+    signal_bounds: Option<Arc<SignalBounds>>,
     order: SignalComputeOrder,
 }
 
@@ -170,6 +253,7 @@ impl Clone for SignalComputer {
             region_count: self.region_count.clone(),
             current_timestamp: self.current_timestamp,
             linear_regression: self.linear_regression.clone(),
+            signal_bounds: self.signal_bounds.clone(),
             order: self.order.clone(),
         }
     }
@@ -204,6 +288,10 @@ impl SignalComputer {
 
         let query = query.as_ref().map(|q| QueryData {
             simple_terms: q.simple_terms().to_vec(),
+            expanded_terms: q.expanded_terms().to_vec(),
+            expansion_boost: q.expansion_boost(),
+            segmented_terms: q.segmented_terms().to_vec(),
+            segmentation_boost: q.segmentation_boost(),
             optic_rules: q
                 .optics()
                 .iter()
@@ -214,8 +302,10 @@ impl SignalComputer {
                 })
                 .cloned()
                 .collect(),
+            debug_optic_rules: q.debug_optic().map(|o| o.rules.clone()).unwrap_or_default(),
             selected_region: q.region().cloned(),
             lang: q.lang(),
+            verbatim: q.is_verbatim(),
         });
 
         let mut s = Self {
@@ -226,6 +316,7 @@ impl SignalComputer {
             region_count: None,
             current_timestamp: None,
             linear_regression: None,
+            signal_bounds: None,
             query_data: query,
             order: SignalComputeOrder::empty(),
         };
@@ -257,6 +348,11 @@ impl SignalComputer {
                             continue;
                         }
 
+                        // the stemmed fields would also score other forms of the words
+                        if query.verbatim && text_field.is_stemmed() {
+                            continue;
+                        }
+
                         let simple_query = itertools::intersperse(
                             query.simple_terms.iter().map(|s| s.as_str()),
                             " ",
@@ -276,34 +372,80 @@ impl SignalComputer {
                             continue;
                         }
 
+                        let num_terms = terms.len();
+                        let num_query_terms = terms.iter().unique().count();
+
+                        let mut boosts = vec![1.0; terms.len()];
+
+                        // the expansions are single terms, so they only match the fields of single terms
+                        if text_field.ngram_size() == 1 {
+                            let mut tokenizer = text_field.tokenizer(query.lang.as_ref());
+
+                            let expansions = query
+                                .expanded_terms
+                                .iter()
+                                .map(|term| (term, query.expansion_boost))
+                                .chain(
+                                    query
+                                        .segmented_terms
+                                        .iter()
+                                        .map(|term| (term, query.segmentation_boost)),
+                                );
+
+                            for (expanded, boost) in expansions {
+                                let mut stream = tokenizer.token_stream(expanded);
+
+                                while let Some(token) = stream.next() {
+                                    let term =
+                                        tantivy::Term::from_field_text(tv_field, &token.text);
+
+                                    if !terms.contains(&term) {
+                                        terms.push(term);
+                                        boosts.push(boost);
+                                    }
+                                }
+                            }
+                        }
+
                         let fieldnorm_reader = segment_reader.get_fieldnorms_reader(tv_field)?;
                         let inverted_index = segment_reader.inverted_index(tv_field)?;
 
                         let mut matching_terms = Vec::with_capacity(terms.len());
+                        let mut matching_boosts = Vec::with_capacity(terms.len());
                         let mut postings = Vec::with_capacity(terms.len());
-                        for term in &terms {
+                        let mut query_postings = Vec::with_capacity(num_query_terms);
+                        for (i, (term, boost)) in terms.iter().zip(&boosts).enumerate() {
                             if let Some(p) =
                                 inverted_index.read_postings(term, text_field.record_option())?
                             {
+                                if i < num_terms && !matching_terms.contains(term) {
+                                    query_postings.push(postings.len());
+                                }
+
                                 postings.push(p);
                                 matching_terms.push(term.clone());
+                                matching_boosts.push(*boost);
                             }
                         }
                         let bm25 = MultiBm25Weight::for_terms(
                             tv_searcher,
                             &matching_terms,
                             text_field.bm25_constants(),
-                        )?;
+                        )?
+                        .with_boosts(&matching_boosts);
                         let bm25f = MultiBm25FWeight::for_terms(
                             tv_searcher,
                             &matching_terms,
                             text_field.bm25_constants(),
-                        );
+                        )
+                        .with_boosts(&matching_boosts);
 
                         text_fields.insert(
                             text_field,
                             TextFieldData {
                                 postings,
+                                query_postings,
+                                num_query_terms,
                                 bm25,
                                 bm25f,
                                 fieldnorm_reader,
@@ -349,6 +491,42 @@ impl SignalComputer {
         optic_rule_boosts
     }
 
+    fn prepare_optic_debug(
+        &self,
+        tv_searcher: &tantivy::Searcher,
+        segment_reader: &tantivy::SegmentReader,
+        fastfield_reader: &fastfield_reader::FastFieldReader,
+    ) -> Vec<RuleMatcher> {
+        let mut matchers = Vec::new();
+
+        if let Some(query) = &self.query_data {
+            matchers = query
+                .debug_optic_rules
+                .iter()
+                .enumerate()
+                .filter_map(|(i, rule)| {
+                    rule.as_searchable_rule(tv_searcher.schema(), fastfield_reader)
+                        .map(|(_, searchable)| (i, rule.action, searchable))
+                })
+                .map(|(i, action, searchable)| RuleMatcher {
+                    docset: searchable
+                        .query
+                        .weight(tantivy::query::EnableScoring::Enabled {
+                            searcher: tv_searcher,
+                            statistics_provider: tv_searcher,
+                        })
+                        .unwrap()
+                        .scorer(segment_reader, 0.0)
+                        .unwrap(),
+                    rule: i,
+                    action,
+                })
+                .collect();
+        }
+
+        matchers
+    }
+
     pub fn register_segment(
         &mut self,
         tv_searcher: &tantivy::Searcher,
@@ -358,6 +536,8 @@ impl SignalComputer {
         let fastfield_segment_reader = fastfield_reader.get_segment(&segment_reader.segment_id());
         let text_fields = self.prepare_textfields(tv_searcher, segment_reader)?;
         let optic_rule_boosts = self.prepare_optic(tv_searcher, segment_reader, fastfield_reader);
+        let optic_rule_matchers =
+            self.prepare_optic_debug(tv_searcher, segment_reader, fastfield_reader);
 
         self.segment_reader = Some(RefCell::new(SegmentReader {
             text_fields,
@@ -365,6 +545,7 @@ impl SignalComputer {
             optic_boosts: OpticBoosts {
                 rules: optic_rule_boosts,
             },
+            optic_rule_matchers,
         }));
 
         Ok(())
@@ -382,6 +563,11 @@ impl SignalComputer {
         self.linear_regression = Some(linear_model);
     }
 
+    /// Clamp the computed signals to `bounds` before they are weighted.
+    pub fn set_signal_bounds(&mut self, bounds: Arc<SignalBounds>) {
+        self.signal_bounds = Some(bounds);
+    }
+
     /// Computes the scored signals for a given document.
     ///
     /// Important: This function assues that the docs a scored in ascending order of docid
@@ -390,7 +576,12 @@ impl SignalComputer {
     /// be returned.
     /// This function also assumes that the segment reader has been set.
     pub fn compute_signals(&self, doc: DocId) -> impl Iterator<Item = Option<ComputedSignal>> + '_ {
-        self.order.compute(doc, self)
+        self.order.compute(doc, self).map(|computed| {
+            computed.map(|computed| match &self.signal_bounds {
+                Some(bounds) => bounds.apply(computed),
+                None => computed,
+            })
+        })
     }
 
     pub fn boosts(&mut self, doc: DocId) -> Option<f64> {
@@ -421,12 +612,36 @@ impl SignalComputer {
         })
     }
 
+    /// Returns the rules of the debugged optic that matches the document.
+    /// Empty unless the query has `optic_debug` enabled.
+    pub fn optic_rule_matches(&mut self, doc: DocId) -> Vec<OpticRuleMatch> {
+        let mut res = Vec::new();
+
+        if let Some(segment_reader) = self.segment_reader.as_ref() {
+            for matcher in &mut segment_reader.borrow_mut().optic_rule_matchers {
+                if matcher.docset.doc() > doc {
+                    continue;
+                }
+
+                if matcher.docset.doc() == doc || matcher.docset.seek(doc) == doc {
+                    res.push(OpticRuleMatch::new(matcher.rule, &matcher.action));
+                }
+            }
+        }
+
+        res
+    }
+
     pub fn precompute_score(&self, webpage: &Webpage) -> f64 {
         SignalEnum::all()
             .filter_map(|signal| {
                 signal
                     .precompute(webpage, self)
-                    .map(|score| ComputedSignal { signal, score })
+                    .map(|score| ComputedSignal {
+                        signal,
+                        score,
+                        unclamped_score: None,
+                    })
             })
             .map(|computed| self.coefficient(&computed.signal) * computed.score)
             .sum()
@@ -86,6 +86,7 @@ impl SignalComputeOrder {
                             .map(|score| ComputedSignal {
                                 signal: *signal,
                                 score,
+                                unclamped_score: None,
                             })
                     }),
             )
@@ -127,6 +128,7 @@ impl NGramComputeOrder {
                     .map(|score| ComputedSignal {
                         signal: *signal,
                         score,
+                        unclamped_score: None,
                     })
                     .map(|mut c| {
                         c.score *= NGRAM_DAMPENING.powi(hits);
@@ -16,6 +16,7 @@
 
 use crate::enum_map::EnumMap;
 
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use thiserror::Error;
@@ -109,10 +110,103 @@ impl SignalCoefficient {
     }
 }
 
+/// Bounds a signal is clamped to before it is weighted, so a single document
+/// with an extreme value for one signal cannot dominate the ranking.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct SignalBound {
+    #[serde(default)]
+    pub floor: Option<f64>,
+    #[serde(default)]
+    pub ceiling: Option<f64>,
+}
+
+impl SignalBound {
+    pub fn clamp(&self, score: f64) -> f64 {
+        let mut score = score;
+
+        if let Some(floor) = self.floor {
+            score = score.max(floor);
+        }
+
+        if let Some(ceiling) = self.ceiling {
+            score = score.min(ceiling);
+        }
+
+        score
+    }
+}
+
+/// The bounds of each signal. Signals without bounds are left untouched.
+#[derive(
+    Debug, Clone, Default, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+)]
+pub struct SignalBounds {
+    map: EnumMap<SignalEnum, SignalBound>,
+}
+
+impl SignalBounds {
+    pub fn new(bounds: impl Iterator<Item = (SignalEnum, SignalBound)>) -> Self {
+        let mut map = EnumMap::default();
+
+        for (signal, bound) in bounds {
+            map.insert(signal, bound);
+        }
+
+        Self { map }
+    }
+
+    /// Parse the bounds from a map of signal names as used in the config.
+    pub fn from_names(bounds: &HashMap<String, SignalBound>) -> Result<Self, Error> {
+        let bounds = bounds
+            .iter()
+            .map(|(name, bound)| {
+                SignalEnumDiscriminants::from_str(name)
+                    .map(|signal| (SignalEnum::from(signal), *bound))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::new(bounds.into_iter()))
+    }
+
+    pub fn get(&self, signal: &SignalEnum) -> Option<&SignalBound> {
+        self.map.get(*signal)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.len() == 0
+    }
+
+    pub fn apply(&self, mut computed: ComputedSignal) -> ComputedSignal {
+        if let Some(bound) = self.get(&computed.signal) {
+            let clamped = bound.clamp(computed.score);
+
+            if clamped != computed.score {
+                computed.unclamped_score = Some(computed.score);
+                computed.score = clamped;
+            }
+        }
+
+        computed
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct ComputedSignal {
     pub signal: SignalEnum,
     pub score: f64,
+    /// The score before it was clamped by the signal bounds.
+    /// `None` if the score was within the bounds.
+    pub unclamped_score: Option<f64>,
 }
 
 #[derive(
@@ -129,4 +223,7 @@ pub struct ComputedSignal {
 pub struct SignalScore {
     pub coefficient: f64,
     pub value: f64,
+    /// The value before it was clamped by the signal bounds, if it was clamped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unclamped_value: Option<f64>,
 }
@@ -347,11 +347,7 @@ impl Signal for UpdateTimestamp {
     }
 
     fn precompute(self, webpage: &Webpage, signal_computer: &SignalComputer) -> Option<f64> {
-        let update_timestamp = webpage
-            .html
-            .updated_time()
-            .map(|date| date.timestamp().max(0))
-            .unwrap_or(0) as usize;
+        let update_timestamp = webpage.content_timestamp().unwrap_or(0) as usize;
 
         Some(score_timestamp(update_timestamp, signal_computer))
     }
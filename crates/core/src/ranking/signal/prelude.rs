@@ -88,6 +88,7 @@ pub enum SignalEnum {
     Bm25StemmedTitle,
     Bm25StemmedCleanBody,
     Bm25AllBody,
+    Bm25Boilerplate,
     Bm25Keywords,
     Bm25BacklinkText,
     IdfSumUrl,
@@ -119,6 +120,7 @@ pub enum SignalEnum {
     LinkDensity,
     TitleEmbeddingSimilarity,
     KeywordEmbeddingSimilarity,
+    TermProximity,
 }
 
 enum_dispatch_from_discriminant!(SignalEnumDiscriminants => SignalEnum,
@@ -133,6 +135,7 @@ enum_dispatch_from_discriminant!(SignalEnumDiscriminants => SignalEnum,
     Bm25StemmedTitle,
     Bm25StemmedCleanBody,
     Bm25AllBody,
+    Bm25Boilerplate,
     Bm25Keywords,
     Bm25BacklinkText,
     IdfSumUrl,
@@ -164,6 +167,7 @@ enum_dispatch_from_discriminant!(SignalEnumDiscriminants => SignalEnum,
     LinkDensity,
     TitleEmbeddingSimilarity,
     KeywordEmbeddingSimilarity,
+    TermProximity,
 ]);
 
 impl SignalEnum {
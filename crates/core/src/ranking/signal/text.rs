@@ -343,6 +343,40 @@ impl Signal for Bm25AllBody {
     }
 }
 
+/// Matches outside of the main content of the page, like in the navigation or the footer,
+/// say little about what the page is about, so they only count a little.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct Bm25Boilerplate;
+impl Signal for Bm25Boilerplate {
+    fn default_coefficient(&self) -> f64 {
+        0.0005
+    }
+
+    fn as_field(&self) -> Option<Field> {
+        Some(Field::Text(schema::text_field::Boilerplate.into()))
+    }
+
+    fn compute(&self, doc: DocId, signal_computer: &SignalComputer) -> Option<f64> {
+        let mut seg_reader = signal_computer.segment_reader().unwrap().borrow_mut();
+
+        seg_reader
+            .text_fields_mut()
+            .get_mut(self.as_textfield().unwrap())
+            .map(|field| field.bm25(doc))
+    }
+}
+
 #[derive(
     Debug,
     Clone,
@@ -840,3 +874,43 @@ impl Signal for KeywordEmbeddingSimilarity {
         None // computed in later ranking stage
     }
 }
+
+/// How close together the terms of the query are in the title or the clean body of the
+/// page, whichever has them closest. Only computed if the query sets a proximity weight,
+/// see [`crate::searcher::SearchQuery::proximity_weight`].
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct TermProximity;
+impl Signal for TermProximity {
+    fn default_coefficient(&self) -> f64 {
+        0.0
+    }
+
+    fn as_field(&self) -> Option<Field> {
+        None
+    }
+
+    fn compute(&self, doc: DocId, signal_computer: &SignalComputer) -> Option<f64> {
+        let mut seg_reader = signal_computer.segment_reader().unwrap().borrow_mut();
+        let text_fields = seg_reader.text_fields_mut();
+
+        [
+            schema::text_field::Title.into(),
+            schema::text_field::CleanBody.into(),
+        ]
+        .into_iter()
+        .filter_map(|field| text_fields.get_mut(field))
+        .filter_map(|field| field.proximity(doc))
+        .reduce(f64::max)
+    }
+}
@@ -0,0 +1,800 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Warm standby replication of a search index from a primary search server.
+//!
+//! The primary describes its last commit in a [`Manifest`]: the files of the committed
+//! segments, including the deletes of the segments, and the metadata of the index, all
+//! with their checksums. A replica polls the manifest and downloads the commit into a new
+//! generation next to the one it is serving. Segment files never change once they are
+//! written, so the files the replica already has are linked from its current generation
+//! and only new files are downloaded. The downloads are fetched in ranges and resume from
+//! the partially downloaded file if a poll fails.
+//!
+//! When every file is downloaded and verified, the new generation is opened and swapped in
+//! for the searches that start after it. Searches that are running on the previous
+//! generation finish on it, so the previous generation is kept on disk until the next swap.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    future::Future,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    config::{RetrievalConfig, SnippetConfig},
+    index::{Index, FRESHNESS_FILE_NAME, INVERTED_INDEX_SUBFOLDER_NAME, REGION_COUNT_FILE_NAME},
+    inverted_index::SCHEMA_VERSION_FILE,
+    Result,
+};
+
+const META_FILE_NAME: &str = "meta.json";
+const MANIFEST_FILE_NAME: &str = "replication_manifest.json";
+const CURRENT_FILE_NAME: &str = "CURRENT";
+const GENERATION_PREFIX: &str = "gen-";
+
+/// The most bytes the primary reads for a single range, no matter how many the replica asks for.
+pub const MAX_RANGE_LEN: u64 = 16 * 1024 * 1024;
+
+/// A file of a committed segment. The path is relative to the root of the index.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct SegmentFile {
+    pub path: String,
+    pub len: u64,
+    /// Hex encoded md5 digest of the content.
+    pub checksum: String,
+}
+
+/// A file that describes the index, like the segments of the commit or the region counts.
+/// They are rewritten by every commit, so their content is sent with the manifest.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct MetadataFile {
+    pub path: String,
+    pub content: Vec<u8>,
+    pub checksum: String,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct Manifest {
+    /// The opstamp of the commit. Merges of segments keep the opstamp, so a replica
+    /// compares the files of the manifests to know if the index has changed.
+    pub generation: u64,
+    pub segment_files: Vec<SegmentFile>,
+    pub metadata: Vec<MetadataFile>,
+}
+
+/// Where a replica gets the commits of the primary from.
+pub trait SegmentSource {
+    fn manifest(&self) -> impl Future<Output = Result<Manifest>> + Send;
+
+    /// At most `len` bytes of the segment file starting at `offset`. A source may return fewer
+    /// bytes, in which case the rest is read from the next range.
+    fn read_range(
+        &self,
+        path: &str,
+        offset: u64,
+        len: u64,
+    ) -> impl Future<Output = Result<Vec<u8>>> + Send;
+}
+
+fn checksum(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut context = md5::Context::new();
+    let mut buf = vec![0; 1024 * 1024];
+
+    loop {
+        let read = file.read(&mut buf)?;
+
+        if read == 0 {
+            break;
+        }
+
+        context.consume(&buf[..read]);
+    }
+
+    Ok(format!("{:x}", context.compute()))
+}
+
+/// Only accept paths inside the index, so a manifest can't point outside of it.
+fn relative_path(path: &str) -> Result<&Path> {
+    let relative = Path::new(path);
+
+    if relative.as_os_str().is_empty()
+        || !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!("{path} is not a path inside the index");
+    }
+
+    Ok(relative)
+}
+
+/// Serves the commits of an index to the replicas.
+#[derive(Default)]
+pub struct Primary {
+    // segment files never change, so their checksums are computed once
+    checksums: Mutex<HashMap<String, String>>,
+}
+
+impl Primary {
+    fn segment_paths(metas: &tantivy::IndexMeta) -> Vec<String> {
+        metas
+            .segments
+            .iter()
+            .flat_map(|segment| segment.list_files())
+            .map(|file| {
+                Path::new(INVERTED_INDEX_SUBFOLDER_NAME)
+                    .join(file)
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    pub fn manifest(&self, index: &Index) -> Result<Manifest> {
+        let metas = index.inverted_index.load_metas()?;
+        let root = index.path();
+
+        let mut segment_files = Vec::new();
+        let mut seen_paths = HashSet::new();
+
+        for path in Self::segment_paths(&metas) {
+            let full_path = root.join(&path);
+
+            // not every segment has every component, e.g. segments without deletes
+            if !full_path.exists() {
+                continue;
+            }
+
+            let len = fs::metadata(&full_path)?.len();
+
+            let cached = self
+                .checksums
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&path)
+                .cloned();
+
+            let checksum = match cached {
+                Some(checksum) => checksum,
+                None => {
+                    let checksum = checksum(&full_path)?;
+                    self.checksums
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(path.clone(), checksum.clone());
+                    checksum
+                }
+            };
+
+            seen_paths.insert(path.clone());
+            segment_files.push(SegmentFile {
+                path,
+                len,
+                checksum,
+            });
+        }
+
+        segment_files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        // forget the checksums of segments that have been merged away
+        self.checksums
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|path, _| seen_paths.contains(path));
+
+        // the meta is serialized from the loaded commit instead of read from disk,
+        // so it lists exactly the segments of the manifest even if a merge finishes meanwhile.
+        let mut meta = serde_json::to_vec_pretty(&metas)?;
+        writeln!(&mut meta)?;
+
+        let mut metadata = vec![(
+            Path::new(INVERTED_INDEX_SUBFOLDER_NAME).join(META_FILE_NAME),
+            meta,
+        )];
+
+        for path in [
+            Path::new(INVERTED_INDEX_SUBFOLDER_NAME).join(SCHEMA_VERSION_FILE),
+            PathBuf::from(REGION_COUNT_FILE_NAME),
+            PathBuf::from(FRESHNESS_FILE_NAME),
+        ] {
+            let full_path = root.join(&path);
+
+            if full_path.exists() {
+                metadata.push((path, fs::read(full_path)?));
+            }
+        }
+
+        Ok(Manifest {
+            generation: metas.opstamp,
+            segment_files,
+            metadata: metadata
+                .into_iter()
+                .map(|(path, content)| MetadataFile {
+                    path: path.to_string_lossy().to_string(),
+                    checksum: format!("{:x}", md5::compute(&content)),
+                    content,
+                })
+                .collect(),
+        })
+    }
+
+    pub fn read_range(&self, index: &Index, path: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        relative_path(path)?;
+
+        let metas = index.inverted_index.load_metas()?;
+        if !Self::segment_paths(&metas).iter().any(|p| p == path) {
+            bail!("{path} is not a file of a committed segment");
+        }
+
+        let mut file = File::open(index.path().join(path))?;
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut buf = Vec::new();
+        file.take(len.min(MAX_RANGE_LEN)).read_to_end(&mut buf)?;
+
+        Ok(buf)
+    }
+}
+
+/// A primary in the same process.
+pub struct LocalSource {
+    primary: Primary,
+    index: ReplicatedIndex,
+}
+
+impl LocalSource {
+    pub fn new(index: ReplicatedIndex) -> Self {
+        Self {
+            primary: Primary::default(),
+            index,
+        }
+    }
+}
+
+impl SegmentSource for LocalSource {
+    async fn manifest(&self) -> Result<Manifest> {
+        self.primary.manifest(&self.index.read())
+    }
+
+    async fn read_range(&self, path: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        self.primary
+            .read_range(&self.index.read(), path, offset, len)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Settings {
+    snippet: Option<SnippetConfig>,
+    webpage_cache_capacity: Option<usize>,
+    store_block_cache_capacity: Option<usize>,
+    retrieval: Option<RetrievalConfig>,
+}
+
+impl Settings {
+    fn apply(&self, index: &mut Index) -> Result<()> {
+        if let Some(config) = &self.snippet {
+            index.inverted_index.set_snippet_config(config.clone());
+        }
+
+        if let Some(capacity) = self.webpage_cache_capacity {
+            index.inverted_index.set_webpage_cache_capacity(capacity);
+        }
+
+        if let Some(num_blocks) = self.store_block_cache_capacity {
+            index
+                .inverted_index
+                .set_store_block_cache_capacity(num_blocks)?;
+        }
+
+        if let Some(config) = &self.retrieval {
+            index.inverted_index.set_retrieval_config(config)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An index that can be swapped for a newer generation while it is searched.
+///
+/// The settings of the index are kept, so they are applied to the generations that
+/// are swapped in later.
+#[derive(Clone)]
+pub struct ReplicatedIndex {
+    index: Arc<RwLock<Arc<Index>>>,
+    settings: Arc<Mutex<Settings>>,
+}
+
+impl ReplicatedIndex {
+    pub fn new(index: Index) -> Self {
+        Self {
+            index: Arc::new(RwLock::new(Arc::new(index))),
+            settings: Arc::new(Mutex::new(Settings::default())),
+        }
+    }
+
+    /// The current generation. Searches keep the generation they started
+    /// on even if a newer generation is swapped in meanwhile.
+    pub fn read(&self) -> Arc<Index> {
+        Arc::clone(&self.index.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn update_settings(&self, update: impl FnOnce(&mut Settings)) -> Result<()> {
+        let mut settings = self.settings.lock().unwrap_or_else(|e| e.into_inner());
+        update(&mut settings);
+
+        let mut index = self.index.write().unwrap_or_else(|e| e.into_inner());
+        match Arc::get_mut(&mut index) {
+            Some(index) => settings.apply(index),
+            None => {
+                tracing::warn!(
+                    "the index is being searched, the settings apply from the next generation"
+                );
+                Ok(())
+            }
+        }
+    }
+
+    pub fn set_snippet_config(&self, config: SnippetConfig) {
+        self.update_settings(|settings| settings.snippet = Some(config))
+            .ok();
+    }
+
+    pub fn set_webpage_cache_capacity(&self, capacity: usize) {
+        self.update_settings(|settings| settings.webpage_cache_capacity = Some(capacity))
+            .ok();
+    }
+
+    pub fn set_store_block_cache_capacity(&self, num_blocks: usize) -> Result<()> {
+        self.update_settings(|settings| settings.store_block_cache_capacity = Some(num_blocks))
+    }
+
+    pub fn set_retrieval_config(&self, config: RetrievalConfig) -> Result<()> {
+        self.update_settings(|settings| settings.retrieval = Some(config))
+    }
+
+    /// Serve `index` to the searches that start from now on.
+    fn swap(&self, mut index: Index) -> Result<()> {
+        self.settings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .apply(&mut index)?;
+
+        // warm the new generation before it is swapped in, so the first
+        // searches on it don't pay for the warmup.
+        index.inverted_index.fastfield_reader().warm_all();
+
+        let previous = std::mem::replace(
+            &mut *self.index.write().unwrap_or_else(|e| e.into_inner()),
+            Arc::new(index),
+        );
+        drop(previous);
+
+        Ok(())
+    }
+}
+
+struct Generation {
+    number: u64,
+    manifest: Option<Manifest>,
+}
+
+impl Generation {
+    fn dir_name(number: u64) -> String {
+        format!("{GENERATION_PREFIX}{number}")
+    }
+}
+
+/// Keeps an index in `path` up to date with the commits of a primary.
+pub struct Replica<S> {
+    source: S,
+    path: PathBuf,
+    index: ReplicatedIndex,
+    current: Generation,
+    chunk_size: u64,
+}
+
+impl<S: SegmentSource> Replica<S> {
+    /// Open the generation the replica served last, or an empty index if it hasn't
+    /// replicated anything yet.
+    pub fn open<P: AsRef<Path>>(path: P, source: S, chunk_size: u64) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path)?;
+
+        let current = match fs::read_to_string(path.join(CURRENT_FILE_NAME)) {
+            Ok(name) => {
+                let number = name
+                    .trim()
+                    .strip_prefix(GENERATION_PREFIX)
+                    .and_then(|number| number.parse().ok())
+                    .ok_or_else(|| anyhow!("{name} is not a generation"))?;
+
+                let manifest = serde_json::from_slice(&fs::read(
+                    path.join(Generation::dir_name(number))
+                        .join(MANIFEST_FILE_NAME),
+                )?)?;
+
+                Generation {
+                    number,
+                    manifest: Some(manifest),
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Generation {
+                number: 0,
+                manifest: None,
+            },
+            Err(err) => return Err(err.into()),
+        };
+
+        let index = Index::open(path.join(Generation::dir_name(current.number)))?;
+
+        Ok(Self {
+            source,
+            path,
+            index: ReplicatedIndex::new(index),
+            current,
+            chunk_size: chunk_size.clamp(1, MAX_RANGE_LEN),
+        })
+    }
+
+    pub fn index(&self) -> ReplicatedIndex {
+        self.index.clone()
+    }
+
+    fn generation_path(&self, number: u64) -> PathBuf {
+        self.path.join(Generation::dir_name(number))
+    }
+
+    /// Replicate the latest commit of the primary if it differs from the served generation.
+    /// Returns whether a new generation was swapped in.
+    ///
+    /// A failed poll keeps serving the current generation, and the next poll resumes
+    /// the downloads.
+    pub async fn poll(&mut self) -> Result<bool> {
+        let manifest = self.source.manifest().await?;
+
+        if self.current.manifest.as_ref() == Some(&manifest) {
+            return Ok(false);
+        }
+
+        let number = self.current.number + 1;
+        let dir = self.generation_path(number);
+        fs::create_dir_all(dir.join(INVERTED_INDEX_SUBFOLDER_NAME))?;
+
+        for file in &manifest.segment_files {
+            self.fetch(&dir, file).await?;
+        }
+
+        for file in &manifest.metadata {
+            if format!("{:x}", md5::compute(&file.content)) != file.checksum {
+                bail!("checksum mismatch for {}", file.path);
+            }
+
+            fs::write(dir.join(relative_path(&file.path)?), &file.content)?;
+        }
+
+        remove_unlisted(&dir.join(INVERTED_INDEX_SUBFOLDER_NAME), &dir, &manifest)?;
+        fs::write(
+            dir.join(MANIFEST_FILE_NAME),
+            serde_json::to_vec_pretty(&manifest)?,
+        )?;
+
+        self.index.swap(Index::open(&dir)?)?;
+
+        let current = self.path.join(CURRENT_FILE_NAME);
+        let tmp = current.with_extension("tmp");
+        fs::write(&tmp, Generation::dir_name(number))?;
+        fs::rename(tmp, current)?;
+
+        let previous = self.current.number;
+        self.current = Generation {
+            number,
+            manifest: Some(manifest),
+        };
+
+        self.remove_old_generations(previous)?;
+
+        Ok(true)
+    }
+
+    async fn fetch(&self, dir: &Path, file: &SegmentFile) -> Result<()> {
+        let target = dir.join(relative_path(&file.path)?);
+
+        // left by an earlier poll that failed later on
+        if target.exists() {
+            if checksum(&target)? == file.checksum {
+                return Ok(());
+            }
+
+            fs::remove_file(&target)?;
+        }
+
+        if self
+            .current
+            .manifest
+            .as_ref()
+            .is_some_and(|manifest| manifest.segment_files.contains(file))
+        {
+            let existing = self
+                .generation_path(self.current.number)
+                .join(relative_path(&file.path)?);
+
+            if fs::hard_link(&existing, &target).is_err() {
+                fs::copy(&existing, &target)?;
+            }
+
+            return Ok(());
+        }
+
+        let partial = PathBuf::from(format!("{}.partial", target.display()));
+        let mut out = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&partial)?;
+
+        let mut offset = out.metadata()?.len();
+        if offset > file.len {
+            out.set_len(0)?;
+            offset = 0;
+        }
+
+        while offset < file.len {
+            let len = self.chunk_size.min(file.len - offset);
+            let chunk = self.source.read_range(&file.path, offset, len).await?;
+
+            if chunk.is_empty() {
+                bail!("{} ended after {} of {} bytes", file.path, offset, file.len);
+            }
+
+            out.write_all(&chunk)?;
+            offset += chunk.len() as u64;
+        }
+
+        out.sync_all()?;
+        drop(out);
+
+        if checksum(&partial)? != file.checksum {
+            fs::remove_file(&partial)?;
+            bail!("checksum mismatch for {}", file.path);
+        }
+
+        fs::rename(partial, target)?;
+
+        Ok(())
+    }
+
+    /// Remove every generation except the current one and the previous one,
+    /// which might still be searched.
+    fn remove_old_generations(&self, previous: u64) -> Result<()> {
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+
+            let Some(number) = name
+                .strip_prefix(GENERATION_PREFIX)
+                .and_then(|number| number.parse::<u64>().ok())
+            else {
+                continue;
+            };
+
+            if number != self.current.number && number != previous {
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Remove the files in `dir` that are not in the manifest, like downloads
+/// of segments that were merged away before they were replicated.
+fn remove_unlisted(dir: &Path, root: &Path, manifest: &Manifest) -> Result<()> {
+    let listed: HashSet<_> = manifest
+        .segment_files
+        .iter()
+        .map(|file| root.join(&file.path))
+        .chain(manifest.metadata.iter().map(|file| root.join(&file.path)))
+        .collect();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_file() && !listed.contains(&path) {
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        searcher::{LocalSearcher, SearchGuard, SearchQuery, SearchableIndex},
+        webpage::Webpage,
+    };
+
+    use super::*;
+
+    fn webpage(host: &str) -> Webpage {
+        Webpage::test_parse(
+            &format!(
+                r#"
+            <html>
+                <head>
+                    <title>Replicated {host}</title>
+                </head>
+                <body>
+                    The page about {host} {}
+                </body>
+            </html>
+            "#,
+                crate::rand_words(100)
+            ),
+            &format!("https://www.{host}.com"),
+        )
+        .unwrap()
+    }
+
+    fn urls(searcher: &LocalSearcher<ReplicatedIndex>) -> Vec<String> {
+        let mut urls: Vec<_> = searcher
+            .search(&SearchQuery {
+                query: "replicated".to_string(),
+                ..Default::default()
+            })
+            .unwrap()
+            .webpages
+            .into_iter()
+            .map(|webpage| webpage.url)
+            .collect();
+
+        urls.sort();
+        urls
+    }
+
+    fn commit(primary: &ReplicatedIndex, webpage: &Webpage) {
+        let mut index = primary.index.write().unwrap();
+        let index = Arc::get_mut(&mut index).unwrap();
+
+        index.insert(webpage).unwrap();
+        index.commit().unwrap();
+    }
+
+    #[tokio::test]
+    async fn replica_serves_new_commits() {
+        let primary = ReplicatedIndex::new(Index::temporary().unwrap());
+        commit(&primary, &webpage("a"));
+
+        let path = crate::gen_temp_path();
+        let mut replica = Replica::open(&path, LocalSource::new(primary.clone()), 1024).unwrap();
+        let searcher = LocalSearcher::new(replica.index());
+
+        assert!(urls(&searcher).is_empty());
+        assert!(replica.poll().await.unwrap());
+        assert_eq!(urls(&searcher), vec!["https://www.a.com/"]);
+        assert!(!replica.poll().await.unwrap());
+
+        // a search that is running while the next generation is swapped in
+        let in_flight = searcher.index().guard();
+
+        commit(&primary, &webpage("b"));
+        assert!(replica.poll().await.unwrap());
+
+        assert_eq!(
+            urls(&searcher),
+            vec!["https://www.a.com/", "https://www.b.com/"]
+        );
+        assert!(in_flight
+            .inverted_index()
+            .get_webpage("https://www.a.com/")
+            .is_some());
+        assert!(in_flight
+            .inverted_index()
+            .get_webpage("https://www.b.com/")
+            .is_none());
+
+        drop(in_flight);
+        drop(searcher);
+        drop(replica);
+
+        // a restarted replica serves the generation it had replicated
+        let replica = Replica::open(&path, LocalSource::new(primary), 1024).unwrap();
+        let searcher = LocalSearcher::new(replica.index());
+        assert_eq!(
+            urls(&searcher),
+            vec!["https://www.a.com/", "https://www.b.com/"]
+        );
+    }
+
+    struct CorruptSource {
+        inner: LocalSource,
+        corrupt: bool,
+    }
+
+    impl SegmentSource for CorruptSource {
+        async fn manifest(&self) -> Result<Manifest> {
+            self.inner.manifest().await
+        }
+
+        async fn read_range(&self, path: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+            let mut bytes = self.inner.read_range(path, offset, len).await?;
+
+            if self.corrupt && offset == 0 {
+                bytes[0] = bytes[0].wrapping_add(1);
+            }
+
+            Ok(bytes)
+        }
+    }
+
+    #[tokio::test]
+    async fn corrupt_download_is_not_served() {
+        let primary = ReplicatedIndex::new(Index::temporary().unwrap());
+        commit(&primary, &webpage("a"));
+
+        let mut replica = Replica::open(
+            crate::gen_temp_path(),
+            CorruptSource {
+                inner: LocalSource::new(primary),
+                corrupt: true,
+            },
+            1024,
+        )
+        .unwrap();
+        let searcher = LocalSearcher::new(replica.index());
+
+        assert!(replica.poll().await.is_err());
+        assert!(urls(&searcher).is_empty());
+
+        replica.source.corrupt = false;
+        assert!(replica.poll().await.unwrap());
+        assert_eq!(urls(&searcher), vec!["https://www.a.com/"]);
+    }
+}
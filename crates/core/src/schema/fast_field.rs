@@ -25,6 +25,7 @@ use crate::{
     enum_dispatch_from_discriminant,
     enum_map::InsertEnumMapKey,
     simhash,
+    webgraph::Node,
     webpage::{html::FnCache, Html, Webpage},
     Result,
 };
@@ -126,9 +127,16 @@ pub enum FastFieldEnum {
     NumPathAndQueryDigits,
     LikelyHasAds,
     LikelyHasPaywall,
+    ContentLabelFlags,
     LinkDensity,
+    Language,
     TitleEmbeddings,
     KeywordEmbeddings,
+    RawUrl,
+    RawTitle,
+    TitleQuality,
+    CanonicalUrlHash,
+    InsertedAt,
 }
 
 enum_dispatch_from_discriminant!(FastFieldEnumDiscriminants => FastFieldEnum,
@@ -169,9 +177,16 @@ enum_dispatch_from_discriminant!(FastFieldEnumDiscriminants => FastFieldEnum,
     NumPathAndQueryDigits,
     LikelyHasAds,
     LikelyHasPaywall,
+    ContentLabelFlags,
     LinkDensity,
+    Language,
     TitleEmbeddings,
     KeywordEmbeddings,
+    RawUrl,
+    RawTitle,
+    TitleQuality,
+    CanonicalUrlHash,
+    InsertedAt,
 ]);
 
 impl FastFieldEnum {
@@ -386,15 +401,23 @@ impl FastField for LastUpdated {
 
     fn add_html_tantivy(
         &self,
-        html: &Html,
+        _html: &Html,
         _cache: &mut FnCache,
+        _doc: &mut TantivyDocument,
+        _schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn add_webpage_tantivy(
+        &self,
+        webpage: &Webpage,
         doc: &mut TantivyDocument,
         schema: &tantivy::schema::Schema,
     ) -> Result<()> {
         doc.add_u64(
             self.tantivy_field(schema),
-            html.updated_time()
-                .map_or(0, |time| time.timestamp().max(0) as u64),
+            webpage.content_timestamp().unwrap_or(0),
         );
 
         Ok(())
@@ -1118,6 +1141,41 @@ impl FastField for LikelyHasPaywall {
     }
 }
 
+/// The content labels of the page encoded by
+/// [`crate::webpage::content_labels::ContentLabels::flags`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContentLabelFlags;
+impl FastField for ContentLabelFlags {
+    fn name(&self) -> &str {
+        "content_label_flags"
+    }
+
+    fn is_stored(&self) -> bool {
+        true
+    }
+
+    fn add_html_tantivy(
+        &self,
+        _html: &Html,
+        _cache: &mut FnCache,
+        _doc: &mut TantivyDocument,
+        _schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn add_webpage_tantivy(
+        &self,
+        webpage: &Webpage,
+        doc: &mut TantivyDocument,
+        schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        doc.add_u64(self.tantivy_field(schema), webpage.content_labels().flags());
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct LinkDensity;
 impl FastField for LinkDensity {
@@ -1145,6 +1203,56 @@ impl FastField for LinkDensity {
     }
 }
 
+/// The detected language of the page encoded by [`Language::encode`].
+/// Pages without a detected language get the value `0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Language;
+
+impl Language {
+    /// Pack the ISO 639-3 code of the language into a u64 so the values
+    /// are stable across versions of the language detector.
+    pub fn encode(lang: &whatlang::Lang) -> u64 {
+        lang.code()
+            .bytes()
+            .fold(0, |acc, byte| (acc << 8) | byte as u64)
+    }
+
+    pub fn decode(val: u64) -> Option<whatlang::Lang> {
+        if val == 0 {
+            return None;
+        }
+
+        let code: Vec<u8> = val
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|byte| *byte == 0)
+            .collect();
+
+        whatlang::Lang::from_code(std::str::from_utf8(&code).ok()?)
+    }
+}
+
+impl FastField for Language {
+    fn name(&self) -> &str {
+        "language"
+    }
+
+    fn add_html_tantivy(
+        &self,
+        html: &Html,
+        _cache: &mut FnCache,
+        doc: &mut TantivyDocument,
+        schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        doc.add_u64(
+            self.tantivy_field(schema),
+            html.lang().map(Language::encode).unwrap_or(0),
+        );
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TitleEmbeddings;
 impl FastField for TitleEmbeddings {
@@ -1232,3 +1340,162 @@ impl FastField for KeywordEmbeddings {
         Ok(())
     }
 }
+
+/// The url of the page as a column, so results can be shown with their url
+/// when their document cannot be read from the doc store in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RawUrl;
+impl FastField for RawUrl {
+    fn name(&self) -> &str {
+        "raw_url"
+    }
+
+    fn data_type(&self) -> DataType {
+        DataType::Bytes
+    }
+
+    fn indexing_option(&self) -> IndexingOption {
+        IndexingOption::Bytes(BytesOptions::default().set_fast())
+    }
+
+    fn add_html_tantivy(
+        &self,
+        html: &Html,
+        _cache: &mut FnCache,
+        doc: &mut TantivyDocument,
+        schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        doc.add_bytes(self.tantivy_field(schema), html.url().as_str().as_bytes());
+
+        Ok(())
+    }
+}
+
+/// The title of the page as a column. See [`RawUrl`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RawTitle;
+impl FastField for RawTitle {
+    fn name(&self) -> &str {
+        "raw_title"
+    }
+
+    fn data_type(&self) -> DataType {
+        DataType::Bytes
+    }
+
+    fn indexing_option(&self) -> IndexingOption {
+        IndexingOption::Bytes(BytesOptions::default().set_fast())
+    }
+
+    fn add_html_tantivy(
+        &self,
+        _html: &Html,
+        cache: &mut FnCache,
+        doc: &mut TantivyDocument,
+        schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        let title = cache
+            .pretokenize_title()
+            .as_ref()
+            .map(|title| title.text.as_bytes())
+            .unwrap_or_default();
+
+        doc.add_bytes(self.tantivy_field(schema), title);
+
+        Ok(())
+    }
+}
+
+/// Whether the title of the page is good, missing or junk. See [`crate::webpage::title`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TitleQuality;
+impl FastField for TitleQuality {
+    fn name(&self) -> &str {
+        "title_quality"
+    }
+
+    fn is_stored(&self) -> bool {
+        true
+    }
+
+    fn add_html_tantivy(
+        &self,
+        html: &Html,
+        _cache: &mut FnCache,
+        doc: &mut TantivyDocument,
+        schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        doc.add_u64(self.tantivy_field(schema), html.title_quality().as_u64());
+
+        Ok(())
+    }
+}
+
+/// The id of the webgraph node of the url, which is the same for the urls that only differ
+/// in their scheme, a `www.` prefix of the host or a trailing slash. Segments indexed before
+/// the field was added read it as 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CanonicalUrlHash;
+impl FastField for CanonicalUrlHash {
+    fn name(&self) -> &str {
+        "canonical_url_hash"
+    }
+
+    fn is_indexed(&self) -> bool {
+        false
+    }
+
+    fn add_html_tantivy(
+        &self,
+        html: &Html,
+        _cache: &mut FnCache,
+        doc: &mut TantivyDocument,
+        schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        doc.add_u64(
+            self.tantivy_field(schema),
+            Node::from(html.url()).id().as_u64(),
+        );
+
+        Ok(())
+    }
+}
+
+/// Seconds since the unix epoch of the insertion of the page, so the earliest insertion of
+/// a set of pages can be read without walking the terms of the insertion timestamps.
+/// Segments indexed before the field was added read it as 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InsertedAt;
+impl FastField for InsertedAt {
+    fn name(&self) -> &str {
+        "inserted_at"
+    }
+
+    fn is_indexed(&self) -> bool {
+        false
+    }
+
+    fn add_html_tantivy(
+        &self,
+        _html: &Html,
+        _cache: &mut FnCache,
+        _doc: &mut TantivyDocument,
+        _schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn add_webpage_tantivy(
+        &self,
+        webpage: &Webpage,
+        doc: &mut TantivyDocument,
+        schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        doc.add_u64(
+            self.tantivy_field(schema),
+            webpage.inserted_at.timestamp().max(0) as u64,
+        );
+
+        Ok(())
+    }
+}
@@ -17,6 +17,8 @@
 pub mod fast_field;
 pub mod text_field;
 
+use std::collections::HashMap;
+
 use tantivy::schema::{BytesOptions, DateOptions, NumericOptions, TextOptions};
 
 pub use fast_field::{DataType, FastFieldEnum};
@@ -26,6 +28,25 @@ use self::{fast_field::FastField, text_field::TextField};
 
 pub const FLOAT_SCALING: u64 = 1_000_000_000;
 
+/// Version of the schema created by [`create_schema`]. Must be bumped whenever a field
+/// is added to the schema, the options of an existing field change or the text of a field
+/// is tokenized differently.
+pub const SCHEMA_VERSION: u32 = 8;
+
+/// The oldest schema version that can still be opened (read-only). Indexes created before
+/// the schema was versioned are version 0.
+///
+/// Version 7 splits Chinese and Japanese text into bigrams. The older indexes have the text
+/// as single tokens, which the queries no longer match, so they must be rebuilt.
+pub const MIN_SUPPORTED_SCHEMA_VERSION: u32 = 7;
+
+static FIELDS_BY_NAME: once_cell::sync::Lazy<HashMap<String, Field>> =
+    once_cell::sync::Lazy::new(|| {
+        Field::all()
+            .map(|field| (field.name().to_string(), field))
+            .collect()
+    });
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Field {
     Fast(FastFieldEnum),
@@ -48,6 +69,10 @@ impl Field {
         None
     }
 
+    pub fn from_name(name: &str) -> Option<Field> {
+        FIELDS_BY_NAME.get(name).copied()
+    }
+
     #[inline]
     pub fn all() -> impl Iterator<Item = Field> {
         TextFieldEnum::all()
@@ -99,9 +124,14 @@ impl Field {
 }
 
 pub fn create_schema() -> tantivy::schema::Schema {
+    schema_from_fields(Field::all())
+}
+
+/// A schema with only the given fields, e.g. to simulate an index created with an older schema.
+pub(crate) fn schema_from_fields(fields: impl Iterator<Item = Field>) -> tantivy::schema::Schema {
     let mut builder = tantivy::schema::Schema::builder();
 
-    for field in Field::all() {
+    for field in fields {
         match field.indexing_option() {
             IndexingOption::Text(options) => builder.add_text_field(field.name(), options),
             IndexingOption::Integer(options) => builder.add_u64_field(field.name(), options),
@@ -119,3 +149,86 @@ pub enum IndexingOption {
     DateTime(DateOptions),
     Bytes(BytesOptions),
 }
+
+/// Resolves the fields of an index schema to the fields of the current schema.
+///
+/// [`Field::get`] assumes the field ids of the index match the current schema, which is
+/// not the case for indexes created with an older schema that lacks some of the fields.
+#[derive(Debug, Clone)]
+pub struct FieldMapping {
+    fields: Vec<Option<Field>>,
+    missing: Vec<Field>,
+}
+
+impl FieldMapping {
+    pub fn new(schema: &tantivy::schema::Schema) -> Self {
+        Self {
+            fields: schema
+                .fields()
+                .map(|(_, entry)| Field::from_name(entry.name()))
+                .collect(),
+            missing: Field::all()
+                .filter(|field| schema.get_field(field.name()).is_err())
+                .collect(),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, field: tantivy::schema::Field) -> Option<Field> {
+        self.fields
+            .get(field.field_id() as usize)
+            .copied()
+            .flatten()
+    }
+
+    /// Fields of the current schema that are not in the index.
+    pub fn missing(&self) -> &[Field] {
+        &self.missing
+    }
+
+    /// Whether the index has exactly the fields of the current schema in the same order.
+    pub fn is_current(&self) -> bool {
+        self.missing.is_empty()
+            && self.fields.len() == Field::all().count()
+            && self
+                .fields
+                .iter()
+                .enumerate()
+                .all(|(id, field)| *field == Field::get(id))
+    }
+}
+
+/// Convert a document from one schema to another. Fields are matched by name, fields that
+/// are not in `to` are dropped and fields that are only in `to` get a typed default value,
+/// i.e. empty text, 0 or the unix epoch.
+pub fn remap_document(
+    doc: &tantivy::TantivyDocument,
+    from: &tantivy::schema::Schema,
+    to: &tantivy::schema::Schema,
+) -> tantivy::TantivyDocument {
+    let mut res = tantivy::TantivyDocument::new();
+
+    for (field, value) in doc.field_values() {
+        if let Ok(target) = to.get_field(from.get_field_name(field)) {
+            res.add_field_value(target, value);
+        }
+    }
+
+    for (target, entry) in to.fields() {
+        if from.get_field(entry.name()).is_ok() {
+            continue;
+        }
+
+        match Field::from_name(entry.name()).map(|field| field.indexing_option()) {
+            Some(IndexingOption::Text(_)) => res.add_text(target, ""),
+            Some(IndexingOption::Integer(_)) => res.add_u64(target, 0),
+            Some(IndexingOption::DateTime(_)) => {
+                res.add_date(target, tantivy::DateTime::from_timestamp_secs(0))
+            }
+            Some(IndexingOption::Bytes(_)) => res.add_bytes(target, &[]),
+            None => {}
+        }
+    }
+
+    res
+}
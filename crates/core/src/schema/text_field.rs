@@ -30,7 +30,8 @@ use crate::{
     enum_map::InsertEnumMapKey,
     ranking::bm25::Bm25Constants,
     tokenizer::{
-        self, BigramTokenizer, Identity, JsonField, Tokenizer, TrigramTokenizer, UrlTokenizer,
+        self, BigramTokenizer, Identity, JsonField, Tokenizer, TokenizerKind, TrigramTokenizer,
+        UrlTokenizer,
     },
     webpage::Html,
     Result,
@@ -63,13 +64,16 @@ pub trait TextField:
         Ok(())
     }
 
-    #[allow(unused_variables)]
     fn tokenizer(&self, lang: Option<&whatlang::Lang>) -> Tokenizer {
-        Tokenizer::default()
+        Tokenizer::default().with_kind(TokenizerKind::for_lang(lang))
     }
 
+    /// The language of a short query is often detected wrongly, so the runs of Chinese and
+    /// Japanese characters of a query are always split into bigrams like the pages that are
+    /// tokenized with [`TokenizerKind::Cjk`]. Text in the other scripts is tokenized the same
+    /// by both kinds.
     fn query_tokenizer(&self, lang: Option<&whatlang::Lang>) -> Tokenizer {
-        self.tokenizer(lang)
+        self.tokenizer(lang).with_kind(TokenizerKind::Cjk)
     }
 
     fn ngram_size(&self) -> usize {
@@ -98,6 +102,12 @@ pub trait TextField:
         self.is_searchable() && self.has_pos()
     }
 
+    /// Whether the words of the field are stemmed, so they
+    /// also match other forms of the words of the query.
+    fn is_stemmed(&self) -> bool {
+        false
+    }
+
     fn is_stored(&self) -> bool {
         false
     }
@@ -156,6 +166,7 @@ pub enum TextFieldEnum {
     StemmedTitle,
     StemmedCleanBody,
     AllBody,
+    Boilerplate,
     Url,
     UrlNoTokenizer,
     UrlForSiteOperator,
@@ -186,10 +197,13 @@ pub enum TextFieldEnum {
     MicroformatTags,
     /// can either be NSFW or SFW (see safety classifier)
     SafetyClassification,
+    /// one term for each content label of the page (see content labels)
+    ContentLabels,
     InsertionTimestamp,
     RecipeFirstIngredientTagId,
     Keywords,
     Links,
+    SynthesizedTitle,
 }
 
 enum_dispatch_from_discriminant!(TextFieldEnumDiscriminants => TextFieldEnum,
@@ -199,6 +213,7 @@ enum_dispatch_from_discriminant!(TextFieldEnumDiscriminants => TextFieldEnum,
     StemmedTitle,
     StemmedCleanBody,
     AllBody,
+    Boilerplate,
     Url,
     UrlNoTokenizer,
     UrlForSiteOperator,
@@ -223,10 +238,12 @@ enum_dispatch_from_discriminant!(TextFieldEnumDiscriminants => TextFieldEnum,
     TitleTrigrams,
     MicroformatTags,
     SafetyClassification,
+    ContentLabels,
     InsertionTimestamp,
     RecipeFirstIngredientTagId,
     Keywords,
     Links,
+    SynthesizedTitle,
 ]);
 
 impl TextFieldEnum {
@@ -379,6 +396,10 @@ impl TextField for StemmedTitle {
         }
     }
 
+    fn is_stemmed(&self) -> bool {
+        true
+    }
+
     fn is_searchable(&self) -> bool {
         true
     }
@@ -425,6 +446,10 @@ impl TextField for StemmedCleanBody {
         }
     }
 
+    fn is_stemmed(&self) -> bool {
+        true
+    }
+
     fn is_stored(&self) -> bool {
         true
     }
@@ -495,6 +520,36 @@ impl TextField for AllBody {
     }
 }
 
+/// The text outside of the main content region of the page. The main content
+/// itself is indexed in the clean body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Boilerplate;
+impl TextField for Boilerplate {
+    fn name(&self) -> &str {
+        "boilerplate"
+    }
+
+    fn is_searchable(&self) -> bool {
+        true
+    }
+
+    fn add_html_tantivy(
+        &self,
+        _html: &Html,
+        cache: &mut FnCache,
+        doc: &mut TantivyDocument,
+        schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        doc.add_pre_tokenized_text(
+            self.tantivy_field(schema)
+                .unwrap_or_else(|| panic!("could not find field '{}' in index", self.name())),
+            cache.pretokenize_boilerplate_text().clone(),
+        );
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Url;
 impl TextField for Url {
@@ -1440,6 +1495,45 @@ impl TextField for SafetyClassification {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContentLabels;
+impl TextField for ContentLabels {
+    fn name(&self) -> &str {
+        "content_labels"
+    }
+
+    fn tokenizer(&self, _: Option<&whatlang::Lang>) -> Tokenizer {
+        Tokenizer::Identity(Identity {})
+    }
+
+    fn add_html_tantivy(
+        &self,
+        _html: &Html,
+        _cache: &mut FnCache,
+        _doc: &mut TantivyDocument,
+        _schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn add_webpage_tantivy(
+        &self,
+        webpage: &crate::webpage::Webpage,
+        doc: &mut TantivyDocument,
+        schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        let field = self
+            .tantivy_field(schema)
+            .unwrap_or_else(|| panic!("could not find field '{}' in index", self.name()));
+
+        for label in webpage.content_labels().iter() {
+            doc.add_text(field, label.as_str());
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct InsertionTimestamp;
 impl TextField for InsertionTimestamp {
@@ -1586,3 +1680,44 @@ impl TextField for Links {
         Ok(())
     }
 }
+
+/// The title shown instead of a missing or junk title. See [`crate::webpage::title`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SynthesizedTitle;
+impl TextField for SynthesizedTitle {
+    fn name(&self) -> &str {
+        "synthesized_title"
+    }
+
+    fn is_stored(&self) -> bool {
+        true
+    }
+
+    fn add_html_tantivy(
+        &self,
+        _html: &Html,
+        _cache: &mut FnCache,
+        _doc: &mut TantivyDocument,
+        _schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn add_webpage_tantivy(
+        &self,
+        webpage: &crate::webpage::Webpage,
+        doc: &mut TantivyDocument,
+        schema: &tantivy::schema::Schema,
+    ) -> Result<()> {
+        doc.add_text(
+            self.tantivy_field(schema)
+                .unwrap_or_else(|| panic!("could not find field '{}' in index", self.name())),
+            webpage
+                .synthesized_title()
+                .map(|(title, _)| title)
+                .unwrap_or_default(),
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Breadcrumbs of the site hierarchy of a result, like `docs.rs › Tokio › Net`.
+//!
+//! The breadcrumbs come from the `BreadcrumbList` structured data of the page if it has one.
+//! Otherwise they are derived from the path of the url, where each segment is decoded and
+//! made readable. The first breadcrumb is always the host of the page.
+
+use url::Url;
+use utoipa::ToSchema;
+
+use crate::webpage::schema_org::{Item, Property};
+
+/// The maximum number of path segments used for the breadcrumbs derived from the url.
+pub const MAX_URL_SEGMENTS: usize = 4;
+
+const INDEX_PAGES: [&str; 6] = [
+    "index.html",
+    "index.htm",
+    "index.php",
+    "default.aspx",
+    "default.html",
+    "default.htm",
+];
+
+const PAGE_EXTENSIONS: [&str; 5] = [".html", ".htm", ".php", ".aspx", ".asp"];
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct Breadcrumb {
+    pub name: String,
+    pub url: Option<String>,
+}
+
+pub fn breadcrumbs(url: &Url, schema_org: &[Item]) -> Vec<Breadcrumb> {
+    let host = match url.host_str() {
+        Some(host) => host.trim_start_matches("www."),
+        None => return Vec::new(),
+    };
+
+    let mut res = vec![Breadcrumb {
+        name: host.to_string(),
+        url: Some(format!("{}://{}/", url.scheme(), url.host_str().unwrap())),
+    }];
+
+    match from_structured_data(schema_org) {
+        Some(crumbs) => res.extend(crumbs),
+        None => res.extend(from_url(url)),
+    }
+
+    res
+}
+
+/// The name of the last crumb of the `BreadcrumbList` structured data of the page.
+pub fn structured_data_tail(schema_org: &[Item]) -> Option<String> {
+    from_structured_data(schema_org)?
+        .pop()
+        .map(|crumb| crumb.name)
+}
+
+fn string_property(item: &Item, key: &str) -> Option<String> {
+    item.properties
+        .get(key)
+        .and_then(|prop| prop.clone().one())
+        .and_then(|prop| prop.try_into_string())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+fn list_item(item: &Item) -> Option<(u64, Breadcrumb)> {
+    let position = string_property(item, "position")
+        .and_then(|pos| pos.parse().ok())
+        .unwrap_or(u64::MAX);
+
+    // the item is either the url of the crumb or a `Thing` with a name and an id
+    let (item_name, item_url) = match item.properties.get("item").and_then(|p| p.clone().one()) {
+        Some(Property::String(url)) => (None, Some(url)),
+        Some(Property::Item(thing)) => (
+            string_property(&thing, "name"),
+            string_property(&thing, "@id").or_else(|| string_property(&thing, "url")),
+        ),
+        None => (None, None),
+    };
+
+    let name = string_property(item, "name").or(item_name)?;
+
+    Some((
+        position,
+        Breadcrumb {
+            name,
+            url: item_url,
+        },
+    ))
+}
+
+fn from_structured_data(schema_org: &[Item]) -> Option<Vec<Breadcrumb>> {
+    let list = schema_org
+        .iter()
+        .find(|item| item.types_contains("BreadcrumbList"))?;
+
+    let mut crumbs: Vec<_> = list
+        .properties
+        .get("itemListElement")?
+        .clone()
+        .many()
+        .into_iter()
+        .filter_map(|prop| prop.try_into_item())
+        .filter_map(|item| list_item(&item))
+        .collect();
+
+    crumbs.sort_by_key(|(position, _)| *position);
+
+    let crumbs: Vec<_> = crumbs.into_iter().map(|(_, crumb)| crumb).collect();
+
+    if crumbs.is_empty() {
+        None
+    } else {
+        Some(crumbs)
+    }
+}
+
+/// Make a path segment readable. Hyphens and underscores become spaces and the first letter
+/// of each word is capitalized if it is ascii, so the rest of the segment is left as is.
+fn readable(segment: &str) -> String {
+    segment
+        .split(['-', '_', ' '])
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn from_url(url: &Url) -> Vec<Breadcrumb> {
+    let mut segments: Vec<String> = url
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            percent_encoding::percent_decode_str(segment)
+                .decode_utf8_lossy()
+                .to_string()
+        })
+        .collect();
+
+    if segments
+        .last()
+        .map(|last| INDEX_PAGES.contains(&last.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+    {
+        segments.pop();
+    }
+
+    if let Some(last) = segments.last_mut() {
+        let lowercase = last.to_ascii_lowercase();
+
+        if let Some(ext) = PAGE_EXTENSIONS.iter().find(|ext| lowercase.ends_with(*ext)) {
+            last.truncate(last.len() - ext.len());
+        }
+    }
+
+    segments
+        .iter()
+        .map(|segment| readable(segment))
+        .filter(|name| !name.is_empty())
+        .take(MAX_URL_SEGMENTS)
+        .map(|name| Breadcrumb { name, url: None })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::webpage::Html;
+
+    use super::*;
+
+    fn names(url: &str, schema_org: &[Item]) -> Vec<String> {
+        breadcrumbs(&Url::parse(url).unwrap(), schema_org)
+            .into_iter()
+            .map(|crumb| crumb.name)
+            .collect()
+    }
+
+    #[test]
+    fn from_url_path() {
+        assert_eq!(
+            names("https://docs.rs/tokio/net/TcpListener", &[]),
+            vec!["docs.rs", "Tokio", "Net", "TcpListener"]
+        );
+
+        assert_eq!(
+            names("https://www.example.com/best-rust_books/", &[]),
+            vec!["example.com", "Best Rust Books"]
+        );
+
+        assert_eq!(names("https://example.com/", &[]), vec!["example.com"]);
+        assert_eq!(
+            names("https://example.com/docs/index.html", &[]),
+            vec!["example.com", "Docs"]
+        );
+        assert_eq!(
+            names("https://example.com/docs/getting-started.html?q=1", &[]),
+            vec!["example.com", "Docs", "Getting Started"]
+        );
+    }
+
+    #[test]
+    fn deep_and_non_ascii_paths() {
+        assert_eq!(
+            names("https://example.com/a/b/c/d/e/f/g", &[]),
+            vec!["example.com", "A", "B", "C", "D"]
+        );
+
+        assert_eq!(
+            names("https://example.com/%C3%A9t%C3%A9/stra%C3%9Fe", &[]),
+            vec!["example.com", "été", "straße"]
+        );
+    }
+
+    #[test]
+    fn structured_data_breadcrumbs() {
+        let html = Html::parse(
+            r#"
+            <html>
+                <head>
+                    <script type="application/ld+json">
+                    {
+                        "@context": "https://schema.org",
+                        "@type": "BreadcrumbList",
+                        "itemListElement": [
+                            {
+                                "@type": "ListItem",
+                                "position": 2,
+                                "name": "Europe",
+                                "item": "https://www.nytimes.com/section/world/europe"
+                            },
+                            {
+                                "@type": "ListItem",
+                                "position": 1,
+                                "name": "World",
+                                "item": "https://www.nytimes.com/section/world"
+                            }
+                        ]
+                    }
+                    </script>
+                </head>
+                <body></body>
+            </html>
+            "#,
+            "https://www.nytimes.com/2015/10/26/world/europe/article.html",
+        )
+        .unwrap();
+
+        let crumbs = breadcrumbs(
+            &Url::parse("https://www.nytimes.com/2015/10/26/world/europe/article.html").unwrap(),
+            &html.schema_org(),
+        );
+
+        assert_eq!(
+            crumbs,
+            vec![
+                Breadcrumb {
+                    name: "nytimes.com".to_string(),
+                    url: Some("https://www.nytimes.com/".to_string()),
+                },
+                Breadcrumb {
+                    name: "World".to_string(),
+                    url: Some("https://www.nytimes.com/section/world".to_string()),
+                },
+                Breadcrumb {
+                    name: "Europe".to_string(),
+                    url: Some("https://www.nytimes.com/section/world/europe".to_string()),
+                },
+            ]
+        );
+    }
+}
@@ -21,7 +21,7 @@ use utoipa::ToSchema;
 
 use crate::entity_index::{
     entity::{EntitySnippet, Span},
-    EntityMatch,
+    DisambiguatedEntity, EntityCandidate, EntityMatch,
 };
 
 #[derive(
@@ -29,12 +29,35 @@ use crate::entity_index::{
 )]
 #[serde(rename_all = "camelCase")]
 pub struct DisplayedEntity {
+    pub id: String,
     pub title: String,
     pub small_abstract: EntitySnippet,
     pub image_id: Option<String>,
     pub related_entities: Vec<DisplayedEntity>,
     pub info: Vec<(String, EntitySnippet)>,
     pub match_score: f32,
+    /// Other entities the query might refer to. Searching with one of them pinned
+    /// shows that entity instead.
+    pub alternatives: Vec<EntityCandidate>,
+}
+
+impl DisplayedEntity {
+    pub fn remove_images(&mut self) {
+        self.image_id = None;
+
+        for related in &mut self.related_entities {
+            related.remove_images();
+        }
+    }
+}
+
+impl From<DisambiguatedEntity> for DisplayedEntity {
+    fn from(disambiguated: DisambiguatedEntity) -> Self {
+        Self {
+            alternatives: disambiguated.alternatives,
+            ..disambiguated.best.into()
+        }
+    }
 }
 
 impl From<EntityMatch> for DisplayedEntity {
@@ -47,6 +70,7 @@ impl From<EntityMatch> for DisplayedEntity {
         let small_abstract = EntitySnippet::from_span(&entity_abstract, 300);
 
         Self {
+            id: m.entity.id,
             title: m.entity.title,
             small_abstract,
             image_id: m.entity.image_id,
@@ -72,6 +96,7 @@ impl From<EntityMatch> for DisplayedEntity {
                 })
                 .collect(),
             match_score: m.score,
+            alternatives: Vec::new(),
         }
     }
 }
@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+mod breadcrumbs;
 mod entity;
 mod schema_org;
 mod stack_overflow;
@@ -29,16 +30,19 @@ use utoipa::ToSchema;
 use crate::api::search::ReturnBody;
 
 use crate::{
+    api::search::SafeSearch,
     highlighted::HighlightedFragment,
     inverted_index::RetrievedWebpage,
+    query::optic::OpticRuleMatch,
     ranking::{SignalEnumDiscriminants, SignalScore},
     searcher::SearchQuery,
     snippet::TextSnippet,
     web_spell::{self, CorrectionTerm},
-    webpage::url_ext::UrlExt,
+    webpage::{content_labels::ContentLabel, url_ext::UrlExt},
 };
 
 pub use self::stack_overflow::{create_stackoverflow_sidebar, CodeOrText};
+pub use breadcrumbs::{structured_data_tail, Breadcrumb};
 pub use entity::DisplayedEntity;
 pub use schema_org::{OneOrManyProperty, OneOrManyString, Property, StructuredData};
 
@@ -191,15 +195,27 @@ pub struct DisplayedWebpage {
     pub site: String,
     pub domain: String,
     pub pretty_url: String,
+    pub breadcrumbs: Vec<Breadcrumb>,
     pub snippet: Snippet,
     #[cfg(feature = "return_body")]
     pub body: Option<String>,
     pub rich_snippet: Option<RichSnippet>,
     pub ranking_signals: Option<HashMap<SignalEnumDiscriminants, SignalScore>>,
+    /// The factor the score was multiplied by for the boosted hosts of the query.
+    pub history_boost: Option<f64>,
+    pub optic_rule_matches: Option<Vec<OpticRuleMatch>>,
     pub structured_data: Option<Vec<StructuredData>>,
     pub score: Option<f64>,
     pub likely_has_ads: bool,
     pub likely_has_paywall: bool,
+    pub content_labels: Vec<ContentLabel>,
+    /// The result is labelled and the query asked for content warnings, so the
+    /// thumbnail should be blurred until the user clicks through.
+    pub content_warning: bool,
+    /// The page could not be read in time, so it is shown without a snippet.
+    pub degraded: bool,
+    /// The title was synthesized because the title of the page was missing or junk.
+    pub synthesized_title: bool,
 }
 
 #[derive(
@@ -222,6 +238,7 @@ impl DisplayedWebpage {
         let url = Url::parse(&webpage.url).unwrap();
         let domain = url.root_domain().unwrap_or_default().to_string();
         let pretty_url = prettify_url(&url);
+        let breadcrumbs = breadcrumbs::breadcrumbs(&url, &webpage.schema_org);
 
         let structured_data = if query.return_structured_data {
             Some(
@@ -241,19 +258,29 @@ impl DisplayedWebpage {
             ReturnBody::Truncated(n) => webpage.body.chars().take(n).collect::<String>(),
         });
 
+        let content_labels: Vec<_> = webpage.content_labels.iter().collect();
+        let content_warning = query.safe_search == SafeSearch::Warn && !content_labels.is_empty();
+
         Self {
             title: webpage.title,
             site: url.normalized_host().unwrap_or_default().to_string(),
             url: webpage.url,
             pretty_url,
+            breadcrumbs,
             domain,
             snippet,
             #[cfg(feature = "return_body")]
             body,
             ranking_signals: None,
+            history_boost: None,
+            optic_rule_matches: None,
             score: None,
             likely_has_ads: webpage.likely_has_ads,
             likely_has_paywall: webpage.likely_has_paywall,
+            content_labels,
+            content_warning,
+            degraded: webpage.degraded,
+            synthesized_title: webpage.title_synthesized,
             rich_snippet,
             structured_data,
         }
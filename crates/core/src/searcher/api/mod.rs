@@ -15,26 +15,36 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 mod sidebar;
+mod site_info;
 mod widget;
 
+pub use self::site_info::{RemoteSiteInfoSources, SiteInfo, SiteInfoManager, SiteInfoSources};
+
 use std::future::Future;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use itertools::{intersperse, Itertools};
+use optics::HostRankings;
 use url::Url;
 
 use ahash::AHashMap as HashMap;
 
 use crate::bangs::{Bang, BangHit};
 use crate::collector::{self, approx_count, Doc};
-use crate::config::{ApiConfig, ApiSpellCheck, ApiThresholds, CollectorConfig, WidgetsConfig};
+use crate::config::{
+    ApiConfig, ApiSpellCheck, ApiThresholds, CollectorConfig, DiversityConfig, HostRankingWeights,
+    LatencyBudgetConfig, RelFlagWeights, WidgetsConfig,
+};
 use crate::enum_map::EnumMap;
 use crate::image_store::Image;
 use crate::inverted_index::RetrievedWebpage;
 use crate::models::dual_encoder::DualEncoder;
 use crate::ranking::models::cross_encoder::CrossEncoderModel;
-use crate::ranking::pipeline::{PrecisionRankingWebpage, RankableWebpage, RecallRankingWebpage};
+use crate::ranking::pipeline::{
+    diversity::Fingerprint, LatencyBudget, PipelineStage, PrecisionRankingWebpage, RankableWebpage,
+    RecallRankingWebpage,
+};
 use crate::ranking::{
     bitvec_similarity, inbound_similarity, SignalCoefficient, SignalEnum, SignalScore,
 };
@@ -53,7 +63,8 @@ use crate::{query, webgraph, Result};
 use self::sidebar::SidebarManager;
 use self::widget::WidgetManager;
 
-use super::{distributed, live, SearchQuery, SearchResult, WebsitesResult};
+use super::timings::{Recorder, Timings};
+use super::{distributed, live, sanitize, SearchQuery, SearchResult, WebsitesResult};
 
 #[derive(Clone)]
 pub enum ScoredWebpagePointer {
@@ -92,6 +103,18 @@ impl RankableWebpage for ScoredWebpagePointer {
             ScoredWebpagePointer::Live(p) => p.website.signals(),
         }
     }
+
+    fn fingerprint(&self) -> Option<Fingerprint> {
+        self.as_ranking().fingerprint()
+    }
+
+    fn host(&self) -> Option<webgraph::NodeID> {
+        RankableWebpage::host(self.as_ranking())
+    }
+
+    fn set_history_boost(&mut self, boost: f64) {
+        self.as_ranking_mut().set_history_boost(boost);
+    }
 }
 
 impl collector::Doc for ScoredWebpagePointer {
@@ -104,6 +127,26 @@ impl collector::Doc for ScoredWebpagePointer {
     }
 }
 
+/// The round trip to each shard with the stages of the search on the shard as its children.
+/// Only the shards that were timed are included.
+fn shard_timings(shards: &mut [distributed::InitialSearchResultShard]) -> Vec<Timings> {
+    shards
+        .iter_mut()
+        .filter_map(|shard| {
+            let round_trip = shard.round_trip?;
+
+            Some(
+                Timings::new(format!("shard {}", shard.shard.as_u64()), round_trip)
+                    .with_children(shard.local_result.timings.take().into_iter().collect()),
+            )
+        })
+        .collect()
+}
+
+fn host_centrality(store: &speedy_kv::Db<webgraph::NodeID, f64>, host: &webgraph::NodeID) -> f64 {
+    store.get(host).ok().flatten().unwrap_or_default()
+}
+
 pub fn add_ranking_signals(
     websites: &mut [DisplayedWebpage],
     pointers: &[ScoredWebpagePointer],
@@ -119,12 +162,18 @@ pub fn add_ranking_signals(
                     SignalScore {
                         value: *signal_value,
                         coefficient: coeffs.get(&signal),
+                        unclamped_value: pointer
+                            .as_ranking()
+                            .unclamped_signals()
+                            .get(signal)
+                            .copied(),
                     },
                 );
             }
         }
 
         website.ranking_signals = Some(signals);
+        website.history_boost = pointer.as_ranking().history_boost();
     }
 }
 
@@ -133,7 +182,13 @@ pub struct Config {
     pub thresholds: ApiThresholds,
     pub widgets: WidgetsConfig,
     pub collector: CollectorConfig,
+    pub diversity: DiversityConfig,
+    pub latency_budget: LatencyBudgetConfig,
     pub spell_check: Option<ApiSpellCheck>,
+    pub fail_on_missing_shards: bool,
+    pub rel_flag_weights: RelFlagWeights,
+    pub host_ranking_weights: HostRankingWeights,
+    pub host_centrality_store_path: Option<String>,
 }
 
 impl From<ApiConfig> for Config {
@@ -142,7 +197,13 @@ impl From<ApiConfig> for Config {
             thresholds: conf.thresholds,
             widgets: conf.widgets,
             collector: conf.collector,
+            diversity: conf.diversity,
+            latency_budget: conf.latency_budget,
             spell_check: conf.spell_check,
+            fail_on_missing_shards: conf.fail_on_missing_shards,
+            rel_flag_weights: conf.rel_flag_weights,
+            host_ranking_weights: conf.host_ranking_weights,
+            host_centrality_store_path: conf.host_centrality_store_path,
         }
     }
 }
@@ -215,9 +276,15 @@ pub struct ApiSearcher<S, L, G> {
     dual_encoder: Option<Arc<DualEncoder>>,
     bangs: Bangs,
     collector_config: CollectorConfig,
+    diversity: DiversityConfig,
+    latency_budget: LatencyBudgetConfig,
     widget_manager: WidgetManager,
     spell_checker: Option<SpellChecker>,
     webgraph: Option<G>,
+    fail_on_missing_shards: bool,
+    rel_flag_weights: RelFlagWeights,
+    host_ranking_weights: HostRankingWeights,
+    host_centrality: Option<Arc<speedy_kv::Db<webgraph::NodeID, f64>>>,
 }
 
 impl<S, L, G> ApiSearcher<S, L, G>
@@ -246,11 +313,22 @@ where
             dual_encoder: None,
             bangs,
             collector_config: config.collector,
+            diversity: config.diversity,
+            latency_budget: config.latency_budget,
             widget_manager,
             spell_checker: config
                 .spell_check
                 .map(|c| SpellChecker::open(c.path, c.correction_config).unwrap()),
             webgraph: None,
+            fail_on_missing_shards: config.fail_on_missing_shards,
+            rel_flag_weights: config.rel_flag_weights,
+            host_ranking_weights: config.host_ranking_weights,
+            host_centrality: config.host_centrality_store_path.map(|path| {
+                Arc::new(
+                    speedy_kv::Db::open_or_create(std::path::Path::new(&path).join("harmonic"))
+                        .unwrap(),
+                )
+            }),
         }
     }
 
@@ -317,16 +395,39 @@ where
         Ok(self.bangs.get(&parsed_terms))
     }
 
-    pub async fn widget(&self, query: &str) -> Option<Widget> {
-        self.widget_manager.widget(query).await
+    pub async fn widget(&self, query: &SearchQuery) -> Option<Widget> {
+        if !query.features().instant_answers {
+            return None;
+        }
+
+        let query = sanitize::sanitize(&query.query).ok()?.query;
+        self.widget_manager.widget(&query).await
     }
 
-    pub async fn sidebar(&self, query: &str) -> Option<DisplayedSidebar> {
-        self.sidebar_manager.sidebar(query).await
+    pub async fn sidebar(&self, query: &SearchQuery) -> Option<DisplayedSidebar> {
+        let features = query.features();
+
+        if !features.sidebar {
+            return None;
+        }
+
+        let query = SearchQuery {
+            query: sanitize::sanitize(&query.query).ok()?.query,
+            ..query.clone()
+        };
+        let mut sidebar = self.sidebar_manager.sidebar(&query).await;
+
+        if !features.thumbnails {
+            if let Some(DisplayedSidebar::Entity(entity)) = &mut sidebar {
+                entity.remove_images();
+            }
+        }
+
+        sidebar
     }
 
     pub fn spell_check(&self, query: &str) -> Option<HighlightedSpellCorrection> {
-        let query = query.to_lowercase();
+        let query = sanitize::sanitize(query).ok()?.query.to_lowercase();
 
         let terms = query::parser::parse(&query).ok()?;
 
@@ -451,7 +552,19 @@ where
 
     async fn inbound_vecs(&self, ids: &[webgraph::NodeID]) -> Vec<bitvec_similarity::BitVec> {
         match self.webgraph.as_ref() {
-            Some(webgraph) => bitvec_similarity::BitVec::batch_new_for(ids, webgraph).await,
+            Some(webgraph) => webgraph
+                .batch_raw_ingoing(ids, EdgeLimit::Limit(128))
+                .await
+                .iter()
+                .map(|edges| match self.host_centrality.as_ref() {
+                    Some(centrality) => inbound_similarity::weighted_inbound_vec(
+                        edges,
+                        &self.rel_flag_weights,
+                        |id| host_centrality(centrality, id),
+                    ),
+                    None => inbound_similarity::inbound_vec(edges, &self.rel_flag_weights),
+                })
+                .collect(),
             None => vec![bitvec_similarity::BitVec::default(); ids.len()],
         }
     }
@@ -462,6 +575,7 @@ where
         initial_results: Vec<distributed::InitialSearchResultShard>,
         live_results: Vec<live::InitialSearchResultSplit>,
         pipeline: RankingPipeline<ScoredWebpagePointer>,
+        timings: &mut Recorder,
     ) -> (Vec<ScoredWebpagePointer>, bool) {
         let mut collector = BucketCollector::new(pipeline.collector_top_n(), collector_config);
 
@@ -540,86 +654,198 @@ where
             .take(pipeline.collector_top_n())
             .collect::<Vec<_>>();
 
-        let res = pipeline.apply(top_websites);
+        let res = pipeline.apply_timed(top_websites, timings);
 
         (res, has_more)
     }
 
-    async fn inbound_scorer(&self, query: &SearchQuery) -> inbound_similarity::Scorer {
+    async fn inbound_scorer(
+        &self,
+        query: &SearchQuery,
+        cache_capacity: usize,
+    ) -> inbound_similarity::Scorer {
         match self.webgraph.as_ref() {
             Some(webgraph) => {
-                let host_rankings = query.host_rankings();
-
-                let liked: Vec<_> = host_rankings
-                    .liked
-                    .iter()
-                    .map(|n| webgraph::Node::from(n.clone()).into_host().id())
-                    .collect();
+                let empty = HostRankings::empty();
+                let seeds = inbound_similarity::Seeds::merge(
+                    query.host_rankings.as_ref().unwrap_or(&empty),
+                    query
+                        .optic
+                        .as_ref()
+                        .map(|optic| &optic.host_rankings)
+                        .unwrap_or(&empty),
+                    self.host_ranking_weights.optic,
+                );
 
-                let disliked: Vec<_> = host_rankings
-                    .disliked
-                    .iter()
-                    .map(|n| webgraph::Node::from(n.clone()).into_host().id())
-                    .collect();
-                inbound_similarity::Scorer::new(webgraph, &liked, &disliked, false).await
+                match self.host_centrality.as_ref() {
+                    Some(centrality) => {
+                        inbound_similarity::Scorer::with_weighted_seeds(
+                            webgraph,
+                            &seeds,
+                            false,
+                            cache_capacity,
+                            |id| host_centrality(centrality, id),
+                        )
+                        .await
+                    }
+                    None => {
+                        inbound_similarity::Scorer::with_seeds(
+                            webgraph,
+                            &seeds,
+                            false,
+                            cache_capacity,
+                        )
+                        .await
+                    }
+                }
             }
             None => inbound_similarity::Scorer::empty(),
         }
     }
 
+    /// The stages of the pipelines that have a scorer, in the order they run.
+    fn budgeted_stages(&self) -> Vec<PipelineStage> {
+        let mut stages = Vec::new();
+
+        if self.dual_encoder.is_some() {
+            stages.push(PipelineStage::TitleEmbeddings);
+            stages.push(PipelineStage::KeywordEmbeddings);
+        }
+
+        if self.webgraph.is_some() {
+            stages.push(PipelineStage::InboundSimilarity);
+        }
+
+        if self.cross_encoder.is_some() {
+            stages.push(PipelineStage::CrossEncoder);
+        }
+
+        stages
+    }
+
     async fn search_websites(&self, query: &SearchQuery) -> Result<WebsitesResult> {
         let start = Instant::now();
+        let mut timings = Recorder::new(query.debug_timings);
 
         if query.is_empty() {
             return Err(distributed::Error::EmptyQuery.into());
         }
 
-        let mut search_query = query.clone();
-        let inbound_scorer = self.inbound_scorer(&search_query).await;
+        let budget = query
+            .latency_budget_ms
+            .or(self.latency_budget.budget_ms)
+            .map(|budget_ms| {
+                Arc::new(LatencyBudget::new(
+                    Duration::from_millis(budget_ms),
+                    &self.latency_budget,
+                    self.budgeted_stages(),
+                ))
+            });
 
+        let mut search_query = query.clone();
         let top_n = search_query.num_results;
 
+        let mut diversity = self.diversity.clone();
+        if let Some(lambda) = search_query.diversity_lambda {
+            diversity.lambda = lambda;
+        }
+
+        let top_n_considered = if diversity.is_enabled() {
+            top_n.max(diversity.top_n)
+        } else {
+            top_n
+        };
+
+        // every shard returns up to `top_n_considered` webpages to the recall stage,
+        // so it scores at most that many hosts per shard.
+        let cache_capacity = top_n_considered * self.distributed_searcher.num_shards().await;
+
+        let stage_start = timings.start();
+        let inbound_scorer = self.inbound_scorer(&search_query, cache_capacity).await;
+        timings.record("inbound_scorer", stage_start);
+
         // This pipeline should be created before the first search is performed
         // so the query knows how many results to fetch from the indices
-        let recall_pipeline: RankingPipeline<ScoredWebpagePointer> =
+        let mut recall_pipeline: RankingPipeline<ScoredWebpagePointer> =
             RankingPipeline::<ScoredWebpagePointer>::recall_stage(
                 &mut search_query,
                 inbound_scorer,
                 self.lambda_model.clone(),
                 self.dual_encoder.clone(),
                 self.collector_config.clone(),
-                top_n,
-            );
+                top_n_considered,
+            )
+            .with_diversity(diversity);
 
-        let (initial_results, live_results) = tokio::join!(
+        if let Some(budget) = &budget {
+            recall_pipeline = recall_pipeline.with_budget(Arc::clone(budget));
+        }
+
+        let stage_start = timings.start();
+        let (mut initial_results, live_results) = tokio::join!(
             self.distributed_searcher.search_initial(&search_query),
             self.search_initial_from_live(&search_query),
         );
+        timings.record_with(
+            "search_initial",
+            stage_start,
+            shard_timings(&mut initial_results.shards),
+        );
+
+        if initial_results.is_degraded() {
+            if self.fail_on_missing_shards {
+                return Err(
+                    distributed::Error::ShardsUnavailable(initial_results.missing_shards).into(),
+                );
+            }
+
+            tracing::warn!(
+                "shards {:?} are unavailable, returning partial results",
+                initial_results.missing_shards
+            );
+        }
+
+        let degraded = initial_results.is_degraded();
+        let missing_shards = initial_results
+            .missing_shards
+            .iter()
+            .map(|shard| shard.as_u64())
+            .collect();
+        let initial_results = initial_results.shards;
+        let redirect = initial_results
+            .iter()
+            .find_map(|result| result.local_result.redirect.clone());
 
         let num_docs = initial_results
             .iter()
             .map(|result| result.local_result.num_websites)
             .fold(approx_count::Count::Exact(0), |acc, count| acc + count);
 
+        let mut stages = Recorder::new(timings.is_enabled());
+        let stage_start = timings.start();
         let (top_websites, has_more_results) = self
             .combine_results(
                 self.collector_config.clone(),
                 initial_results,
                 live_results.unwrap_or_default(),
                 recall_pipeline,
+                &mut stages,
             )
             .await;
+        timings.record_with("recall_pipeline", stage_start, stages.into_stages());
 
+        let stage_start = timings.start();
         let retrieved_webpages = self
             .retrieve_webpages(&search_query.query, &top_websites)
             .await;
+        timings.record("retrieve", stage_start);
 
         let mut search_query = SearchQuery {
             page: 0,
             ..query.clone()
         };
 
-        let reranking_pipeline: RankingPipeline<PrecisionRankingWebpage> =
+        let mut reranking_pipeline: RankingPipeline<PrecisionRankingWebpage> =
             RankingPipeline::<PrecisionRankingWebpage>::reranker(
                 &mut search_query,
                 self.cross_encoder.clone(),
@@ -628,12 +854,28 @@ where
                 query.num_results,
             )?;
 
-        let retrieved_webpages = reranking_pipeline.apply(retrieved_webpages);
+        if let Some(budget) = &budget {
+            reranking_pipeline = reranking_pipeline.with_budget(Arc::clone(budget));
+        }
+
+        let mut stages = Recorder::new(timings.is_enabled());
+        let stage_start = timings.start();
+        let retrieved_webpages = reranking_pipeline.apply_timed(retrieved_webpages, &mut stages);
+        timings.record_with("rerank_pipeline", stage_start, stages.into_stages());
 
         let mut retrieved_webpages: Vec<_> = retrieved_webpages
             .into_iter()
-            .map(|webpage| webpage.into_retrieved_webpage())
-            .map(|webpage| DisplayedWebpage::new(webpage, &search_query))
+            .map(|webpage| {
+                let optic_rule_matches = webpage.ranking().optic_rule_matches().to_vec();
+                let mut webpage =
+                    DisplayedWebpage::new(webpage.into_retrieved_webpage(), &search_query);
+
+                if query.optic_debug {
+                    webpage.optic_rule_matches = Some(optic_rule_matches);
+                }
+
+                webpage
+            })
             .collect();
 
         if retrieved_webpages.len() != top_websites.len() {
@@ -652,6 +894,11 @@ where
             website.score = Some(pointer.score());
         }
 
+        if let Some(sort) = &query.sort_page {
+            sort.sort(&mut retrieved_webpages);
+        }
+
+        let optic_debug = query.optic_debug_summary(&retrieved_webpages);
         let search_duration_ms = start.elapsed().as_millis();
 
         Ok(WebsitesResult {
@@ -659,15 +906,36 @@ where
             webpages: retrieved_webpages,
             search_duration_ms,
             has_more_results,
+            optic_debug,
+            degraded,
+            missing_shards,
+            degraded_stages: budget
+                .map(|budget| budget.degraded_stages())
+                .unwrap_or_default(),
+            query_truncated: false,
+            timings: timings.finish("search", Some(start)),
+            features: query.features(),
+            redirect,
         })
     }
 
+    /// Queries that are empty or too long fail with a [`distributed::Error`]
+    /// before they reach the shards.
     pub async fn search(&self, query: &SearchQuery) -> Result<SearchResult> {
-        if let Some(bang) = self.check_bangs(query).await? {
+        let sanitized = sanitize::sanitize(&query.query)?;
+        let query = SearchQuery {
+            query: sanitized.query,
+            ..query.clone()
+        };
+
+        if let Some(bang) = self.check_bangs(&query).await? {
             return Ok(SearchResult::Bang(Box::new(bang)));
         }
 
-        Ok(SearchResult::Websites(self.search_websites(query).await?))
+        let mut result = self.search_websites(&query).await?;
+        result.query_truncated = sanitized.truncated;
+
+        Ok(SearchResult::Websites(result))
     }
 
     pub async fn get_webpage(&self, url: &str) -> Result<Option<RetrievedWebpage>> {
@@ -685,3 +953,358 @@ where
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        distributed::member::ShardId,
+        entity_index::DisambiguatedEntity,
+        index::Index,
+        searcher::{
+            distributed::{InitialSearchResult, InitialSearchResultShard, SearchClient},
+            live::LiveSearcher,
+            LocalSearcher,
+        },
+        webpage::Webpage,
+    };
+
+    use super::*;
+
+    const CONTENT: &str = "this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever";
+
+    /// A search client with a local searcher per shard where the
+    /// shards without a searcher behave as if they could not be reached.
+    struct ShardedLocalClient {
+        shards: Vec<(ShardId, Option<LocalSearcher<Index>>)>,
+    }
+
+    impl ShardedLocalClient {
+        fn searcher(&self, shard: ShardId) -> Option<&LocalSearcher<Index>> {
+            self.shards
+                .iter()
+                .find(|(id, _)| *id == shard)
+                .and_then(|(_, searcher)| searcher.as_ref())
+        }
+    }
+
+    impl SearchClient for ShardedLocalClient {
+        async fn num_shards(&self) -> usize {
+            self.shards.len()
+        }
+
+        async fn search_initial(&self, query: &SearchQuery) -> InitialSearchResult {
+            let mut result = InitialSearchResult::default();
+
+            for (shard, searcher) in &self.shards {
+                match searcher {
+                    Some(searcher) => {
+                        let start = Instant::now();
+                        let local_result = searcher.search_initial(query, true).unwrap();
+
+                        result.shards.push(InitialSearchResultShard {
+                            local_result,
+                            shard: *shard,
+                            round_trip: query.debug_timings.then(|| start.elapsed()),
+                        })
+                    }
+                    None => result.missing_shards.push(*shard),
+                }
+            }
+
+            result
+        }
+
+        async fn retrieve_webpages(
+            &self,
+            top_websites: &[(usize, distributed::ScoredWebpagePointer)],
+            query: &str,
+        ) -> Vec<(usize, PrecisionRankingWebpage)> {
+            top_websites
+                .iter()
+                .filter_map(|(i, pointer)| {
+                    let webpage = self
+                        .searcher(pointer.shard)?
+                        .retrieve_websites(&[pointer.website.pointer().clone()], query)
+                        .ok()?
+                        .pop()?;
+
+                    Some((
+                        *i,
+                        PrecisionRankingWebpage::new(webpage, pointer.website.clone()),
+                    ))
+                })
+                .collect()
+        }
+
+        async fn search_entity(
+            &self,
+            _query: &str,
+            _pinned_entity: Option<&str>,
+        ) -> Option<DisambiguatedEntity> {
+            None
+        }
+
+        async fn get_webpage(&self, _url: &str) -> Result<Option<RetrievedWebpage>> {
+            Ok(None)
+        }
+
+        async fn get_homepage_descriptions(
+            &self,
+            _urls: &[Url],
+        ) -> std::collections::HashMap<Url, String> {
+            std::collections::HashMap::new()
+        }
+
+        async fn get_entity_image(
+            &self,
+            _image_id: &str,
+            _max_height: Option<u64>,
+            _max_width: Option<u64>,
+        ) -> Result<Option<Image>> {
+            Ok(None)
+        }
+    }
+
+    fn shard(url: &str) -> LocalSearcher<Index> {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(
+                &Webpage::test_parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Example website</title>
+                        </head>
+                        <body>
+                            {CONTENT}
+                        </body>
+                    </html>
+                "#
+                    ),
+                    url,
+                )
+                .unwrap(),
+            )
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        LocalSearcher::new(index)
+    }
+
+    fn client() -> ShardedLocalClient {
+        ShardedLocalClient {
+            shards: vec![
+                (ShardId::new(0), Some(shard("https://www.a.com"))),
+                (ShardId::new(1), None),
+            ],
+        }
+    }
+
+    fn query() -> SearchQuery {
+        SearchQuery {
+            query: "example".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn degraded_search() {
+        let searcher: ApiSearcher<_, LiveSearcher, webgraph::Webgraph> =
+            ApiSearcher::new(client(), Bangs::empty(), Config::default());
+
+        let result = searcher
+            .search(&query())
+            .await
+            .expect("Search failed")
+            .into_websites_result();
+
+        assert_eq!(result.webpages.len(), 1);
+        assert_eq!(result.webpages[0].url, "https://www.a.com/");
+        assert!(result.degraded);
+        assert_eq!(result.missing_shards, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn fail_on_missing_shards() {
+        let searcher: ApiSearcher<_, LiveSearcher, webgraph::Webgraph> = ApiSearcher::new(
+            client(),
+            Bangs::empty(),
+            Config {
+                fail_on_missing_shards: true,
+                ..Default::default()
+            },
+        );
+
+        assert!(searcher.search(&query()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn oversized_query_fails_fast() {
+        let searcher: ApiSearcher<_, LiveSearcher, webgraph::Webgraph> =
+            ApiSearcher::new(client(), Bangs::empty(), Config::default());
+
+        let query = SearchQuery {
+            query: "example ".repeat(6_400),
+            ..Default::default()
+        };
+        assert!(query.query.len() >= 50_000);
+
+        let start = Instant::now();
+        let err = searcher.search(&query).await.unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(distributed::Error::QueryTooLong { .. })
+        ));
+        assert!(searcher.widget(&query).await.is_none());
+        assert!(searcher.spell_check(&query.query).is_none());
+    }
+
+    #[tokio::test]
+    async fn optic_disables_sidebar() {
+        let searcher: ApiSearcher<_, LiveSearcher, webgraph::Webgraph> =
+            ApiSearcher::new(client(), Bangs::empty(), Config::default());
+
+        let result = searcher
+            .search(&query())
+            .await
+            .unwrap()
+            .into_websites_result();
+        assert_eq!(result.features, optics::Features::default());
+
+        searcher.sidebar(&query()).await;
+        assert_eq!(searcher.sidebar_manager.num_computed(), 1);
+
+        let query = SearchQuery {
+            optic: Some(optics::Optic::parse("Features { sidebar: off };").unwrap()),
+            ..query()
+        };
+
+        assert!(searcher.sidebar(&query).await.is_none());
+        assert_eq!(searcher.sidebar_manager.num_computed(), 1);
+
+        let result = searcher
+            .search(&query)
+            .await
+            .unwrap()
+            .into_websites_result();
+        assert!(!result.features.sidebar);
+        assert!(result.features.instant_answers);
+        assert_eq!(result.webpages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sanitized_queries() {
+        let searcher: ApiSearcher<_, LiveSearcher, webgraph::Webgraph> =
+            ApiSearcher::new(client(), Bangs::empty(), Config::default());
+
+        let search = |query: String| {
+            let searcher = &searcher;
+            async move {
+                searcher
+                    .search(&SearchQuery {
+                        query,
+                        ..Default::default()
+                    })
+                    .await
+            }
+        };
+
+        for query in ["", "  \t ", "?!"] {
+            let err = search(query.to_string()).await.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(distributed::Error::EmptyQuery)
+            ));
+        }
+
+        let result = search(" this  is the ".to_string())
+            .await
+            .unwrap()
+            .into_websites_result();
+        assert_eq!(result.webpages.len(), 1);
+        assert!(!result.query_truncated);
+
+        let result = search(["example"; 40].join(" "))
+            .await
+            .unwrap()
+            .into_websites_result();
+        assert_eq!(result.webpages.len(), 1);
+        assert!(result.query_truncated);
+    }
+
+    #[tokio::test]
+    async fn debug_timings() {
+        // the timings are only accurate to the microsecond
+        const SLACK_MS: f64 = 1.0;
+
+        let client = ShardedLocalClient {
+            shards: vec![
+                (ShardId::new(0), Some(shard("https://www.a.com"))),
+                (ShardId::new(1), Some(shard("https://www.b.com"))),
+            ],
+        };
+        let searcher: ApiSearcher<_, LiveSearcher, webgraph::Webgraph> =
+            ApiSearcher::new(client, Bangs::empty(), Config::default());
+
+        let result = searcher
+            .search(&query())
+            .await
+            .unwrap()
+            .into_websites_result();
+        assert!(result.timings.is_none());
+
+        let result = searcher
+            .search(&SearchQuery {
+                debug_timings: true,
+                ..query()
+            })
+            .await
+            .unwrap()
+            .into_websites_result();
+
+        let timings = result.timings.unwrap();
+        assert_eq!(timings.name, "search");
+
+        for stage in [
+            "inbound_scorer",
+            "search_initial",
+            "recall_pipeline",
+            "retrieve",
+            "rerank_pipeline",
+        ] {
+            assert!(timings.child(stage).is_some(), "missing stage {stage}");
+        }
+
+        for pipeline in ["recall_pipeline", "rerank_pipeline"] {
+            let pipeline = timings.child(pipeline).unwrap();
+            assert!(pipeline.child("score").is_some());
+            assert!(pipeline.child("sort").is_some());
+        }
+
+        let initial = timings.child("search_initial").unwrap();
+        assert_eq!(initial.children.len(), 2);
+
+        for (i, shard) in initial.children.iter().enumerate() {
+            assert_eq!(shard.name, format!("shard {i}"));
+
+            let local = shard.child("search_initial").unwrap();
+            for stage in ["parse_query", "collect", "retrieve", "recall_pipeline"] {
+                assert!(local.child(stage).is_some(), "missing shard stage {stage}");
+            }
+            assert!(local.child("collect").unwrap().child("segment 0").is_some());
+
+            assert!(local.duration_ms <= shard.duration_ms + SLACK_MS);
+        }
+
+        // the shards are searched one after the other by the test client
+        let shards_ms: f64 = initial.children.iter().map(|shard| shard.duration_ms).sum();
+        assert!(shards_ms <= initial.duration_ms + SLACK_MS);
+
+        let stages_ms: f64 = timings.children.iter().map(|stage| stage.duration_ms).sum();
+        assert!(stages_ms <= timings.duration_ms + SLACK_MS);
+    }
+}
@@ -15,7 +15,13 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{config::ApiThresholds, ranking::pipeline::RecallRankingWebpage, Result};
-use std::{cmp::Ordering, sync::Arc};
+use std::{
+    cmp::Ordering,
+    sync::{
+        atomic::{AtomicU64, Ordering as AtomicOrdering},
+        Arc,
+    },
+};
 
 use optics::Optic;
 use url::Url;
@@ -28,6 +34,7 @@ use crate::{
 pub struct SidebarManager<S> {
     distributed_searcher: Arc<S>,
     thresholds: ApiThresholds,
+    num_computed: AtomicU64,
 }
 
 impl<S> SidebarManager<S>
@@ -38,9 +45,15 @@ where
         Self {
             distributed_searcher,
             thresholds,
+            num_computed: AtomicU64::new(0),
         }
     }
 
+    /// The number of sidebars that have been computed.
+    pub fn num_computed(&self) -> u64 {
+        self.num_computed.load(AtomicOrdering::Relaxed)
+    }
+
     pub async fn stackoverflow(&self, query: &str) -> Result<Option<DisplayedSidebar>> {
         let query = SearchQuery {
             query: query.to_string(),
@@ -53,6 +66,7 @@ where
             .distributed_searcher
             .search_initial(&query)
             .await
+            .shards
             .into_iter()
             .filter_map(|result| {
                 result
@@ -92,14 +106,20 @@ where
         Ok(None)
     }
 
-    pub async fn sidebar(&self, query: &str) -> Option<DisplayedSidebar> {
+    /// A pinned entity is shown regardless of how well it matches the query.
+    pub async fn sidebar(&self, query: &SearchQuery) -> Option<DisplayedSidebar> {
+        self.num_computed.fetch_add(1, AtomicOrdering::Relaxed);
+
+        let pinned_entity = query.pinned_entity.as_deref();
         let (entity, stackoverflow) = futures::join!(
-            self.distributed_searcher.search_entity(query),
-            self.stackoverflow(query)
+            self.distributed_searcher
+                .search_entity(&query.query, pinned_entity),
+            self.stackoverflow(&query.query)
         );
 
         if let Some(entity) = entity {
-            if entity.score as f64 > self.thresholds.entity_sidebar {
+            if pinned_entity.is_some() || entity.best.score as f64 > self.thresholds.entity_sidebar
+            {
                 return Some(DisplayedSidebar::Entity(entity.into()));
             }
         }
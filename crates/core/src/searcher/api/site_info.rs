@@ -0,0 +1,464 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The "about this site" panel.
+//!
+//! The information about a site is gathered from the index, the webgraph and the entity
+//! index. Every source is optional: a source that fails or has not answered within the time
+//! budget is left out of the panel instead of failing it, and the panel is marked as degraded.
+//! Only complete panels are cached, so a degraded panel is gathered again on the next request.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use url::Url;
+use utoipa::ToSchema;
+
+use crate::config::SiteInfoConfig;
+use crate::inverted_index::HostStats;
+use crate::ttl_cache::TTLCache;
+use crate::webgraph::remote::RemoteWebgraph;
+use crate::webgraph::{CoLinkLimits, CoLinkedHost, Node};
+use crate::webpage::url_ext::UrlExt;
+use crate::Result;
+
+use super::super::distributed::{DistributedSearcher, SearchClient};
+
+const NUM_TOPICS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SiteInfo {
+    pub host: String,
+    /// Seconds since the unix epoch of when the first page of the site was indexed.
+    pub first_indexed: Option<i64>,
+    pub num_pages: Option<u64>,
+    pub topics: Vec<String>,
+    /// The percentile of the linking hosts in the webgraph that the site is at least as central as.
+    pub centrality_percentile: Option<u8>,
+    /// The number of hosts linking to the site.
+    pub num_backlinks: Option<u64>,
+    /// The hosts that are commonly linked to by the hosts linking to the site, most common first.
+    pub co_linked_hosts: Vec<CoLinkedHost>,
+    pub has_entity: Option<bool>,
+    /// Some of the sources failed or did not answer in time.
+    pub degraded: bool,
+}
+
+/// The sources of the panel. `Ok(None)` means the source has no information about the
+/// site (or is not configured), while an error means the source could not be reached.
+pub trait SiteInfoSources {
+    fn host_stats(&self, host: &str) -> impl Future<Output = Result<Option<HostStats>>> + Send;
+
+    fn centrality_percentile(&self, rank: u64) -> impl Future<Output = Result<Option<u8>>> + Send;
+
+    fn num_backlinks(&self, host: &str) -> impl Future<Output = Result<Option<u64>>> + Send;
+
+    fn co_linked_hosts(
+        &self,
+        host: &str,
+        limits: CoLinkLimits,
+        n: usize,
+    ) -> impl Future<Output = Result<Option<Vec<CoLinkedHost>>>> + Send;
+
+    fn has_entity(&self, host: &str) -> impl Future<Output = Result<Option<bool>>> + Send;
+}
+
+pub struct SiteInfoManager<S> {
+    sources: S,
+    budget: Duration,
+    co_link_limits: CoLinkLimits,
+    num_co_linked_hosts: usize,
+    cache: Mutex<TTLCache<String, SiteInfo>>,
+}
+
+impl<S> SiteInfoManager<S>
+where
+    S: SiteInfoSources,
+{
+    pub fn new(sources: S, config: &SiteInfoConfig) -> Self {
+        Self {
+            sources,
+            budget: Duration::from_millis(config.budget_ms),
+            co_link_limits: CoLinkLimits {
+                max_linkers: config.co_links_max_linkers,
+                max_destinations: config.co_links_max_destinations,
+            },
+            num_co_linked_hosts: config.num_co_linked_hosts,
+            cache: Mutex::new(TTLCache::with_ttl_and_max_size(
+                Duration::from_secs(config.cache_ttl_secs),
+                Some(config.cache_size),
+            )),
+        }
+    }
+
+    pub async fn site_info(&self, host: &str) -> SiteInfo {
+        let host = host.trim().trim_start_matches("www.").to_lowercase();
+
+        if let Some(info) = self
+            .cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&host)
+        {
+            return info.clone();
+        }
+
+        let deadline = tokio::time::Instant::now() + self.budget;
+
+        // the centrality percentile is looked up from the rank stored in the index,
+        // so it shares the budget with the index lookup.
+        let index = async {
+            let stats = answer(deadline, "index", self.sources.host_stats(&host)).await;
+
+            let percentile = match &stats {
+                Some(Some(HostStats {
+                    centrality_rank: Some(rank),
+                    ..
+                })) => {
+                    answer(
+                        deadline,
+                        "centrality",
+                        self.sources.centrality_percentile(*rank),
+                    )
+                    .await
+                }
+                Some(_) => Some(None),
+                None => None,
+            };
+
+            (stats, percentile)
+        };
+
+        let ((stats, percentile), num_backlinks, co_linked_hosts, has_entity) = tokio::join!(
+            index,
+            answer(deadline, "webgraph", self.sources.num_backlinks(&host)),
+            answer(
+                deadline,
+                "co-links",
+                self.sources
+                    .co_linked_hosts(&host, self.co_link_limits, self.num_co_linked_hosts)
+            ),
+            answer(deadline, "entity", self.sources.has_entity(&host)),
+        );
+
+        let degraded = stats.is_none()
+            || percentile.is_none()
+            || num_backlinks.is_none()
+            || co_linked_hosts.is_none()
+            || has_entity.is_none();
+
+        let stats = stats.flatten();
+
+        let info = SiteInfo {
+            first_indexed: stats.as_ref().and_then(|stats| stats.first_indexed),
+            num_pages: stats.as_ref().map(|stats| stats.num_pages),
+            topics: stats
+                .map(|stats| {
+                    stats
+                        .keywords
+                        .into_iter()
+                        .take(NUM_TOPICS)
+                        .map(|(keyword, _)| keyword)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            centrality_percentile: percentile.flatten(),
+            num_backlinks: num_backlinks.flatten(),
+            co_linked_hosts: co_linked_hosts.flatten().unwrap_or_default(),
+            has_entity: has_entity.flatten(),
+            degraded,
+            host,
+        };
+
+        if !degraded {
+            self.cache
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(info.host.clone(), info.clone());
+        }
+
+        info
+    }
+}
+
+/// The answer of a source, or `None` if it failed or did not answer before the deadline.
+async fn answer<T>(
+    deadline: tokio::time::Instant,
+    source: &str,
+    fut: impl Future<Output = Result<Option<T>>>,
+) -> Option<Option<T>> {
+    match tokio::time::timeout_at(deadline, fut).await {
+        Ok(Ok(res)) => Some(res),
+        Ok(Err(err)) => {
+            tracing::debug!(?err, "site info source {source} failed");
+            None
+        }
+        Err(_) => {
+            tracing::debug!("site info source {source} timed out");
+            None
+        }
+    }
+}
+
+pub struct RemoteSiteInfoSources {
+    searcher: Arc<DistributedSearcher>,
+    host_webgraph: Option<Arc<RemoteWebgraph>>,
+    entity_threshold: f64,
+}
+
+impl RemoteSiteInfoSources {
+    pub fn new(searcher: Arc<DistributedSearcher>, entity_threshold: f64) -> Self {
+        Self {
+            searcher,
+            host_webgraph: None,
+            entity_threshold,
+        }
+    }
+
+    pub fn with_host_webgraph(mut self, host_webgraph: Arc<RemoteWebgraph>) -> Self {
+        self.host_webgraph = Some(host_webgraph);
+        self
+    }
+}
+
+/// The name of the site without its public suffix, e.g. `github` for `docs.github.com`.
+fn site_name(host: &str) -> Option<String> {
+    let url = Url::parse(&format!("http://{host}")).ok()?;
+    let domain = url.root_domain()?;
+    let tld = url.tld()?;
+
+    domain
+        .strip_suffix(tld)
+        .map(|name| name.trim_end_matches('.').to_string())
+        .filter(|name| !name.is_empty())
+}
+
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+impl SiteInfoSources for RemoteSiteInfoSources {
+    async fn host_stats(&self, host: &str) -> Result<Option<HostStats>> {
+        self.searcher.host_stats(host).await
+    }
+
+    async fn centrality_percentile(&self, rank: u64) -> Result<Option<u8>> {
+        match &self.host_webgraph {
+            Some(webgraph) => webgraph.centrality_percentile(rank).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn num_backlinks(&self, host: &str) -> Result<Option<u64>> {
+        let webgraph = match &self.host_webgraph {
+            Some(webgraph) => webgraph,
+            None => return Ok(None),
+        };
+
+        let node = Node::from(Url::parse(&format!("http://{host}"))?).into_host();
+
+        Ok(Some(webgraph.num_ingoing_edges(node.id()).await? as u64))
+    }
+
+    async fn co_linked_hosts(
+        &self,
+        host: &str,
+        limits: CoLinkLimits,
+        n: usize,
+    ) -> Result<Option<Vec<CoLinkedHost>>> {
+        let webgraph = match &self.host_webgraph {
+            Some(webgraph) => webgraph,
+            None => return Ok(None),
+        };
+
+        let node = Node::from(Url::parse(&format!("http://{host}"))?).into_host();
+
+        Ok(Some(webgraph.co_linked_hosts(node, limits, n).await?))
+    }
+
+    /// The entity index is searched by the name of the site, and the site has an
+    /// entity if the best match is confident and has the same name as the site.
+    async fn has_entity(&self, host: &str) -> Result<Option<bool>> {
+        let name = match site_name(host) {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+
+        Ok(Some(
+            self.searcher
+                .search_entity(&name, None)
+                .await
+                .map(|entity| {
+                    entity.best.score as f64 > self.entity_threshold
+                        && normalize_name(&entity.best.entity.title) == normalize_name(&name)
+                })
+                .unwrap_or(false),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MockSources {
+        slow_webgraph: bool,
+        failing_index: bool,
+        num_calls: AtomicUsize,
+    }
+
+    impl SiteInfoSources for MockSources {
+        async fn host_stats(&self, _: &str) -> Result<Option<HostStats>> {
+            self.num_calls.fetch_add(1, Ordering::SeqCst);
+
+            if self.failing_index {
+                return Err(anyhow!("shards unavailable"));
+            }
+
+            Ok(Some(HostStats {
+                num_pages: 42,
+                first_indexed: Some(1_000),
+                centrality_rank: Some(10),
+                keywords: vec![("rust".to_string(), 3), ("search".to_string(), 2)],
+            }))
+        }
+
+        async fn centrality_percentile(&self, rank: u64) -> Result<Option<u8>> {
+            Ok(Some(100 - rank as u8))
+        }
+
+        async fn num_backlinks(&self, _: &str) -> Result<Option<u64>> {
+            if self.slow_webgraph {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+
+            Ok(Some(7))
+        }
+
+        async fn co_linked_hosts(
+            &self,
+            _: &str,
+            limits: CoLinkLimits,
+            n: usize,
+        ) -> Result<Option<Vec<CoLinkedHost>>> {
+            assert_eq!(limits.max_linkers, 32);
+            assert_eq!(n, 8);
+
+            Ok(Some(vec![CoLinkedHost {
+                host: "docs.rs".to_string(),
+                score: 0.5,
+            }]))
+        }
+
+        async fn has_entity(&self, _: &str) -> Result<Option<bool>> {
+            Ok(Some(true))
+        }
+    }
+
+    fn manager(sources: MockSources) -> SiteInfoManager<MockSources> {
+        SiteInfoManager::new(
+            sources,
+            &SiteInfoConfig {
+                budget_ms: 50,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn all_sources() {
+        let manager = manager(MockSources::default());
+
+        let info = manager.site_info("www.Example.com").await;
+
+        assert_eq!(
+            info,
+            SiteInfo {
+                host: "example.com".to_string(),
+                first_indexed: Some(1_000),
+                num_pages: Some(42),
+                topics: vec!["rust".to_string(), "search".to_string()],
+                centrality_percentile: Some(90),
+                num_backlinks: Some(7),
+                co_linked_hosts: vec![CoLinkedHost {
+                    host: "docs.rs".to_string(),
+                    score: 0.5,
+                }],
+                has_entity: Some(true),
+                degraded: false,
+            }
+        );
+
+        assert_eq!(manager.site_info("example.com").await, info);
+        assert_eq!(manager.sources.num_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn source_times_out() {
+        let manager = manager(MockSources {
+            slow_webgraph: true,
+            ..Default::default()
+        });
+
+        let start = std::time::Instant::now();
+        let info = manager.site_info("example.com").await;
+
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(info.degraded);
+        assert_eq!(info.num_backlinks, None);
+        assert_eq!(info.num_pages, Some(42));
+        assert_eq!(info.centrality_percentile, Some(90));
+        assert_eq!(info.has_entity, Some(true));
+
+        // degraded panels are not cached
+        manager.site_info("example.com").await;
+        assert_eq!(manager.sources.num_calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn index_fails() {
+        let manager = manager(MockSources {
+            failing_index: true,
+            ..Default::default()
+        });
+
+        let info = manager.site_info("example.com").await;
+
+        assert!(info.degraded);
+        assert_eq!(info.num_pages, None);
+        assert_eq!(info.first_indexed, None);
+        assert!(info.topics.is_empty());
+        assert_eq!(info.centrality_percentile, None);
+        assert_eq!(info.num_backlinks, Some(7));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(site_name("docs.github.com"), Some("github".to_string()));
+        assert_eq!(site_name("bbc.co.uk"), Some("bbc".to_string()));
+        assert_eq!(normalize_name("Git-Hub"), "github");
+    }
+}
@@ -18,25 +18,33 @@ use crate::{
     distributed::{
         cluster::Cluster,
         member::{Service, ShardId},
-        sonic::replication::{
-            AllShardsSelector, RandomReplicaSelector, RemoteClient, ReplicatedClient,
-            ReusableClientManager, ReusableShardedClient, Shard, ShardIdentifier, ShardedClient,
-            SpecificShardSelector,
+        sonic::{
+            replication::{
+                AllReplicaSelector, AllShardsSelector, RandomReplicaSelector, ReplicatedClient,
+                ReusableClientManager, ReusableShardedClient, Shard, ShardIdentifier,
+                ShardedClient, SpecificShardSelector,
+            },
+            CircuitBreakers,
         },
     },
-    entity_index::EntityMatch,
+    entity_index::DisambiguatedEntity,
     entrypoint::{
         entity_search_server,
         search_server::{self, SearchService},
     },
+    freshness::FreshnessHistogram,
     image_store::Image,
     index::Index,
-    inverted_index::{RetrievedWebpage, WebpagePointer},
+    inverted_index::{HostStats, RetrievedWebpage, WebpagePointer},
     ranking::pipeline::{PrecisionRankingWebpage, RecallRankingWebpage},
     Result,
 };
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use fnv::FnvHashMap;
 use futures::future::join_all;
@@ -58,15 +66,23 @@ pub enum Error {
     #[error("Query cannot be empty")]
     EmptyQuery,
 
+    #[error("Query is too long ({len} bytes, the limit is {max} bytes)")]
+    QueryTooLong { len: usize, max: usize },
+
     #[error("Webpage not found")]
     WebpageNotFound,
+
+    #[error("Shards {0:?} are unavailable")]
+    ShardsUnavailable(Vec<ShardId>),
 }
 
 pub trait SearchClient {
+    fn num_shards(&self) -> impl Future<Output = usize> + Send;
+
     fn search_initial(
         &self,
         query: &SearchQuery,
-    ) -> impl Future<Output = Vec<InitialSearchResultShard>> + Send;
+    ) -> impl Future<Output = InitialSearchResult> + Send;
 
     fn retrieve_webpages(
         &self,
@@ -74,7 +90,11 @@ pub trait SearchClient {
         query: &str,
     ) -> impl Future<Output = Vec<(usize, PrecisionRankingWebpage)>> + Send;
 
-    fn search_entity(&self, query: &str) -> impl Future<Output = Option<EntityMatch>> + Send;
+    fn search_entity(
+        &self,
+        query: &str,
+        pinned_entity: Option<&str>,
+    ) -> impl Future<Output = Option<DisambiguatedEntity>> + Send;
 
     fn get_webpage(
         &self,
@@ -106,9 +126,26 @@ impl ShardIdentifier for ShardId {}
 pub struct InitialSearchResultShard {
     pub local_result: InitialWebsiteResult,
     pub shard: ShardId,
+    /// The round trip of the request to the shard if the query has `debug_timings` enabled.
+    pub round_trip: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+pub struct InitialSearchResult {
+    pub shards: Vec<InitialSearchResultShard>,
+    /// Shards that did not return a result, e.g. because none of their replicas could be reached.
+    pub missing_shards: Vec<ShardId>,
+}
+
+impl InitialSearchResult {
+    pub fn is_degraded(&self) -> bool {
+        !self.missing_shards.is_empty()
+    }
 }
 
-struct SearchClientManager;
+struct SearchClientManager {
+    breakers: CircuitBreakers,
+}
 
 impl ReusableClientManager for SearchClientManager {
     const CLIENT_REFRESH_INTERVAL: std::time::Duration = CLIENT_REFRESH_INTERVAL;
@@ -128,8 +165,12 @@ impl ReusableClientManager for SearchClientManager {
         let mut shard_clients = Vec::new();
 
         for (id, replicas) in shards {
-            let replicated =
-                ReplicatedClient::new(replicas.into_iter().map(RemoteClient::new).collect());
+            let replicated = ReplicatedClient::new(
+                replicas
+                    .into_iter()
+                    .map(|addr| self.breakers.client(addr))
+                    .collect(),
+            );
             let shard = Shard::new(id, replicated);
             shard_clients.push(shard);
         }
@@ -138,7 +179,9 @@ impl ReusableClientManager for SearchClientManager {
     }
 }
 
-struct EntitySearchClientManager;
+struct EntitySearchClientManager {
+    breakers: CircuitBreakers,
+}
 
 impl ReusableClientManager for EntitySearchClientManager {
     const CLIENT_REFRESH_INTERVAL: std::time::Duration = CLIENT_REFRESH_INTERVAL;
@@ -150,7 +193,7 @@ impl ReusableClientManager for EntitySearchClientManager {
         let mut replicas = Vec::new();
         for member in cluster.members().await {
             if let Service::EntitySearcher { host } = member.service {
-                replicas.push(RemoteClient::new(host));
+                replicas.push(self.breakers.client(host));
             }
         }
 
@@ -171,12 +214,23 @@ pub struct DistributedSearcher {
 
 impl DistributedSearcher {
     pub async fn new(cluster: Arc<Cluster>) -> Self {
+        Self::with_circuit_breakers(cluster, CircuitBreakers::default()).await
+    }
+
+    /// Searcher that shares the circuit breakers of the servers with other searchers.
+    pub async fn with_circuit_breakers(cluster: Arc<Cluster>, breakers: CircuitBreakers) -> Self {
         Self {
             client: Mutex::new(
-                ReusableShardedClient::new(cluster.clone(), SearchClientManager).await,
+                ReusableShardedClient::new(
+                    cluster.clone(),
+                    SearchClientManager {
+                        breakers: breakers.clone(),
+                    },
+                )
+                .await,
             ),
             entiy_client: Mutex::new(
-                ReusableShardedClient::new(cluster, EntitySearchClientManager).await,
+                ReusableShardedClient::new(cluster, EntitySearchClientManager { breakers }).await,
             ),
         }
     }
@@ -189,6 +243,128 @@ impl DistributedSearcher {
         self.entiy_client.lock().await.conn().await
     }
 
+    /// The statistics of the host combined over all shards.
+    pub async fn host_stats(&self, host: &str) -> Result<Option<HostStats>> {
+        let client = self.conn().await;
+
+        let res = client
+            .send(
+                search_server::GetHostStats {
+                    host: host.to_string(),
+                },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await
+            .map_err(|_| Error::SearchFailed)?;
+
+        Ok(res
+            .into_iter()
+            .filter_map(|(_, reps)| reps.into_iter().find_map(|(_, stats)| stats))
+            .reduce(HostStats::merge))
+    }
+
+    /// The number of pages that match the query summed over all shards.
+    pub async fn count(&self, query: &SearchQuery) -> Result<u64> {
+        let client = self.conn().await;
+
+        let res = client
+            .send(
+                search_server::Count {
+                    query: query.clone(),
+                },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await
+            .map_err(|_| Error::SearchFailed)?;
+
+        Ok(res
+            .into_iter()
+            .filter_map(|(_, reps)| reps.into_iter().find_map(|(_, count)| count))
+            .sum())
+    }
+
+    /// How recently the pages of all the shards were crawled.
+    /// The metadata of a single replica is used for each shard.
+    pub async fn freshness(&self) -> Result<FreshnessHistogram> {
+        let client = self.conn().await;
+
+        let res = client
+            .send(
+                search_server::GetIndexMetadata,
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await
+            .map_err(|_| Error::SearchFailed)?;
+
+        Ok(res
+            .into_iter()
+            .filter_map(|(_, reps)| reps.into_iter().next().map(|(_, metadata)| metadata))
+            .map(|metadata| metadata.freshness)
+            .reduce(FreshnessHistogram::merge)
+            .unwrap_or_default())
+    }
+
+    /// A number that changes whenever a shard starts serving a new commit of its index.
+    /// The newest generation of the replicas of each shard is used, so replicas that
+    /// still serve an older commit don't make the number change back and forth.
+    pub async fn index_generation(&self) -> Result<u64> {
+        let client = self.conn().await;
+
+        let res = client
+            .send(
+                search_server::GetIndexMetadata,
+                &AllShardsSelector,
+                &AllReplicaSelector,
+            )
+            .await
+            .map_err(|_| Error::SearchFailed)?;
+
+        Ok(res
+            .into_iter()
+            .filter_map(|(_, reps)| {
+                reps.into_iter()
+                    .map(|(_, metadata)| metadata.generation)
+                    .max()
+            })
+            .fold(0, u64::wrapping_add))
+    }
+
+    /// Send the search to each shard on its own, so the round trip to each shard can be timed.
+    async fn search_initial_timed(
+        &self,
+        client: &ShardedClient<SearchService, ShardId>,
+        query: &SearchQuery,
+    ) -> Vec<InitialSearchResultShard> {
+        let futures = client.shard_ids().into_iter().map(|shard| async move {
+            let start = Instant::now();
+            let res = client
+                .send(
+                    search_server::Search {
+                        query: query.clone(),
+                    },
+                    &SpecificShardSelector(shard),
+                    &RandomReplicaSelector,
+                )
+                .await;
+            let round_trip = start.elapsed();
+
+            res.ok()?
+                .pop()
+                .and_then(|(_, mut res)| res.pop())
+                .and_then(|(_, res)| res)
+                .map(|local_result| InitialSearchResultShard {
+                    local_result,
+                    shard,
+                    round_trip: Some(round_trip),
+                })
+        });
+
+        join_all(futures).await.into_iter().flatten().collect()
+    }
+
     async fn retrieve_webpages_from_shard(
         &self,
         shard: ShardId,
@@ -223,11 +399,17 @@ impl DistributedSearcher {
 }
 
 impl SearchClient for DistributedSearcher {
-    async fn search_initial(&self, query: &SearchQuery) -> Vec<InitialSearchResultShard> {
+    async fn num_shards(&self) -> usize {
+        self.conn().await.shard_ids().len()
+    }
+
+    async fn search_initial(&self, query: &SearchQuery) -> InitialSearchResult {
         let client = self.conn().await;
         let mut results = Vec::new();
 
-        if let Ok(res) = client
+        if query.debug_timings {
+            results = self.search_initial_timed(&client, query).await;
+        } else if let Ok(res) = client
             .send(
                 search_server::Search {
                     query: query.clone(),
@@ -242,12 +424,22 @@ impl SearchClient for DistributedSearcher {
                     results.push(InitialSearchResultShard {
                         local_result: res,
                         shard: shard_id,
+                        round_trip: None,
                     });
                 }
             }
         }
 
-        results
+        let missing_shards = client
+            .shard_ids()
+            .into_iter()
+            .filter(|id| !results.iter().any(|res| res.shard == *id))
+            .collect();
+
+        InitialSearchResult {
+            shards: results,
+            missing_shards,
+        }
     }
 
     async fn retrieve_webpages(
@@ -365,13 +557,18 @@ impl SearchClient for DistributedSearcher {
             .and_then(|(_, v)| v))
     }
 
-    async fn search_entity(&self, query: &str) -> Option<EntityMatch> {
+    async fn search_entity(
+        &self,
+        query: &str,
+        pinned_entity: Option<&str>,
+    ) -> Option<DisambiguatedEntity> {
         let client = self.entity_conn().await;
 
         client
             .send(
                 entity_search_server::Search {
                     query: query.to_string(),
+                    pinned_entity: pinned_entity.map(|id| id.to_string()),
                 },
                 &AllShardsSelector,
                 &RandomReplicaSelector,
@@ -393,13 +590,21 @@ impl From<LocalSearcher<Index>> for LocalSearchClient {
 }
 
 impl SearchClient for LocalSearchClient {
-    async fn search_initial(&self, query: &SearchQuery) -> Vec<InitialSearchResultShard> {
+    async fn num_shards(&self) -> usize {
+        1
+    }
+
+    async fn search_initial(&self, query: &SearchQuery) -> InitialSearchResult {
         let res = self.0.search_initial(query, true).unwrap();
 
-        vec![InitialSearchResultShard {
-            local_result: res,
-            shard: ShardId::new(0),
-        }]
+        InitialSearchResult {
+            shards: vec![InitialSearchResultShard {
+                local_result: res,
+                shard: ShardId::new(0),
+                round_trip: None,
+            }],
+            missing_shards: Vec::new(),
+        }
     }
 
     async fn retrieve_webpages(
@@ -454,7 +659,11 @@ impl SearchClient for LocalSearchClient {
         Ok(None)
     }
 
-    async fn search_entity(&self, _query: &str) -> Option<EntityMatch> {
+    async fn search_entity(
+        &self,
+        _query: &str,
+        _pinned_entity: Option<&str>,
+    ) -> Option<DisambiguatedEntity> {
         None
     }
 }
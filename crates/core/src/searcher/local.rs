@@ -20,23 +20,28 @@ use std::sync::{Arc, RwLockReadGuard};
 use itertools::Itertools;
 use url::Url;
 
-use crate::collector::approx_count;
-use crate::config::{CollectorConfig, SnippetConfig};
+use crate::collector::{approx_count, MemoryBudget, SearchCursor};
+use crate::config::{CollectorConfig, RetrievalConfig, SnippetConfig, WarmupConfig};
+use crate::fastfield_reader::Warmup;
 use crate::index::Index;
-use crate::inverted_index::{InvertedIndex, RetrievedWebpage};
+use crate::inverted_index::{HostStats, InvertedIndex, RetrievedWebpage};
 use crate::models::dual_encoder::DualEncoder;
-use crate::query::Query;
+use crate::query::{segmentation::Segmenter, synonyms::SynonymExpander, Query};
 use crate::ranking::models::lambdamart::LambdaMART;
 use crate::ranking::models::linear::LinearRegression;
 use crate::ranking::pipeline::{
     LocalRecallRankingWebpage, PrecisionRankingWebpage, RankingPipeline, RecallRankingWebpage,
 };
-use crate::ranking::{Ranker, SignalComputer, SignalEnum, SignalScore};
+use crate::ranking::{Ranker, SignalBounds, SignalComputer, SignalEnum, SignalScore};
+use crate::replication::ReplicatedIndex;
+use crate::schema::FastFieldEnum;
 use crate::search_ctx::Ctx;
 use crate::search_prettifier::DisplayedWebpage;
+use crate::webgraph::Node;
 use crate::{inverted_index, live_index, Result};
 
-use super::WebsitesResult;
+use super::timings::{Recorder, Timings};
+use super::{sanitize, WebsitesResult};
 use super::{InitialWebsiteResult, SearchQuery};
 
 pub trait SearchableIndex {
@@ -46,6 +51,9 @@ pub trait SearchableIndex {
 
     fn guard(&self) -> Self::SearchGuard<'_>;
     fn set_snippet_config(&mut self, config: SnippetConfig);
+    fn set_webpage_cache_capacity(&mut self, capacity: usize);
+    fn set_store_block_cache_capacity(&mut self, num_blocks: usize) -> Result<()>;
+    fn set_retrieval_config(&mut self, config: RetrievalConfig) -> Result<()>;
 }
 
 pub trait SearchGuard<'a> {
@@ -65,6 +73,19 @@ impl SearchableIndex for Index {
     fn set_snippet_config(&mut self, config: SnippetConfig) {
         self.inverted_index.set_snippet_config(config);
     }
+
+    fn set_webpage_cache_capacity(&mut self, capacity: usize) {
+        self.inverted_index.set_webpage_cache_capacity(capacity);
+    }
+
+    fn set_store_block_cache_capacity(&mut self, num_blocks: usize) -> Result<()> {
+        self.inverted_index
+            .set_store_block_cache_capacity(num_blocks)
+    }
+
+    fn set_retrieval_config(&mut self, config: RetrievalConfig) -> Result<()> {
+        self.inverted_index.set_retrieval_config(&config)
+    }
 }
 
 pub struct NormalIndexSearchGuard<'a> {
@@ -89,6 +110,22 @@ impl SearchableIndex for Arc<live_index::Index> {
     fn set_snippet_config(&mut self, config: SnippetConfig) {
         self.write().inverted_index.set_snippet_config(config);
     }
+
+    fn set_webpage_cache_capacity(&mut self, capacity: usize) {
+        self.write()
+            .inverted_index
+            .set_webpage_cache_capacity(capacity);
+    }
+
+    fn set_store_block_cache_capacity(&mut self, num_blocks: usize) -> Result<()> {
+        self.write()
+            .inverted_index
+            .set_store_block_cache_capacity(num_blocks)
+    }
+
+    fn set_retrieval_config(&mut self, config: RetrievalConfig) -> Result<()> {
+        self.write().inverted_index.set_retrieval_config(&config)
+    }
 }
 
 pub struct LiveIndexSearchGuard<'a> {
@@ -101,12 +138,52 @@ impl<'a> SearchGuard<'a> for LiveIndexSearchGuard<'a> {
     }
 }
 
+impl SearchableIndex for ReplicatedIndex {
+    type SearchGuard<'a> = ReplicatedIndexSearchGuard;
+
+    fn guard(&self) -> Self::SearchGuard<'_> {
+        ReplicatedIndexSearchGuard { index: self.read() }
+    }
+
+    fn set_snippet_config(&mut self, config: SnippetConfig) {
+        ReplicatedIndex::set_snippet_config(self, config);
+    }
+
+    fn set_webpage_cache_capacity(&mut self, capacity: usize) {
+        ReplicatedIndex::set_webpage_cache_capacity(self, capacity);
+    }
+
+    fn set_store_block_cache_capacity(&mut self, num_blocks: usize) -> Result<()> {
+        ReplicatedIndex::set_store_block_cache_capacity(self, num_blocks)
+    }
+
+    fn set_retrieval_config(&mut self, config: RetrievalConfig) -> Result<()> {
+        ReplicatedIndex::set_retrieval_config(self, config)
+    }
+}
+
+/// Holds on to the generation the search started on.
+pub struct ReplicatedIndexSearchGuard {
+    index: Arc<Index>,
+}
+
+impl<'a> SearchGuard<'a> for ReplicatedIndexSearchGuard {
+    fn search_index(&self) -> &'_ Index {
+        &self.index
+    }
+}
+
 pub struct LocalSearcher<I: SearchableIndex> {
     index: I,
     linear_regression: Option<Arc<LinearRegression>>,
     lambda_model: Option<Arc<LambdaMART>>,
     dual_encoder: Option<Arc<DualEncoder>>,
     collector_config: CollectorConfig,
+    current_timestamp: Option<usize>,
+    signal_bounds: Option<Arc<SignalBounds>>,
+    collapse_field: Option<FastFieldEnum>,
+    synonyms: Option<Arc<SynonymExpander>>,
+    segmenter: Option<Arc<Segmenter>>,
 }
 
 impl<I> From<I> for LocalSearcher<I>
@@ -122,6 +199,8 @@ struct InvertedIndexResult {
     webpages: Vec<LocalRecallRankingWebpage>,
     num_hits: approx_count::Count,
     has_more: bool,
+    next_cursor: Option<SearchCursor>,
+    redirect: Option<String>,
 }
 
 impl<I> LocalSearcher<I>
@@ -135,6 +214,11 @@ where
             lambda_model: None,
             dual_encoder: None,
             collector_config: CollectorConfig::default(),
+            current_timestamp: None,
+            signal_bounds: None,
+            collapse_field: None,
+            synonyms: None,
+            segmenter: None,
         }
     }
 
@@ -154,17 +238,65 @@ where
         self.collector_config = config;
     }
 
+    /// Expand the plain terms of the queries with their synonyms.
+    pub fn set_synonyms(&mut self, synonyms: SynonymExpander) {
+        self.synonyms = Some(Arc::new(synonyms));
+    }
+
+    /// Split the terms of the queries that are written without spaces and
+    /// match the terms that look like a domain in the pages of the host.
+    pub fn set_segmenter(&mut self, segmenter: Segmenter) {
+        self.segmenter = Some(Arc::new(segmenter));
+    }
+
+    pub fn set_signal_bounds(&mut self, bounds: SignalBounds) {
+        self.signal_bounds = Some(Arc::new(bounds));
+    }
+
     pub fn set_snippet_config(&mut self, config: SnippetConfig) {
         self.index.set_snippet_config(config);
     }
 
+    pub fn set_webpage_cache_capacity(&mut self, capacity: usize) {
+        self.index.set_webpage_cache_capacity(capacity);
+    }
+
+    /// Keep the decompressed doc store blocks in a single cache shared by all requests,
+    /// so the blocks of popular results are not decompressed for every request.
+    pub fn set_store_block_cache_capacity(&mut self, num_blocks: usize) -> Result<()> {
+        self.index.set_store_block_cache_capacity(num_blocks)
+    }
+
+    /// Read the documents of the results in parallel, and return the documents that are
+    /// not read within the timeout with only their url and title instead of waiting for them.
+    pub fn set_retrieval_config(&mut self, config: RetrievalConfig) -> Result<()> {
+        self.index.set_retrieval_config(config)
+    }
+
+    /// Compute the time dependent signals relative to `timestamp` (seconds since the unix epoch)
+    /// instead of the current time.
+    pub fn set_current_timestamp(&mut self, timestamp: usize) {
+        self.current_timestamp = Some(timestamp);
+    }
+
+    /// Only return the highest scoring result for each value of the fast field.
+    pub fn set_collapse_field(&mut self, field: FastFieldEnum) {
+        self.collapse_field = Some(field);
+    }
+
     fn parse_query<'a, G: SearchGuard<'a>>(
         &'a self,
         ctx: &Ctx,
         guard: &G,
         query: &SearchQuery,
     ) -> Result<Query> {
-        Query::parse(ctx, query, guard.inverted_index())
+        Query::parse_with_expansions(
+            ctx,
+            query,
+            guard.inverted_index(),
+            self.synonyms.as_deref(),
+            self.segmenter.as_deref(),
+        )
     }
 
     fn ranker<'a, G: SearchGuard<'a>>(
@@ -182,11 +314,22 @@ where
 
         ranker.de_rank_similar(de_rank_similar);
 
+        if let Some(host_filter) = query.host_filter() {
+            ranker = ranker.with_host_filter(Arc::clone(host_filter));
+        }
+
+        let num_segments = guard.inverted_index().num_segments();
+        let mut max_docs = self.collector_config.max_docs_considered;
+
+        if let Some(budget) = self.collector_config.memory_budget {
+            let docs_per_segment = MemoryBudget::new(budget)
+                .docs_per_segment(query.num_results() + query.offset(), num_segments)?;
+
+            max_docs = max_docs.min(docs_per_segment * num_segments.max(1));
+        }
+
         Ok(ranker
-            .with_max_docs(
-                self.collector_config.max_docs_considered,
-                guard.inverted_index().num_segments(),
-            )
+            .with_max_docs(max_docs, num_segments)
             .with_num_results(query.num_results())
             .with_offset(query.offset()))
     }
@@ -197,8 +340,16 @@ where
         guard: &G,
         query: &SearchQuery,
         de_rank_similar: bool,
+        timings: &mut Recorder,
     ) -> Result<InvertedIndexResult> {
         let mut query = query.clone();
+        let page_size = query.num_results;
+
+        // a page after a cursor is ranked on its own, so the pipeline must not skip any of it
+        if query.search_after.is_some() {
+            query.page = 0;
+        }
+
         let pipeline: RankingPipeline<LocalRecallRankingWebpage> =
             RankingPipeline::<LocalRecallRankingWebpage>::recall_stage(
                 &mut query,
@@ -207,7 +358,7 @@ where
                 self.collector_config.clone(),
                 100,
             );
-        let parsed_query = self.parse_query(ctx, guard, &query)?;
+        let parsed_query = timings.time("parse_query", || self.parse_query(ctx, guard, &query))?;
 
         let mut computer = SignalComputer::new(Some(&parsed_query));
 
@@ -224,35 +375,103 @@ where
             computer.set_linear_model(model.clone());
         }
 
-        let ranker = self.ranker(&parsed_query, guard, de_rank_similar, computer)?;
+        if let Some(timestamp) = self.current_timestamp {
+            computer.set_current_timestamp(timestamp);
+        }
 
-        let res = guard.inverted_index().search_initial(
-            &parsed_query,
-            ctx,
-            ranker.collector(ctx.clone()),
-        )?;
+        if let Some(bounds) = self.signal_bounds.as_ref() {
+            computer.set_signal_bounds(bounds.clone());
+        }
+
+        let mut ranker = self.ranker(&parsed_query, guard, de_rank_similar, computer)?;
+
+        if let Some(cursor) = query.search_after {
+            // one more than the page to know whether there is a next page
+            ranker = ranker
+                .with_num_results(page_size + 1)
+                .with_offset(0)
+                .with_search_after(cursor);
+        }
+
+        let collector = ranker.collector(ctx.clone());
+        let mut segment_timings = timings.is_enabled().then(Vec::new);
+        let start = timings.start();
+        let res = match self.collapse_field {
+            Some(field) => guard.inverted_index().search_initial_timed(
+                &parsed_query,
+                ctx,
+                collector.collapse_by(field),
+                segment_timings.as_mut(),
+            )?,
+            None => guard.inverted_index().search_initial_timed(
+                &parsed_query,
+                ctx,
+                collector,
+                segment_timings.as_mut(),
+            )?,
+        };
+        timings.record_with(
+            "collect",
+            start,
+            segment_timings
+                .unwrap_or_default()
+                .into_iter()
+                .map(|timing| Timings::new(format!("segment {}", timing.segment), timing.duration))
+                .collect(),
+        );
+
+        let mut top_websites = res.top_websites;
+        let next_cursor = match query.search_after {
+            Some(_) if top_websites.len() > page_size => {
+                top_websites.truncate(page_size);
+                top_websites.last().map(SearchCursor::after)
+            }
+            _ => None,
+        };
 
         let fastfield_reader = guard.inverted_index().fastfield_reader();
 
-        let ranking_websites = guard.inverted_index().retrieve_ranking_websites(
-            ctx,
-            res.top_websites,
-            ranker.computer(),
-            &fastfield_reader,
-        )?;
+        let ranking_websites = timings.time("retrieve", || {
+            guard.inverted_index().retrieve_ranking_websites(
+                ctx,
+                top_websites,
+                ranker.computer(),
+                &fastfield_reader,
+            )
+        })?;
 
         let pipe_top_n = pipeline.top_n;
-        let has_more = ranking_websites.len() > pipe_top_n;
+        let has_more = next_cursor.is_some() || ranking_websites.len() > pipe_top_n;
+
+        let mut stages = Recorder::new(timings.is_enabled());
+        let start = timings.start();
+        let ranking_websites = pipeline.apply_timed(ranking_websites, &mut stages);
+        timings.record_with("recall_pipeline", start, stages.into_stages());
 
-        let ranking_websites = pipeline.apply(ranking_websites);
+        let redirect = parsed_query
+            .navigational()
+            .filter(|url| guard.inverted_index().get_homepage(url).is_some())
+            .map(|url| url.to_string());
 
         Ok(InvertedIndexResult {
             webpages: ranking_websites,
             num_hits: res.num_websites,
             has_more,
+            next_cursor,
+            redirect,
         })
     }
 
+    /// Warm the fast fields of the largest segments first and the rest in the background.
+    /// Queries can be served while the warmup runs; they warm any cold segment they hit.
+    pub fn warmup(&self, config: &WarmupConfig) -> Warmup {
+        self.index
+            .guard()
+            .inverted_index()
+            .fastfield_reader()
+            .warmup(config)
+    }
+
     pub fn index(&self) -> &I {
         &self.index
     }
@@ -262,18 +481,43 @@ where
         query: &SearchQuery,
         de_rank_similar: bool,
     ) -> Result<InitialWebsiteResult> {
+        let mut timings = Recorder::new(query.debug_timings);
+        let start = timings.start();
+
         let guard = self.index.guard();
         let ctx = guard.inverted_index().local_search_ctx();
         let inverted_index_result =
-            self.search_inverted_index(&ctx, &guard, query, de_rank_similar)?;
+            self.search_inverted_index(&ctx, &guard, query, de_rank_similar, &mut timings)?;
+
+        let websites = if query.dedup_urls {
+            timings.time("dedup", || {
+                dedup_canonical_urls(guard.inverted_index(), inverted_index_result.webpages)
+            })?
+        } else {
+            inverted_index_result.webpages
+        };
 
         Ok(InitialWebsiteResult {
-            websites: inverted_index_result.webpages,
+            websites,
             num_websites: inverted_index_result.num_hits,
             has_more: inverted_index_result.has_more,
+            timings: timings.finish("search_initial", start),
+            next_cursor: inverted_index_result.next_cursor,
+            redirect: inverted_index_result.redirect,
         })
     }
 
+    /// The number of pages that match the query. This is much cheaper than a search,
+    /// since the pages are neither ranked nor retrieved. Allowlists of hosts that are too
+    /// large to be compiled into the query are not applied to the count.
+    pub fn count(&self, query: &SearchQuery) -> Result<u64> {
+        let guard = self.index.guard();
+        let ctx = guard.inverted_index().local_search_ctx();
+        let parsed_query = self.parse_query(&ctx, &guard, query)?;
+
+        guard.inverted_index().count(&parsed_query, &ctx)
+    }
+
     pub fn retrieve_websites(
         &self,
         websites: &[inverted_index::WebpagePointer],
@@ -295,6 +539,13 @@ where
         use std::time::Instant;
 
         let start = Instant::now();
+        let mut timings = Recorder::new(query.debug_timings);
+
+        let sanitized = sanitize::sanitize(&query.query)?;
+        let query = &SearchQuery {
+            query: sanitized.query,
+            ..query.clone()
+        };
         let mut search_query = query.clone();
 
         let pipeline = {
@@ -317,7 +568,11 @@ where
             }
         };
 
-        let search_result = self.search_initial(&search_query, true)?;
+        let mut search_result = self.search_initial(&search_query, true)?;
+
+        if let Some(shard_timings) = search_result.timings.take() {
+            timings.push(shard_timings);
+        }
 
         let pointers: Vec<_> = search_result
             .websites
@@ -325,8 +580,10 @@ where
             .map(|website| website.pointer().clone())
             .collect();
 
-        let websites: Vec<_> = self
-            .retrieve_websites(&pointers, &query.query)?
+        let websites: Vec<_> = timings
+            .time("retrieve", || {
+                self.retrieve_websites(&pointers, &query.query)
+            })?
             .into_iter()
             .zip_eq(search_result.websites)
             .map(|(webpage, ranking)| {
@@ -337,7 +594,10 @@ where
 
         let search_len = websites.len();
 
-        let top_websites = pipeline.apply(websites);
+        let mut stages = Recorder::new(timings.is_enabled());
+        let pipeline_start = timings.start();
+        let top_websites = pipeline.apply_timed(websites, &mut stages);
+        timings.record_with("rerank_pipeline", pipeline_start, stages.into_stages());
 
         let has_more_results = search_len != top_websites.len();
 
@@ -346,7 +606,9 @@ where
             .map(|website| website.ranking().pointer().clone())
             .collect();
 
-        let retrieved_sites = self.retrieve_websites(&pointers, &search_query.query)?;
+        let retrieved_sites = timings.time("retrieve_top", || {
+            self.retrieve_websites(&pointers, &search_query.query)
+        })?;
 
         let coefficients = query.signal_coefficients();
 
@@ -365,19 +627,43 @@ where
                         SignalScore {
                             value: *score,
                             coefficient: coefficients.get(&signal),
+                            unclamped_value: ranking
+                                .ranking()
+                                .unclamped_signals()
+                                .get(signal)
+                                .copied(),
                         },
                     );
                 }
             }
 
             webpage.ranking_signals = Some(ranking_signals);
+            webpage.history_boost = ranking.ranking().history_boost();
+
+            if query.optic_debug {
+                webpage.optic_rule_matches = Some(ranking.ranking().optic_rule_matches().to_vec());
+            }
+        }
+
+        if let Some(sort) = &query.sort_page {
+            sort.sort(&mut webpages);
         }
 
+        let optic_debug = query.optic_debug_summary(&webpages);
+
         Ok(WebsitesResult {
             num_hits: search_result.num_websites,
             webpages,
             search_duration_ms: start.elapsed().as_millis(),
             has_more_results,
+            optic_debug,
+            degraded: false,
+            missing_shards: Vec::new(),
+            degraded_stages: Vec::new(),
+            query_truncated: sanitized.truncated,
+            timings: timings.finish("search", Some(start)),
+            features: query.features(),
+            redirect: search_result.redirect,
         })
     }
 
@@ -388,12 +674,81 @@ where
     pub fn get_homepage(&self, url: &Url) -> Option<RetrievedWebpage> {
         self.index.guard().inverted_index().get_homepage(url)
     }
+
+    pub fn host_stats(&self, host: &str) -> Result<Option<HostStats>> {
+        self.index.guard().inverted_index().host_stats(host)
+    }
+}
+
+/// Collapse the websites whose urls have the same canonical form (as nodes in the webgraph),
+/// keeping the highest scoring website of each group at the position of the first one.
+///
+/// The canonical urls are compared by their hash in the fast fields. Only the websites
+/// of segments indexed before the hash was added are read from the stored documents.
+fn dedup_canonical_urls(
+    inverted_index: &InvertedIndex,
+    websites: Vec<LocalRecallRankingWebpage>,
+) -> Result<Vec<LocalRecallRankingWebpage>> {
+    let pointers: Vec<_> = websites
+        .iter()
+        .map(|website| website.pointer().clone())
+        .collect();
+    let mut hashes = inverted_index.canonical_url_hashes(&pointers);
+
+    let missing: Vec<_> = pointers
+        .iter()
+        .zip(&hashes)
+        .filter(|(_, hash)| hash.is_none())
+        .map(|(pointer, _)| pointer.clone())
+        .collect();
+
+    if !missing.is_empty() {
+        let mut urls = inverted_index.retrieve_urls(&missing)?.into_iter();
+
+        for hash in hashes.iter_mut().filter(|hash| hash.is_none()) {
+            *hash = urls.next().map(|url| {
+                let node = match Url::parse(&url) {
+                    Ok(url) => Node::from(url),
+                    Err(_) => Node::from_raw(url),
+                };
+
+                node.id().as_u64()
+            });
+        }
+    }
+
+    let mut positions: HashMap<u64, usize> = HashMap::new();
+    let mut deduped: Vec<LocalRecallRankingWebpage> = Vec::with_capacity(websites.len());
+
+    for (website, canonical) in websites.into_iter().zip_eq(hashes) {
+        let canonical = canonical.unwrap_or_default();
+
+        match positions.get(&canonical) {
+            Some(&pos) => {
+                if website.score() > deduped[pos].score() {
+                    deduped[pos] = website;
+                }
+            }
+            None => {
+                positions.insert(canonical, deduped.len());
+                deduped.push(website);
+            }
+        }
+    }
+
+    Ok(deduped)
 }
 
 #[cfg(test)]
 mod tests {
+    use tantivy::tokenizer::TokenStream;
+    use whatlang::Lang;
+
     use crate::{
+        api::search::{PageSort, SortField, SortOrder},
+        schema::text_field::{self, TextField},
         searcher::NUM_RESULTS_PER_PAGE,
+        tokenizer::{Tokenizer, TokenizerKind},
         webpage::{Html, Webpage},
     };
 
@@ -457,4 +812,801 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn search_after_cursor() {
+        const NUM_WEBSITES: usize = 53;
+        const PAGE_SIZE: usize = 5;
+
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for i in 0..NUM_WEBSITES {
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        r#"
+            <html>
+                <head>
+                    <title>Example website</title>
+                </head>
+                <body>
+                    test
+                </body>
+            </html>
+            "#,
+                        &format!("https://www.{i}.com"),
+                    )
+                    .unwrap(),
+                    // some of the websites are tied
+                    host_centrality: (i / 3) as f64,
+                    fetch_time_ms: 500,
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+
+            // spread the websites over a few segments
+            if i % 20 == 19 {
+                index.commit().unwrap();
+            }
+        }
+
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index);
+
+        let mut cursor = SearchCursor::Start;
+        let mut seen = Vec::new();
+        let mut num_pages = 0;
+
+        loop {
+            let res = searcher
+                .search_initial(
+                    &SearchQuery {
+                        query: "test".to_string(),
+                        num_results: PAGE_SIZE,
+                        search_after: Some(cursor),
+                        ..Default::default()
+                    },
+                    true,
+                )
+                .unwrap();
+
+            num_pages += 1;
+            assert!(res.websites.len() <= PAGE_SIZE);
+            assert_eq!(res.has_more, res.next_cursor.is_some());
+
+            seen.extend(res.websites.iter().map(|website| website.pointer().address));
+
+            match res.next_cursor {
+                Some(next) => {
+                    assert_eq!(res.websites.len(), PAGE_SIZE);
+                    cursor = next;
+                }
+                None => break,
+            }
+        }
+
+        assert_eq!(num_pages, NUM_WEBSITES.div_ceil(PAGE_SIZE));
+
+        let unique: std::collections::HashSet<_> = seen.iter().copied().collect();
+        assert_eq!(
+            unique.len(),
+            seen.len(),
+            "a result was on more than one page"
+        );
+        assert_eq!(seen.len(), NUM_WEBSITES, "a result was not on any page");
+    }
+
+    #[test]
+    fn queries_during_background_warmup() {
+        let path = crate::gen_temp_path();
+        let mut index = Index::open(&path).unwrap();
+        index.prepare_writer().unwrap();
+
+        for (segment, num_docs) in [3, 1, 2].into_iter().enumerate() {
+            for i in 0..num_docs {
+                index
+                    .insert(&Webpage {
+                        html: Html::parse(
+                            r#"
+            <html>
+                <head>
+                    <title>Example website</title>
+                </head>
+                <body>
+                    test
+                </body>
+            </html>
+            "#,
+                            &format!("https://www.{segment}-{i}.com"),
+                        )
+                        .unwrap(),
+                        ..Default::default()
+                    })
+                    .unwrap();
+            }
+
+            index.commit().unwrap();
+        }
+        drop(index);
+
+        let searcher = LocalSearcher::new(Index::open(&path).unwrap());
+        let reader = searcher.index().inverted_index.fastfield_reader();
+        let segments = reader.segments_by_priority().to_vec();
+        assert_eq!(segments.len(), 3);
+        assert!(segments.iter().all(|segment| !reader.is_warm(segment)));
+
+        let warmup = searcher.warmup(&WarmupConfig {
+            priority_segments: 1,
+            background_delay_ms: 500,
+        });
+        warmup.wait_ready();
+
+        assert!(!warmup.is_done());
+        assert!(reader.is_warm(&segments[0]));
+        assert!(!reader.is_warm(&segments[2]));
+
+        let res = searcher
+            .search(&SearchQuery {
+                query: "test".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(res.webpages.len(), 6);
+
+        warmup.wait_done();
+        assert!(segments.iter().all(|segment| reader.is_warm(segment)));
+    }
+
+    #[test]
+    fn canonical_url_duplicates() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for url in ["http://a/x", "https://a/x/", "https://b/x"] {
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        r#"
+            <html>
+                <head>
+                    <title>Example website</title>
+                </head>
+                <body>
+                    test
+                </body>
+            </html>
+            "#,
+                        url,
+                    )
+                    .unwrap(),
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index);
+
+        let search = |dedup_urls: bool| {
+            searcher
+                .search_initial(
+                    &SearchQuery {
+                        query: "test".to_string(),
+                        dedup_urls,
+                        ..Default::default()
+                    },
+                    true,
+                )
+                .unwrap()
+                .websites
+                .len()
+        };
+
+        assert_eq!(search(false), 3);
+        assert_eq!(search(true), 2);
+    }
+
+    #[test]
+    fn store_block_cache_is_shared_between_requests() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for segment in 0..3 {
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        r#"
+            <html>
+                <head>
+                    <title>Example website</title>
+                </head>
+                <body>
+                    test
+                </body>
+            </html>
+            "#,
+                        &format!("https://www.{segment}.com"),
+                    )
+                    .unwrap(),
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+
+            index.commit().unwrap();
+        }
+
+        let mut searcher = LocalSearcher::new(index);
+        searcher.set_store_block_cache_capacity(10).unwrap();
+
+        let search = || {
+            searcher
+                .search(&SearchQuery {
+                    query: "test".to_string(),
+                    ..Default::default()
+                })
+                .unwrap()
+                .webpages
+                .len()
+        };
+
+        assert_eq!(search(), 3);
+
+        let cache = searcher.index().inverted_index.store_block_cache().unwrap();
+        let first = cache.stats();
+        assert_eq!(first.cache_misses, 3);
+
+        assert_eq!(search(), 3);
+        assert_eq!(search(), 3);
+
+        // the later requests decompress no blocks
+        let retrievals = first.cache_hits + first.cache_misses;
+        let stats = cache.stats();
+        assert_eq!(stats.cache_misses, first.cache_misses);
+        assert_eq!(stats.cache_hits, first.cache_hits + 2 * retrievals);
+        assert_eq!(stats.num_entries, 3);
+    }
+
+    #[test]
+    fn collector_memory_budget() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for i in 0..10 {
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        r#"
+            <html>
+                <head>
+                    <title>Example website</title>
+                </head>
+                <body>
+                    test
+                </body>
+            </html>
+            "#,
+                        &format!("https://www.{i}.com"),
+                    )
+                    .unwrap(),
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let mut searcher = LocalSearcher::new(index);
+        let query = SearchQuery {
+            query: "test".to_string(),
+            ..Default::default()
+        };
+
+        searcher.set_collector_config(CollectorConfig {
+            memory_budget: Some(64),
+            ..Default::default()
+        });
+
+        let err = searcher.search_initial(&query, true).unwrap_err();
+        let err = err
+            .downcast_ref::<crate::collector::BudgetExceeded>()
+            .expect("search should fail with a budget error");
+        assert_eq!(err.budget, 64);
+        assert!(err.required > err.budget);
+        assert!(searcher.search(&query).is_err());
+
+        searcher.set_collector_config(CollectorConfig {
+            memory_budget: Some(1024 * 1024),
+            ..Default::default()
+        });
+
+        assert_eq!(searcher.search(&query).unwrap().webpages.len(), 10);
+    }
+
+    #[test]
+    fn synonym_expansion() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for (host, title, body) in [
+            ("a", "Automobile repair", "We fix every automobile in town"),
+            ("b", "Bicycle repair", "We fix every bicycle in town"),
+            ("d", "Car repair", "We fix every car in town"),
+        ] {
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        &format!(
+                            r#"
+            <html>
+                <head>
+                    <title>{title}</title>
+                </head>
+                <body>
+                    {body}
+                </body>
+            </html>
+            "#
+                        ),
+                        &format!("https://www.{host}.com"),
+                    )
+                    .unwrap(),
+                    fetch_time_ms: 500,
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let urls = |searcher: &LocalSearcher<Index>, query: &str| -> Vec<String> {
+            searcher
+                .search(&SearchQuery {
+                    query: query.to_string(),
+                    ..Default::default()
+                })
+                .unwrap()
+                .webpages
+                .into_iter()
+                .map(|webpage| webpage.url)
+                .collect()
+        };
+
+        let mut searcher = LocalSearcher::new(index);
+        assert_eq!(urls(&searcher, "car repair"), vec!["https://www.d.com/"]);
+
+        searcher.set_synonyms(SynonymExpander::new([vec!["car", "automobile"]], 0.5));
+
+        // the literal term ranks above its synonym
+        assert_eq!(
+            urls(&searcher, "car repair"),
+            vec!["https://www.d.com/", "https://www.a.com/"]
+        );
+        assert_eq!(
+            urls(&searcher, "automobile"),
+            vec!["https://www.a.com/", "https://www.d.com/"]
+        );
+
+        // phrases are not expanded
+        assert_eq!(urls(&searcher, "\"every car\""), vec!["https://www.d.com/"]);
+    }
+
+    fn segmentation_index(pages: &[(&str, &str, &str)]) -> LocalSearcher<Index> {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for (url, title, body) in pages {
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        &format!(
+                            r#"
+            <html>
+                <head>
+                    <title>{title}</title>
+                </head>
+                <body>
+                    {body}
+                </body>
+            </html>
+            "#
+                        ),
+                        url,
+                    )
+                    .unwrap(),
+                    fetch_time_ms: 500,
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let mut searcher = LocalSearcher::new(index);
+        let segmenter =
+            Segmenter::from_index(searcher.index().guard().inverted_index(), 1, 0.5).unwrap();
+        searcher.set_segmenter(segmenter);
+
+        searcher
+    }
+
+    #[test]
+    fn concatenated_terms_are_segmented() {
+        let searcher = segmentation_index(&[
+            (
+                "https://www.a.com",
+                "The async book",
+                "Learn to write async programs in rust with this book",
+            ),
+            (
+                "https://www.b.com",
+                "Cooking book",
+                "Recipes for every day of the week",
+            ),
+        ]);
+
+        let search = |query: &str, verbatim: bool| -> Vec<String> {
+            searcher
+                .search(&SearchQuery {
+                    query: query.to_string(),
+                    verbatim,
+                    ..Default::default()
+                })
+                .unwrap()
+                .webpages
+                .into_iter()
+                .map(|webpage| webpage.url)
+                .collect()
+        };
+
+        assert_eq!(search("rustasyncbook", false), vec!["https://www.a.com/"]);
+        assert_eq!(search("RustAsyncBook", false), vec!["https://www.a.com/"]);
+
+        // phrases and verbatim queries are searched as they are written
+        assert!(search("\"rustasyncbook\"", false).is_empty());
+        assert!(search("rustasyncbook", true).is_empty());
+    }
+
+    #[test]
+    fn domain_query_finds_homepage() {
+        let searcher = segmentation_index(&[
+            ("https://www.example.com", "Example", "Welcome to our site"),
+            (
+                "https://www.example.com/about",
+                "About us",
+                "We have been making examples since 1999",
+            ),
+            (
+                "https://www.other.com",
+                "Other",
+                "A review of example.com, the best example of example.com",
+            ),
+        ]);
+
+        let res = searcher
+            .search(&SearchQuery {
+                query: "example.com".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(res.webpages[0].url, "https://www.example.com/");
+        assert!(res
+            .webpages
+            .iter()
+            .any(|webpage| webpage.url == "https://www.example.com/about"));
+        assert_eq!(res.redirect.as_deref(), Some("https://example.com/"));
+
+        let res = searcher
+            .search(&SearchQuery {
+                query: "example.com reviews".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(res.redirect, None);
+
+        let res = searcher
+            .search(&SearchQuery {
+                query: "missing.com".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(res.redirect, None);
+    }
+
+    #[test]
+    fn search_fields() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for (host, title, body) in [
+            ("a", "Bicycle repair", "We fix every car in town"),
+            ("b", "Car repair", "We fix every bicycle in town"),
+        ] {
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        &format!(
+                            r#"
+            <html>
+                <head>
+                    <title>{title}</title>
+                </head>
+                <body>
+                    {body}
+                </body>
+            </html>
+            "#
+                        ),
+                        &format!("https://www.{host}.com"),
+                    )
+                    .unwrap(),
+                    fetch_time_ms: 500,
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index);
+
+        let search = |query: &str, fields: Option<&[&str]>| {
+            searcher.search(&SearchQuery {
+                query: query.to_string(),
+                search_fields: fields
+                    .map(|fields| fields.iter().map(|field| field.to_string()).collect()),
+                ..Default::default()
+            })
+        };
+        let urls = |query: &str, fields: Option<&[&str]>| -> Vec<String> {
+            let mut urls: Vec<_> = search(query, fields)
+                .unwrap()
+                .webpages
+                .into_iter()
+                .map(|webpage| webpage.url)
+                .collect();
+            urls.sort();
+            urls
+        };
+
+        assert_eq!(
+            urls("car", None),
+            vec!["https://www.a.com/", "https://www.b.com/"]
+        );
+        assert_eq!(urls("car", Some(&["title"])), vec!["https://www.b.com/"]);
+        assert_eq!(urls("car", Some(&["body"])), vec!["https://www.a.com/"]);
+        assert_eq!(
+            urls("\"every car\"", Some(&["title"])),
+            Vec::<String>::new()
+        );
+
+        // terms for a specific field are not restricted
+        assert_eq!(
+            urls("car inbody:bicycle", Some(&["title"])),
+            vec!["https://www.b.com/"]
+        );
+
+        assert!(search("car", Some(&["not_a_field"])).is_err());
+        assert!(search("car", Some(&[])).is_err());
+    }
+
+    #[test]
+    fn sort_by_title_with_locale() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for (host, title) in [
+            ("a", "Zèbre des plaines"),
+            ("b", "Été des animaux"),
+            ("c", "Eau des animaux"),
+            ("d", "Élan des forêts"),
+        ] {
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        &format!(
+                            r#"
+            <html>
+                <head>
+                    <title>{title}</title>
+                </head>
+                <body>
+                    Les animaux des plaines et des forêts
+                </body>
+            </html>
+            "#
+                        ),
+                        &format!("https://www.{host}.com"),
+                    )
+                    .unwrap(),
+                    fetch_time_ms: 500,
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index);
+
+        let titles = |order: SortOrder, locale: Option<&str>| -> Vec<String> {
+            searcher
+                .search(&SearchQuery {
+                    query: "animaux".to_string(),
+                    sort_page: Some(PageSort {
+                        field: SortField::Title,
+                        order,
+                        locale: locale.map(|locale| locale.to_string()),
+                    }),
+                    ..Default::default()
+                })
+                .unwrap()
+                .webpages
+                .into_iter()
+                .map(|webpage| webpage.title)
+                .collect()
+        };
+
+        assert_eq!(
+            titles(SortOrder::Ascending, Some("fr")),
+            vec![
+                "Eau des animaux",
+                "Élan des forêts",
+                "Été des animaux",
+                "Zèbre des plaines"
+            ]
+        );
+        assert_eq!(
+            titles(SortOrder::Descending, Some("fr")),
+            vec![
+                "Zèbre des plaines",
+                "Été des animaux",
+                "Élan des forêts",
+                "Eau des animaux"
+            ]
+        );
+
+        // without a locale the accented letters sort after `z`
+        assert_eq!(
+            titles(SortOrder::Ascending, None),
+            vec![
+                "Eau des animaux",
+                "Zèbre des plaines",
+                "Élan des forêts",
+                "Été des animaux"
+            ]
+        );
+    }
+
+    #[test]
+    fn japanese_words_without_spaces() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        let html = Html::parse(
+            r#"
+            <html>
+                <head>
+                    <title>東京都の天気予報</title>
+                </head>
+                <body>
+                    東京都の今日の天気は晴れのち曇りで、午後からは所により雨が降るでしょう。
+                    明日は朝から気温が上がり、日中は汗ばむような陽気になりそうです。
+                    お出かけの際は、熱中症に十分注意してください。
+                </body>
+            </html>
+            "#,
+            "https://www.example.jp",
+        )
+        .unwrap();
+
+        assert_eq!(html.lang(), Some(&Lang::Jpn));
+        assert_eq!(html.tokenizer_kind(), TokenizerKind::Cjk);
+
+        // the words are not separated by whitespace, so the title is a single word
+        // to the standard tokenizer
+        let mut tokenizer = Tokenizer::default();
+        let mut stream =
+            tantivy::tokenizer::Tokenizer::token_stream(&mut tokenizer, "東京都の天気予報");
+        let mut words = Vec::new();
+        while let Some(token) = stream.next() {
+            words.push(token.text.clone());
+        }
+        assert_eq!(words, vec!["東京都の天気予報".to_string()]);
+
+        index
+            .insert(&Webpage {
+                html,
+                fetch_time_ms: 500,
+                ..Default::default()
+            })
+            .expect("failed to insert webpage");
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index);
+        let urls = |query: &str| -> Vec<String> {
+            searcher
+                .search(&SearchQuery {
+                    query: query.to_string(),
+                    ..Default::default()
+                })
+                .unwrap()
+                .webpages
+                .into_iter()
+                .map(|webpage| webpage.url)
+                .collect()
+        };
+
+        assert_eq!(urls("天気"), vec!["https://www.example.jp/"]);
+        assert_eq!(urls("天気予報"), vec!["https://www.example.jp/"]);
+        assert!(urls("大阪").is_empty());
+
+        // the query is split by its script, even if its language is detected as another
+        let mut tokenizer = text_field::AllBody.query_tokenizer(Some(&Lang::Eng));
+        let mut stream = tantivy::tokenizer::Tokenizer::token_stream(&mut tokenizer, "東京都");
+        let mut words = Vec::new();
+        while let Some(token) = stream.next() {
+            words.push(token.text.clone());
+        }
+        assert_eq!(words, vec!["東京".to_string(), "京都".to_string()]);
+    }
+
+    #[test]
+    fn count_matches_results() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for i in 0..12 {
+            let (title, body) = match i % 3 {
+                0 => ("Car repair", "We fix every car in town"),
+                1 => ("Bicycle repair", "We fix every bicycle in town"),
+                _ => ("Bakery", "We bake bread every morning"),
+            };
+
+            index
+                .insert(&Webpage {
+                    html: Html::parse(
+                        &format!(
+                            r#"
+            <html>
+                <head>
+                    <title>{title}</title>
+                </head>
+                <body>
+                    {body}
+                </body>
+            </html>
+            "#
+                        ),
+                        &format!("https://www.{i}.com"),
+                    )
+                    .unwrap(),
+                    fetch_time_ms: 500,
+                    ..Default::default()
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index);
+
+        for (query, expected) in [
+            ("repair", 8),
+            ("every", 12),
+            ("\"every car\"", 4),
+            ("repair -bicycle", 4),
+            ("every site:www.3.com", 1),
+            ("intitle:bakery", 4),
+            ("submarine", 0),
+        ] {
+            let query = SearchQuery {
+                query: query.to_string(),
+                num_results: 100,
+                ..Default::default()
+            };
+
+            let count = searcher.count(&query).unwrap();
+            assert_eq!(count, expected, "{}", query.query);
+            assert_eq!(
+                count as usize,
+                searcher.search(&query).unwrap().webpages.len(),
+                "{}",
+                query.query
+            );
+        }
+    }
 }
@@ -18,24 +18,32 @@ pub mod api;
 pub mod distributed;
 pub mod live;
 pub mod local;
+pub mod sanitize;
+pub mod timings;
 
 pub use distributed::*;
 pub use local::*;
-use optics::{HostRankings, Optic};
+use optics::{Features, HostRankings, Optic};
 
 use utoipa::ToSchema;
 
-use crate::api::search::ReturnBody;
+use crate::api::search::{PageSort, ReturnBody, SafeSearch};
 
 use crate::{
     bangs::BangHit,
-    collector::approx_count::Count,
+    collector::{approx_count::Count, SearchCursor},
     config::defaults,
-    ranking::{pipeline::LocalRecallRankingWebpage, SignalCoefficient},
+    query::optic::OpticDebugSummary,
+    ranking::{
+        pipeline::{DegradedStage, LocalRecallRankingWebpage},
+        SignalCoefficient, TermProximity,
+    },
     search_prettifier::DisplayedWebpage,
     webpage::region::Region,
 };
 
+use self::timings::Timings;
+
 pub const NUM_RESULTS_PER_PAGE: usize = 20;
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
@@ -64,6 +72,21 @@ pub struct WebsitesResult {
     pub num_hits: Count,
     pub search_duration_ms: u128,
     pub has_more_results: bool,
+    pub optic_debug: Option<OpticDebugSummary>,
+    /// Some shards could not be reached, so the results only cover part of the index.
+    pub degraded: bool,
+    pub missing_shards: Vec<u64>,
+    /// Ranking stages that were skipped or scored fewer results to stay within the latency budget.
+    pub degraded_stages: Vec<DegradedStage>,
+    /// The query had too many terms, so only the first of them were searched for.
+    pub query_truncated: bool,
+    /// Where the time of the search was spent. Only set for queries with `debug_timings`.
+    pub timings: Option<Timings>,
+    /// The components of the results page that the optic of the query leaves on.
+    pub features: Features,
+    /// The url a query of only a domain names, if the host is in the index,
+    /// so the client can offer to go to the site directly.
+    pub redirect: Option<String>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Clone)]
@@ -75,12 +98,57 @@ pub struct SearchQuery {
     pub optic: Option<Optic>,
     pub host_rankings: Option<HostRankings>,
     pub return_ranking_signals: bool,
-    pub safe_search: bool,
+    pub safe_search: SafeSearch,
     pub count_results_exact: bool,
     pub return_body: Option<ReturnBody>,
     pub return_structured_data: bool,
+    pub optic_debug: bool,
+
+    /// Overrides the configured diversity tradeoff for this query.
+    pub diversity_lambda: Option<f64>,
+
+    /// Only return results from these hosts. No restriction if empty.
+    pub restrict_hosts: Vec<String>,
+
+    /// Collapse results that are the same page under different urls, e.g. with
+    /// and without a trailing slash, keeping the highest scoring of them.
+    pub dedup_urls: bool,
+
+    /// Overrides the configured latency budget of the ranking stages for this query.
+    pub latency_budget_ms: Option<u64>,
+
+    /// Hosts from the click history of the user to boost, see [`crate::ranking::pipeline::history`].
+    pub boosted_hosts: Vec<String>,
 
     pub signal_coefficients: SignalCoefficient,
+
+    /// Weight of the proximity of the terms of the query in the results, which overrides
+    /// the coefficient of [`crate::ranking::signal::TermProximity`]. Single term queries
+    /// are not affected.
+    pub proximity_weight: Option<f64>,
+
+    /// Record where the time of the search is spent, see [`timings`].
+    pub debug_timings: bool,
+
+    /// Page through the results of a local searcher with a cursor instead of `page`,
+    /// see [`crate::collector::SearchCursor`].
+    pub search_after: Option<SearchCursor>,
+
+    /// The id of the entity to show in the sidebar, which bypasses the disambiguation
+    /// of the entities matching the query.
+    pub pinned_entity: Option<String>,
+
+    /// Only match the plain terms and phrases of the query in the text fields with
+    /// these names. All the searchable fields are used if not set.
+    pub search_fields: Option<Vec<String>>,
+
+    /// Reorder the results of the page by a string field instead of their score.
+    /// The pages themselves are still chosen by score, see [`PageSort`].
+    pub sort_page: Option<PageSort>,
+
+    /// Search for the exact words of the query, like they were all quoted. The terms
+    /// are not stemmed, expanded with synonyms or spell corrected.
+    pub verbatim: bool,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
@@ -88,6 +156,12 @@ pub struct InitialWebsiteResult {
     pub num_websites: Count,
     pub websites: Vec<LocalRecallRankingWebpage>,
     pub has_more: bool,
+    /// The stages of the search on the shard if the query has `debug_timings` enabled.
+    pub timings: Option<Timings>,
+    /// The cursor of the next page if the query has a cursor and there are more results.
+    pub next_cursor: Option<SearchCursor>,
+    /// The url a query of only a domain names, if the host has a homepage in the shard.
+    pub redirect: Option<String>,
 }
 
 impl Default for SearchQuery {
@@ -107,7 +181,20 @@ impl Default for SearchQuery {
             count_results_exact: defaults::SearchQuery::count_results_exact(),
             return_body: None,
             return_structured_data: defaults::SearchQuery::return_structured_data(),
+            optic_debug: defaults::SearchQuery::optic_debug(),
+            diversity_lambda: None,
+            restrict_hosts: Vec::new(),
+            dedup_urls: defaults::SearchQuery::dedup_urls(),
+            latency_budget_ms: None,
+            boosted_hosts: Vec::new(),
             signal_coefficients: Default::default(),
+            proximity_weight: None,
+            debug_timings: defaults::SearchQuery::debug_timings(),
+            search_after: None,
+            pinned_entity: None,
+            search_fields: None,
+            sort_page: None,
+            verbatim: defaults::SearchQuery::verbatim(),
         }
     }
 }
@@ -118,20 +205,41 @@ impl SearchQuery {
     }
 
     pub fn signal_coefficients(&self) -> SignalCoefficient {
-        self.signal_coefficients.clone()
-    }
-
-    pub fn host_rankings(&self) -> HostRankings {
-        let mut rankings = HostRankings::empty();
+        let mut coefficients = self.signal_coefficients.clone();
 
-        if let Some(host_rankings) = &self.host_rankings {
-            rankings.merge_into(host_rankings.clone());
+        if let Some(weight) = self.proximity_weight {
+            coefficients.merge_overwrite(SignalCoefficient::new(std::iter::once((
+                TermProximity.into(),
+                weight,
+            ))));
         }
 
-        if let Some(optic) = &self.optic {
-            rankings.merge_into(optic.host_rankings.clone());
+        coefficients
+    }
+
+    /// The components of the results page to compute for the query. They are all
+    /// enabled unless the optic turns some of them off.
+    pub fn features(&self) -> Features {
+        self.optic
+            .as_ref()
+            .map(|optic| optic.features)
+            .unwrap_or_default()
+    }
+
+    /// Summarises which rules of the optic did not match any of the returned webpages.
+    /// Returns `None` unless the query has `optic_debug` enabled.
+    pub fn optic_debug_summary(&self, webpages: &[DisplayedWebpage]) -> Option<OpticDebugSummary> {
+        if !self.optic_debug {
+            return None;
         }
 
-        rankings
+        self.optic.as_ref().map(|optic| {
+            OpticDebugSummary::new(
+                optic,
+                webpages
+                    .iter()
+                    .map(|webpage| webpage.optic_rule_matches.as_deref().unwrap_or_default()),
+            )
+        })
     }
 }
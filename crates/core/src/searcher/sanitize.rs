@@ -0,0 +1,204 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Sanitation of the query text before it is parsed.
+//!
+//! Whitespace is collapsed, queries without any searchable characters or that are too long
+//! are rejected, and only the first [`MAX_TERMS_PER_QUERY`] terms are kept. When a query has
+//! more terms than that, its stopwords are dropped first so the kept terms are the ones that
+//! matter, unless the query is nothing but stopwords, in which case they are all the query has.
+
+use crate::query::parser::MAX_TERMS_PER_QUERY;
+use crate::stopwords;
+
+use super::distributed::Error;
+
+/// Longer queries are rejected before they are parsed. Real queries are far shorter,
+/// so the limit only stops pasted documents from reaching the parser and the index.
+pub const MAX_QUERY_BYTES: usize = 2048;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SanitizedQuery {
+    pub query: String,
+    /// Some terms of the query were dropped.
+    pub truncated: bool,
+}
+
+fn is_punctuation(term: &str) -> bool {
+    term.chars().all(|c| {
+        c.is_ascii_punctuation()
+            || matches!(
+                c,
+                '¡' | '¿' | '«' | '»' | '“' | '”' | '„' | '‘' | '’' | '…' | '–' | '—'
+            )
+    })
+}
+
+/// The terms that are plain words, i.e. not part of a phrase, a bang or a field selector.
+fn plain_words(terms: &[&str]) -> Vec<bool> {
+    let mut in_phrase = false;
+
+    terms
+        .iter()
+        .map(|term| {
+            let starts_in_phrase = in_phrase;
+            in_phrase ^= term.matches('"').count() % 2 == 1;
+
+            !starts_in_phrase
+                && !term.contains('"')
+                && !term.contains(':')
+                && !term.starts_with('!')
+                && !term.starts_with('-')
+        })
+        .collect()
+}
+
+fn without_stopwords<'a>(query: &str, terms: &[&'a str]) -> Vec<&'a str> {
+    let lang = whatlang::detect(query)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang())
+        .unwrap_or(whatlang::Lang::Eng);
+
+    let stopwords = match stopwords::get(&lang) {
+        Some(stopwords) => stopwords,
+        None => return terms.to_vec(),
+    };
+
+    terms
+        .iter()
+        .zip(plain_words(terms))
+        .filter(|(term, plain)| !plain || !stopwords.contains(&term.to_lowercase()))
+        .map(|(term, _)| *term)
+        .collect()
+}
+
+pub fn sanitize(query: &str) -> Result<SanitizedQuery, Error> {
+    if query.len() > MAX_QUERY_BYTES {
+        return Err(Error::QueryTooLong {
+            len: query.len(),
+            max: MAX_QUERY_BYTES,
+        });
+    }
+
+    let mut terms: Vec<_> = query.split_whitespace().collect();
+
+    if terms.iter().all(|term| is_punctuation(term)) {
+        return Err(Error::EmptyQuery);
+    }
+
+    let mut truncated = false;
+
+    if terms.len() > MAX_TERMS_PER_QUERY {
+        let informative = without_stopwords(query, &terms);
+
+        if !informative.is_empty() {
+            terms = informative;
+        }
+
+        terms.truncate(MAX_TERMS_PER_QUERY);
+        truncated = true;
+    }
+
+    Ok(SanitizedQuery {
+        query: terms.join(" "),
+        truncated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitized(query: &str) -> String {
+        sanitize(query).unwrap().query
+    }
+
+    #[test]
+    fn collapses_whitespace() {
+        assert_eq!(
+            sanitized("  best \t example\n\nwebsite  "),
+            "best example website"
+        );
+        assert!(!sanitize("best example").unwrap().truncated);
+    }
+
+    #[test]
+    fn empty_queries() {
+        for query in ["", "   ", "\t\n", "?", "... !! ?", "¿¡ —"] {
+            assert!(
+                matches!(sanitize(query), Err(Error::EmptyQuery)),
+                "{query:?}"
+            );
+        }
+
+        assert_eq!(sanitized("c++"), "c++");
+        assert_eq!(sanitized("? stract"), "? stract");
+    }
+
+    #[test]
+    fn oversized_queries() {
+        let query = "a ".repeat(MAX_QUERY_BYTES);
+
+        assert!(matches!(
+            sanitize(&query),
+            Err(Error::QueryTooLong {
+                len,
+                max: MAX_QUERY_BYTES
+            }) if len == query.len()
+        ));
+
+        let query = "a".repeat(MAX_QUERY_BYTES);
+        assert_eq!(sanitized(&query), query);
+    }
+
+    #[test]
+    fn caps_number_of_terms() {
+        let words: Vec<_> = (0..MAX_TERMS_PER_QUERY + 10)
+            .map(|i| format!("word{i}"))
+            .collect();
+
+        let res = sanitize(&words.join(" ")).unwrap();
+        assert!(res.truncated);
+        assert_eq!(res.query, words[..MAX_TERMS_PER_QUERY].join(" "));
+
+        let res = sanitize(&words[..MAX_TERMS_PER_QUERY].join(" ")).unwrap();
+        assert!(!res.truncated);
+    }
+
+    #[test]
+    fn stopwords_dropped_before_cap() {
+        let mut words = vec!["the"; MAX_TERMS_PER_QUERY];
+        words.push("rust");
+        words.push("\"the");
+        words.push("book\"");
+
+        let res = sanitize(&words.join(" ")).unwrap();
+        assert!(res.truncated);
+        assert_eq!(res.query, "rust \"the book\"");
+    }
+
+    #[test]
+    fn stopword_only_queries_keep_stopwords() {
+        assert_eq!(sanitized("the of and"), "the of and");
+
+        let query = ["to be or not to be"; 10].join(" ");
+        let res = sanitize(&query).unwrap();
+
+        assert!(res.truncated);
+        assert_eq!(res.query.split_whitespace().count(), MAX_TERMS_PER_QUERY);
+        assert!(res.query.starts_with("to be or not to be"));
+    }
+}
@@ -0,0 +1,174 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Latency breakdown of a search for queries with `debug_timings` enabled.
+//!
+//! The stages of a search are recorded by a [`Recorder`]. A disabled recorder never reads
+//! the clock, so queries without `debug_timings` don't pay for the timings.
+
+use std::time::{Duration, Instant};
+
+use utoipa::ToSchema;
+
+/// The time spent in a stage of the search and the stages it consists of.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct Timings {
+    pub name: String,
+    pub duration_ms: f64,
+    pub children: Vec<Timings>,
+}
+
+impl Timings {
+    pub fn new(name: impl Into<String>, duration: Duration) -> Self {
+        Self {
+            name: name.into(),
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_children(mut self, children: Vec<Timings>) -> Self {
+        self.children = children;
+        self
+    }
+
+    /// The first stage with the name, searched depth first and including this stage.
+    pub fn find(&self, name: &str) -> Option<&Timings> {
+        if self.name == name {
+            return Some(self);
+        }
+
+        self.children.iter().find_map(|child| child.find(name))
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Timings> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+/// Records the stages of a search in the order they finish.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    stages: Option<Vec<Timings>>,
+}
+
+impl Recorder {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            stages: enabled.then(Vec::new),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(false)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.stages.is_some()
+    }
+
+    /// The start of a stage, or `None` if the recorder is disabled.
+    pub fn start(&self) -> Option<Instant> {
+        self.stages.as_ref().map(|_| Instant::now())
+    }
+
+    /// Record the stage that began at `start`.
+    pub fn record(&mut self, name: &str, start: Option<Instant>) {
+        self.record_with(name, start, Vec::new());
+    }
+
+    pub fn record_with(&mut self, name: &str, start: Option<Instant>, children: Vec<Timings>) {
+        if let (Some(stages), Some(start)) = (self.stages.as_mut(), start) {
+            stages.push(Timings::new(name, start.elapsed()).with_children(children));
+        }
+    }
+
+    /// Record a stage that was timed elsewhere, e.g. by a search server.
+    pub fn push(&mut self, timings: Timings) {
+        if let Some(stages) = self.stages.as_mut() {
+            stages.push(timings);
+        }
+    }
+
+    /// Run `f` as a stage.
+    pub fn time<R>(&mut self, name: &str, f: impl FnOnce() -> R) -> R {
+        let start = self.start();
+        let res = f();
+        self.record(name, start);
+
+        res
+    }
+
+    /// The recorded stages. Empty if the recorder is disabled.
+    pub fn into_stages(self) -> Vec<Timings> {
+        self.stages.unwrap_or_default()
+    }
+
+    /// The recorded stages as the children of a stage that began at `start`.
+    pub fn finish(self, name: &str, start: Option<Instant>) -> Option<Timings> {
+        let start = start?;
+        let stages = self.stages?;
+
+        Some(Timings::new(name, start.elapsed()).with_children(stages))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_recorder_records_nothing() {
+        let mut recorder = Recorder::disabled();
+
+        assert!(recorder.start().is_none());
+        assert_eq!(recorder.time("stage", || 1 + 1), 2);
+        recorder.push(Timings::new("remote", Duration::from_millis(1)));
+
+        assert!(recorder.finish("search", None).is_none());
+    }
+
+    #[test]
+    fn nested_stages() {
+        let mut recorder = Recorder::new(true);
+        let start = recorder.start();
+
+        recorder.time("first", || std::thread::sleep(Duration::from_millis(2)));
+
+        let mut inner = Recorder::new(true);
+        let inner_start = inner.start();
+        inner.time("inner", || ());
+        recorder.record_with("second", inner_start, inner.into_stages());
+
+        let timings = recorder.finish("search", start).unwrap();
+
+        assert_eq!(timings.name, "search");
+        assert!(timings.child("first").unwrap().duration_ms >= 2.0);
+        assert!(timings.child("inner").is_none());
+        assert!(timings.find("inner").is_some());
+        assert!(timings.children.iter().map(|c| c.duration_ms).sum::<f64>() <= timings.duration_ms);
+    }
+}
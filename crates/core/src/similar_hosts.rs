@@ -47,7 +47,14 @@ impl SimilarHostsFinder {
     }
 
     async fn scorer(&self, liked: &[NodeID]) -> inbound_similarity::Scorer {
-        inbound_similarity::Scorer::new(&self.webgraph, liked, &[], true).await
+        inbound_similarity::Scorer::new(
+            &self.webgraph,
+            liked,
+            &[],
+            true,
+            inbound_similarity::DEFAULT_CACHE_CAPACITY,
+        )
+        .await
     }
 
     pub async fn find_similar_hosts(&self, nodes: &[String], limit: usize) -> Vec<ScoredNode> {
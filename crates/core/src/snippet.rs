@@ -73,13 +73,116 @@ impl TextSnippet {
     }
 }
 
+/// A phrase from the query. The words of a phrase are highlighted as a single fragment
+/// spanning the tightest window where they occur in order with at most `slop` other
+/// words between them, the same way the phrase is matched against the term positions
+/// in the index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phrase {
+    pub terms: Vec<String>,
+    pub slop: u32,
+}
+
+impl Phrase {
+    pub fn new(terms: Vec<String>) -> Self {
+        Self { terms, slop: 0 }
+    }
+
+    pub fn with_slop(mut self, slop: u32) -> Self {
+        self.slop = slop;
+        self
+    }
+}
+
+struct PositionedToken {
+    text: String,
+    position: usize,
+    offset: Range<usize>,
+}
+
+fn tokenize_with_positions(text: &str, tokenizer: &mut Tokenizer) -> Vec<PositionedToken> {
+    let mut tokens = Vec::new();
+    let mut stream = tantivy::tokenizer::Tokenizer::token_stream(tokenizer, text);
+
+    while let Some(tok) = stream.next() {
+        tokens.push(PositionedToken {
+            text: tok.text.clone(),
+            position: tok.position,
+            offset: tok.offset_from..tok.offset_to,
+        });
+    }
+
+    tokens
+}
+
+/// The non-overlapping windows of `tokens` that match the phrase. Where several
+/// matches overlap, the one spanning the fewest positions is kept.
+fn phrase_windows(tokens: &[PositionedToken], phrase: &[String], slop: u32) -> Vec<Range<usize>> {
+    if phrase.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+
+    for (start, first) in tokens.iter().enumerate() {
+        if first.text != phrase[0] {
+            continue;
+        }
+
+        let mut last = start;
+        let mut found = true;
+
+        for (i, term) in phrase.iter().enumerate().skip(1) {
+            // the first occurrence of the term after the previous one gives the tightest window.
+            let next = tokens[last + 1..]
+                .iter()
+                .position(|tok| tok.text == *term && tok.position > tokens[last].position)
+                .map(|idx| idx + last + 1);
+
+            match next {
+                Some(next) if tokens[next].position - first.position - i <= slop as usize => {
+                    last = next;
+                }
+                _ => {
+                    found = false;
+                    break;
+                }
+            }
+        }
+
+        if found {
+            matches.push((
+                tokens[last].position - first.position,
+                first.offset.start..tokens[last].offset.end,
+            ));
+        }
+    }
+
+    matches.sort_by(|(a_span, a), (b_span, b)| a_span.cmp(b_span).then(a.start.cmp(&b.start)));
+
+    let mut windows: Vec<Range<usize>> = Vec::new();
+
+    for (_, window) in matches {
+        if windows
+            .iter()
+            .all(|other| window.end <= other.start || other.end <= window.start)
+        {
+            windows.push(window);
+        }
+    }
+
+    windows.sort_by_key(|window| window.start);
+
+    windows
+}
+
 struct SnippetBuilder {
     fragment: String,
     highlights: Vec<Range<usize>>,
 }
 
 impl SnippetBuilder {
-    fn highlight(&mut self, terms: &HashSet<String>, lang: whatlang::Lang) {
+    fn highlight(&mut self, terms: &HashSet<String>, phrases: &[Phrase], lang: whatlang::Lang) {
         for mut tokenizer in [
             Tokenizer::Stemmed(Stemmed::with_forced_language(lang)),
             Tokenizer::Normal(Normal::default()),
@@ -95,6 +198,47 @@ impl SnippetBuilder {
             }
         }
 
+        let mut windows = Vec::new();
+
+        for mut tokenizer in [
+            Tokenizer::Stemmed(Stemmed::with_forced_language(lang)),
+            Tokenizer::Normal(Normal::default()),
+        ] {
+            let tokens = tokenize_with_positions(&self.fragment, &mut tokenizer);
+
+            for phrase in phrases {
+                let terms: Vec<String> = phrase
+                    .terms
+                    .iter()
+                    .flat_map(|term| {
+                        tokenize_with_positions(term, &mut tokenizer)
+                            .into_iter()
+                            .map(|tok| tok.text)
+                    })
+                    .collect();
+
+                windows.extend(phrase_windows(&tokens, &terms, phrase.slop));
+            }
+        }
+
+        // the phrase windows found by the different tokenizers are merged,
+        // and replace the highlights of their individual terms.
+        windows.sort_by_key(|window| window.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for window in windows {
+            match merged.last_mut() {
+                Some(last) if window.start < last.end => last.end = last.end.max(window.end),
+                _ => merged.push(window),
+            }
+        }
+
+        self.highlights.retain(|range| {
+            !merged
+                .iter()
+                .any(|window| window.start < range.end && range.start < window.end)
+        });
+        self.highlights.extend(merged);
+
         // remove overlapping ranges
         self.highlights
             .sort_by(|a, b| a.start.cmp(&b.start).then(a.end.cmp(&b.end)));
@@ -214,6 +358,7 @@ fn score_passages_with_bm25(passages: &mut [PassageCandidate], terms: &HashSet<S
 fn snippet_string_builder(
     text: &str,
     terms: &[String],
+    phrases: &[Phrase],
     lang: whatlang::Lang,
     config: SnippetConfig,
     mut tokenizer: Tokenizer,
@@ -240,7 +385,7 @@ fn snippet_string_builder(
             highlights: Vec::new(),
         };
 
-        snippet.highlight(&terms, lang);
+        snippet.highlight(&terms, phrases, lang);
 
         return snippet;
     }
@@ -277,7 +422,7 @@ fn snippet_string_builder(
             snippet.trim_to_chars(config.desired_num_chars + config.delta_num_chars);
         }
     }
-    snippet.highlight(&terms, lang);
+    snippet.highlight(&terms, phrases, lang);
 
     snippet
 }
@@ -285,11 +430,13 @@ fn snippet_string_builder(
 fn snippet_string(
     text: &str,
     terms: &[String],
+    phrases: &[Phrase],
     lang: whatlang::Lang,
     config: SnippetConfig,
 ) -> TextSnippet {
     let tokenizer = Tokenizer::Normal(Normal::default());
-    let snip = snippet_string_builder(text, terms, lang, config.clone(), tokenizer).build();
+    let snip =
+        snippet_string_builder(text, terms, phrases, lang, config.clone(), tokenizer).build();
 
     if !snip.fragments.is_empty()
         && snip
@@ -301,7 +448,7 @@ fn snippet_string(
     }
 
     let tokenizer = Tokenizer::Stemmed(Stemmed::with_forced_language(lang));
-    snippet_string_builder(text, terms, lang, config, tokenizer).build()
+    snippet_string_builder(text, terms, phrases, lang, config, tokenizer).build()
 }
 
 pub fn generate(query: &Query, text: &str, region: &Region, config: SnippetConfig) -> TextSnippet {
@@ -328,12 +475,18 @@ pub fn generate(query: &Query, text: &str, region: &Region, config: SnippetConfi
         };
     }
 
+    let phrases: Vec<_> = query
+        .phrases()
+        .iter()
+        .map(|terms| Phrase::new(terms.clone()))
+        .collect();
+
     match config.max_considered_words {
         Some(num_words) => {
             let text = text.split_whitespace().take(num_words).join(" ");
-            snippet_string(&text, query.simple_terms(), lang, config)
+            snippet_string(&text, query.simple_terms(), &phrases, lang, config)
         }
-        None => snippet_string(text, query.simple_terms(), lang, config),
+        None => snippet_string(text, query.simple_terms(), &phrases, lang, config),
     }
 }
 
@@ -415,6 +568,55 @@ Survey in 2016, 2017, and 2018."#;
         assert_eq!(highlight(result.webpages[0].snippet.clone()), format!("{HIGHLIGHTEN_PREFIX}Rust{HIGHLIGHTEN_POSTFIX} is a systems programming {HIGHLIGHTEN_PREFIX}language{HIGHLIGHTEN_POSTFIX} sponsored by Mozilla which describes it as a \"safe, concurrent, practical {HIGHLIGHTEN_PREFIX}language{HIGHLIGHTEN_POSTFIX}\", supporting functional and imperative-procedural paradigms. {HIGHLIGHTEN_PREFIX}Rust{HIGHLIGHTEN_POSTFIX} is syntactically similar to C++[according to whom?], but its designers intend it to provide better memory safety while still maintaining "));
     }
 
+    #[test]
+    fn snippet_from_main_content() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(
+                &Webpage::test_parse(
+                    r#"
+                    <html>
+                        <head>
+                            <title>Website for rustaceans</title>
+                        </head>
+                        <body>
+                            <nav><a href="/">Rust language news</a> <a href="/about">About the Rust language</a></nav>
+                            <div class="cookie-consent">
+                                <p>We at the Rust language website use cookies, and similar technologies, to measure how the Rust language pages are used.</p>
+                            </div>
+                            <article>
+                                <p>Rust is a systems programming language, sponsored by Mozilla, which describes it as a safe, concurrent and practical language.</p>
+                                <p>Rust is syntactically similar to C++, but its designers intend it to provide better memory safety while maintaining performance.</p>
+                            </article>
+                            <footer><p>Copyright, the Rust language website, all rights reserved, and the Rust language foundation.</p></footer>
+                        </body>
+                    </html>
+                    "#,
+                    "https://www.example.com",
+                )
+                .unwrap(),
+            )
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        let searcher = LocalSearcher::from(index);
+
+        let result = searcher
+            .search(&SearchQuery {
+                query: "rust language".to_string(),
+                ..Default::default()
+            })
+            .expect("Search failed");
+
+        assert_eq!(result.webpages.len(), 1);
+
+        let snippet = highlight(result.webpages[0].snippet.clone());
+        assert!(snippet.contains("systems programming"));
+        assert!(!snippet.contains("cookies"));
+        assert!(!snippet.contains("Copyright"));
+    }
+
     #[test]
     fn stemmed_words_snippet_highlight() {
         let mut index = Index::temporary().expect("Unable to open index");
@@ -504,6 +706,7 @@ Survey in 2016, 2017, and 2018."#;
                 text: snippet_string(
                     "this is a test",
                     &[],
+                    &[],
                     whatlang::Lang::Eng,
                     SnippetConfig::default()
                 )
@@ -521,6 +724,7 @@ Survey in 2016, 2017, and 2018."#;
                 text: snippet_string(
                     "",
                     &["test".to_string()],
+                    &[],
                     whatlang::Lang::Eng,
                     SnippetConfig::default()
                 )
@@ -532,7 +736,7 @@ Survey in 2016, 2017, and 2018."#;
         assert_eq!(
             highlight(Snippet {
                 date: None,
-                text: snippet_string("", &[], whatlang::Lang::Eng, SnippetConfig::default())
+                text: snippet_string("", &[], &[], whatlang::Lang::Eng, SnippetConfig::default())
             })
             .as_str(),
             ""
@@ -544,6 +748,7 @@ Survey in 2016, 2017, and 2018."#;
         let snip = snippet_string_builder(
             "this is a test",
             &["thisis".to_string()],
+            &[],
             whatlang::Lang::Eng,
             SnippetConfig::default(),
             Tokenizer::Normal(Normal::default()),
@@ -561,4 +766,89 @@ Survey in 2016, 2017, and 2018."#;
             "<b>this is</b> a test"
         );
     }
+
+    #[test]
+    fn phrase_highlight_is_contiguous() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(
+                &Webpage::test_parse(
+                    &format!(
+                        r#"
+                        <html>
+                            <head>
+                                <title>Website for runners</title>
+                            </head>
+                            <body>
+                                {TEST_TEXT}
+                            </body>
+                        </html>
+                    "#
+                    ),
+                    "https://www.example.com",
+                )
+                .unwrap(),
+            )
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        let searcher = LocalSearcher::from(index);
+
+        let result = searcher
+            .search(&SearchQuery {
+                query: "\"systems programming language\"".to_string(),
+                ..Default::default()
+            })
+            .expect("Search failed");
+
+        assert_eq!(result.webpages.len(), 1);
+
+        let highlighted = highlight(result.webpages[0].snippet.clone());
+        assert!(highlighted.contains(&format!(
+            "{HIGHLIGHTEN_PREFIX}systems programming language{HIGHLIGHTEN_POSTFIX}"
+        )));
+
+        // terms of the phrase outside the phrase are still highlighted on their own.
+        assert!(highlighted.contains(&format!(
+            "practical {HIGHLIGHTEN_PREFIX}language{HIGHLIGHTEN_POSTFIX}"
+        )));
+    }
+
+    #[test]
+    fn slop_phrase_tightest_window() {
+        let phrase = Phrase::new(vec!["rust".to_string(), "language".to_string()]);
+
+        let snip = |text: &str, phrase: &Phrase| {
+            highlight(Snippet {
+                date: None,
+                text: snippet_string(
+                    text,
+                    &[],
+                    &[phrase.clone()],
+                    whatlang::Lang::Eng,
+                    SnippetConfig::default(),
+                ),
+            })
+        };
+
+        assert_eq!(
+            snip("rust and rust is a language", &phrase.clone().with_slop(4)),
+            "rust and <b>rust is a language</b>"
+        );
+
+        assert_eq!(
+            snip("rust is a language", &phrase.clone().with_slop(1)),
+            "rust is a language"
+        );
+        assert_eq!(
+            snip("rust is a language", &phrase.clone().with_slop(2)),
+            "<b>rust is a language</b>"
+        );
+
+        assert_eq!(
+            snip("rust language and a rust language", &phrase),
+            "<b>rust language</b> and a <b>rust language</b>"
+        );
+    }
 }
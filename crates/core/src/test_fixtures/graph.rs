@@ -0,0 +1,218 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    executor::Executor,
+    gen_temp_path,
+    webgraph::{
+        centrality::{harmonic::HarmonicCentrality, store_harmonic},
+        Compression, Node, NodeID, Webgraph, WebgraphWriter,
+    },
+    webpage::html::links::RelFlags,
+};
+
+/// Builder of a webgraph where every ordered pair of nodes is linked
+/// with the probability of the link density.
+pub struct GraphFixture {
+    seed: u64,
+    hosts: usize,
+    pages_per_host: usize,
+    link_density: f64,
+    segments: usize,
+    edges: Vec<(Node, Node, String)>,
+    rels: HashMap<(NodeID, NodeID), RelFlags>,
+}
+
+impl GraphFixture {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            hosts: 0,
+            pages_per_host: 1,
+            link_density: 0.1,
+            segments: 1,
+            edges: Vec::new(),
+            rels: HashMap::new(),
+        }
+    }
+
+    /// The hosts are named by [`super::host`].
+    pub fn hosts(mut self, hosts: usize) -> Self {
+        self.hosts = hosts;
+        self
+    }
+
+    /// The nodes are the hosts if there is a single page per host,
+    /// and otherwise the pages `https://host0.com/0`, `https://host0.com/1` and so on.
+    pub fn pages_per_host(mut self, pages_per_host: usize) -> Self {
+        self.pages_per_host = pages_per_host.max(1);
+        self
+    }
+
+    pub fn link_density(mut self, link_density: f64) -> Self {
+        self.link_density = link_density.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Spread the edges over this many segments.
+    pub fn segments(mut self, segments: usize) -> Self {
+        self.segments = segments.max(1);
+        self
+    }
+
+    /// Add these edges to the generated ones, for tests that need a specific shape.
+    pub fn edges<I>(mut self, edges: I) -> Self
+    where
+        I: IntoIterator<Item = (Node, Node, String)>,
+    {
+        self.edges.extend(edges);
+        self
+    }
+
+    /// Set the rel flags of the edges from `from` to `to`. The edges have no flags otherwise.
+    pub fn rel(mut self, from: &Node, to: &Node, rel: RelFlags) -> Self {
+        self.rels.insert((from.id(), to.id()), rel);
+        self
+    }
+
+    fn nodes(&self) -> Vec<Node> {
+        (0..self.hosts)
+            .flat_map(|host| {
+                let host = super::host(host);
+
+                (0..self.pages_per_host).map(move |page| {
+                    if self.pages_per_host == 1 {
+                        Node::from(host.as_str())
+                    } else {
+                        Node::from(format!("https://{host}/{page}"))
+                    }
+                })
+            })
+            .collect()
+    }
+
+    pub fn build(self) -> FixtureGraph {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut nodes = self.nodes();
+
+        let mut edges = Vec::new();
+        for from in &nodes {
+            for to in &nodes {
+                if from != to && rng.gen_bool(self.link_density) {
+                    edges.push((from.clone(), to.clone(), String::new()));
+                }
+            }
+        }
+
+        for (from, to, _) in &self.edges {
+            for node in [from, to] {
+                if !nodes.contains(node) {
+                    nodes.push(node.clone());
+                }
+            }
+        }
+        edges.extend(self.edges);
+
+        let mut segments: Vec<_> = (0..self.segments)
+            .map(|_| {
+                WebgraphWriter::new(
+                    gen_temp_path(),
+                    Executor::single_thread(),
+                    Compression::default(),
+                    None,
+                )
+            })
+            .collect();
+
+        for (i, (from, to, label)) in edges.iter().enumerate() {
+            segments[i % self.segments].insert(
+                from.clone(),
+                to.clone(),
+                label.clone(),
+                self.rels
+                    .get(&(from.id(), to.id()))
+                    .copied()
+                    .unwrap_or_default(),
+            );
+        }
+
+        let mut segments = segments.into_iter().map(|mut writer| {
+            writer.commit();
+            writer.finalize()
+        });
+
+        let mut graph = segments.next().unwrap();
+        for segment in segments {
+            graph.merge(segment).unwrap();
+        }
+        graph.refresh_centrality_quantiles();
+
+        let centrality = HarmonicCentrality::calculate(&graph);
+        let centrality = store_harmonic(
+            centrality
+                .iter()
+                .map(|(node, centrality)| (*node, centrality)),
+            gen_temp_path(),
+        );
+
+        FixtureGraph {
+            graph,
+            centrality,
+            nodes,
+            edges,
+        }
+    }
+}
+
+pub struct FixtureGraph {
+    graph: Webgraph,
+    centrality: speedy_kv::Db<NodeID, f64>,
+    nodes: Vec<Node>,
+    edges: Vec<(Node, Node, String)>,
+}
+
+impl FixtureGraph {
+    pub fn graph(&self) -> &Webgraph {
+        &self.graph
+    }
+
+    pub fn into_graph(self) -> Webgraph {
+        self.graph
+    }
+
+    /// The harmonic centralities of the nodes, stored like
+    /// the output of [`crate::entrypoint::Centrality::build_harmonic`].
+    pub fn centrality_store(&self) -> &speedy_kv::Db<NodeID, f64> {
+        &self.centrality
+    }
+
+    pub fn centrality(&self, node: &Node) -> f64 {
+        self.centrality.get(&node.id()).unwrap().unwrap_or_default()
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// The edges in the order they were generated, followed by the added edges.
+    pub fn edges(&self) -> &[(Node, Node, String)] {
+        &self.edges
+    }
+}
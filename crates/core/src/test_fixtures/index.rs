@@ -0,0 +1,364 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
+use whatlang::Lang;
+
+use crate::{
+    index::Index,
+    webgraph::Node,
+    webpage::{Html, Webpage},
+};
+
+use super::FixtureGraph;
+
+// common words of the languages, most frequent first, so the language
+// of the generated text is detected as the language it was generated for.
+const ENGLISH: &[&str] = &[
+    "the", "of", "and", "to", "in", "is", "that", "for", "it", "with", "as", "was", "on", "be",
+    "at", "by", "this", "have", "from", "or", "one", "had", "not", "but", "what", "all", "were",
+    "when", "we", "there", "can", "your", "which", "their", "said", "if", "do", "will", "each",
+    "about", "how", "up", "out", "them", "then", "she", "many", "some", "so", "these", "would",
+    "other", "into", "has", "more", "her", "two", "like", "him", "see", "time", "could", "no",
+    "make", "than", "first", "been", "its", "who", "now", "people", "my", "made", "over", "did",
+    "down", "only", "way", "find", "use", "may", "water", "long", "little", "very", "after",
+    "words", "called", "just", "where", "most", "know",
+];
+
+const GERMAN: &[&str] = &[
+    "der", "die", "und", "in", "den", "von", "zu", "das", "mit", "sich", "des", "auf", "für",
+    "ist", "im", "dem", "nicht", "ein", "eine", "als", "auch", "es", "an", "werden", "aus", "er",
+    "hat", "dass", "sie", "nach", "wird", "bei", "einer", "um", "am", "sind", "noch", "wie",
+    "einem", "über", "einen", "so", "zum", "war", "haben", "nur", "oder", "aber", "vor", "zur",
+    "bis", "mehr", "durch", "man", "sein", "wurde", "sei", "hatte", "kann", "gegen", "vom",
+    "können", "schon", "wenn", "habe", "seine", "ihre", "dann", "unter", "wir", "soll", "ich",
+    "eines", "jahr", "zwei", "jahren", "diese", "dieser", "wieder", "keine", "seiner", "worden",
+    "zwischen", "immer", "sagte",
+];
+
+const FRENCH: &[&str] = &[
+    "de",
+    "la",
+    "le",
+    "et",
+    "les",
+    "des",
+    "en",
+    "un",
+    "du",
+    "une",
+    "que",
+    "est",
+    "pour",
+    "qui",
+    "dans",
+    "par",
+    "plus",
+    "pas",
+    "au",
+    "sur",
+    "ne",
+    "se",
+    "ce",
+    "il",
+    "sont",
+    "aux",
+    "avec",
+    "son",
+    "ou",
+    "mais",
+    "comme",
+    "nous",
+    "vous",
+    "leur",
+    "elle",
+    "ils",
+    "été",
+    "cette",
+    "sa",
+    "ses",
+    "fait",
+    "tout",
+    "bien",
+    "aussi",
+    "entre",
+    "deux",
+    "même",
+    "sans",
+    "peut",
+    "ces",
+    "après",
+    "très",
+    "faire",
+    "encore",
+    "autres",
+    "depuis",
+    "dont",
+    "nos",
+    "avoir",
+    "temps",
+    "sous",
+    "où",
+    "ans",
+    "première",
+    "donc",
+    "jamais",
+    "toujours",
+    "monde",
+    "chose",
+    "homme",
+    "jour",
+    "beaucoup",
+    "quelque",
+    "pourquoi",
+];
+
+const SPANISH: &[&str] = &[
+    "de", "la", "que", "el", "en", "y", "a", "los", "se", "del", "las", "un", "por", "con", "no",
+    "una", "su", "para", "es", "al", "lo", "como", "más", "pero", "sus", "le", "ya", "o", "este",
+    "sí", "porque", "esta", "entre", "cuando", "muy", "sin", "sobre", "también", "me", "hasta",
+    "hay", "donde", "quien", "desde", "todo", "nos", "durante", "todos", "uno", "les", "ni",
+    "contra", "otros", "ese", "eso", "ante", "ellos", "esto", "antes", "algunos", "qué", "unos",
+    "yo", "otro", "otras", "otra", "él", "tanto", "esa", "estos", "mucho", "quienes", "nada",
+    "muchos", "cual", "poco", "ella", "estar", "estas", "algunas", "algo", "nosotros",
+];
+
+fn vocabulary(lang: Lang) -> &'static [&'static str] {
+    match lang {
+        Lang::Eng => ENGLISH,
+        Lang::Deu => GERMAN,
+        Lang::Fra => FRENCH,
+        Lang::Spa => SPANISH,
+        _ => panic!("the index fixture has no vocabulary for {lang:?}"),
+    }
+}
+
+/// Builder of an index of generated documents. The words of a document are drawn
+/// from the vocabulary of its language with a zipfian distribution.
+pub struct IndexFixture {
+    seed: u64,
+    documents: usize,
+    hosts: usize,
+    languages: Vec<Lang>,
+    words_per_document: usize,
+    zipf_exponent: f64,
+    terms: Vec<(String, f64)>,
+    segments: usize,
+    centralities: Vec<(Node, f64)>,
+}
+
+impl IndexFixture {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            documents: 10,
+            hosts: 1,
+            languages: vec![Lang::Eng],
+            words_per_document: 200,
+            zipf_exponent: 1.0,
+            terms: Vec::new(),
+            segments: 1,
+            centralities: Vec::new(),
+        }
+    }
+
+    pub fn documents(mut self, documents: usize) -> Self {
+        self.documents = documents;
+        self
+    }
+
+    /// The documents are spread over this many hosts, named by [`super::host`].
+    pub fn hosts(mut self, hosts: usize) -> Self {
+        self.hosts = hosts.max(1);
+        self
+    }
+
+    /// Each document is in one of these languages. Only english, german,
+    /// french and spanish have a vocabulary.
+    pub fn languages(mut self, languages: &[Lang]) -> Self {
+        assert!(!languages.is_empty());
+
+        for lang in languages {
+            vocabulary(*lang);
+        }
+
+        self.languages = languages.to_vec();
+        self
+    }
+
+    pub fn words_per_document(mut self, words_per_document: usize) -> Self {
+        self.words_per_document = words_per_document;
+        self
+    }
+
+    /// The skew of the word frequencies, where `0.0` draws every word of
+    /// the vocabulary equally often.
+    pub fn zipf_exponent(mut self, zipf_exponent: f64) -> Self {
+        self.zipf_exponent = zipf_exponent;
+        self
+    }
+
+    /// Add the term to this fraction of the documents, in addition to
+    /// the words of the vocabulary.
+    pub fn term(mut self, term: &str, document_frequency: f64) -> Self {
+        self.terms
+            .push((term.to_string(), document_frequency.clamp(0.0, 1.0)));
+        self
+    }
+
+    /// Commit the documents in this many segments.
+    pub fn segments(mut self, segments: usize) -> Self {
+        self.segments = segments.max(1);
+        self
+    }
+
+    /// Use the centralities of the graph for the hosts and pages of the documents.
+    pub fn graph(mut self, graph: &FixtureGraph) -> Self {
+        self.centralities = graph
+            .nodes()
+            .iter()
+            .map(|node| (node.clone(), graph.centrality(node)))
+            .collect();
+        self
+    }
+
+    fn centrality(&self, node: &Node) -> f64 {
+        self.centralities
+            .iter()
+            .find(|(n, _)| n == node)
+            .map(|(_, centrality)| *centrality)
+            .unwrap_or_default()
+    }
+
+    fn generate(&self) -> Vec<FixtureDocument> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        (0..self.documents)
+            .map(|i| {
+                let lang = self.languages[rng.gen_range(0..self.languages.len())];
+                let vocabulary = vocabulary(lang);
+                let distribution = WeightedIndex::new(
+                    (0..vocabulary.len())
+                        .map(|rank| 1.0 / ((rank + 1) as f64).powf(self.zipf_exponent)),
+                )
+                .unwrap();
+
+                let mut words: Vec<String> = (0..self.words_per_document)
+                    .map(|_| vocabulary[distribution.sample(&mut rng)].to_string())
+                    .collect();
+
+                let mut terms = Vec::new();
+                for (term, document_frequency) in &self.terms {
+                    if rng.gen_bool(*document_frequency) {
+                        let pos = rng.gen_range(0..=words.len());
+                        words.insert(pos, term.clone());
+                        terms.push(term.clone());
+                    }
+                }
+
+                let host = super::host(i % self.hosts);
+
+                FixtureDocument {
+                    url: format!("https://{host}/{}", i / self.hosts),
+                    title: words.iter().take(4).cloned().collect::<Vec<_>>().join(" "),
+                    body: words.join(" "),
+                    host,
+                    lang,
+                    terms,
+                }
+            })
+            .collect()
+    }
+
+    pub fn build(self) -> FixtureIndex {
+        let documents = self.generate();
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        let chunk_size = documents.len().div_ceil(self.segments).max(1);
+
+        for chunk in documents.chunks(chunk_size) {
+            for document in chunk {
+                let host = Node::from(document.host.as_str());
+
+                index
+                    .insert(&Webpage {
+                        html: Html::parse(
+                            &format!(
+                                r#"
+                        <html>
+                            <head>
+                                <title>{}</title>
+                            </head>
+                            <body>
+                                <p>{}</p>
+                            </body>
+                        </html>
+                    "#,
+                                document.title, document.body
+                            ),
+                            &document.url,
+                        )
+                        .unwrap(),
+                        host_centrality: self.centrality(&host),
+                        page_centrality: self.centrality(&Node::from(document.url.as_str())),
+                        fetch_time_ms: 500,
+                        node_id: Some(host.id()),
+                        ..Default::default()
+                    })
+                    .expect("failed to insert webpage");
+            }
+
+            index.commit().unwrap();
+        }
+
+        FixtureIndex { index, documents }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureDocument {
+    pub url: String,
+    pub host: String,
+    pub lang: Lang,
+    pub title: String,
+    pub body: String,
+    /// The terms added by [`IndexFixture::term`] that are in the document.
+    pub terms: Vec<String>,
+}
+
+pub struct FixtureIndex {
+    index: Index,
+    documents: Vec<FixtureDocument>,
+}
+
+impl FixtureIndex {
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    pub fn into_index(self) -> Index {
+        self.index
+    }
+
+    /// The documents in the order they were inserted.
+    pub fn documents(&self) -> &[FixtureDocument] {
+        &self.documents
+    }
+}
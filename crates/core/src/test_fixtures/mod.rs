@@ -0,0 +1,151 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Deterministic fixtures for the tests.
+//!
+//! [`GraphFixture`] builds a webgraph with the matching harmonic centralities and
+//! [`IndexFixture`] builds an index of generated documents. Everything that is generated
+//! is derived from the seed of the fixture, so two fixtures with the same seed and
+//! settings have the same nodes, edges, documents and therefore the same scores. Only
+//! the ids of the segments differ between runs.
+//!
+//! ```ignore
+//! let graph = GraphFixture::new(1).hosts(50).link_density(0.1).segments(3).build();
+//! let index = IndexFixture::new(1)
+//!     .documents(200)
+//!     .term("example", 0.3)
+//!     .graph(&graph)
+//!     .segments(2)
+//!     .build();
+//! ```
+
+mod graph;
+mod index;
+
+pub use graph::{FixtureGraph, GraphFixture};
+pub use index::{FixtureDocument, FixtureIndex, IndexFixture};
+
+/// The name of the `i`th host of the fixtures.
+pub fn host(i: usize) -> String {
+    format!("host{i}.com")
+}
+
+#[cfg(test)]
+mod tests {
+    use whatlang::Lang;
+
+    use crate::searcher::{LocalSearcher, SearchQuery};
+
+    use super::*;
+
+    #[test]
+    fn graphs_are_deterministic() {
+        let build = |seed| {
+            GraphFixture::new(seed)
+                .hosts(20)
+                .pages_per_host(2)
+                .link_density(0.05)
+                .segments(3)
+                .build()
+        };
+
+        let a = build(7);
+        let b = build(7);
+
+        assert!(!a.edges().is_empty());
+        assert_eq!(a.edges(), b.edges());
+        assert_eq!(a.graph().stats().num_segments, 3);
+
+        let centralities = |graph: &FixtureGraph| -> Vec<_> {
+            graph
+                .nodes()
+                .iter()
+                .map(|node| (node.id(), graph.centrality(node)))
+                .collect()
+        };
+
+        assert_eq!(centralities(&a), centralities(&b));
+        assert!(centralities(&a)
+            .iter()
+            .any(|(_, centrality)| *centrality > 0.0));
+
+        assert_ne!(build(8).edges(), a.edges());
+    }
+
+    #[test]
+    fn indexes_are_deterministic() {
+        let graph = GraphFixture::new(3).hosts(5).link_density(0.3).build();
+
+        let build = || {
+            IndexFixture::new(3)
+                .documents(40)
+                .hosts(5)
+                .languages(&[Lang::Eng, Lang::Fra])
+                .term("example", 0.5)
+                .graph(&graph)
+                .segments(2)
+                .build()
+        };
+
+        let a = build();
+        let b = build();
+
+        assert_eq!(a.documents(), b.documents());
+        assert_eq!(a.index().inverted_index.num_segments(), 2);
+
+        let with_term = a
+            .documents()
+            .iter()
+            .filter(|document| document.terms.iter().any(|term| term == "example"))
+            .count();
+        assert!(with_term > 0 && with_term < 40);
+
+        // the order of the segments differ between the indexes, so results
+        // with the same score can be returned in a different order
+        let results = |index: FixtureIndex| -> (Vec<String>, Vec<f64>) {
+            let searcher = LocalSearcher::new(index.into_index());
+            let query = SearchQuery {
+                query: "example".to_string(),
+                num_results: 100,
+                ..Default::default()
+            };
+
+            let mut urls: Vec<_> = searcher
+                .search(&query)
+                .unwrap()
+                .webpages
+                .into_iter()
+                .map(|webpage| webpage.url)
+                .collect();
+            urls.sort();
+
+            let mut scores: Vec<_> = searcher
+                .search_initial(&query, true)
+                .unwrap()
+                .websites
+                .iter()
+                .map(|website| website.score())
+                .collect();
+            scores.sort_by(f64::total_cmp);
+
+            (urls, scores)
+        };
+
+        let a = results(a);
+        assert_eq!(a.0.len(), with_term);
+        assert_eq!(a, results(b));
+    }
+}
@@ -55,6 +55,42 @@ impl From<Lang> for MyStemmer {
     }
 }
 
+/// How the text of a document is split into words.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenizerKind {
+    /// Words are separated by whitespace and punctuation.
+    #[default]
+    Standard,
+    /// Like [`TokenizerKind::Standard`], but runs of Chinese and Japanese characters
+    /// are also split into bigrams. See [`CjkBigrams`].
+    Cjk,
+}
+
+impl TokenizerKind {
+    /// The languages that are tokenized with [`TokenizerKind::Cjk`] by default.
+    pub const CJK_LANGS: [Lang; 2] = [Lang::Cmn, Lang::Jpn];
+
+    /// The default tokenizer of text in the language.
+    pub fn for_lang(lang: Option<&Lang>) -> Self {
+        match lang {
+            Some(lang) if Self::CJK_LANGS.contains(lang) => Self::Cjk,
+            _ => Self::Standard,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum Tokenizer {
     Normal(Normal),
@@ -78,6 +114,14 @@ impl Tokenizer {
             Tokenizer::Url(_) => UrlTokenizer::as_str(),
         }
     }
+
+    /// Split the words of the text as the kind says. Only the [`Normal`] tokenizer is affected.
+    pub fn with_kind(self, kind: TokenizerKind) -> Self {
+        match self {
+            Tokenizer::Normal(normal) => Tokenizer::Normal(normal.with_kind(kind)),
+            tokenizer => tokenizer,
+        }
+    }
 }
 
 impl From<Stemmed> for Tokenizer {
@@ -95,6 +139,7 @@ impl Default for Tokenizer {
 #[derive(Clone, Default)]
 pub struct Normal {
     stopwords: Option<Vec<String>>,
+    kind: TokenizerKind,
     analyzer: Option<TextAnalyzer>,
 }
 
@@ -106,7 +151,16 @@ impl Normal {
     pub fn with_stopwords(stopwords: Vec<String>) -> Self {
         Self {
             stopwords: Some(stopwords),
+            kind: TokenizerKind::default(),
+            analyzer: None,
+        }
+    }
+
+    pub fn with_kind(self, kind: TokenizerKind) -> Self {
+        Self {
+            kind,
             analyzer: None,
+            ..self
         }
     }
 }
@@ -192,21 +246,43 @@ impl tantivy::tokenizer::Tokenizer for Tokenizer {
     }
 }
 
+fn normal_analyzer<T>(tokenizer: T, stopwords: Option<&Vec<String>>) -> TextAnalyzer
+where
+    T: tantivy::tokenizer::Tokenizer,
+{
+    let builder = TextAnalyzer::builder(tokenizer).filter(LowerCaser);
+
+    if let Some(stopwords) = stopwords {
+        builder
+            .filter(StopWordFilter::remove(stopwords.clone()))
+            .build()
+    } else {
+        builder.build()
+    }
+}
+
+fn stemmed_analyzer<T>(tokenizer: T, lang: Option<Lang>) -> TextAnalyzer
+where
+    T: tantivy::tokenizer::Tokenizer,
+{
+    let builder = TextAnalyzer::builder(tokenizer).filter(LowerCaser);
+
+    match lang {
+        Some(lang) => builder.filter(MyStemmer::from(lang).0).build(),
+        None => builder.build(),
+    }
+}
+
 impl tantivy::tokenizer::Tokenizer for Normal {
     type TokenStream<'a> = BoxTokenStream<'a>;
 
     fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
-        let builder = TextAnalyzer::builder(Simple).filter(LowerCaser);
-
-        self.analyzer = if let Some(stopwords) = &self.stopwords {
-            Some(
-                builder
-                    .filter(StopWordFilter::remove(stopwords.clone()))
-                    .build(),
-            )
-        } else {
-            Some(builder.build())
-        };
+        let stopwords = self.stopwords.as_ref();
+
+        self.analyzer = Some(match self.kind {
+            TokenizerKind::Standard => normal_analyzer(Simple, stopwords),
+            TokenizerKind::Cjk => normal_analyzer(CjkBigrams, stopwords),
+        });
 
         self.analyzer.as_mut().unwrap().token_stream(text)
     }
@@ -216,17 +292,15 @@ impl tantivy::tokenizer::Tokenizer for Stemmed {
     type TokenStream<'a> = BoxTokenStream<'a>;
 
     fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
-        let builder = TextAnalyzer::builder(Simple).filter(LowerCaser);
-
         let lang = match self.force_language {
             Some(lang) => Some(lang),
             None => whatlang::detect_lang(text),
         };
 
-        self.analyzer = match lang {
-            Some(lang) => Some(builder.filter(MyStemmer::from(lang).0).build()),
-            None => Some(builder.build()),
-        };
+        self.analyzer = Some(match TokenizerKind::for_lang(lang.as_ref()) {
+            TokenizerKind::Standard => stemmed_analyzer(Simple, lang),
+            TokenizerKind::Cjk => stemmed_analyzer(CjkBigrams, lang),
+        });
 
         self.analyzer.as_mut().unwrap().token_stream(text)
     }
@@ -357,6 +431,123 @@ impl<'a> tantivy::tokenizer::TokenStream for SimpleTokenStream<'a> {
     }
 }
 
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // hiragana and katakana
+        | '\u{31f0}'..='\u{31ff}' // katakana phonetic extensions
+        | '\u{3400}'..='\u{4dbf}' // cjk unified ideographs extension a
+        | '\u{4e00}'..='\u{9fff}' // cjk unified ideographs
+        | '\u{f900}'..='\u{faff}' // cjk compatibility ideographs
+        | '\u{ff66}'..='\u{ff9f}' // halfwidth katakana
+        | '\u{20000}'..='\u{2fa1f}' // the supplementary ideographic planes
+    )
+}
+
+/// Chinese and Japanese don't separate the words by whitespace, so a sentence becomes a single
+/// token in the [`Simple`] tokenizer. This tokenizer splits the runs of Chinese and Japanese
+/// characters into overlapping bigrams instead (`東京都` becomes `東京` and `京都`), which lets
+/// the words inside the runs be matched without a dictionary of the words of the languages.
+/// Queries with more than two characters match the consecutive bigrams as a phrase.
+/// Text in other scripts is tokenized like in [`Simple`].
+#[derive(Clone)]
+pub struct CjkBigrams;
+
+pub struct CjkBigramsTokenStream<'a> {
+    inner: BoxTokenStream<'a>,
+    pending: VecDeque<tantivy::tokenizer::Token>,
+    token: tantivy::tokenizer::Token,
+    next_position: usize,
+}
+
+impl tantivy::tokenizer::Tokenizer for CjkBigrams {
+    type TokenStream<'a> = BoxTokenStream<'a>;
+
+    fn token_stream<'a>(&mut self, text: &'a str) -> Self::TokenStream<'a> {
+        BoxTokenStream::new(CjkBigramsTokenStream {
+            inner: tantivy::tokenizer::Tokenizer::token_stream(&mut Simple, text),
+            pending: VecDeque::new(),
+            token: tantivy::tokenizer::Token::default(),
+            next_position: 0,
+        })
+    }
+}
+
+fn split_cjk(token: &tantivy::tokenizer::Token, out: &mut VecDeque<tantivy::tokenizer::Token>) {
+    let text = &token.text;
+    let mut push = |start: usize, end: usize| {
+        out.push_back(tantivy::tokenizer::Token {
+            offset_from: token.offset_from + start,
+            offset_to: token.offset_from + end,
+            text: text[start..end].to_string(),
+            ..Default::default()
+        })
+    };
+
+    // (is_cjk, start, end) of the runs of characters in the same kind of script
+    let mut runs: Vec<(bool, usize, usize)> = Vec::new();
+    for (i, c) in text.char_indices() {
+        let cjk = is_cjk(c);
+        let end = i + c.len_utf8();
+
+        match runs.last_mut() {
+            Some((run_cjk, _, run_end)) if *run_cjk == cjk => *run_end = end,
+            _ => runs.push((cjk, i, end)),
+        }
+    }
+
+    for (cjk, start, end) in runs {
+        if !cjk {
+            push(start, end);
+            continue;
+        }
+
+        let boundaries: Vec<_> = text[start..end]
+            .char_indices()
+            .map(|(i, _)| start + i)
+            .chain([end])
+            .collect();
+
+        if boundaries.len() < 3 {
+            push(start, end);
+        } else {
+            for window in boundaries.windows(3) {
+                push(window[0], window[2]);
+            }
+        }
+    }
+}
+
+impl<'a> tantivy::tokenizer::TokenStream for CjkBigramsTokenStream<'a> {
+    fn advance(&mut self) -> bool {
+        while self.pending.is_empty() {
+            if !self.inner.advance() {
+                return false;
+            }
+
+            let token = self.inner.token();
+            if token.text.chars().any(is_cjk) {
+                split_cjk(token, &mut self.pending);
+            } else {
+                self.pending.push_back(token.clone());
+            }
+        }
+
+        self.token = self.pending.pop_front().unwrap();
+        self.token.position = self.next_position;
+        self.next_position += 1;
+
+        true
+    }
+
+    fn token(&self) -> &tantivy::tokenizer::Token {
+        &self.token
+    }
+
+    fn token_mut(&mut self) -> &mut tantivy::tokenizer::Token {
+        &mut self.token
+    }
+}
+
 pub struct NGramTokenStream<'a, const N: usize> {
     inner: BoxTokenStream<'a>,
     token: tantivy::tokenizer::Token,
@@ -803,6 +994,18 @@ mod tests {
         res
     }
 
+    fn tokenize_cjk(s: &str) -> Vec<String> {
+        let mut res = Vec::new();
+        let mut tokenizer = Normal::default().with_kind(TokenizerKind::Cjk);
+        let mut stream = tokenizer.token_stream(s);
+
+        while let Some(token) = stream.next() {
+            res.push(token.text.clone());
+        }
+
+        res
+    }
+
     fn tokenize_json(s: &str) -> Vec<String> {
         let mut res = Vec::new();
         let mut tokenizer = JsonField;
@@ -1087,6 +1290,31 @@ key1.key2="this\" is @ a # test""#;
         );
     }
 
+    #[test]
+    fn cjk_bigrams() {
+        assert_eq!(
+            tokenize_cjk("東京都の天気"),
+            vec!["東京", "京都", "都の", "の天", "天気"]
+        );
+        assert_eq!(
+            tokenize_cjk("Visit 東京 in Tokyo東京都"),
+            vec!["visit", "東京", "in", "tokyo", "東京", "京都"]
+        );
+        assert_eq!(tokenize_cjk("test 漢.com"), vec!["test", "漢", ".", "com"]);
+        assert_eq!(
+            tokenize_cjk("this is a test"),
+            tokenize_simple("this is a test")
+        );
+
+        let mut tokenizer = Normal::default().with_kind(TokenizerKind::Cjk);
+        let mut stream = tokenizer.token_stream("a 東京都");
+        let mut tokens = Vec::new();
+        while let Some(token) = stream.next() {
+            tokens.push((token.position, token.offset_from, token.offset_to));
+        }
+        assert_eq!(tokens, vec![(0, 0, 1), (1, 2, 8), (2, 5, 11)]);
+    }
+
     #[test]
     fn cyrillic() {
         assert_eq!(tokenize_simple("test б.com"), vec!["test", "б", ".", "com"]);
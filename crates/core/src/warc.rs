@@ -176,22 +176,9 @@ impl WarcFile {
         config: &S3Config,
         buf: &mut W,
     ) -> Result<()> {
-        let bucket = s3::Bucket::new(
-            &config.bucket,
-            s3::Region::Custom {
-                region: "".to_string(),
-                endpoint: config.endpoint.clone(),
-            },
-            s3::creds::Credentials {
-                access_key: Some(config.access_key.clone()),
-                secret_key: Some(config.secret_key.clone()),
-                security_token: None,
-                session_token: None,
-                expiration: None,
-            },
-        )?
-        .with_path_style()
-        .with_request_timeout(Duration::from_secs(30 * 60))?;
+        let bucket = config
+            .bucket()?
+            .with_request_timeout(Duration::from_secs(30 * 60))?;
 
         let res = bucket.get_object_blocking(warc_path)?;
 
@@ -302,12 +289,43 @@ impl Response {
 pub struct Metadata {
     // fetchTimeMs
     pub fetch_time_ms: u64,
+    // redirectedFrom
+    /// The url that was requested if the server redirected to the url of the record.
+    #[cfg_attr(
+        test,
+        proptest(strategy = "proptest::option::of(\"https://[a-z]{1,16}\\\\.com/[a-z]{0,16}\")")
+    )]
+    pub redirected_from: Option<String>,
+    // lastModified
+    /// Seconds since the unix epoch of the `Last-Modified` header of the response.
+    pub last_modified: Option<u64>,
+    // sitemapLastmod
+    /// Seconds since the unix epoch of the `<lastmod>` of the url in the sitemap of the site.
+    pub sitemap_lastmod: Option<u64>,
+    // robotsVersion
+    /// The id of the [`crate::crawler::robots_audit::RobotsVersion`] that allowed the
+    /// requested url to be fetched.
+    pub robots_version: Option<u32>,
+    // robotsRule
+    /// The index of the rule of the robots.txt that matched the requested url.
+    pub robots_rule: Option<u32>,
+    // fetchedAt
+    /// Seconds since the unix epoch of when the page was fetched.
+    pub fetched_at: Option<u64>,
 }
 
 impl Metadata {
     fn from_raw(record: RawWarcRecord) -> Result<Self> {
         let r = BufReader::new(&record.content[..]);
 
+        let mut fetch_time_ms = None;
+        let mut redirected_from = None;
+        let mut last_modified = None;
+        let mut sitemap_lastmod = None;
+        let mut robots_version = None;
+        let mut robots_rule = None;
+        let mut fetched_at = None;
+
         for line in r.lines() {
             let mut line = line?;
             if let Some(semi) = line.find(':') {
@@ -315,13 +333,35 @@ impl Metadata {
                 line.pop(); // remove colon
                 let key = line;
                 if key == "fetchTimeMs" {
-                    let fetch_time_ms = value.parse::<u64>()?;
-                    return Ok(Self { fetch_time_ms });
+                    fetch_time_ms = Some(value.parse::<u64>()?);
+                } else if key == "redirectedFrom" {
+                    redirected_from = Some(value);
+                } else if key == "lastModified" {
+                    last_modified = value.parse::<u64>().ok();
+                } else if key == "sitemapLastmod" {
+                    sitemap_lastmod = value.parse::<u64>().ok();
+                } else if key == "robotsVersion" {
+                    robots_version = value.parse::<u32>().ok();
+                } else if key == "robotsRule" {
+                    robots_rule = value.parse::<u32>().ok();
+                } else if key == "fetchedAt" {
+                    fetched_at = value.parse::<u64>().ok();
                 }
             }
         }
 
-        Err(Error::WarcParse("Failed to parse metadata".to_string()).into())
+        match fetch_time_ms {
+            Some(fetch_time_ms) => Ok(Self {
+                fetch_time_ms,
+                redirected_from,
+                last_modified,
+                sitemap_lastmod,
+                robots_version,
+                robots_rule,
+                fetched_at,
+            }),
+            None => Err(Error::WarcParse("Failed to parse metadata".to_string()).into()),
+        }
     }
 }
 
@@ -631,7 +671,32 @@ impl WarcWriter {
         self.writer
             .write_all("WARC-Type: metadata\r\n".as_bytes())?;
 
-        let body = format!("fetchTimeMs: {}", record.metadata.fetch_time_ms);
+        let mut body = format!("fetchTimeMs: {}", record.metadata.fetch_time_ms);
+
+        if let Some(redirected_from) = &record.metadata.redirected_from {
+            body.push_str(&format!("\r\nredirectedFrom: {redirected_from}"));
+        }
+
+        if let Some(last_modified) = record.metadata.last_modified {
+            body.push_str(&format!("\r\nlastModified: {last_modified}"));
+        }
+
+        if let Some(sitemap_lastmod) = record.metadata.sitemap_lastmod {
+            body.push_str(&format!("\r\nsitemapLastmod: {sitemap_lastmod}"));
+        }
+
+        if let Some(robots_version) = record.metadata.robots_version {
+            body.push_str(&format!("\r\nrobotsVersion: {robots_version}"));
+        }
+
+        if let Some(robots_rule) = record.metadata.robots_rule {
+            body.push_str(&format!("\r\nrobotsRule: {robots_rule}"));
+        }
+
+        if let Some(fetched_at) = record.metadata.fetched_at {
+            body.push_str(&format!("\r\nfetchedAt: {fetched_at}"));
+        }
+
         let content_len = body.len();
 
         self.writer
@@ -751,6 +816,12 @@ mod tests {
             },
             metadata: Metadata {
                 fetch_time_ms: 1337,
+                redirected_from: None,
+                last_modified: None,
+                sitemap_lastmod: None,
+                robots_version: None,
+                robots_rule: None,
+                fetched_at: None,
             },
         };
         writer.write(&record1).unwrap();
@@ -765,6 +836,12 @@ mod tests {
             },
             metadata: Metadata {
                 fetch_time_ms: 4242,
+                redirected_from: Some("https://c.com/".to_string()),
+                last_modified: Some(1_445_412_480),
+                sitemap_lastmod: Some(1_445_000_000),
+                robots_version: Some(3_000_000_000),
+                robots_rule: Some(2),
+                fetched_at: Some(1_445_412_600),
             },
         };
         writer.write(&record2).unwrap();
@@ -784,6 +861,16 @@ mod tests {
         assert_eq!(&records[1].request.url, "https://b.com");
         assert_eq!(&records[1].response.body, "body of b");
         assert_eq!(records[1].metadata.fetch_time_ms, 4242);
+        assert_eq!(
+            records[1].metadata.redirected_from.as_deref(),
+            Some("https://c.com/")
+        );
+        assert_eq!(records[0].metadata.redirected_from, None);
+
+        assert_eq!(records[0].metadata.last_modified, None);
+        assert_eq!(records[0].metadata.sitemap_lastmod, None);
+        assert_eq!(records[1].metadata.last_modified, Some(1_445_412_480));
+        assert_eq!(records[1].metadata.sitemap_lastmod, Some(1_445_000_000));
     }
 
     #[test]
@@ -799,7 +886,15 @@ mod tests {
                 body: utf8.to_string(),
                 payload_type: Some(PayloadType::Html),
             },
-            metadata: Metadata { fetch_time_ms: 0 },
+            metadata: Metadata {
+                fetch_time_ms: 0,
+                redirected_from: None,
+                last_modified: None,
+                sitemap_lastmod: None,
+                robots_version: None,
+                robots_rule: None,
+                fetched_at: None,
+            },
         };
         writer.write(&record).unwrap();
 
@@ -831,7 +926,15 @@ mod tests {
                 body: body.to_string(),
                 payload_type: Some(PayloadType::Html),
             },
-            metadata: Metadata { fetch_time_ms: 0 },
+            metadata: Metadata {
+                fetch_time_ms: 0,
+                redirected_from: None,
+                last_modified: None,
+                sitemap_lastmod: None,
+                robots_version: None,
+                robots_rule: None,
+                fetched_at: None,
+            },
         };
         writer.write(&record).unwrap();
 
@@ -0,0 +1,208 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Per thread pools of the buffers used to collect the edges of a query.
+//!
+//! Every edge query loads the edges of each segment into a buffer and collects them
+//! into a single buffer before they are deduplicated, sorted and limited. Under load these
+//! buffers would otherwise be allocated and freed for every query, so they are returned to
+//! a pool owned by the thread that ran the query and reused by its next query.
+
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+};
+
+use crate::config::EdgeBufferPoolConfig;
+
+use super::SegmentEdge;
+
+pub struct BufferPool<T> {
+    buffers: RefCell<Vec<Vec<T>>>,
+}
+
+impl<T> Default for BufferPool<T> {
+    fn default() -> Self {
+        Self {
+            buffers: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl<T> BufferPool<T> {
+    fn take(&self) -> Vec<T> {
+        self.buffers.borrow_mut().pop().unwrap_or_default()
+    }
+
+    fn put(&self, mut buf: Vec<T>, config: &EdgeBufferPoolConfig) {
+        // very large buffers are not kept around after the query
+        // that needed them, so a single outlier does not pin the memory.
+        if buf.capacity() > config.max_buffer_len {
+            return;
+        }
+
+        let mut buffers = self.buffers.borrow_mut();
+        if buffers.len() < config.max_buffers {
+            buf.clear();
+            buffers.push(buf);
+        }
+    }
+
+    /// The memory of the buffers in the pool.
+    #[cfg(test)]
+    fn memory(&self) -> Vec<(*const T, usize)> {
+        let mut memory: Vec<_> = self
+            .buffers
+            .borrow()
+            .iter()
+            .map(|buf| (buf.as_ptr(), buf.capacity()))
+            .collect();
+        memory.sort();
+        memory
+    }
+}
+
+thread_local! {
+    static EDGES: BufferPool<SegmentEdge<()>> = BufferPool::default();
+    static LABELLED_EDGES: BufferPool<SegmentEdge<String>> = BufferPool::default();
+}
+
+/// Types that have a buffer pool for each thread.
+pub trait Pooled: Sized + 'static {
+    fn with_pool<R>(f: impl FnOnce(&BufferPool<Self>) -> R) -> R;
+}
+
+impl Pooled for SegmentEdge<()> {
+    fn with_pool<R>(f: impl FnOnce(&BufferPool<Self>) -> R) -> R {
+        EDGES.with(f)
+    }
+}
+
+impl Pooled for SegmentEdge<String> {
+    fn with_pool<R>(f: impl FnOnce(&BufferPool<Self>) -> R) -> R {
+        LABELLED_EDGES.with(f)
+    }
+}
+
+/// An empty buffer borrowed from the pool of the current thread.
+/// The buffer is returned to the pool when dropped.
+pub struct PooledBuffer<T: Pooled> {
+    buf: Vec<T>,
+    config: EdgeBufferPoolConfig,
+}
+
+impl<T: Pooled> PooledBuffer<T> {
+    pub fn new(config: EdgeBufferPoolConfig) -> Self {
+        let buf = if config.max_buffers > 0 {
+            T::with_pool(|pool| pool.take())
+        } else {
+            Vec::new()
+        };
+
+        Self { buf, config }
+    }
+}
+
+impl<T: Pooled> Deref for PooledBuffer<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl<T: Pooled> DerefMut for PooledBuffer<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl<T: Pooled> Drop for PooledBuffer<T> {
+    fn drop(&mut self) {
+        if self.config.max_buffers > 0 {
+            let buf = std::mem::take(&mut self.buf);
+            T::with_pool(|pool| pool.put(buf, &self.config));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::webgraph::{tests::test_graph, EdgeLimit, Node};
+
+    use super::*;
+
+    fn memory() -> Vec<(*const SegmentEdge<()>, usize)> {
+        SegmentEdge::<()>::with_pool(|pool| pool.memory())
+    }
+
+    #[test]
+    fn reuse_across_queries() {
+        let graph = test_graph();
+        let node = Node::from("C").id();
+
+        let expected = graph.raw_ingoing_edges(&node, EdgeLimit::Unlimited);
+        assert_eq!(expected.len(), 3);
+
+        // the buffers of the first query hold the edges, so they have been allocated
+        let warm = memory();
+        assert!(warm.iter().map(|(_, capacity)| capacity).sum::<usize>() >= 2 * expected.len());
+
+        for _ in 0..100 {
+            assert_eq!(
+                graph.raw_ingoing_edges(&node, EdgeLimit::Unlimited),
+                expected
+            );
+        }
+
+        // the later queries ran in the same memory without allocating new buffers
+        assert_eq!(memory(), warm);
+    }
+
+    #[test]
+    fn disabled() {
+        let config = EdgeBufferPoolConfig {
+            max_buffers: 0,
+            ..Default::default()
+        };
+        let before = memory();
+
+        for _ in 0..10 {
+            let mut buf: PooledBuffer<SegmentEdge<()>> = PooledBuffer::new(config);
+            buf.reserve(10);
+        }
+
+        assert_eq!(memory(), before);
+    }
+
+    #[test]
+    fn large_buffers_are_freed() {
+        let config = EdgeBufferPoolConfig {
+            max_buffers: 2,
+            max_buffer_len: 16,
+        };
+
+        let mut buf: PooledBuffer<SegmentEdge<()>> = PooledBuffer::new(config);
+        buf.reserve(1024);
+        let large = buf.as_ptr();
+        drop(buf);
+
+        assert!(memory().iter().all(|(ptr, _)| *ptr != large));
+
+        let buf: PooledBuffer<SegmentEdge<()>> = PooledBuffer::new(config);
+        assert_eq!(buf.capacity(), 0);
+    }
+}
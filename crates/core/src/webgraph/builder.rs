@@ -16,13 +16,15 @@
 
 use std::path::Path;
 
-use crate::executor::Executor;
+use crate::{config::EdgeBufferPoolConfig, executor::Executor};
 
 use super::Webgraph;
 
 pub struct WebgraphBuilder {
     path: Box<Path>,
     executor: Executor,
+    edge_buffer_pool: EdgeBufferPoolConfig,
+    strict_id2node: bool,
 }
 
 impl WebgraphBuilder {
@@ -30,6 +32,8 @@ impl WebgraphBuilder {
         Self {
             path: path.as_ref().into(),
             executor: Executor::multi_thread("webgraph").unwrap(),
+            edge_buffer_pool: EdgeBufferPoolConfig::default(),
+            strict_id2node: false,
         }
     }
 
@@ -38,7 +42,21 @@ impl WebgraphBuilder {
         self
     }
 
+    pub fn edge_buffer_pool(mut self, config: EdgeBufferPoolConfig) -> Self {
+        self.edge_buffer_pool = config;
+        self
+    }
+
+    /// Search every segment of the id2node db when a node is looked up,
+    /// so names that collide in the hash are found.
+    pub fn strict_id2node(mut self, strict: bool) -> Self {
+        self.strict_id2node = strict;
+        self
+    }
+
     pub fn open(self) -> Webgraph {
-        Webgraph::open(self.path, self.executor)
+        let mut graph = Webgraph::open(self.path, self.executor, self.edge_buffer_pool);
+        graph.id2node.set_strict(self.strict_id2node);
+        graph
     }
 }
@@ -0,0 +1,358 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Export of a centrality store as an [Arrow IPC file](https://arrow.apache.org/docs/format/Columnar.html#ipc-file-format)
+//! or a [Parquet file](https://parquet.apache.org/docs/file-format/).
+//!
+//! Both files have the columns
+//!
+//! | column    | type      |                                                       |
+//! |-----------|-----------|-------------------------------------------------------|
+//! | `node_id` | `uint64`  | the id of the node in the webgraph                    |
+//! | `host`    | `utf8`    | the name of the node, empty if it is not in the graph |
+//! | `score`   | `float64` | the centrality of the node                            |
+//!
+//! and its rows are ordered by descending score, with nodes of the same score ordered by
+//! their id. The rows are written in record batches of [`BATCH_SIZE`] rows, so the file can
+//! be memory mapped and read a batch at a time by e.g. `pyarrow.ipc.open_file` or Spark
+//! without loading all of it. [`CentralityReader`] reads the file back in Rust.
+//!
+//! The Parquet file has a row group per [`PARQUET_ROW_GROUP_SIZE`] rows, compressed with
+//! snappy, for tools like pandas, DuckDB or Spark that read Parquet rather than Arrow IPC.
+
+use std::{
+    io::{Read, Seek, Write},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail};
+use arrow_array::{
+    builder::{Float64Builder, StringBuilder, UInt64Builder},
+    cast::AsArray,
+    types::{Float64Type, UInt64Type},
+    ArrayRef, RecordBatch,
+};
+use arrow_ipc::{reader::FileReader, writer::FileWriter};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+
+use super::{host, sorted_centralities};
+use crate::{
+    webgraph::{NodeID, Webgraph},
+    Result,
+};
+
+/// Number of rows in each record batch of the file.
+pub const BATCH_SIZE: usize = 8_192;
+
+/// Number of rows in each row group of a Parquet export.
+pub const PARQUET_ROW_GROUP_SIZE: usize = 128 * BATCH_SIZE;
+
+fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("node_id", DataType::UInt64, false),
+        Field::new("host", DataType::Utf8, false),
+        Field::new("score", DataType::Float64, false),
+    ]))
+}
+
+struct Batch {
+    node_ids: UInt64Builder,
+    hosts: StringBuilder,
+    scores: Float64Builder,
+    len: usize,
+}
+
+impl Batch {
+    fn new() -> Self {
+        Self {
+            node_ids: UInt64Builder::with_capacity(BATCH_SIZE),
+            hosts: StringBuilder::new(),
+            scores: Float64Builder::with_capacity(BATCH_SIZE),
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, node_id: NodeID, host: &str, score: f64) {
+        self.node_ids.append_value(node_id.as_u64());
+        self.hosts.append_value(host);
+        self.scores.append_value(score);
+        self.len += 1;
+    }
+
+    /// Finish the rows pushed so far into a record batch and start a new one.
+    fn finish(&mut self, schema: &SchemaRef) -> Result<RecordBatch> {
+        self.len = 0;
+
+        Ok(RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(self.node_ids.finish()) as ArrayRef,
+                Arc::new(self.hosts.finish()) as ArrayRef,
+                Arc::new(self.scores.finish()) as ArrayRef,
+            ],
+        )?)
+    }
+}
+
+/// Write every centrality in `store` as a row of an Arrow IPC file, with the host resolved
+/// using `graph`. At most [`BATCH_SIZE`] rows are held in memory before they are written.
+/// Returns the number of rows written.
+pub fn export_arrow<W: Write>(
+    store: &speedy_kv::Db<NodeID, f64>,
+    graph: &Webgraph,
+    writer: W,
+) -> Result<u64> {
+    let schema = schema();
+    let mut wtr = FileWriter::try_new(writer, &schema)?;
+    let mut batch = Batch::new();
+    let mut rows = 0;
+
+    for (node_id, centrality) in sorted_centralities(store)? {
+        batch.push(node_id, &host(graph, &node_id), centrality);
+        rows += 1;
+
+        if batch.len >= BATCH_SIZE {
+            wtr.write(&batch.finish(&schema)?)?;
+        }
+    }
+
+    if batch.len > 0 {
+        wtr.write(&batch.finish(&schema)?)?;
+    }
+
+    wtr.finish()?;
+
+    Ok(rows)
+}
+
+/// Write every centrality in `store` as a row of a Parquet file, with the host resolved
+/// using `graph`. Like [`export_arrow`], at most [`BATCH_SIZE`] rows are built at a time,
+/// and the writer flushes a row group every [`PARQUET_ROW_GROUP_SIZE`] rows. Returns the
+/// number of rows written.
+pub fn export_parquet<W: Write + Send>(
+    store: &speedy_kv::Db<NodeID, f64>,
+    graph: &Webgraph,
+    writer: W,
+) -> Result<u64> {
+    let schema = schema();
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_max_row_group_size(PARQUET_ROW_GROUP_SIZE)
+        .build();
+    let mut wtr = ArrowWriter::try_new(writer, schema.clone(), Some(props))?;
+    let mut batch = Batch::new();
+    let mut rows = 0;
+
+    for (node_id, centrality) in sorted_centralities(store)? {
+        batch.push(node_id, &host(graph, &node_id), centrality);
+        rows += 1;
+
+        if batch.len >= BATCH_SIZE {
+            wtr.write(&batch.finish(&schema)?)?;
+        }
+    }
+
+    if batch.len > 0 {
+        wtr.write(&batch.finish(&schema)?)?;
+    }
+
+    wtr.close()?;
+
+    Ok(rows)
+}
+
+/// A row of an exported centrality file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CentralityRow {
+    pub node_id: NodeID,
+    pub host: String,
+    pub score: f64,
+}
+
+/// Reads the rows of a file written by [`export_arrow`] in the order they were written,
+/// one record batch at a time.
+pub struct CentralityReader<R: Read + Seek> {
+    reader: FileReader<R>,
+    batch: Option<RecordBatch>,
+    row: usize,
+}
+
+impl<R: Read + Seek> CentralityReader<R> {
+    /// Open the file. Fails if it does not have the columns of an export.
+    pub fn new(reader: R) -> Result<Self> {
+        let reader = FileReader::try_new(reader, None)?;
+
+        if reader.schema().fields() != schema().fields() {
+            bail!(
+                "not a centrality export, expected the schema {:?} but got {:?}",
+                schema(),
+                reader.schema()
+            );
+        }
+
+        Ok(Self {
+            reader,
+            batch: None,
+            row: 0,
+        })
+    }
+
+    /// Number of record batches in the file.
+    pub fn num_batches(&self) -> usize {
+        self.reader.num_batches()
+    }
+
+    fn row(batch: &RecordBatch, row: usize) -> CentralityRow {
+        CentralityRow {
+            node_id: NodeID::from(batch.column(0).as_primitive::<UInt64Type>().value(row)),
+            host: batch.column(1).as_string::<i32>().value(row).to_string(),
+            score: batch.column(2).as_primitive::<Float64Type>().value(row),
+        }
+    }
+}
+
+impl<R: Read + Seek> Iterator for CentralityReader<R> {
+    type Item = Result<CentralityRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(batch) = &self.batch {
+                if self.row < batch.num_rows() {
+                    self.row += 1;
+                    return Some(Ok(Self::row(batch, self.row - 1)));
+                }
+            }
+
+            match self.reader.next()? {
+                Ok(batch) => {
+                    self.batch = Some(batch);
+                    self.row = 0;
+                }
+                Err(err) => return Some(Err(anyhow!(err))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::webgraph::{
+        centrality::{harmonic::HarmonicCentrality, store_harmonic},
+        tests::test_graph,
+    };
+
+    use super::*;
+
+    #[test]
+    fn export_and_read_back() {
+        let graph = test_graph();
+        let harmonic = HarmonicCentrality::calculate(&graph);
+
+        let store = store_harmonic(
+            harmonic.iter().map(|(n, c)| (*n, c)),
+            crate::gen_temp_path(),
+        );
+
+        let mut out = Vec::new();
+        let written = export_arrow(&store, &graph, &mut out).unwrap();
+
+        let reader = CentralityReader::new(Cursor::new(out)).unwrap();
+        assert_eq!(
+            reader.num_batches(),
+            (written as usize).div_ceil(BATCH_SIZE)
+        );
+
+        let rows: Vec<_> = reader.map(|row| row.unwrap()).collect();
+        assert_eq!(rows.len() as u64, written);
+        assert_eq!(rows.len(), harmonic.iter().count());
+
+        for pair in rows.windows(2) {
+            assert!(
+                pair[0].score > pair[1].score
+                    || (pair[0].score == pair[1].score && pair[0].node_id < pair[1].node_id)
+            );
+        }
+
+        for (node_id, centrality) in harmonic.iter() {
+            let row = rows.iter().find(|row| row.node_id == *node_id).unwrap();
+
+            assert_eq!(row.host, graph.id2node(node_id).unwrap().as_str());
+            assert_eq!(row.score, centrality);
+        }
+    }
+
+    #[test]
+    fn export_parquet_rows() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let graph = test_graph();
+        let harmonic = HarmonicCentrality::calculate(&graph);
+
+        let store = store_harmonic(
+            harmonic.iter().map(|(n, c)| (*n, c)),
+            crate::gen_temp_path(),
+        );
+
+        let path = crate::gen_temp_path().join("centrality.parquet");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let written =
+            export_parquet(&store, &graph, std::fs::File::create(&path).unwrap()).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let mut rows = Vec::new();
+        for batch in reader {
+            let batch = batch.unwrap();
+            assert_eq!(batch.schema().fields(), schema().fields());
+
+            for row in 0..batch.num_rows() {
+                rows.push(CentralityReader::<Cursor<Vec<u8>>>::row(&batch, row));
+            }
+        }
+
+        assert_eq!(rows.len() as u64, written);
+        assert_eq!(rows.len(), harmonic.iter().count());
+
+        for (node_id, centrality) in harmonic.iter() {
+            let row = rows.iter().find(|row| row.node_id == *node_id).unwrap();
+
+            assert_eq!(row.host, graph.id2node(node_id).unwrap().as_str());
+            assert_eq!(row.score, centrality);
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "other",
+            DataType::UInt64,
+            false,
+        )]));
+
+        let mut out = Vec::new();
+        let mut wtr = FileWriter::try_new(&mut out, &schema).unwrap();
+        wtr.finish().unwrap();
+        drop(wtr);
+
+        assert!(CentralityReader::new(Cursor::new(out)).is_err());
+    }
+}
@@ -24,6 +24,7 @@ use indicatif::{ProgressBar, ProgressStyle};
 use crate::{
     intmap::IntMap,
     webgraph::{EdgeLimit, Node, NodeID, Webgraph},
+    webpage::html::links::RelFlags,
 };
 
 fn calculate(graph: &Webgraph, with_progress: bool) -> (HashMap<Node, f64>, i32) {
@@ -70,7 +71,11 @@ fn calculate(graph: &Webgraph, with_progress: bool) -> (HashMap<Node, f64>, i32)
 
         while let Some(v) = q.pop_front() {
             stack.push(v);
-            for edge in graph.raw_outgoing_edges(&v, EdgeLimit::Unlimited) {
+            for edge in graph
+                .raw_outgoing_edges(&v, EdgeLimit::Unlimited)
+                .into_iter()
+                .filter(|edge| !edge.rel.contains(RelFlags::REDIRECT))
+            {
                 let w = edge.to;
 
                 if !distances.contains_key(&w) {
@@ -23,7 +23,10 @@ use bloom::U64BloomFilter;
 use rayon::prelude::*;
 use std::{collections::BTreeMap, path::Path, sync::Mutex};
 
-use crate::webgraph::{EdgeLimit, NodeID, Webgraph};
+use crate::{
+    webgraph::{EdgeLimit, NodeID, Webgraph},
+    webpage::html::links::RelFlags,
+};
 
 struct BloomMap {
     map: Vec<Mutex<U64BloomFilter>>,
@@ -82,9 +85,12 @@ impl DerivedCentrality {
 
         let has_outgoing = BloomMap::new(8, num_nodes as u64, 0.01);
 
-        page_graph.par_edges().for_each(|edge| {
-            has_outgoing.insert(&edge.from);
-        });
+        page_graph
+            .par_edges()
+            .filter(|edge| !edge.rel.contains(RelFlags::REDIRECT))
+            .for_each(|edge| {
+                has_outgoing.insert(&edge.from);
+            });
 
         let has_outgoing = has_outgoing.finalize();
 
@@ -103,6 +109,7 @@ impl DerivedCentrality {
                     let mut ingoing: Vec<_> = page_graph
                         .raw_ingoing_edges(&id, EdgeLimit::Limit(128))
                         .into_iter()
+                        .filter(|e| !e.rel.contains(RelFlags::REDIRECT))
                         .filter_map(|e| page_graph.id2node(&e.from))
                         .map(|n| n.into_host())
                         .collect();
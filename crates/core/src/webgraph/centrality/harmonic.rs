@@ -44,6 +44,7 @@ pub static SKIPPED_REL: once_cell::sync::Lazy<RelFlags> = once_cell::sync::Lazy:
         | RelFlags::LINK_TAG
         | RelFlags::SCRIPT_TAG
         | RelFlags::SAME_ICANN_DOMAIN
+        | RelFlags::REDIRECT
 });
 
 type Counter = BTreeMap<NodeID, HyperLogLog<HYPERLOGLOG_COUNTERS>>;
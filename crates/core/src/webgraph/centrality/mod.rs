@@ -14,13 +14,14 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{cmp::Reverse, fs::File, path::Path};
+use std::{cmp::Reverse, fs::File, io::Write, path::Path};
 
-use crate::{external_sort::ExternalSorter, SortableFloat};
+use crate::{external_sort::ExternalSorter, Result, SortableFloat};
 
-use super::{Node, NodeID};
+use super::{Node, NodeID, Webgraph};
 
 pub mod approx_harmonic;
+pub mod arrow;
 pub mod betweenness;
 pub mod derived_harmonic;
 pub mod harmonic;
@@ -114,10 +115,144 @@ where
     store
 }
 
+/// Number of centralities the exports sort in memory at a time. Larger stores are sorted
+/// in chunks on disk.
+const EXPORT_SORT_CHUNK_SIZE: usize = 10_000_000;
+
+/// The centralities of `store` by descending score. Nodes with the same score are ordered
+/// by their id, so an export of the same store always has the same rows in the same order.
+fn sorted_centralities(
+    store: &speedy_kv::Db<NodeID, f64>,
+) -> Result<impl Iterator<Item = (NodeID, f64)>> {
+    Ok(ExternalSorter::new()
+        .with_chunk_size(EXPORT_SORT_CHUNK_SIZE)
+        .sort(
+            store
+                .iter()
+                .map(|(node_id, centrality)| (Reverse(SortableFloat(centrality)), node_id)),
+        )?
+        .map(|(Reverse(SortableFloat(centrality)), node_id)| (node_id, centrality)))
+}
+
+/// The host of the node in `graph`, or an empty string if the node is not in the graph.
+fn host(graph: &Webgraph, node_id: &NodeID) -> String {
+    graph
+        .id2node(node_id)
+        .map(|node| node.as_str().to_string())
+        .unwrap_or_default()
+}
+
+/// Write every centrality in `store` as a `(node_id, host, score)` csv row, where the host is
+/// the name of the node in `graph`. The rows are ordered like [`sorted_centralities`] and
+/// written as they are read from the sorted chunks, so the export never holds all the rows
+/// in memory. Nodes that are not in the graph get an empty host. Returns the number of rows
+/// written.
+pub fn export_csv<W: Write>(
+    store: &speedy_kv::Db<NodeID, f64>,
+    graph: &Webgraph,
+    writer: W,
+) -> Result<u64> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record(["node_id", "host", "score"])?;
+
+    let mut rows = 0;
+
+    for (node_id, centrality) in sorted_centralities(store)? {
+        let host = host(graph, &node_id);
+
+        wtr.write_record(&[node_id.as_u64().to_string(), host, centrality.to_string()])?;
+
+        rows += 1;
+    }
+
+    wtr.flush()?;
+
+    Ok(rows)
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use crate::webgraph::tests::test_graph;
+
     use super::*;
 
+    #[test]
+    fn export_centrality_csv() {
+        let graph = test_graph();
+        let harmonic = harmonic::HarmonicCentrality::calculate(&graph);
+
+        let store = store_harmonic(
+            harmonic.iter().map(|(n, c)| (*n, c)),
+            crate::gen_temp_path(),
+        );
+
+        let mut out = Vec::new();
+        let rows = export_csv(&store, &graph, &mut out).unwrap();
+
+        let mut rdr = csv::Reader::from_reader(out.as_slice());
+        assert_eq!(
+            rdr.headers().unwrap().iter().collect::<Vec<_>>(),
+            vec!["node_id", "host", "score"]
+        );
+
+        let exported: HashMap<u64, (String, f64)> = rdr
+            .records()
+            .map(|record| {
+                let record = record.unwrap();
+                (
+                    record[0].parse().unwrap(),
+                    (record[1].to_string(), record[2].parse().unwrap()),
+                )
+            })
+            .collect();
+
+        assert_eq!(rows, exported.len() as u64);
+        assert_eq!(exported.len(), harmonic.iter().count());
+
+        for (node_id, centrality) in harmonic.iter() {
+            let (host, score) = &exported[&node_id.as_u64()];
+
+            assert_eq!(host, graph.id2node(node_id).unwrap().as_str());
+            assert_eq!(*score, centrality);
+        }
+    }
+
+    #[test]
+    fn exported_rows_are_sorted() {
+        let graph = test_graph();
+        let harmonic = harmonic::HarmonicCentrality::calculate(&graph);
+
+        let store = store_harmonic(
+            harmonic.iter().map(|(n, c)| (*n, c)),
+            crate::gen_temp_path(),
+        );
+
+        let export = || {
+            let mut out = Vec::new();
+            export_csv(&store, &graph, &mut out).unwrap();
+            out
+        };
+
+        let out = export();
+        assert_eq!(out, export());
+
+        let rows: Vec<(u64, f64)> = csv::Reader::from_reader(out.as_slice())
+            .records()
+            .map(|record| {
+                let record = record.unwrap();
+                (record[0].parse().unwrap(), record[2].parse().unwrap())
+            })
+            .collect();
+
+        assert_eq!(rows.len(), harmonic.iter().count());
+        for pair in rows.windows(2) {
+            let ((a_id, a_score), (b_id, b_score)) = (pair[0], pair[1]);
+            assert!(a_score > b_score || (a_score == b_score && a_id < b_id));
+        }
+    }
+
     #[test]
     fn test_top_k() {
         let hits = [
@@ -0,0 +1,288 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The hosts that are commonly linked together with a host ("people also link to").
+//!
+//! The most central hosts linking to the host are looked up in the host graph, and the
+//! hosts those linkers also link to are counted. A co-linked host is scored by the
+//! fraction of the linkers that link to it. Both fan-outs are bounded by the
+//! [`CoLinkLimits`], so the query never reads more than
+//! `max_linkers * max_destinations` edges no matter how well linked the host is.
+//!
+//! The outgoing edges of a host are stored on the shard that crawled it, so every
+//! shard counts the linkers it knows of and the counts of the shards are summed.
+//! The linkers are split evenly between the shards with [`CoLinkLimits::per_shard`], so
+//! the limits bound the edges read by the whole cluster and not by each shard.
+
+use std::collections::HashMap;
+
+use utoipa::ToSchema;
+
+use super::{EdgeLimit, EdgeQueryCap, Node, NodeID, Webgraph};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct CoLinkLimits {
+    /// Number of the most central linking hosts whose links are counted.
+    pub max_linkers: usize,
+    /// Number of the links of each linker that are counted.
+    pub max_destinations: usize,
+}
+
+impl CoLinkLimits {
+    /// The limits of each of the shards of a graph, so the shards together count at most
+    /// `max_linkers` linkers. The links of a linker are all on the shard that crawled it,
+    /// so the number of links of each linker is not split.
+    pub fn per_shard(self, num_shards: usize) -> Self {
+        Self {
+            max_linkers: self.max_linkers.div_ceil(num_shards.max(1)),
+            max_destinations: self.max_destinations,
+        }
+    }
+}
+
+/// The number of linkers of a host that also link to each of the other hosts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct CoLinkCounts {
+    num_linkers: u64,
+    counts: HashMap<NodeID, u64>,
+    truncated: bool,
+}
+
+impl CoLinkCounts {
+    pub fn merge(&mut self, other: CoLinkCounts) {
+        self.num_linkers += other.num_linkers;
+
+        for (host, count) in other.counts {
+            *self.counts.entry(host).or_default() += count;
+        }
+
+        self.truncated |= other.truncated;
+    }
+
+    pub fn num_linkers(&self) -> u64 {
+        self.num_linkers
+    }
+
+    /// Some of the linkers hit the edge cap, so some of their links were not counted.
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The `n` hosts with the highest counts and their scores, highest score first.
+    pub fn top(&self, n: usize) -> Vec<(NodeID, f64)> {
+        let mut top: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(id, count)| (*id, *count))
+            .collect();
+        top.sort_by(|(a_id, a), (b_id, b)| b.cmp(a).then_with(|| a_id.cmp(b_id)));
+
+        top.into_iter()
+            .take(n)
+            .map(|(id, count)| (id, count as f64 / self.num_linkers.max(1) as f64))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CoLinkedHost {
+    pub host: String,
+    /// The fraction of the sampled linkers of the host that also link to this host.
+    pub score: f64,
+}
+
+impl Webgraph {
+    /// Count the hosts that the linkers of the host also link to.
+    /// This is meant for the host graph.
+    pub fn co_link_counts(
+        &self,
+        host: &Node,
+        limits: CoLinkLimits,
+        cap: EdgeQueryCap,
+    ) -> CoLinkCounts {
+        let host = host.clone().into_host().id();
+        let mut counts = CoLinkCounts::default();
+
+        let linkers =
+            self.raw_ingoing_edges_capped(&host, EdgeLimit::Limit(limits.max_linkers), cap);
+        counts.truncated |= linkers.truncated;
+
+        for linker in linkers.edges.into_iter().map(|edge| edge.from) {
+            if linker == host {
+                continue;
+            }
+
+            counts.num_linkers += 1;
+
+            let destinations = self.raw_outgoing_edges_capped(
+                &linker,
+                EdgeLimit::Limit(limits.max_destinations),
+                cap,
+            );
+            counts.truncated |= destinations.truncated;
+
+            for destination in destinations.edges.into_iter().map(|edge| edge.to) {
+                if destination != host && destination != linker {
+                    *counts.counts.entry(destination).or_default() += 1;
+                }
+            }
+        }
+
+        counts
+    }
+
+    /// The `n` hosts most commonly linked together with the host.
+    pub fn co_linked_hosts(
+        &self,
+        host: &Node,
+        limits: CoLinkLimits,
+        n: usize,
+    ) -> Vec<CoLinkedHost> {
+        self.co_link_counts(host, limits, EdgeQueryCap::unbounded())
+            .top(n)
+            .into_iter()
+            .filter_map(|(id, score)| {
+                self.id2node(&id).map(|node| CoLinkedHost {
+                    host: node.as_str().to_string(),
+                    score,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_fixtures::GraphFixture;
+
+    use super::*;
+
+    const LIMITS: CoLinkLimits = CoLinkLimits {
+        max_linkers: 16,
+        max_destinations: 16,
+    };
+
+    fn edges(edges: &[(&str, &str)]) -> Vec<(Node, Node, String)> {
+        edges
+            .iter()
+            .map(|(from, to)| (Node::from(*from), Node::from(*to), String::new()))
+            .collect()
+    }
+
+    #[test]
+    fn co_linked_hosts_rank_first() {
+        // every linker of rust-lang.org also links to crates.io, most of them to docs.rs
+        let graph = GraphFixture::new(0)
+            .hosts(20)
+            .link_density(0.05)
+            .edges(edges(&[
+                ("a.com", "rust-lang.org"),
+                ("b.com", "rust-lang.org"),
+                ("c.com", "rust-lang.org"),
+                ("d.com", "rust-lang.org"),
+                ("a.com", "crates.io"),
+                ("b.com", "crates.io"),
+                ("c.com", "crates.io"),
+                ("d.com", "crates.io"),
+                ("a.com", "docs.rs"),
+                ("b.com", "docs.rs"),
+                ("c.com", "docs.rs"),
+                ("d.com", "unrelated.com"),
+                ("e.com", "unrelated.com"),
+                ("e.com", "other.com"),
+            ]))
+            .build()
+            .into_graph();
+
+        let hosts = graph.co_linked_hosts(&Node::from("rust-lang.org"), LIMITS, 3);
+
+        assert_eq!(hosts[0].host, "crates.io");
+        assert_eq!(hosts[0].score, 1.0);
+        assert_eq!(hosts[1].host, "docs.rs");
+        assert_eq!(hosts[1].score, 0.75);
+        assert!(hosts.iter().all(|host| host.host != "rust-lang.org"));
+        assert!(hosts.iter().all(|host| host.host != "other.com"));
+    }
+
+    #[test]
+    fn fan_out_is_bounded() {
+        let graph = GraphFixture::new(0)
+            .edges((0..10).flat_map(|i| {
+                let linker = Node::from(format!("linker{i}.com"));
+
+                [
+                    (linker.clone(), Node::from("x.com"), String::new()),
+                    (linker.clone(), Node::from("y.com"), String::new()),
+                    (linker, Node::from("z.com"), String::new()),
+                ]
+            }))
+            .build()
+            .into_graph();
+
+        let counts = graph.co_link_counts(
+            &Node::from("x.com"),
+            CoLinkLimits {
+                max_linkers: 4,
+                max_destinations: 1,
+            },
+            EdgeQueryCap::unbounded(),
+        );
+
+        assert_eq!(counts.num_linkers(), 4);
+        assert!(counts.counts.values().sum::<u64>() <= 4);
+    }
+
+    #[test]
+    fn limits_are_split_between_shards() {
+        let limits = CoLinkLimits {
+            max_linkers: 10,
+            max_destinations: 8,
+        };
+
+        assert_eq!(limits.per_shard(1), limits);
+        assert_eq!(limits.per_shard(0), limits);
+        assert_eq!(
+            limits.per_shard(3),
+            CoLinkLimits {
+                max_linkers: 4,
+                max_destinations: 8,
+            }
+        );
+    }
+
+    #[test]
+    fn merge_shards() {
+        let mut a = CoLinkCounts::default();
+        a.num_linkers = 2;
+        a.counts.insert(Node::from("y.com").id(), 2);
+
+        let mut b = CoLinkCounts::default();
+        b.num_linkers = 2;
+        b.counts.insert(Node::from("y.com").id(), 1);
+        b.counts.insert(Node::from("z.com").id(), 2);
+
+        a.merge(b);
+
+        assert_eq!(
+            a.top(2),
+            vec![
+                (Node::from("y.com").id(), 0.75),
+                (Node::from("z.com").id(), 0.5)
+            ]
+        );
+    }
+}
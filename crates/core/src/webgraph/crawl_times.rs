@@ -0,0 +1,190 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The latest time each source node of a segment was crawled.
+//!
+//! When a page is crawled again, its outgoing edges from the new crawl should replace the
+//! ones from the earlier crawls instead of being added to them, otherwise links that were
+//! removed from the page would stay in the graph forever. Segments written with crawl
+//! tracking therefore store the latest time each source node was crawled, including the
+//! nodes that had no edges in the crawl, and
+//! [`super::Webgraph::merge_all_segments_superseding`] only keeps the edges of a source node
+//! from the segments where it was crawled most recently. Segments without crawl times, like
+//! the segments of graphs built before the times were tracked, count as crawled at time 0.
+
+use std::{collections::HashMap, path::Path};
+
+use super::NodeID;
+use crate::Result;
+
+pub const CRAWL_TIMES: &str = "crawl_times";
+
+#[derive(Default)]
+pub struct CrawlTimesWriter {
+    times: HashMap<NodeID, u64>,
+}
+
+impl CrawlTimesWriter {
+    /// Edges with an unknown time of discovery (0) don't tell when the source was crawled.
+    pub fn insert(&mut self, source: NodeID, crawled_at: u64) {
+        if crawled_at == 0 {
+            return;
+        }
+
+        let latest = self.times.entry(source).or_default();
+        *latest = (*latest).max(crawled_at);
+    }
+
+    pub fn finalize<P: AsRef<Path>>(self, path: P) -> Result<CrawlTimes> {
+        CrawlTimes::write(path, self.times)
+    }
+}
+
+pub struct CrawlTimes {
+    db: speedy_kv::Db<NodeID, u64>,
+}
+
+impl CrawlTimes {
+    /// The crawl times of a segment, if they were tracked when it was written.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        if !path.as_ref().exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            db: speedy_kv::Db::open_or_create(path)?,
+        }))
+    }
+
+    fn write<P: AsRef<Path>>(path: P, times: HashMap<NodeID, u64>) -> Result<Self> {
+        let mut db = speedy_kv::Db::open_or_create(path)?;
+
+        for (node, crawled_at) in times {
+            db.insert(node, crawled_at)?;
+        }
+
+        db.commit()?;
+
+        Ok(Self { db })
+    }
+
+    /// 0 if the node was not crawled in the segment.
+    pub fn get(&self, node: &NodeID) -> u64 {
+        self.db.get(node).ok().flatten().unwrap_or_default()
+    }
+
+    /// Write the latest crawl time of every node in any of the segments to `path`.
+    pub fn merge<P: AsRef<Path>>(segments: &[&CrawlTimes], path: P) -> Result<Self> {
+        let mut times: HashMap<NodeID, u64> = HashMap::new();
+
+        for segment in segments {
+            for (node, crawled_at) in segment.db.iter() {
+                let latest = times.entry(node).or_default();
+                *latest = (*latest).max(crawled_at);
+            }
+        }
+
+        Self::write(path, times)
+    }
+}
+
+/// The crawl times of the segments that are being merged, in the order of the segments.
+pub struct Supersession {
+    segments: Vec<Option<CrawlTimes>>,
+}
+
+impl Supersession {
+    pub fn new(segments: Vec<Option<CrawlTimes>>) -> Self {
+        Self { segments }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.iter().all(Option::is_none)
+    }
+
+    fn crawled_at(&self, segment: usize, node: &NodeID) -> u64 {
+        self.segments[segment]
+            .as_ref()
+            .map(|times| times.get(node))
+            .unwrap_or_default()
+    }
+
+    /// Whether the edges from `source` in the segment are from the latest crawl of the source.
+    /// Crawls at the same time are all the latest.
+    pub fn is_latest(&self, segment: usize, source: &NodeID) -> bool {
+        let crawled_at = self.crawled_at(segment, source);
+
+        (0..self.segments.len())
+            .filter(|other| *other != segment)
+            .all(|other| self.crawled_at(other, source) <= crawled_at)
+    }
+
+    /// Write the merged crawl times of the segments to `path`, if any of them had any.
+    pub fn finalize<P: AsRef<Path>>(self, path: P) -> Result<()> {
+        let segments: Vec<_> = self.segments.iter().flatten().collect();
+
+        if !segments.is_empty() {
+            CrawlTimes::merge(&segments, path)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gen_temp_path;
+
+    use super::*;
+
+    fn times(times: &[(u64, u64)]) -> CrawlTimes {
+        let mut writer = CrawlTimesWriter::default();
+
+        for (node, crawled_at) in times {
+            writer.insert(NodeID::from(*node), *crawled_at);
+        }
+
+        writer.finalize(gen_temp_path()).unwrap()
+    }
+
+    #[test]
+    fn latest_crawl_wins() {
+        let supersession = Supersession::new(vec![
+            None,
+            Some(times(&[(1, 10), (2, 10), (1, 5)])),
+            Some(times(&[(1, 20), (3, 0)])),
+        ]);
+
+        assert!(!supersession.is_latest(1, &NodeID::from(1u64)));
+        assert!(supersession.is_latest(2, &NodeID::from(1u64)));
+
+        assert!(!supersession.is_latest(0, &NodeID::from(2u64)));
+        assert!(supersession.is_latest(1, &NodeID::from(2u64)));
+
+        // nodes without a known crawl time are kept everywhere
+        for segment in 0..3 {
+            assert!(supersession.is_latest(segment, &NodeID::from(3u64)));
+        }
+
+        let path = gen_temp_path();
+        supersession.finalize(&path).unwrap();
+        let merged = CrawlTimes::open(&path).unwrap().unwrap();
+
+        assert_eq!(merged.get(&NodeID::from(1u64)), 20);
+        assert_eq!(merged.get(&NodeID::from(2u64)), 10);
+        assert_eq!(merged.get(&NodeID::from(3u64)), 0);
+    }
+}
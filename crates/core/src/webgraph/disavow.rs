@@ -0,0 +1,180 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Disavowed linking nodes, for leaving the links a site does not vouch for out of
+//! a backlink analysis, or for looking at only those links.
+
+use std::sync::Arc;
+
+use fnv::FnvHashSet;
+
+use super::NodeID;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisavowMode {
+    /// Leave out the links from disavowed nodes.
+    #[default]
+    Exclude,
+    /// Only keep the links from disavowed nodes.
+    Only,
+}
+
+/// A set of disavowed linking nodes. For the host graph these are hosts and for the
+/// page graph pages. The set is shared, so the filter is cheap to clone even for long lists.
+#[derive(Debug, Clone, Default)]
+pub struct DisavowFilter {
+    nodes: Arc<FnvHashSet<NodeID>>,
+    mode: DisavowMode,
+}
+
+impl DisavowFilter {
+    pub fn new(nodes: impl IntoIterator<Item = NodeID>) -> Self {
+        Self {
+            nodes: Arc::new(nodes.into_iter().collect()),
+            mode: DisavowMode::default(),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: DisavowMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn mode(&self) -> DisavowMode {
+        self.mode
+    }
+
+    pub fn is_disavowed(&self, node: &NodeID) -> bool {
+        self.nodes.contains(node)
+    }
+
+    /// Whether a link from the node should be kept.
+    pub fn matches(&self, from: &NodeID) -> bool {
+        match self.mode {
+            DisavowMode::Exclude => !self.is_disavowed(from),
+            DisavowMode::Only => self.is_disavowed(from),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        executor::Executor,
+        webgraph::{
+            Compression, EdgeLimit, Node, SubdomainBacklinksQuery, Webgraph, WebgraphWriter,
+        },
+        webpage::html::links::RelFlags,
+    };
+
+    use super::*;
+
+    fn graph() -> Webgraph {
+        let mut writer = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        );
+
+        for from in ["a.com", "b.com", "spam.com", "spam2.com"] {
+            writer.insert(
+                Node::from(from),
+                Node::from("x.com"),
+                String::new(),
+                RelFlags::default(),
+            );
+        }
+
+        writer.insert(
+            Node::from("spam.com"),
+            Node::from("blog.x.com"),
+            String::new(),
+            RelFlags::default(),
+        );
+
+        writer.commit();
+
+        writer.finalize()
+    }
+
+    fn backlinks(graph: &Webgraph, filter: &DisavowFilter) -> Vec<String> {
+        let mut hosts: Vec<_> = graph
+            .ingoing_edges_filtered(
+                Node::from("x.com"),
+                None,
+                None,
+                Some(filter),
+                EdgeLimit::Unlimited,
+            )
+            .into_iter()
+            .map(|edge| edge.from.as_str().to_string())
+            .collect();
+        hosts.sort();
+
+        hosts
+    }
+
+    #[test]
+    fn disavowed_linkers() {
+        let graph = graph();
+        let filter =
+            DisavowFilter::new([Node::from("spam.com").id(), Node::from("spam2.com").id()]);
+
+        assert_eq!(backlinks(&graph, &filter), vec!["a.com", "b.com"]);
+        assert_eq!(
+            backlinks(&graph, &filter.clone().with_mode(DisavowMode::Only)),
+            vec!["spam.com", "spam2.com"]
+        );
+        assert_eq!(
+            backlinks(&graph, &DisavowFilter::default()),
+            vec!["a.com", "b.com", "spam.com", "spam2.com"]
+        );
+
+        // the limit is applied after the filter
+        assert_eq!(
+            graph
+                .ingoing_edges_filtered(
+                    Node::from("x.com"),
+                    None,
+                    None,
+                    Some(&filter.clone().with_mode(DisavowMode::Only)),
+                    EdgeLimit::Limit(2),
+                )
+                .len(),
+            2
+        );
+
+        let query = SubdomainBacklinksQuery::new(&Node::from("x.com")).unwrap();
+        assert_eq!(query.run(&graph).num_backlinks(), 5);
+        assert_eq!(
+            SubdomainBacklinksQuery::new(&Node::from("x.com"))
+                .unwrap()
+                .with_disavow(filter.clone())
+                .run(&graph)
+                .num_backlinks(),
+            2
+        );
+        assert_eq!(
+            SubdomainBacklinksQuery::new(&Node::from("x.com"))
+                .unwrap()
+                .with_disavow(filter.with_mode(DisavowMode::Only))
+                .run(&graph)
+                .num_backlinks(),
+            3
+        );
+    }
+}
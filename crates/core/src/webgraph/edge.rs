@@ -20,6 +20,8 @@ use crate::webpage::html::links::RelFlags;
 
 use super::{merge::NodeDatum, FullNodeID, Node, NodeID};
 
+/// The default number of characters stored for the label of an edge. Longer labels are
+/// truncated by the [`super::WebgraphWriter`].
 pub const MAX_LABEL_LENGTH: usize = 1024;
 
 pub trait EdgeLabel
@@ -50,7 +52,23 @@ impl EdgeLabel for () {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, bincode::Encode, bincode::Decode)]
+/// An edge between two nodes.
+///
+/// The JSON representation is
+/// `{"from": 1, "to": 2, "rel": ["nofollow"], "label": "anchor text", "discoveredAt": 0}`
+/// where `from` and `to` are node ids and `rel` is the names of the rel flags.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+#[serde(rename_all = "camelCase")]
 pub struct Edge<L>
 where
     L: EdgeLabel,
@@ -59,6 +77,9 @@ where
     pub to: NodeID,
     pub rel: RelFlags,
     pub label: L,
+    /// Seconds since the unix epoch when the edge was first discovered.
+    /// 0 means the time of discovery is unknown.
+    pub discovered_at: u64,
 }
 
 impl<L> Edge<L>
@@ -70,6 +91,27 @@ where
     }
 }
 
+/// Only keep edges where all the `required` flags are set and none of the `excluded` flags are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RelFlagsFilter {
+    pub required: RelFlags,
+    pub excluded: RelFlags,
+}
+
+impl RelFlagsFilter {
+    /// Links that pass on ranking signals, i.e. are neither `nofollow` nor `sponsored`.
+    pub fn follow() -> Self {
+        Self {
+            required: RelFlags::empty(),
+            excluded: RelFlags::NOFOLLOW | RelFlags::SPONSORED,
+        }
+    }
+
+    pub fn matches(&self, rel: RelFlags) -> bool {
+        rel.contains(self.required) && !rel.intersects(self.excluded)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, bincode::Encode, bincode::Decode)]
 pub struct InsertableEdge<L>
 where
@@ -79,6 +121,7 @@ where
     pub to: FullNodeID,
     pub rel: RelFlags,
     pub label: L,
+    pub discovered_at: u64,
 }
 
 #[cfg(test)]
@@ -92,6 +135,7 @@ where
             to: edge.to.id,
             rel: edge.rel,
             label: edge.label,
+            discovered_at: edge.discovered_at,
         }
     }
 }
@@ -113,6 +157,7 @@ pub struct FullEdge {
     pub from: Node,
     pub to: Node,
     pub label: String,
+    pub discovered_at: u64,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -124,6 +169,17 @@ where
     pub to: NodeDatum,
     pub rel: RelFlags,
     pub label: L,
+    pub discovered_at: u64,
+}
+
+impl<L> SegmentEdge<L>
+where
+    L: EdgeLabel,
+{
+    /// Redirects are recorded as edges, but they are not links of the page.
+    pub fn is_redirect(&self) -> bool {
+        self.rel.contains(RelFlags::REDIRECT)
+    }
 }
 
 impl<L> From<SegmentEdge<L>> for Edge<L>
@@ -136,6 +192,7 @@ where
             to: edge.to.node(),
             rel: edge.rel,
             label: edge.label,
+            discovered_at: edge.discovered_at,
         }
     }
 }
@@ -151,6 +208,7 @@ where
             to: NodeDatum::new(edge.to, 0),
             rel: edge.rel,
             label: edge.label,
+            discovered_at: edge.discovered_at,
         }
     }
 }
@@ -166,6 +224,7 @@ where
             to: NodeDatum::new(edge.to.id, 0),
             rel: edge.rel,
             label: edge.label,
+            discovered_at: edge.discovered_at,
         }
     }
 }
@@ -175,6 +234,7 @@ pub struct StoredEdge<L = ()> {
     pub other: NodeDatum,
     pub rel: RelFlags,
     pub label: L,
+    pub discovered_at: u64,
 }
 
 impl StoredEdge<()> {
@@ -183,6 +243,7 @@ impl StoredEdge<()> {
             other,
             rel,
             label: (),
+            discovered_at: 0,
         }
     }
 }
@@ -193,6 +254,14 @@ impl<L> StoredEdge<L> {
             other: self.other,
             rel: self.rel,
             label,
+            discovered_at: self.discovered_at,
+        }
+    }
+
+    pub fn with_discovered_at(self, discovered_at: u64) -> Self {
+        StoredEdge {
+            discovered_at,
+            ..self
         }
     }
 
@@ -210,4 +279,53 @@ impl<L> StoredEdge<L> {
     pub fn rel(&self) -> RelFlags {
         self.rel
     }
+
+    #[inline]
+    pub fn discovered_at(&self) -> u64 {
+        self.discovered_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edge_json() {
+        let edge = Edge {
+            from: NodeID::from(1u64),
+            to: NodeID::from(2u64),
+            rel: RelFlags::NOFOLLOW | RelFlags::SPONSORED,
+            label: "anchor text".to_string(),
+            discovered_at: 1_700_000_000,
+        };
+
+        let json = serde_json::to_value(&edge).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "from": 1,
+                "to": 2,
+                "rel": ["nofollow", "sponsored"],
+                "label": "anchor text",
+                "discoveredAt": 1_700_000_000u64,
+            })
+        );
+
+        let parsed: Edge<String> = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, edge);
+
+        let unlabeled = Edge {
+            from: NodeID::from(1u64),
+            to: NodeID::from(2u64),
+            rel: RelFlags::empty(),
+            label: (),
+            discovered_at: 0,
+        };
+
+        let json = serde_json::to_value(&unlabeled).unwrap();
+        assert_eq!(json["label"], serde_json::Value::Null);
+        assert_eq!(json["rel"], serde_json::json!([]));
+        assert_eq!(serde_json::from_value::<Edge<()>>(json).unwrap(), unlabeled);
+    }
 }
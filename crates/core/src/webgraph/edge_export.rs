@@ -0,0 +1,421 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Export of the full edge list of a webgraph for offline processing.
+//!
+//! The edges are read from the segments in parallel and sent through a bounded channel to
+//! the thread that writes them, so only [`EXPORT_BUFFER_SIZE`] edges are held in memory no
+//! matter how large the graph is. An edge that is present in more than one segment is
+//! exported once for each of them, and the edges are in no particular order.
+//!
+//! The TSV format has a header line and a line for each edge with the columns
+//! `from_id, to_id, rel_flags, sort_score, label` followed by `from_host, to_host` if the
+//! hosts are resolved. Backslashes, tabs and newlines in the text columns are escaped as
+//! `\\`, `\t`, `\n` and `\r`. The binary format is a sequence of [`ExportedEdge`]s, each
+//! encoded with bincode and prefixed by its length as a little-endian `u32`.
+
+use std::{
+    fmt::Display,
+    io::{BufRead, Read, Write},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail};
+use itertools::Either;
+use rayon::prelude::*;
+
+use super::{NodeID, SegmentEdge, Webgraph};
+use crate::{webpage::html::links::RelFlags, Result};
+
+/// Number of edges that can be waiting to be written.
+pub const EXPORT_BUFFER_SIZE: usize = 10_000;
+
+const TSV_COLUMNS: [&str; 5] = ["from_id", "to_id", "rel_flags", "sort_score", "label"];
+const TSV_HOST_COLUMNS: [&str; 2] = ["from_host", "to_host"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeExportFormat {
+    Tsv,
+    Binary,
+}
+
+impl Display for EdgeExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Tsv => "tsv",
+            Self::Binary => "binary",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for EdgeExportFormat {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tsv" => Ok(Self::Tsv),
+            "binary" => Ok(Self::Binary),
+            _ => Err(crate::Error::UnknownCLIOption),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, bincode::Encode, bincode::Decode)]
+pub struct ExportedEdge {
+    pub from: NodeID,
+    pub to: NodeID,
+    pub rel: RelFlags,
+    /// The sort key of the destination of the edge.
+    pub sort_score: u64,
+    pub label: String,
+    /// The hosts are only set if they were resolved during the export.
+    pub from_host: Option<String>,
+    pub to_host: Option<String>,
+}
+
+impl ExportedEdge {
+    fn new(edge: SegmentEdge<String>, graph: &Webgraph, resolve_hosts: bool) -> Self {
+        let host = |id: &NodeID| {
+            if resolve_hosts {
+                Some(
+                    graph
+                        .id2node(id)
+                        .map(|node| node.as_str().to_string())
+                        .unwrap_or_default(),
+                )
+            } else {
+                None
+            }
+        };
+
+        Self {
+            from: edge.from.node(),
+            to: edge.to.node(),
+            rel: edge.rel,
+            sort_score: edge.to.sort_key(),
+            from_host: host(&edge.from.node()),
+            to_host: host(&edge.to.node()),
+            label: edge.label,
+        }
+    }
+
+    fn write_tsv<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}",
+            self.from.as_u64(),
+            self.to.as_u64(),
+            self.rel.as_u32(),
+            self.sort_score,
+            escape(&self.label)
+        )?;
+
+        if let (Some(from_host), Some(to_host)) = (&self.from_host, &self.to_host) {
+            write!(writer, "\t{}\t{}", escape(from_host), escape(to_host))?;
+        }
+
+        writeln!(writer)?;
+
+        Ok(())
+    }
+
+    fn parse_tsv(line: &str, with_hosts: bool) -> Result<Self> {
+        let columns: Vec<_> = line.split('\t').collect();
+        let expected = if with_hosts {
+            TSV_COLUMNS.len() + TSV_HOST_COLUMNS.len()
+        } else {
+            TSV_COLUMNS.len()
+        };
+
+        if columns.len() != expected {
+            bail!(
+                "expected {expected} columns but the line has {}",
+                columns.len()
+            );
+        }
+
+        Ok(Self {
+            from: NodeID::from(columns[0].parse::<u64>()?),
+            to: NodeID::from(columns[1].parse::<u64>()?),
+            rel: RelFlags::from(columns[2].parse::<u32>()?),
+            sort_score: columns[3].parse()?,
+            label: unescape(columns[4])?,
+            from_host: columns.get(5).map(|host| unescape(host)).transpose()?,
+            to_host: columns.get(6).map(|host| unescape(host)).transpose()?,
+        })
+    }
+
+    fn write_binary<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let bytes = bincode::encode_to_vec(self, bincode::config::standard())?;
+
+        writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        writer.write_all(&bytes)?;
+
+        Ok(())
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '\\' => res.push_str("\\\\"),
+            '\t' => res.push_str("\\t"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            c => res.push(c),
+        }
+    }
+
+    res
+}
+
+fn unescape(s: &str) -> Result<String> {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('\\') => res.push('\\'),
+            Some('t') => res.push('\t'),
+            Some('n') => res.push('\n'),
+            Some('r') => res.push('\r'),
+            Some(c) => bail!("unknown escape sequence '\\{c}'"),
+            None => bail!("unterminated escape sequence"),
+        }
+    }
+
+    Ok(res)
+}
+
+impl Webgraph {
+    /// Stream every edge of the graph to `writer` in the given format. The ids of the nodes
+    /// are resolved to their hosts if `resolve_hosts` is set. Returns the number of edges
+    /// written.
+    pub fn export_edges<W: Write>(
+        &self,
+        mut writer: W,
+        format: EdgeExportFormat,
+        resolve_hosts: bool,
+    ) -> Result<u64> {
+        if format == EdgeExportFormat::Tsv {
+            let mut header = TSV_COLUMNS.to_vec();
+            if resolve_hosts {
+                header.extend(TSV_HOST_COLUMNS);
+            }
+
+            writeln!(writer, "{}", header.join("\t"))?;
+        }
+
+        let (tx, rx) = crossbeam_channel::bounded(EXPORT_BUFFER_SIZE);
+
+        let written = std::thread::scope(|s| {
+            s.spawn(move || {
+                // the receiver is dropped if writing fails, which stops the producers
+                let _ = self
+                    .par_edges_with_label()
+                    .try_for_each_with(tx, |tx, edge| {
+                        tx.send(ExportedEdge::new(edge, self, resolve_hosts))
+                    });
+            });
+
+            let mut written = 0;
+
+            for edge in rx {
+                match format {
+                    EdgeExportFormat::Tsv => edge.write_tsv(&mut writer)?,
+                    EdgeExportFormat::Binary => edge.write_binary(&mut writer)?,
+                }
+
+                written += 1;
+            }
+
+            Result::<_>::Ok(written)
+        })?;
+
+        writer.flush()?;
+
+        Ok(written)
+    }
+}
+
+/// Read the edges of a file written by [`Webgraph::export_edges`].
+pub fn read_edges<R: BufRead>(
+    mut reader: R,
+    format: EdgeExportFormat,
+) -> Result<impl Iterator<Item = Result<ExportedEdge>>> {
+    let with_hosts = match format {
+        EdgeExportFormat::Tsv => {
+            let mut header = String::new();
+            reader.read_line(&mut header)?;
+
+            let columns: Vec<_> = header.trim_end_matches('\n').split('\t').collect();
+
+            if columns == TSV_COLUMNS {
+                false
+            } else if columns == [TSV_COLUMNS.as_slice(), TSV_HOST_COLUMNS.as_slice()].concat() {
+                true
+            } else {
+                bail!("not an edge export, unexpected header {header:?}");
+            }
+        }
+        EdgeExportFormat::Binary => false,
+    };
+
+    Ok(match format {
+        EdgeExportFormat::Tsv => Either::Left(
+            reader
+                .lines()
+                .map(move |line| ExportedEdge::parse_tsv(&line?, with_hosts)),
+        ),
+        EdgeExportFormat::Binary => Either::Right(std::iter::from_fn(move || {
+            read_binary(&mut reader).transpose()
+        })),
+    })
+}
+
+fn read_binary<R: Read>(reader: &mut R) -> Result<Option<ExportedEdge>> {
+    let mut len = [0; 4];
+
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+
+    let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(|err| anyhow!("truncated edge: {err}"))?;
+
+    let (edge, _) = bincode::decode_from_slice(&bytes, bincode::config::standard())?;
+
+    Ok(Some(edge))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, io::Cursor};
+
+    use crate::{
+        test_fixtures::GraphFixture,
+        webgraph::{tests::test_graph, Node},
+    };
+
+    use super::*;
+
+    fn expected(graph: &Webgraph, resolve_hosts: bool) -> HashSet<ExportedEdge> {
+        graph
+            .segments
+            .iter()
+            .flat_map(|segment| segment.edges_with_label())
+            .map(|edge| ExportedEdge::new(edge, graph, resolve_hosts))
+            .collect()
+    }
+
+    fn export(graph: &Webgraph, format: EdgeExportFormat, resolve_hosts: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        let written = graph.export_edges(&mut out, format, resolve_hosts).unwrap();
+        assert_eq!(written, graph.edges().count() as u64);
+
+        out
+    }
+
+    fn read_back(out: Vec<u8>, format: EdgeExportFormat) -> HashSet<ExportedEdge> {
+        read_edges(Cursor::new(out), format)
+            .unwrap()
+            .map(|edge| edge.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn export_and_read_back() {
+        let graph = test_graph();
+
+        for format in [EdgeExportFormat::Tsv, EdgeExportFormat::Binary] {
+            for resolve_hosts in [false, true] {
+                let edges = read_back(export(&graph, format, resolve_hosts), format);
+
+                assert_eq!(edges, expected(&graph, resolve_hosts));
+                assert_eq!(
+                    edges
+                        .iter()
+                        .map(|edge| (edge.from, edge.to))
+                        .collect::<HashSet<_>>(),
+                    graph
+                        .edges()
+                        .map(|edge| (edge.from, edge.to))
+                        .collect::<HashSet<_>>()
+                );
+
+                for edge in &edges {
+                    if resolve_hosts {
+                        let from = graph.id2node(&edge.from).unwrap();
+                        assert_eq!(edge.from_host.as_deref(), Some(from.as_str()));
+                    } else {
+                        assert_eq!(edge.from_host, None);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn labels_are_escaped() {
+        let labels = ["tab\there", "new\nline\r\n", "back\\slash \\t", "plain"];
+
+        let graph = GraphFixture::new(0)
+            .edges(labels.iter().enumerate().map(|(i, label)| {
+                (
+                    Node::from(format!("{i}.com").as_str()),
+                    Node::from("b.com"),
+                    label.to_string(),
+                )
+            }))
+            .build()
+            .into_graph();
+
+        let out = export(&graph, EdgeExportFormat::Tsv, true);
+        assert_eq!(
+            String::from_utf8(out.clone()).unwrap().lines().count(),
+            labels.len() + 1
+        );
+
+        let edges = read_back(out, EdgeExportFormat::Tsv);
+        assert_eq!(edges, expected(&graph, true));
+        assert_eq!(
+            edges
+                .iter()
+                .map(|edge| edge.label.as_str())
+                .collect::<HashSet<_>>(),
+            labels.into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn rejects_bad_escapes() {
+        assert!(unescape("a\\xb").is_err());
+        assert!(unescape("a\\").is_err());
+        assert_eq!(unescape(&escape("a\\t\tb")).unwrap(), "a\\t\tb");
+    }
+}
@@ -14,18 +14,69 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/license
 
-use std::path::Path;
+//! The names of the nodes by their ids.
+//!
+//! The id of a node is a hash of its name, so the name that is found for an id is hashed
+//! again and compared to the id before it is returned. A name with another hash means
+//! that the db is corrupted or that two names collided in the hash, and the node is
+//! treated as not found rather than returning the wrong node. In strict mode, every
+//! segment is searched for the id and an error is returned if they disagree on the name.
+
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::anyhow;
+
+use crate::Result;
 
 use super::{Node, NodeID};
 
 pub struct Id2NodeDb {
     db: speedy_kv::Db<NodeID, Node>,
+    hasher: fn(&Node) -> NodeID,
+    strict: bool,
+    num_mismatches: AtomicU64,
 }
 
 impl Id2NodeDb {
     pub fn open<P: AsRef<Path>>(path: P) -> Self {
         Self {
             db: speedy_kv::Db::open_or_create(path).unwrap(),
+            hasher: Node::id,
+            strict: false,
+            num_mismatches: AtomicU64::new(0),
+        }
+    }
+
+    /// Hash the names with another function than [`Node::id`], to provoke collisions.
+    #[cfg(test)]
+    fn with_hasher(mut self, hasher: fn(&Node) -> NodeID) -> Self {
+        self.hasher = hasher;
+        self
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// The number of lookups that found a name with another hash than the id.
+    pub fn num_mismatches(&self) -> u64 {
+        self.num_mismatches.load(Ordering::Relaxed)
+    }
+
+    fn verify(&self, id: &NodeID, node: Node) -> Option<Node> {
+        if (self.hasher)(&node) == *id {
+            Some(node)
+        } else {
+            self.num_mismatches.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                "the name {:?} stored for node {:?} has another id",
+                node.as_str(),
+                id
+            );
+            None
         }
     }
 
@@ -33,8 +84,41 @@ impl Id2NodeDb {
         self.db.insert(*id, node.clone()).unwrap();
     }
 
+    /// The node with the id. Errors in strict mode are logged and treated as not found.
     pub fn get(&self, id: &NodeID) -> Option<Node> {
-        self.db.get(id).unwrap()
+        match self.try_get(id) {
+            Ok(node) => node,
+            Err(err) => {
+                tracing::error!("failed to look up node {:?}: {:?}", id, err);
+                None
+            }
+        }
+    }
+
+    pub fn try_get(&self, id: &NodeID) -> Result<Option<Node>> {
+        if self.strict {
+            self.get_strict(id)
+        } else {
+            Ok(self.db.get(id)?.and_then(|node| self.verify(id, node)))
+        }
+    }
+
+    /// Look up the id in every segment and fail if more than one name is stored for it.
+    pub fn get_strict(&self, id: &NodeID) -> Result<Option<Node>> {
+        let mut nodes = self.db.get_all(id)?;
+        nodes.sort();
+        nodes.dedup();
+
+        match nodes.len() {
+            0 => Ok(None),
+            1 => Ok(nodes.pop().and_then(|node| self.verify(id, node))),
+            _ => Err(anyhow!(
+                "{} distinct names are stored for node {:?}: {:?}",
+                nodes.len(),
+                id,
+                nodes.iter().map(|node| node.as_str()).collect::<Vec<_>>()
+            )),
+        }
     }
 
     pub fn keys(&self) -> impl Iterator<Item = NodeID> + '_ {
@@ -72,7 +156,7 @@ mod tests {
         let mut db = Id2NodeDb::open(gen_temp_path());
 
         let a_node = Node::from("a".to_string());
-        let a_id = NodeID::from(0_u64);
+        let a_id = a_node.id();
 
         db.put(&a_id, &a_node);
         db.flush();
@@ -80,7 +164,7 @@ mod tests {
         assert_eq!(db.get(&a_id), Some(a_node.clone()));
 
         let b_node = Node::from("b".to_string());
-        let b_id = NodeID::from(1_u64);
+        let b_id = b_node.id();
 
         assert_eq!(db.get(&b_id), None);
 
@@ -90,4 +174,57 @@ mod tests {
         assert_eq!(db.get(&b_id), Some(b_node));
         assert_eq!(db.get(&a_id), Some(a_node));
     }
+
+    #[test]
+    fn corrupted_name_is_not_found() {
+        let mut db = Id2NodeDb::open(gen_temp_path());
+
+        let a_node = Node::from("a".to_string());
+        let b_node = Node::from("b".to_string());
+
+        db.put(&a_node.id(), &b_node);
+        db.put(&b_node.id(), &b_node);
+        db.flush();
+
+        assert_eq!(db.get(&a_node.id()), None);
+        assert_eq!(db.num_mismatches(), 1);
+
+        assert_eq!(db.get(&b_node.id()), Some(b_node));
+        assert_eq!(db.num_mismatches(), 1);
+    }
+
+    #[test]
+    fn strict_mode_detects_collisions() {
+        let mut db = Id2NodeDb::open(gen_temp_path()).with_hasher(|_| NodeID::from(0_u64));
+        let id = NodeID::from(0_u64);
+
+        let a_node = Node::from("a".to_string());
+        let b_node = Node::from("b".to_string());
+
+        db.put(&id, &a_node);
+        db.flush();
+        db.put(&id, &b_node);
+        db.flush();
+
+        // the names hash to the id, so the collision is only found by the strict lookup
+        assert_eq!(db.get(&id), Some(b_node.clone()));
+        assert!(db.get_strict(&id).is_err());
+
+        db.set_strict(true);
+        assert_eq!(db.get(&id), None);
+        assert!(db.try_get(&id).is_err());
+
+        db.put(&id, &b_node);
+        db.flush();
+        assert!(db.get_strict(&id).is_err());
+
+        let mut db = Id2NodeDb::open(gen_temp_path()).with_hasher(|_| NodeID::from(0_u64));
+        db.set_strict(true);
+        db.put(&id, &a_node);
+        db.flush();
+        db.put(&id, &a_node);
+        db.flush();
+
+        assert_eq!(db.try_get(&id).unwrap(), Some(a_node));
+    }
 }
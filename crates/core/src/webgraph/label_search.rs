@@ -0,0 +1,371 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Search the labels (anchor texts) of the links to a node.
+//!
+//! The graph has no text index of the labels, so the ingoing edges of the node are read,
+//! bounded by the edge query caps, and the edges whose label contains a term of the query
+//! are returned. With [`LabelScoring::Relevance`] the labels are scored with BM25, using
+//! the labels that were read as the corpus, and the edges are ordered by a blend of the
+//! relevance of their label and their position in the sort order of the graph, where the
+//! edges from the most central nodes come first. With [`LabelScoring::SortKey`] the
+//! matching edges keep the sort order of the graph.
+//!
+//! The edges of a node are split between the shards of a distributed graph, so each shard
+//! only returns its [`LabelMatch`]es with their raw relevance, and the relevances are
+//! normalized by [`LabelSearchQuery::rank`] once the matches of all the shards are merged.
+
+use std::collections::HashMap;
+
+use tantivy::fieldnorm::FieldNormReader;
+use utoipa::ToSchema;
+
+use super::{CappedEdges, EdgeLimit, EdgeQueryCap, FullEdge, Node, Webgraph};
+use crate::ranking::bm25::{Bm25Constants, Bm25Weight};
+
+#[derive(Debug, Clone, Copy, PartialEq, bincode::Encode, bincode::Decode)]
+pub enum LabelScoring {
+    /// The matching edges in the sort order of the graph.
+    SortKey,
+    /// The matching edges by the blended score
+    /// `(1 - sort_key_weight) * relevance + sort_key_weight * position`,
+    /// where both the BM25 relevance of the label and the position in the sort order are
+    /// normalized to be between 0 and 1.
+    Relevance { sort_key_weight: f64 },
+}
+
+impl Default for LabelScoring {
+    fn default() -> Self {
+        Self::Relevance {
+            sort_key_weight: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, bincode::Encode, bincode::Decode)]
+pub struct LabelSearchQuery {
+    pub query: String,
+    pub limit: usize,
+    pub scoring: LabelScoring,
+}
+
+/// An edge whose label matches the query, before the scores are normalized.
+#[derive(Debug, Clone, PartialEq, bincode::Encode, bincode::Decode)]
+pub struct LabelMatch {
+    pub edge: FullEdge,
+    /// The BM25 score of the label.
+    pub relevance: f64,
+    /// The position of the edge in the sort order of its graph, from 1 for the first edge
+    /// towards 0 for the last.
+    pub position: f64,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoredEdge {
+    pub edge: FullEdge,
+    pub score: f64,
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+impl LabelSearchQuery {
+    /// Score the edges that match the query, best first. The edges must be in the sort
+    /// order of the graph.
+    pub fn score(&self, edges: Vec<FullEdge>) -> Vec<ScoredEdge> {
+        self.rank(self.matches(edges))
+    }
+
+    /// The edges that match the query with their raw relevance. The edges must be in the
+    /// sort order of the graph.
+    pub fn matches(&self, edges: Vec<FullEdge>) -> Vec<LabelMatch> {
+        let mut terms = tokenize(&self.query);
+        terms.sort();
+        terms.dedup();
+
+        let labels: Vec<_> = edges.iter().map(|edge| tokenize(&edge.label)).collect();
+        let num_edges = edges.len().max(1) as f64;
+
+        let mut doc_freqs: HashMap<&str, u64> = HashMap::new();
+        for label in &labels {
+            let mut label_terms: Vec<_> = label.iter().map(String::as_str).collect();
+            label_terms.sort_unstable();
+            label_terms.dedup();
+
+            for term in label_terms {
+                *doc_freqs.entry(term).or_default() += 1;
+            }
+        }
+
+        let total_len: usize = labels.iter().map(Vec::len).sum();
+        let average_len = (total_len as f32 / labels.len().max(1) as f32).max(1.0);
+
+        let weights: Vec<_> = terms
+            .iter()
+            .map(|term| {
+                Bm25Weight::for_one_term(
+                    doc_freqs.get(term.as_str()).copied().unwrap_or_default(),
+                    labels.len() as u64,
+                    average_len,
+                    Bm25Constants::default(),
+                )
+            })
+            .collect();
+
+        let relevances: Vec<f64> = labels
+            .iter()
+            .map(|label| {
+                let fieldnorm_id = FieldNormReader::fieldnorm_to_id(label.len() as u32);
+
+                terms
+                    .iter()
+                    .zip(&weights)
+                    .map(|(term, weight)| {
+                        let term_freq = label.iter().filter(|token| *token == term).count();
+
+                        if term_freq == 0 {
+                            0.0
+                        } else {
+                            weight.score(fieldnorm_id, term_freq as u32) as f64
+                        }
+                    })
+                    .sum()
+            })
+            .collect();
+
+        edges
+            .into_iter()
+            .zip(relevances)
+            .enumerate()
+            .filter(|(_, (_, relevance))| *relevance > 0.0)
+            .map(|(position, (edge, relevance))| LabelMatch {
+                edge,
+                relevance,
+                position: 1.0 - position as f64 / num_edges,
+            })
+            .collect()
+    }
+
+    /// Score the matches, best first. The relevances are normalized by the most relevant of
+    /// the matches, so the matches from all the shards of a graph must be ranked together.
+    pub fn rank(&self, matches: Vec<LabelMatch>) -> Vec<ScoredEdge> {
+        let max_relevance = matches.iter().map(|m| m.relevance).fold(0.0, f64::max);
+
+        let mut scored: Vec<_> = matches
+            .into_iter()
+            .map(|m| {
+                let score = match self.scoring {
+                    LabelScoring::SortKey => m.position,
+                    LabelScoring::Relevance { sort_key_weight } => {
+                        let sort_key_weight = sort_key_weight.clamp(0.0, 1.0);
+                        (1.0 - sort_key_weight) * m.relevance / max_relevance
+                            + sort_key_weight * m.position
+                    }
+                };
+
+                ScoredEdge {
+                    edge: m.edge,
+                    score,
+                }
+            })
+            .collect();
+
+        scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+        scored.truncate(self.limit);
+
+        scored
+    }
+}
+
+impl Webgraph {
+    /// The ingoing edges of the node whose label matches the query.
+    pub fn search_ingoing_labels(
+        &self,
+        node: Node,
+        query: &LabelSearchQuery,
+        cap: EdgeQueryCap,
+    ) -> CappedEdges<ScoredEdge> {
+        let edges = self.ingoing_edges_capped(node, EdgeLimit::Unlimited, cap);
+
+        CappedEdges {
+            edges: query.score(edges.edges),
+            truncated: edges.truncated,
+        }
+    }
+
+    /// Like [`Webgraph::search_ingoing_labels`], but the matches are not ranked yet so
+    /// they can be ranked together with the matches of the other shards.
+    pub fn match_ingoing_labels(
+        &self,
+        node: Node,
+        query: &LabelSearchQuery,
+        cap: EdgeQueryCap,
+    ) -> CappedEdges<LabelMatch> {
+        let edges = self.ingoing_edges_capped(node, EdgeLimit::Unlimited, cap);
+
+        CappedEdges {
+            edges: query.matches(edges.edges),
+            truncated: edges.truncated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_fixtures::GraphFixture;
+
+    use super::*;
+
+    fn graph() -> Webgraph {
+        GraphFixture::new(0)
+            .edges(
+                [
+                    ("a.com", "the rust programming language"),
+                    (
+                        "b.com",
+                        "rust compiler: compiling rust with the rust compiler",
+                    ),
+                    ("c.com", "click here"),
+                    (
+                        "d.com",
+                        "a long list of links about rust and many other things",
+                    ),
+                ]
+                .into_iter()
+                .map(|(from, label)| {
+                    (
+                        Node::from(from),
+                        Node::from("rust-lang.org"),
+                        label.to_string(),
+                    )
+                }),
+            )
+            .build()
+            .into_graph()
+    }
+
+    fn search(graph: &Webgraph, query: &str, scoring: LabelScoring) -> Vec<ScoredEdge> {
+        graph
+            .search_ingoing_labels(
+                Node::from("rust-lang.org"),
+                &LabelSearchQuery {
+                    query: query.to_string(),
+                    limit: 10,
+                    scoring,
+                },
+                EdgeQueryCap::unbounded(),
+            )
+            .edges
+    }
+
+    fn hosts(edges: &[ScoredEdge]) -> Vec<&str> {
+        edges.iter().map(|edge| edge.edge.from.as_str()).collect()
+    }
+
+    #[test]
+    fn strong_match_outranks_weak_match() {
+        let graph = graph();
+
+        let edges = search(
+            &graph,
+            "Rust compiler",
+            LabelScoring::Relevance {
+                sort_key_weight: 0.0,
+            },
+        );
+
+        assert_eq!(edges.len(), 3);
+        assert_eq!(edges[0].edge.from.as_str(), "b.com");
+        assert_eq!(edges[0].score, 1.0);
+        assert!(edges[1].score > edges[2].score);
+
+        // the short label outranks the long one with the same term frequency
+        assert_eq!(hosts(&edges[1..]), vec!["a.com", "d.com"]);
+        assert!(!hosts(&edges).contains(&"c.com"));
+    }
+
+    #[test]
+    fn sort_key_order() {
+        let graph = graph();
+
+        let expected: Vec<_> = graph
+            .ingoing_edges(Node::from("rust-lang.org"), EdgeLimit::Unlimited)
+            .into_iter()
+            .filter(|edge| edge.label.contains("rust"))
+            .map(|edge| edge.from.as_str().to_string())
+            .collect();
+
+        let edges = search(&graph, "rust", LabelScoring::SortKey);
+        assert_eq!(hosts(&edges), expected);
+
+        let edges = search(
+            &graph,
+            "rust",
+            LabelScoring::Relevance {
+                sort_key_weight: 1.0,
+            },
+        );
+        assert_eq!(hosts(&edges), expected);
+
+        assert!(search(&graph, "", LabelScoring::default()).is_empty());
+    }
+
+    #[test]
+    fn matches_are_normalized_after_merge() {
+        let graph = graph();
+        let query = LabelSearchQuery {
+            query: "rust compiler".to_string(),
+            limit: 10,
+            scoring: LabelScoring::Relevance {
+                sort_key_weight: 0.0,
+            },
+        };
+
+        let matches = graph
+            .match_ingoing_labels(
+                Node::from("rust-lang.org"),
+                &query,
+                EdgeQueryCap::unbounded(),
+            )
+            .edges;
+        let (strong, weak): (Vec<_>, Vec<_>) = matches
+            .into_iter()
+            .partition(|m| m.edge.from.as_str() == "b.com");
+
+        // ranked on their own, the weak matches of a shard would get the top score
+        let weak_alone = query.rank(weak.clone());
+        assert_eq!(weak_alone[0].score, 1.0);
+
+        let merged = query.rank(strong.into_iter().chain(weak).collect());
+        assert_eq!(merged[0].edge.from.as_str(), "b.com");
+        assert_eq!(merged[0].score, 1.0);
+        assert!(merged[1..].iter().all(|edge| edge.score < 1.0));
+    }
+}
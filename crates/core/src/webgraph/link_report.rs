@@ -0,0 +1,417 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The backlinks of a host in the page graph grouped by the linking host.
+//!
+//! Besides the number of links, every group keeps the most common anchor texts and a
+//! histogram of the rel flags of its links. The anchors are counted by the hash of the
+//! normalized text with a space-saving summary, so the memory of a group is bounded no
+//! matter how many distinct anchors link from the host. The text of an anchor is only
+//! kept for the hashes that are tracked.
+
+use std::collections::HashMap;
+
+use utoipa::ToSchema;
+
+use crate::webpage::html::links::RelFlags;
+
+use super::{EdgeLimit, EdgeQueryCap, Node, Webgraph};
+
+/// Number of distinct anchors tracked for each linking host.
+const MAX_TRACKED_ANCHORS: usize = 16;
+
+/// Number of anchors reported for each linking host.
+const NUM_ANCHORS: usize = 5;
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchorCount {
+    pub text: String,
+    /// With more distinct anchors than are tracked this is an upper bound.
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+struct TrackedAnchor {
+    hash: u64,
+    count: u64,
+    text: String,
+}
+
+/// The most common anchors of a group, counted with the space-saving algorithm:
+/// an untracked anchor replaces the least common tracked anchor and inherits its count.
+#[derive(Debug, Clone, Default, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+struct TopAnchors {
+    anchors: Vec<TrackedAnchor>,
+}
+
+impl TopAnchors {
+    fn insert(&mut self, text: &str, count: u64) {
+        let text = text.trim();
+
+        if text.is_empty() {
+            return;
+        }
+
+        let hash = bloom::fast_stable_hash_64(text.to_lowercase().as_bytes());
+
+        if let Some(anchor) = self.anchors.iter_mut().find(|anchor| anchor.hash == hash) {
+            anchor.count += count;
+            return;
+        }
+
+        if self.anchors.len() < MAX_TRACKED_ANCHORS {
+            self.anchors.push(TrackedAnchor {
+                hash,
+                count,
+                text: text.to_string(),
+            });
+            return;
+        }
+
+        if let Some(min) = self.anchors.iter_mut().min_by_key(|anchor| anchor.count) {
+            *min = TrackedAnchor {
+                hash,
+                count: min.count + count,
+                text: text.to_string(),
+            };
+        }
+    }
+
+    fn merge(&mut self, other: TopAnchors) {
+        for anchor in other.anchors {
+            self.insert(&anchor.text, anchor.count);
+        }
+    }
+
+    fn top(&self) -> Vec<AnchorCount> {
+        let mut anchors = self.anchors.clone();
+        anchors.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.text.cmp(&b.text)));
+
+        anchors
+            .into_iter()
+            .take(NUM_ANCHORS)
+            .map(|anchor| AnchorCount {
+                text: anchor.text,
+                count: anchor.count,
+            })
+            .collect()
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct RelHistogram {
+    pub total: u64,
+    pub nofollow: u64,
+    pub sponsored: u64,
+    pub in_footer: u64,
+    pub in_navigation: u64,
+}
+
+impl RelHistogram {
+    fn insert(&mut self, rel: RelFlags) {
+        self.total += 1;
+
+        for (flag, count) in [
+            (RelFlags::NOFOLLOW, &mut self.nofollow),
+            (RelFlags::SPONSORED, &mut self.sponsored),
+            (RelFlags::IS_IN_FOOTER, &mut self.in_footer),
+            (RelFlags::IS_IN_NAVIGATION, &mut self.in_navigation),
+        ] {
+            if rel.contains(flag) {
+                *count += 1;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: RelHistogram) {
+        self.total += other.total;
+        self.nofollow += other.nofollow;
+        self.sponsored += other.sponsored;
+        self.in_footer += other.in_footer;
+        self.in_navigation += other.in_navigation;
+    }
+
+    pub fn nofollow_fraction(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.nofollow as f64 / self.total as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+struct GroupAggregate {
+    anchors: TopAnchors,
+    rel: RelHistogram,
+}
+
+/// The aggregates of every linking host. This is what the webgraph servers return,
+/// so the aggregates of the shards can be merged before they are reported.
+#[derive(Debug, Clone, Default, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct LinkAggregates {
+    groups: HashMap<String, GroupAggregate>,
+    truncated: bool,
+}
+
+impl LinkAggregates {
+    fn insert(&mut self, from_host: String, label: &str, rel: RelFlags) {
+        let group = self.groups.entry(from_host).or_default();
+        group.anchors.insert(label, 1);
+        group.rel.insert(rel);
+    }
+
+    pub fn merge(&mut self, other: LinkAggregates) {
+        for (host, aggregate) in other.groups {
+            let group = self.groups.entry(host).or_default();
+            group.anchors.merge(aggregate.anchors);
+            group.rel.merge(aggregate.rel);
+        }
+
+        self.truncated |= other.truncated;
+    }
+
+    pub fn report(self, host: String) -> LinkReport {
+        let mut groups: Vec<_> = self
+            .groups
+            .into_iter()
+            .map(|(from_host, aggregate)| LinkingHostGroup {
+                host: from_host,
+                num_links: aggregate.rel.total,
+                nofollow_fraction: aggregate.rel.nofollow_fraction(),
+                anchors: aggregate.anchors.top(),
+                rel: aggregate.rel,
+            })
+            .collect();
+
+        groups.sort_by(|a, b| {
+            b.num_links
+                .cmp(&a.num_links)
+                .then_with(|| a.host.cmp(&b.host))
+        });
+
+        LinkReport {
+            host,
+            groups,
+            truncated: self.truncated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkingHostGroup {
+    pub host: String,
+    pub num_links: u64,
+    pub nofollow_fraction: f64,
+    pub anchors: Vec<AnchorCount>,
+    pub rel: RelHistogram,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkReport {
+    pub host: String,
+    /// Sorted by the number of links, most links first.
+    pub groups: Vec<LinkingHostGroup>,
+    /// Some pages hit the edge cap, so there may be more links than reported.
+    pub truncated: bool,
+}
+
+impl Webgraph {
+    /// Aggregate the links from other hosts to the pages of the host.
+    /// This is meant for the page graph, as the host graph has no anchor texts.
+    pub fn link_aggregates(&self, host: &Node, cap: EdgeQueryCap) -> LinkAggregates {
+        let host = host.clone().into_host();
+        let mut aggregates = LinkAggregates::default();
+
+        for page in self.pages_by_host(&host.id()) {
+            let edges = self.raw_ingoing_edges_with_labels_capped(&page, EdgeLimit::Unlimited, cap);
+            aggregates.truncated |= edges.truncated;
+
+            for edge in edges
+                .edges
+                .into_iter()
+                .filter(|edge| !edge.rel.contains(RelFlags::REDIRECT))
+            {
+                let from_host = match self.id2node(&edge.from) {
+                    Some(node) => node.into_host(),
+                    None => continue,
+                };
+
+                if from_host == host {
+                    continue;
+                }
+
+                aggregates.insert(from_host.as_str().to_string(), &edge.label, edge.rel);
+            }
+        }
+
+        aggregates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        executor::Executor,
+        webgraph::{Compression, WebgraphWriter},
+    };
+
+    use super::*;
+
+    #[test]
+    fn grouped_anchors_and_rel_flags() {
+        let mut writer = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        );
+
+        for (from, to, label, rel) in [
+            ("a.com/1", "x.com/1", "Example", RelFlags::default()),
+            ("a.com/2", "x.com/1", "example", RelFlags::NOFOLLOW),
+            ("a.com/3", "x.com/2", "docs", RelFlags::default()),
+            ("a.com/4", "x.com/2", "Example", RelFlags::IS_IN_FOOTER),
+            (
+                "b.com/1",
+                "x.com/1",
+                "sponsor",
+                RelFlags::NOFOLLOW | RelFlags::SPONSORED,
+            ),
+            ("b.com/2", "x.com/2", "sponsor", RelFlags::NOFOLLOW),
+            ("x.com/3", "x.com/1", "home", RelFlags::IS_IN_NAVIGATION),
+        ] {
+            writer.insert(Node::from(from), Node::from(to), label.to_string(), rel);
+        }
+
+        writer.commit();
+        let graph = writer.finalize();
+
+        let report = graph
+            .link_aggregates(&Node::from("x.com"), EdgeQueryCap::unbounded())
+            .report("x.com".to_string());
+
+        assert!(!report.truncated);
+        assert_eq!(report.groups.len(), 2);
+
+        let a = &report.groups[0];
+        assert_eq!(a.host, "a.com");
+        assert_eq!(a.num_links, 4);
+        assert_eq!(
+            a.anchors,
+            vec![
+                AnchorCount {
+                    text: "Example".to_string(),
+                    count: 3
+                },
+                AnchorCount {
+                    text: "docs".to_string(),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(
+            a.rel,
+            RelHistogram {
+                total: 4,
+                nofollow: 1,
+                sponsored: 0,
+                in_footer: 1,
+                in_navigation: 0,
+            }
+        );
+        assert_eq!(a.nofollow_fraction, 0.25);
+
+        let b = &report.groups[1];
+        assert_eq!(b.host, "b.com");
+        assert_eq!(b.num_links, 2);
+        assert_eq!(
+            b.anchors,
+            vec![AnchorCount {
+                text: "sponsor".to_string(),
+                count: 2
+            }]
+        );
+        assert_eq!(b.rel.sponsored, 1);
+        assert_eq!(b.nofollow_fraction, 1.0);
+    }
+
+    #[test]
+    fn bounded_anchors() {
+        let mut anchors = TopAnchors::default();
+
+        for i in 0..1_000 {
+            anchors.insert(&format!("anchor {i}"), 1);
+            anchors.insert("common", 1);
+        }
+
+        assert_eq!(anchors.anchors.len(), MAX_TRACKED_ANCHORS);
+        assert_eq!(
+            anchors.top()[0],
+            AnchorCount {
+                text: "common".to_string(),
+                count: 1_000
+            }
+        );
+    }
+
+    #[test]
+    fn merge_shards() {
+        let mut a = LinkAggregates::default();
+        a.insert("a.com".to_string(), "docs", RelFlags::default());
+
+        let mut b = LinkAggregates::default();
+        b.insert("a.com".to_string(), "docs", RelFlags::NOFOLLOW);
+        b.insert("b.com".to_string(), "blog", RelFlags::default());
+        b.truncated = true;
+
+        a.merge(b);
+        let report = a.report("x.com".to_string());
+
+        assert!(report.truncated);
+        assert_eq!(report.groups[0].host, "a.com");
+        assert_eq!(report.groups[0].num_links, 2);
+        assert_eq!(report.groups[0].anchors[0].count, 2);
+        assert_eq!(report.groups[0].rel.nofollow, 1);
+        assert_eq!(report.groups[1].host, "b.com");
+    }
+}
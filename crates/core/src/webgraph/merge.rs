@@ -171,16 +171,33 @@ impl<'a, L> EdgeMerger<'a, L> {
     }
 }
 
-impl<'a, L> Iterator for EdgeMerger<'a, L> {
+/// The labels of the edges that the [`EdgeMerger`] merges.
+pub trait MergeLabel: Sized {
+    /// Combine a copy of the edge from another segment into the edge.
+    /// The merger already keeps the earliest time of discovery of the copies.
+    fn combine(_edge: &mut StoredEdge<Self>, _duplicate: StoredEdge<Self>) {}
+}
+
+impl MergeLabel for () {}
+
+impl MergeLabel for String {}
+
+impl<'a, L> Iterator for EdgeMerger<'a, L>
+where
+    L: MergeLabel,
+{
     type Item = StoredEdge<L>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let res = self.iters.peek_mut().and_then(|mut item| item.0.next());
+        let mut res = self.iters.peek_mut().and_then(|mut item| item.0.next());
 
-        if let Some(edge) = &res {
+        if let Some(edge) = &mut res {
             while let Some(mut peek) = self.iters.peek_mut() {
                 if peek.0.peek().map(|x| x.other.id) == Some(edge.other.id) {
-                    peek.0.next().unwrap();
+                    let duplicate = peek.0.next().unwrap();
+                    edge.discovered_at =
+                        earliest_discovery(edge.discovered_at, duplicate.discovered_at);
+                    L::combine(edge, duplicate);
                 } else {
                     break;
                 }
@@ -193,6 +210,33 @@ impl<'a, L> Iterator for EdgeMerger<'a, L> {
     }
 }
 
+/// The earliest of two discovery timestamps where 0 (unknown) is ignored.
+pub fn earliest_discovery(a: u64, b: u64) -> u64 {
+    match (a, b) {
+        (0, b) => b,
+        (a, 0) => a,
+        (a, b) => a.min(b),
+    }
+}
+
+/// The order of the nodes in the results of the edge queries. The nodes with the lowest sort
+/// key (the most central ones) come first and ties are broken by the id of the node, so the
+/// results are in the same order on every run and can safely be paginated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SortKey {
+    sort_key: u64,
+    id: NodeID,
+}
+
+impl From<&NodeDatum> for SortKey {
+    fn from(datum: &NodeDatum) -> Self {
+        Self {
+            sort_key: datum.sort_key,
+            id: datum.id,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct NodeDatum {
     id: NodeID,
@@ -207,9 +251,7 @@ impl PartialOrd for NodeDatum {
 
 impl Ord for NodeDatum {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.sort_key
-            .cmp(&other.sort_key)
-            .then_with(|| self.id.cmp(&other.id))
+        SortKey::from(self).cmp(&SortKey::from(other))
     }
 }
 
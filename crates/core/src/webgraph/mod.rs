@@ -13,41 +13,74 @@
 //
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 use std::{fs, io};
 
 use itertools::Itertools;
+use rand::rngs::StdRng;
 use rand::seq::{IteratorRandom, SliceRandom};
+use rand::SeedableRng;
 use rayon::prelude::*;
 use uuid::Uuid;
 
+use self::buffer_pool::{Pooled, PooledBuffer};
 use self::id_node_db::Id2NodeDb;
 use self::segment::Segment;
+use crate::config::EdgeBufferPoolConfig;
 use crate::executor::Executor;
 
 use crate::Result;
 pub use builder::WebgraphBuilder;
+pub use centrality_filter::{CentralityQuantiles, MinCentralityFilter, RankQuantileEstimator};
+pub use co_links::{CoLinkCounts, CoLinkLimits, CoLinkedHost};
 pub use compression::Compression;
+pub use disavow::{DisavowFilter, DisavowMode};
 pub use edge::*;
+pub use edge_export::{read_edges, EdgeExportFormat, ExportedEdge};
+pub use label_search::{LabelMatch, LabelScoring, LabelSearchQuery, ScoredEdge};
+pub use link_report::{AnchorCount, LinkAggregates, LinkReport, LinkingHostGroup, RelHistogram};
+pub use merge::SortKey;
+pub use neighborhood::{Neighborhood, NeighborhoodEdge, NeighborhoodNode, RawNeighborhood};
 pub use node::*;
+pub use query_cap::{CappedCount, CappedEdges, EdgeQueryCap, QueryPriority};
+pub use redirects::{Redirects, MAX_REDIRECT_DEPTH};
 pub use shortest_path::ShortestPaths;
+pub use stats::{GraphStats, Stat};
+pub use subdomains::{SubdomainBacklinks, SubdomainBacklinksQuery};
 pub use writer::WebgraphWriter;
 
+mod buffer_pool;
 mod builder;
 pub mod centrality;
+mod centrality_filter;
+mod co_links;
 mod compression;
+mod crawl_times;
+mod disavow;
 mod edge;
+mod edge_export;
 mod id_node_db;
+mod label_search;
+mod link_report;
 mod merge;
+mod neighborhood;
 mod node;
+mod query_cap;
+mod redirects;
 pub mod remote;
 mod segment;
 mod shortest_path;
+mod stats;
 mod store;
 mod store_writer;
+mod subdomains;
+mod subgraph;
 mod writer;
 
 type SegmentID = String;
@@ -55,6 +88,11 @@ type SegmentID = String;
 #[derive(serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode, Default)]
 struct Meta {
     comitted_segments: Vec<SegmentID>,
+    #[serde(default)]
+    centrality_quantiles: Option<CentralityQuantiles>,
+    /// The crawl batches (warc files) whose links are in the graph.
+    #[serde(default)]
+    applied_batches: Vec<String>,
 }
 
 impl Meta {
@@ -95,18 +133,72 @@ impl Meta {
 pub enum EdgeLimit {
     Unlimited,
     Limit(usize),
+    /// Only the edges where the other node is at least as central as the given fraction
+    /// (between 0 and 1) of the other nodes of the edges, so `Percentile(0.9)` keeps
+    /// roughly the 10% of the edges with the lowest sort keys. The threshold is estimated
+    /// with a [`RankQuantileEstimator`] over the collected edges of each shard, and the
+    /// edges tied with the threshold are all kept.
+    Percentile(f64),
 }
 
 impl EdgeLimit {
+    /// A percentile is resolved from the sort keys of the edges, so it reads every edge here
+    /// and is applied to the collected edges with [`EdgeLimit::apply_by_sort_key`].
     pub fn apply<'a, T>(
         &self,
         it: impl Iterator<Item = T> + 'a,
     ) -> Box<dyn Iterator<Item = T> + 'a> {
         match self {
-            EdgeLimit::Unlimited => Box::new(it),
+            EdgeLimit::Unlimited | EdgeLimit::Percentile(_) => Box::new(it),
             EdgeLimit::Limit(limit) => Box::new(it.take(*limit)),
         }
     }
+
+    /// Apply the limit to edges sorted by their sort key. The edges are drained from
+    /// the buffer, so it can be reused.
+    pub fn apply_by_sort_key<'a, T: 'a>(
+        &self,
+        edges: &'a mut Vec<T>,
+        sort_key: impl Fn(&T) -> u64,
+    ) -> Box<dyn Iterator<Item = T> + 'a> {
+        match self {
+            EdgeLimit::Unlimited | EdgeLimit::Limit(_) => self.apply(edges.drain(..)),
+            EdgeLimit::Percentile(percentile) => {
+                let mut estimator = RankQuantileEstimator::new();
+
+                for edge in edges.iter() {
+                    estimator.insert(sort_key(edge));
+                }
+
+                let num_edges = match estimator.rank_threshold(*percentile) {
+                    Some(threshold) => edges.partition_point(|edge| sort_key(edge) <= threshold),
+                    None => 0,
+                };
+
+                Box::new(edges.drain(..num_edges))
+            }
+        }
+    }
+}
+
+/// A half-open window `[start, end)` of discovery timestamps (seconds since the unix epoch).
+/// Edges with an unknown discovery time are never part of a window.
+#[derive(
+    Debug, Clone, Copy, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode,
+)]
+pub struct TimeRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl TimeRange {
+    pub fn new(start: u64, end: u64) -> Self {
+        Self { start, end }
+    }
+
+    pub fn contains(&self, discovered_at: u64) -> bool {
+        discovered_at != 0 && self.start <= discovered_at && discovered_at < self.end
+    }
 }
 
 pub struct Webgraph {
@@ -115,6 +207,7 @@ pub struct Webgraph {
     executor: Arc<Executor>,
     id2node: Id2NodeDb,
     meta: Meta,
+    edge_buffer_pool: EdgeBufferPoolConfig,
 }
 
 impl Webgraph {
@@ -136,7 +229,11 @@ impl Webgraph {
         self.meta.save(path);
     }
 
-    fn open<P: AsRef<Path>>(path: P, executor: Executor) -> Self {
+    fn open<P: AsRef<Path>>(
+        path: P,
+        executor: Executor,
+        edge_buffer_pool: EdgeBufferPoolConfig,
+    ) -> Self {
         fs::create_dir_all(&path).unwrap();
         let meta = Self::meta(&path);
 
@@ -156,6 +253,7 @@ impl Webgraph {
             executor: Arc::new(executor),
             id2node: Id2NodeDb::open(path.as_ref().join("id2node")),
             meta,
+            edge_buffer_pool,
         }
     }
 
@@ -164,6 +262,8 @@ impl Webgraph {
         self.id2node.merge(other.id2node);
         self.id2node.flush();
 
+        self.add_applied_batches(other.meta.applied_batches);
+
         for segment in other.segments {
             let id = segment.id();
             let new_path = Path::new(&self.path).join("segments");
@@ -182,22 +282,70 @@ impl Webgraph {
     }
 
     pub fn merge_all_segments(&mut self, compression: Compression) -> Result<()> {
+        self.merge_segments(compression, false)
+    }
+
+    /// Merge all the segments, where the outgoing edges of the pages that were crawled again
+    /// replace the edges from their earlier crawls. This needs the segments to be written with
+    /// [`WebgraphWriter::with_crawl_times`], and the edges of the other segments are kept
+    /// unless their source was crawled in a segment that tracks the crawl times.
+    pub fn merge_all_segments_superseding(&mut self, compression: Compression) -> Result<()> {
+        self.merge_segments(compression, true)
+    }
+
+    fn merge_segments(&mut self, compression: Compression, supersede: bool) -> Result<()> {
         let segments = std::mem::take(&mut self.segments);
 
         let id = Uuid::new_v4().to_string();
         let path = Path::new(&self.path).join("segments");
 
-        Segment::merge(segments, compression, &path, id.clone())?;
+        if supersede {
+            Segment::merge_superseding(segments, compression, &path, id.clone())?;
+        } else {
+            Segment::merge(segments, compression, &path, id.clone())?;
+        }
         let new_segment = Segment::open(path, id.clone());
 
         self.segments.push(new_segment);
         self.meta.comitted_segments = vec![id];
 
-        self.save_metadata();
+        self.refresh_centrality_quantiles();
 
         Ok(())
     }
 
+    /// The crawl batches whose links are in the graph, in the order they were added.
+    pub fn applied_batches(&self) -> &[String] {
+        &self.meta.applied_batches
+    }
+
+    pub fn add_applied_batches(&mut self, batches: impl IntoIterator<Item = String>) {
+        for batch in batches {
+            if !self.meta.applied_batches.contains(&batch) {
+                self.meta.applied_batches.push(batch);
+            }
+        }
+
+        self.save_metadata();
+    }
+
+    pub fn centrality_quantiles(&self) -> Option<&CentralityQuantiles> {
+        self.meta.centrality_quantiles.as_ref()
+    }
+
+    /// Recompute the quantile table of the host centrality ranks of the linking nodes.
+    /// This must be called after the segments of the graph have changed for the percentiles
+    /// of [`MinCentralityFilter`] to reflect the graph.
+    pub fn refresh_centrality_quantiles(&mut self) {
+        self.meta.centrality_quantiles = CentralityQuantiles::from_ranks(
+            self.segments
+                .iter()
+                .flat_map(|segment| segment.linking_host_ranks()),
+        );
+
+        self.save_metadata();
+    }
+
     pub fn optimize_read(&mut self) {
         self.executor
             .map(|s| s.optimize_read(), self.segments.iter_mut())
@@ -207,23 +355,112 @@ impl Webgraph {
     }
 
     pub fn ingoing_edges(&self, node: Node, limit: EdgeLimit) -> Vec<FullEdge> {
+        self.ingoing_edges_capped(node, limit, EdgeQueryCap::unbounded())
+            .edges
+    }
+
+    pub fn ingoing_edges_capped(
+        &self,
+        node: Node,
+        limit: EdgeLimit,
+        cap: EdgeQueryCap,
+    ) -> CappedEdges<FullEdge> {
         let dedup = |edges: &mut Vec<SegmentEdge<String>>| {
+            edges.retain(|e| !e.is_redirect());
             edges.sort_by_key(|e| e.from.node());
             edges.dedup_by_key(|e| e.from.node());
         };
 
+        let segment_limit = cap.segment_limit(limit);
+        let (mut edges, timed_out) = self.inner_edges_until(
+            |segment, out| segment.ingoing_edges_with_label_into(&node.id(), &segment_limit, out),
+            dedup,
+            cap.deadline(),
+        );
+        edges.sort_by_key(|e| SortKey::from(&e.from));
+
+        let (limit, truncated) = cap.resolve(limit, edges.len(), timed_out);
+
+        CappedEdges {
+            edges: limit
+                .apply_by_sort_key(&mut edges, |e| e.from.sort_key())
+                .map(|e| FullEdge {
+                    from: self.id2node(&e.from.node()).unwrap(),
+                    to: self.id2node(&e.to.node()).unwrap(),
+                    label: e.label,
+                    discovered_at: e.discovered_at,
+                })
+                .collect(),
+            truncated,
+        }
+    }
+
+    /// Ingoing edges where the linking host passes the centrality filter.
+    pub fn ingoing_edges_with_min_centrality(
+        &self,
+        node: Node,
+        filter: MinCentralityFilter,
+        limit: EdgeLimit,
+    ) -> Vec<FullEdge> {
+        self.ingoing_edges_filtered(node, Some(filter), None, None, limit)
+    }
+
+    /// Ingoing edges that pass all the given filters. The filters are applied
+    /// before the limit, so a limited query returns up to `limit` matching edges.
+    pub fn ingoing_edges_filtered(
+        &self,
+        node: Node,
+        min_centrality: Option<MinCentralityFilter>,
+        rel: Option<RelFlagsFilter>,
+        disavow: Option<&DisavowFilter>,
+        limit: EdgeLimit,
+    ) -> Vec<FullEdge> {
+        let max_rank = min_centrality
+            .map(|filter| filter.rank_threshold(self.centrality_quantiles()))
+            .unwrap_or(u64::MAX);
+
+        // the rel flags and linking nodes are only checked after the edges have been read,
+        // so the segments cannot stop early at the limit.
+        let segment_limit = if rel.is_some() || disavow.is_some() {
+            EdgeLimit::Unlimited
+        } else {
+            limit
+        };
+
+        let dedup = |edges: &mut Vec<SegmentEdge<String>>| {
+            edges.retain(|e| !e.is_redirect());
+            edges.sort_by_key(|e| e.from.node());
+            edges.dedup_by_key(|e| e.from.node());
+
+            if let Some(rel) = rel {
+                edges.retain(|e| rel.matches(e.rel));
+            }
+
+            if let Some(disavow) = disavow {
+                edges.retain(|e| disavow.matches(&e.from.node()));
+            }
+        };
+
         let mut edges = self.inner_edges(
-            |segment| segment.ingoing_edges_with_label(&node.id(), &limit),
+            |segment, out| {
+                segment.ingoing_edges_with_label_up_to_into(
+                    &node.id(),
+                    &segment_limit,
+                    max_rank,
+                    out,
+                )
+            },
             dedup,
         );
-        edges.sort_by(|a, b| a.from.sort_key().cmp(&b.from.sort_key()));
+        edges.sort_by_key(|e| SortKey::from(&e.from));
 
         limit
-            .apply(edges.into_iter())
+            .apply_by_sort_key(&mut edges, |e| e.from.sort_key())
             .map(|e| FullEdge {
                 from: self.id2node(&e.from.node()).unwrap(),
                 to: self.id2node(&e.to.node()).unwrap(),
                 label: e.label,
+                discovered_at: e.discovered_at,
             })
             .collect()
     }
@@ -247,50 +484,277 @@ impl Webgraph {
     }
 
     pub fn raw_ingoing_edges(&self, node: &NodeID, limit: EdgeLimit) -> Vec<Edge<()>> {
+        self.raw_ingoing_edges_capped(node, limit, EdgeQueryCap::unbounded())
+            .edges
+    }
+
+    pub fn raw_ingoing_edges_capped(
+        &self,
+        node: &NodeID,
+        limit: EdgeLimit,
+        cap: EdgeQueryCap,
+    ) -> CappedEdges<Edge<()>> {
         let dedup = |edges: &mut Vec<SegmentEdge<()>>| {
             edges.sort_by_key(|e| e.from.node());
             edges.dedup_by_key(|e| e.from.node());
         };
 
-        let mut edges = self.inner_edges(|segment| segment.ingoing_edges(node, &limit), dedup);
-        edges.sort_by(|a, b| a.from.sort_key().cmp(&b.from.sort_key()));
+        let segment_limit = cap.segment_limit(limit);
+        let (mut edges, timed_out) = self.inner_edges_until(
+            |segment, out| segment.ingoing_edges_into(node, &segment_limit, out),
+            dedup,
+            cap.deadline(),
+        );
+        edges.sort_by_key(|e| SortKey::from(&e.from));
+
+        let (limit, truncated) = cap.resolve(limit, edges.len(), timed_out);
+
+        CappedEdges {
+            edges: limit
+                .apply_by_sort_key(&mut edges, |e| e.from.sort_key())
+                .map(|e| Edge {
+                    from: e.from.node(),
+                    to: e.to.node(),
+                    label: e.label,
+                    rel: e.rel,
+                    discovered_at: e.discovered_at,
+                })
+                .collect(),
+            truncated,
+        }
+    }
+
+    /// Ingoing edges that were first discovered within `range`.
+    ///
+    /// The segments only read the edges within the range, up to the limit. An edge that
+    /// another segment discovered before the range is dropped afterwards, so fewer edges
+    /// than the limit may be returned even if there are more in the range.
+    pub fn ingoing_edges_in_range(
+        &self,
+        node: Node,
+        range: TimeRange,
+        limit: EdgeLimit,
+    ) -> Vec<FullEdge> {
+        self.ingoing_edges_in_range_capped(node, range, limit, EdgeQueryCap::unbounded())
+            .edges
+    }
+
+    pub fn ingoing_edges_in_range_capped(
+        &self,
+        node: Node,
+        range: TimeRange,
+        limit: EdgeLimit,
+        cap: EdgeQueryCap,
+    ) -> CappedEdges<FullEdge> {
+        let deadline = cap.deadline();
+        let segment_limit = cap.segment_limit(limit);
+        let (mut edges, timed_out) = self.inner_edges_until(
+            |segment, out| {
+                segment.ingoing_edges_with_label_matching_into(
+                    &node.id(),
+                    &segment_limit,
+                    |e| !e.is_redirect() && range.contains(e.discovered_at),
+                    out,
+                )
+            },
+            Self::dedup_ingoing_in_range,
+            deadline,
+        );
+        let timed_out =
+            self.retain_first_discovered_in_range(&node.id(), range, &mut edges, deadline)
+                || timed_out;
+        edges.sort_by_key(|e| SortKey::from(&e.from));
+
+        let (limit, truncated) = cap.resolve(limit, edges.len(), timed_out);
+
+        CappedEdges {
+            edges: limit
+                .apply_by_sort_key(&mut edges, |e| e.from.sort_key())
+                .map(|e| FullEdge {
+                    from: self.id2node(&e.from.node()).unwrap(),
+                    to: self.id2node(&e.to.node()).unwrap(),
+                    label: e.label,
+                    discovered_at: e.discovered_at,
+                })
+                .collect(),
+            truncated,
+        }
+    }
+
+    pub fn raw_ingoing_edges_in_range(
+        &self,
+        node: &NodeID,
+        range: TimeRange,
+        limit: EdgeLimit,
+    ) -> Vec<Edge<()>> {
+        let mut edges = self.inner_edges(
+            |segment, out| {
+                segment.ingoing_edges_matching_into(
+                    node,
+                    &limit,
+                    |e| range.contains(e.discovered_at),
+                    out,
+                )
+            },
+            Self::dedup_ingoing_in_range,
+        );
+        self.retain_first_discovered_in_range(node, range, &mut edges, None);
+        edges.sort_by_key(|e| SortKey::from(&e.from));
 
         limit
-            .apply(edges.into_iter())
+            .apply_by_sort_key(&mut edges, |e| e.from.sort_key())
             .map(|e| Edge {
                 from: e.from.node(),
                 to: e.to.node(),
                 label: e.label,
                 rel: e.rel,
+                discovered_at: e.discovered_at,
             })
             .collect()
     }
 
+    /// The number of distinct nodes linking to the node.
+    pub fn num_ingoing_edges(&self, node: &NodeID) -> usize {
+        self.inner_edges(
+            |segment, out| segment.ingoing_edges_into(node, &EdgeLimit::Unlimited, out),
+            |edges: &mut Vec<SegmentEdge<()>>| {
+                edges.sort_by_key(|e| e.from.node());
+                edges.dedup_by_key(|e| e.from.node());
+            },
+        )
+        .len()
+    }
+
+    pub fn num_ingoing_edges_in_range(&self, node: &NodeID, range: TimeRange) -> usize {
+        self.num_ingoing_edges_in_range_capped(node, range, EdgeQueryCap::unbounded())
+            .count
+    }
+
+    /// Counts at most the cap of edges, so the count is truncated for nodes with more
+    /// edges in the range.
+    pub fn num_ingoing_edges_in_range_capped(
+        &self,
+        node: &NodeID,
+        range: TimeRange,
+        cap: EdgeQueryCap,
+    ) -> CappedCount {
+        let deadline = cap.deadline();
+        let segment_limit = cap.segment_limit(EdgeLimit::Unlimited);
+        let (mut edges, timed_out) = self.inner_edges_until(
+            |segment, out| {
+                segment.ingoing_edges_matching_into(
+                    node,
+                    &segment_limit,
+                    |e| range.contains(e.discovered_at),
+                    out,
+                )
+            },
+            Self::dedup_ingoing_in_range,
+            deadline,
+        );
+        let timed_out =
+            self.retain_first_discovered_in_range(node, range, &mut edges, deadline) || timed_out;
+
+        let (limit, truncated) = cap.resolve(EdgeLimit::Unlimited, edges.len(), timed_out);
+
+        CappedCount {
+            count: match limit {
+                EdgeLimit::Limit(limit) => edges.len().min(limit),
+                EdgeLimit::Unlimited | EdgeLimit::Percentile(_) => edges.len(),
+            },
+            truncated,
+        }
+    }
+
+    /// Keep the earliest discovery of each linking node within the range.
+    fn dedup_ingoing_in_range<L: EdgeLabel>(edges: &mut Vec<SegmentEdge<L>>) {
+        edges.sort_by_key(|e| (e.from.node(), e.discovered_at));
+        edges.dedup_by_key(|e| e.from.node());
+    }
+
+    /// An edge can be present in multiple segments with different timestamps, and only
+    /// the earliest known discovery counts. Drop the edges within the range that another
+    /// segment discovered before the range. Returns `true` if the deadline was reached
+    /// before all segments were checked.
+    fn retain_first_discovered_in_range<L: EdgeLabel>(
+        &self,
+        node: &NodeID,
+        range: TimeRange,
+        edges: &mut Vec<SegmentEdge<L>>,
+        deadline: Option<Instant>,
+    ) -> bool {
+        if self.segments.len() < 2 || edges.is_empty() {
+            return false;
+        }
+
+        let linking: HashSet<NodeID> = edges.iter().map(|e| e.from.node()).collect();
+
+        let (earlier, timed_out) = self.inner_edges_until(
+            |segment, out| {
+                segment.ingoing_edges_matching_into(
+                    node,
+                    &EdgeLimit::Unlimited,
+                    |e| {
+                        e.discovered_at != 0
+                            && e.discovered_at < range.start
+                            && linking.contains(&e.from.node())
+                    },
+                    out,
+                )
+            },
+            |_| {},
+            deadline,
+        );
+        let earlier: HashSet<NodeID> = earlier.iter().map(|e| e.from.node()).collect();
+
+        edges.retain(|e| !earlier.contains(&e.from.node()));
+
+        timed_out
+    }
+
     pub fn raw_ingoing_edges_with_labels(
         &self,
         node: &NodeID,
         limit: EdgeLimit,
     ) -> Vec<Edge<String>> {
+        self.raw_ingoing_edges_with_labels_capped(node, limit, EdgeQueryCap::unbounded())
+            .edges
+    }
+
+    pub fn raw_ingoing_edges_with_labels_capped(
+        &self,
+        node: &NodeID,
+        limit: EdgeLimit,
+        cap: EdgeQueryCap,
+    ) -> CappedEdges<Edge<String>> {
         let dedup = |edges: &mut Vec<SegmentEdge<String>>| {
             edges.sort_by_key(|e| e.from.node());
             edges.dedup_by_key(|e| e.from.node());
         };
 
-        let mut edges = self.inner_edges(
-            |segment| segment.ingoing_edges_with_label(node, &limit),
+        let segment_limit = cap.segment_limit(limit);
+        let (mut edges, timed_out) = self.inner_edges_until(
+            |segment, out| segment.ingoing_edges_with_label_into(node, &segment_limit, out),
             dedup,
+            cap.deadline(),
         );
-        edges.sort_by(|a, b| a.from.sort_key().cmp(&b.from.sort_key()));
-
-        limit
-            .apply(edges.into_iter())
-            .map(|e| Edge {
-                from: e.from.node(),
-                to: e.to.node(),
-                label: e.label,
-                rel: e.rel,
-            })
-            .collect()
+        edges.sort_by_key(|e| SortKey::from(&e.from));
+
+        let (limit, truncated) = cap.resolve(limit, edges.len(), timed_out);
+
+        CappedEdges {
+            edges: limit
+                .apply_by_sort_key(&mut edges, |e| e.from.sort_key())
+                .map(|e| Edge {
+                    from: e.from.node(),
+                    to: e.to.node(),
+                    label: e.label,
+                    rel: e.rel,
+                    discovered_at: e.discovered_at,
+                })
+                .collect(),
+            truncated,
+        }
     }
 
     pub fn raw_outgoing_edges_with_labels(
@@ -298,100 +762,212 @@ impl Webgraph {
         node: &NodeID,
         limit: EdgeLimit,
     ) -> Vec<Edge<String>> {
+        self.raw_outgoing_edges_with_labels_capped(node, limit, EdgeQueryCap::unbounded())
+            .edges
+    }
+
+    pub fn raw_outgoing_edges_with_labels_capped(
+        &self,
+        node: &NodeID,
+        limit: EdgeLimit,
+        cap: EdgeQueryCap,
+    ) -> CappedEdges<Edge<String>> {
         let dedup = |edges: &mut Vec<SegmentEdge<String>>| {
             edges.sort_by_key(|e| e.to.node());
             edges.dedup_by_key(|e| e.to.node());
         };
 
-        let mut edges = self.inner_edges(
-            |segment| segment.outgoing_edges_with_label(node, &limit),
+        let segment_limit = cap.segment_limit(limit);
+        let (mut edges, timed_out) = self.inner_edges_until(
+            |segment, out| segment.outgoing_edges_with_label_into(node, &segment_limit, out),
             dedup,
+            cap.deadline(),
         );
 
-        edges.sort_by(|a, b| a.to.sort_key().cmp(&b.to.sort_key()));
-
-        limit
-            .apply(edges.into_iter())
-            .map(|e| Edge {
-                from: e.from.node(),
-                to: e.to.node(),
-                label: e.label,
-                rel: e.rel,
-            })
-            .collect()
+        edges.sort_by_key(|e| SortKey::from(&e.to));
+
+        let (limit, truncated) = cap.resolve(limit, edges.len(), timed_out);
+
+        CappedEdges {
+            edges: limit
+                .apply_by_sort_key(&mut edges, |e| e.to.sort_key())
+                .map(|e| Edge {
+                    from: e.from.node(),
+                    to: e.to.node(),
+                    label: e.label,
+                    rel: e.rel,
+                    discovered_at: e.discovered_at,
+                })
+                .collect(),
+            truncated,
+        }
     }
 
     pub fn outgoing_edges(&self, node: Node, limit: EdgeLimit) -> Vec<FullEdge> {
+        self.outgoing_edges_capped(node, limit, EdgeQueryCap::unbounded())
+            .edges
+    }
+
+    pub fn outgoing_edges_capped(
+        &self,
+        node: Node,
+        limit: EdgeLimit,
+        cap: EdgeQueryCap,
+    ) -> CappedEdges<FullEdge> {
         let dedup = |edges: &mut Vec<SegmentEdge<String>>| {
+            edges.retain(|e| !e.is_redirect());
             edges.sort_by_key(|e| e.to.node());
             edges.dedup_by_key(|e| e.to.node());
         };
 
-        let mut edges = self.inner_edges(
-            |segment| segment.outgoing_edges_with_label(&node.id(), &limit),
+        let segment_limit = cap.segment_limit(limit);
+        let (mut edges, timed_out) = self.inner_edges_until(
+            |segment, out| segment.outgoing_edges_with_label_into(&node.id(), &segment_limit, out),
             dedup,
+            cap.deadline(),
         );
-        edges.sort_by(|a, b| a.to.sort_key().cmp(&b.to.sort_key()));
-
-        limit
-            .apply(edges.into_iter())
-            .map(|e| FullEdge {
-                from: self.id2node(&e.from.node()).unwrap(),
-                to: self.id2node(&e.to.node()).unwrap(),
-                label: e.label,
-            })
-            .collect()
+        edges.sort_by_key(|e| SortKey::from(&e.to));
+
+        let (limit, truncated) = cap.resolve(limit, edges.len(), timed_out);
+
+        CappedEdges {
+            edges: limit
+                .apply_by_sort_key(&mut edges, |e| e.to.sort_key())
+                .map(|e| FullEdge {
+                    from: self.id2node(&e.from.node()).unwrap(),
+                    to: self.id2node(&e.to.node()).unwrap(),
+                    label: e.label,
+                    discovered_at: e.discovered_at,
+                })
+                .collect(),
+            truncated,
+        }
     }
 
     pub fn raw_outgoing_edges(&self, node: &NodeID, limit: EdgeLimit) -> Vec<Edge<()>> {
+        self.raw_outgoing_edges_capped(node, limit, EdgeQueryCap::unbounded())
+            .edges
+    }
+
+    pub fn raw_outgoing_edges_capped(
+        &self,
+        node: &NodeID,
+        limit: EdgeLimit,
+        cap: EdgeQueryCap,
+    ) -> CappedEdges<Edge<()>> {
         let dedup = |edges: &mut Vec<SegmentEdge<()>>| {
             edges.sort_by_key(|e| e.to.node());
             edges.dedup_by_key(|e| e.to.node());
         };
 
-        let mut edges = self.inner_edges(|segment| segment.outgoing_edges(node, &limit), dedup);
-        edges.sort_by(|a, b| a.to.sort_key().cmp(&b.to.sort_key()));
+        let segment_limit = cap.segment_limit(limit);
+        let (mut edges, timed_out) = self.inner_edges_until(
+            |segment, out| segment.outgoing_edges_into(node, &segment_limit, out),
+            dedup,
+            cap.deadline(),
+        );
+        edges.sort_by_key(|e| SortKey::from(&e.to));
+
+        let (limit, truncated) = cap.resolve(limit, edges.len(), timed_out);
+
+        CappedEdges {
+            edges: limit
+                .apply_by_sort_key(&mut edges, |e| e.to.sort_key())
+                .map(|e| Edge {
+                    from: e.from.node(),
+                    to: e.to.node(),
+                    label: e.label,
+                    rel: e.rel,
+                    discovered_at: e.discovered_at,
+                })
+                .collect(),
+            truncated,
+        }
+    }
 
-        limit
-            .apply(edges.into_iter())
-            .map(|e| Edge {
-                from: e.from.node(),
-                to: e.to.node(),
-                label: e.label,
-                rel: e.rel,
-            })
-            .collect()
+    fn inner_edges<F1, F2, L>(&self, loader: F1, dedup: F2) -> PooledBuffer<SegmentEdge<L>>
+    where
+        L: EdgeLabel,
+        SegmentEdge<L>: Pooled,
+        F1: Sized + Sync + Fn(&Segment, &mut Vec<SegmentEdge<L>>),
+        F2: Fn(&mut Vec<SegmentEdge<L>>),
+    {
+        self.inner_edges_until(loader, dedup, None).0
     }
 
-    fn inner_edges<F1, F2, L>(&self, loader: F1, dedup: F2) -> Vec<SegmentEdge<L>>
+    /// Collect the edges from all segments. Segments that have not been read
+    /// when the deadline is reached are skipped, in which case `true` is returned
+    /// along with the edges.
+    ///
+    /// The edges of each segment are loaded into a buffer from the pool of the calling
+    /// thread, so the buffers are returned to the same pool they were taken from.
+    fn inner_edges_until<F1, F2, L>(
+        &self,
+        loader: F1,
+        dedup: F2,
+        deadline: Option<Instant>,
+    ) -> (PooledBuffer<SegmentEdge<L>>, bool)
     where
         L: EdgeLabel,
-        F1: Sized + Sync + Fn(&Segment) -> Vec<SegmentEdge<L>>,
+        SegmentEdge<L>: Pooled,
+        F1: Sized + Sync + Fn(&Segment, &mut Vec<SegmentEdge<L>>),
         F2: Fn(&mut Vec<SegmentEdge<L>>),
     {
-        let mut edges: Vec<_> = self
-            .executor
-            .map(loader, self.segments.iter())
-            .unwrap()
-            .into_iter()
-            .flatten()
+        let timed_out = AtomicBool::new(false);
+
+        // the pool hands out the buffers it got back last first, so taking them in the
+        // reverse order of how they are returned gives every segment the buffer it had
+        // in the previous query
+        let mut edges = PooledBuffer::new(self.edge_buffer_pool);
+        let mut segment_edges: Vec<PooledBuffer<SegmentEdge<L>>> = self
+            .segments
+            .iter()
+            .map(|_| PooledBuffer::new(self.edge_buffer_pool))
             .collect();
+        segment_edges.reverse();
+
+        self.executor
+            .map(
+                |(segment, out): (&Segment, &mut PooledBuffer<SegmentEdge<L>>)| {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        timed_out.store(true, Ordering::Relaxed);
+                        return;
+                    }
+
+                    loader(segment, out)
+                },
+                self.segments.iter().zip(segment_edges.iter_mut()),
+            )
+            .unwrap();
+
+        edges.reserve(segment_edges.iter().map(|buf| buf.len()).sum());
+
+        for buf in &mut segment_edges {
+            edges.append(buf);
+        }
 
-        dedup(&mut edges);
+        dedup(&mut *edges);
 
-        edges
+        (edges, timed_out.into_inner())
     }
 
     pub fn id2node(&self, id: &NodeID) -> Option<Node> {
         self.id2node.get(id)
     }
 
+    /// The number of nodes that were not found because the name stored
+    /// for the id has another id.
+    pub fn num_id2node_mismatches(&self) -> u64 {
+        self.id2node.num_mismatches()
+    }
+
     pub fn nodes(&self) -> impl Iterator<Item = NodeID> + '_ {
         self.id2node.keys()
     }
 
-    pub fn random_nodes_with_outgoing(&self, num: usize) -> Vec<NodeID> {
-        let mut rng = rand::thread_rng();
+    /// Sample `num` nodes with outgoing edges. The same seed gives the same nodes.
+    pub fn random_nodes_with_outgoing(&self, num: usize, seed: u64) -> Vec<NodeID> {
+        let mut rng = StdRng::seed_from_u64(seed);
         let mut nodes = self
             .edges()
             .map(|e| e.from)
@@ -413,6 +989,18 @@ impl Webgraph {
         self.id2node.estimate_num_keys()
     }
 
+    /// Graph level statistics computed from the edge ranges of the segments.
+    /// See [`GraphStats`] for which figures are exact.
+    pub fn stats(&self) -> GraphStats {
+        let segments: Vec<_> = self
+            .segments
+            .iter()
+            .map(|segment| segment.stats())
+            .collect();
+
+        GraphStats::new(self.estimate_num_nodes() as u64, &segments)
+    }
+
     /// Iterate all edges in the graph at least once.
     /// Some edges may be returned multiple times.
     /// This happens if they are present in more than one segment.
@@ -427,11 +1015,18 @@ impl Webgraph {
             .par_iter()
             .flat_map(|segment| segment.edges().par_bridge().map(|e| e.into()))
     }
+
+    /// Like [`Webgraph::par_edges`], but with the labels and sort keys of the edges.
+    pub fn par_edges_with_label(&self) -> impl ParallelIterator<Item = SegmentEdge<String>> + '_ {
+        self.segments
+            .par_iter()
+            .flat_map(|segment| segment.edges_with_label().par_bridge())
+    }
 }
 
 #[cfg(test)]
 pub mod tests {
-    use crate::webpage::html::links::RelFlags;
+    use crate::{test_fixtures::GraphFixture, webpage::html::links::RelFlags};
 
     use super::*;
 
@@ -457,20 +1052,109 @@ pub mod tests {
         //        │
         //        D
 
-        let mut graph = WebgraphWriter::new(
+        GraphFixture::new(0)
+            .edges(test_edges())
+            .build()
+            .into_graph()
+    }
+
+    #[test]
+    fn stats() {
+        let graph = test_graph();
+        let stats = graph.stats();
+
+        assert_eq!(stats.num_segments, 1);
+        assert_eq!(stats.num_nodes.value(), 4);
+        assert_eq!(stats.num_edges, Stat::Exact(5));
+        assert_eq!(stats.max_out_degree, Stat::Exact(2));
+        assert_eq!(stats.max_in_degree, Stat::Exact(3));
+        assert_eq!(stats.avg_degree.value(), 1.25);
+        assert_eq!(stats.density.value(), 5.0 / 12.0);
+
+        let empty = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        )
+        .finalize();
+        let stats = empty.stats();
+
+        assert_eq!(stats.num_nodes.value(), 0);
+        assert_eq!(stats.num_edges.value(), 0);
+        assert_eq!(stats.avg_degree.value(), 0.0);
+        assert_eq!(stats.density.value(), 0.0);
+    }
+
+    #[test]
+    fn capped_edges() {
+        let mut writer = WebgraphWriter::new(
             crate::gen_temp_path(),
             Executor::single_thread(),
             Compression::default(),
             None,
         );
 
-        for (from, to, label) in test_edges() {
-            graph.insert(from, to, label, RelFlags::default());
+        for i in 0..100 {
+            writer.insert(
+                Node::from(format!("{i}.com")),
+                Node::from("hub.com"),
+                String::new(),
+                RelFlags::default(),
+            );
         }
 
-        graph.commit();
+        for i in 0..10 {
+            writer.insert(
+                Node::from(format!("{i}.com")),
+                Node::from("small.com"),
+                String::new(),
+                RelFlags::default(),
+            );
+        }
+
+        let graph = writer.finalize();
+        let hub = Node::from("hub.com");
+        let small = Node::from("small.com");
+
+        let cap = EdgeQueryCap {
+            max_edges: 10,
+            timeout: None,
+        };
+
+        let res = graph.ingoing_edges_capped(hub.clone(), EdgeLimit::Unlimited, cap);
+        assert_eq!(res.edges.len(), 10);
+        assert!(res.truncated);
+
+        let res = graph.raw_ingoing_edges_capped(&hub.id(), EdgeLimit::Limit(50), cap);
+        assert_eq!(res.edges.len(), 10);
+        assert!(res.truncated);
+
+        let res = graph.ingoing_edges_capped(hub.clone(), EdgeLimit::Limit(5), cap);
+        assert_eq!(res.edges.len(), 5);
+        assert!(!res.truncated);
+
+        let res = graph.ingoing_edges_capped(small.clone(), EdgeLimit::Unlimited, cap);
+        assert_eq!(res.edges.len(), 10);
+        assert!(!res.truncated);
+        assert_eq!(
+            res.edges,
+            graph.ingoing_edges(small.clone(), EdgeLimit::Unlimited)
+        );
+
+        let res = graph.outgoing_edges_capped(Node::from("0.com"), EdgeLimit::Unlimited, cap);
+        assert_eq!(res.edges.len(), 2);
+        assert!(!res.truncated);
+
+        let expired = EdgeQueryCap {
+            max_edges: 10,
+            timeout: Some(std::time::Duration::ZERO),
+        };
+        let res = graph.ingoing_edges_capped(small, EdgeLimit::Unlimited, expired);
+        assert!(res.edges.is_empty());
+        assert!(res.truncated);
 
-        graph.finalize()
+        assert_eq!(graph.ingoing_edges(hub, EdgeLimit::Unlimited).len(), 100);
     }
 
     #[test]
@@ -787,6 +1471,44 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn configurable_label_length() {
+        let mut writer = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        )
+        .with_max_label_length(8);
+
+        writer.insert(
+            Node::from("A"),
+            Node::from("B"),
+            "æøå long anchor text".to_string(),
+            RelFlags::NOFOLLOW,
+        );
+        writer.insert(
+            Node::from("A"),
+            Node::from("C"),
+            "short".to_string(),
+            RelFlags::default(),
+        );
+
+        let graph = writer.finalize();
+
+        let edges =
+            graph.raw_outgoing_edges_with_labels(&Node::from("A").id(), EdgeLimit::Unlimited);
+        let truncated = edges.iter().find(|e| e.to == Node::from("B").id()).unwrap();
+        let untouched = edges.iter().find(|e| e.to == Node::from("C").id()).unwrap();
+
+        assert_eq!(truncated.label, "æøå long");
+        assert!(truncated.rel.contains(RelFlags::LABEL_TRUNCATED));
+        assert!(truncated.rel.contains(RelFlags::NOFOLLOW));
+
+        assert_eq!(untouched.label, "short");
+        assert!(!untouched.rel.contains(RelFlags::LABEL_TRUNCATED));
+    }
+
     #[test]
     fn test_edge_limits() {
         let graph = test_graph();
@@ -857,6 +1579,77 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn tied_edges_are_ordered_by_node_id() {
+        let sources: Vec<_> = (0..20).map(|i| Node::from(format!("{i}.com"))).collect();
+
+        let graph = |sources: &[Node], commit_every: usize| {
+            let mut writer = WebgraphWriter::new(
+                crate::gen_temp_path(),
+                Executor::single_thread(),
+                Compression::default(),
+                None,
+            );
+
+            for (i, source) in sources.iter().enumerate() {
+                writer.insert(
+                    source.clone(),
+                    Node::from("target.com"),
+                    String::new(),
+                    RelFlags::default(),
+                );
+                writer.insert(
+                    Node::from("target.com"),
+                    source.clone(),
+                    String::new(),
+                    RelFlags::default(),
+                );
+
+                if (i + 1) % commit_every == 0 {
+                    writer.commit();
+                }
+            }
+
+            writer.commit();
+            writer.finalize()
+        };
+
+        let mut reversed = sources.clone();
+        reversed.reverse();
+
+        let graphs = [
+            graph(&sources, sources.len()),
+            graph(&reversed, sources.len()),
+            graph(&reversed, 3),
+        ];
+
+        let mut expected: Vec<_> = sources.iter().map(|node| node.id()).collect();
+        expected.sort();
+
+        for graph in &graphs {
+            let ingoing: Vec<_> = graph
+                .ingoing_edges(Node::from("target.com"), EdgeLimit::Unlimited)
+                .into_iter()
+                .map(|e| e.from.id())
+                .collect();
+            assert_eq!(ingoing, expected);
+
+            let outgoing: Vec<_> = graph
+                .outgoing_edges(Node::from("target.com"), EdgeLimit::Unlimited)
+                .into_iter()
+                .map(|e| e.to.id())
+                .collect();
+            assert_eq!(outgoing, expected);
+
+            let raw: Vec<_> = graph
+                .raw_ingoing_edges(&Node::from("target.com").id(), EdgeLimit::Limit(5))
+                .into_iter()
+                .map(|e| e.from)
+                .collect();
+            assert_eq!(raw, expected[..5]);
+        }
+    }
+
     #[test]
     fn test_node_normalized() {
         let n = Node::from("http://www.example.com/abc");
@@ -866,6 +1659,39 @@ pub mod tests {
         assert_eq!(n.as_str(), "example.com/abc");
     }
 
+    #[test]
+    fn test_node_registrable_domain() {
+        assert_eq!(
+            Node::from("blog.example.com").registrable_domain(),
+            Some(Node::from("example.com"))
+        );
+        assert_eq!(
+            Node::from("https://a.b.example.co.uk/page").registrable_domain(),
+            Some(Node::from("example.co.uk"))
+        );
+        assert_eq!(
+            Node::from("example.co.uk").registrable_domain(),
+            Some(Node::from("example.co.uk"))
+        );
+        assert_eq!(Node::from("co.uk").registrable_domain(), None);
+    }
+
+    #[test]
+    fn test_node_is_subdomain_of() {
+        let domain = Node::from("example.co.uk");
+
+        assert!(Node::from("blog.example.co.uk").is_subdomain_of(&domain));
+        assert!(Node::from("a.b.example.co.uk/page").is_subdomain_of(&domain));
+        assert!(
+            Node::from("a.blog.example.co.uk").is_subdomain_of(&Node::from("blog.example.co.uk"))
+        );
+
+        assert!(!domain.is_subdomain_of(&domain));
+        assert!(!Node::from("notexample.co.uk").is_subdomain_of(&domain));
+        assert!(!Node::from("blog.example.com").is_subdomain_of(&Node::from("example.co.uk")));
+        assert!(!domain.is_subdomain_of(&Node::from("co.uk")));
+    }
+
     #[test]
     fn test_rel_flags() {
         let mut writer = WebgraphWriter::new(
@@ -889,4 +1715,286 @@ pub mod tests {
             RelFlags::IS_IN_FOOTER | RelFlags::TAG,
         );
     }
+
+    #[test]
+    fn test_edges_in_time_range() {
+        let mut writer = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        );
+
+        for (from, discovered_at) in [("A", 100), ("B", 200), ("C", 300)] {
+            writer.insert_with_timestamp(
+                Node::from(from),
+                Node::from("D"),
+                String::new(),
+                RelFlags::default(),
+                discovered_at,
+            );
+        }
+
+        // edges without a timestamp are never part of a window
+        writer.insert(
+            Node::from("E"),
+            Node::from("D"),
+            String::new(),
+            RelFlags::default(),
+        );
+
+        let mut graph = writer.finalize();
+
+        let mut other = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        );
+
+        // rediscovered later in another segment, so the earliest discovery should be kept
+        other.insert_with_timestamp(
+            Node::from("A"),
+            Node::from("D"),
+            String::new(),
+            RelFlags::default(),
+            400,
+        );
+
+        graph.merge(other.finalize()).unwrap();
+
+        let node = Node::from("D").id();
+
+        assert_eq!(
+            graph.raw_ingoing_edges(&node, EdgeLimit::Unlimited).len(),
+            4
+        );
+        assert_eq!(graph.num_ingoing_edges(&node), 4);
+
+        assert_eq!(
+            graph.num_ingoing_edges_in_range(&node, TimeRange::new(0, u64::MAX)),
+            3
+        );
+        assert_eq!(
+            graph.num_ingoing_edges_in_range(&node, TimeRange::new(150, 300)),
+            1
+        );
+        assert_eq!(
+            graph.num_ingoing_edges_in_range(&node, TimeRange::new(350, 500)),
+            0
+        );
+
+        let mut res: Vec<_> = graph
+            .ingoing_edges_in_range(
+                Node::from("D"),
+                TimeRange::new(100, 301),
+                EdgeLimit::Unlimited,
+            )
+            .into_iter()
+            .map(|e| (e.from, e.discovered_at))
+            .collect();
+        res.sort();
+
+        assert_eq!(
+            res,
+            vec![
+                (Node::from("A"), 100),
+                (Node::from("B"), 200),
+                (Node::from("C"), 300)
+            ]
+        );
+
+        assert_eq!(
+            graph
+                .raw_ingoing_edges_in_range(&node, TimeRange::new(100, 301), EdgeLimit::Limit(2))
+                .len(),
+            2
+        );
+        assert!(graph
+            .raw_ingoing_edges_in_range(&node, TimeRange::new(350, 500), EdgeLimit::Unlimited)
+            .is_empty());
+
+        let cap = EdgeQueryCap {
+            max_edges: 2,
+            timeout: None,
+        };
+        let capped = graph.ingoing_edges_in_range_capped(
+            Node::from("D"),
+            TimeRange::new(100, 301),
+            EdgeLimit::Unlimited,
+            cap,
+        );
+        assert_eq!(capped.edges.len(), 2);
+        assert!(capped.truncated);
+        assert_eq!(
+            graph.num_ingoing_edges_in_range_capped(&node, TimeRange::new(100, 301), cap),
+            CappedCount {
+                count: 2,
+                truncated: true
+            }
+        );
+        assert_eq!(
+            graph.num_ingoing_edges_in_range_capped(&node, TimeRange::new(150, 300), cap),
+            CappedCount {
+                count: 1,
+                truncated: false
+            }
+        );
+
+        graph.merge_all_segments(Compression::default()).unwrap();
+
+        let mut res: Vec<_> = graph
+            .raw_ingoing_edges(&node, EdgeLimit::Unlimited)
+            .into_iter()
+            .map(|e| (e.from, e.discovered_at))
+            .collect();
+        res.sort_by_key(|(_, discovered_at)| *discovered_at);
+
+        assert_eq!(
+            res.into_iter().map(|(_, d)| d).collect::<Vec<_>>(),
+            vec![0, 100, 200, 300]
+        );
+    }
+
+    #[test]
+    fn percentile_limit() {
+        let hosts: Vec<_> = (0..5_000)
+            .map(|i| Node::from(format!("h{i}.com")))
+            .collect();
+
+        let mut rank_store =
+            speedy_kv::Db::open_or_create(crate::gen_temp_path().join("rank-store")).unwrap();
+        for (i, host) in hosts.iter().enumerate() {
+            rank_store.insert(host.id(), (i * i) as u64).unwrap();
+        }
+        rank_store.commit().unwrap();
+
+        let mut wrt = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            Some(Arc::new(rank_store)),
+        );
+
+        for host in &hosts {
+            wrt.insert(
+                host.clone(),
+                Node::from("target.com"),
+                String::new(),
+                RelFlags::default(),
+            );
+            wrt.insert(
+                Node::from("source.com"),
+                host.clone(),
+                String::new(),
+                RelFlags::default(),
+            );
+        }
+
+        let graph = wrt.finalize();
+
+        let backlinks = graph.ingoing_edges(Node::from("target.com"), EdgeLimit::Percentile(0.9));
+        let fraction = backlinks.len() as f64 / hosts.len() as f64;
+        assert!((fraction - 0.1).abs() < 0.05, "{fraction}");
+
+        // the most central linking hosts are the ones kept
+        let mut kept: Vec<_> = backlinks.iter().map(|e| e.from.clone()).collect();
+        kept.sort_by_key(|node| node.as_str().to_string());
+        let mut expected = hosts[..backlinks.len()].to_vec();
+        expected.sort_by_key(|node| node.as_str().to_string());
+        assert_eq!(kept, expected);
+
+        let forwardlinks =
+            graph.outgoing_edges(Node::from("source.com"), EdgeLimit::Percentile(0.5));
+        let fraction = forwardlinks.len() as f64 / hosts.len() as f64;
+        assert!((fraction - 0.5).abs() < 0.05, "{fraction}");
+
+        assert_eq!(
+            graph
+                .ingoing_edges(Node::from("target.com"), EdgeLimit::Percentile(0.0))
+                .len(),
+            hosts.len()
+        );
+        assert!(graph
+            .ingoing_edges(Node::from("nothing.com"), EdgeLimit::Percentile(0.9))
+            .is_empty());
+    }
+
+    #[test]
+    fn min_centrality_filter() {
+        let hosts: Vec<_> = (0..10).map(|i| Node::from(format!("h{i}.com"))).collect();
+
+        let mut rank_store =
+            speedy_kv::Db::open_or_create(crate::gen_temp_path().join("rank-store")).unwrap();
+        for (rank, host) in hosts.iter().enumerate() {
+            rank_store.insert(host.id(), rank as u64).unwrap();
+        }
+        rank_store.commit().unwrap();
+        let rank_store = Arc::new(rank_store);
+
+        // spread the edges over two graphs so the quantiles are computed across segments
+        let mut graphs = Vec::new();
+        for chunk in hosts.chunks(5) {
+            let mut wrt = WebgraphWriter::new(
+                crate::gen_temp_path(),
+                Executor::single_thread(),
+                Compression::default(),
+                Some(rank_store.clone()),
+            );
+
+            for host in chunk {
+                wrt.insert(
+                    host.clone(),
+                    Node::from("target.com"),
+                    host.as_str().to_string(),
+                    RelFlags::default(),
+                );
+            }
+
+            graphs.push(wrt.finalize());
+        }
+
+        let mut graph = graphs.pop().unwrap();
+        for other in graphs {
+            graph.merge(other).unwrap();
+        }
+        graph.refresh_centrality_quantiles();
+
+        let backlinks = |graph: &Webgraph, percentile: u8| -> Vec<String> {
+            let mut labels: Vec<_> = graph
+                .ingoing_edges_with_min_centrality(
+                    Node::from("target.com"),
+                    MinCentralityFilter::new(percentile),
+                    EdgeLimit::Unlimited,
+                )
+                .into_iter()
+                .map(|e| e.label)
+                .collect();
+            labels.sort();
+            labels
+        };
+
+        assert_eq!(backlinks(&graph, 0).len(), 10);
+        assert_eq!(
+            backlinks(&graph, 50),
+            vec!["h0.com", "h1.com", "h2.com", "h3.com", "h4.com"]
+        );
+        assert_eq!(backlinks(&graph, 80), vec!["h0.com", "h1.com"]);
+        assert_eq!(backlinks(&graph, 95), vec!["h0.com"]);
+        assert_eq!(backlinks(&graph, 100), vec!["h0.com"]);
+
+        graph.merge_all_segments(Compression::default()).unwrap();
+        assert_eq!(backlinks(&graph, 80), vec!["h0.com", "h1.com"]);
+
+        let path = graph.path();
+        drop(graph);
+        let graph = WebgraphBuilder::new(path).open();
+        assert_eq!(backlinks(&graph, 50).len(), 5);
+
+        let filtered = SubdomainBacklinksQuery::new(&Node::from("target.com"))
+            .unwrap()
+            .with_min_centrality(MinCentralityFilter::new(80))
+            .run(&graph);
+        assert_eq!(filtered.num_backlinks(), 2);
+    }
 }
@@ -0,0 +1,465 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The link neighborhood of a host, for drawing it as a graph.
+//!
+//! The neighborhood is the host, the most central hosts linking to it (backlinks) and the
+//! most central hosts it links to (forwardlinks). Optionally, the edges among the hosts of
+//! the neighborhood are looked up as well, so the drawing shows how the neighbors are
+//! connected to each other and not just to the host. The number of neighbors in each
+//! direction is capped by [`MAX_NEIGHBORS_PER_DIRECTION`], so the edges among them are
+//! read from at most [`MAX_NEIGHBORHOOD_NODES`] nodes.
+//!
+//! The outgoing edges of a host are stored on the shard that crawled it, so the neighbors
+//! of all the shards are merged before the edges among them are looked up.
+
+use std::collections::{HashMap, HashSet};
+
+use utoipa::ToSchema;
+
+use super::{CappedEdges, EdgeLimit, EdgeQueryCap, Node, NodeID, Webgraph};
+
+/// Maximum number of backlinks and of forwardlinks in a neighborhood.
+pub const MAX_NEIGHBORS_PER_DIRECTION: usize = 50;
+
+/// Maximum number of nodes in a neighborhood, the host and its neighbors in both directions.
+pub const MAX_NEIGHBORHOOD_NODES: usize = 2 * MAX_NEIGHBORS_PER_DIRECTION + 1;
+
+/// The neighborhood of a host by node ids, as found on one or more shards.
+#[derive(Debug, Clone, PartialEq, bincode::Encode, bincode::Decode)]
+pub struct RawNeighborhood {
+    host: NodeID,
+    backlinks: Vec<NodeID>,
+    forwardlinks: Vec<NodeID>,
+    edges: Vec<(NodeID, NodeID)>,
+    centralities: HashMap<NodeID, f64>,
+    truncated: bool,
+}
+
+impl RawNeighborhood {
+    pub fn new(host: NodeID) -> Self {
+        Self {
+            host,
+            backlinks: Vec::new(),
+            forwardlinks: Vec::new(),
+            edges: Vec::new(),
+            centralities: HashMap::new(),
+            truncated: false,
+        }
+    }
+
+    pub fn merge(&mut self, other: RawNeighborhood) {
+        self.backlinks.extend(other.backlinks);
+        self.forwardlinks.extend(other.forwardlinks);
+        self.edges.extend(other.edges);
+        self.centralities.extend(other.centralities);
+        self.truncated |= other.truncated;
+    }
+
+    /// Some of the edge queries hit the edge cap, so some neighbors or edges may be missing.
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    fn centrality(&self, node: &NodeID) -> f64 {
+        self.centralities.get(node).copied().unwrap_or_default()
+    }
+
+    fn top(&self, neighbors: &[NodeID], n: usize) -> Vec<NodeID> {
+        let mut seen = HashSet::new();
+        let mut top: Vec<_> = neighbors
+            .iter()
+            .copied()
+            .filter(|node| *node != self.host && seen.insert(*node))
+            .collect();
+
+        // the neighbors of each shard are already ordered by their rank, so the
+        // order is kept for neighbors without a known centrality
+        top.sort_by(|a, b| self.centrality(b).total_cmp(&self.centrality(a)));
+        top.truncate(n);
+
+        top
+    }
+
+    /// Keep the `n` most central neighbors in each direction and the edges among the kept
+    /// nodes.
+    pub fn limit(&mut self, n: usize) {
+        let n = n.min(MAX_NEIGHBORS_PER_DIRECTION);
+
+        self.backlinks = self.top(&self.backlinks, n);
+        self.forwardlinks = self.top(&self.forwardlinks, n);
+
+        let nodes: HashSet<_> = self.nodes().into_iter().collect();
+        self.centralities.retain(|node, _| nodes.contains(node));
+        self.retain_edges();
+    }
+
+    /// The host followed by its backlinks and forwardlinks, without duplicates.
+    pub fn nodes(&self) -> Vec<NodeID> {
+        let mut seen = HashSet::new();
+
+        std::iter::once(self.host)
+            .chain(self.backlinks.iter().copied())
+            .chain(self.forwardlinks.iter().copied())
+            .filter(|node| seen.insert(*node))
+            .collect()
+    }
+
+    /// Add the edges that are between nodes of the neighborhood.
+    pub fn add_edges(&mut self, edges: CappedEdges<(NodeID, NodeID)>) {
+        self.edges.extend(edges.edges);
+        self.truncated |= edges.truncated;
+        self.retain_edges();
+    }
+
+    fn retain_edges(&mut self) {
+        let nodes: HashSet<_> = self.nodes().into_iter().collect();
+
+        self.edges
+            .retain(|(from, to)| from != to && nodes.contains(from) && nodes.contains(to));
+        self.edges.sort();
+        self.edges.dedup();
+    }
+
+    /// Name the nodes of the neighborhood. Nodes without a name are left out together with
+    /// their edges.
+    pub fn into_neighborhood(self, names: &HashMap<NodeID, Node>) -> Neighborhood {
+        let backlinks: HashSet<_> = self.backlinks.iter().copied().collect();
+        let forwardlinks: HashSet<_> = self.forwardlinks.iter().copied().collect();
+        let name = |node: &NodeID| names.get(node).map(|node| node.as_str().to_string());
+
+        let nodes = self
+            .nodes()
+            .into_iter()
+            .filter_map(|node| {
+                Some(NeighborhoodNode {
+                    host: name(&node)?,
+                    centrality: self.centralities.get(&node).copied(),
+                    is_backlink: backlinks.contains(&node),
+                    is_forwardlink: forwardlinks.contains(&node),
+                })
+            })
+            .collect();
+
+        let edges = self
+            .edges
+            .iter()
+            .filter_map(|(from, to)| {
+                Some(NeighborhoodEdge {
+                    from: name(from)?,
+                    to: name(to)?,
+                })
+            })
+            .collect();
+
+        Neighborhood {
+            nodes,
+            edges,
+            truncated: self.truncated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NeighborhoodNode {
+    pub host: String,
+    /// Harmonic centrality of the host, if the webgraph server has the centrality store.
+    pub centrality: Option<f64>,
+    /// The host links to the center of the neighborhood.
+    pub is_backlink: bool,
+    /// The center of the neighborhood links to the host.
+    pub is_forwardlink: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NeighborhoodEdge {
+    pub from: String,
+    pub to: String,
+}
+
+/// The nodes and edges of a neighborhood. The center of the neighborhood is the first node.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Neighborhood {
+    pub nodes: Vec<NeighborhoodNode>,
+    pub edges: Vec<NeighborhoodEdge>,
+    pub truncated: bool,
+}
+
+impl Webgraph {
+    /// The most central backlinks and forwardlinks of the host, and the edges among them if
+    /// `include_interconnections` is set. This is meant for the host graph.
+    pub fn raw_neighborhood(
+        &self,
+        host: &Node,
+        per_direction_limit: usize,
+        include_interconnections: bool,
+        cap: EdgeQueryCap,
+        centrality: Option<&speedy_kv::Db<NodeID, f64>>,
+    ) -> RawNeighborhood {
+        let host = host.clone().into_host().id();
+        let limit = per_direction_limit.min(MAX_NEIGHBORS_PER_DIRECTION);
+        let mut neighborhood = RawNeighborhood::new(host);
+
+        // one more than the limit, as the host may link to itself
+        let backlinks = self.raw_ingoing_edges_capped(&host, EdgeLimit::Limit(limit + 1), cap);
+        neighborhood.truncated |= backlinks.truncated;
+        neighborhood.backlinks = backlinks.edges.into_iter().map(|e| e.from).collect();
+
+        let forwardlinks = self.raw_outgoing_edges_capped(&host, EdgeLimit::Limit(limit + 1), cap);
+        neighborhood.truncated |= forwardlinks.truncated;
+        neighborhood.forwardlinks = forwardlinks.edges.into_iter().map(|e| e.to).collect();
+
+        if let Some(centrality) = centrality {
+            for node in neighborhood.nodes() {
+                if let Ok(Some(c)) = centrality.get(&node) {
+                    neighborhood.centralities.insert(node, c);
+                }
+            }
+        }
+
+        neighborhood.limit(limit);
+
+        if include_interconnections {
+            neighborhood.add_edges(self.edges_between(&neighborhood.nodes(), cap));
+        }
+
+        neighborhood
+    }
+
+    /// The edges between the nodes, found from the outgoing edges of each node.
+    /// At most [`MAX_NEIGHBORHOOD_NODES`] nodes are read.
+    pub fn edges_between(
+        &self,
+        nodes: &[NodeID],
+        cap: EdgeQueryCap,
+    ) -> CappedEdges<(NodeID, NodeID)> {
+        let nodes = &nodes[..nodes.len().min(MAX_NEIGHBORHOOD_NODES)];
+        let set: HashSet<_> = nodes.iter().copied().collect();
+
+        let mut edges = Vec::new();
+        let mut truncated = false;
+
+        for node in nodes {
+            let outgoing = self.raw_outgoing_edges_capped(node, EdgeLimit::Unlimited, cap);
+            truncated |= outgoing.truncated;
+
+            edges.extend(
+                outgoing
+                    .edges
+                    .into_iter()
+                    .filter(|edge| edge.from != edge.to && set.contains(&edge.to))
+                    .map(|edge| (edge.from, edge.to)),
+            );
+        }
+
+        CappedEdges { edges, truncated }
+    }
+
+    /// The named neighborhood of the host.
+    pub fn neighborhood(
+        &self,
+        host: &Node,
+        per_direction_limit: usize,
+        include_interconnections: bool,
+        centrality: Option<&speedy_kv::Db<NodeID, f64>>,
+    ) -> Neighborhood {
+        let neighborhood = self.raw_neighborhood(
+            host,
+            per_direction_limit,
+            include_interconnections,
+            EdgeQueryCap::unbounded(),
+            centrality,
+        );
+
+        let names = neighborhood
+            .nodes()
+            .into_iter()
+            .filter_map(|id| self.id2node(&id).map(|node| (id, node)))
+            .collect();
+
+        neighborhood.into_neighborhood(&names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_fixtures::GraphFixture;
+
+    use super::*;
+
+    fn edges(edges: &[(&str, &str)]) -> Vec<(Node, Node, String)> {
+        edges
+            .iter()
+            .map(|(from, to)| (Node::from(*from), Node::from(*to), String::new()))
+            .collect()
+    }
+
+    fn hosts(neighborhood: &Neighborhood) -> Vec<&str> {
+        neighborhood
+            .nodes
+            .iter()
+            .map(|node| node.host.as_str())
+            .collect()
+    }
+
+    fn fixture() -> Vec<(Node, Node, String)> {
+        edges(&[
+            ("a.com", "center.com"),
+            ("b.com", "center.com"),
+            ("center.com", "center.com"),
+            ("center.com", "c.com"),
+            ("center.com", "d.com"),
+            ("a.com", "b.com"),
+            ("b.com", "c.com"),
+            ("c.com", "d.com"),
+            ("d.com", "elsewhere.com"),
+            ("elsewhere.com", "a.com"),
+        ])
+    }
+
+    #[test]
+    fn neighbors_and_interconnections() {
+        let graph = GraphFixture::new(0).edges(fixture()).build();
+
+        let neighborhood = graph
+            .graph()
+            .neighborhood(&Node::from("center.com"), 10, true, None);
+
+        assert_eq!(hosts(&neighborhood)[0], "center.com");
+        let mut neighbors = hosts(&neighborhood)[1..].to_vec();
+        neighbors.sort();
+        assert_eq!(neighbors, vec!["a.com", "b.com", "c.com", "d.com"]);
+
+        let a = &neighborhood.nodes[hosts(&neighborhood)
+            .iter()
+            .position(|host| *host == "a.com")
+            .unwrap()];
+        assert!(a.is_backlink);
+        assert!(!a.is_forwardlink);
+
+        let mut edges: Vec<_> = neighborhood
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str()))
+            .collect();
+        edges.sort();
+
+        // the self-loop and the edges to and from elsewhere.com are left out
+        assert_eq!(
+            edges,
+            vec![
+                ("a.com", "b.com"),
+                ("a.com", "center.com"),
+                ("b.com", "c.com"),
+                ("b.com", "center.com"),
+                ("c.com", "d.com"),
+                ("center.com", "c.com"),
+                ("center.com", "d.com"),
+            ]
+        );
+
+        let without = graph
+            .graph()
+            .neighborhood(&Node::from("center.com"), 10, false, None);
+        assert_eq!(without.nodes.len(), neighborhood.nodes.len());
+        assert!(without.edges.is_empty());
+    }
+
+    #[test]
+    fn most_central_neighbors_are_kept() {
+        let graph = GraphFixture::new(0).edges(fixture()).build();
+
+        let neighborhood = graph.graph().neighborhood(
+            &Node::from("center.com"),
+            1,
+            true,
+            Some(graph.centrality_store()),
+        );
+
+        assert_eq!(neighborhood.nodes.len(), 3);
+        let backlink = neighborhood.nodes.iter().find(|node| node.is_backlink);
+        let most_central = ["a.com", "b.com"]
+            .into_iter()
+            .max_by(|a, b| {
+                graph
+                    .centrality(&Node::from(*a))
+                    .total_cmp(&graph.centrality(&Node::from(*b)))
+            })
+            .unwrap();
+        assert_eq!(backlink.unwrap().host, most_central);
+        assert!(neighborhood
+            .nodes
+            .iter()
+            .all(|node| node.centrality.is_some()));
+        assert!(neighborhood.edges.len() <= 3);
+    }
+
+    #[test]
+    fn neighborhood_is_capped() {
+        let graph = GraphFixture::new(0)
+            .edges((0..2 * MAX_NEIGHBORS_PER_DIRECTION).map(|i| {
+                (
+                    Node::from(format!("linker{i}.com")),
+                    Node::from("center.com"),
+                    String::new(),
+                )
+            }))
+            .build();
+
+        let neighborhood =
+            graph
+                .graph()
+                .neighborhood(&Node::from("center.com"), usize::MAX, true, None);
+
+        assert_eq!(neighborhood.nodes.len(), MAX_NEIGHBORS_PER_DIRECTION + 1);
+    }
+
+    #[test]
+    fn merge_shards() {
+        let (center, a, b, c) = (
+            Node::from("center.com").id(),
+            Node::from("a.com").id(),
+            Node::from("b.com").id(),
+            Node::from("c.com").id(),
+        );
+
+        let mut first = RawNeighborhood::new(center);
+        first.backlinks = vec![a, b];
+        first.centralities = HashMap::from([(a, 0.1), (b, 0.5)]);
+
+        let mut second = RawNeighborhood::new(center);
+        second.backlinks = vec![c, a];
+        second.centralities = HashMap::from([(c, 0.3)]);
+        second.truncated = true;
+
+        first.merge(second);
+        first.limit(2);
+
+        assert_eq!(first.backlinks, vec![b, c]);
+        assert!(first.truncated());
+
+        first.add_edges(CappedEdges {
+            edges: vec![(b, c), (a, b), (c, center), (c, c)],
+            truncated: false,
+        });
+        assert_eq!(first.edges.len(), 2);
+        assert!(first.edges.contains(&(b, c)));
+        assert!(first.edges.contains(&(c, center)));
+    }
+}
@@ -18,7 +18,7 @@ use bloom::fast_stable_hash_128;
 use url::Url;
 use utoipa::ToSchema;
 
-use crate::{intmap, webpage::url_ext::UrlExt};
+use crate::{distributed::member::ShardId, intmap, webpage::url_ext::UrlExt};
 
 #[derive(
     Debug,
@@ -40,6 +40,14 @@ impl NodeID {
     pub fn as_u64(self) -> u64 {
         self.0
     }
+
+    /// The shard this node is assigned to when nodes are spread over the sorted `shards`,
+    /// like the shard ids of [`crate::ampc::dht::Client::shard_ids`]. This is the shard the
+    /// dht stores the node's keys in, so clients can group batched requests by shard before
+    /// sending them. See [`ShardId::for_key`] for the hash.
+    pub fn shard(self, shards: &[ShardId]) -> Option<ShardId> {
+        ShardId::for_key(&self.0.to_le_bytes(), shards)
+    }
 }
 
 impl From<u128> for NodeID {
@@ -112,20 +120,54 @@ pub struct Node {
 
 impl Node {
     pub fn into_host(self) -> Node {
+        match self.host_url() {
+            Some(url) => {
+                let host = url.normalized_host().unwrap_or_default().to_string();
+                Node { name: host }
+            }
+            None => Node {
+                name: String::new(),
+            },
+        }
+    }
+
+    fn host_url(&self) -> Option<Url> {
         let url = if self.name.contains("://") {
             Url::parse(&self.name)
         } else {
             Url::parse(&("http://".to_string() + self.name.as_str()))
         };
 
-        match url {
-            Ok(url) => {
-                let host = url.normalized_host().unwrap_or_default().to_string();
-                Node { name: host }
-            }
-            Err(_) => Node {
-                name: String::new(),
-            },
+        url.ok()
+    }
+
+    /// The domain that can be registered under a public suffix, e.g. `example.co.uk`
+    /// for `blog.example.co.uk`. Returns `None` if the host is itself a public suffix.
+    pub fn registrable_domain(&self) -> Option<Node> {
+        let url = self.host_url()?;
+        let domain = url.root_domain()?;
+
+        Some(Node {
+            name: domain.to_string(),
+        })
+    }
+
+    /// Whether the host of this node is a proper subdomain of the host of `other`.
+    /// Both must belong to the same registrable domain, so nothing is a subdomain of a public suffix.
+    pub fn is_subdomain_of(&self, other: &Node) -> bool {
+        let host = self.clone().into_host();
+        let other = other.clone().into_host();
+
+        if host.name.is_empty() || other.name.is_empty() {
+            return false;
+        }
+
+        match (host.registrable_domain(), other.registrable_domain()) {
+            (Some(a), Some(b)) if a == b => host
+                .name
+                .strip_suffix(other.name.as_str())
+                .is_some_and(|subdomain| subdomain.ends_with('.')),
+            _ => false,
         }
     }
 
@@ -133,6 +175,12 @@ impl Node {
         self.name.as_str()
     }
 
+    /// A node with exactly this name, without normalizing it first.
+    #[cfg(test)]
+    pub fn from_raw(name: String) -> Self {
+        Node { name }
+    }
+
     pub fn id(&self) -> NodeID {
         fast_stable_hash_128(self.name.as_bytes()).into()
     }
@@ -192,3 +240,39 @@ pub fn normalize_url(url: &Url) -> String {
 
     normalized
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn shards_are_balanced() {
+        // the ids of the shards that joined the cluster need not be contiguous
+        let shards: Vec<_> = (0..16).map(|i| ShardId::new(i * 3 + 1)).collect();
+        let num_nodes = 100_000;
+
+        let mut rng = rand::thread_rng();
+        let mut counts = vec![0usize; shards.len()];
+
+        for _ in 0..num_nodes {
+            let node = NodeID::from(rng.gen::<u64>());
+            let shard = node.shard(&shards).unwrap();
+
+            assert_eq!(node.shard(&shards), Some(shard));
+
+            counts[shards.iter().position(|s| *s == shard).unwrap()] += 1;
+        }
+
+        assert_eq!(NodeID::from(1u64).shard(&[]), None);
+
+        let expected = num_nodes / shards.len();
+        for count in counts {
+            assert!(
+                count.abs_diff(expected) < expected / 10,
+                "{count} vs {expected}"
+            );
+        }
+    }
+}
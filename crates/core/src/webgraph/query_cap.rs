@@ -0,0 +1,208 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Bounds on the work done by a single edge query, so a client asking for every
+//! backlink of a node with millions of edges cannot monopolize a webgraph server.
+
+use std::time::{Duration, Instant};
+
+use crate::config::EdgeQueryCapsConfig;
+
+use super::EdgeLimit;
+
+/// Who is asking. Internal queries are allowed to materialize more edges than frontend queries.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub enum QueryPriority {
+    #[default]
+    Internal,
+    Frontend,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeQueryCap {
+    pub max_edges: usize,
+    pub timeout: Option<Duration>,
+}
+
+impl EdgeQueryCap {
+    pub fn unbounded() -> Self {
+        Self {
+            max_edges: usize::MAX,
+            timeout: None,
+        }
+    }
+
+    pub fn from_config(config: &EdgeQueryCapsConfig, priority: QueryPriority) -> Self {
+        Self {
+            max_edges: match priority {
+                QueryPriority::Internal => config.max_edges_internal,
+                QueryPriority::Frontend => config.max_edges,
+            },
+            timeout: config.timeout_ms.map(Duration::from_millis),
+        }
+    }
+
+    pub(super) fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    fn is_unbounded(&self, limit: &EdgeLimit) -> bool {
+        match limit {
+            EdgeLimit::Unlimited | EdgeLimit::Percentile(_) => self.max_edges == usize::MAX,
+            EdgeLimit::Limit(limit) => *limit <= self.max_edges,
+        }
+    }
+
+    /// The limit each segment reads up to. One edge more than the cap is read,
+    /// so a result with exactly `max_edges` edges is not reported as truncated.
+    pub(super) fn segment_limit(&self, limit: EdgeLimit) -> EdgeLimit {
+        if self.is_unbounded(&limit) {
+            limit
+        } else {
+            EdgeLimit::Limit(self.max_edges.saturating_add(1))
+        }
+    }
+
+    /// The limit to apply to the collected edges and whether the result is truncated.
+    /// A percentile is kept when the cap is hit, so it is resolved over the edges
+    /// that were read and the result is marked as truncated.
+    pub(super) fn resolve(
+        &self,
+        limit: EdgeLimit,
+        num_edges: usize,
+        timed_out: bool,
+    ) -> (EdgeLimit, bool) {
+        if self.is_unbounded(&limit) {
+            (limit, timed_out)
+        } else if let EdgeLimit::Percentile(_) = limit {
+            (limit, timed_out || num_edges > self.max_edges)
+        } else {
+            (
+                EdgeLimit::Limit(self.max_edges),
+                timed_out || num_edges > self.max_edges,
+            )
+        }
+    }
+}
+
+/// The edges of a capped query. `truncated` is set if the query hit the cap
+/// or the timeout, in which case there may be more edges than returned.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct CappedEdges<T> {
+    pub edges: Vec<T>,
+    pub truncated: bool,
+}
+
+/// The number of edges of a capped query, which counts at most the cap.
+/// `truncated` is set like for [`CappedEdges`].
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct CappedCount {
+    pub count: usize,
+    pub truncated: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let cap = EdgeQueryCap {
+            max_edges: 10,
+            timeout: None,
+        };
+
+        assert!(matches!(
+            cap.segment_limit(EdgeLimit::Limit(5)),
+            EdgeLimit::Limit(5)
+        ));
+        assert!(matches!(
+            cap.segment_limit(EdgeLimit::Unlimited),
+            EdgeLimit::Limit(11)
+        ));
+
+        assert!(matches!(
+            cap.resolve(EdgeLimit::Unlimited, 10, false),
+            (EdgeLimit::Limit(10), false)
+        ));
+        assert!(matches!(
+            cap.resolve(EdgeLimit::Unlimited, 11, false),
+            (EdgeLimit::Limit(10), true)
+        ));
+        assert!(matches!(
+            cap.resolve(EdgeLimit::Limit(5), 5, true),
+            (EdgeLimit::Limit(5), true)
+        ));
+
+        assert!(matches!(
+            cap.resolve(EdgeLimit::Percentile(0.9), 11, false),
+            (EdgeLimit::Percentile(_), true)
+        ));
+
+        assert!(matches!(
+            EdgeQueryCap::unbounded().segment_limit(EdgeLimit::Unlimited),
+            EdgeLimit::Unlimited
+        ));
+    }
+
+    #[test]
+    fn priority() {
+        let config = EdgeQueryCapsConfig {
+            max_edges: 10,
+            max_edges_internal: 100,
+            timeout_ms: None,
+        };
+
+        assert_eq!(
+            EdgeQueryCap::from_config(&config, QueryPriority::Frontend).max_edges,
+            10
+        );
+        assert_eq!(
+            EdgeQueryCap::from_config(&config, QueryPriority::Internal).max_edges,
+            100
+        );
+    }
+}
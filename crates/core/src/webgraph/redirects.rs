@@ -0,0 +1,260 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Resolution of the redirects found during crawling. A redirect is stored as an edge from
+//! the redirecting page to its target with the [`RelFlags::REDIRECT`] flag, so the final
+//! destination of a page is found by following its redirect edges.
+//!
+//! Chains are followed for at most [`MAX_REDIRECT_DEPTH`] redirects. A chain that ends in a
+//! loop resolves to the node of the loop with the lowest id, so every node in or leading into
+//! the loop resolves to the same node regardless of where the chain starts.
+
+use std::collections::BTreeSet;
+
+use crate::webpage::html::links::RelFlags;
+
+use super::{EdgeLimit, Node, NodeID, Webgraph};
+
+pub const MAX_REDIRECT_DEPTH: usize = 8;
+
+pub trait Redirects {
+    /// The final destination of the redirects starting at `node`. Nodes that do not
+    /// redirect resolve to themselves.
+    fn resolve_redirects(&self, node: Node) -> Node;
+    fn raw_resolve_redirects(&self, node: NodeID) -> NodeID;
+
+    /// The nodes whose redirects resolve to `node`, not including `node` itself.
+    fn raw_redirect_sources(&self, node: NodeID) -> Vec<NodeID>;
+}
+
+impl Webgraph {
+    /// If a page has been recrawled after its redirect changed, it can have several redirect
+    /// edges. The one with the lowest target id is used so the resolution is deterministic.
+    fn redirect_target(&self, node: NodeID) -> Option<NodeID> {
+        self.raw_outgoing_edges(&node, EdgeLimit::Unlimited)
+            .into_iter()
+            .filter(|edge| edge.rel.contains(RelFlags::REDIRECT))
+            .map(|edge| edge.to)
+            .min()
+    }
+}
+
+impl Redirects for Webgraph {
+    fn resolve_redirects(&self, node: Node) -> Node {
+        let id = self.raw_resolve_redirects(node.id());
+
+        if id == node.id() {
+            return node;
+        }
+
+        self.id2node(&id).unwrap_or(node)
+    }
+
+    fn raw_resolve_redirects(&self, node: NodeID) -> NodeID {
+        let mut chain = vec![node];
+
+        for _ in 0..MAX_REDIRECT_DEPTH {
+            let current = *chain.last().unwrap();
+
+            let next = match self.redirect_target(current) {
+                Some(next) if next != current => next,
+                _ => return current,
+            };
+
+            if let Some(pos) = chain.iter().position(|node| *node == next) {
+                return chain[pos..].iter().copied().min().unwrap();
+            }
+
+            chain.push(next);
+        }
+
+        *chain.last().unwrap()
+    }
+
+    fn raw_redirect_sources(&self, node: NodeID) -> Vec<NodeID> {
+        let mut visited = BTreeSet::from([node]);
+        let mut frontier = vec![node];
+
+        for _ in 0..MAX_REDIRECT_DEPTH {
+            let mut next = Vec::new();
+
+            for current in frontier {
+                for edge in self.raw_ingoing_edges(&current, EdgeLimit::Unlimited) {
+                    if edge.rel.contains(RelFlags::REDIRECT) && visited.insert(edge.from) {
+                        next.push(edge.from);
+                    }
+                }
+            }
+
+            if next.is_empty() {
+                break;
+            }
+
+            frontier = next;
+        }
+
+        // a node in a loop only resolves to the lowest node of the loop
+        visited
+            .into_iter()
+            .filter(|source| *source != node)
+            .filter(|source| self.raw_resolve_redirects(*source) == node)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        executor::Executor,
+        webgraph::{Compression, WebgraphWriter},
+    };
+
+    use super::*;
+
+    fn graph() -> Webgraph {
+        let mut writer = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        );
+
+        for (from, to, rel) in [
+            ("a.com", "b.com", RelFlags::REDIRECT),
+            ("b.com", "c.com", RelFlags::REDIRECT),
+            ("c.com", "d.com", RelFlags::default()),
+            ("x.com", "y.com", RelFlags::REDIRECT),
+            ("y.com", "z.com", RelFlags::REDIRECT),
+            ("z.com", "x.com", RelFlags::REDIRECT),
+            ("w.com", "x.com", RelFlags::REDIRECT),
+        ] {
+            writer.insert(Node::from(from), Node::from(to), String::new(), rel);
+        }
+
+        writer.commit();
+
+        writer.finalize()
+    }
+
+    #[test]
+    fn chain() {
+        let graph = graph();
+
+        for node in ["a.com", "b.com", "c.com"] {
+            assert_eq!(
+                graph.resolve_redirects(Node::from(node)),
+                Node::from("c.com")
+            );
+        }
+
+        // plain links are not redirects
+        assert_eq!(
+            graph.resolve_redirects(Node::from("d.com")),
+            Node::from("d.com")
+        );
+
+        let sources: BTreeSet<_> = graph
+            .raw_redirect_sources(Node::from("c.com").id())
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            sources,
+            BTreeSet::from([Node::from("a.com").id(), Node::from("b.com").id()])
+        );
+    }
+
+    #[test]
+    fn loop_is_broken_deterministically() {
+        let graph = graph();
+
+        let lowest = ["x.com", "y.com", "z.com"]
+            .into_iter()
+            .map(|node| Node::from(node).id())
+            .min()
+            .unwrap();
+
+        for node in ["w.com", "x.com", "y.com", "z.com"] {
+            assert_eq!(graph.raw_resolve_redirects(Node::from(node).id()), lowest);
+        }
+
+        let mut sources = graph.raw_redirect_sources(lowest);
+        sources.sort();
+
+        let mut expected: Vec<_> = ["w.com", "x.com", "y.com", "z.com"]
+            .into_iter()
+            .map(|node| Node::from(node).id())
+            .filter(|node| *node != lowest)
+            .collect();
+        expected.sort();
+
+        assert_eq!(sources, expected);
+    }
+
+    #[test]
+    fn depth_is_capped() {
+        let mut writer = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        );
+
+        for i in 0..MAX_REDIRECT_DEPTH + 5 {
+            writer.insert(
+                Node::from(format!("{i}.com")),
+                Node::from(format!("{}.com", i + 1)),
+                String::new(),
+                RelFlags::REDIRECT,
+            );
+        }
+
+        writer.commit();
+        let graph = writer.finalize();
+
+        assert_eq!(
+            graph.resolve_redirects(Node::from("0.com")),
+            Node::from(format!("{MAX_REDIRECT_DEPTH}.com"))
+        );
+    }
+
+    #[test]
+    fn redirects_are_not_links() {
+        let graph = graph();
+
+        assert!(graph
+            .ingoing_edges(Node::from("b.com"), EdgeLimit::Unlimited)
+            .is_empty());
+        assert!(graph
+            .outgoing_edges(Node::from("a.com"), EdgeLimit::Unlimited)
+            .is_empty());
+
+        let links: Vec<_> = graph
+            .outgoing_edges(Node::from("c.com"), EdgeLimit::Unlimited)
+            .into_iter()
+            .map(|edge| edge.to)
+            .collect();
+        assert_eq!(links, vec![Node::from("d.com")]);
+
+        // the raw edges still carry the redirects, so they can be resolved
+        assert_eq!(
+            graph
+                .raw_ingoing_edges(&Node::from("b.com").id(), EdgeLimit::Unlimited)
+                .len(),
+            1
+        );
+    }
+}
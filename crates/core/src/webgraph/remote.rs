@@ -33,13 +33,20 @@ use crate::{
         },
     },
     entrypoint::webgraph_server::{
-        GetNode, IngoingEdges, OutgoingEdges, PagesByHosts, RawIngoingEdges,
-        RawIngoingEdgesWithLabels, RawOutgoingEdges, RawOutgoingEdgesWithLabels, WebGraphService,
+        self, CentralityPercentile, DominantLanguage, EdgesBetween, GetNode, HostCoLinkCounts,
+        HostLinkAggregates, IngoingEdges, IngoingEdgesInRange, IngoingLabelSearch, NumIngoingEdges,
+        NumIngoingEdgesInRange, OutgoingEdges, PagesByHosts, RawIngoingEdges,
+        RawIngoingEdgesWithLabels, RawOutgoingEdges, RawOutgoingEdgesWithLabels,
+        RawRedirectSources, ResolveRedirects, WebGraphService,
     },
     Result,
 };
 
-use super::{Edge, EdgeLimit, FullEdge, Node, NodeID};
+use super::{
+    CappedCount, CappedEdges, CoLinkCounts, CoLinkLimits, CoLinkedHost, Edge, EdgeLimit, FullEdge,
+    LabelSearchQuery, LinkAggregates, LinkReport, Neighborhood, Node, NodeID, QueryPriority,
+    RawNeighborhood, ScoredEdge, TimeRange,
+};
 
 struct WebgraphClientManager {
     granularity: WebgraphGranularity,
@@ -92,6 +99,7 @@ impl sonic::replication::ReusableClientManager for WebgraphClientManager {
 #[derive(Clone)]
 pub struct RemoteWebgraph {
     client: Arc<Mutex<sonic::replication::ReusableShardedClient<WebgraphClientManager>>>,
+    priority: QueryPriority,
 }
 
 impl RemoteWebgraph {
@@ -102,9 +110,16 @@ impl RemoteWebgraph {
             client: Arc::new(Mutex::new(
                 sonic::replication::ReusableShardedClient::new(cluster, manager).await,
             )),
+            priority: QueryPriority::default(),
         }
     }
 
+    /// The priority sent with the edge queries, which decides the caps the servers apply.
+    pub fn with_priority(mut self, priority: QueryPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
     async fn conn(&self) -> Arc<sonic::replication::ShardedClient<WebGraphService, ShardId>> {
         self.client.lock().await.conn().await
     }
@@ -175,11 +190,166 @@ impl RemoteWebgraph {
     }
 
     pub async fn ingoing_edges(&self, node: Node, limit: EdgeLimit) -> Result<Vec<FullEdge>> {
+        Ok(self.ingoing_edges_capped(node, limit).await?.edges)
+    }
+
+    /// The edges of the node and whether any shard truncated its edges
+    /// because the query reached the caps of the server.
+    pub async fn ingoing_edges_capped(
+        &self,
+        node: Node,
+        limit: EdgeLimit,
+    ) -> Result<CappedEdges<FullEdge>> {
+        let res = self
+            .conn()
+            .await
+            .send(
+                IngoingEdges {
+                    node,
+                    limit,
+                    priority: self.priority,
+                },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await?;
+
+        let mut edges = CappedEdges {
+            edges: Vec::new(),
+            truncated: false,
+        };
+
+        for (_, reps) in res {
+            debug_assert!(reps.len() <= 1);
+
+            for (_, rep) in reps {
+                edges.edges.extend(rep.edges);
+                edges.truncated |= rep.truncated;
+            }
+        }
+
+        Ok(edges)
+    }
+
+    /// The ingoing edges of the node whose label matches the query, best first. The labels
+    /// are matched on each shard and the matches of all the shards are ranked together.
+    pub async fn search_ingoing_labels(
+        &self,
+        node: Node,
+        query: LabelSearchQuery,
+    ) -> Result<CappedEdges<ScoredEdge>> {
+        let res = self
+            .conn()
+            .await
+            .send(
+                IngoingLabelSearch {
+                    node,
+                    query: query.clone(),
+                    priority: self.priority,
+                },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await?;
+
+        let mut matches = Vec::new();
+        let mut truncated = false;
+
+        for rep in res
+            .into_iter()
+            .flat_map(|(_, reps)| reps.into_iter().map(|(_, rep)| rep))
+        {
+            matches.extend(rep.edges);
+            truncated |= rep.truncated;
+        }
+
+        Ok(CappedEdges {
+            edges: query.rank(matches),
+            truncated,
+        })
+    }
+
+    pub async fn ingoing_edges_in_range(
+        &self,
+        node: Node,
+        range: TimeRange,
+        limit: EdgeLimit,
+    ) -> Result<CappedEdges<FullEdge>> {
+        let res = self
+            .conn()
+            .await
+            .send(
+                IngoingEdgesInRange {
+                    node,
+                    range,
+                    limit,
+                    priority: self.priority,
+                },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await?;
+
+        let mut edges = CappedEdges {
+            edges: Vec::new(),
+            truncated: false,
+        };
+
+        for (_, reps) in res {
+            debug_assert!(reps.len() <= 1);
+
+            for (_, rep) in reps {
+                edges.edges.extend(rep.edges);
+                edges.truncated |= rep.truncated;
+            }
+        }
+
+        Ok(edges)
+    }
+
+    pub async fn num_ingoing_edges_in_range(
+        &self,
+        id: NodeID,
+        range: TimeRange,
+    ) -> Result<CappedCount> {
+        let res = self
+            .conn()
+            .await
+            .send(
+                NumIngoingEdgesInRange {
+                    node: id,
+                    range,
+                    priority: self.priority,
+                },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await?;
+
+        let mut count = CappedCount {
+            count: 0,
+            truncated: false,
+        };
+
+        for (_, reps) in res {
+            debug_assert!(reps.len() <= 1);
+
+            for (_, rep) in reps {
+                count.count += rep.count;
+                count.truncated |= rep.truncated;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// The number of distinct nodes linking to the node.
+    pub async fn num_ingoing_edges(&self, id: NodeID) -> Result<usize> {
         let res = self
             .conn()
             .await
             .send(
-                IngoingEdges { node, limit },
+                NumIngoingEdges { node: id },
                 &AllShardsSelector,
                 &RandomReplicaSelector,
             )
@@ -189,9 +359,9 @@ impl RemoteWebgraph {
             .into_iter()
             .flat_map(|(_, reps)| {
                 debug_assert!(reps.len() <= 1);
-                reps.into_iter().flat_map(|(_, rep)| rep)
+                reps.into_iter().map(|(_, rep)| rep)
             })
-            .collect())
+            .sum())
     }
 
     pub async fn raw_ingoing_edges(&self, id: NodeID, limit: EdgeLimit) -> Result<Vec<Edge<()>>> {
@@ -199,7 +369,11 @@ impl RemoteWebgraph {
             .conn()
             .await
             .send(
-                RawIngoingEdges { node: id, limit },
+                RawIngoingEdges {
+                    node: id,
+                    limit,
+                    priority: self.priority,
+                },
                 &AllShardsSelector,
                 &RandomReplicaSelector,
             )
@@ -209,7 +383,7 @@ impl RemoteWebgraph {
             .into_iter()
             .flat_map(|(_, reps)| {
                 debug_assert!(reps.len() <= 1);
-                reps.into_iter().flat_map(|(_, rep)| rep)
+                reps.into_iter().flat_map(|(_, rep)| rep.edges)
             })
             .collect())
     }
@@ -223,7 +397,11 @@ impl RemoteWebgraph {
             .conn()
             .await
             .send(
-                RawIngoingEdgesWithLabels { node: id, limit },
+                RawIngoingEdgesWithLabels {
+                    node: id,
+                    limit,
+                    priority: self.priority,
+                },
                 &AllShardsSelector,
                 &RandomReplicaSelector,
             )
@@ -233,7 +411,7 @@ impl RemoteWebgraph {
             .into_iter()
             .flat_map(|(_, reps)| {
                 debug_assert!(reps.len() <= 1);
-                reps.into_iter().flat_map(|(_, rep)| rep)
+                reps.into_iter().flat_map(|(_, rep)| rep.edges)
             })
             .collect())
     }
@@ -245,7 +423,11 @@ impl RemoteWebgraph {
     ) -> Result<Vec<Vec<Edge<String>>>> {
         let reqs: Vec<_> = ids
             .iter()
-            .map(|id| RawIngoingEdgesWithLabels { node: *id, limit })
+            .map(|id| RawIngoingEdgesWithLabels {
+                node: *id,
+                limit,
+                priority: self.priority,
+            })
             .collect();
 
         let res = self
@@ -261,7 +443,7 @@ impl RemoteWebgraph {
 
             for (_, res) in res {
                 for (i, rep) in res.into_iter().enumerate() {
-                    edges[i].extend(rep);
+                    edges[i].extend(rep.edges);
                 }
             }
         }
@@ -276,7 +458,11 @@ impl RemoteWebgraph {
     ) -> Result<Vec<Vec<Edge<()>>>> {
         let reqs: Vec<_> = ids
             .iter()
-            .map(|id| RawIngoingEdges { node: *id, limit })
+            .map(|id| RawIngoingEdges {
+                node: *id,
+                limit,
+                priority: self.priority,
+            })
             .collect();
 
         let res = self
@@ -292,7 +478,7 @@ impl RemoteWebgraph {
 
             for (_, res) in res {
                 for (i, rep) in res.into_iter().enumerate() {
-                    edges[i].extend(rep);
+                    edges[i].extend(rep.edges);
                 }
             }
         }
@@ -301,23 +487,45 @@ impl RemoteWebgraph {
     }
 
     pub async fn outgoing_edges(&self, node: Node, limit: EdgeLimit) -> Result<Vec<FullEdge>> {
+        Ok(self.outgoing_edges_capped(node, limit).await?.edges)
+    }
+
+    /// The edges of the node and whether any shard truncated its edges
+    /// because the query reached the caps of the server.
+    pub async fn outgoing_edges_capped(
+        &self,
+        node: Node,
+        limit: EdgeLimit,
+    ) -> Result<CappedEdges<FullEdge>> {
         let res = self
             .conn()
             .await
             .send(
-                OutgoingEdges { node, limit },
+                OutgoingEdges {
+                    node,
+                    limit,
+                    priority: self.priority,
+                },
                 &AllShardsSelector,
                 &RandomReplicaSelector,
             )
             .await?;
 
-        Ok(res
-            .into_iter()
-            .flat_map(|(_, reps)| {
-                debug_assert!(reps.len() <= 1);
-                reps.into_iter().flat_map(|(_, rep)| rep)
-            })
-            .collect())
+        let mut edges = CappedEdges {
+            edges: Vec::new(),
+            truncated: false,
+        };
+
+        for (_, reps) in res {
+            debug_assert!(reps.len() <= 1);
+
+            for (_, rep) in reps {
+                edges.edges.extend(rep.edges);
+                edges.truncated |= rep.truncated;
+            }
+        }
+
+        Ok(edges)
     }
 
     pub async fn raw_outgoing_edges(&self, id: NodeID, limit: EdgeLimit) -> Result<Vec<Edge<()>>> {
@@ -325,7 +533,11 @@ impl RemoteWebgraph {
             .conn()
             .await
             .send(
-                RawOutgoingEdges { node: id, limit },
+                RawOutgoingEdges {
+                    node: id,
+                    limit,
+                    priority: self.priority,
+                },
                 &AllShardsSelector,
                 &RandomReplicaSelector,
             )
@@ -335,7 +547,7 @@ impl RemoteWebgraph {
             .into_iter()
             .flat_map(|(_, reps)| {
                 debug_assert!(reps.len() <= 1);
-                reps.into_iter().flat_map(|(_, rep)| rep)
+                reps.into_iter().flat_map(|(_, rep)| rep.edges)
             })
             .collect())
     }
@@ -349,7 +561,11 @@ impl RemoteWebgraph {
             .conn()
             .await
             .send(
-                RawOutgoingEdgesWithLabels { node: id, limit },
+                RawOutgoingEdgesWithLabels {
+                    node: id,
+                    limit,
+                    priority: self.priority,
+                },
                 &AllShardsSelector,
                 &RandomReplicaSelector,
             )
@@ -359,7 +575,7 @@ impl RemoteWebgraph {
             .into_iter()
             .flat_map(|(_, reps)| {
                 debug_assert!(reps.len() <= 1);
-                reps.into_iter().flat_map(|(_, rep)| rep)
+                reps.into_iter().flat_map(|(_, rep)| rep.edges)
             })
             .collect())
     }
@@ -371,7 +587,11 @@ impl RemoteWebgraph {
     ) -> Result<Vec<Vec<Edge<()>>>> {
         let reqs: Vec<_> = ids
             .iter()
-            .map(|id| RawOutgoingEdges { node: *id, limit })
+            .map(|id| RawOutgoingEdges {
+                node: *id,
+                limit,
+                priority: self.priority,
+            })
             .collect();
 
         let res = self
@@ -387,7 +607,7 @@ impl RemoteWebgraph {
 
             for (_, res) in res {
                 for (i, rep) in res.into_iter().enumerate() {
-                    edges[i].extend(rep);
+                    edges[i].extend(rep.edges);
                 }
             }
         }
@@ -417,4 +637,247 @@ impl RemoteWebgraph {
             .unique()
             .collect())
     }
+
+    /// The ISO 639-3 code of the dominant language of the host, if the webgraph
+    /// servers have a host language store.
+    pub async fn dominant_language(&self, host: NodeID) -> Result<Option<String>> {
+        let res = self
+            .conn()
+            .await
+            .send(
+                DominantLanguage { node: host },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await?;
+
+        Ok(res
+            .into_iter()
+            .flat_map(|(_, res)| res.into_iter().map(|(_, v)| v))
+            .find(|lang| lang.is_some())
+            .flatten())
+    }
+
+    /// The percentile of the linking hosts in the graph that a host with the centrality
+    /// rank is at least as central as, if the graph has a quantile table.
+    pub async fn centrality_percentile(&self, rank: u64) -> Result<Option<u8>> {
+        let res = self
+            .conn()
+            .await
+            .send(
+                CentralityPercentile { rank },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await?;
+
+        // the shards each have their own quantile table, but as the nodes are spread
+        // evenly over the shards the tables are close enough that any of them will do.
+        Ok(res
+            .into_iter()
+            .flat_map(|(_, res)| res.into_iter().map(|(_, v)| v))
+            .find(|percentile| percentile.is_some())
+            .flatten())
+    }
+
+    /// The final destination of the redirects starting at `node`. The redirects are only
+    /// followed within each shard, and if several shards resolve the node the destination
+    /// with the lowest id is used.
+    pub async fn resolve_redirects(&self, node: Node) -> Result<Node> {
+        let res = self
+            .conn()
+            .await
+            .send(
+                ResolveRedirects { node: node.clone() },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await?;
+
+        Ok(res
+            .into_iter()
+            .flat_map(|(_, res)| res.into_iter().map(|(_, v)| v))
+            .filter(|destination| destination.id() != node.id())
+            .min_by_key(|destination| destination.id())
+            .unwrap_or(node))
+    }
+
+    pub async fn batch_raw_redirect_sources(&self, ids: &[NodeID]) -> Result<Vec<Vec<NodeID>>> {
+        let reqs: Vec<_> = ids
+            .iter()
+            .map(|id| RawRedirectSources { node: *id })
+            .collect();
+
+        let res = self
+            .conn()
+            .await
+            .batch_send(&reqs, &AllShardsSelector, &RandomReplicaSelector)
+            .await?;
+
+        let mut sources = vec![vec![]; ids.len()];
+
+        for (_, res) in res {
+            debug_assert!(res.len() <= 1);
+
+            for (_, res) in res {
+                for (i, rep) in res.into_iter().enumerate() {
+                    sources[i].extend(rep);
+                }
+            }
+        }
+
+        for sources in &mut sources {
+            sources.sort();
+            sources.dedup();
+        }
+
+        Ok(sources)
+    }
+
+    /// The backlinks of the host grouped by the linking host. Only meaningful for the page graph.
+    pub async fn link_report(&self, host: Node) -> Result<LinkReport> {
+        let host = host.into_host();
+
+        let res = self
+            .conn()
+            .await
+            .send(
+                HostLinkAggregates {
+                    host: host.clone(),
+                    priority: self.priority,
+                },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await?;
+
+        let mut aggregates = LinkAggregates::default();
+
+        for aggregate in res
+            .into_iter()
+            .flat_map(|(_, reps)| reps.into_iter().map(|(_, rep)| rep))
+        {
+            aggregates.merge(aggregate);
+        }
+
+        Ok(aggregates.report(host.as_str().to_string()))
+    }
+
+    /// The `n` hosts most commonly linked together with the host. Only meaningful for the host graph.
+    pub async fn co_linked_hosts(
+        &self,
+        host: Node,
+        limits: CoLinkLimits,
+        n: usize,
+    ) -> Result<Vec<CoLinkedHost>> {
+        let conn = self.conn().await;
+        let limits = limits.per_shard(conn.shard_ids().len());
+
+        let res = conn
+            .send(
+                HostCoLinkCounts {
+                    host: host.into_host(),
+                    limits,
+                    priority: self.priority,
+                },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await?;
+
+        let mut counts = CoLinkCounts::default();
+
+        for shard_counts in res
+            .into_iter()
+            .flat_map(|(_, reps)| reps.into_iter().map(|(_, rep)| rep))
+        {
+            counts.merge(shard_counts);
+        }
+
+        let top = counts.top(n);
+        let ids: Vec<_> = top.iter().map(|(id, _)| *id).collect();
+        let nodes = self.batch_get_node(&ids).await?;
+
+        Ok(top
+            .into_iter()
+            .zip_eq(nodes)
+            .filter_map(|((_, score), node)| {
+                node.map(|node| CoLinkedHost {
+                    host: node.as_str().to_string(),
+                    score,
+                })
+            })
+            .collect())
+    }
+
+    /// The most central backlinks and forwardlinks of the host, and the edges among them if
+    /// `include_interconnections` is set. Only meaningful for the host graph.
+    pub async fn neighborhood(
+        &self,
+        host: Node,
+        per_direction_limit: usize,
+        include_interconnections: bool,
+    ) -> Result<Neighborhood> {
+        let host = host.into_host();
+
+        // the edges among the neighbors can only be looked up once
+        // the neighbors of all the shards are known
+        let res = self
+            .conn()
+            .await
+            .send(
+                webgraph_server::Neighborhood {
+                    host: host.clone(),
+                    per_direction_limit,
+                    include_interconnections: false,
+                    priority: self.priority,
+                },
+                &AllShardsSelector,
+                &RandomReplicaSelector,
+            )
+            .await?;
+
+        let mut neighborhood = RawNeighborhood::new(host.id());
+
+        for shard_neighborhood in res
+            .into_iter()
+            .flat_map(|(_, reps)| reps.into_iter().map(|(_, rep)| rep))
+        {
+            neighborhood.merge(shard_neighborhood);
+        }
+
+        neighborhood.limit(per_direction_limit);
+        let nodes = neighborhood.nodes();
+
+        if include_interconnections {
+            let res = self
+                .conn()
+                .await
+                .send(
+                    EdgesBetween {
+                        nodes: nodes.clone(),
+                        priority: self.priority,
+                    },
+                    &AllShardsSelector,
+                    &RandomReplicaSelector,
+                )
+                .await?;
+
+            for edges in res
+                .into_iter()
+                .flat_map(|(_, reps)| reps.into_iter().map(|(_, rep)| rep))
+            {
+                neighborhood.add_edges(edges);
+            }
+        }
+
+        let names = nodes
+            .iter()
+            .copied()
+            .zip_eq(self.batch_get_node(&nodes).await?)
+            .filter_map(|(id, node)| node.map(|node| (id, node)))
+            .collect();
+
+        Ok(neighborhood.into_neighborhood(&names))
+    }
 }
@@ -21,8 +21,11 @@ use std::{
 };
 
 use super::{
-    store::EdgeStore, store_writer::EdgeStoreWriter, Compression, EdgeLimit, InsertableEdge,
-    NodeID, SegmentEdge,
+    crawl_times::{CrawlTimes, CrawlTimesWriter, Supersession, CRAWL_TIMES},
+    stats::SegmentStats,
+    store::EdgeStore,
+    store_writer::EdgeStoreWriter,
+    Compression, EdgeLimit, InsertableEdge, NodeID, SegmentEdge,
 };
 use crate::Result;
 
@@ -32,6 +35,7 @@ const REVERSED_ADJACENCY_STORE: &str = "reversed_adjacency";
 pub struct SegmentWriter {
     adjacency: EdgeStoreWriter,
     reversed_adjacency: EdgeStoreWriter,
+    crawl_times: Option<CrawlTimesWriter>,
     id: String,
     folder_path: String,
 }
@@ -59,6 +63,7 @@ impl SegmentWriter {
                 true,
                 host_centrality_rank_store.clone(),
             ),
+            crawl_times: None,
             folder_path: folder_path
                 .as_ref()
                 .as_os_str()
@@ -69,16 +74,38 @@ impl SegmentWriter {
         }
     }
 
+    /// Store the latest crawl time of every source node, so the edges of the nodes can be
+    /// superseded by later crawls. See [`super::crawl_times`].
+    pub fn track_crawl_times(&mut self) {
+        self.crawl_times = Some(CrawlTimesWriter::default());
+    }
+
     pub fn finalize(self) -> Segment {
+        let path = Path::new(&self.folder_path).join(&self.id);
+
         Segment {
             adjacency: self.adjacency.finalize(),
             reversed_adjacency: self.reversed_adjacency.finalize(),
+            crawl_times: self
+                .crawl_times
+                .map(|times| times.finalize(path.join(CRAWL_TIMES)).unwrap()),
             folder_path: self.folder_path,
             id: self.id,
         }
     }
 
+    /// Record that the source was crawled, even if none of its edges are inserted.
+    pub fn record_crawl(&mut self, source: NodeID, crawled_at: u64) {
+        if let Some(times) = &mut self.crawl_times {
+            times.insert(source, crawled_at);
+        }
+    }
+
     pub fn insert(&mut self, edge: InsertableEdge<String>) {
+        if let Some(times) = &mut self.crawl_times {
+            times.insert(edge.from.id, edge.discovered_at);
+        }
+
         self.adjacency.put(edge.clone());
         self.reversed_adjacency.put(edge);
     }
@@ -87,6 +114,7 @@ impl SegmentWriter {
 pub struct Segment {
     adjacency: EdgeStore,
     reversed_adjacency: EdgeStore,
+    crawl_times: Option<CrawlTimes>,
     id: String,
     folder_path: String,
 }
@@ -102,6 +130,8 @@ impl Segment {
                     .join(REVERSED_ADJACENCY_STORE),
                 true,
             ),
+            crawl_times: CrawlTimes::open(folder_path.as_ref().join(&id).join(CRAWL_TIMES))
+                .unwrap(),
             folder_path: folder_path
                 .as_ref()
                 .as_os_str()
@@ -117,6 +147,27 @@ impl Segment {
         label_compression: Compression,
         folder: P,
         id: String,
+    ) -> Result<()> {
+        Self::merge_inner(segments, label_compression, folder, id, false)
+    }
+
+    /// Like [`Segment::merge`], but the edges of a source node are only kept from the
+    /// segments where the node was crawled most recently.
+    pub fn merge_superseding<P: AsRef<Path>>(
+        segments: Vec<Self>,
+        label_compression: Compression,
+        folder: P,
+        id: String,
+    ) -> Result<()> {
+        Self::merge_inner(segments, label_compression, folder, id, true)
+    }
+
+    fn merge_inner<P: AsRef<Path>>(
+        mut segments: Vec<Self>,
+        label_compression: Compression,
+        folder: P,
+        id: String,
+        supersede: bool,
     ) -> Result<()> {
         if segments.is_empty() {
             return Ok(());
@@ -130,14 +181,26 @@ impl Segment {
 
         let old_paths = segments.iter().map(|s| s.path()).collect::<Vec<_>>();
 
+        let supersession = Arc::new(Supersession::new(
+            segments.iter_mut().map(|s| s.crawl_times.take()).collect(),
+        ));
+        let filter = (supersede && !supersession.is_empty()).then(|| Arc::clone(&supersession));
+
         let (adjacency, reversed_adjacency) = segments
             .into_iter()
             .map(|s| (s.adjacency, s.reversed_adjacency))
             .unzip();
 
         let adjacency_path = folder.as_ref().join(&id).join(ADJACENCY_STORE);
-        let adjacency =
-            thread::spawn(move || EdgeStore::merge(adjacency, label_compression, adjacency_path));
+        let adjacency_filter = filter.clone();
+        let adjacency = thread::spawn(move || {
+            EdgeStore::merge(
+                adjacency,
+                label_compression,
+                adjacency_path,
+                adjacency_filter.as_deref(),
+            )
+        });
 
         let reversed_adjacency_path = folder.as_ref().join(&id).join(REVERSED_ADJACENCY_STORE);
         let reversed_adjacency = thread::spawn(move || {
@@ -145,12 +208,17 @@ impl Segment {
                 reversed_adjacency,
                 label_compression,
                 reversed_adjacency_path,
+                filter.as_deref(),
             )
         });
 
         adjacency.join().unwrap()?;
         reversed_adjacency.join().unwrap()?;
 
+        Arc::into_inner(supersession)
+            .expect("the merge threads are done")
+            .finalize(folder.as_ref().join(&id).join(CRAWL_TIMES))?;
+
         for path in old_paths {
             if Path::new(&path).exists() {
                 std::fs::remove_dir_all(path)?;
@@ -184,6 +252,108 @@ impl Segment {
         self.reversed_adjacency.get_without_label(node, limit)
     }
 
+    pub fn ingoing_edges_with_label_up_to(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        max_sort_key: u64,
+    ) -> Vec<SegmentEdge<String>> {
+        self.reversed_adjacency
+            .get_with_label_up_to(node, limit, max_sort_key)
+    }
+
+    // the `_into` variants add the edges to a buffer the caller can reuse
+
+    pub fn outgoing_edges_with_label_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        out: &mut Vec<SegmentEdge<String>>,
+    ) {
+        self.adjacency.get_with_label_into(node, limit, out)
+    }
+
+    pub fn outgoing_edges_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        out: &mut Vec<SegmentEdge<()>>,
+    ) {
+        self.adjacency.get_without_label_into(node, limit, out)
+    }
+
+    pub fn ingoing_edges_with_label_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        out: &mut Vec<SegmentEdge<String>>,
+    ) {
+        self.reversed_adjacency
+            .get_with_label_into(node, limit, out)
+    }
+
+    pub fn ingoing_edges_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        out: &mut Vec<SegmentEdge<()>>,
+    ) {
+        self.reversed_adjacency
+            .get_without_label_into(node, limit, out)
+    }
+
+    /// Only the ingoing edges that match `filter`. The limit applies to the matching edges.
+    pub fn ingoing_edges_matching_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        filter: impl Fn(&SegmentEdge<()>) -> bool,
+        out: &mut Vec<SegmentEdge<()>>,
+    ) {
+        self.reversed_adjacency
+            .get_without_label_matching_into(node, limit, filter, out)
+    }
+
+    pub fn ingoing_edges_with_label_matching_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        filter: impl Fn(&SegmentEdge<String>) -> bool,
+        out: &mut Vec<SegmentEdge<String>>,
+    ) {
+        self.reversed_adjacency
+            .get_with_label_matching_into(node, limit, filter, out)
+    }
+
+    pub fn ingoing_edges_with_label_up_to_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        max_sort_key: u64,
+        out: &mut Vec<SegmentEdge<String>>,
+    ) {
+        self.reversed_adjacency
+            .get_with_label_up_to_into(node, limit, max_sort_key, out)
+    }
+
+    /// The host centrality rank of every node with outgoing edges in the segment.
+    pub fn linking_host_ranks(&self) -> impl Iterator<Item = u64> + '_ {
+        self.adjacency.sort_keys()
+    }
+
+    pub fn stats(&self) -> SegmentStats {
+        let mut stats = SegmentStats::default();
+
+        for degree in self.adjacency.degrees() {
+            stats.num_edges += degree;
+            stats.max_out_degree = stats.max_out_degree.max(degree);
+        }
+
+        stats.max_in_degree = self.reversed_adjacency.degrees().max().unwrap_or_default();
+
+        stats
+    }
+
     pub fn pages_by_host(&self, host_node: &NodeID) -> Vec<NodeID> {
         self.reversed_adjacency.nodes_by_host(host_node)
     }
@@ -200,6 +370,10 @@ impl Segment {
         self.adjacency.iter_without_label()
     }
 
+    pub fn edges_with_label(&self) -> impl Iterator<Item = SegmentEdge<String>> + '_ + Send + Sync {
+        self.adjacency.iter_with_label()
+    }
+
     pub fn optimize_read(&mut self) {
         self.adjacency.optimize_read();
         self.reversed_adjacency.optimize_read();
@@ -252,24 +426,28 @@ mod test {
             to: b.clone(),
             label: String::new(),
             rel: RelFlags::default(),
+            discovered_at: 0,
         });
         edges.push(InsertableEdge {
             from: b.clone(),
             to: c.clone(),
             label: String::new(),
             rel: RelFlags::default(),
+            discovered_at: 0,
         });
         edges.push(InsertableEdge {
             from: c.clone(),
             to: a.clone(),
             label: String::new(),
             rel: RelFlags::default(),
+            discovered_at: 0,
         });
         edges.push(InsertableEdge {
             from: a.clone(),
             to: c.clone(),
             label: String::new(),
             rel: RelFlags::default(),
+            discovered_at: 0,
         });
 
         for edge in &edges {
@@ -289,6 +467,7 @@ mod test {
                     to: b.id,
                     label: (),
                     rel: RelFlags::default(),
+                    discovered_at: 0,
                 }
                 .into(),
                 Edge {
@@ -296,6 +475,7 @@ mod test {
                     to: c.id,
                     label: (),
                     rel: RelFlags::default(),
+                    discovered_at: 0,
                 }
                 .into(),
             ]
@@ -310,6 +490,7 @@ mod test {
                 to: c.id,
                 label: (),
                 rel: RelFlags::default(),
+                discovered_at: 0,
             }
             .into(),]
         );
@@ -323,6 +504,7 @@ mod test {
                 to: a.id,
                 label: (),
                 rel: RelFlags::default(),
+                discovered_at: 0,
             }
             .into(),]
         );
@@ -335,6 +517,7 @@ mod test {
                 to: a.id,
                 label: (),
                 rel: RelFlags::default(),
+                discovered_at: 0,
             }
             .into(),]
         );
@@ -347,6 +530,7 @@ mod test {
                 to: b.id,
                 label: (),
                 rel: RelFlags::default(),
+                discovered_at: 0,
             }
             .into(),]
         );
@@ -361,6 +545,7 @@ mod test {
                     to: c.id,
                     label: (),
                     rel: RelFlags::default(),
+                    discovered_at: 0,
                 }
                 .into(),
                 Edge {
@@ -368,6 +553,7 @@ mod test {
                     to: c.id,
                     label: (),
                     rel: RelFlags::default(),
+                    discovered_at: 0,
                 }
                 .into(),
             ]
@@ -29,11 +29,14 @@ pub trait ShortestPaths {
     fn reversed_distances(&self, source: Node) -> BTreeMap<Node, u8>;
 }
 
-fn dijkstra_multi<F1, F2, L>(
+/// The distances from the nearest of the sources. Once `max_nodes` nodes have been
+/// reached, nodes that haven't been reached yet are no longer added to the frontier.
+pub(super) fn dijkstra_multi<F1, F2, L>(
     sources: &[NodeID],
     node_edges: F1,
     edge_node: F2,
     max_dist: Option<u8>,
+    max_nodes: Option<usize>,
 ) -> BTreeMap<NodeID, u8>
 where
     L: EdgeLabel,
@@ -65,6 +68,11 @@ where
         }
 
         for edge in node_edges(v) {
+            let is_full = max_nodes.is_some_and(|max_nodes| distances.len() >= max_nodes);
+            if is_full && !distances.contains_key(&edge_node(&edge)) {
+                continue;
+            }
+
             if cost + 1 < *distances.get(&edge_node(&edge)).unwrap_or(&u8::MAX) {
                 let d = cost + 1;
 
@@ -92,6 +100,7 @@ impl ShortestPaths for Webgraph {
             |node| self.raw_outgoing_edges(&node, super::EdgeLimit::Unlimited),
             |edge| edge.to,
             Some(max_dist),
+            None,
         )
     }
 
@@ -101,6 +110,7 @@ impl ShortestPaths for Webgraph {
             |node| self.raw_outgoing_edges(&node, super::EdgeLimit::Unlimited),
             |edge| edge.to,
             None,
+            None,
         )
     }
 
@@ -110,6 +120,7 @@ impl ShortestPaths for Webgraph {
             |node| self.raw_ingoing_edges(&node, super::EdgeLimit::Unlimited),
             |edge| edge.from,
             None,
+            None,
         )
     }
 
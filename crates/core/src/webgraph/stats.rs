@@ -0,0 +1,122 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Graph level statistics for sanity checks.
+//!
+//! The statistics are computed from the edge ranges of each node, so no edges are read.
+//! A node and its edges can be stored in several segments until the segments are merged,
+//! which makes some of the figures estimates for graphs with more than one segment.
+
+/// A statistic that is either exact or an estimate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stat<T> {
+    Exact(T),
+    Estimate(T),
+}
+
+impl<T: Copy> Stat<T> {
+    pub fn value(&self) -> T {
+        match self {
+            Stat::Exact(value) | Stat::Estimate(value) => *value,
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Stat::Exact(_))
+    }
+
+    fn exact_if(exact: bool, value: T) -> Self {
+        if exact {
+            Stat::Exact(value)
+        } else {
+            Stat::Estimate(value)
+        }
+    }
+}
+
+/// The edge statistics of a single segment. These are always exact.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SegmentStats {
+    pub num_edges: u64,
+    pub max_out_degree: u64,
+    pub max_in_degree: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphStats {
+    pub num_segments: usize,
+    /// The number of entries in the id to node store. Nodes are counted
+    /// once for each store segment they were inserted into until the store is merged.
+    pub num_nodes: Stat<u64>,
+    /// Edges present in several segments are counted once per segment,
+    /// so for graphs with more than one segment this is an upper bound.
+    pub num_edges: Stat<u64>,
+    /// Average number of outgoing (and ingoing) edges per node.
+    pub avg_degree: Stat<f64>,
+    /// For graphs with more than one segment this is the largest degree in any single
+    /// segment, which is a lower bound.
+    pub max_out_degree: Stat<u64>,
+    pub max_in_degree: Stat<u64>,
+    /// Fraction of the possible directed edges between distinct nodes that are present.
+    pub density: Stat<f64>,
+}
+
+impl GraphStats {
+    pub(super) fn new(num_nodes: u64, segments: &[SegmentStats]) -> Self {
+        let single_segment = segments.len() <= 1;
+
+        let num_nodes = Stat::Estimate(num_nodes);
+        let num_edges = Stat::exact_if(
+            single_segment,
+            segments.iter().map(|segment| segment.num_edges).sum(),
+        );
+
+        // the node count is never exact, so neither are the figures derived from it.
+        let nodes = num_nodes.value() as f64;
+        let edges = num_edges.value() as f64;
+
+        let avg_degree = if nodes > 0.0 { edges / nodes } else { 0.0 };
+        let density = if nodes > 1.0 {
+            edges / (nodes * (nodes - 1.0))
+        } else {
+            0.0
+        };
+
+        Self {
+            num_segments: segments.len(),
+            num_nodes,
+            num_edges,
+            avg_degree: Stat::Estimate(avg_degree),
+            max_out_degree: Stat::exact_if(
+                single_segment,
+                segments
+                    .iter()
+                    .map(|segment| segment.max_out_degree)
+                    .max()
+                    .unwrap_or_default(),
+            ),
+            max_in_degree: Stat::exact_if(
+                single_segment,
+                segments
+                    .iter()
+                    .map(|segment| segment.max_in_degree)
+                    .max()
+                    .unwrap_or_default(),
+            ),
+            density: Stat::Estimate(density),
+        }
+    }
+}
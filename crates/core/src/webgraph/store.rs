@@ -17,7 +17,7 @@
 use std::{fs::File, ops::Range, path::Path};
 
 use crate::{
-    webgraph::merge::{EdgeMerger, MergeIter},
+    webgraph::merge::{EdgeMerger, MergeIter, MergeLabel},
     webpage::html::links::RelFlags,
     Result,
 };
@@ -33,6 +33,7 @@ use fst::Automaton;
 use itertools::Itertools;
 
 use super::{
+    crawl_times::Supersession,
     merge::{MergeNode, MergeSegmentOrd, NodeDatum},
     Compression, EdgeLimit, FullNodeID, NodeID, SegmentEdge, StoredEdge,
 };
@@ -248,19 +249,29 @@ impl ConstSerializable for EdgeRange {
     }
 }
 
+/// The timestamp is stored as u32 seconds in what used to be the padding
+/// of the struct. Edges written before timestamps were introduced
+/// therefore deserialize with a `discovered_at` of 0.
+const STORED_DISCOVERED_AT_OFFSET: usize = NodeDatum::BYTES + RelFlags::BYTES;
+
 impl ConstSerializable for StoredEdge {
-    const BYTES: usize = std::mem::size_of::<StoredEdge>();
+    const BYTES: usize = STORED_DISCOVERED_AT_OFFSET + u32::BYTES;
 
     fn serialize(&self, buf: &mut [u8]) {
         self.other.serialize(&mut buf[..NodeDatum::BYTES]);
-        self.rel.serialize(&mut buf[NodeDatum::BYTES..]);
+        self.rel
+            .serialize(&mut buf[NodeDatum::BYTES..STORED_DISCOVERED_AT_OFFSET]);
+
+        let discovered_at = u32::try_from(self.discovered_at).unwrap_or(u32::MAX);
+        discovered_at.serialize(&mut buf[STORED_DISCOVERED_AT_OFFSET..]);
     }
 
     fn deserialize(buf: &[u8]) -> Self {
         let other = NodeDatum::deserialize(&buf[..NodeDatum::BYTES]);
-        let rel = RelFlags::deserialize(&buf[NodeDatum::BYTES..]);
+        let rel = RelFlags::deserialize(&buf[NodeDatum::BYTES..STORED_DISCOVERED_AT_OFFSET]);
+        let discovered_at = u32::deserialize(&buf[STORED_DISCOVERED_AT_OFFSET..]);
 
-        Self::new(other, rel)
+        Self::new(other, rel).with_discovered_at(discovered_at as u64)
     }
 }
 
@@ -301,6 +312,22 @@ impl CompressedLabelBlock {
     }
 }
 
+/// The label of an edge while it is merged, and whether the edge is from the latest crawl
+/// of its source node.
+struct CrawledLabel {
+    label: String,
+    latest: bool,
+}
+
+impl MergeLabel for CrawledLabel {
+    fn combine(edge: &mut StoredEdge<Self>, duplicate: StoredEdge<Self>) {
+        if duplicate.label.latest && !edge.label.latest {
+            edge.rel = duplicate.rel;
+            edge.label = duplicate.label;
+        }
+    }
+}
+
 pub struct EdgeStore {
     reversed: bool,
     ranges: RangesDb,
@@ -332,14 +359,24 @@ impl EdgeStore {
         self.hosts.optimize_read();
     }
 
+    /// The edges that are only in the superseded crawls of their source nodes are dropped.
+    /// The stores are keyed by the source nodes of their edges unless they are reversed.
     fn merge_postings_for_node<'a>(
         buf: &[MergeNode<MergeSegmentOrd>],
         stores: &'a [EdgeStore],
-    ) -> EdgeMerger<'a> {
+        supersession: Option<&'a Supersession>,
+    ) -> impl Iterator<Item = StoredEdge<String>> + 'a {
         let mut edges = Vec::new();
 
         for node in buf {
-            let store = &stores[node.ord().as_usize()];
+            let ord = node.ord().as_usize();
+            let store = &stores[ord];
+
+            let reversed = store.reversed;
+            let node_is_latest = reversed
+                || supersession
+                    .map_or(true, |supersession| supersession.is_latest(ord, &node.id()));
+
             let stored_edges = store.edges.slice(usize_range(node.range().range.clone()));
             let labels = store
                 .edge_labels
@@ -347,20 +384,33 @@ impl EdgeStore {
                 .map(|r| r.decompress())
                 .flat_map(|block| block.labels.into_iter());
 
-            edges.push(
-                stored_edges
-                    .zip_eq(labels)
-                    .map(|(edge, label)| edge.with_label(label)),
-            );
+            edges.push(stored_edges.zip_eq(labels).map(move |(edge, label)| {
+                let latest = match supersession {
+                    Some(supersession) if reversed => {
+                        supersession.is_latest(ord, &edge.other.node())
+                    }
+                    _ => node_is_latest,
+                };
+
+                edge.with_label(CrawledLabel { label, latest })
+            }));
         }
 
         EdgeMerger::new(edges)
+            .filter(|edge| edge.label.latest)
+            .map(|edge| StoredEdge {
+                other: edge.other,
+                rel: edge.rel,
+                label: edge.label.label,
+                discovered_at: edge.discovered_at,
+            })
     }
 
     fn merge_postings<P: AsRef<Path>>(
         stores: &[EdgeStore],
         label_compression: Compression,
         folder: P,
+        supersession: Option<&Supersession>,
     ) -> Result<Self> {
         let reversed = stores[0].reversed;
         let mut ranges = RangesDb::open(folder.as_ref().join("ranges"));
@@ -398,7 +448,7 @@ impl EdgeStore {
                 continue;
             }
 
-            let edges = Self::merge_postings_for_node(&buf, stores);
+            let edges = Self::merge_postings_for_node(&buf, stores, supersession);
 
             // write postings
             let node_sort_key = buf[0].range().sort_key;
@@ -434,6 +484,11 @@ impl EdgeStore {
                 }
             }
 
+            // every edge of the node was superseded
+            if first_node_offset.is_none() {
+                continue;
+            }
+
             let label_range = Range {
                 start: first_label_offset.unwrap().start,
                 end: last_label_offset.unwrap().start + last_label_offset.unwrap().num_bytes,
@@ -475,6 +530,7 @@ impl EdgeStore {
         stores: Vec<EdgeStore>,
         label_compression: Compression,
         path: P,
+        supersession: Option<&Supersession>,
     ) -> Result<()> {
         if stores.is_empty() {
             return Ok(());
@@ -489,7 +545,7 @@ impl EdgeStore {
             bail!("Cannot merge stores with different reversed flags");
         }
 
-        let mut res = Self::merge_postings(&stores, label_compression, path)?;
+        let mut res = Self::merge_postings(&stores, label_compression, path, supersession)?;
 
         for store in stores {
             res.hosts.merge(store.hosts);
@@ -501,6 +557,30 @@ impl EdgeStore {
     }
 
     pub fn get_with_label(&self, node: &NodeID, limit: &EdgeLimit) -> Vec<SegmentEdge<String>> {
+        let mut edges = Vec::new();
+        self.get_with_label_into(node, limit, &mut edges);
+        edges
+    }
+
+    /// Like [`EdgeStore::get_with_label`], but the edges are added to `out`.
+    pub fn get_with_label_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        out: &mut Vec<SegmentEdge<String>>,
+    ) {
+        self.get_with_label_matching_into(node, limit, |_| true, out)
+    }
+
+    /// Like [`EdgeStore::get_with_label_into`], but only the edges that match `filter`.
+    /// The limit applies to the matching edges, so no edges are read after it is reached.
+    pub fn get_with_label_matching_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        filter: impl Fn(&SegmentEdge<String>) -> bool,
+        out: &mut Vec<SegmentEdge<String>>,
+    ) {
         let node_bytes = node.as_u64().to_le_bytes();
 
         match (
@@ -517,12 +597,9 @@ impl EdgeStore {
                     .map(|r| r.decompress())
                     .flat_map(|block| block.labels.into_iter());
 
-                let labels = limit.apply(labels);
-
                 let edges = self.edges.slice(usize_range(node_range.range));
-                let edges = limit.apply(edges);
 
-                labels
+                let edges = labels
                     .zip_eq(edges)
                     .map(|(label, edge)| {
                         if self.reversed {
@@ -531,6 +608,7 @@ impl EdgeStore {
                                 to: NodeDatum::new(*node, node_range.sort_key),
                                 rel: edge.rel,
                                 label,
+                                discovered_at: edge.discovered_at,
                             }
                         } else {
                             SegmentEdge {
@@ -538,28 +616,121 @@ impl EdgeStore {
                                 to: edge.other,
                                 rel: edge.rel,
                                 label,
+                                discovered_at: edge.discovered_at,
                             }
                         }
                     })
-                    .collect()
+                    .filter(|edge| filter(edge));
+
+                out.extend(limit.apply(edges));
+            }
+            _ => {}
+        }
+    }
+
+    /// Like [`EdgeStore::get_with_label`], but only the edges where the sort key of the
+    /// other node is at most `max_sort_key`. The edges of a node are sorted by the sort key,
+    /// so the edges after the first one above the threshold are never read.
+    pub fn get_with_label_up_to(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        max_sort_key: u64,
+    ) -> Vec<SegmentEdge<String>> {
+        let mut edges = Vec::new();
+        self.get_with_label_up_to_into(node, limit, max_sort_key, &mut edges);
+        edges
+    }
+
+    pub fn get_with_label_up_to_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        max_sort_key: u64,
+        out: &mut Vec<SegmentEdge<String>>,
+    ) {
+        let node_bytes = node.as_u64().to_le_bytes();
+
+        match (
+            self.ranges.edges.get_raw(&node_bytes),
+            self.ranges.labels.get_raw(&node_bytes),
+        ) {
+            (Some(node_range_bytes), Some(edge_range_bytes)) => {
+                let node_range = EdgeRange::deserialize(node_range_bytes.as_bytes());
+                let edge_range: Range<u64> = Range::deserialize(edge_range_bytes.as_bytes());
+
+                let edges: Vec<_> = limit
+                    .apply(
+                        self.edges
+                            .slice(usize_range(node_range.range))
+                            .take_while(|edge| edge.other.sort_key() <= max_sort_key),
+                    )
+                    .collect();
+
+                let labels = self
+                    .edge_labels
+                    .slice(usize_range(edge_range))
+                    .map(|r| r.decompress())
+                    .flat_map(|block| block.labels.into_iter())
+                    .take(edges.len());
+
+                out.extend(labels.zip_eq(edges).map(|(label, edge)| {
+                    if self.reversed {
+                        SegmentEdge {
+                            from: edge.other,
+                            to: NodeDatum::new(*node, node_range.sort_key),
+                            rel: edge.rel,
+                            label,
+                            discovered_at: edge.discovered_at,
+                        }
+                    } else {
+                        SegmentEdge {
+                            from: NodeDatum::new(*node, node_range.sort_key),
+                            to: edge.other,
+                            rel: edge.rel,
+                            label,
+                            discovered_at: edge.discovered_at,
+                        }
+                    }
+                }));
             }
-            _ => Vec::new(),
+            _ => {}
         }
     }
 
     pub fn get_without_label(&self, node: &NodeID, limit: &EdgeLimit) -> Vec<SegmentEdge<()>> {
+        let mut edges = Vec::new();
+        self.get_without_label_into(node, limit, &mut edges);
+        edges
+    }
+
+    pub fn get_without_label_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        out: &mut Vec<SegmentEdge<()>>,
+    ) {
+        self.get_without_label_matching_into(node, limit, |_| true, out)
+    }
+
+    /// Like [`EdgeStore::get_without_label_into`], but only the edges that match `filter`.
+    /// The limit applies to the matching edges, so no edges are read after it is reached.
+    pub fn get_without_label_matching_into(
+        &self,
+        node: &NodeID,
+        limit: &EdgeLimit,
+        filter: impl Fn(&SegmentEdge<()>) -> bool,
+        out: &mut Vec<SegmentEdge<()>>,
+    ) {
         let node_bytes = node.as_u64().to_le_bytes();
 
         match self.ranges.edges.get_raw(&node_bytes) {
             Some(node_range_bytes) => {
                 let edge_range = EdgeRange::deserialize(node_range_bytes.as_bytes());
 
-                let edges = self.edges.slice(usize_range(edge_range.range));
-
-                let edges = limit.apply(edges);
-
-                edges
-                    .into_iter()
+                let edges = self
+                    .edges
+                    .slice(usize_range(edge_range.range))
                     .map(|edge| {
                         if self.reversed {
                             SegmentEdge {
@@ -567,6 +738,7 @@ impl EdgeStore {
                                 to: NodeDatum::new(*node, edge_range.sort_key),
                                 rel: edge.rel,
                                 label: (),
+                                discovered_at: edge.discovered_at,
                             }
                         } else {
                             SegmentEdge {
@@ -574,12 +746,15 @@ impl EdgeStore {
                                 to: edge.other,
                                 rel: edge.rel,
                                 label: (),
+                                discovered_at: edge.discovered_at,
                             }
                         }
                     })
-                    .collect()
+                    .filter(|edge| filter(edge));
+
+                out.extend(limit.apply(edges));
             }
-            _ => Vec::new(),
+            _ => {}
         }
     }
 
@@ -587,6 +762,22 @@ impl EdgeStore {
         self.hosts.get(host)
     }
 
+    /// The number of edges of every node with edges in the store.
+    pub fn degrees(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ranges.edges.iter_raw().map(|(_, val)| {
+            let range = EdgeRange::deserialize(val.as_bytes()).range;
+            range.end - range.start
+        })
+    }
+
+    /// The sort key of every node with edges in the store.
+    pub fn sort_keys(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ranges
+            .edges
+            .iter_raw()
+            .map(|(_, val)| EdgeRange::deserialize(val.as_bytes()).sort_key)
+    }
+
     pub fn iter_without_label(&self) -> impl Iterator<Item = SegmentEdge<()>> + '_ + Send + Sync {
         self.ranges.edges.iter_raw().flat_map(move |(key, val)| {
             let node = u64::from_le_bytes((key.as_bytes()).try_into().unwrap());
@@ -606,6 +797,7 @@ impl EdgeStore {
                         to: NodeDatum::new(node, edge_range.sort_key),
                         rel: edge.rel,
                         label: (),
+                        discovered_at: edge.discovered_at,
                     }
                 } else {
                     SegmentEdge {
@@ -613,6 +805,48 @@ impl EdgeStore {
                         to: edge.other,
                         rel: edge.rel,
                         label: (),
+                        discovered_at: edge.discovered_at,
+                    }
+                }
+            })
+        })
+    }
+
+    /// Like [`EdgeStore::iter_without_label`], but with the labels of the edges. The labels
+    /// of one node are decompressed at a time.
+    pub fn iter_with_label(&self) -> impl Iterator<Item = SegmentEdge<String>> + '_ + Send + Sync {
+        self.ranges.merge_nodes().flat_map(move |node| {
+            let labels = self
+                .edge_labels
+                .slice(usize_range(node.labels()))
+                .map(|r| r.decompress())
+                .flat_map(|block| block.labels.into_iter())
+                .collect::<Vec<_>>();
+
+            let edges = self
+                .edges
+                .slice(usize_range(node.range().range))
+                .collect::<Vec<_>>();
+
+            let id = node.id();
+            let sort_key = node.range().sort_key;
+
+            edges.into_iter().zip_eq(labels).map(move |(edge, label)| {
+                if self.reversed {
+                    SegmentEdge {
+                        from: edge.other,
+                        to: NodeDatum::new(id, sort_key),
+                        rel: edge.rel,
+                        label,
+                        discovered_at: edge.discovered_at,
+                    }
+                } else {
+                    SegmentEdge {
+                        from: NodeDatum::new(id, sort_key),
+                        to: edge.other,
+                        rel: edge.rel,
+                        label,
+                        discovered_at: edge.discovered_at,
                     }
                 }
             })
@@ -648,6 +882,7 @@ mod tests {
             },
             label: "test".to_string(),
             rel: RelFlags::default(),
+            discovered_at: 0,
         };
 
         kv.put(e.clone());
@@ -688,6 +923,7 @@ mod tests {
             },
             label: "test".to_string(),
             rel: RelFlags::default(),
+            discovered_at: 0,
         };
 
         kv.put(e.clone());
@@ -723,6 +959,7 @@ mod tests {
                 },
                 label: "test".to_string(),
                 rel: RelFlags::default(),
+                discovered_at: 0,
             };
 
             kv.put(e.clone());
@@ -763,6 +1000,7 @@ mod tests {
             },
             label: "1".to_string(),
             rel: RelFlags::default(),
+            discovered_at: 0,
         };
 
         let e2 = InsertableEdge {
@@ -776,6 +1014,7 @@ mod tests {
             },
             label: "2".to_string(),
             rel: RelFlags::default(),
+            discovered_at: 0,
         };
 
         let e3 = InsertableEdge {
@@ -789,6 +1028,7 @@ mod tests {
             },
             label: "3".to_string(),
             rel: RelFlags::default(),
+            discovered_at: 0,
         };
 
         kv.put(e1.clone());
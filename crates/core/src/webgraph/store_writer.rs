@@ -323,7 +323,7 @@ impl FinalEdgeStoreWriter {
 
             let datum = NodeDatum::new(node, sort_key);
             let rel = edge.rel;
-            stored_edges.push(StoredEdge::new(datum, rel));
+            stored_edges.push(StoredEdge::new(datum, rel).with_discovered_at(edge.discovered_at));
         }
 
         let edge_labels: Vec<_> = edge_labels
@@ -0,0 +1,289 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{
+    DisavowFilter, EdgeLimit, FullEdge, MinCentralityFilter, Node, RelFlagsFilter, Webgraph,
+};
+
+/// The backlinks of every host under a registrable domain, grouped by host.
+#[derive(Debug, Clone)]
+pub struct SubdomainBacklinks {
+    pub domain: Node,
+    pub hosts: BTreeMap<Node, Vec<FullEdge>>,
+}
+
+impl SubdomainBacklinks {
+    pub fn num_backlinks(&self) -> usize {
+        self.hosts.values().map(|edges| edges.len()).sum()
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = &FullEdge> + '_ {
+        self.hosts.values().flatten()
+    }
+
+    /// Number of distinct hosts linking to any host under the domain.
+    pub fn num_linking_hosts(&self) -> usize {
+        self.edges()
+            .map(|edge| edge.from.clone().into_host())
+            .collect::<BTreeSet<_>>()
+            .len()
+    }
+}
+
+/// Aggregate the backlinks across the registrable domain of a node and all its subdomains,
+/// so `blog.example.com` and `shop.example.com` are both counted towards `example.com`.
+///
+/// The graph has no index from a domain to its subdomains, so this scans every node in the graph.
+pub struct SubdomainBacklinksQuery {
+    domain: Node,
+    limit: EdgeLimit,
+    min_centrality: Option<MinCentralityFilter>,
+    rel: Option<RelFlagsFilter>,
+    disavow: Option<DisavowFilter>,
+}
+
+impl SubdomainBacklinksQuery {
+    /// Returns `None` if the node has no registrable domain (e.g. it is a public suffix).
+    pub fn new(node: &Node) -> Option<Self> {
+        Some(Self {
+            domain: node.registrable_domain()?,
+            limit: EdgeLimit::Unlimited,
+            min_centrality: None,
+            rel: None,
+            disavow: None,
+        })
+    }
+
+    /// Limit the number of backlinks retrieved for each node under the domain.
+    pub fn with_limit(mut self, limit: EdgeLimit) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Only count backlinks from hosts that pass the centrality filter.
+    pub fn with_min_centrality(mut self, filter: MinCentralityFilter) -> Self {
+        self.min_centrality = Some(filter);
+        self
+    }
+
+    /// Only count backlinks with rel flags that pass the filter,
+    /// e.g. [`RelFlagsFilter::follow`] to ignore `nofollow` links.
+    pub fn with_rel_filter(mut self, filter: RelFlagsFilter) -> Self {
+        self.rel = Some(filter);
+        self
+    }
+
+    /// Leave out the backlinks from disavowed hosts, or with
+    /// [`super::DisavowMode::Only`] only count those.
+    pub fn with_disavow(mut self, filter: DisavowFilter) -> Self {
+        self.disavow = Some(filter);
+        self
+    }
+
+    pub fn domain(&self) -> &Node {
+        &self.domain
+    }
+
+    fn is_under_domain(&self, node: &Node) -> bool {
+        node.clone().into_host() == self.domain || node.is_subdomain_of(&self.domain)
+    }
+
+    pub fn run(&self, graph: &Webgraph) -> SubdomainBacklinks {
+        let mut hosts: BTreeMap<Node, Vec<FullEdge>> = BTreeMap::new();
+
+        for (node, _) in graph.node_ids() {
+            if !self.is_under_domain(&node) {
+                continue;
+            }
+
+            let edges =
+                if self.min_centrality.is_none() && self.rel.is_none() && self.disavow.is_none() {
+                    graph.ingoing_edges(node.clone(), self.limit)
+                } else {
+                    graph.ingoing_edges_filtered(
+                        node.clone(),
+                        self.min_centrality,
+                        self.rel,
+                        self.disavow.as_ref(),
+                        self.limit,
+                    )
+                };
+
+            if !edges.is_empty() {
+                hosts.entry(node.into_host()).or_default().extend(edges);
+            }
+        }
+
+        SubdomainBacklinks {
+            domain: self.domain.clone(),
+            hosts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        executor::Executor,
+        webgraph::{Compression, WebgraphWriter},
+        webpage::html::links::RelFlags,
+    };
+
+    use super::*;
+
+    fn graph() -> Webgraph {
+        let mut writer = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        );
+
+        for (from, to) in [
+            ("a.com", "blog.x.com"),
+            ("b.com", "blog.x.com"),
+            ("a.com", "shop.x.com"),
+            ("c.com", "x.com"),
+            ("c.com", "notx.com"),
+            ("a.com", "blog.y.co.uk"),
+            ("b.com", "y.co.uk"),
+            ("c.com", "z.co.uk"),
+        ] {
+            writer.insert(
+                Node::from(from),
+                Node::from(to),
+                String::new(),
+                RelFlags::default(),
+            );
+        }
+
+        writer.commit();
+
+        writer.finalize()
+    }
+
+    fn num_backlinks(backlinks: &SubdomainBacklinks, host: &str) -> usize {
+        backlinks
+            .hosts
+            .get(&Node::from(host))
+            .map(|edges| edges.len())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn groups_subdomains() {
+        let graph = graph();
+
+        let query = SubdomainBacklinksQuery::new(&Node::from("shop.x.com")).unwrap();
+        assert_eq!(query.domain(), &Node::from("x.com"));
+
+        let backlinks = query.run(&graph);
+
+        assert_eq!(backlinks.domain, Node::from("x.com"));
+        assert_eq!(backlinks.hosts.len(), 3);
+        assert_eq!(num_backlinks(&backlinks, "blog.x.com"), 2);
+        assert_eq!(num_backlinks(&backlinks, "shop.x.com"), 1);
+        assert_eq!(num_backlinks(&backlinks, "x.com"), 1);
+        assert_eq!(num_backlinks(&backlinks, "notx.com"), 0);
+        assert_eq!(backlinks.num_backlinks(), 4);
+    }
+
+    #[test]
+    fn multi_label_suffix() {
+        let graph = graph();
+
+        let backlinks = SubdomainBacklinksQuery::new(&Node::from("y.co.uk"))
+            .unwrap()
+            .run(&graph);
+
+        assert_eq!(backlinks.hosts.len(), 2);
+        assert_eq!(num_backlinks(&backlinks, "blog.y.co.uk"), 1);
+        assert_eq!(num_backlinks(&backlinks, "y.co.uk"), 1);
+        assert!(backlinks
+            .edges()
+            .all(|edge| edge.to != Node::from("z.co.uk")));
+
+        assert!(SubdomainBacklinksQuery::new(&Node::from("co.uk")).is_none());
+    }
+
+    #[test]
+    fn follow_only() {
+        let mut writer = WebgraphWriter::new(
+            crate::gen_temp_path(),
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        );
+
+        for (from, to, rel) in [
+            ("a.com", "blog.x.com", RelFlags::default()),
+            ("b.com", "blog.x.com", RelFlags::NOFOLLOW),
+            ("b.com/page", "shop.x.com", RelFlags::IS_IN_FOOTER),
+            ("c.com", "x.com", RelFlags::SPONSORED),
+            (
+                "d.com",
+                "x.com",
+                RelFlags::NOFOLLOW | RelFlags::IS_IN_FOOTER,
+            ),
+            ("e.com", "shop.x.com", RelFlags::TAG),
+        ] {
+            writer.insert(Node::from(from), Node::from(to), String::new(), rel);
+        }
+
+        let graph = writer.finalize();
+        let query = SubdomainBacklinksQuery::new(&Node::from("x.com")).unwrap();
+
+        let all = query.run(&graph);
+        assert_eq!(all.num_backlinks(), 6);
+        assert_eq!(all.num_linking_hosts(), 5);
+
+        let query = query.with_rel_filter(RelFlagsFilter::follow());
+        let follow = query.run(&graph);
+        assert_eq!(follow.num_backlinks(), 3);
+        assert_eq!(follow.num_linking_hosts(), 3);
+        assert_eq!(num_backlinks(&follow, "blog.x.com"), 1);
+        assert_eq!(num_backlinks(&follow, "shop.x.com"), 2);
+        assert_eq!(num_backlinks(&follow, "x.com"), 0);
+
+        let limited = query.with_limit(EdgeLimit::Limit(1)).run(&graph);
+        assert_eq!(num_backlinks(&limited, "blog.x.com"), 1);
+        assert_eq!(num_backlinks(&limited, "shop.x.com"), 1);
+
+        let footer = SubdomainBacklinksQuery::new(&Node::from("x.com"))
+            .unwrap()
+            .with_rel_filter(RelFlagsFilter {
+                required: RelFlags::IS_IN_FOOTER,
+                excluded: RelFlags::empty(),
+            })
+            .run(&graph);
+        assert_eq!(footer.num_backlinks(), 2);
+    }
+
+    #[test]
+    fn limit() {
+        let graph = graph();
+
+        let backlinks = SubdomainBacklinksQuery::new(&Node::from("x.com"))
+            .unwrap()
+            .with_limit(EdgeLimit::Limit(1))
+            .run(&graph);
+
+        assert_eq!(num_backlinks(&backlinks, "blog.x.com"), 1);
+        assert_eq!(backlinks.num_backlinks(), 3);
+    }
+}
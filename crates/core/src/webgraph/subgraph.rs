@@ -0,0 +1,280 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::config::WebgraphGranularity;
+use crate::executor::Executor;
+
+use super::shortest_path::dijkstra_multi;
+use super::{Compression, Edge, EdgeLimit, Node, NodeID, Webgraph, WebgraphWriter};
+
+/// The most nodes in the neighborhood from [`Webgraph::neighbors_within`].
+pub const MAX_NEIGHBORHOOD_NODES: usize = 10_000;
+
+impl Webgraph {
+    /// The subgraph induced by `nodes`, i.e. the edges where both endpoints are in `nodes`,
+    /// written to a new graph at `path`.
+    ///
+    /// All the edges of the graph are scanned once, and the edges of the subgraph along with
+    /// the names of `nodes` are kept in memory until the new graph is written. This is fine for
+    /// focused analysis of a few thousand nodes, but a node set covering a large part of the
+    /// graph needs memory in the order of the edges between them. The anchor texts of the
+    /// edges are not part of the scan, so the edges of the subgraph have empty labels.
+    pub fn subgraph<P: AsRef<Path>>(&self, nodes: &HashSet<NodeID>, path: P) -> Webgraph {
+        let edges: Vec<_> = self
+            .par_edges()
+            .filter(|edge| nodes.contains(&edge.from) && nodes.contains(&edge.to))
+            .collect();
+
+        let names: HashMap<NodeID, Node> = nodes
+            .iter()
+            .filter_map(|id| self.id2node(id).map(|node| (*id, node)))
+            .collect();
+
+        let mut writer = WebgraphWriter::new(
+            path,
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        );
+
+        for edge in edges {
+            if let (Some(from), Some(to)) = (names.get(&edge.from), names.get(&edge.to)) {
+                writer.insert_with_timestamp(
+                    from.clone(),
+                    to.clone(),
+                    String::new(),
+                    edge.rel,
+                    edge.discovered_at,
+                );
+            }
+        }
+
+        writer.finalize()
+    }
+
+    /// The egonet of `source`: the nodes within `radius` links of it in either direction
+    /// and the edges among them, written to a new graph at `path`.
+    ///
+    /// The nearest nodes are kept if there are more than [`MAX_NEIGHBORHOOD_NODES`] nodes
+    /// within the radius. With [`WebgraphGranularity::Host`] the pages of the neighborhood
+    /// are merged into their hosts, so the neighborhood of a page in a page graph becomes a
+    /// graph of the hosts that link to and from it. Like [`Webgraph::subgraph`], the edges
+    /// have empty labels.
+    pub fn neighbors_within<P: AsRef<Path>>(
+        &self,
+        source: &Node,
+        radius: u8,
+        granularity: WebgraphGranularity,
+        path: P,
+    ) -> Webgraph {
+        let distances = dijkstra_multi(
+            &[source.id()],
+            |node| {
+                let mut edges = self.raw_outgoing_edges(&node, EdgeLimit::Unlimited);
+                edges.extend(
+                    self.raw_ingoing_edges(&node, EdgeLimit::Unlimited)
+                        .into_iter()
+                        .map(|edge| Edge {
+                            from: edge.to,
+                            to: edge.from,
+                            ..edge
+                        }),
+                );
+                edges
+            },
+            |edge| edge.to,
+            Some(radius),
+            Some(MAX_NEIGHBORHOOD_NODES),
+        );
+
+        let names: HashMap<NodeID, Node> = distances
+            .into_iter()
+            .filter(|(_, distance)| *distance <= radius)
+            .filter_map(|(id, _)| {
+                self.id2node(&id).map(|node| {
+                    let node = match granularity {
+                        WebgraphGranularity::Host => node.into_host(),
+                        WebgraphGranularity::Page => node,
+                    };
+
+                    (id, node)
+                })
+            })
+            .collect();
+
+        let mut writer = WebgraphWriter::new(
+            path,
+            Executor::single_thread(),
+            Compression::default(),
+            None,
+        );
+
+        let mut inserted = HashSet::new();
+
+        for (id, from) in &names {
+            for edge in self.raw_outgoing_edges(id, EdgeLimit::Unlimited) {
+                let Some(to) = names.get(&edge.to) else {
+                    continue;
+                };
+
+                // the pages of two hosts can link to each other many times
+                if inserted.insert((from.id(), to.id())) {
+                    writer.insert_with_timestamp(
+                        from.clone(),
+                        to.clone(),
+                        String::new(),
+                        edge.rel,
+                        edge.discovered_at,
+                    );
+                }
+            }
+        }
+
+        writer.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_fixtures::GraphFixture, webgraph::tests::test_graph};
+
+    use super::*;
+
+    fn sorted_edges(graph: &Webgraph) -> Vec<(String, String)> {
+        let mut edges: Vec<_> = graph
+            .edges()
+            .map(|edge| {
+                (
+                    graph.id2node(&edge.from).unwrap().as_str().to_string(),
+                    graph.id2node(&edge.to).unwrap().as_str().to_string(),
+                )
+            })
+            .collect();
+        edges.sort();
+        edges.dedup();
+        edges
+    }
+
+    #[test]
+    fn induced_edges_only() {
+        let graph = test_graph();
+
+        let nodes: HashSet<_> = ["A", "B", "C"]
+            .into_iter()
+            .map(|node| Node::from(node).id())
+            .collect();
+
+        let subgraph = graph.subgraph(&nodes, crate::gen_temp_path());
+
+        let mut edges: Vec<_> = subgraph
+            .edges()
+            .map(|edge| {
+                (
+                    subgraph.id2node(&edge.from).unwrap().as_str().to_string(),
+                    subgraph.id2node(&edge.to).unwrap().as_str().to_string(),
+                )
+            })
+            .collect();
+        edges.sort();
+        edges.dedup();
+
+        // D -> C crosses the boundary of the node set
+        assert_eq!(
+            edges,
+            vec![
+                ("a".to_string(), "b".to_string()),
+                ("a".to_string(), "c".to_string()),
+                ("b".to_string(), "c".to_string()),
+                ("c".to_string(), "a".to_string()),
+            ]
+        );
+
+        assert!(subgraph.id2node(&Node::from("D").id()).is_none());
+        assert!(subgraph
+            .ingoing_edges(Node::from("C"), EdgeLimit::Unlimited)
+            .iter()
+            .all(|edge| edge.from != Node::from("D")));
+    }
+
+    #[test]
+    fn egonet_of_direct_neighbors() {
+        let graph = test_graph();
+
+        // A links to B and B links to C
+        let egonet = graph.neighbors_within(
+            &Node::from("B"),
+            1,
+            WebgraphGranularity::Page,
+            crate::gen_temp_path(),
+        );
+
+        assert_eq!(
+            sorted_edges(&egonet),
+            vec![
+                ("a".to_string(), "b".to_string()),
+                ("a".to_string(), "c".to_string()),
+                ("b".to_string(), "c".to_string()),
+                ("c".to_string(), "a".to_string()),
+            ]
+        );
+        assert!(egonet.id2node(&Node::from("D").id()).is_none());
+
+        let egonet = graph.neighbors_within(
+            &Node::from("B"),
+            2,
+            WebgraphGranularity::Page,
+            crate::gen_temp_path(),
+        );
+        assert_eq!(sorted_edges(&egonet), sorted_edges(&graph));
+    }
+
+    #[test]
+    fn egonet_of_hosts() {
+        let graph = GraphFixture::new(0)
+            .edges(
+                [
+                    ("https://a.com/1", "https://b.com/1"),
+                    ("https://a.com/2", "https://b.com/1"),
+                    ("https://b.com/1", "https://a.com/1"),
+                    ("https://c.com/1", "https://a.com/2"),
+                ]
+                .map(|(from, to)| (Node::from(from), Node::from(to), String::new())),
+            )
+            .build()
+            .into_graph();
+
+        let egonet = graph.neighbors_within(
+            &Node::from("https://b.com/1"),
+            1,
+            WebgraphGranularity::Host,
+            crate::gen_temp_path(),
+        );
+
+        assert_eq!(
+            sorted_edges(&egonet),
+            vec![
+                ("a.com".to_string(), "b.com".to_string()),
+                ("b.com".to_string(), "a.com".to_string()),
+            ]
+        );
+        assert_eq!(egonet.edges().count(), 2);
+    }
+}
@@ -29,6 +29,7 @@ pub struct WebgraphWriter {
     id2node: Id2NodeDb,
     executor: Executor,
     meta: Meta,
+    max_label_length: usize,
 }
 
 impl WebgraphWriter {
@@ -70,9 +71,33 @@ impl WebgraphWriter {
             id2node: Id2NodeDb::open(path.as_ref().join("id2node")),
             executor,
             meta,
+            max_label_length: MAX_LABEL_LENGTH,
         }
     }
 
+    /// Store at most `max_label_length` characters of the labels. Edges with longer labels
+    /// get the [`RelFlags::LABEL_TRUNCATED`] flag.
+    pub fn with_max_label_length(mut self, max_label_length: usize) -> Self {
+        self.max_label_length = max_label_length;
+        self
+    }
+
+    /// Track the latest crawl time of the source of every edge, so the edges can be superseded
+    /// by a later crawl of the source when the segments are merged with
+    /// [`Webgraph::merge_all_segments_superseding`]. The crawl time of an edge is the time it
+    /// is inserted with. Pages without any edges must be recorded with
+    /// [`WebgraphWriter::record_crawl`] for their earlier edges to be superseded.
+    pub fn with_crawl_times(mut self) -> Self {
+        self.segment.track_crawl_times();
+        self
+    }
+
+    /// Record that the node was crawled at `crawled_at` (seconds since the unix epoch), so
+    /// its edges from earlier crawls are superseded even if it has no edges now.
+    pub fn record_crawl(&mut self, node: &Node, crawled_at: u64) {
+        self.segment.record_crawl(node.id(), crawled_at);
+    }
+
     pub fn id2node(&self, id: &NodeID) -> Option<Node> {
         self.id2node.get(id)
     }
@@ -86,10 +111,27 @@ impl WebgraphWriter {
     }
 
     pub fn insert(&mut self, from: Node, to: Node, label: String, rel: RelFlags) {
+        self.insert_with_timestamp(from, to, label, rel, 0);
+    }
+
+    /// Insert an edge that was discovered at `discovered_at` (seconds since the unix epoch).
+    pub fn insert_with_timestamp(
+        &mut self,
+        from: Node,
+        to: Node,
+        mut label: String,
+        mut rel: RelFlags,
+        discovered_at: u64,
+    ) {
         if from == to {
             return;
         }
 
+        if let Some((end, _)) = label.char_indices().nth(self.max_label_length) {
+            label.truncate(end);
+            rel |= RelFlags::LABEL_TRUNCATED;
+        }
+
         let (from_id, to_id) = (
             self.id_or_assign(from.clone()),
             self.id_or_assign(to.clone()),
@@ -98,8 +140,9 @@ impl WebgraphWriter {
         let edge = InsertableEdge {
             from: from_id,
             to: to_id,
-            label: label.chars().take(MAX_LABEL_LENGTH).collect(),
+            label,
             rel,
+            discovered_at,
         };
 
         self.segment.insert(edge);
@@ -113,12 +156,17 @@ impl WebgraphWriter {
     pub fn finalize(mut self) -> Webgraph {
         self.commit();
 
-        Webgraph {
+        let mut graph = Webgraph {
             path: self.path,
             segments: vec![self.segment.finalize()],
             executor: self.executor.into(),
             id2node: self.id2node,
             meta: self.meta,
-        }
+            edge_buffer_pool: Default::default(),
+        };
+
+        graph.refresh_centrality_quantiles();
+
+        graph
     }
 }
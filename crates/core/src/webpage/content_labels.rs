@@ -0,0 +1,191 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Labels for content that warrants a warning, like graphic imagery in the news.
+//!
+//! A page has a set of labels, where the empty set means that nothing was flagged. The
+//! labels are stored as flags in a fast field when the page is indexed and are shown on the
+//! results, so the frontend can blur the thumbnails of flagged results behind a
+//! click-through. The labels of the safety classifier are mapped to content labels, so
+//! pages classified as `NSFW` are labelled [`ContentLabel::Adult`].
+
+use std::fmt::Display;
+
+use utoipa::ToSchema;
+
+use super::safety_classifier;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContentLabel {
+    Adult,
+    Violence,
+    MedicalGraphic,
+}
+
+impl ContentLabel {
+    pub const ALL: [ContentLabel; 3] = [
+        ContentLabel::Adult,
+        ContentLabel::Violence,
+        ContentLabel::MedicalGraphic,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentLabel::Adult => "adult",
+            ContentLabel::Violence => "violence",
+            ContentLabel::MedicalGraphic => "medical-graphic",
+        }
+    }
+
+    fn flag(&self) -> u64 {
+        match self {
+            ContentLabel::Adult => 1 << 0,
+            ContentLabel::Violence => 1 << 1,
+            ContentLabel::MedicalGraphic => 1 << 2,
+        }
+    }
+}
+
+impl Display for ContentLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl TryFrom<&str> for ContentLabel {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        ContentLabel::ALL
+            .into_iter()
+            .find(|label| label.as_str() == value)
+            .ok_or_else(|| format!("invalid content label: {}", value))
+    }
+}
+
+/// A set of [`ContentLabel`]s, stored as flags.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+)]
+pub struct ContentLabels(u64);
+
+impl ContentLabels {
+    pub fn from_flags(flags: u64) -> Self {
+        let known = ContentLabel::ALL
+            .iter()
+            .fold(0, |flags, label| flags | label.flag());
+
+        Self(flags & known)
+    }
+
+    pub fn flags(&self) -> u64 {
+        self.0
+    }
+
+    pub fn insert(&mut self, label: ContentLabel) {
+        self.0 |= label.flag();
+    }
+
+    pub fn contains(&self, label: ContentLabel) -> bool {
+        self.0 & label.flag() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ContentLabel> + '_ {
+        ContentLabel::ALL
+            .into_iter()
+            .filter(|label| self.contains(*label))
+    }
+}
+
+impl FromIterator<ContentLabel> for ContentLabels {
+    fn from_iter<T: IntoIterator<Item = ContentLabel>>(iter: T) -> Self {
+        let mut labels = Self::default();
+
+        for label in iter {
+            labels.insert(label);
+        }
+
+        labels
+    }
+}
+
+impl From<safety_classifier::Label> for ContentLabels {
+    fn from(label: safety_classifier::Label) -> Self {
+        match label {
+            safety_classifier::Label::SFW => Self::default(),
+            safety_classifier::Label::NSFW => [ContentLabel::Adult].into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_roundtrip() {
+        let labels: ContentLabels = [ContentLabel::MedicalGraphic, ContentLabel::Adult]
+            .into_iter()
+            .collect();
+
+        assert_eq!(ContentLabels::from_flags(labels.flags()), labels);
+        assert_eq!(
+            labels.iter().collect::<Vec<_>>(),
+            vec![ContentLabel::Adult, ContentLabel::MedicalGraphic]
+        );
+        assert!(!labels.contains(ContentLabel::Violence));
+
+        // flags of unknown labels are ignored
+        assert!(ContentLabels::from_flags(1 << 63).is_empty());
+
+        for label in ContentLabel::ALL {
+            assert_eq!(ContentLabel::try_from(label.as_str()), Ok(label));
+        }
+    }
+}
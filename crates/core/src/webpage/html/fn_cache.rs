@@ -65,6 +65,7 @@ cache! {
     pretokenize_title -> Result<PreTokenizedString>,
     pretokenize_all_text -> Result<PreTokenizedString>,
     pretokenize_clean_text -> PreTokenizedString,
+    pretokenize_boilerplate_text -> PreTokenizedString,
     pretokenize_url -> PreTokenizedString,
     pretokenize_url_for_site_operator -> PreTokenizedString,
     pretokenize_domain -> PreTokenizedString,
@@ -40,7 +40,7 @@ use crate::schema::Field;
 
 impl Html {
     pub fn pretokenize_title(&self) -> Result<PreTokenizedString> {
-        let title = self.title();
+        let title = self.indexed_title();
 
         if title.is_none() {
             return Err(Error::EmptyField("title").into());
@@ -66,6 +66,11 @@ impl Html {
         self.pretokenize_string(clean_text, text_field::CleanBody.into())
     }
 
+    pub fn pretokenize_boilerplate_text(&self) -> PreTokenizedString {
+        let boilerplate_text = self.boilerplate_text().cloned().unwrap_or_default();
+        self.pretokenize_string(boilerplate_text, text_field::Boilerplate.into())
+    }
+
     pub fn pretokenize_url(&self) -> PreTokenizedString {
         let url = self.url().to_string();
         self.pretokenize_string(url, text_field::Url.into())
@@ -108,7 +113,10 @@ impl Html {
     }
 
     fn pretokenize_string(&self, text: String, field: TextFieldEnum) -> PreTokenizedString {
-        self.pretokenize_string_with(text, field.tokenizer(self.lang()))
+        let tokenizer = field
+            .tokenizer(self.lang())
+            .with_kind(self.tokenizer_kind());
+        self.pretokenize_string_with(text, tokenizer)
     }
 
     fn pretokenize_string_with(
@@ -188,7 +196,7 @@ impl Html {
     }
 
     pub fn title_hash(&self) -> [u64; 2] {
-        split_u128(hash(self.title().unwrap_or_default()).0)
+        split_u128(hash(self.indexed_title().unwrap_or_default()).0)
     }
 
     pub fn as_tantivy(&self, schema: &tantivy::schema::Schema) -> Result<TantivyDocument> {
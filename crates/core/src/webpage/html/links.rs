@@ -122,6 +122,44 @@ bitflags! {
         const SCRIPT_TAG = 1 << 19;
         const META_TAG = 1 << 20;
         const SAME_ICANN_DOMAIN = 1 << 21;
+        /// The source redirected to the destination when it was crawled.
+        const REDIRECT = 1 << 22;
+        /// The anchor text was longer than the label length cap of the graph and was truncated.
+        const LABEL_TRUNCATED = 1 << 23;
+    }
+}
+
+/// Serialized as the lowercase names of the set flags, e.g. `["nofollow", "is_in_footer"]`.
+/// Bits without a name are not serialized.
+impl serde::Serialize for RelFlags {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter_names().map(|(name, _)| name.to_lowercase()))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for RelFlags {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        let mut res = RelFlags::empty();
+
+        for name in names {
+            match RelFlags::from_name(&name.to_uppercase()) {
+                Some(flag) => res |= flag,
+                None => {
+                    return Err(serde::de::Error::custom(format!(
+                        "unknown rel flag: {name}"
+                    )))
+                }
+            }
+        }
+
+        Ok(res)
     }
 }
 
@@ -504,6 +542,20 @@ impl Html {
 mod tests {
     use super::*;
 
+    #[test]
+    fn rel_flags_json() {
+        let flags = RelFlags::NOFOLLOW | RelFlags::PRIVACY_POLICY | RelFlags::IS_IN_FOOTER;
+
+        let json = serde_json::to_string(&flags).unwrap();
+        assert_eq!(json, r#"["nofollow","privacy_policy","is_in_footer"]"#);
+
+        let parsed: RelFlags = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, flags);
+
+        assert_eq!(serde_json::to_string(&RelFlags::empty()).unwrap(), "[]");
+        assert!(serde_json::from_str::<RelFlags>(r#"["not_a_flag"]"#).is_err());
+    }
+
     #[test]
     fn simple_favicon() {
         let raw = r#"
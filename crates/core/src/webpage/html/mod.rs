@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::{enum_map::EnumSet, Result};
+use crate::{enum_map::EnumSet, tokenizer::TokenizerKind, Result};
 use chrono::{DateTime, FixedOffset, Utc};
 use itertools::Itertools;
 use kuchiki::{traits::TendrilSink, NodeRef};
@@ -48,7 +48,9 @@ pub struct Html {
     root: NodeRef, // this is reference counted (cheap to clone)
     all_text: Option<String>,
     clean_text: Option<String>,
+    boilerplate_text: Option<String>,
     lang: Option<Lang>,
+    tokenizer_kind: TokenizerKind,
     robots: Option<EnumSet<RobotsMeta>>,
 }
 
@@ -75,7 +77,9 @@ impl Html {
             root,
             all_text: None,
             clean_text: None,
+            boilerplate_text: None,
             lang: None,
+            tokenizer_kind: TokenizerKind::default(),
             url,
             robots: None,
         };
@@ -113,6 +117,17 @@ impl Html {
         self.lang.as_ref()
     }
 
+    /// How the text of the page is split into words when it is indexed.
+    pub fn tokenizer_kind(&self) -> TokenizerKind {
+        self.tokenizer_kind
+    }
+
+    /// Override the tokenizer that was chosen from the language of the page. Must be
+    /// set before the page is indexed.
+    pub fn set_tokenizer_kind(&mut self, kind: TokenizerKind) {
+        self.tokenizer_kind = kind;
+    }
+
     pub fn canonical_url(&self) -> Option<Url> {
         let mut canonical_url = None;
 
@@ -137,6 +152,12 @@ impl Html {
         self.clean_text.as_ref()
     }
 
+    /// The text outside of the main content region of the page, like navigation, footers
+    /// and sidebars. Only set if a main content region was found.
+    pub fn boilerplate_text(&self) -> Option<&String> {
+        self.boilerplate_text.as_ref()
+    }
+
     fn all_text(&self) -> Option<String> {
         self.all_text.clone()
     }
@@ -437,6 +458,14 @@ impl Html {
             .and_then(|metadata| metadata.get("content").cloned())
     }
 
+    /// The text of the first `<h1>` of the page.
+    pub fn h1(&self) -> Option<String> {
+        self.root
+            .select_first("h1")
+            .map(|h1| h1.text_contents().trim().to_string())
+            .filter(|h1| !h1.is_empty())
+    }
+
     pub fn is_homepage(&self) -> bool {
         self.url().is_homepage()
     }
@@ -633,6 +662,72 @@ mod tests {
         assert!(!webpage.clean_text().unwrap().contains("not"));
     }
 
+    const BOILERPLATE_PAGE: &str = r#"
+        <html>
+            <head>
+                <title>An article</title>
+            </head>
+            <body>
+                <nav>
+                    <ul>
+                        <li><a href="/">Home</a></li>
+                        <li><a href="/news">News from all over the world</a></li>
+                    </ul>
+                </nav>
+                <div id="cookie-notice">
+                    <p>This website uses cookies to give you the best experience, to analyse the traffic and to personalise the ads that you see.</p>
+                </div>
+                <div class="sidebar">
+                    <p>Popular posts: the sidebar lists the most read posts, the latest posts, and the posts that are recommended by the editors.</p>
+                </div>
+                <article>
+                    <h1>The article</h1>
+                    <p>The article starts with a paragraph that is long enough to be the main content of the page, and it has commas, which helps.</p>
+                    <p>The article continues with another paragraph that is also long enough, and it also has commas, so the article is the main content.</p>
+                </article>
+                <footer>
+                    <p>Copyright of the website, all rights reserved, and a long list of legal text that nobody reads but is on every page.</p>
+                </footer>
+            </body>
+        </html>
+    "#;
+
+    #[test]
+    fn main_content_split() {
+        let webpage = Html::parse(BOILERPLATE_PAGE, "https://www.example.com").unwrap();
+
+        let clean_text = webpage.clean_text().unwrap();
+        assert!(clean_text.contains("The article starts with a paragraph"));
+        assert!(clean_text.contains("The article continues with another paragraph"));
+        assert!(!clean_text.contains("cookies"));
+        assert!(!clean_text.contains("Popular posts"));
+        assert!(!clean_text.contains("Copyright"));
+
+        let boilerplate = webpage.boilerplate_text().unwrap();
+        assert!(boilerplate.contains("News from all over the world"));
+        assert!(boilerplate.contains("cookies"));
+        assert!(boilerplate.contains("Popular posts"));
+        assert!(boilerplate.contains("Copyright"));
+        assert!(!boilerplate.contains("The article starts"));
+
+        let all_text = webpage.all_text().unwrap();
+        assert!(all_text.contains("cookies"));
+        assert!(all_text.contains("The article starts"));
+    }
+
+    #[test]
+    fn main_content_split_disabled() {
+        let mut webpage =
+            Html::parse_without_text(BOILERPLATE_PAGE, "https://www.example.com").unwrap();
+        webpage.parse_text_with(false);
+
+        assert!(webpage.boilerplate_text().is_none());
+
+        let clean_text = webpage.clean_text().unwrap();
+        assert!(clean_text.contains("The article starts with a paragraph"));
+        assert!(clean_text.contains("cookies"));
+    }
+
     #[test]
     fn co_uk_domain() {
         let raw = "";
@@ -16,12 +16,25 @@
 
 use whatlang::Lang;
 
-use crate::webpage::just_text::{JustText, Paragraph};
+use crate::{
+    tokenizer::TokenizerKind,
+    webpage::{
+        just_text::{JustText, Paragraph},
+        main_content,
+    },
+};
 
 use super::Html;
 
 impl Html {
     pub fn parse_text(&mut self) {
+        self.parse_text_with(true);
+    }
+
+    /// Extract the text of the page. If `main_content_split` is set and the page has a main
+    /// content region, the clean text only comes from the main content and the text outside
+    /// of it becomes the boilerplate text.
+    pub fn parse_text_with(&mut self, main_content_split: bool) {
         let paragraphs = JustText::paragraphs(self.root.clone());
 
         self.lang = paragraphs
@@ -37,8 +50,35 @@ impl Html {
                 })
             });
 
-        self.all_text = Html::calculate_all_text(&paragraphs, &self.lang.unwrap_or(Lang::Eng));
-        self.clean_text = Html::calculate_clean_text(&paragraphs, &self.lang.unwrap_or(Lang::Eng));
+        self.tokenizer_kind = TokenizerKind::for_lang(self.lang.as_ref());
+
+        let lang = self.lang.unwrap_or(Lang::Eng);
+
+        self.all_text = Html::calculate_all_text(&paragraphs, &lang);
+        self.boilerplate_text = None;
+
+        let main = if main_content_split {
+            main_content::find(&self.root)
+        } else {
+            None
+        };
+
+        match main {
+            Some(main) => {
+                let main_paragraphs = JustText::paragraphs(main.clone());
+                let boilerplate_paragraphs =
+                    JustText::paragraphs_excluding(self.root.clone(), &main);
+
+                // the main content is what the page is about, so it is kept even if
+                // the jusText classifier finds it too short
+                self.clean_text = Html::calculate_clean_text(&main_paragraphs, &lang)
+                    .or_else(|| Html::calculate_all_text(&main_paragraphs, &lang));
+                self.boilerplate_text = Html::calculate_all_text(&boilerplate_paragraphs, &lang);
+            }
+            None => {
+                self.clean_text = Html::calculate_clean_text(&paragraphs, &lang);
+            }
+        }
     }
 
     fn calculate_clean_text(paragraphs: &[Paragraph], lang: &Lang) -> Option<String> {
@@ -237,6 +237,16 @@ fn is_paragraph_breaking(elem: &ElementData) -> bool {
 
 impl JustText {
     pub fn paragraphs(root: NodeRef) -> Vec<Paragraph> {
+        Self::paragraphs_inner(root, None)
+    }
+
+    /// The paragraphs of `root` outside the subtree of `excluded`. The excluded subtree
+    /// breaks the paragraph it is part of.
+    pub fn paragraphs_excluding(root: NodeRef, excluded: &NodeRef) -> Vec<Paragraph> {
+        Self::paragraphs_inner(root, Some(excluded))
+    }
+
+    fn paragraphs_inner(root: NodeRef, excluded: Option<&NodeRef>) -> Vec<Paragraph> {
         let mut res = Vec::new();
 
         let mut preprocessor =
@@ -247,8 +257,30 @@ impl JustText {
         let mut paragraph = Paragraph::new();
 
         let mut heading_count = 0;
+        let mut inside_excluded = false;
 
         for edge in root.traverse() {
+            if let Some(excluded) = excluded {
+                match &edge {
+                    NodeEdge::Start(node) if node == excluded => {
+                        inside_excluded = true;
+
+                        if paragraph.contains_text() {
+                            res.push(paragraph);
+                        }
+
+                        paragraph = Paragraph::new();
+                        continue;
+                    }
+                    NodeEdge::End(node) if node == excluded => {
+                        inside_excluded = false;
+                        continue;
+                    }
+                    _ if inside_excluded => continue,
+                    _ => {}
+                }
+            }
+
             preprocessor.update(&edge);
             if preprocessor.is_inside_removed() {
                 continue;
@@ -0,0 +1,334 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Readability-style detection of the main content region of a page.
+//!
+//! Every paragraph-like element with enough text scores its parent and, with half the
+//! weight, its grandparent. Paragraphs score higher with more text and more commas.
+//! The candidates get an initial score from their tag and from their class and id, and
+//! the final score of a candidate is scaled down by its link density. Regions that are
+//! clearly boilerplate, like `<nav>`, `<footer>` or a cookie banner, are never scored.
+//!
+//! The best candidate is the main content of the page, unless no candidate scores high
+//! enough or the best candidate is the entire body, in which case the page has no
+//! separate main content.
+
+use std::collections::HashMap;
+
+use kuchiki::{ElementData, NodeRef};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+const MIN_PARAGRAPH_CHARS: usize = 25;
+const MIN_SCORE: f64 = 15.0;
+
+const BOILERPLATE_TAGS: [&str; 14] = [
+    "nav", "footer", "header", "aside", "form", "script", "style", "noscript", "iframe",
+    "template", "button", "select", "dialog", "svg",
+];
+
+const BOILERPLATE_ROLES: [&str; 6] = [
+    "navigation",
+    "banner",
+    "contentinfo",
+    "complementary",
+    "dialog",
+    "alertdialog",
+];
+
+const PARAGRAPH_TAGS: [&str; 4] = ["p", "pre", "td", "blockquote"];
+
+static UNLIKELY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)banner|breadcrumb|combx|comment|community|consent|cookie|disqus|footer|gdpr|menu|modal|navbar|popup|related|remark|replies|share|shoutbox|sidebar|skyscraper|social|sponsor|subscribe|newsletter|pager|pagination").unwrap()
+});
+
+static MAYBE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)and|article|body|column|content|main|shadow").unwrap());
+
+static POSITIVE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)article|body|content|entry|hentry|h-entry|main|page|post|text|blog|story")
+        .unwrap()
+});
+
+static NEGATIVE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)-ad-|hidden|banner|combx|comment|contact|cookie|consent|footer|gdpr|masthead|meta|outbrain|promo|related|scroll|share|shoutbox|sidebar|skyscraper|sponsor|shopping|tags|widget").unwrap()
+});
+
+fn tag(element: &ElementData) -> &str {
+    element.name.local.as_ref()
+}
+
+fn class_and_id(element: &ElementData) -> (String, String) {
+    let attributes = element.attributes.borrow();
+
+    (
+        attributes.get("class").unwrap_or_default().to_string(),
+        attributes.get("id").unwrap_or_default().to_string(),
+    )
+}
+
+fn is_boilerplate(element: &ElementData) -> bool {
+    let tag = tag(element);
+
+    if matches!(tag, "html" | "body" | "article" | "main") {
+        return false;
+    }
+
+    if BOILERPLATE_TAGS.contains(&tag) {
+        return true;
+    }
+
+    if let Some(role) = element.attributes.borrow().get("role") {
+        if BOILERPLATE_ROLES.contains(&role) {
+            return true;
+        }
+    }
+
+    let (class, id) = class_and_id(element);
+    let class_and_id = format!("{class} {id}");
+
+    UNLIKELY.is_match(&class_and_id) && !MAYBE.is_match(&class_and_id)
+}
+
+fn initial_score(element: &ElementData) -> f64 {
+    let mut score = match tag(element) {
+        "article" | "main" => 10.0,
+        "div" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+
+    let (class, id) = class_and_id(element);
+
+    for name in [class, id] {
+        if name.is_empty() {
+            continue;
+        }
+
+        if NEGATIVE.is_match(&name) {
+            score -= 25.0;
+        }
+
+        if POSITIVE.is_match(&name) {
+            score += 25.0;
+        }
+    }
+
+    if element.attributes.borrow().get("role") == Some("main") {
+        score += 25.0;
+    }
+
+    score
+}
+
+/// The fraction of the text of `node` that is inside links.
+#[allow(clippy::cast_precision_loss)]
+fn link_density(node: &NodeRef) -> f64 {
+    let text_len = node.text_contents().trim().chars().count();
+
+    if text_len == 0 {
+        return 0.0;
+    }
+
+    let link_len: usize = node
+        .descendants()
+        .filter(|node| {
+            node.as_element()
+                .map(|element| tag(element) == "a")
+                .unwrap_or(false)
+        })
+        .map(|link| link.text_contents().trim().chars().count())
+        .sum();
+
+    link_len as f64 / text_len as f64
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn paragraph_score(text: &str) -> f64 {
+    let len = text.chars().count();
+
+    1.0 + text.matches(',').count() as f64 + (len as f64 / 100.0).min(3.0)
+}
+
+struct Candidate {
+    node: NodeRef,
+    score: f64,
+}
+
+#[derive(Default)]
+struct Candidates {
+    index: HashMap<*const kuchiki::Node, usize>,
+    candidates: Vec<Candidate>,
+}
+
+impl Candidates {
+    fn add(&mut self, node: NodeRef, score: f64) {
+        let element_score = match node.as_element() {
+            Some(element) => initial_score(element),
+            None => return,
+        };
+
+        let key = &*node as *const kuchiki::Node;
+
+        let idx = *self.index.entry(key).or_insert_with(|| {
+            self.candidates.push(Candidate {
+                node,
+                score: element_score,
+            });
+
+            self.candidates.len() - 1
+        });
+
+        self.candidates[idx].score += score;
+    }
+
+    fn best(self) -> Option<(NodeRef, f64)> {
+        self.candidates
+            .into_iter()
+            .map(|candidate| {
+                let score = candidate.score * (1.0 - link_density(&candidate.node));
+                (candidate.node, score)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+}
+
+fn score_paragraphs(node: &NodeRef, candidates: &mut Candidates) {
+    for child in node.children() {
+        let element = match child.as_element() {
+            Some(element) => element,
+            None => continue,
+        };
+
+        if is_boilerplate(element) {
+            continue;
+        }
+
+        if PARAGRAPH_TAGS.contains(&tag(element)) {
+            let text = child.text_contents();
+            let text = text.trim();
+
+            if text.chars().count() >= MIN_PARAGRAPH_CHARS {
+                let score = paragraph_score(text);
+
+                if let Some(parent) = child.parent() {
+                    if let Some(grandparent) = parent.parent() {
+                        candidates.add(grandparent, score / 2.0);
+                    }
+
+                    candidates.add(parent, score);
+                }
+            }
+        }
+
+        score_paragraphs(&child, candidates);
+    }
+}
+
+/// The element holding the main content of the page rooted at `root`, if the page has a
+/// main content region that is separate from the rest of the page.
+pub fn find(root: &NodeRef) -> Option<NodeRef> {
+    let mut candidates = Candidates::default();
+    score_paragraphs(root, &mut candidates);
+
+    let (node, score) = candidates.best()?;
+
+    if score < MIN_SCORE {
+        return None;
+    }
+
+    match node.as_element().map(tag) {
+        Some("body" | "html") | None => None,
+        Some(_) => Some(node),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kuchiki::traits::TendrilSink;
+
+    use super::*;
+
+    fn main_text(html: &str) -> Option<String> {
+        let root = kuchiki::parse_html().one(html);
+        find(&root).map(|node| node.text_contents())
+    }
+
+    #[test]
+    fn article_between_boilerplate() {
+        let text = main_text(
+            r#"
+            <html>
+                <body>
+                    <nav><ul><li><a href="/">Home</a></li><li><a href="/about">About us and everything else</a></li></ul></nav>
+                    <div class="cookie-banner"><p>We use cookies to improve your experience, to analyse traffic and to show ads.</p></div>
+                    <div class="content">
+                        <p>The first paragraph of the article is long enough, and it has a comma or two, to count.</p>
+                        <p>The second paragraph of the article is also long enough, with commas, to count as well.</p>
+                    </div>
+                    <div class="sidebar"><p>Popular posts from the rest of the site, which are not part of the article.</p></div>
+                    <footer><p>Copyright, all rights reserved, and some other text that is long enough.</p></footer>
+                </body>
+            </html>
+            "#,
+        )
+        .unwrap();
+
+        assert!(text.contains("first paragraph of the article"));
+        assert!(text.contains("second paragraph of the article"));
+        assert!(!text.contains("cookies"));
+        assert!(!text.contains("Popular posts"));
+        assert!(!text.contains("Copyright"));
+    }
+
+    #[test]
+    fn link_lists_are_not_main_content() {
+        let text = main_text(
+            r#"
+            <html>
+                <body>
+                    <div>
+                        <p><a href="/a">A link with a long anchor text, that is, a very long one</a></p>
+                        <p><a href="/b">Another link with a long anchor text, that is, a long one</a></p>
+                    </div>
+                </body>
+            </html>
+            "#,
+        );
+
+        assert_eq!(text, None);
+    }
+
+    #[test]
+    fn whole_body_is_not_a_region() {
+        let text = main_text(
+            r#"
+            <html>
+                <body>
+                    <p>The first paragraph, which is long enough, has commas, many commas, but it is directly in the body.</p>
+                    <p>The second paragraph, which is long enough, has commas, many commas, but it is directly in the body.</p>
+                    <p>The third paragraph, which is long enough, has commas, many commas, but it is directly in the body.</p>
+                    <p>The fourth paragraph, which is long enough, has commas, many commas, but it is directly in the body.</p>
+                </body>
+            </html>
+            "#,
+        );
+
+        assert_eq!(text, None);
+    }
+}
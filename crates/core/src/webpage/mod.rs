@@ -27,12 +27,17 @@ use tantivy::TantivyDocument;
 use url::Url;
 
 mod adservers;
+pub mod content_labels;
 pub mod html;
 mod just_text;
+mod main_content;
 pub mod region;
 pub mod safety_classifier;
 pub mod schema_org;
+pub mod title;
+pub mod url_canonical;
 pub mod url_ext;
+use self::content_labels::ContentLabels;
 use self::html::links::RelFlags;
 pub use self::html::Html;
 
@@ -47,11 +52,19 @@ pub struct Webpage {
     pub page_centrality: f64,
     pub page_centrality_rank: u64,
     pub fetch_time_ms: u64,
+    /// Seconds since the unix epoch of the `Last-Modified` header when the page was crawled.
+    pub last_modified: Option<u64>,
+    /// Seconds since the unix epoch of the `<lastmod>` of the page in the sitemap of the site.
+    pub sitemap_lastmod: Option<u64>,
     pub pre_computed_score: f64,
     pub node_id: Option<NodeID>,
     pub dmoz_description: Option<String>,
     pub safety_classification: Option<safety_classifier::Label>,
+    /// Labels from other sources than the safety classifier, see [`Webpage::content_labels`].
+    pub content_labels: ContentLabels,
     pub inserted_at: DateTime<Utc>,
+    /// When the crawler fetched the page, if the crawl recorded it.
+    pub fetched_at: Option<DateTime<Utc>>,
     pub keywords: Vec<String>,
     pub title_embedding: Option<Tensor>,
     pub keyword_embedding: Option<Tensor>,
@@ -68,11 +81,15 @@ impl Default for Webpage {
             page_centrality: Default::default(),
             page_centrality_rank: u64::MAX,
             fetch_time_ms: Default::default(),
+            last_modified: Default::default(),
+            sitemap_lastmod: Default::default(),
             pre_computed_score: Default::default(),
             node_id: Default::default(),
             dmoz_description: Default::default(),
             safety_classification: Default::default(),
+            content_labels: Default::default(),
             inserted_at: Utc::now(),
+            fetched_at: Default::default(),
             keywords: Default::default(),
             title_embedding: Default::default(),
             keyword_embedding: Default::default(),
@@ -90,11 +107,15 @@ impl From<Html> for Webpage {
             page_centrality: Default::default(),
             page_centrality_rank: u64::MAX,
             fetch_time_ms: Default::default(),
+            last_modified: Default::default(),
+            sitemap_lastmod: Default::default(),
             pre_computed_score: Default::default(),
             node_id: Default::default(),
             dmoz_description: Default::default(),
             safety_classification: Default::default(),
+            content_labels: Default::default(),
             inserted_at: Utc::now(),
+            fetched_at: Default::default(),
             keywords: Default::default(),
             title_embedding: Default::default(),
             keyword_embedding: Default::default(),
@@ -113,6 +134,14 @@ impl Webpage {
         })
     }
 
+    /// The content labels of the page, including the ones mapped from its safety classification.
+    pub fn content_labels(&self) -> ContentLabels {
+        self.safety_classification
+            .map(ContentLabels::from)
+            .unwrap_or_default()
+            .union(self.content_labels)
+    }
+
     pub fn dmoz_description(&self) -> Option<String> {
         self.dmoz_description.as_ref().and_then(|desc| {
             if !self.html.metadata().iter().any(|metadata| {
@@ -129,6 +158,28 @@ impl Webpage {
         })
     }
 
+    /// Best-effort timestamp (seconds since the unix epoch) of when the content of the page
+    /// last changed. The sources are tried in order of how reliably they track the content:
+    ///
+    /// 1. the `Last-Modified` header of the response,
+    /// 2. the `og:updated_time` or `article:modified_time` metadata of the page,
+    /// 3. the `<lastmod>` of the page in the sitemap of the site.
+    ///
+    /// Timestamps in the future are ignored.
+    pub fn content_timestamp(&self) -> Option<u64> {
+        let now = Utc::now().timestamp().max(0) as u64;
+
+        self.last_modified
+            .filter(|ts| *ts <= now)
+            .or_else(|| {
+                self.html
+                    .updated_time()
+                    .map(|time| time.timestamp().max(0) as u64)
+            })
+            .or_else(|| self.sitemap_lastmod.filter(|ts| *ts <= now))
+            .filter(|ts| *ts > 0)
+    }
+
     pub fn as_tantivy(&self, schema: &tantivy::schema::Schema) -> Result<TantivyDocument> {
         let mut doc = self.html.as_tantivy(schema)?;
 
@@ -162,6 +213,51 @@ pub type Meta = HashMap<String, String>;
 mod tests {
     use super::*;
 
+    #[test]
+    fn content_timestamp_precedence() {
+        let html = || {
+            Html::parse(
+                r#"
+                <html>
+                    <head>
+                        <meta property="og:updated_time" content="2022-06-22T19:37:34+00:00" />
+                    </head>
+                </html>
+                "#,
+                "https://www.example.com",
+            )
+            .unwrap()
+        };
+        let meta_timestamp = 1_655_926_654;
+
+        let webpage = Webpage {
+            html: html(),
+            last_modified: Some(1_445_412_480),
+            sitemap_lastmod: Some(1_000_000_000),
+            ..Default::default()
+        };
+        assert_eq!(webpage.content_timestamp(), Some(1_445_412_480));
+
+        let webpage = Webpage {
+            html: html(),
+            sitemap_lastmod: Some(1_000_000_000),
+            ..Default::default()
+        };
+        assert_eq!(webpage.content_timestamp(), Some(meta_timestamp));
+
+        let webpage = Webpage {
+            sitemap_lastmod: Some(1_000_000_000),
+            ..Default::default()
+        };
+        assert_eq!(webpage.content_timestamp(), Some(1_000_000_000));
+
+        let webpage = Webpage {
+            last_modified: Some(u64::MAX),
+            ..Default::default()
+        };
+        assert_eq!(webpage.content_timestamp(), None);
+    }
+
     #[test]
     fn dmoz_description() {
         let html = Html::parse(
@@ -0,0 +1,568 @@
+// Stract is an open source web search engine.
+// Copyright (C) 2024 Stract ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Titles for the pages whose own title is missing or junk.
+//!
+//! A title is junk if it says nothing about the page, like "Untitled Document" or the
+//! name of the domain. The quality of the title is stored when the page is indexed, and
+//! for missing and junk titles a title is synthesized from the first of these sources
+//! that has a title that is not junk itself:
+//!
+//! 1. the `og:title` metadata,
+//! 2. the first `<h1>` of the page,
+//! 3. the last crumb of the `BreadcrumbList` structured data,
+//! 4. the most common anchor text of the backlinks, if it is used by at least
+//!    [`MIN_ANCHOR_CONSENSUS`] of them,
+//! 5. the first sentence of the main content of the page.
+//!
+//! The synthesized title is shown instead of the junk title when the page is retrieved.
+//! Pages without a title are indexed with the synthesized title from the sources of the
+//! page itself, as the backlinks are not known when the page is parsed.
+
+use std::collections::HashMap;
+
+use url::Url;
+
+use super::{url_ext::UrlExt, Html, Webpage};
+
+/// Synthesized titles are cut at a word boundary to at most this many characters.
+pub const MAX_SYNTHESIZED_TITLE_CHARS: usize = 80;
+
+/// Minimum number of backlinks that must agree on an anchor text.
+pub const MIN_ANCHOR_CONSENSUS: usize = 2;
+
+const JUNK_TITLES: [&str; 18] = [
+    "untitled",
+    "untitled document",
+    "untitled page",
+    "no title",
+    "title",
+    "page title",
+    "document",
+    "new document",
+    "new page",
+    "page",
+    "home",
+    "home page",
+    "homepage",
+    "index",
+    "welcome",
+    "default",
+    "null",
+    "undefined",
+];
+
+/// Anchor texts that say nothing about the page they link to.
+const GENERIC_ANCHORS: [&str; 10] = [
+    "here",
+    "click here",
+    "link",
+    "this",
+    "website",
+    "read more",
+    "more",
+    "source",
+    "visit",
+    "homepage",
+];
+
+const FILE_EXTENSIONS: [&str; 5] = [".html", ".htm", ".php", ".aspx", ".asp"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TitleQuality {
+    #[default]
+    Good,
+    Missing,
+    Junk,
+}
+
+impl TitleQuality {
+    pub fn as_u64(&self) -> u64 {
+        match self {
+            TitleQuality::Good => 0,
+            TitleQuality::Missing => 1,
+            TitleQuality::Junk => 2,
+        }
+    }
+
+    /// Unknown ids, like the 0 of documents indexed before the quality was stored, are good.
+    pub fn from_u64(id: u64) -> Self {
+        match id {
+            1 => TitleQuality::Missing,
+            2 => TitleQuality::Junk,
+            _ => TitleQuality::Good,
+        }
+    }
+
+    pub fn is_good(&self) -> bool {
+        matches!(self, TitleQuality::Good)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleSource {
+    OgTitle,
+    H1,
+    Breadcrumb,
+    Anchors,
+    FirstSentence,
+}
+
+/// Lowercase the text and collapse its whitespace.
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn is_domain_of(title: &str, url: &Url) -> bool {
+    let host = url.host_str().unwrap_or_default();
+    let domain = url.root_domain().unwrap_or(host);
+    let domain_name = url
+        .tld()
+        .and_then(|tld| domain.strip_suffix(tld))
+        .map(|name| name.trim_end_matches('.'))
+        .unwrap_or(domain);
+
+    [
+        host,
+        url.normalized_host().unwrap_or(host),
+        domain,
+        domain_name,
+        url.as_str().trim_end_matches('/'),
+    ]
+    .iter()
+    .any(|name| !name.is_empty() && title == name.to_lowercase())
+}
+
+pub fn title_quality(title: Option<&str>, url: &Url) -> TitleQuality {
+    let title = normalize(title.unwrap_or_default());
+
+    if title.is_empty() {
+        return TitleQuality::Missing;
+    }
+
+    let trimmed = title.trim_matches(|c: char| !c.is_alphanumeric());
+
+    if trimmed.is_empty()
+        || JUNK_TITLES.contains(&trimmed)
+        || is_domain_of(&title, url)
+        || is_domain_of(trimmed, url)
+        || FILE_EXTENSIONS.iter().any(|ext| title.ends_with(ext))
+    {
+        TitleQuality::Junk
+    } else {
+        TitleQuality::Good
+    }
+}
+
+/// Collapse the whitespace of the text and cut it at a word boundary.
+fn shorten(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if text.chars().count() <= MAX_SYNTHESIZED_TITLE_CHARS {
+        return text;
+    }
+
+    let mut res = String::new();
+    for word in text.split(' ') {
+        if res.chars().count() + word.chars().count() + 1 > MAX_SYNTHESIZED_TITLE_CHARS {
+            break;
+        }
+
+        if !res.is_empty() {
+            res.push(' ');
+        }
+        res.push_str(word);
+    }
+
+    if res.is_empty() {
+        res = text.chars().take(MAX_SYNTHESIZED_TITLE_CHARS).collect();
+    }
+
+    res.push('…');
+    res
+}
+
+fn first_sentence(text: &str) -> Option<&str> {
+    let text = text.trim();
+    let end = text
+        .char_indices()
+        .find(|(i, c)| {
+            matches!(c, '.' | '!' | '?')
+                && !text[i + c.len_utf8()..].starts_with(|c: char| !c.is_whitespace())
+                || *c == '\n'
+        })
+        .map(|(i, c)| if c == '\n' { i } else { i + c.len_utf8() })
+        .unwrap_or(text.len());
+
+    Some(text[..end].trim()).filter(|sentence| !sentence.is_empty())
+}
+
+fn anchor_consensus(labels: &[String], url: &Url) -> Option<String> {
+    let mut counts: HashMap<String, (usize, &str)> = HashMap::new();
+
+    for label in labels {
+        let normalized = normalize(label);
+
+        if normalized.is_empty() || GENERIC_ANCHORS.contains(&normalized.as_str()) {
+            continue;
+        }
+
+        counts.entry(normalized).or_insert((0, label)).0 += 1;
+    }
+
+    counts
+        .into_iter()
+        .filter(|(_, (count, _))| *count >= MIN_ANCHOR_CONSENSUS)
+        .max_by(|(a, (a_count, _)), (b, (b_count, _))| a_count.cmp(b_count).then(b.cmp(a)))
+        .map(|(_, (_, label))| label.to_string())
+        .filter(|label| title_quality(Some(label), url).is_good())
+}
+
+/// The first title from the sources in order that is not junk.
+fn synthesize(html: &Html, backlink_labels: &[String]) -> Option<(String, TitleSource)> {
+    let url = html.url();
+    let good = |title: Option<String>| {
+        title
+            .map(|title| shorten(&title))
+            .filter(|title| title_quality(Some(title), url).is_good())
+    };
+
+    if let Some(title) = good(html.og_title()) {
+        return Some((title, TitleSource::OgTitle));
+    }
+
+    if let Some(title) = good(html.h1()) {
+        return Some((title, TitleSource::H1));
+    }
+
+    if let Some(title) = good(crate::search_prettifier::structured_data_tail(
+        &html.schema_org(),
+    )) {
+        return Some((title, TitleSource::Breadcrumb));
+    }
+
+    if let Some(title) = good(anchor_consensus(backlink_labels, url)) {
+        return Some((title, TitleSource::Anchors));
+    }
+
+    good(
+        html.clean_text()
+            .map(String::as_str)
+            .and_then(first_sentence)
+            .map(str::to_string),
+    )
+    .map(|title| (title, TitleSource::FirstSentence))
+}
+
+impl Html {
+    pub fn title_quality(&self) -> TitleQuality {
+        title_quality(self.title().as_deref(), self.url())
+    }
+
+    /// The title of the page, or the title synthesized from the page if it has none.
+    pub fn indexed_title(&self) -> Option<String> {
+        self.title()
+            .or_else(|| synthesize(self, &[]).map(|(title, _)| title))
+    }
+}
+
+impl Webpage {
+    /// A title for the page if its own title is missing or junk.
+    pub fn synthesized_title(&self) -> Option<(String, TitleSource)> {
+        if self.html.title_quality().is_good() {
+            return None;
+        }
+
+        synthesize(&self.html, &self.backlink_labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        index::Index,
+        searcher::{LocalSearcher, SearchQuery},
+    };
+
+    use super::*;
+
+    fn quality(title: &str, url: &str) -> TitleQuality {
+        title_quality(Some(title), &Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn junk_titles() {
+        let url = "https://www.example.com/blog/post";
+
+        assert_eq!(quality("", url), TitleQuality::Missing);
+        assert_eq!(quality("  \n ", url), TitleQuality::Missing);
+
+        for junk in [
+            "Untitled Document",
+            "untitled",
+            "Home",
+            "- Home -",
+            "index",
+            "www.example.com",
+            "example.com",
+            "Example",
+            "https://www.example.com/blog/post",
+            "page.html",
+            "***",
+        ] {
+            assert_eq!(quality(junk, url), TitleQuality::Junk, "{junk}");
+        }
+
+        for good in [
+            "How to bake bread",
+            "Example Domain Registration",
+            "Home brewing for beginners",
+            "Index of refraction",
+            "2024",
+        ] {
+            assert_eq!(quality(good, url), TitleQuality::Good, "{good}");
+        }
+    }
+
+    #[test]
+    fn shorten_at_word_boundary() {
+        assert_eq!(shorten("  a short   title "), "a short title");
+
+        let long = "word ".repeat(40);
+        let short = shorten(&long);
+        assert!(short.chars().count() <= MAX_SYNTHESIZED_TITLE_CHARS + 1);
+        assert!(short.ends_with("word…"));
+    }
+
+    #[test]
+    fn sentences() {
+        assert_eq!(
+            first_sentence("Bread is easy to bake. You need flour."),
+            Some("Bread is easy to bake.")
+        );
+        assert_eq!(
+            first_sentence("Version 1.2 is out! Get it now"),
+            Some("Version 1.2 is out!")
+        );
+        assert_eq!(first_sentence("No end\nnext line"), Some("No end"));
+        assert_eq!(first_sentence("   "), None);
+    }
+
+    #[test]
+    fn anchors_need_consensus() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let labels = |labels: &[&str]| -> Vec<String> {
+            labels.iter().map(|label| label.to_string()).collect()
+        };
+
+        assert_eq!(
+            anchor_consensus(
+                &labels(&[
+                    "Bread recipes",
+                    "bread  recipes",
+                    "click here",
+                    "click here",
+                    "click here",
+                    "cakes"
+                ]),
+                &url
+            ),
+            Some("Bread recipes".to_string())
+        );
+        assert_eq!(anchor_consensus(&labels(&["bread", "cakes"]), &url), None);
+        assert_eq!(
+            anchor_consensus(&labels(&["example.com", "example.com"]), &url),
+            None
+        );
+    }
+
+    fn page(url: &str, head: &str, body: &str, backlink_labels: &[&str]) -> Webpage {
+        Webpage {
+            html: Html::parse(
+                &format!(
+                    r#"
+            <html>
+                <head>
+                    {head}
+                </head>
+                <body>
+                    {body}
+                </body>
+            </html>
+            "#
+                ),
+                url,
+            )
+            .unwrap(),
+            backlink_labels: backlink_labels
+                .iter()
+                .map(|label| label.to_string())
+                .collect(),
+            fetch_time_ms: 500,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fallback_titles() {
+        let untitled = "<title>Untitled Document</title>";
+        let breadcrumbs = r#"
+            <title>Untitled Document</title>
+            <script type="application/ld+json">
+            {
+                "@context": "https://schema.org",
+                "@type": "BreadcrumbList",
+                "itemListElement": [
+                    {
+                        "@type": "ListItem",
+                        "position": 1,
+                        "name": "Recipes",
+                        "item": "https://www.crumbs.com/recipes"
+                    },
+                    {
+                        "@type": "ListItem",
+                        "position": 2,
+                        "name": "Whole wheat bread",
+                        "item": "https://www.crumbs.com/recipes/whole-wheat"
+                    }
+                ]
+            }
+            </script>
+        "#;
+        let og_title = r#"
+            <title>Untitled Document</title>
+            <meta property="og:title" content="Bread from the open graph" />
+        "#;
+
+        let pages = [
+            (
+                page(
+                    "https://www.good.com/",
+                    "<title>Bread baking basics</title>",
+                    "<h1>Something else</h1> We bake bread.",
+                    &[],
+                ),
+                "Bread baking basics",
+                None,
+            ),
+            (
+                page(
+                    "https://www.og.com/",
+                    og_title,
+                    "<h1>A heading</h1> We bake bread.",
+                    &[],
+                ),
+                "Bread from the open graph",
+                Some(TitleSource::OgTitle),
+            ),
+            (
+                page(
+                    "https://www.heading.com/",
+                    untitled,
+                    "<h1>Bread in a heading</h1> We bake bread.",
+                    &[],
+                ),
+                "Bread in a heading",
+                Some(TitleSource::H1),
+            ),
+            (
+                page(
+                    "https://www.crumbs.com/recipes/whole-wheat/bread",
+                    breadcrumbs,
+                    "We bake bread.",
+                    &[],
+                ),
+                "Whole wheat bread",
+                Some(TitleSource::Breadcrumb),
+            ),
+            (
+                page(
+                    "https://www.anchors.com/",
+                    untitled,
+                    "We bake bread.",
+                    &[
+                        "Sourdough starter guide",
+                        "sourdough starter  guide",
+                        "here",
+                    ],
+                ),
+                "Sourdough starter guide",
+                Some(TitleSource::Anchors),
+            ),
+            (
+                page(
+                    "https://www.sentence.com/",
+                    untitled,
+                    "Rye bread needs time. It rises slowly.",
+                    &[],
+                ),
+                "Rye bread needs time.",
+                Some(TitleSource::FirstSentence),
+            ),
+            (
+                page(
+                    "https://www.missing.com/",
+                    "",
+                    "Spelt bread is nutty. It is also old.",
+                    &[],
+                ),
+                "Spelt bread is nutty.",
+                Some(TitleSource::FirstSentence),
+            ),
+        ];
+
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for (webpage, expected, source) in &pages {
+            assert_eq!(
+                webpage.synthesized_title(),
+                source.map(|source| (expected.to_string(), source)),
+                "{}",
+                webpage.html.url()
+            );
+
+            index.insert(webpage).expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index);
+        let res = searcher
+            .search(&SearchQuery {
+                query: "bread".to_string(),
+                num_results: 100,
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(res.webpages.len(), pages.len());
+
+        for (webpage, expected, source) in &pages {
+            let url = webpage.html.url().as_str();
+            let result = res
+                .webpages
+                .iter()
+                .find(|result| result.url == url)
+                .unwrap_or_else(|| panic!("{url} was not found"));
+
+            assert_eq!(result.title, *expected, "{url}");
+            assert_eq!(result.synthesized_title, source.is_some(), "{url}");
+        }
+    }
+}
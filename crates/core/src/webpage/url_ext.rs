@@ -36,10 +36,13 @@ pub trait UrlExt {
     fn icann_domain(&self) -> Option<&str>;
     fn root_domain(&self) -> Option<&str>;
     fn normalized_host(&self) -> Option<&str>;
+    /// Canonicalize the url in place. See [`super::url_canonical`].
     fn normalize(&mut self);
     fn subdomain(&self) -> Option<&str>;
     fn is_homepage(&self) -> bool;
     fn tld(&self) -> Option<&str>;
+    /// Whether the host ends in a suffix of the icann list, and not just in any label.
+    fn has_known_tld(&self) -> bool;
 }
 
 impl UrlExt for url::Url {
@@ -61,38 +64,7 @@ impl UrlExt for url::Url {
     }
 
     fn normalize(&mut self) {
-        self.set_fragment(None); // remove fragment (e.g. #comments
-
-        let queries: Vec<_> = self
-            .query_pairs()
-            .filter(|(key, _)| {
-                !key.starts_with("utm_")
-                    && !key.starts_with("fbclid")
-                    && !key.starts_with("gclid")
-                    && !key.starts_with("msclkid")
-            })
-            .map(|(key, value)| (key.to_string(), value.to_string()))
-            .collect();
-
-        {
-            let mut query_mut = self.query_pairs_mut();
-            query_mut.clear();
-            if !queries.is_empty() {
-                query_mut.extend_pairs(queries);
-            }
-        }
-
-        if self.query().unwrap_or_default().is_empty() {
-            self.set_query(None);
-        }
-
-        if !self.username().is_empty() {
-            let _ = self.set_username("");
-        }
-
-        if self.password().is_some() {
-            let _ = self.set_password(None);
-        }
+        super::url_canonical::canonicalize(self);
     }
 
     fn subdomain(&self) -> Option<&str> {
@@ -117,6 +89,12 @@ impl UrlExt for url::Url {
         let suffix = std::str::from_utf8(ICANN_LIST.suffix(host.as_bytes())?.as_bytes()).ok()?;
         Some(suffix)
     }
+
+    fn has_known_tld(&self) -> bool {
+        self.host_str()
+            .and_then(|host| ICANN_LIST.suffix(host.as_bytes()))
+            .is_some_and(|suffix| suffix.is_known())
+    }
 }
 
 #[cfg(test)]
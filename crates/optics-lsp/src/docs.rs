@@ -64,6 +64,10 @@ pub fn token_docs(token: &optics::Token) -> Option<&'static str> {
         As an example, `Schema(\"BlogPosting\")` matches all pages that contains the https://schema.org/BlogPosting entity. Note that `Schema` \
         does not support the pattern syntax, but only simple strings.",
 
+        optics::Token::ContentLabel => "`ContentLabel(\"...\")` matches any search result that has the content label defined in `\"...\"`. \
+        The labels are `adult`, `violence` and `medical-graphic`, so `Rule { Matches { ContentLabel(\"violence\") }, Action(Discard) }` removes the results with violent content. \
+        Note that `ContentLabel` does not support the pattern syntax, but only simple strings.",
+
         optics::Token::Ranking => "When results are ranked we take a weighted sum of various signals to give each webpage a score for the specific query. \
         The top scored results are then presented to the user. `Ranking` allows you to alter the weight of all the `Signal`s and text `Field`s.",
 
@@ -91,6 +95,9 @@ pub fn token_docs(token: &optics::Token) -> Option<&'static str> {
         so results that are heavily linked to from your disliked sites will be downranked. Note therefore, that `Dislike` not only alters the ranking of the specifc site, \
         but also sites that are heavily linked to from the disliked site.",
 
+        optics::Token::Features => "`Features { sidebar: off, ... }` turns components of the search results page on or off. \
+        The features are `sidebar`, `discussions`, `instant_answers` and `thumbnails`, and they are all on unless turned off.",
+
         _ => return None,
     })
 }
@@ -57,16 +57,23 @@ impl From<Error> for JsValue {
 #[derive(Debug)]
 struct File {
     source: String,
-    optic: Result<Optic, optics::Error>,
+    optic: Result<(Optic, Vec<optics::Warning>), optics::Error>,
 }
 impl File {
     fn new(source: String) -> Self {
         File {
-            optic: optics::parse(&source),
+            optic: optics::parse_with_warnings(&source),
             source,
         }
     }
 
+    fn warnings(&self) -> &[optics::Warning] {
+        match &self.optic {
+            Ok((_, warnings)) => warnings,
+            Err(_) => &[],
+        }
+    }
+
     fn error(&self) -> Option<optics::Error> {
         if let Err(err) = &self.optic {
             Some(err.clone())
@@ -173,18 +180,27 @@ impl OpticsBackend {
 
     fn send_diagnostics(&self, url: Url) {
         if let Some(f) = self.files.get(&url) {
-            self.send_diagnostic(url, f.error().map(|err| err_to_diagnostic(err, &f.source)));
+            let diagnostics = f
+                .error()
+                .map(|err| err_to_diagnostic(err, &f.source))
+                .into_iter()
+                .chain(
+                    f.warnings()
+                        .iter()
+                        .map(|warning| warning_to_diagnostic(warning, &f.source)),
+                )
+                .collect();
+
+            self.send_diagnostic(url, diagnostics);
         }
     }
 
-    fn send_diagnostic(&self, url: Url, diagnostic: Option<Diagnostic>) {
+    fn send_diagnostic(&self, url: Url, diagnostics: Vec<Diagnostic>) {
         let this = &JsValue::null();
 
         let params = PublishDiagnosticsParams {
             uri: url,
-            diagnostics: diagnostic
-                .map(|diagnostic| vec![diagnostic])
-                .unwrap_or_default(),
+            diagnostics,
             version: None,
         };
         log(&format!("Sending diagnostic {params:?}"));
@@ -304,6 +320,25 @@ fn err_to_diagnostic(err: optics::Error, source: &str) -> Diagnostic {
     }
 }
 
+fn warning_to_diagnostic(warning: &optics::Warning, source: &str) -> Diagnostic {
+    match warning {
+        optics::Warning::UnknownFeature {
+            token: (start, tok, end),
+        } => {
+            let message = format!("Unknown feature \"{tok}\" is ignored");
+            Diagnostic {
+                range: Range {
+                    start: offset_to_pos(*start, source),
+                    end: offset_to_pos(*end, source),
+                },
+                severity: Some(DiagnosticSeverity::WARNING),
+                message,
+                ..Default::default()
+            }
+        }
+    }
+}
+
 fn offset_to_pos(offset: usize, src: &str) -> Position {
     if src[..offset].is_empty() {
         return Position::new(0, 0);
@@ -30,6 +30,8 @@ pub struct RawOptic {
     pub rules: Vec<RawRule>,
     pub host_preferences: Vec<RawHostPreference>,
     pub discard_non_matching: bool,
+    pub verbatim: bool,
+    pub features: Vec<RawFeature>,
 }
 
 impl From<Vec<RawOpticBlock>> for RawOptic {
@@ -37,12 +39,16 @@ impl From<Vec<RawOpticBlock>> for RawOptic {
         let mut rules = Vec::new();
         let mut host_preferences = Vec::new();
         let mut discard_non_matching = false;
+        let mut verbatim = false;
+        let mut features = Vec::new();
 
         for block in blocks {
             match block {
                 RawOpticBlock::Rule(rule) => rules.push(rule),
                 RawOpticBlock::HostPreference(pref) => host_preferences.push(pref),
                 RawOpticBlock::DiscardNonMatching => discard_non_matching = true,
+                RawOpticBlock::Verbatim => verbatim = true,
+                RawOpticBlock::Features(block) => features.extend(block),
             }
         }
 
@@ -50,6 +56,8 @@ impl From<Vec<RawOpticBlock>> for RawOptic {
             rules,
             host_preferences,
             discard_non_matching,
+            verbatim,
+            features,
         }
     }
 }
@@ -59,6 +67,8 @@ pub enum RawOpticBlock {
     Rule(RawRule),
     HostPreference(RawHostPreference),
     DiscardNonMatching,
+    Verbatim,
+    Features(Vec<RawFeature>),
 }
 
 #[derive(Debug, PartialEq)]
@@ -67,6 +77,13 @@ pub struct RawRule {
     pub action: Option<RawAction>,
 }
 
+/// A component of the search results page that the optic turns on or off.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RawFeature {
+    pub name: (usize, String, usize),
+    pub enabled: bool,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum RawHostPreference {
     Like(String),
@@ -86,6 +103,7 @@ pub enum RawMatchPart {
     Content(String),
     MicroformatTag(String),
     Schema(String),
+    ContentLabel(String),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -170,6 +188,8 @@ mod tests {
                 ],
                 host_preferences: vec![],
                 discard_non_matching: false,
+                verbatim: false,
+                features: vec![],
             }
         );
     }
@@ -213,6 +233,8 @@ mod tests {
                 ],
                 host_preferences: vec![],
                 discard_non_matching: false,
+                verbatim: false,
+                features: vec![],
             }
         );
     }
@@ -257,10 +279,68 @@ mod tests {
                 ],
                 host_preferences: vec![],
                 discard_non_matching: true,
+                verbatim: false,
+                features: vec![],
             }
         );
     }
 
+    #[test]
+    fn verbatim() {
+        let optic = parse(
+            r#"
+            Verbatim;
+            DiscardNonMatching;
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            optic,
+            RawOptic {
+                rules: vec![],
+                host_preferences: vec![],
+                discard_non_matching: true,
+                verbatim: true,
+                features: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn features() {
+        let optic = parse(
+            r#"
+            Features {
+                sidebar: off,
+                instant_answers: on
+            };
+            Verbatim;
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            optic.features,
+            vec![
+                RawFeature {
+                    name: (40, "sidebar".to_string(), 47),
+                    enabled: false,
+                },
+                RawFeature {
+                    name: (70, "instant_answers".to_string(), 85),
+                    enabled: true,
+                },
+            ]
+        );
+        assert!(optic.verbatim);
+
+        assert!(matches!(
+            parse("Features { sidebar: maybe }"),
+            Err(Error::UnexpectedToken { .. })
+        ));
+    }
+
     #[test]
     fn quickstart_parse() {
         assert!(parse(include_str!("../testcases/samples/quickstart.optic")).is_ok());
@@ -23,6 +23,7 @@ use super::{Error, Result};
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token<'a> {
     SemiColon,
+    Colon,
     Comma,
     OpenBracket,
     CloseBracket,
@@ -30,6 +31,7 @@ pub enum Token<'a> {
     CloseParenthesis,
 
     DiscardNonMatching,
+    Verbatim,
     Rule,
     RankingPipeline,
     Ranking,
@@ -45,27 +47,32 @@ pub enum Token<'a> {
     Content,
     MicroformatTag,
     Schema,
+    ContentLabel,
     Action,
     Boost,
     Downrank,
     Discard,
     Like,
     Dislike,
+    Features,
 
     String(&'a str),
     Number(&'a str),
+    Ident(&'a str),
 }
 
 impl<'a> Display for Token<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Token::SemiColon => f.write_str(";"),
+            Token::Colon => f.write_str(":"),
             Token::Comma => f.write_str(","),
             Token::OpenBracket => f.write_str("{"),
             Token::CloseBracket => f.write_str("}"),
             Token::OpenParenthesis => f.write_str("("),
             Token::CloseParenthesis => f.write_str(")"),
             Token::DiscardNonMatching => f.write_str("DiscardNonMatching"),
+            Token::Verbatim => f.write_str("Verbatim"),
             Token::Rule => f.write_str("Rule"),
             Token::RankingPipeline => f.write_str("RankingPipeline"),
             Token::Ranking => f.write_str("Ranking"),
@@ -81,14 +88,17 @@ impl<'a> Display for Token<'a> {
             Token::Content => f.write_str("Content"),
             Token::MicroformatTag => f.write_str("MicroformatTag"),
             Token::Schema => f.write_str("Schema"),
+            Token::ContentLabel => f.write_str("ContentLabel"),
             Token::Action => f.write_str("Action"),
             Token::Boost => f.write_str("Boost"),
             Token::Downrank => f.write_str("Downrank"),
             Token::Discard => f.write_str("Discard"),
             Token::Like => f.write_str("Like"),
             Token::Dislike => f.write_str("Dislike"),
+            Token::Features => f.write_str("Features"),
             Token::String(s) => write!(f, "\"{s}\""),
             Token::Number(n) => write!(f, "{n}"),
+            Token::Ident(i) => f.write_str(i),
         }
     }
 }
@@ -107,6 +117,8 @@ enum Outer<'a> {
 
     #[token(";")]
     SemiColon,
+    #[token(":")]
+    Colon,
     #[token(",")]
     Comma,
     #[token("{")]
@@ -120,6 +132,8 @@ enum Outer<'a> {
 
     #[token("DiscardNonMatching")]
     DiscardNonMatching,
+    #[token("Verbatim")]
+    Verbatim,
     #[token("Rule")]
     Rule,
     #[token("RankingPipeline")]
@@ -150,6 +164,8 @@ enum Outer<'a> {
     MicroformatTag,
     #[token("Schema")]
     Schema,
+    #[token("ContentLabel")]
+    ContentLabel,
     #[token("Action")]
     Action,
     #[token("Boost")]
@@ -162,9 +178,14 @@ enum Outer<'a> {
     Like,
     #[token("Dislike")]
     Dislike,
+    #[token("Features")]
+    #[token("features")]
+    Features,
 
     #[regex(r"[+-]?([0-9]*[.])?[0-9]+", |lex| lex.slice())]
     Number(&'a str),
+    #[regex(r"[a-z_][a-z0-9_]*", |lex| lex.slice())]
+    Ident(&'a str),
 }
 
 #[derive(Logos, Debug, PartialEq, Clone)]
@@ -298,6 +319,7 @@ impl<'source> Iterator for LexerBridge<'source> {
                 Outer::StartBlockComment => Some(Err(Error::Unknown(s.start, s.end))),
                 Outer::StartLineComment => Some(Err(Error::Unknown(s.start, s.end))),
                 Outer::SemiColon => Some(Ok((s.start, Token::SemiColon, s.end))),
+                Outer::Colon => Some(Ok((s.start, Token::Colon, s.end))),
                 Outer::Comma => Some(Ok((s.start, Token::Comma, s.end))),
                 Outer::OpenBracket => Some(Ok((s.start, Token::OpenBracket, s.end))),
                 Outer::CloseBracket => Some(Ok((s.start, Token::CloseBracket, s.end))),
@@ -318,14 +340,18 @@ impl<'source> Iterator for LexerBridge<'source> {
                 Outer::Content => Some(Ok((s.start, Token::Content, s.end))),
                 Outer::MicroformatTag => Some(Ok((s.start, Token::MicroformatTag, s.end))),
                 Outer::Schema => Some(Ok((s.start, Token::Schema, s.end))),
+                Outer::ContentLabel => Some(Ok((s.start, Token::ContentLabel, s.end))),
                 Outer::Action => Some(Ok((s.start, Token::Action, s.end))),
                 Outer::Boost => Some(Ok((s.start, Token::Boost, s.end))),
                 Outer::Downrank => Some(Ok((s.start, Token::Downrank, s.end))),
                 Outer::Discard => Some(Ok((s.start, Token::Discard, s.end))),
                 Outer::Like => Some(Ok((s.start, Token::Like, s.end))),
                 Outer::Dislike => Some(Ok((s.start, Token::Dislike, s.end))),
+                Outer::Features => Some(Ok((s.start, Token::Features, s.end))),
                 Outer::Number(n) => Some(Ok((s.start, Token::Number(n), s.end))),
+                Outer::Ident(i) => Some(Ok((s.start, Token::Ident(i), s.end))),
                 Outer::DiscardNonMatching => Some(Ok((s.start, Token::DiscardNonMatching, s.end))),
+                Outer::Verbatim => Some(Ok((s.start, Token::Verbatim, s.end))),
             }
         } else {
             None
@@ -436,4 +462,33 @@ mod tests {
 
         assert_eq!(lexer.filter_map(std::result::Result::ok).count(), 0);
     }
+
+    #[test]
+    fn features() {
+        let s = r#"
+            features { sidebar: off, instant_answers: on }
+        "#;
+
+        let lexer = LexerBridge::new(s);
+
+        let result: Vec<Token> = lexer
+            .filter_map(std::result::Result::ok)
+            .map(|(_, t, _)| t)
+            .collect();
+
+        let expected = vec![
+            Token::Features,
+            Token::OpenBracket,
+            Token::Ident("sidebar"),
+            Token::Colon,
+            Token::Ident("off"),
+            Token::Comma,
+            Token::Ident("instant_answers"),
+            Token::Colon,
+            Token::Ident("on"),
+            Token::CloseBracket,
+        ];
+
+        assert_eq!(result, expected);
+    }
 }
@@ -18,7 +18,7 @@ pub mod ast;
 mod lexer;
 
 use itertools::Itertools;
-use std::fmt::Display;
+use std::{collections::HashSet, fmt::Display};
 use thiserror::Error;
 use utoipa::ToSchema;
 
@@ -63,51 +63,86 @@ pub enum Error {
     Pattern,
 }
 
+/// Problems in an optic that don't prevent it from being used.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum Warning {
+    #[error("Unknown feature")]
+    UnknownFeature {
+        /// the name of the feature
+        token: (usize, String, usize),
+    },
+}
+
 pub fn parse(optic: &str) -> Result<Optic> {
+    parse_with_warnings(optic).map(|(optic, _)| optic)
+}
+
+pub fn parse_with_warnings(optic: &str) -> Result<(Optic, Vec<Warning>)> {
     let raw_optic = ast::parse(optic)?;
 
-    Optic::try_from(raw_optic)
+    compile(raw_optic)
 }
 
 impl TryFrom<RawOptic> for Optic {
     type Error = Error;
 
     fn try_from(raw: RawOptic) -> Result<Self> {
-        let mut rules = Vec::new();
-        let mut blocked = Vec::new();
+        compile(raw).map(|(optic, _)| optic)
+    }
+}
 
-        for rule in raw.rules {
-            let rule = Rule::try_from(rule)?;
+fn compile(raw: RawOptic) -> Result<(Optic, Vec<Warning>)> {
+    let mut rules = Vec::new();
+    let mut blocked = Vec::new();
 
-            let blocked_sites = rule.as_blocked_sites();
+    for rule in raw.rules {
+        let rule = Rule::try_from(rule)?;
 
-            if blocked_sites.is_empty() {
-                rules.push(rule);
-            } else {
-                blocked.extend(blocked_sites);
-            }
+        let blocked_sites = rule.as_blocked_sites();
+
+        if blocked_sites.is_empty() {
+            rules.push(rule);
+        } else {
+            blocked.extend(blocked_sites);
         }
+    }
 
-        let mut liked_hosts = Vec::new();
-        let mut disliked_hosts = Vec::new();
+    let mut liked_hosts = Vec::new();
+    let mut disliked_hosts = Vec::new();
 
-        for pref in raw.host_preferences {
-            match pref {
-                ast::RawHostPreference::Like(host) => liked_hosts.push(host),
-                ast::RawHostPreference::Dislike(host) => disliked_hosts.push(host),
-            }
+    for pref in raw.host_preferences {
+        match pref {
+            ast::RawHostPreference::Like(host) => liked_hosts.push(host),
+            ast::RawHostPreference::Dislike(host) => disliked_hosts.push(host),
         }
+    }
 
-        Ok(Self {
-            rules,
-            discard_non_matching: raw.discard_non_matching,
-            host_rankings: HostRankings {
-                liked: liked_hosts,
-                disliked: disliked_hosts,
-                blocked,
-            },
-        })
+    let mut features = Features::default();
+    let mut warnings = Vec::new();
+
+    for feature in raw.features {
+        if !features.set(&feature.name.1, feature.enabled) {
+            // unknown features are ignored so optics written for newer
+            // versions of the search engine still work
+            warnings.push(Warning::UnknownFeature {
+                token: feature.name,
+            });
+        }
     }
+
+    let optic = Optic {
+        rules,
+        discard_non_matching: raw.discard_non_matching,
+        verbatim: raw.verbatim,
+        host_rankings: HostRankings {
+            liked: liked_hosts,
+            disliked: disliked_hosts,
+            blocked,
+        },
+        features,
+    };
+
+    Ok((optic, warnings))
 }
 
 impl TryFrom<RawRule> for Rule {
@@ -168,6 +203,7 @@ impl Display for Matching {
             MatchLocation::Content => "Content",
             MatchLocation::MicroformatTag => "MicroformatTag",
             MatchLocation::Schema => "Schema",
+            MatchLocation::ContentLabel => "ContentLabel",
         };
         write!(f, "{s}(\"")?;
 
@@ -192,6 +228,7 @@ impl TryFrom<RawMatchPart> for Matching {
             RawMatchPart::Content(s) => (s, MatchLocation::Content),
             RawMatchPart::MicroformatTag(s) => (s, MatchLocation::MicroformatTag),
             RawMatchPart::Schema(s) => (s, MatchLocation::Schema),
+            RawMatchPart::ContentLabel(s) => (s, MatchLocation::ContentLabel),
         };
 
         let mut pattern = Vec::new();
@@ -318,6 +355,7 @@ pub enum MatchLocation {
     Content,
     MicroformatTag,
     Schema,
+    ContentLabel,
 }
 
 #[derive(
@@ -365,12 +403,21 @@ pub struct Optic {
     pub host_rankings: HostRankings,
     pub rules: Vec<Rule>,
     pub discard_non_matching: bool,
+    /// Search for the exact terms of the query without stemming, synonyms or spelling corrections.
+    pub verbatim: bool,
+    /// The components of the search results page that are shown with this optic.
+    #[serde(default)]
+    pub features: Features,
 }
 
 impl Optic {
     pub fn parse(optic: &str) -> Result<Self> {
         parse(optic)
     }
+
+    pub fn parse_with_warnings(optic: &str) -> Result<(Self, Vec<Warning>)> {
+        parse_with_warnings(optic)
+    }
 }
 
 impl Display for Optic {
@@ -379,6 +426,12 @@ impl Display for Optic {
             writeln!(f, "DiscardNonMatching;")?;
         }
 
+        if self.verbatim {
+            writeln!(f, "Verbatim;")?;
+        }
+
+        write!(f, "{}", self.features)?;
+
         for rule in &self.rules {
             write!(f, "{rule}")?;
         }
@@ -387,6 +440,77 @@ impl Display for Optic {
     }
 }
 
+/// The components of the search results page besides the results themselves.
+/// They are all shown unless an optic turns them off in a
+/// `Features { sidebar: off, ... }` block, in which case they are not computed either.
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    Clone,
+    Copy,
+    serde::Serialize,
+    serde::Deserialize,
+    bincode::Encode,
+    bincode::Decode,
+    ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct Features {
+    /// The entity sidebar, or the stackoverflow answer shown in its place.
+    pub sidebar: bool,
+    pub discussions: bool,
+    /// Widgets like the calculator and thesaurus.
+    pub instant_answers: bool,
+    /// The images of the sidebar entities.
+    pub thumbnails: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            sidebar: true,
+            discussions: true,
+            instant_answers: true,
+            thumbnails: true,
+        }
+    }
+}
+
+impl Features {
+    /// Turn the feature on or off. Returns `false` if there is no feature with the name.
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        match name {
+            "sidebar" => self.sidebar = enabled,
+            "discussions" => self.discussions = enabled,
+            "instant_answers" => self.instant_answers = enabled,
+            "thumbnails" => self.thumbnails = enabled,
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+impl Display for Features {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if *self == Self::default() {
+            return Ok(());
+        }
+
+        let state = |enabled: bool| if enabled { "on" } else { "off" };
+
+        writeln!(
+            f,
+            "Features {{ sidebar: {}, discussions: {}, instant_answers: {}, thumbnails: {} }};",
+            state(self.sidebar),
+            state(self.discussions),
+            state(self.instant_answers),
+            state(self.thumbnails)
+        )
+    }
+}
+
 #[derive(
     Debug,
     PartialEq,
@@ -568,6 +692,38 @@ impl HostRankings {
         self.disliked.extend(host_rankings.disliked);
         self.blocked.extend(host_rankings.blocked);
     }
+
+    /// The union of the host rankings of the user and the ones of an optic. A host that is
+    /// ranked by both keeps the ranking of the user, so a host the user likes is neither
+    /// disliked nor blocked because the optic does so.
+    #[must_use]
+    pub fn merge_user(user: HostRankings, optic: HostRankings) -> HostRankings {
+        let mut merged = HostRankings::empty();
+        let mut seen = HashSet::new();
+        let key = |host: &str| host.strip_prefix("www.").unwrap_or(host).to_string();
+
+        for rankings in [user, optic] {
+            for host in rankings.liked {
+                if seen.insert(key(&host)) {
+                    merged.liked.push(host);
+                }
+            }
+
+            for host in rankings.disliked {
+                if seen.insert(key(&host)) {
+                    merged.disliked.push(host);
+                }
+            }
+
+            for host in rankings.blocked {
+                if seen.insert(key(&host)) {
+                    merged.blocked.push(host);
+                }
+            }
+        }
+
+        merged
+    }
 }
 
 #[cfg(test)]
@@ -617,6 +773,12 @@ mod tests {
                 action: Action::Boost(0),
             }],
             discard_non_matching: true,
+            verbatim: true,
+            features: Features {
+                sidebar: false,
+                thumbnails: false,
+                ..Default::default()
+            },
         };
 
         let exported = optic.to_string();
@@ -627,4 +789,53 @@ mod tests {
 
         assert_eq!(optic, parsed);
     }
+
+    #[test]
+    fn user_host_rankings_win() {
+        let user = HostRankings {
+            liked: vec!["a.com".to_string()],
+            disliked: vec![],
+            blocked: vec!["b.com".to_string()],
+        };
+        let optic = HostRankings {
+            liked: vec!["c.com".to_string(), "www.b.com".to_string()],
+            disliked: vec!["a.com".to_string()],
+            blocked: vec!["www.a.com".to_string(), "d.com".to_string()],
+        };
+
+        assert_eq!(
+            HostRankings::merge_user(user, optic),
+            HostRankings {
+                liked: vec!["a.com".to_string(), "c.com".to_string()],
+                disliked: vec![],
+                blocked: vec!["b.com".to_string(), "d.com".to_string()],
+            }
+        );
+    }
+    #[test]
+    fn features() {
+        let optic = Optic::parse("Verbatim;").unwrap();
+        assert_eq!(optic.features, Features::default());
+
+        let (optic, warnings) = Optic::parse_with_warnings(
+            "Features { sidebar: off, discussions: off, holograms: on, instant_answers: off }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            optic.features,
+            Features {
+                sidebar: false,
+                discussions: false,
+                instant_answers: false,
+                thumbnails: true,
+            }
+        );
+        assert_eq!(
+            warnings,
+            vec![Warning::UnknownFeature {
+                token: (43, "holograms".to_string(), 52)
+            }]
+        );
+    }
 }
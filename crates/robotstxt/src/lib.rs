@@ -234,7 +234,9 @@ impl Robots {
         }
     }
 
-    fn is_precise_path_allowed(&self, path: &str) -> bool {
+    /// The index of the most specific rule matching the path. Equally specific
+    /// rules are ordered like [`Rule`], so allow rules take precedence.
+    fn precise_matched_rule(&self, path: &str) -> Option<usize> {
         let mut path = path.to_string();
 
         if path.is_empty() {
@@ -242,33 +244,54 @@ impl Robots {
         }
 
         if path == "/robots.txt" {
-            return true;
+            return None;
         }
 
-        let mut matches: Vec<_> = self
-            .rules
+        self.rules
             .iter()
-            .filter(|rule| rule.pattern.matches(&path))
-            .collect();
-
-        matches.sort();
+            .enumerate()
+            .filter(|(_, rule)| rule.pattern.matches(&path))
+            .min_by(|(_, a), (_, b)| a.cmp(b))
+            .map(|(idx, _)| idx)
+    }
 
-        matches
-            .first()
-            .map(|rule| rule.directive == Directive::Allow)
+    fn is_rule_allowing(&self, rule: Option<usize>) -> bool {
+        rule.map(|idx| self.rules[idx].directive == Directive::Allow)
             .unwrap_or(true)
     }
 
-    pub fn is_path_allowed(&self, path: &str) -> bool {
-        let res = self.is_precise_path_allowed(path);
+    fn matched_path_rule(&self, path: &str) -> Option<usize> {
+        let rule = self.precise_matched_rule(path);
 
-        if !res && path.ends_with('/') {
-            self.is_precise_path_allowed(format!("{}index.html", path).as_str())
+        if !self.is_rule_allowing(rule) && path.ends_with('/') {
+            self.precise_matched_rule(format!("{}index.html", path).as_str())
         } else {
-            res
+            rule
         }
     }
 
+    pub fn is_path_allowed(&self, path: &str) -> bool {
+        self.is_rule_allowing(self.matched_path_rule(path))
+    }
+
+    /// The index of the rule that decides whether the url is allowed,
+    /// or `None` if no rule matches and the url is allowed by default.
+    pub fn matched_rule(&self, url: &Url) -> Option<usize> {
+        self.matched_path_rule(&Self::prepare_path(url))
+    }
+
+    /// The rule at the index as it would be written in the robots.txt, e.g. `Disallow: /private`.
+    pub fn rule(&self, idx: usize) -> Option<String> {
+        self.rules.get(idx).map(|rule| {
+            let directive = match rule.directive {
+                Directive::Allow => "Allow",
+                Directive::Disallow => "Disallow",
+            };
+
+            format!("{directive}: {}", rule.pattern.as_str())
+        })
+    }
+
     pub fn crawl_delay(&self) -> Option<Duration> {
         self.crawl_delay.map(Duration::from_secs_f32)
     }
@@ -1618,4 +1641,37 @@ Allow: /
         let robots = Robots::parse("BarBot", &robotstxt).unwrap();
         assert_eq!(robots.crawl_delay(), None);
     }
+
+    #[test]
+    fn test_matched_rule() {
+        let robotstxt = r#"
+User-agent: FooBot
+Disallow: /private
+Allow: /private/public
+Disallow: /dir/
+Allow: /dir/index.html
+"#;
+        let robots = Robots::parse("FooBot", robotstxt).unwrap();
+        let rule = |url: &str| {
+            robots
+                .matched_rule(&Url::parse(url).unwrap())
+                .and_then(|idx| robots.rule(idx))
+        };
+
+        assert_eq!(
+            rule("http://foo.bar/private/x"),
+            Some("Disallow: /private".to_string())
+        );
+        assert_eq!(
+            rule("http://foo.bar/private/public/x"),
+            Some("Allow: /private/public".to_string())
+        );
+        assert_eq!(
+            rule("http://foo.bar/dir/"),
+            Some("Allow: /dir/index.html".to_string())
+        );
+        assert_eq!(rule("http://foo.bar/other"), None);
+        assert_eq!(rule("http://foo.bar/robots.txt"), None);
+        assert_eq!(robots.rule(4), None);
+    }
 }
@@ -63,6 +63,10 @@ impl Pattern {
         self.len
     }
 
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
     pub fn matches(&self, path: &str) -> bool {
         let path = percent_encode(path);
         let parts = self.pattern.split('*');
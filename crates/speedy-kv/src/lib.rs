@@ -347,6 +347,23 @@ impl<K, V> Db<K, V> {
             .find_map(|segment| segment.get_raw(key.as_bytes()).ok().flatten())
     }
 
+    /// The values of the key in every segment, newest first. A key that was inserted
+    /// again after a commit is in several segments until they are merged.
+    pub fn get_all_raw<'a, SerializedKey>(
+        &'a self,
+        key: SerializedKey,
+    ) -> impl Iterator<Item = SerializedRef<'a, V>> + 'a
+    where
+        SerializedKey: Into<SerializedRef<'a, K>>,
+    {
+        let key: SerializedRef<'a, K> = key.into();
+
+        self.segments
+            .iter()
+            .rev()
+            .filter_map(move |segment| segment.get_raw(key.as_bytes()).ok().flatten())
+    }
+
     pub fn search_raw<'a, A>(
         &'a self,
         query: A,
@@ -456,6 +473,17 @@ where
             None => Ok(None),
         }
     }
+
+    pub fn get_all(&self, key: &K) -> Result<Vec<V>> {
+        let key = bincode::encode_to_vec(key, bincode::config::standard())?;
+
+        self.get_all_raw(key.as_slice())
+            .map(|v| {
+                let (v, _) = bincode::decode_from_slice(v.as_bytes(), bincode::config::standard())?;
+                Ok(v)
+            })
+            .collect()
+    }
 }
 impl<K, V> Db<K, V>
 where
@@ -577,6 +605,23 @@ mod tests {
         assert_eq!(db.get(&4).unwrap(), Some(5));
     }
 
+    #[test]
+    fn test_get_all() {
+        let mut db = Db::open_or_create(gen_temp_path()).unwrap();
+
+        db.insert(1, 2).unwrap();
+        db.commit().unwrap();
+
+        db.insert(1, 3).unwrap();
+        db.insert(2, 4).unwrap();
+        db.commit().unwrap();
+
+        assert_eq!(db.get(&1).unwrap(), Some(3));
+        assert_eq!(db.get_all(&1).unwrap(), vec![3, 2]);
+        assert_eq!(db.get_all(&2).unwrap(), vec![4]);
+        assert_eq!(db.get_all(&3).unwrap(), Vec::<i32>::new());
+    }
+
     #[test]
     fn test_segment_merge() {
         let mut db = Db::open_or_create(gen_temp_path()).unwrap();
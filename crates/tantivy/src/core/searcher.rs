@@ -9,7 +9,7 @@ use crate::query::{Bm25StatisticsProvider, EnableScoring, Query};
 use crate::schema::document::DocumentDeserialize;
 use crate::schema::{Schema, Term};
 use crate::space_usage::SearcherSpaceUsage;
-use crate::store::{CacheStats, StoreReader};
+use crate::store::{CacheStats, DocStoreCache, StoreReader};
 use crate::{DocAddress, Index, Opstamp, TrackedObject};
 
 /// Identifies the searcher generation accessed by a [`Searcher`].
@@ -246,7 +246,7 @@ impl SearcherInner {
         index: Index,
         segment_readers: Vec<SegmentReader>,
         generation: TrackedObject<SearcherGeneration>,
-        doc_store_cache_num_blocks: usize,
+        doc_store_cache: &DocStoreCache,
     ) -> io::Result<SearcherInner> {
         assert_eq!(
             &segment_readers
@@ -258,7 +258,14 @@ impl SearcherInner {
         );
         let store_readers: Vec<StoreReader> = segment_readers
             .iter()
-            .map(|segment_reader| segment_reader.get_store_reader(doc_store_cache_num_blocks))
+            .map(|segment_reader| match doc_store_cache {
+                DocStoreCache::PerSegment(num_blocks) => {
+                    segment_reader.get_store_reader(*num_blocks)
+                }
+                DocStoreCache::Shared(cache) => {
+                    segment_reader.get_store_reader_with_shared_cache(cache)
+                }
+            })
             .collect::<io::Result<Vec<_>>>()?;
 
         Ok(SearcherInner {
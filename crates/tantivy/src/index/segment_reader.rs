@@ -14,7 +14,7 @@ use crate::index::{InvertedIndexReader, Segment, SegmentComponent, SegmentId};
 use crate::json_utils::json_path_sep_to_dot;
 use crate::schema::{Field, IndexRecordOption, Schema, Type};
 use crate::space_usage::SegmentSpaceUsage;
-use crate::store::StoreReader;
+use crate::store::{SharedBlockCache, StoreReader};
 use crate::termdict::TermDictionary;
 use crate::{DocId, Opstamp};
 
@@ -139,6 +139,19 @@ impl SegmentReader {
         StoreReader::open(self.store_file.clone(), cache_num_blocks)
     }
 
+    /// Accessor to the segment's [`StoreReader`](crate::store::StoreReader) that caches its
+    /// decompressed blocks in `cache`, which may be shared with the readers of other segments.
+    pub fn get_store_reader_with_shared_cache(
+        &self,
+        cache: &SharedBlockCache,
+    ) -> io::Result<StoreReader> {
+        StoreReader::open_with_shared_cache(
+            self.store_file.clone(),
+            cache.clone(),
+            self.segment_id(),
+        )
+    }
+
     /// Open a new segment for reading.
     pub fn open(segment: &Segment) -> crate::Result<SegmentReader> {
         Self::open_with_custom_alive_set(segment, None)
@@ -1,5 +1,6 @@
 mod warming;
 
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::atomic::AtomicU64;
 use std::sync::{atomic, Arc, Weak};
 
@@ -9,7 +10,8 @@ pub use warming::Warmer;
 use self::warming::WarmingState;
 use crate::core::searcher::{SearcherGeneration, SearcherInner};
 use crate::directory::{Directory, WatchCallback, WatchHandle, META_LOCK};
-use crate::store::DOCSTORE_CACHE_CAPACITY;
+use crate::index::SegmentId;
+use crate::store::{DocStoreCache, SharedBlockCache, DOCSTORE_CACHE_CAPACITY};
 use crate::{Index, Inventory, Searcher, SegmentReader, TrackedObject};
 
 /// Defines when a new version of the index should be reloaded.
@@ -37,13 +39,15 @@ pub enum ReloadPolicy {
 /// - [`Warmer`] implementations
 /// - number of warming threads, for parallelizing warming work
 /// - The cache size of the underlying doc store readers.
+/// - Whether segments that cannot be opened are skipped.
 #[derive(Clone)]
 pub struct IndexReaderBuilder {
     reload_policy: ReloadPolicy,
     index: Index,
     warmers: Vec<Weak<dyn Warmer>>,
     num_warming_threads: usize,
-    doc_store_cache_num_blocks: usize,
+    doc_store_cache: DocStoreCache,
+    skip_corrupt_segments: bool,
 }
 
 impl IndexReaderBuilder {
@@ -54,7 +58,8 @@ impl IndexReaderBuilder {
             index,
             warmers: Vec::new(),
             num_warming_threads: 1,
-            doc_store_cache_num_blocks: DOCSTORE_CACHE_CAPACITY,
+            doc_store_cache: DocStoreCache::PerSegment(DOCSTORE_CACHE_CAPACITY),
+            skip_corrupt_segments: false,
         }
     }
 
@@ -71,7 +76,8 @@ impl IndexReaderBuilder {
             searcher_generation_inventory.clone(),
         )?;
         let inner_reader = InnerIndexReader::new(
-            self.doc_store_cache_num_blocks,
+            self.doc_store_cache,
+            self.skip_corrupt_segments,
             self.index,
             warming_state,
             searcher_generation_inventory,
@@ -122,7 +128,26 @@ impl IndexReaderBuilder {
         mut self,
         doc_store_cache_num_blocks: usize,
     ) -> IndexReaderBuilder {
-        self.doc_store_cache_num_blocks = doc_store_cache_num_blocks;
+        self.doc_store_cache = DocStoreCache::PerSegment(doc_store_cache_num_blocks);
+        self
+    }
+
+    /// Sets a cache of decompressed blocks that is shared by the doc store readers of all
+    /// segments, instead of a cache per reader. The cache is kept when the reader is reloaded
+    /// and can also be shared between readers.
+    #[must_use]
+    pub fn doc_store_shared_cache(mut self, cache: SharedBlockCache) -> IndexReaderBuilder {
+        self.doc_store_cache = DocStoreCache::Shared(cache);
+        self
+    }
+
+    /// Skip the segments that cannot be opened instead of failing to open the reader.
+    ///
+    /// The skipped segments are logged and can be listed with
+    /// [`IndexReader::skipped_segments()`], while the searchers serve the remaining segments.
+    #[must_use]
+    pub fn skip_corrupt_segments(mut self, skip_corrupt_segments: bool) -> IndexReaderBuilder {
+        self.skip_corrupt_segments = skip_corrupt_segments;
         self
     }
 
@@ -153,7 +178,9 @@ impl TryInto<IndexReader> for IndexReaderBuilder {
 }
 
 struct InnerIndexReader {
-    doc_store_cache_num_blocks: usize,
+    doc_store_cache: DocStoreCache,
+    skip_corrupt_segments: bool,
+    skipped_segments: ArcSwap<Vec<SegmentId>>,
     index: Index,
     warming_state: WarmingState,
     searcher: arc_swap::ArcSwap<SearcherInner>,
@@ -163,7 +190,8 @@ struct InnerIndexReader {
 
 impl InnerIndexReader {
     fn new(
-        doc_store_cache_num_blocks: usize,
+        doc_store_cache: DocStoreCache,
+        skip_corrupt_segments: bool,
         index: Index,
         warming_state: WarmingState,
         // The searcher_generation_inventory is not used as source, but as target to track the
@@ -172,15 +200,18 @@ impl InnerIndexReader {
     ) -> crate::Result<Self> {
         let searcher_generation_counter: Arc<AtomicU64> = Default::default();
 
-        let searcher = Self::create_searcher(
+        let (searcher, skipped_segments) = Self::create_searcher(
             &index,
-            doc_store_cache_num_blocks,
+            &doc_store_cache,
+            skip_corrupt_segments,
             &warming_state,
             &searcher_generation_counter,
             &searcher_generation_inventory,
         )?;
         Ok(InnerIndexReader {
-            doc_store_cache_num_blocks,
+            doc_store_cache,
+            skip_corrupt_segments,
+            skipped_segments: ArcSwap::from_pointee(skipped_segments),
             index,
             warming_state,
             searcher: ArcSwap::from(searcher),
@@ -192,15 +223,51 @@ impl InnerIndexReader {
     ///
     /// This function acquires a lock to prevent GC from removing files
     /// as we are opening our index.
-    fn open_segment_readers(index: &Index) -> crate::Result<Vec<SegmentReader>> {
+    ///
+    /// If `skip_corrupt_segments` is set, the segments that fail to open are
+    /// returned alongside the readers instead of failing.
+    fn open_segment_readers(
+        index: &Index,
+        skip_corrupt_segments: bool,
+    ) -> crate::Result<(Vec<SegmentReader>, Vec<SegmentId>)> {
         // Prevents segment files from getting deleted while we are in the process of opening them
         let _meta_lock = index.directory().acquire_lock(&META_LOCK)?;
         let searchable_segments = index.searchable_segments()?;
-        let segment_readers = searchable_segments
-            .iter()
-            .map(SegmentReader::open)
-            .collect::<crate::Result<_>>()?;
-        Ok(segment_readers)
+
+        if !skip_corrupt_segments {
+            let segment_readers = searchable_segments
+                .iter()
+                .map(SegmentReader::open)
+                .collect::<crate::Result<_>>()?;
+            return Ok((segment_readers, Vec::new()));
+        }
+
+        let mut segment_readers = Vec::with_capacity(searchable_segments.len());
+        let mut skipped_segments = Vec::new();
+
+        for segment in &searchable_segments {
+            // corrupt data can make the readers panic instead of returning an error
+            let res = panic::catch_unwind(AssertUnwindSafe(|| SegmentReader::open(segment)))
+                .unwrap_or_else(|_| {
+                    Err(crate::TantivyError::InternalError(
+                        "panicked while opening the segment".to_string(),
+                    ))
+                });
+
+            match res {
+                Ok(segment_reader) => segment_readers.push(segment_reader),
+                Err(err) => {
+                    error!(
+                        "Skipping segment {} that could not be opened. {:?}",
+                        segment.id().uuid_string(),
+                        err
+                    );
+                    skipped_segments.push(segment.id());
+                }
+            }
+        }
+
+        Ok((segment_readers, skipped_segments))
     }
 
     fn track_segment_readers_in_inventory(
@@ -216,12 +283,14 @@ impl InnerIndexReader {
 
     fn create_searcher(
         index: &Index,
-        doc_store_cache_num_blocks: usize,
+        doc_store_cache: &DocStoreCache,
+        skip_corrupt_segments: bool,
         warming_state: &WarmingState,
         searcher_generation_counter: &Arc<AtomicU64>,
         searcher_generation_inventory: &Inventory<SearcherGeneration>,
-    ) -> crate::Result<Arc<SearcherInner>> {
-        let segment_readers = Self::open_segment_readers(index)?;
+    ) -> crate::Result<(Arc<SearcherInner>, Vec<SegmentId>)> {
+        let (segment_readers, skipped_segments) =
+            Self::open_segment_readers(index, skip_corrupt_segments)?;
         let searcher_generation = Self::track_segment_readers_in_inventory(
             &segment_readers,
             searcher_generation_counter,
@@ -234,23 +303,25 @@ impl InnerIndexReader {
             index.clone(),
             segment_readers,
             searcher_generation,
-            doc_store_cache_num_blocks,
+            doc_store_cache,
         )?);
 
         warming_state.warm_new_searcher_generation(&searcher.clone().into())?;
-        Ok(searcher)
+        Ok((searcher, skipped_segments))
     }
 
     fn reload(&self) -> crate::Result<()> {
-        let searcher = Self::create_searcher(
+        let (searcher, skipped_segments) = Self::create_searcher(
             &self.index,
-            self.doc_store_cache_num_blocks,
+            &self.doc_store_cache,
+            self.skip_corrupt_segments,
             &self.warming_state,
             &self.searcher_generation_counter,
             &self.searcher_generation_inventory,
         )?;
 
         self.searcher.store(searcher);
+        self.skipped_segments.store(Arc::new(skipped_segments));
 
         Ok(())
     }
@@ -301,4 +372,12 @@ impl IndexReader {
     pub fn searcher(&self) -> Searcher {
         self.inner.searcher()
     }
+
+    /// The segments that could not be opened when the searcher was last loaded.
+    ///
+    /// Always empty unless the reader was built with
+    /// [`IndexReaderBuilder::skip_corrupt_segments()`].
+    pub fn skipped_segments(&self) -> Vec<SegmentId> {
+        self.inner.skipped_segments.load().as_ref().clone()
+    }
 }
@@ -37,8 +37,8 @@ mod reader;
 mod writer;
 pub use self::compressors::Compressor;
 pub use self::decompressors::Decompressor;
-pub(crate) use self::reader::DOCSTORE_CACHE_CAPACITY;
-pub use self::reader::{CacheStats, StoreReader};
+pub(crate) use self::reader::{DocStoreCache, DOCSTORE_CACHE_CAPACITY};
+pub use self::reader::{CacheStats, SharedBlockCache, StoreReader};
 pub use self::writer::StoreWriter;
 mod store_compressor;
 
@@ -14,6 +14,7 @@ use super::Decompressor;
 use crate::directory::FileSlice;
 use crate::error::DataCorruption;
 use crate::fastfield::AliveBitSet;
+use crate::index::SegmentId;
 use crate::schema::document::{BinaryDocumentDeserializer, DocumentDeserialize};
 use crate::space_usage::StoreSpaceUsage;
 use crate::store::index::Checkpoint;
@@ -32,20 +33,89 @@ pub struct StoreReader {
     cache: BlockCache,
 }
 
+/// How the store readers of a searcher cache decompressed blocks.
+#[derive(Clone)]
+pub(crate) enum DocStoreCache {
+    /// Every store reader has its own LRU of this many blocks.
+    PerSegment(usize),
+    /// The store readers share a single LRU.
+    Shared(SharedBlockCache),
+}
+
+/// An LRU of decompressed blocks that is shared by the store readers of all segments.
+///
+/// Unlike the cache of a single store reader, the shared cache outlives the searcher, so hot
+/// blocks stay cached when the index is reloaded, and its size bounds the memory of the cache
+/// regardless of the number of segments.
+#[derive(Clone)]
+pub struct SharedBlockCache {
+    inner: Arc<SharedBlockCacheInner>,
+}
+
+struct SharedBlockCacheInner {
+    cache: Mutex<LruCache<(SegmentId, usize), Block>>,
+    cache_hits: AtomicUsize,
+    cache_misses: AtomicUsize,
+}
+
+impl SharedBlockCache {
+    /// Creates a cache that holds up to `num_blocks` decompressed blocks.
+    pub fn new(num_blocks: NonZeroUsize) -> SharedBlockCache {
+        SharedBlockCache {
+            inner: Arc::new(SharedBlockCacheInner {
+                cache: Mutex::new(LruCache::new(num_blocks)),
+                cache_hits: Default::default(),
+                cache_misses: Default::default(),
+            }),
+        }
+    }
+
+    fn get(&self, key: &(SegmentId, usize)) -> Option<Block> {
+        let block = self.inner.cache.lock().unwrap().get(key).cloned();
+
+        if block.is_some() {
+            self.inner.cache_hits.fetch_add(1, Ordering::SeqCst);
+        } else {
+            self.inner.cache_misses.fetch_add(1, Ordering::SeqCst);
+        }
+
+        block
+    }
+
+    fn put(&self, key: (SegmentId, usize), data: Block) {
+        self.inner.cache.lock().unwrap().put(key, data);
+    }
+
+    /// Returns the cache hit and miss statistics of all the store readers that share the cache.
+    /// Every miss is a block that was decompressed.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            num_entries: self.inner.cache.lock().unwrap().len(),
+            cache_hits: self.inner.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.inner.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
 /// The cache for decompressed blocks.
 struct BlockCache {
     cache: Option<Mutex<LruCache<usize, Block>>>,
+    shared: Option<(SharedBlockCache, SegmentId)>,
     cache_hits: AtomicUsize,
     cache_misses: AtomicUsize,
 }
 
 impl BlockCache {
     fn get_from_cache(&self, pos: usize) -> Option<Block> {
-        if let Some(block) = self
-            .cache
-            .as_ref()
-            .and_then(|cache| cache.lock().unwrap().get(&pos).cloned())
-        {
+        let block = match &self.shared {
+            Some((shared, segment_id)) => shared.get(&(*segment_id, pos)),
+            None => self
+                .cache
+                .as_ref()
+                .and_then(|cache| cache.lock().unwrap().get(&pos).cloned()),
+        };
+
+        if let Some(block) = block {
             self.cache_hits.fetch_add(1, Ordering::SeqCst);
             return Some(block);
         }
@@ -54,11 +124,14 @@ impl BlockCache {
     }
 
     fn put_into_cache(&self, pos: usize, data: Block) {
-        if let Some(cache) = self.cache.as_ref() {
+        if let Some((shared, segment_id)) = &self.shared {
+            shared.put((*segment_id, pos), data);
+        } else if let Some(cache) = self.cache.as_ref() {
             cache.lock().unwrap().put(pos, data);
         }
     }
 
+    /// The entries of a shared cache are not counted, as they belong to all the readers.
     fn stats(&self) -> CacheStats {
         CacheStats {
             cache_hits: self.cache_hits.load(Ordering::Relaxed),
@@ -118,6 +191,39 @@ impl StoreReader {
     /// `cache_num_blocks` sets the number of decompressed blocks to be cached in an LRU.
     /// The size of blocks is configurable, this should be reflexted in the
     pub fn open(store_file: FileSlice, cache_num_blocks: usize) -> io::Result<StoreReader> {
+        Self::open_with_block_cache(
+            store_file,
+            BlockCache {
+                cache: NonZeroUsize::new(cache_num_blocks)
+                    .map(|cache_num_blocks| Mutex::new(LruCache::new(cache_num_blocks))),
+                shared: None,
+                cache_hits: Default::default(),
+                cache_misses: Default::default(),
+            },
+        )
+    }
+
+    /// Opens a store reader that caches its blocks in a cache shared with other readers.
+    ///
+    /// The blocks are keyed by `segment_id`, so the readers sharing the cache must belong to
+    /// different segments.
+    pub fn open_with_shared_cache(
+        store_file: FileSlice,
+        cache: SharedBlockCache,
+        segment_id: SegmentId,
+    ) -> io::Result<StoreReader> {
+        Self::open_with_block_cache(
+            store_file,
+            BlockCache {
+                cache: None,
+                shared: Some((cache, segment_id)),
+                cache_hits: Default::default(),
+                cache_misses: Default::default(),
+            },
+        )
+    }
+
+    fn open_with_block_cache(store_file: FileSlice, cache: BlockCache) -> io::Result<StoreReader> {
         let (footer, data_and_offset) = DocStoreFooter::extract_footer(store_file)?;
 
         let (data_file, offset_index_file) = data_and_offset.split(footer.offset as usize);
@@ -128,12 +234,7 @@ impl StoreReader {
         Ok(StoreReader {
             decompressor: footer.decompressor,
             data: data_file,
-            cache: BlockCache {
-                cache: NonZeroUsize::new(cache_num_blocks)
-                    .map(|cache_num_blocks| Mutex::new(LruCache::new(cache_num_blocks))),
-                cache_hits: Default::default(),
-                cache_misses: Default::default(),
-            },
+            cache,
             skip_index: Arc::new(skip_index),
             space_usage,
         })
@@ -394,4 +495,51 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_store_shared_cache() -> crate::Result<()> {
+        let directory = RamDirectory::create();
+        let path = Path::new("store");
+        let writer = directory.open_write(path)?;
+        let schema = write_lorem_ipsum_store(writer, 500, Compressor::default(), BLOCK_SIZE, true);
+        let title = schema.get_field("title").unwrap();
+
+        let cache = SharedBlockCache::new(NonZeroUsize::new(DOCSTORE_CACHE_CAPACITY).unwrap());
+        let first = StoreReader::open_with_shared_cache(
+            directory.open_read(path)?,
+            cache.clone(),
+            SegmentId::generate_random(),
+        )?;
+        let second = StoreReader::open_with_shared_cache(
+            directory.open_read(path)?,
+            cache.clone(),
+            SegmentId::generate_random(),
+        )?;
+
+        let doc: TantivyDocument = first.get(0)?;
+        assert_eq!(get_text_field(&doc, &title), Some("Doc 0"));
+        assert_eq!(cache.stats().cache_misses, 1);
+        assert_eq!(cache.stats().num_entries, 1);
+
+        // a reader that is reopened on the same segment reuses the cached block
+        let reopened = StoreReader::open_with_shared_cache(
+            directory.open_read(path)?,
+            cache.clone(),
+            first.cache.shared.as_ref().unwrap().1,
+        )?;
+        let doc: TantivyDocument = reopened.get(0)?;
+        assert_eq!(get_text_field(&doc, &title), Some("Doc 0"));
+        assert_eq!(cache.stats().cache_hits, 1);
+        assert_eq!(cache.stats().cache_misses, 1);
+
+        // the blocks of other segments are cached separately
+        let doc: TantivyDocument = second.get(0)?;
+        assert_eq!(get_text_field(&doc, &title), Some("Doc 0"));
+        assert_eq!(cache.stats().cache_misses, 2);
+        assert_eq!(cache.stats().num_entries, 2);
+
+        assert_eq!(first.cache_stats().num_entries, 0);
+
+        Ok(())
+    }
 }
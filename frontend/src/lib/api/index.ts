@@ -114,6 +114,10 @@ export const api = {
     requestPlain('POST', `/beta/api/explore/export`, body, options),
   hostsExport: (body: HostsExportOpticParams, options?: ApiOptions) =>
     requestPlain('POST', `/beta/api/hosts/export`, body, options),
+  hostsSiteInfo: (body: SiteInfoQuery, options?: ApiOptions) =>
+    requestJson<SiteInfo>('POST', `/beta/api/hosts/site_info`, body, options),
+  indexFreshness: (options?: ApiOptions) =>
+    requestJson<FreshnessHistogram>('GET', `/beta/api/index/freshness`, options),
   search: (body: ApiSearchQuery, options?: ApiOptions) =>
     requestJson<ApiSearchResult>('POST', `/beta/api/search`, body, options),
   searchSidebar: (body: SidebarQuery, options?: ApiOptions) =>
@@ -144,6 +148,17 @@ export const api = {
       `/beta/api/webgraph/host/knows?${new URLSearchParams(query)}`,
       options,
     ),
+  webgraphHostLinkReport: (
+    query: {
+      host: string;
+    },
+    options?: ApiOptions,
+  ) =>
+    requestJson<LinkReport>(
+      'POST',
+      `/beta/api/webgraph/host/link_report?${new URLSearchParams(query)}`,
+      options,
+    ),
   webgraphHostOutgoing: (
     query: {
       host: string;
@@ -181,19 +196,35 @@ export const api = {
     ),
 };
 
+export type AnchorCount = {
+  count: number;
+  text: string;
+};
+export type ApiSafeSearch = boolean | SafeSearch;
 export type ApiSearchQuery = {
+  boostedHosts?: string[];
   countResultsExact?: boolean;
+  debugTimings?: boolean;
+  dedupUrls?: boolean;
+  diversityLambda?: number;
   flattenResponse?: boolean;
   hostRankings?: HostRankings;
+  latencyBudgetMs?: number;
   numResults?: number;
   optic?: string;
+  opticDebug?: boolean;
   page?: number;
+  proximityWeight?: number;
   query: string;
+  restrictHosts?: string[];
   returnRankingSignals?: boolean;
   returnStructuredData?: boolean;
-  safeSearch?: boolean;
+  safeSearch?: ApiSafeSearch;
+  searchFields?: string[];
   selectedRegion?: Region;
   signalCoefficients?: {};
+  sortPage?: PageSort;
+  verbatim?: boolean;
 };
 export type ApiSearchResult =
   | (WebsitesResult & {
@@ -215,10 +246,18 @@ export type BangHit = {
   bang: Bang;
   redirectTo: UrlWrapper;
 };
+export type Breadcrumb = {
+  name: string;
+  url?: string;
+};
 export type Calculation = {
   input: string;
   result: string;
 };
+export type CoLinkedHost = {
+  host: string;
+  score: number;
+};
 export type CodeOrText =
   | {
       _type: 'code';
@@ -228,6 +267,7 @@ export type CodeOrText =
       _type: 'text';
       value: string;
     };
+export type ContentLabel = 'adult' | 'violence' | 'medical-graphic';
 export type Count =
   | {
       _type: 'exact';
@@ -237,6 +277,11 @@ export type Count =
       _type: 'approximate';
       value: number;
     };
+export type DegradedStage = {
+  numCandidates: number;
+  numScored: number;
+  stage: PipelineStage;
+};
 export type Definition = string;
 export type DisplayedAnswer = {
   answer: string;
@@ -246,6 +291,8 @@ export type DisplayedAnswer = {
   url: string;
 };
 export type DisplayedEntity = {
+  alternatives: EntityCandidate[];
+  id: string;
   imageId?: string;
   info: string & EntitySnippet[][];
   matchScore: number;
@@ -266,9 +313,15 @@ export type DisplayedSidebar =
       };
     };
 export type DisplayedWebpage = {
+  breadcrumbs: Breadcrumb[];
+  contentLabels: ContentLabel[];
+  contentWarning: boolean;
+  degraded: boolean;
   domain: string;
+  historyBoost?: number;
   likelyHasAds: boolean;
   likelyHasPaywall: boolean;
+  opticRuleMatches?: OpticRuleMatch[];
   prettyUrl: string;
   rankingSignals?: {};
   richSnippet?: RichSnippet;
@@ -276,9 +329,15 @@ export type DisplayedWebpage = {
   site: string;
   snippet: Snippet;
   structuredData?: StructuredData[];
+  synthesizedTitle: boolean;
   title: string;
   url: string;
 };
+export type EntityCandidate = {
+  confidence: number;
+  id: string;
+  title: string;
+};
 export type EntitySnippet = {
   fragments: EntitySnippetFragment[];
 };
@@ -297,7 +356,22 @@ export type ExploreExportOpticParams = {
   chosenHosts: string[];
   similarHosts: string[];
 };
+export type Features = {
+  discussions: boolean;
+  instantAnswers: boolean;
+  sidebar: boolean;
+  thumbnails: boolean;
+};
+export type FreshnessBucket = {
+  count: number;
+  maxAgeDays: number;
+};
+export type FreshnessHistogram = {
+  buckets: FreshnessBucket[];
+  total: number;
+};
 export type FullEdge = {
+  discoveredAt: number;
   from: Node;
   label: string;
   to: Node;
@@ -329,11 +403,36 @@ export type KnowsHost =
       _type: 'unknown';
     };
 export type Lemma = string;
+export type LinkReport = {
+  groups: LinkingHostGroup[];
+  host: string;
+  truncated: boolean;
+};
+export type LinkingHostGroup = {
+  anchors: AnchorCount[];
+  host: string;
+  nofollowFraction: number;
+  numLinks: number;
+  rel: RelHistogram;
+};
 export type Node = {
   name: string;
 };
 export type OneOrManyProperty = Property | Property[];
+export type OpticDebugSummary = {
+  unmatchedRules: number[];
+};
+export type OpticRuleMatch = {
+  action: string;
+  rule: number;
+  scoreDelta: number;
+};
 export type OneOrManyString = string | string[];
+export type PageSort = {
+  field: SortField;
+  locale?: string;
+  order?: SortOrder;
+};
 export type PartOfSpeech = 'noun' | 'verb' | 'adjective' | 'adjectiveSatellite' | 'adverb';
 export const PART_OF_SPEECHES = [
   'noun',
@@ -346,9 +445,27 @@ export type PartOfSpeechMeaning = {
   meanings: WordMeaning[];
   pos: PartOfSpeech;
 };
+export type PipelineStage =
+  | 'titleEmbeddings'
+  | 'keywordEmbeddings'
+  | 'inboundSimilarity'
+  | 'crossEncoder';
+export const PIPELINE_STAGES = [
+  'titleEmbeddings',
+  'keywordEmbeddings',
+  'inboundSimilarity',
+  'crossEncoder',
+] satisfies PipelineStage[];
 export type Property = string | StructuredData;
 export type Region = 'All' | 'Denmark' | 'France' | 'Germany' | 'Spain' | 'US';
 export const REGIONS = ['All', 'Denmark', 'France', 'Germany', 'Spain', 'US'] satisfies Region[];
+export type RelHistogram = {
+  inFooter: number;
+  inNavigation: number;
+  nofollow: number;
+  sponsored: number;
+  total: number;
+};
 export type ReturnBody =
   | {
       _type: 'all';
@@ -362,12 +479,15 @@ export type RichSnippet = {
   answers: StackOverflowAnswer[];
   question: StackOverflowQuestion;
 };
+export type SafeSearch = 'off' | 'warn' | 'strict';
 export type ScoredHost = {
   description?: string;
   host: string;
   score: number;
 };
 export type SidebarQuery = {
+  optic?: string;
+  pinnedEntity?: string;
   query: string;
 };
 export type SignalEnumDiscriminants =
@@ -381,6 +501,7 @@ export type SignalEnumDiscriminants =
   | 'bm25_stemmed_title'
   | 'bm25_stemmed_clean_body'
   | 'bm25_all_body'
+  | 'bm25_boilerplate'
   | 'bm25_keywords'
   | 'bm25_backlink_text'
   | 'idf_sum_url'
@@ -411,7 +532,8 @@ export type SignalEnumDiscriminants =
   | 'url_slashes'
   | 'link_density'
   | 'title_embedding_similarity'
-  | 'keyword_embedding_similarity';
+  | 'keyword_embedding_similarity'
+  | 'term_proximity';
 export const SIGNAL_ENUM_DISCRIMINANTS = [
   'bm25_f',
   'bm25_title',
@@ -423,6 +545,7 @@ export const SIGNAL_ENUM_DISCRIMINANTS = [
   'bm25_stemmed_title',
   'bm25_stemmed_clean_body',
   'bm25_all_body',
+  'bm25_boilerplate',
   'bm25_keywords',
   'bm25_backlink_text',
   'idf_sum_url',
@@ -454,21 +577,40 @@ export const SIGNAL_ENUM_DISCRIMINANTS = [
   'link_density',
   'title_embedding_similarity',
   'keyword_embedding_similarity',
+  'term_proximity',
 ] satisfies SignalEnumDiscriminants[];
 export type SignalScore = {
   coefficient: number;
+  unclampedValue?: number;
   value: number;
 };
 export type SimilarHostsParams = {
   hosts: string[];
   topN: number;
 };
+export type SiteInfo = {
+  centralityPercentile?: number;
+  coLinkedHosts: CoLinkedHost[];
+  degraded: boolean;
+  firstIndexed?: number;
+  hasEntity?: boolean;
+  host: string;
+  numBacklinks?: number;
+  numPages?: number;
+  topics: string[];
+};
+export type SiteInfoQuery = {
+  host: string;
+};
 export type Snippet = {
   date?: string;
   text: TextSnippet;
 };
+export type SortField = 'title' | 'url' | 'domain';
+export type SortOrder = 'ascending' | 'descending';
 export type SpellcheckQuery = {
   query: string;
+  verbatim?: boolean;
 };
 export type StackOverflowAnswer = {
   accepted: boolean;
@@ -494,11 +636,24 @@ export type ThesaurusWidget = {
   meanings: PartOfSpeechMeaning[];
   term: Lemma;
 };
+export type Timings = {
+  children: Timings[];
+  durationMs: number;
+  name: string;
+};
 export type UrlWrapper = string;
 export type WebsitesResult = {
+  degraded: boolean;
+  degradedStages: DegradedStage[];
+  features: Features;
   hasMoreResults: boolean;
+  missingShards: number[];
   numHits: Count;
+  opticDebug?: OpticDebugSummary;
+  queryTruncated: boolean;
+  redirect?: string;
   searchDurationMs: number;
+  timings?: Timings;
   webpages: DisplayedWebpage[];
 };
 export type Widget =
@@ -511,6 +666,7 @@ export type Widget =
       value: ThesaurusWidget;
     };
 export type WidgetQuery = {
+  optic?: string;
   query: string;
 };
 export type WordMeaning = {
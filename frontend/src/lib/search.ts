@@ -21,6 +21,8 @@ export type SearchParams = {
   compressedHostRankings: string | null;
   hostRankings: RankedSites | undefined;
   showRankingSignals?: boolean;
+  pinnedEntity?: string;
+  verbatim: boolean;
 };
 
 export type SearchResults =
@@ -33,7 +35,20 @@ export type SearchResults =
     })
   | (BangHit & {
       _type: 'bang';
-    });
+    })
+  | QueryError;
+
+/** The query was rejected by the API, e.g. because it was empty or too long. */
+export type QueryError = {
+  _type: 'error';
+  message: string;
+};
+
+const queryError = async (err: unknown): Promise<QueryError> => {
+  const message = err instanceof Promise ? await err.catch(() => '') : '';
+
+  return { _type: 'error', message: message || 'The search could not be performed' };
+};
 
 export const extractSearchParams = (searchParams: URLSearchParams | FormData): SearchParams => {
   const query = (searchParams.get('q') as string | undefined) ?? '';
@@ -47,6 +62,9 @@ export const extractSearchParams = (searchParams: URLSearchParams | FormData): S
   const host_rankings = compressedhost_rankings
     ? decompressRanked(compressedhost_rankings)
     : void 0;
+  const pinnedEntity = (searchParams.get('entity') as string | undefined) || void 0;
+  const verbatimParam = searchParams.get('verbatim') as string | undefined;
+  const verbatim = verbatimParam == '1' || verbatimParam == 'true';
 
   return {
     query,
@@ -56,6 +74,8 @@ export const extractSearchParams = (searchParams: URLSearchParams | FormData): S
     safeSearch,
     compressedHostRankings: compressedhost_rankings,
     hostRankings: host_rankings,
+    pinnedEntity,
+    verbatim,
   };
 };
 
@@ -99,15 +119,18 @@ Rule {
 };`;
 
 export const search = async (params: SearchParams, options: ApiOptions) => {
+  const optic = params.optic && (await fetchRemoteOptic({ opticUrl: params.optic, fetch }));
+
   const { data: websitesReq } = api.search(
     {
       query: params.query,
       page: params.currentPage - 1,
       safeSearch: params.safeSearch,
-      optic: params.optic && (await fetchRemoteOptic({ opticUrl: params.optic, fetch })),
+      optic,
       selectedRegion: params.selectedRegion,
       hostRankings: params.hostRankings,
       returnRankingSignals: params.showRankingSignals,
+      verbatim: params.verbatim,
     },
     options,
   );
@@ -117,6 +140,7 @@ export const search = async (params: SearchParams, options: ApiOptions) => {
       ? api.searchWidget(
           {
             query: params.query,
+            optic,
           },
           options,
         )
@@ -127,6 +151,8 @@ export const search = async (params: SearchParams, options: ApiOptions) => {
       ? api.searchSidebar(
           {
             query: params.query,
+            pinnedEntity: params.pinnedEntity,
+            optic,
           },
           options,
         )
@@ -142,21 +168,37 @@ export const search = async (params: SearchParams, options: ApiOptions) => {
             safeSearch: params.safeSearch,
             selectedRegion: params.selectedRegion,
             hostRankings: params.hostRankings,
+            verbatim: params.verbatim,
           },
           options,
         )
       : { data: undefined };
 
-  const { data: spellcheckReq } = api.searchSpellcheck({ query: params.query }, options);
+  const { data: spellcheckReq } = api.searchSpellcheck(
+    { query: params.query, verbatim: params.verbatim },
+    options,
+  );
 
   const [websites, widget, sidebar, discussionsRes, spellCorrection] = await Promise.all([
-    websitesReq,
+    websitesReq.catch(queryError),
     widgetReq,
     sidebarReq,
-    discussionsReq,
+    discussionsReq?.catch(() => undefined),
     spellcheckReq,
   ]);
-  const discussions = discussionsRes?._type == 'websites' ? discussionsRes.webpages : undefined;
+
+  if (websites._type == 'error') {
+    return websites;
+  }
+
+  // the api skips the sidebar and widget for optics that turn them off, but the
+  // discussions come from a separate search so they are dropped here
+  const discussions =
+    websites._type == 'websites' &&
+    websites.features.discussions &&
+    discussionsRes?._type == 'websites'
+      ? discussionsRes.webpages
+      : undefined;
 
   const results: SearchResults =
     websites._type == 'websites'